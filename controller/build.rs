@@ -0,0 +1,43 @@
+use std::{env, path::Path, process::Command};
+
+/// Run git in the crate directory, `None` if git isn't there or it fails
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|out| out.trim().to_string())
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // the crate only knows the target triple through the build script
+    println!(
+        "cargo:rustc-env=RAC_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+
+    // outside a git checkout the hash is left out and the crate shows it as unknown
+    let Some(hash) = git(&["rev-parse", "--short=12", "HEAD"]) else {
+        return;
+    };
+    println!("cargo:rustc-env=RAC_GIT_HASH={hash}");
+
+    // rebuild when HEAD moves, to another branch or to a new commit on the same one
+    if let Some(dir) = git(&["rev-parse", "--git-dir"]) {
+        let dir = Path::new(&dir);
+        let mut watched = vec![dir.join("HEAD")];
+        if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            watched.push(dir.join(branch));
+        }
+
+        // a missing file would rebuild every time
+        for path in watched.iter().filter(|path| path.exists()) {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+}
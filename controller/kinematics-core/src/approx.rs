@@ -0,0 +1,114 @@
+//! Comparing floats within a tolerance, where `==` is exact and rounding errors make it fail
+//!
+//! The `PartialEq` impls stay exact, these are for tests and checks that expect a result to be
+//! off by a little
+
+use crate::position::{CordinateVec, CylinderVec, SphereVec};
+
+/// Equal within a tolerance
+pub trait ApproxEq {
+    /// True if every value of `self` is within `eps` of the same one of `other`
+    fn approx_eq(&self, other: &Self, eps: f64) -> bool;
+}
+
+/// Assert that two values are equal within a tolerance, see [`ApproxEq`]
+///
+/// The tolerance is `1e-9` unless it's given, a message and its format arguments can follow it
+///
+/// # Examples
+/// ```rust
+/// use kinematics_core::{assert_approx_eq, position::CordinateVec};
+///
+/// let sum = CordinateVec::new(0.1, 0.2, 0.) + CordinateVec::new(0.2, 0.1, 0.);
+///
+/// assert_approx_eq!(sum, CordinateVec::new(0.3, 0.3, 0.));
+/// assert_approx_eq!(sum.x, 0.3, 1e-12, "x of {sum:?}");
+/// ```
+#[macro_export]
+macro_rules! assert_approx_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::assert_approx_eq!($left, $right, 1e-9)
+    };
+    ($left:expr, $right:expr, $eps:expr $(,)?) => {{
+        let (left, right, eps) = (&$left, &$right, $eps);
+        assert!(
+            $crate::approx::ApproxEq::approx_eq(left, right, eps),
+            "{left:?} is not within {eps} of {right:?}"
+        );
+    }};
+    ($left:expr, $right:expr, $eps:expr, $($message:tt)+) => {{
+        let (left, right, eps) = (&$left, &$right, $eps);
+        assert!(
+            $crate::approx::ApproxEq::approx_eq(left, right, eps),
+            "{left:?} is not within {eps} of {right:?}: {}",
+            format_args!($($message)+)
+        );
+    }};
+}
+
+impl ApproxEq for f64 {
+    fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        (self - other).abs() <= eps
+    }
+}
+
+/// The `(base, shoulder, elbow)` angles the inverse kinematics return
+impl ApproxEq for (f64, f64, f64) {
+    fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        self.0.approx_eq(&other.0, eps)
+            && self.1.approx_eq(&other.1, eps)
+            && self.2.approx_eq(&other.2, eps)
+    }
+}
+
+impl ApproxEq for CordinateVec {
+    fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        self.x.approx_eq(&other.x, eps)
+            && self.y.approx_eq(&other.y, eps)
+            && self.z.approx_eq(&other.z, eps)
+    }
+}
+
+/// Compares the azimuth as it is, like [`SphereVec`]
+impl ApproxEq for CylinderVec {
+    fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        self.azmut.approx_eq(&other.azmut, eps)
+            && self.radius.approx_eq(&other.radius, eps)
+            && self.z.approx_eq(&other.z, eps)
+    }
+}
+
+/// Compares the angles as they are, canonicalize both first for angles whole turns apart
+impl ApproxEq for SphereVec {
+    fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        self.azmut.approx_eq(&other.azmut, eps)
+            && self.polar.approx_eq(&other.polar, eps)
+            && self.distance.approx_eq(&other.distance, eps)
+            && self.flat_distance.approx_eq(&other.flat_distance, eps)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn approx_eq() {
+        let a = CordinateVec::new(1., 2., 3.);
+        assert!(a.approx_eq(&CordinateVec::new(1. + 1e-10, 2., 3. - 1e-10), 1e-9));
+        assert!(!a.approx_eq(&CordinateVec::new(1., 2.1, 3.), 1e-9));
+        assert!(!a.approx_eq(&CordinateVec::new(f64::NAN, 2., 3.), 1e-9));
+
+        let sphere = a.to_sphere();
+        assert_approx_eq!(sphere, sphere * (1. + 1e-12));
+        assert!(!sphere.approx_eq(&sphere.with_polar(sphere.polar + 0.1), 1e-3));
+
+        assert_approx_eq!((1., 2., 3.), (1.05, 1.95, 3.), 0.1, "with a message");
+    }
+
+    #[test]
+    #[should_panic(expected = "is not within")]
+    fn assert_approx_eq() {
+        assert_approx_eq!(1., 1.1);
+    }
+}
@@ -0,0 +1,240 @@
+//! Limits on where the head may go, applied one after the other
+//!
+//! The arm's reach and the floor are here, the controller adds its workspace profiles and the
+//! taught envelope, and anything else a rig needs can implement [`WorkspaceConstraint`]
+
+use core::fmt::Debug;
+
+use crate::position::CordinateVec;
+
+/// Most times the constraints are applied in turn, moving the head into one can move it out of
+/// another. Curved constraints meeting at a shallow angle take a few passes to settle
+pub const RESOLVE_PASSES: usize = 16;
+
+/// Violations shallower than this are rounding errors and don't count
+pub const TOLERANCE: f64 = 1e-9;
+
+/// How far outside of a constraint a position is and which way is back in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Violation {
+    /// Distance to the closest allowed position
+    pub depth: f64,
+
+    /// Unit vector from the position towards the closest allowed position
+    pub normal: CordinateVec,
+}
+
+impl Violation {
+    /// The violation of a head at `position` that may be at `allowed` at the closest, `None`
+    /// if they are within [`TOLERANCE`]
+    pub fn between(position: CordinateVec, allowed: CordinateVec) -> Option<Self> {
+        let offset = allowed - position;
+        let depth = offset.dst();
+
+        (depth > TOLERANCE).then(|| Self {
+            depth,
+            normal: offset * (1. / depth),
+        })
+    }
+}
+
+/// Somewhere the head may or may not be
+///
+/// # Examples
+/// A wall the head has to stay behind, pressing into the corner with the floor slides along
+/// both
+/// ```rust
+/// use kinematics_core::{
+///     constraint::{resolve, Floor, WorkspaceConstraint},
+///     position::CordinateVec,
+/// };
+///
+/// /// Everything in front of `y` is out of bounds
+/// #[derive(Debug)]
+/// struct Wall {
+///     y: f64,
+/// }
+///
+/// impl WorkspaceConstraint for Wall {
+///     fn project(&self, position: CordinateVec) -> CordinateVec {
+///         CordinateVec {
+///             y: position.y.min(self.y),
+///             ..position
+///         }
+///     }
+/// }
+///
+/// let wall = Wall { y: 20. };
+/// assert!(wall.violation(CordinateVec::new(0., 10., 0.)).is_none());
+/// assert_eq!(wall.violation(CordinateVec::new(0., 25., 0.)).unwrap().depth, 5.);
+///
+/// let constraints: [&dyn WorkspaceConstraint; 2] = [&Floor { z: 0. }, &wall];
+/// let position = resolve(constraints, CordinateVec::new(40., 30., -10.));
+/// assert_eq!(position, CordinateVec::new(40., 20., 0.));
+/// ```
+pub trait WorkspaceConstraint: Debug {
+    /// Closest position to `position` the head may be at, `position` itself if it's allowed
+    ///
+    /// Moving straight to the closest position keeps the part of a motion along the boundary,
+    /// so the head slides along it instead of stopping dead
+    fn project(&self, position: CordinateVec) -> CordinateVec;
+
+    /// How far the head at `position` is out of bounds, `None` if it's allowed
+    fn violation(&self, position: CordinateVec) -> Option<Violation> {
+        Violation::between(position, self.project(position))
+    }
+}
+
+/// The head can't be further from the shoulder than the arm is long
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Reach {
+    pub radius: f64,
+}
+
+/// The head can't come closer to the shoulder than the arm folded up, `|upper - lower|`
+///
+/// Right on the shoulder there's no way out to go, the head goes out along y
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MinReach {
+    pub radius: f64,
+}
+
+/// The head can't go below `z`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Floor {
+    pub z: f64,
+}
+
+impl WorkspaceConstraint for Reach {
+    /// A non-finite position is left as it is, so it can still be caught
+    fn project(&self, position: CordinateVec) -> CordinateVec {
+        if position.dst() > self.radius {
+            position.scale_to(self.radius)
+        } else {
+            position
+        }
+    }
+}
+
+impl WorkspaceConstraint for MinReach {
+    /// A non-finite position is left as it is, so it can still be caught
+    fn project(&self, position: CordinateVec) -> CordinateVec {
+        if position.dst() < self.radius {
+            let out = position
+                .normalized()
+                .unwrap_or(CordinateVec::new(0., 1., 0.));
+            out * self.radius
+        } else {
+            position
+        }
+    }
+}
+
+impl WorkspaceConstraint for Floor {
+    fn project(&self, position: CordinateVec) -> CordinateVec {
+        CordinateVec {
+            z: position.z.max(self.z),
+            ..position
+        }
+    }
+}
+
+/// Move `position` into all of the constraints, applying them in order and again until none of
+/// them is violated
+///
+/// Where they overlap the head ends up where they meet, on an edge or in a corner. Constraints
+/// that can't all be met leave it wherever [`RESOLVE_PASSES`] passes got it to, which favours
+/// the last ones
+pub fn resolve<'a, I>(constraints: I, mut position: CordinateVec) -> CordinateVec
+where
+    I: IntoIterator<Item = &'a dyn WorkspaceConstraint>,
+    I::IntoIter: Clone,
+{
+    let constraints = constraints.into_iter();
+    for _ in 0..RESOLVE_PASSES {
+        let mut clear = true;
+        for constraint in constraints.clone() {
+            // projected even within the tolerance, so the head ends up exactly on a boundary
+            clear &= constraint.violation(position).is_none();
+            position = constraint.project(position);
+        }
+
+        if clear {
+            break;
+        }
+    }
+
+    position
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assert_approx_eq;
+
+    fn close(actual: CordinateVec, expected: CordinateVec) {
+        assert_approx_eq!(actual, expected);
+    }
+
+    #[test]
+    fn reach() {
+        let reach = Reach { radius: 10. };
+        assert_eq!(reach.violation(CordinateVec::new(6., 0., 8.)), None);
+
+        let violation = reach.violation(CordinateVec::new(12., 0., 16.)).unwrap();
+        assert!((violation.depth - 10.).abs() < 1e-9);
+        close(violation.normal, CordinateVec::new(-0.6, 0., -0.8));
+        close(
+            reach.project(CordinateVec::new(12., 0., 16.)),
+            CordinateVec::new(6., 0., 8.),
+        );
+    }
+
+    #[test]
+    fn min_reach() {
+        let inner = MinReach { radius: 10. };
+        assert_eq!(inner.violation(CordinateVec::new(6., 0., 8.)), None);
+
+        let violation = inner.violation(CordinateVec::new(3., 0., 4.)).unwrap();
+        assert!((violation.depth - 5.).abs() < 1e-9);
+        close(violation.normal, CordinateVec::new(0.6, 0., 0.8));
+
+        // straight out from the shoulder, along y when right on it
+        close(
+            inner.project(CordinateVec::new(0., 0., -1e-3)),
+            CordinateVec::new(0., 0., -10.),
+        );
+        assert_eq!(
+            inner.project(CordinateVec::default()),
+            CordinateVec::new(0., 10., 0.)
+        );
+
+        let nan = CordinateVec::new(f64::NAN, 0., 0.);
+        assert!(inner.project(nan).x.is_nan());
+    }
+
+    #[test]
+    fn overlapping() {
+        let reach = Reach { radius: 10. };
+        let floor = Floor { z: 6. };
+        let constraints: [&dyn WorkspaceConstraint; 2] = [&reach, &floor];
+
+        // out of reach and below the floor ends up on the circle where they meet
+        let position = resolve(constraints, CordinateVec::new(20., 0., 0.));
+        assert_eq!(floor.violation(position), None);
+        assert!(reach
+            .violation(position)
+            .is_none_or(|violation| violation.depth < 1e-6));
+        assert_approx_eq!(position, CordinateVec::new(8., 0., 6.), 1e-6);
+
+        // the same constraints in the same order always give the same position
+        assert_eq!(
+            resolve(constraints, CordinateVec::new(20., 0., 0.)),
+            position
+        );
+        assert_eq!(
+            resolve(constraints, CordinateVec::new(3., 4., 7.)),
+            CordinateVec::new(3., 4., 7.)
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! The float functions the kinematics use, from std or from libm without the `std` feature
+//!
+//! Not every target has these in core, libm works everywhere
+
+#[cfg(feature = "std")]
+pub fn sqrt(x: f64) -> f64 {
+    x.sqrt()
+}
+
+#[cfg(not(feature = "std"))]
+pub fn sqrt(x: f64) -> f64 {
+    libm::sqrt(x)
+}
+
+#[cfg(feature = "std")]
+pub fn powi(x: f64, n: i32) -> f64 {
+    x.powi(n)
+}
+
+#[cfg(not(feature = "std"))]
+pub fn powi(x: f64, n: i32) -> f64 {
+    libm::pow(x, n as f64)
+}
+
+#[cfg(feature = "std")]
+pub fn sin(x: f64) -> f64 {
+    x.sin()
+}
+
+#[cfg(not(feature = "std"))]
+pub fn sin(x: f64) -> f64 {
+    libm::sin(x)
+}
+
+#[cfg(feature = "std")]
+pub fn cos(x: f64) -> f64 {
+    x.cos()
+}
+
+#[cfg(not(feature = "std"))]
+pub fn cos(x: f64) -> f64 {
+    libm::cos(x)
+}
+
+#[cfg(feature = "std")]
+pub fn atan(x: f64) -> f64 {
+    x.atan()
+}
+
+#[cfg(not(feature = "std"))]
+pub fn atan(x: f64) -> f64 {
+    libm::atan(x)
+}
+
+#[cfg(feature = "std")]
+pub fn atan2(y: f64, x: f64) -> f64 {
+    y.atan2(x)
+}
+
+#[cfg(not(feature = "std"))]
+pub fn atan2(y: f64, x: f64) -> f64 {
+    libm::atan2(y, x)
+}
+
+#[cfg(feature = "std")]
+pub fn acos(x: f64) -> f64 {
+    x.acos()
+}
+
+#[cfg(not(feature = "std"))]
+pub fn acos(x: f64) -> f64 {
+    libm::acos(x)
+}
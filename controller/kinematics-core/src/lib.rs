@@ -0,0 +1,13 @@
+//! Kinematics and motion models of the arm
+//!
+//! Nothing here needs std or an allocator, so the same math can run on the microcontroller.
+//! The float functions come from std with the default `std` feature and from libm without it
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod approx;
+pub mod constraint;
+mod float;
+pub mod motion;
+pub mod position;
+pub mod segments;
+pub mod triangle;
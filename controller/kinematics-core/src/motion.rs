@@ -0,0 +1,1161 @@
+use crate::{
+    float::{atan, powi, sqrt},
+    triangle,
+};
+use core::{
+    cell::Cell,
+    f64::consts::PI,
+    fmt::{self, Debug},
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A double linkage based motion system
+///
+/// The controlled angle is connected to the arm using two rods.
+/// One of the rods is tied to the controlled pivot point.
+/// the other is connected between the first rod and the arm
+///
+/// The arm angles it reaches are worked out once when it's built, with [`DoubleLinkage::new`]
+/// or read from a file, so the rods can't be changed afterwards
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "DoubleLinkageFields"))]
+pub struct DoubleLinkage {
+    /// Distance from the pivot to the connection point
+    connection_radial_offset: f64,
+
+    /// distance from the centerline of the arm to the connection point
+    connection_linear_offset: f64,
+
+    /// how far behind the controlled pivot is from the arm pivot
+    controll_pivot_horizontal_offset: f64,
+    /// how far above the controlled pivot is from the arm pivot
+    controll_pivot_vertical_offset: f64,
+
+    /// Length or rod connected to controller pivot
+    controller_pivot_rod_length: f64,
+
+    /// Length of rod connecting `controller_pivot_rod_length` to connection
+    connection_rod_length: f64,
+
+    /// See [`DoubleLinkage::achievable_range`]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    reach: Option<(f64, f64)>,
+}
+
+/// The rods of a [`DoubleLinkage`] before its reach is worked out
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct DoubleLinkageFields {
+    connection_radial_offset: f64,
+    connection_linear_offset: f64,
+    controll_pivot_horizontal_offset: f64,
+    controll_pivot_vertical_offset: f64,
+    controller_pivot_rod_length: f64,
+    connection_rod_length: f64,
+}
+
+/// A direct drive based motion system
+///
+/// The controlled angle is directly connected to the arm
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DirectDrive {}
+
+/// A direct drive motions system with a offset
+///
+/// The controlled angle is directly connected to the arm but with a offset
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DirectDriveOffset {
+    pub offset: f64,
+}
+
+/// A gear drive based motion system
+///
+/// The controlled angle is connected to the arm and a gear ratio is used when calculating the
+/// controlled angle
+///
+/// Read from a file it's checked like [`GearDrive::new`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "GearDriveFields"))]
+pub struct GearDrive {
+    pub gear_ratio: f64,
+
+    /// Added to the controlled angle after the ratio, in degrees
+    pub offset: f64,
+}
+
+/// The fields of a [`GearDrive`] before they are checked
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct GearDriveFields {
+    gear_ratio: f64,
+    #[serde(default)]
+    offset: f64,
+}
+
+/// A belt drive based motion system
+///
+/// The controlled angle turns the driver pulley and the belt turns the driven one on the arm.
+/// The belt gives a little before it pulls the other way, so the controlled angle runs ahead by
+/// half the slack in whichever direction the arm last moved
+///
+/// [`Motion`] takes `&self`, so the last target and direction are kept in a [`Cell`]. That makes
+/// it the only motion that isn't `Copy`, it has no [`MotionKind`] so it can't be written in a file
+/// or turn a joint of the controller
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeltDrive {
+    driver_teeth: u16,
+    driven_teeth: u16,
+
+    /// Added to the controlled angle, in degrees
+    pub offset: f64,
+
+    /// How far the controlled angle turns before the belt pulls the other way, in degrees
+    slack: f64,
+
+    /// The last target angle and whether it was growing, `None` before the first
+    history: Cell<Option<(f64, bool)>>,
+}
+
+/// Why a motion can't be built
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JointConfigError {
+    /// A gear ratio of 0 turns every angle into the same, one that isn't finite into none
+    GearRatio,
+
+    /// A pulley without teeth
+    NoTeeth,
+
+    /// The slack is negative or not finite
+    InvalidSlack,
+}
+
+/// Why a motion has no controlled angle for a target
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MotionError {
+    /// The linkage only closes for arm angles from `min` to `max`, see
+    /// [`DoubleLinkage::achievable_range`]
+    OutOfReach { min: f64, max: f64 },
+
+    /// The target isn't finite or the motion turns it into no angle at all
+    NoSolution,
+}
+
+/// Trait for join motion
+pub trait Motion {
+    /// The controlled angle for the arm at `target`, NaN where there is none
+    fn get_pivot_angle(&self, target: f64) -> f64;
+
+    /// Same as [`Motion::get_pivot_angle`], with why there is no controlled angle instead of
+    /// NaN
+    ///
+    /// By default any angle that isn't finite is [`MotionError::NoSolution`]
+    fn try_pivot_angle(&self, target: f64) -> Result<f64, MotionError> {
+        Some(self.get_pivot_angle(target))
+            .filter(|pivot| pivot.is_finite())
+            .ok_or(MotionError::NoSolution)
+    }
+
+    /// The motion as a [`MotionKind`], `None` for one that isn't any of them
+    fn kind(&self) -> Option<MotionKind> {
+        None
+    }
+
+    /// Find the target angle that [`Motion::get_pivot_angle`] turns into `pivot`
+    ///
+    /// By default this searches between `min` and `max`, assuming the pivot angle only ever
+    /// grows or only ever shrinks with the target. Motions with a closed form inverse override
+    /// it and ignore the range
+    fn get_target_angle(&self, pivot: f64, min: f64, max: f64) -> f64 {
+        let (mut low, mut high) = (min, max);
+        let rising = self.get_pivot_angle(high) >= self.get_pivot_angle(low);
+        for _ in 0..48 {
+            let middle = (low + high) / 2.;
+            if (self.get_pivot_angle(middle) < pivot) == rising {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        (low + high) / 2.
+    }
+
+    /// The arm angle the joint is at with the controlled angle at `pivot`, like one read back
+    /// from the servo
+    ///
+    /// `None` if no arm angle turns the controlled angle to `pivot`. By default there is no
+    /// inverse, use [`Motion::get_target_angle`] with the joint's range for those
+    fn get_arm_angle(&self, _pivot: f64) -> Option<f64> {
+        None
+    }
+}
+
+/// Any of the motions above without a `Box<dyn Motion>`, for where there is no allocator
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MotionKind {
+    DirectDrive(DirectDrive),
+    DirectDriveOffset(DirectDriveOffset),
+    GearDrive(GearDrive),
+    DoubleLinkage(DoubleLinkage),
+    Chained(Chained<MotionStage, MotionStage>),
+}
+
+/// One of the motions of a [`Chained`] in a [`MotionKind`], any of them but another chain
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MotionStage {
+    DirectDrive(DirectDrive),
+    DirectDriveOffset(DirectDriveOffset),
+    GearDrive(GearDrive),
+    DoubleLinkage(DoubleLinkage),
+}
+
+/// Two motions one after the other, the controlled angle of `first` is the target of `then`
+///
+/// Like a linkage turned through a gear, the linkage `first` and the gear `then`
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Chained<A, B> {
+    pub first: A,
+    pub then: B,
+}
+
+impl DirectDrive {
+    pub fn new() -> DirectDrive {
+        DirectDrive {}
+    }
+}
+
+impl DoubleLinkage {
+    pub fn new(
+        connection_radial_offset: f64,
+        connection_linear_offset: f64,
+        controll_pivot_horizontal_offset: f64,
+        controll_pivot_vertical_offset: f64,
+        controller_pivot_rod_length: f64,
+        connection_rod_length: f64,
+    ) -> Self {
+        let mut linkage = Self {
+            connection_radial_offset,
+            connection_linear_offset,
+            controll_pivot_horizontal_offset,
+            controll_pivot_vertical_offset,
+            controller_pivot_rod_length,
+            connection_rod_length,
+            reach: None,
+        };
+        linkage.reach = linkage.search_reach();
+        linkage
+    }
+
+    /// Calculate the angle and distance between the arm pivot and the arm connection point
+    ///
+    /// # Returns
+    /// (angle, distance)
+    /// angle in radians
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::motion::DoubleLinkage;
+    /// let linkage = DoubleLinkage::new(1., 1., 1., 1., 1., 1.);
+    /// let (angle, distance) = linkage.connection_offset();
+    /// ```
+    pub fn connection_offset(&self) -> (f64, f64) {
+        let angle = atan(self.connection_radial_offset / self.connection_linear_offset);
+        let distance =
+            sqrt(powi(self.connection_radial_offset, 2) + powi(self.connection_linear_offset, 2));
+
+        (angle, distance)
+    }
+
+    /// Calculate the angle and distance between the arm pivot and the arm connection point
+    ///
+    /// # Returns
+    /// (angle, distance)
+    /// angle in radians
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::motion::DoubleLinkage;
+    ///
+    /// let linkage = DoubleLinkage::new(1., 1., 1., 1., 1., 1.);
+    /// let (angle, distance) = linkage.controller_offset();
+    /// ```
+    pub fn controller_offset(&self) -> (f64, f64) {
+        let angle =
+            atan(self.controll_pivot_horizontal_offset / self.controll_pivot_vertical_offset);
+        let distance = sqrt(
+            powi(self.controll_pivot_horizontal_offset, 2)
+                + powi(self.controll_pivot_vertical_offset, 2),
+        );
+
+        (angle, distance)
+    }
+
+    /// The lowest and highest arm angle from 0° to 180° the linkage closes for, `None` if it
+    /// closes for none of them
+    ///
+    /// Past them the rods are too short to reach and [`Motion::get_pivot_angle`] is NaN. The
+    /// angles it closes for are assumed to be one stretch, its ends are searched for to well
+    /// within a millionth of a degree
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::motion::{DoubleLinkage, Motion};
+    ///
+    /// let linkage = DoubleLinkage::new(1., 10., 10., 1., 10., 20.);
+    /// let (min, max) = linkage.achievable_range().unwrap();
+    ///
+    /// assert_eq!(min, 0.);
+    /// assert!(linkage.get_pivot_angle(max).is_finite());
+    /// assert!(linkage.get_pivot_angle(max + 1e-6).is_nan());
+    /// ```
+    pub fn achievable_range(&self) -> Option<(f64, f64)> {
+        self.reach
+    }
+
+    /// Search the arm angles for [`DoubleLinkage::achievable_range`]
+    fn search_reach(&self) -> Option<(f64, f64)> {
+        let (min, max) = LINKAGE_RANGE;
+        let steps = ((max - min) / LINKAGE_STEP) as usize;
+        let angle = |step: usize| min + step as f64 * LINKAGE_STEP;
+        let closes = |angle: f64| self.get_pivot_angle(angle).is_finite();
+
+        let first = (0..=steps).map(angle).find(|&angle| closes(angle))?;
+        let last = (0..=steps).rev().map(angle).find(|&angle| closes(angle))?;
+        let low = if first > min {
+            self.edge(first - LINKAGE_STEP, first)
+        } else {
+            min
+        };
+        let high = if last < max {
+            self.edge(last + LINKAGE_STEP, last)
+        } else {
+            max
+        };
+
+        Some((low, high))
+    }
+
+    /// The arm angle between `open`, where the linkage doesn't close, and `closed`, where it
+    /// does, that is right at the edge and still closes
+    fn edge(&self, mut open: f64, mut closed: f64) -> f64 {
+        for _ in 0..48 {
+            let middle = (open + closed) / 2.;
+            if self.get_pivot_angle(middle).is_finite() {
+                closed = middle;
+            } else {
+                open = middle;
+            }
+        }
+
+        closed
+    }
+}
+
+impl GearDrive {
+    /// A gear drive turning the controlled angle `gear_ratio` degrees for every degree of the
+    /// arm, without an offset
+    ///
+    /// A negative ratio turns it the other way
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::motion::{GearDrive, JointConfigError, Motion};
+    ///
+    /// let gear = GearDrive::new(2.).unwrap().with_offset(10.);
+    ///
+    /// assert_eq!(gear.get_pivot_angle(30.), 70.);
+    /// assert_eq!(GearDrive::new(0.), Err(JointConfigError::GearRatio));
+    /// ```
+    pub fn new(gear_ratio: f64) -> Result<Self, JointConfigError> {
+        if !gear_ratio.is_finite() || gear_ratio == 0. {
+            return Err(JointConfigError::GearRatio);
+        }
+
+        Ok(Self {
+            gear_ratio,
+            offset: 0.,
+        })
+    }
+
+    /// The same gear drive with the controlled angle turned `offset` degrees further
+    pub fn with_offset(self, offset: f64) -> Self {
+        Self { offset, ..self }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<DoubleLinkageFields> for DoubleLinkage {
+    fn from(fields: DoubleLinkageFields) -> Self {
+        DoubleLinkage::new(
+            fields.connection_radial_offset,
+            fields.connection_linear_offset,
+            fields.controll_pivot_horizontal_offset,
+            fields.controll_pivot_vertical_offset,
+            fields.controller_pivot_rod_length,
+            fields.connection_rod_length,
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<GearDriveFields> for GearDrive {
+    type Error = JointConfigError;
+
+    fn try_from(fields: GearDriveFields) -> Result<Self, Self::Error> {
+        Ok(GearDrive::new(fields.gear_ratio)?.with_offset(fields.offset))
+    }
+}
+
+impl BeltDrive {
+    /// A belt from a pulley with `driver_teeth` on the controlled angle to one with
+    /// `driven_teeth` on the arm
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::motion::{BeltDrive, JointConfigError, Motion};
+    ///
+    /// let belt = BeltDrive::new(20, 60, 0., 2.).unwrap();
+    ///
+    /// assert_eq!(belt.ratio(), 3.);
+    /// assert_eq!(belt.get_pivot_angle(10.), 31.);
+    /// assert_eq!(belt.get_pivot_angle(0.), -1.);
+    /// assert_eq!(BeltDrive::new(0, 60, 0., 2.), Err(JointConfigError::NoTeeth));
+    /// ```
+    pub fn new(
+        driver_teeth: u16,
+        driven_teeth: u16,
+        offset: f64,
+        slack: f64,
+    ) -> Result<Self, JointConfigError> {
+        if driver_teeth == 0 || driven_teeth == 0 {
+            return Err(JointConfigError::NoTeeth);
+        }
+        if !(slack.is_finite() && slack >= 0.) {
+            return Err(JointConfigError::InvalidSlack);
+        }
+
+        Ok(Self {
+            driver_teeth,
+            driven_teeth,
+            offset,
+            slack,
+            history: Cell::new(None),
+        })
+    }
+
+    /// Teeth on the pulley on the controlled angle
+    pub fn driver_teeth(&self) -> u16 {
+        self.driver_teeth
+    }
+
+    /// Teeth on the pulley on the arm
+    pub fn driven_teeth(&self) -> u16 {
+        self.driven_teeth
+    }
+
+    /// Degrees the controlled angle turns for every degree of the arm
+    pub fn ratio(&self) -> f64 {
+        f64::from(self.driven_teeth) / f64::from(self.driver_teeth)
+    }
+
+    /// How far the controlled angle turns before the belt pulls the other way, in degrees
+    pub fn slack(&self) -> f64 {
+        self.slack
+    }
+
+    /// Half the slack, ahead in the direction the arm last moved. Growing before it first moves
+    fn take_up(&self, rising: bool) -> f64 {
+        if rising {
+            self.slack / 2.
+        } else {
+            -self.slack / 2.
+        }
+    }
+
+    /// Whether the arm last moved to a growing angle
+    fn rising(&self) -> bool {
+        self.history.get().is_none_or(|(_, rising)| rising)
+    }
+}
+
+/// The arm angles a [`DoubleLinkage`] is searched over for [`Motion::get_arm_angle`], the
+/// most an arm joint turns
+const LINKAGE_RANGE: (f64, f64) = (0., 180.);
+
+/// Degrees between the arm angles sampled to find where the controlled angle crosses the one
+/// searched for, narrow enough that it doesn't turn back between two of them
+const LINKAGE_STEP: f64 = 1.;
+
+impl Motion for DirectDrive {
+    fn kind(&self) -> Option<MotionKind> {
+        Some((*self).into())
+    }
+
+    fn get_pivot_angle(&self, target: f64) -> f64 {
+        target
+    }
+
+    fn get_target_angle(&self, pivot: f64, _min: f64, _max: f64) -> f64 {
+        pivot
+    }
+
+    fn get_arm_angle(&self, pivot: f64) -> Option<f64> {
+        Some(pivot).filter(|angle| angle.is_finite())
+    }
+}
+
+impl Motion for DoubleLinkage {
+    fn kind(&self) -> Option<MotionKind> {
+        Some((*self).into())
+    }
+
+    fn get_pivot_angle(&self, target: f64) -> f64 {
+        let connection = self.connection_offset();
+        let controller = self.controller_offset();
+
+        let inner_target_angle = PI - target.to_radians() - connection.0;
+
+        let connection_to_controller = triangle::length_from_two_lengths_and_angle(
+            inner_target_angle,
+            connection.1,
+            controller.1,
+        );
+
+        let angle = {
+            let x = triangle::a_from_lengths(
+                connection_to_controller,
+                self.controller_pivot_rod_length,
+                self.connection_rod_length,
+            );
+
+            let y = triangle::a_from_lengths(connection_to_controller, controller.1, connection.1);
+
+            x + y
+        };
+
+        angle.to_degrees()
+    }
+
+    /// [`MotionError::OutOfReach`] for a target past the [`DoubleLinkage::achievable_range`]
+    fn try_pivot_angle(&self, target: f64) -> Result<f64, MotionError> {
+        let pivot = self.get_pivot_angle(target);
+        if pivot.is_finite() {
+            return Ok(pivot);
+        }
+
+        match self.achievable_range() {
+            Some((min, max)) if target.is_finite() => Err(MotionError::OutOfReach { min, max }),
+            _ => Err(MotionError::NoSolution),
+        }
+    }
+
+    /// Searches the arm angles from 0° to 180° the linkage closes for, the lowest one if more
+    /// than one turn the controlled angle to `pivot`
+    fn get_arm_angle(&self, pivot: f64) -> Option<f64> {
+        if !pivot.is_finite() {
+            return None;
+        }
+
+        let (min, max) = LINKAGE_RANGE;
+        let steps = ((max - min) / LINKAGE_STEP) as usize;
+        let angle = |step: usize| min + step as f64 * LINKAGE_STEP;
+        let crosses = |(low, high): (f64, f64)| {
+            let (low, high) = (self.get_pivot_angle(low), self.get_pivot_angle(high));
+            low.is_finite() && high.is_finite() && (low - pivot) * (high - pivot) <= 0.
+        };
+        let (low, high) = (0..steps)
+            .map(|step| (angle(step), angle(step + 1)))
+            .find(|&range| crosses(range))?;
+
+        Some(self.get_target_angle(pivot, low, high))
+    }
+}
+
+impl Motion for DirectDriveOffset {
+    fn kind(&self) -> Option<MotionKind> {
+        Some((*self).into())
+    }
+
+    fn get_pivot_angle(&self, target: f64) -> f64 {
+        target + self.offset
+    }
+
+    fn get_target_angle(&self, pivot: f64, _min: f64, _max: f64) -> f64 {
+        pivot - self.offset
+    }
+
+    fn get_arm_angle(&self, pivot: f64) -> Option<f64> {
+        Some(pivot - self.offset).filter(|angle| angle.is_finite())
+    }
+}
+
+impl Motion for GearDrive {
+    fn kind(&self) -> Option<MotionKind> {
+        Some((*self).into())
+    }
+
+    fn get_pivot_angle(&self, target: f64) -> f64 {
+        target * self.gear_ratio + self.offset
+    }
+
+    fn get_target_angle(&self, pivot: f64, _min: f64, _max: f64) -> f64 {
+        (pivot - self.offset) / self.gear_ratio
+    }
+
+    /// `None` for a gear ratio of 0, every arm angle turns the controlled angle to the offset
+    fn get_arm_angle(&self, pivot: f64) -> Option<f64> {
+        Some((pivot - self.offset) / self.gear_ratio).filter(|angle| angle.is_finite())
+    }
+}
+
+/// Turning to a new target moves the belt, a target the same as the last keeps the direction
+impl Motion for BeltDrive {
+    fn get_pivot_angle(&self, target: f64) -> f64 {
+        let rising = match self.history.get() {
+            Some((last, rising)) if target == last => rising,
+            Some((last, _)) => target > last,
+            None => true,
+        };
+        self.history.set(Some((target, rising)));
+
+        target * self.ratio() + self.offset + self.take_up(rising)
+    }
+
+    /// The target the controlled angle is at with the belt pulling the way it last did, this
+    /// doesn't move the belt
+    fn get_target_angle(&self, pivot: f64, _min: f64, _max: f64) -> f64 {
+        (pivot - self.offset - self.take_up(self.rising())) / self.ratio()
+    }
+
+    /// Like [`Motion::get_target_angle`], with the slack the belt last pulled with
+    fn get_arm_angle(&self, pivot: f64) -> Option<f64> {
+        Some(self.get_target_angle(pivot, 0., 0.)).filter(|angle| angle.is_finite())
+    }
+}
+
+impl<A: Motion, B: Motion> Chained<A, B> {
+    pub fn new(first: A, then: B) -> Self {
+        Chained { first, then }
+    }
+}
+
+impl<A: Motion, B: Motion> Motion for Chained<A, B> {
+    /// `None` unless both are a [`MotionStage`]
+    fn kind(&self) -> Option<MotionKind> {
+        let first = self.first.kind()?.try_into().ok()?;
+        let then = self.then.kind()?.try_into().ok()?;
+        Some(MotionKind::Chained(Chained { first, then }))
+    }
+
+    fn get_pivot_angle(&self, target: f64) -> f64 {
+        let between = self.first.get_pivot_angle(target);
+        self.then.get_pivot_angle(between)
+    }
+
+    /// Out of reach of `then` is [`MotionError::NoSolution`], its reach isn't in arm angles
+    fn try_pivot_angle(&self, target: f64) -> Result<f64, MotionError> {
+        let between = self.first.try_pivot_angle(target)?;
+        self.then
+            .try_pivot_angle(between)
+            .map_err(|_| MotionError::NoSolution)
+    }
+
+    /// Searches `then` between where `first` turns `min` and `max`
+    fn get_target_angle(&self, pivot: f64, min: f64, max: f64) -> f64 {
+        let (low, high) = (
+            self.first.get_pivot_angle(min),
+            self.first.get_pivot_angle(max),
+        );
+        let between = self
+            .then
+            .get_target_angle(pivot, low.min(high), low.max(high));
+        self.first.get_target_angle(between, min, max)
+    }
+
+    fn get_arm_angle(&self, pivot: f64) -> Option<f64> {
+        self.first.get_arm_angle(self.then.get_arm_angle(pivot)?)
+    }
+}
+
+impl MotionKind {
+    /// The motion it wraps
+    fn motion(&self) -> &dyn Motion {
+        match self {
+            MotionKind::DirectDrive(motion) => motion,
+            MotionKind::DirectDriveOffset(motion) => motion,
+            MotionKind::GearDrive(motion) => motion,
+            MotionKind::DoubleLinkage(motion) => motion,
+            MotionKind::Chained(motion) => motion,
+        }
+    }
+}
+
+impl MotionStage {
+    /// The motion it wraps
+    fn motion(&self) -> &dyn Motion {
+        match self {
+            MotionStage::DirectDrive(motion) => motion,
+            MotionStage::DirectDriveOffset(motion) => motion,
+            MotionStage::GearDrive(motion) => motion,
+            MotionStage::DoubleLinkage(motion) => motion,
+        }
+    }
+}
+
+impl Motion for MotionKind {
+    fn kind(&self) -> Option<MotionKind> {
+        Some(*self)
+    }
+
+    fn get_pivot_angle(&self, target: f64) -> f64 {
+        self.motion().get_pivot_angle(target)
+    }
+
+    fn try_pivot_angle(&self, target: f64) -> Result<f64, MotionError> {
+        self.motion().try_pivot_angle(target)
+    }
+
+    fn get_target_angle(&self, pivot: f64, min: f64, max: f64) -> f64 {
+        self.motion().get_target_angle(pivot, min, max)
+    }
+
+    fn get_arm_angle(&self, pivot: f64) -> Option<f64> {
+        self.motion().get_arm_angle(pivot)
+    }
+}
+
+impl Motion for MotionStage {
+    fn kind(&self) -> Option<MotionKind> {
+        Some((*self).into())
+    }
+
+    fn get_pivot_angle(&self, target: f64) -> f64 {
+        self.motion().get_pivot_angle(target)
+    }
+
+    fn try_pivot_angle(&self, target: f64) -> Result<f64, MotionError> {
+        self.motion().try_pivot_angle(target)
+    }
+
+    fn get_target_angle(&self, pivot: f64, min: f64, max: f64) -> f64 {
+        self.motion().get_target_angle(pivot, min, max)
+    }
+
+    fn get_arm_angle(&self, pivot: f64) -> Option<f64> {
+        self.motion().get_arm_angle(pivot)
+    }
+}
+
+impl From<DirectDrive> for MotionKind {
+    fn from(value: DirectDrive) -> Self {
+        MotionKind::DirectDrive(value)
+    }
+}
+
+impl From<DirectDriveOffset> for MotionKind {
+    fn from(value: DirectDriveOffset) -> Self {
+        MotionKind::DirectDriveOffset(value)
+    }
+}
+
+impl From<GearDrive> for MotionKind {
+    fn from(value: GearDrive) -> Self {
+        MotionKind::GearDrive(value)
+    }
+}
+
+impl From<DoubleLinkage> for MotionKind {
+    fn from(value: DoubleLinkage) -> Self {
+        MotionKind::DoubleLinkage(value)
+    }
+}
+
+impl From<Chained<MotionStage, MotionStage>> for MotionKind {
+    fn from(value: Chained<MotionStage, MotionStage>) -> Self {
+        MotionKind::Chained(value)
+    }
+}
+
+impl From<MotionStage> for MotionKind {
+    fn from(value: MotionStage) -> Self {
+        match value {
+            MotionStage::DirectDrive(motion) => motion.into(),
+            MotionStage::DirectDriveOffset(motion) => motion.into(),
+            MotionStage::GearDrive(motion) => motion.into(),
+            MotionStage::DoubleLinkage(motion) => motion.into(),
+        }
+    }
+}
+
+impl From<DirectDrive> for MotionStage {
+    fn from(value: DirectDrive) -> Self {
+        MotionStage::DirectDrive(value)
+    }
+}
+
+impl From<DirectDriveOffset> for MotionStage {
+    fn from(value: DirectDriveOffset) -> Self {
+        MotionStage::DirectDriveOffset(value)
+    }
+}
+
+impl From<GearDrive> for MotionStage {
+    fn from(value: GearDrive) -> Self {
+        MotionStage::GearDrive(value)
+    }
+}
+
+impl From<DoubleLinkage> for MotionStage {
+    fn from(value: DoubleLinkage) -> Self {
+        MotionStage::DoubleLinkage(value)
+    }
+}
+
+/// Gives a chain back, a chain can't be a stage of another
+impl TryFrom<MotionKind> for MotionStage {
+    type Error = MotionKind;
+
+    fn try_from(value: MotionKind) -> Result<Self, Self::Error> {
+        match value {
+            MotionKind::DirectDrive(motion) => Ok(MotionStage::DirectDrive(motion)),
+            MotionKind::DirectDriveOffset(motion) => Ok(MotionStage::DirectDriveOffset(motion)),
+            MotionKind::GearDrive(motion) => Ok(MotionStage::GearDrive(motion)),
+            MotionKind::DoubleLinkage(motion) => Ok(MotionStage::DoubleLinkage(motion)),
+            MotionKind::Chained(_) => Err(value),
+        }
+    }
+}
+
+impl fmt::Display for JointConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JointConfigError::GearRatio => {
+                write!(f, "the gear ratio is 0 or not finite")
+            }
+            JointConfigError::NoTeeth => write!(f, "a pulley of the belt has no teeth"),
+            JointConfigError::InvalidSlack => {
+                write!(f, "the slack of the belt is negative or not finite")
+            }
+        }
+    }
+}
+
+impl core::error::Error for JointConfigError {}
+
+impl fmt::Display for MotionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MotionError::OutOfReach { min, max } => {
+                write!(f, "the linkage only reaches from {min:.1}° to {max:.1}°")
+            }
+            MotionError::NoSolution => write!(f, "the motion has no angle for the target"),
+        }
+    }
+}
+
+impl core::error::Error for MotionError {}
+
+/// The [`MotionKind`] it is, every stage of a chain, or only where it turns 0 to for a motion
+/// that isn't one
+impl Debug for dyn Motion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind() {
+            Some(kind) => kind.fmt(f),
+            None => f
+                .debug_struct("MotionField")
+                .field("motion", &self.get_pivot_angle(0.))
+                .finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn motion_kind() {
+        let linkage = DoubleLinkage::new(1., 10., 10., 1., 10., 20.);
+        let motions: [(&dyn Motion, MotionKind); 4] = [
+            (&DirectDrive::new(), DirectDrive::new().into()),
+            (
+                &DirectDriveOffset { offset: 10. },
+                DirectDriveOffset { offset: 10. }.into(),
+            ),
+            (
+                &GearDrive::new(2.).unwrap().with_offset(10.),
+                GearDrive::new(2.).unwrap().with_offset(10.).into(),
+            ),
+            (&linkage, linkage.into()),
+        ];
+
+        // the same angles as the motion it wraps, both ways
+        for (motion, kind) in motions {
+            for target in [45., 60., 85.] {
+                let pivot = motion.get_pivot_angle(target);
+                assert_eq!(kind.get_pivot_angle(target), pivot);
+                assert_eq!(
+                    kind.get_target_angle(pivot, 45., 85.),
+                    motion.get_target_angle(pivot, 45., 85.)
+                );
+                assert!((kind.get_target_angle(pivot, 45., 85.) - target).abs() < 1e-9);
+                assert_eq!(kind.get_arm_angle(pivot), motion.get_arm_angle(pivot));
+            }
+            assert_eq!(motion.kind(), Some(kind));
+        }
+    }
+
+    #[test]
+    fn chained() {
+        let linkage = DoubleLinkage::new(1., 10., 10., 1., 10., 20.);
+        let gear = GearDrive::new(2.).unwrap();
+        let offset = DirectDriveOffset { offset: -15. };
+        let linked = Chained::new(MotionStage::DoubleLinkage(linkage), gear.into());
+        let geared = Chained::new(offset, gear);
+
+        // the same as turning the stages one after the other by hand
+        for target in [45., 60., 85.] {
+            let by_hand = gear.get_pivot_angle(linkage.get_pivot_angle(target));
+            assert_eq!(linked.get_pivot_angle(target), by_hand);
+            assert_eq!(linked.try_pivot_angle(target), Ok(by_hand));
+            assert!((linked.get_target_angle(by_hand, 45., 85.) - target).abs() < 1e-9);
+            assert!((linked.get_arm_angle(by_hand).unwrap() - target).abs() < 1e-9);
+
+            let by_hand = gear.get_pivot_angle(offset.get_pivot_angle(target));
+            assert_eq!(geared.get_pivot_angle(target), by_hand);
+            assert_eq!(geared.get_arm_angle(by_hand), Some(target));
+        }
+
+        // out of reach of the linkage in the chain
+        assert!(matches!(
+            linked.try_pivot_angle(170.),
+            Err(MotionError::OutOfReach { .. })
+        ));
+
+        // as a kind, only when both stages are one
+        let kind = MotionKind::Chained(linked);
+        assert_eq!(linked.kind(), Some(kind));
+        assert_eq!(kind.get_pivot_angle(60.), linked.get_pivot_angle(60.));
+        let stages = Chained::new(offset.into(), gear.into());
+        assert_eq!(geared.kind(), Some(MotionKind::Chained(stages)));
+        let belt = BeltDrive::new(20, 40, 0., 0.).unwrap();
+        assert_eq!(Chained::new(gear, belt).kind(), None);
+        assert_eq!(MotionStage::try_from(kind), Err(kind));
+    }
+
+    #[test]
+    fn chained_debug() {
+        let chain = Chained::new(
+            MotionStage::DoubleLinkage(DoubleLinkage::new(1., 10., 10., 1., 10., 20.)),
+            MotionStage::GearDrive(GearDrive::new(2.).unwrap()),
+        );
+        let motion: &dyn Motion = &chain;
+
+        // no format! without std, write into a buffer on the stack
+        struct Buffer {
+            bytes: [u8; 512],
+            len: usize,
+        }
+        impl core::fmt::Write for Buffer {
+            fn write_str(&mut self, s: &str) -> core::fmt::Result {
+                let end = self.len + s.len();
+                self.bytes
+                    .get_mut(self.len..end)
+                    .ok_or(core::fmt::Error)?
+                    .copy_from_slice(s.as_bytes());
+                self.len = end;
+                Ok(())
+            }
+        }
+        let mut buffer = Buffer {
+            bytes: [0; 512],
+            len: 0,
+        };
+        core::fmt::Write::write_fmt(&mut buffer, format_args!("{motion:?}")).unwrap();
+        let debug = core::str::from_utf8(&buffer.bytes[..buffer.len]).unwrap();
+
+        // every stage, not only where it turns 0 to
+        assert!(debug.starts_with("Chained"), "{debug}");
+        assert!(debug.contains("DoubleLinkage"), "{debug}");
+        assert!(debug.contains("gear_ratio: 2.0"), "{debug}");
+    }
+
+    #[test]
+    fn arm_angle() {
+        let closed: [&dyn Motion; 4] = [
+            &DirectDrive::new(),
+            &DirectDriveOffset { offset: -12.5 },
+            &GearDrive::new(2.5).unwrap().with_offset(10.),
+            &GearDrive::new(-0.5).unwrap(),
+        ];
+        for motion in closed {
+            for arm in (-360..=360).map(|angle| f64::from(angle) / 2.) {
+                let pivot = motion.get_pivot_angle(arm);
+                let back = motion.get_arm_angle(pivot).unwrap();
+                assert!((back - arm).abs() < 1e-12, "{arm} {back}");
+            }
+            assert_eq!(motion.get_arm_angle(f64::NAN), None);
+            assert_eq!(motion.get_arm_angle(f64::INFINITY), None);
+        }
+        assert_eq!(
+            GearDrive {
+                gear_ratio: 0.,
+                offset: 0.
+            }
+            .get_arm_angle(10.),
+            None
+        );
+
+        let linkage = DoubleLinkage::new(1., 10., 10., 1., 10., 20.);
+        let arms = (0..=180)
+            .map(f64::from)
+            .filter(|&arm| linkage.get_pivot_angle(arm).is_finite());
+        assert!(arms.clone().count() > 90);
+        for arm in arms.clone() {
+            let back = linkage.get_arm_angle(linkage.get_pivot_angle(arm)).unwrap();
+            assert!((back - arm).abs() < 1e-9, "{arm} {back}");
+        }
+
+        // further than the linkage turns the controlled angle either way
+        let pivots = arms.map(|arm| linkage.get_pivot_angle(arm));
+        let lowest = pivots.clone().fold(f64::INFINITY, f64::min);
+        let highest = pivots.fold(f64::NEG_INFINITY, f64::max);
+        assert_eq!(linkage.get_arm_angle(lowest - 1.), None);
+        assert_eq!(linkage.get_arm_angle(highest + 1.), None);
+        assert_eq!(linkage.get_arm_angle(f64::NAN), None);
+
+        // no inverse for a motion that doesn't have one
+        struct Squared;
+        impl Motion for Squared {
+            fn get_pivot_angle(&self, target: f64) -> f64 {
+                target * target
+            }
+        }
+        assert_eq!(Squared.get_arm_angle(4.), None);
+    }
+
+    #[test]
+    fn linkage_reach() {
+        // the rods only reach the arm up to about 113°
+        let linkage = DoubleLinkage::new(1., 10., 10., 1., 10., 20.);
+        let (min, max) = linkage.achievable_range().unwrap();
+        assert_eq!(min, 0.);
+        assert!((110. ..115.).contains(&max), "{max}");
+        assert!(linkage.get_pivot_angle(max).is_finite());
+        assert!(linkage.get_pivot_angle(max + 1e-9).is_nan());
+
+        assert_eq!(
+            linkage.try_pivot_angle(60.),
+            Ok(linkage.get_pivot_angle(60.))
+        );
+        assert_eq!(
+            linkage.try_pivot_angle(150.),
+            Err(MotionError::OutOfReach { min, max })
+        );
+        let kind = MotionKind::from(linkage);
+        assert_eq!(kind.try_pivot_angle(150.), linkage.try_pivot_angle(150.));
+        assert_eq!(
+            linkage.try_pivot_angle(f64::NAN),
+            Err(MotionError::NoSolution)
+        );
+
+        // the rods are too short to reach the arm at any angle
+        let impossible = DoubleLinkage::new(1., 2., 10., 1., 0.5, 0.5);
+        assert_eq!(impossible.achievable_range(), None);
+        assert_eq!(
+            impossible.try_pivot_angle(60.),
+            Err(MotionError::NoSolution)
+        );
+
+        // short rods only reach the arm folded past about 127°
+        let short = DoubleLinkage::new(1., 10., 10., 1., 4., 4.);
+        let (min, max) = short.achievable_range().unwrap();
+        assert!((125. ..130.).contains(&min) && max == 180., "{min} {max}");
+        assert!(short.get_pivot_angle(min).is_finite());
+        assert!(short.get_pivot_angle(min - 1e-6).is_nan());
+
+        // the motions that always have an angle
+        assert_eq!(DirectDrive::new().try_pivot_angle(200.), Ok(200.));
+        assert_eq!(
+            DirectDrive::new().try_pivot_angle(f64::INFINITY),
+            Err(MotionError::NoSolution)
+        );
+    }
+
+    #[test]
+    fn gear_drive() {
+        let gear = GearDrive::new(2.).unwrap().with_offset(10.);
+        assert_eq!(gear.get_pivot_angle(0.), 10.);
+        assert_eq!(gear.get_pivot_angle(40.), 90.);
+        assert_eq!(gear.get_target_angle(90., 0., 180.), 40.);
+        assert_eq!(gear.get_arm_angle(10.), Some(0.));
+
+        assert_eq!(GearDrive::new(0.), Err(JointConfigError::GearRatio));
+        assert_eq!(GearDrive::new(-0.), Err(JointConfigError::GearRatio));
+        assert_eq!(GearDrive::new(f64::NAN), Err(JointConfigError::GearRatio));
+        assert_eq!(
+            GearDrive::new(f64::INFINITY),
+            Err(JointConfigError::GearRatio)
+        );
+        assert_eq!(GearDrive::new(-1.5).unwrap().get_pivot_angle(10.), -15.);
+    }
+
+    #[test]
+    fn belt_drive() {
+        assert_eq!(
+            BeltDrive::new(20, 0, 0., 1.),
+            Err(JointConfigError::NoTeeth)
+        );
+        assert_eq!(
+            BeltDrive::new(20, 40, 0., -1.),
+            Err(JointConfigError::InvalidSlack)
+        );
+        assert_eq!(
+            BeltDrive::new(20, 40, 0., f64::NAN),
+            Err(JointConfigError::InvalidSlack)
+        );
+
+        let belt = BeltDrive::new(16, 40, 5., 1.5).unwrap();
+        assert_eq!((belt.driver_teeth(), belt.driven_teeth()), (16, 40));
+        assert_eq!(belt.ratio(), 2.5);
+        assert_eq!(belt.kind(), None);
+
+        // the first target pulls as if it was growing
+        assert_eq!(belt.get_pivot_angle(10.), 10. * 2.5 + 5. + 0.75);
+        // the same target again doesn't move the belt
+        assert_eq!(belt.get_pivot_angle(10.), 10. * 2.5 + 5. + 0.75);
+        assert!((belt.get_target_angle(30.75, 0., 90.) - 10.).abs() < 1e-12);
+        let back = belt.get_target_angle(30.75, 0., 90.);
+        assert_eq!(belt.get_arm_angle(30.75), Some(back));
+        assert_eq!(belt.get_arm_angle(f64::NAN), None);
+    }
+
+    #[test]
+    fn belt_slack() {
+        let belt = BeltDrive::new(20, 30, -3., 2.).unwrap();
+        let forward: [f64; 91] = core::array::from_fn(|t| belt.get_pivot_angle(t as f64));
+        let backward: [f64; 91] = core::array::from_fn(|t| belt.get_pivot_angle((90 - t) as f64));
+
+        // the turn at 90 stays rising until the arm moves back from it
+        for (i, (&up, &down)) in forward.iter().zip(backward.iter().rev()).enumerate() {
+            let expected = if i == 90 { 0. } else { 2. };
+            assert!((up - down - expected).abs() < 1e-12, "{i}: {up} {down}");
+        }
+
+        // the target angle reads back with the slack the belt last pulled with
+        for target in [0., 45., 80.] {
+            let pivot = belt.get_pivot_angle(target);
+            assert!((belt.get_target_angle(pivot, 0., 90.) - target).abs() < 1e-12);
+        }
+    }
+}
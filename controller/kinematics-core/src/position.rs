@@ -0,0 +1,2223 @@
+use crate::{
+    float::{atan2, cos, powi, sin, sqrt},
+    triangle::a_from_lengths,
+};
+use core::{
+    f64::consts::{PI, TAU},
+    fmt,
+    ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, RangeInclusive, Sub, SubAssign},
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Defines a 3d position using x, y and z coordinates
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CordinateVec {
+    /// Side to side
+    pub x: f64,
+
+    /// Forward and backward
+    pub y: f64,
+
+    /// Up and down
+    pub z: f64,
+}
+
+/// Name of [`CordinateVec`] in the old copy of the kinematics, for code written against it
+#[deprecated(note = "use CordinateVec")]
+pub type Vec3D = CordinateVec;
+
+/// Name of [`SphereVec`] in the old copy of the kinematics, for code written against it
+#[deprecated(note = "use SphereVec")]
+pub type SpherePos = SphereVec;
+
+/// Defines a position using spherical coordinates
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SphereVec {
+    /// Horizontal angle from origin to position from the x axis
+    pub azmut: f64,
+
+    /// Vertical angle from origin to position from the z axis
+    pub polar: f64,
+
+    /// Distance from origin
+    pub distance: f64,
+
+    /// Distance from origin on flat ground, `distance * sin(polar)` with the polar angle from
+    /// the z axis
+    pub flat_distance: f64,
+}
+
+/// Defines a position using cylindrical coordinates, how far round the base, how far out and
+/// how high
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CylinderVec {
+    /// Horizontal angle from origin to position from the x axis
+    pub azmut: f64,
+
+    /// Distance from the z axis
+    pub radius: f64,
+
+    /// Up and down
+    pub z: f64,
+}
+
+/// The `(base, shoulder, elbow)` angles that put the head at a position, see
+/// [`CordinateVec::ik_solutions`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IkSolutions {
+    /// The elbow bent back towards the z axis, what [`CordinateVec::inverse_kinematics`]
+    /// returns
+    pub primary: (f64, f64, f64),
+
+    /// The elbow bent the other way, mirrored over the line to the head, with the elbow past
+    /// 180°. None with the arm stretched out or folded up, where both are the same
+    pub mirrored: Option<(f64, f64, f64)>,
+}
+
+impl IkSolutions {
+    /// Both solutions, the primary one first
+    pub fn iter(&self) -> impl Iterator<Item = (f64, f64, f64)> {
+        core::iter::once(self.primary).chain(self.mirrored)
+    }
+
+    /// The solution the joints travel the least to from `current`, summed over the joints in
+    /// degrees. The primary one on a tie
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// let solutions = CordinateVec::new(0., 100., 0.).ik_solutions(100., 100.).unwrap();
+    /// let mirrored = solutions.mirrored.unwrap();
+    ///
+    /// assert_eq!(solutions.closest_to(mirrored), mirrored);
+    /// ```
+    pub fn closest_to(&self, current: (f64, f64, f64)) -> (f64, f64, f64) {
+        let travel = |(base, shoulder, elbow): (f64, f64, f64)| {
+            (base - current.0).abs() + (shoulder - current.1).abs() + (elbow - current.2).abs()
+        };
+
+        match self.mirrored {
+            Some(mirrored) if travel(mirrored) < travel(self.primary) => mirrored,
+            _ => self.primary,
+        }
+    }
+}
+
+/// One of the joints the inverse kinematics solve for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum JointName {
+    Base,
+    Shoulder,
+    Elbow,
+    Wrist,
+}
+
+/// Range in degrees each joint can turn through, see
+/// [`CordinateVec::inverse_kinematics_limited`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IkLimits {
+    pub base: RangeInclusive<f64>,
+    pub shoulder: RangeInclusive<f64>,
+    pub elbow: RangeInclusive<f64>,
+}
+
+/// Why the inverse kinematics found no angles for a position
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum KinematicsError {
+    /// Further from the shoulder than the arm reaches stretched out
+    OutOfReach { distance: f64, max: f64 },
+
+    /// Closer to the shoulder than the arm folds up
+    BelowMinReach,
+
+    /// Within reach but the arm lengths and the distance make no triangle, a rounding error
+    /// right at the edge of the reach
+    InvalidTriangle,
+
+    /// Neither way the elbow can bend keeps the joint in its range, the first joint out of it
+    JointLimit(JointName),
+
+    /// The position isn't finite or an arm isn't longer than 0
+    Degenerate,
+
+    /// Closer to the z axis than the tool reaches across from its mount, see
+    /// [`CordinateVec::tool_mount`]
+    ToolOffset,
+}
+
+impl CordinateVec {
+    /// Creates a new Position
+    /// # Arguments
+    /// * `x` - Side to side position
+    /// * `y` - Forward and backward position
+    /// * `z` - Up and down position
+    #[allow(unused)]
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Clamp all the values in the position to a range
+    ///
+    /// # Arguments
+    /// * `min` - The minimum value for the position
+    /// * `max` - The maximum value for the position
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    /// let mut position = CordinateVec::new(12., 9., -50.);
+    /// position.cube_clamp(-5., 10.);
+    ///
+    /// assert_eq!(position, CordinateVec::new(10., 9., -5.));
+    /// ```
+    pub fn cube_clamp(&mut self, min: f64, max: f64) {
+        self.clamp_vec(Self::new(min, min, min), Self::new(max, max, max));
+    }
+
+    /// Clamp every axis of the position to its own range
+    ///
+    /// # Arguments
+    /// * `min` - The minimum value for each axis
+    /// * `max` - The maximum value for each axis
+    ///
+    /// # Panics
+    /// If `min` is greater than `max` on any axis, or either is NaN, like [`f64::clamp`]
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    /// let mut position = CordinateVec::new(12., 9., -50.);
+    /// position.clamp_vec(CordinateVec::new(-5., -5., -20.), CordinateVec::new(10., 5., 20.));
+    ///
+    /// assert_eq!(position, CordinateVec::new(10., 5., -20.));
+    /// ```
+    pub fn clamp_vec(&mut self, min: Self, max: Self) {
+        self.x = self.x.clamp(min.x, max.x);
+        self.y = self.y.clamp(min.y, max.y);
+        self.z = self.z.clamp(min.z, max.z);
+    }
+
+    /// The smaller value of each axis
+    pub fn component_min(&self, other: &Self) -> Self {
+        Self::new(
+            self.x.min(other.x),
+            self.y.min(other.y),
+            self.z.min(other.z),
+        )
+    }
+
+    /// The larger value of each axis
+    pub fn component_max(&self, other: &Self) -> Self {
+        Self::new(
+            self.x.max(other.x),
+            self.y.max(other.y),
+            self.z.max(other.z),
+        )
+    }
+
+    /// The absolute value of each axis
+    pub fn abs(&self) -> Self {
+        Self::new(self.x.abs(), self.y.abs(), self.z.abs())
+    }
+
+    /// Calculates the angles for the arm to reach a position, with the elbow bent back
+    /// towards the z axis
+    ///
+    /// The primary one of [`CordinateVec::ik_solutions`]
+    ///
+    /// # Arguments
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    ///
+    /// # Returns
+    /// Ok(Arm) - The angles for the arm to reach the position
+    ///
+    /// Err(KinematicsError) - Why no valid solution was found
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// let mut position = CordinateVec::new(1., 1., 1.);
+    ///
+    /// let (base, shoulder, elbow) = position.inverse_kinematics(10., 10.).unwrap();
+    /// ```
+    pub fn inverse_kinematics(
+        &mut self,
+        upper_arm: f64,
+        lower_arm: f64,
+    ) -> Result<(f64, f64, f64), KinematicsError> {
+        self.ik_solutions(upper_arm, lower_arm)
+            .map(|solutions| solutions.primary)
+    }
+
+    /// Calculates both ways the arm can bend to reach a position
+    ///
+    /// # Arguments
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    ///
+    /// # Returns
+    /// Ok(IkSolutions) - The angles for the arm to reach the position
+    ///
+    /// Err(KinematicsError) - Why no valid solution was found
+    pub fn ik_solutions(
+        &self,
+        upper_arm: f64,
+        lower_arm: f64,
+    ) -> Result<IkSolutions, KinematicsError> {
+        if !(self.is_finite() && upper_arm > 0. && lower_arm > 0.) {
+            return Err(KinematicsError::Degenerate);
+        }
+
+        // spherical representation of the position
+        let spos = &self.to_sphere();
+
+        // base angle
+        let base = spos.azmut.to_degrees() + 90.;
+
+        // elbow angle
+        let elbow = a_from_lengths(upper_arm, lower_arm, spos.distance).to_degrees();
+
+        // the line to the head measured from the z axis over its whole range and the upper
+        // arm's lead on it, so the shoulder is continuous wherever the head goes
+        let polar = atan2(spos.flat_distance, self.z).to_degrees();
+        let lead = a_from_lengths(spos.distance, lower_arm, upper_arm).to_degrees();
+
+        // make sure all the angles are valid
+        if !(polar.is_finite() && lead.is_finite() && base.is_finite() && elbow.is_finite()) {
+            let max = upper_arm + lower_arm;
+            return Err(if spos.distance > max {
+                KinematicsError::OutOfReach {
+                    distance: spos.distance,
+                    max,
+                }
+            } else if spos.distance < (upper_arm - lower_arm).abs() {
+                KinematicsError::BelowMinReach
+            } else {
+                KinematicsError::InvalidTriangle
+            });
+        }
+
+        Ok(IkSolutions {
+            primary: (base, polar + lead, elbow),
+            mirrored: (lead > 1e-9).then_some((base, polar - lead, 360. - elbow)),
+        })
+    }
+
+    /// Calculates the angles for the arm to reach a position with every joint in its range
+    ///
+    /// Only the solutions of [`CordinateVec::ik_solutions`] with all the joints in `limits`
+    /// are kept, the primary one is the mirrored one if that's the only one left
+    ///
+    /// # Arguments
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    /// * `limits` - The range of each joint
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::{CordinateVec, IkLimits, JointName, KinematicsError};
+    ///
+    /// let limits = IkLimits {
+    ///     base: 0.0..=180.,
+    ///     shoulder: 0.0..=180.,
+    ///     elbow: 0.0..=180.,
+    /// };
+    ///
+    /// // behind the base
+    /// let behind = CordinateVec::new(-100., 10., 50.);
+    /// assert_eq!(
+    ///     behind.inverse_kinematics_limited(100., 100., &limits),
+    ///     Err(KinematicsError::JointLimit(JointName::Base))
+    /// );
+    /// ```
+    pub fn inverse_kinematics_limited(
+        &self,
+        upper_arm: f64,
+        lower_arm: f64,
+        limits: &IkLimits,
+    ) -> Result<IkSolutions, KinematicsError> {
+        let solutions = self.ik_solutions(upper_arm, lower_arm)?;
+
+        let out_of_range = |(base, shoulder, elbow): (f64, f64, f64)| {
+            [
+                (JointName::Base, &limits.base, base),
+                (JointName::Shoulder, &limits.shoulder, shoulder),
+                (JointName::Elbow, &limits.elbow, elbow),
+            ]
+            .into_iter()
+            .find(|(_, range, angle)| !range.contains(angle))
+            .map(|(joint, ..)| joint)
+        };
+
+        let mut valid = solutions
+            .iter()
+            .filter(|&angles| out_of_range(angles).is_none());
+        match valid.next() {
+            Some(primary) => Ok(IkSolutions {
+                primary,
+                mirrored: valid.next(),
+            }),
+            None => Err(KinematicsError::JointLimit(
+                out_of_range(solutions.primary).unwrap_or(JointName::Base),
+            )),
+        }
+    }
+
+    /// Calculates the position the arm reaches with the given angles, the reverse of
+    /// [`CordinateVec::inverse_kinematics`]
+    ///
+    /// The shoulder angle is taken to be measured from the z axis, like inverse_kinematics
+    /// returns it. An elbow past 180° bends the other way, like the mirrored one of
+    /// [`CordinateVec::ik_solutions`]
+    ///
+    /// # Arguments
+    /// * `base` - base angle in degrees
+    /// * `shoulder` - shoulder angle in degrees
+    /// * `elbow` - angle between the upper and lower arm in degrees
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    pub fn forward_kinematics(
+        base: f64,
+        shoulder: f64,
+        elbow: f64,
+        upper_arm: f64,
+        lower_arm: f64,
+    ) -> Self {
+        // distance from the shoulder to the head
+        let distance = sqrt(
+            powi(upper_arm, 2) + powi(lower_arm, 2)
+                - 2. * upper_arm * lower_arm * cos(elbow.to_radians()),
+        );
+
+        // angle between the upper arm and the line to the head
+        let offset = a_from_lengths(distance, lower_arm, upper_arm);
+        let offset = if elbow > 180. { -offset } else { offset };
+
+        let polar = shoulder.to_radians() - offset;
+        let azmut = (base - 90.).to_radians();
+        let flat_distance = distance * sin(polar);
+
+        Self {
+            x: flat_distance * cos(azmut),
+            y: flat_distance * sin(azmut),
+            z: distance * cos(polar),
+        }
+    }
+
+    /// Where the wrist has to be for the tip of the claw to be here, with the claw pointing
+    /// `claw_pitch` degrees from the z axis in line with the arm, see [`wrist_angle`]
+    ///
+    /// # Arguments
+    /// * `wrist_length` - The length from the wrist to the tip of the claw
+    /// * `claw_pitch` - Angle of the claw from the z axis in degrees, 90 holds it level
+    pub fn wrist_point(&self, wrist_length: f64, claw_pitch: f64) -> Self {
+        *self - claw(self.azmut(), wrist_length, claw_pitch)
+    }
+
+    /// Where the tip of the claw is with the wrist here, the reverse of
+    /// [`CordinateVec::wrist_point`]
+    ///
+    /// # Arguments
+    /// * `wrist_length` - The length from the wrist to the tip of the claw
+    /// * `claw_pitch` - Angle of the claw from the z axis in degrees, 90 holds it level
+    pub fn claw_tip(&self, wrist_length: f64, claw_pitch: f64) -> Self {
+        *self + claw(self.azmut(), wrist_length, claw_pitch)
+    }
+
+    /// Where a tool `offset` from its mount has to be mounted for its tip to be here
+    ///
+    /// The offset turns with the base: x points straight out from the z axis, y across
+    /// counterclockwise seen from above and z up. `None` if the tip is closer to the z axis
+    /// than the offset is across
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::{assert_approx_eq, position::CordinateVec};
+    ///
+    /// // a gripper reaching 40 further out than where it's mounted
+    /// let offset = CordinateVec::new(40., 0., 0.);
+    /// let mount = CordinateVec::new(0., 150., 20.).tool_mount(offset).unwrap();
+    ///
+    /// assert_approx_eq!(mount, CordinateVec::new(0., 110., 20.));
+    /// assert_approx_eq!(mount.tool_tip(offset), CordinateVec::new(0., 150., 20.));
+    /// ```
+    pub fn tool_mount(&self, offset: Self) -> Option<Self> {
+        if offset == Self::default() {
+            return Some(*self);
+        }
+
+        // the tip is out and across from the mount, at right angles
+        let out = sqrt(powi(self.f_dst(), 2) - powi(offset.y, 2));
+        if !out.is_finite() {
+            return None;
+        }
+        let azmut = self.azmut() - atan2(offset.y, out);
+        let flat_distance = out - offset.x;
+
+        Some(Self {
+            x: flat_distance * cos(azmut),
+            y: flat_distance * sin(azmut),
+            z: self.z - offset.z,
+        })
+    }
+
+    /// Where the tip of a tool `offset` from its mount is with the mount here, the reverse of
+    /// [`CordinateVec::tool_mount`]
+    pub fn tool_tip(&self, offset: Self) -> Self {
+        *self + offset.rotated_z(self.azmut())
+    }
+
+    /// Calculates the angles for the arm to put the tip of the claw at a position, with the
+    /// claw held at `claw_pitch`
+    ///
+    /// The base, shoulder and elbow are the ones of [`CordinateVec::inverse_kinematics`] for
+    /// the [`CordinateVec::wrist_point`], the wrist turns the claw to the pitch from there
+    ///
+    /// # Arguments
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    /// * `wrist_length` - The length from the wrist to the tip of the claw
+    /// * `claw_pitch` - Angle of the claw from the z axis in degrees, 90 holds it level
+    ///
+    /// # Returns
+    /// Ok((base, shoulder, elbow, wrist)) - The angles for the arm to reach the position
+    ///
+    /// Err(KinematicsError) - Why the wrist can't be put where it has to be
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::{assert_approx_eq, position::{wrist_angle, CordinateVec}};
+    ///
+    /// let tip = CordinateVec::new(0., 150., 20.);
+    /// let angles = tip.inverse_kinematics_4dof(100., 100., 40., 90.).unwrap();
+    /// let (base, shoulder, elbow, wrist) = angles;
+    ///
+    /// assert_eq!(wrist, wrist_angle(shoulder, elbow, 90.));
+    /// let wrist_point = CordinateVec::forward_kinematics(base, shoulder, elbow, 100., 100.);
+    /// assert_approx_eq!(wrist_point.claw_tip(40., 90.), tip);
+    /// ```
+    pub fn inverse_kinematics_4dof(
+        &self,
+        upper_arm: f64,
+        lower_arm: f64,
+        wrist_length: f64,
+        claw_pitch: f64,
+    ) -> Result<(f64, f64, f64, f64), KinematicsError> {
+        if !(wrist_length.is_finite() && claw_pitch.is_finite()) {
+            return Err(KinematicsError::Degenerate);
+        }
+
+        let (base, shoulder, elbow) = self
+            .wrist_point(wrist_length, claw_pitch)
+            .ik_solutions(upper_arm, lower_arm)?
+            .primary;
+        let wrist = wrist_angle(shoulder, elbow, claw_pitch);
+        Ok((base, shoulder, elbow, wrist))
+    }
+
+    /// Calculates the distance from origin on flat ground
+    ///
+    /// since this value is only on the x,y plane the z axis is irrelevant
+    ///
+    /// sqrt(X^2 + Y^2)
+    pub fn f_dst(&self) -> f64 {
+        sqrt(powi(self.x, 2) + powi(self.y, 2))
+    }
+
+    /// True if no coordinate is NaN or infinite
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
+    /// Calculates the distance from origin
+    ///
+    /// sqrt(X^2 + Y^2 + Z^2)
+    pub fn dst(&self) -> f64 {
+        sqrt(powi(self.x, 2) + powi(self.y, 2) + powi(self.z, 2))
+    }
+
+    /// Distance from here to `other`
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// let from = CordinateVec::new(1., 2., 3.);
+    ///
+    /// assert_eq!(from.distance_to(&CordinateVec::new(4., 6., 3.)), 5.);
+    /// ```
+    pub fn distance_to(&self, other: &Self) -> f64 {
+        (*other - *self).dst()
+    }
+
+    /// Unit vector from here towards `other`, `None` when they're the same point
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// let from = CordinateVec::new(1., 2., 3.);
+    ///
+    /// let down = from.direction_to(&CordinateVec::new(1., 2., -5.));
+    ///
+    /// assert_eq!(down, Some(CordinateVec::new(0., 0., -1.)));
+    /// assert_eq!(from.direction_to(&from), None);
+    /// ```
+    pub fn direction_to(&self, other: &Self) -> Option<Self> {
+        (*other - *self).normalized()
+    }
+
+    /// Dot product, how far this goes along `other` scaled by its length
+    ///
+    /// X1 * X2 + Y1 * Y2 + Z1 * Z2
+    pub fn dot(&self, other: &Self) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Length of the vector, the same as [`CordinateVec::dst`]
+    pub fn length(&self) -> f64 {
+        self.dst()
+    }
+
+    /// Cross product, at right angles to both with the length of the area they span
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// let x = CordinateVec::new(1., 0., 0.);
+    /// let y = CordinateVec::new(0., 1., 0.);
+    ///
+    /// assert_eq!(x.cross(&y), CordinateVec::new(0., 0., 1.));
+    /// ```
+    pub fn cross(&self, other: &Self) -> Self {
+        Self {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    /// The same direction with a length of 1, `None` for the zero vector or one that isn't
+    /// finite, since they have no direction
+    pub fn normalized(&self) -> Option<Self> {
+        let length = self.length();
+        (length > 0. && length.is_finite()).then(|| *self / length)
+    }
+
+    /// The same direction with the given length, the zero vector stays zero
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// assert_eq!(CordinateVec::new(0., 0., -4.).scale_to(10.), CordinateVec::new(0., 0., -10.));
+    /// assert_eq!(CordinateVec::default().scale_to(10.), CordinateVec::default());
+    /// ```
+    pub fn scale_to(&self, length: f64) -> Self {
+        self.normalized().unwrap_or_default() * length
+    }
+
+    /// The point `t` of the way from here to `other`, beyond them for `t` outside of 0..=1
+    ///
+    /// `t` of 0 and 1 give the ends exactly, not just close to them
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// let from = CordinateVec::new(0., 10., 4.);
+    /// let to = CordinateVec::new(10., 10., 0.);
+    ///
+    /// assert_eq!(from.lerp(&to, 0.25), CordinateVec::new(2.5, 10., 3.));
+    /// ```
+    pub fn lerp(&self, other: &Self, t: f64) -> Self {
+        match t {
+            0. => *self,
+            1. => *other,
+            _ => *self + (*other - *self) * t,
+        }
+    }
+
+    /// Go at most `max_step` towards `target`, landing exactly on it once it's that close
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// let target = CordinateVec::new(0., 0., 3.);
+    /// let step = CordinateVec::default().move_towards(&target, 2.);
+    ///
+    /// assert_eq!(step, CordinateVec::new(0., 0., 2.));
+    /// assert_eq!(step.move_towards(&target, 2.), target);
+    /// ```
+    pub fn move_towards(&self, target: &Self, max_step: f64) -> Self {
+        let towards = *target - *self;
+        if towards.dst() <= max_step {
+            *target
+        } else {
+            *self + towards.scale_to(max_step)
+        }
+    }
+
+    /// Turned `radians` about the z axis, counterclockwise seen from above like
+    /// [`CordinateVec::azmut`] counts
+    ///
+    /// # Examples
+    /// ```rust
+    /// use core::f64::consts::FRAC_PI_2;
+    /// use kinematics_core::{assert_approx_eq, position::CordinateVec};
+    ///
+    /// let turned = CordinateVec::new(10., 0., 5.).rotated_z(FRAC_PI_2);
+    ///
+    /// assert_approx_eq!(turned, CordinateVec::new(0., 10., 5.));
+    /// ```
+    pub fn rotated_z(&self, radians: f64) -> Self {
+        let (sin, cos) = (sin(radians), cos(radians));
+        Self {
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos,
+            z: self.z,
+        }
+    }
+
+    /// Turned `radians` about `axis` through the origin, counterclockwise looking down the
+    /// axis towards the origin, with Rodrigues' rotation formula
+    ///
+    /// The length of `axis` doesn't matter, a zero or non finite one has no direction and
+    /// leaves the position as it is
+    ///
+    /// # Examples
+    /// ```rust
+    /// use core::f64::consts::FRAC_PI_2;
+    /// use kinematics_core::{assert_approx_eq, position::CordinateVec};
+    ///
+    /// let axis = CordinateVec::new(1., 0., 0.);
+    /// let turned = CordinateVec::new(0., 10., 0.).rotated_about(axis, FRAC_PI_2);
+    ///
+    /// assert_approx_eq!(turned, CordinateVec::new(0., 0., 10.));
+    /// ```
+    pub fn rotated_about(&self, axis: Self, radians: f64) -> Self {
+        let Some(axis) = axis.normalized() else {
+            return *self;
+        };
+        let (sin, cos) = (sin(radians), cos(radians));
+
+        *self * cos + axis.cross(self) * sin + axis * axis.dot(self) * (1. - cos)
+    }
+
+    /// Calculates the horizontal angle from origin to position from the x axis
+    ///
+    /// arctan(y / x) in whichever quadrant the position is, from -PI to PI. PI/2 straight
+    /// along y and 0 straight above or below the origin
+    pub fn azmut(&self) -> f64 {
+        atan2(self.y, self.x)
+    }
+
+    /// Calculates the vertical angle from origin to position from the z axis
+    ///
+    /// arctan(f_dst / z) on whichever side of the origin z is, from 0 straight up to PI
+    /// straight down. PI/2 level with the origin and 0 on it
+    pub fn polar(&self) -> f64 {
+        atan2(self.f_dst(), self.z)
+    }
+
+    /// Converts a 3d position to spherical coordinates
+    ///
+    /// Due to floating point errors the position might
+    /// not be exactly right but it is usualy close enough
+    ///
+    /// # Examples
+    /// ```rust
+    /// use core::f64::consts::{PI, SQRT_2};
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// let position = CordinateVec::new(1., 1., 0.);
+    ///
+    /// let sphere = position.to_sphere();
+    ///
+    /// assert_eq!(sphere.azmut, PI / 4.);
+    /// assert_eq!(sphere.polar, PI / 2.);
+    /// assert_eq!(sphere.distance, SQRT_2);
+    /// assert_eq!(sphere.flat_distance, SQRT_2);
+    /// ```
+    pub fn to_sphere(self) -> SphereVec {
+        SphereVec {
+            azmut: self.azmut(),
+            polar: self.polar(),
+            distance: self.dst(),
+            flat_distance: self.f_dst(),
+        }
+    }
+
+    /// Converts a 3d position to cylindrical coordinates, the azimuth from -PI to PI like
+    /// [`CordinateVec::azmut`]
+    ///
+    /// # Examples
+    /// ```rust
+    /// use core::f64::consts::PI;
+    /// use kinematics_core::position::CordinateVec;
+    ///
+    /// let cylinder = CordinateVec::new(0., -3., 7.).to_cylinder();
+    ///
+    /// assert_eq!(cylinder.azmut, -PI / 2.);
+    /// assert_eq!(cylinder.radius, 3.);
+    /// assert_eq!(cylinder.z, 7.);
+    /// ```
+    pub fn to_cylinder(self) -> CylinderVec {
+        CylinderVec {
+            azmut: self.azmut(),
+            radius: self.f_dst(),
+            z: self.z,
+        }
+    }
+}
+
+impl SphereVec {
+    /// Creates a new position
+    ///
+    /// # Arguments
+    /// * `azmut` - Horizontal angle from origin to position from the x axis
+    /// * `polar` - Vertical angle from origin to position from the z axis
+    /// * `dst` - Distance from origin
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::SphereVec;
+    /// let pos = SphereVec::new(0., 0., 0.);
+    /// ```
+    #[allow(unused)]
+    pub fn new(azmut: f64, polar: f64, dst: f64) -> Self {
+        Self {
+            azmut,
+            polar,
+            distance: dst,
+            flat_distance: dst * sin(polar),
+        }
+    }
+
+    /// updates the distance from origin
+    ///
+    /// The angles stay as they are, so the position moves straight towards or away from origin
+    ///
+    /// # Arguments
+    /// * `dst` - The new distance from origin
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::SphereVec;
+    /// let mut pos = SphereVec::new(0., 0., 0.);
+    ///
+    /// pos.update_dst(10.);
+    ///
+    /// assert_eq!(pos.distance, 10.);
+    /// ```
+    pub fn update_dst(&mut self, dst: f64) {
+        self.distance = dst;
+        self.flat_distance = dst * sin(self.polar);
+    }
+
+    /// The same position at azimuth `azmut`, the polar angle and distances stay
+    ///
+    /// # Examples
+    /// ```rust
+    /// use core::f64::consts::PI;
+    /// use kinematics_core::position::SphereVec;
+    ///
+    /// // orbit the base a quarter turn
+    /// let pos = SphereVec::new(0., PI / 2., 10.);
+    /// let turned = pos.with_azimuth(pos.azmut + PI / 2.).to_position();
+    ///
+    /// assert!(turned.x.abs() < 1e-9 && (turned.y - 10.).abs() < 1e-9);
+    /// ```
+    pub fn with_azimuth(self, azmut: f64) -> Self {
+        Self { azmut, ..self }
+    }
+
+    /// The same position at polar angle `polar`, the flat distance follows it
+    pub fn with_polar(self, polar: f64) -> Self {
+        Self::new(self.azmut, polar, self.distance)
+    }
+
+    /// The same point with the azimuth in `(-π, π]`, the polar angle in `[0, π]` and the
+    /// distance not negative, the ranges [`CordinateVec::to_sphere`] gives
+    ///
+    /// A negative distance points the other way, and so does a polar angle past either end of
+    /// its range, the azimuth turns half a turn for both
+    ///
+    /// # Examples
+    /// ```rust
+    /// use core::f64::consts::PI;
+    /// use kinematics_core::position::SphereVec;
+    ///
+    /// let pos = SphereVec::new(3. * PI, -PI / 4., -2.).canonicalize();
+    ///
+    /// assert!((pos.azmut - PI).abs() < 1e-9);
+    /// assert!((pos.polar - 3. * PI / 4.).abs() < 1e-9);
+    /// assert_eq!(pos.distance, 2.);
+    /// ```
+    pub fn canonicalize(self) -> Self {
+        let (mut azmut, mut polar, mut distance) = (self.azmut, self.polar, self.distance);
+        if distance < 0. {
+            distance = -distance;
+            polar = PI - polar;
+            azmut += PI;
+        }
+
+        polar = wrap_angle(polar);
+        if polar < 0. {
+            polar = -polar;
+            azmut += PI;
+        }
+
+        Self::new(wrap_angle(azmut), polar, distance)
+    }
+
+    /// Converts spherical coordinates to a 3d position
+    ///
+    /// due to floating point errors the position might
+    /// not be exactly the same but it is usualy close enough
+    ///
+    /// # Examples
+    /// ```rust
+    /// use core::f64::consts::{PI, SQRT_2};
+    /// use kinematics_core::position::SphereVec;
+    /// let pos = SphereVec::new(PI / 4., PI / 2., SQRT_2);
+    ///
+    /// let position = pos.to_position();
+    ///
+    /// assert_eq!(position.x.round(), 1.);
+    /// assert_eq!(position.y.round(), 1.);
+    /// assert_eq!(position.z.round(), 0.);
+    /// ```
+    pub fn to_position(self) -> CordinateVec {
+        CordinateVec {
+            x: self.flat_distance * cos(self.azmut),
+            y: self.flat_distance * sin(self.azmut),
+            z: self.distance * cos(self.polar),
+        }
+    }
+
+    /// Converts spherical coordinates to cylindrical ones, the azimuth stays as it is
+    pub fn to_cylinder(self) -> CylinderVec {
+        CylinderVec {
+            azmut: self.azmut,
+            radius: self.flat_distance,
+            z: self.distance * cos(self.polar),
+        }
+    }
+}
+
+impl CylinderVec {
+    /// Creates a new position
+    ///
+    /// # Arguments
+    /// * `azmut` - Horizontal angle from origin to position from the x axis
+    /// * `radius` - Distance from the z axis
+    /// * `z` - Up and down position
+    pub fn new(azmut: f64, radius: f64, z: f64) -> Self {
+        Self { azmut, radius, z }
+    }
+
+    /// Clamp the distance from the z axis to a range, the azimuth and height stay
+    ///
+    /// # Arguments
+    /// * `min` - The minimum distance from the z axis
+    /// * `max` - The maximum distance from the z axis
+    ///
+    /// # Panics
+    /// If `min` is greater than `max`, or either is NaN, like [`f64::clamp`]
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::position::CylinderVec;
+    /// let mut position = CylinderVec::new(1., 250., 40.);
+    /// position.clamp_radius(20., 200.);
+    ///
+    /// assert_eq!(position, CylinderVec::new(1., 200., 40.));
+    /// ```
+    pub fn clamp_radius(&mut self, min: f64, max: f64) {
+        self.radius = self.radius.clamp(min, max);
+    }
+
+    /// Converts cylindrical coordinates to a 3d position
+    ///
+    /// # Examples
+    /// ```rust
+    /// use core::f64::consts::PI;
+    /// use kinematics_core::{assert_approx_eq, position::{CordinateVec, CylinderVec}};
+    ///
+    /// let position = CylinderVec::new(PI / 2., 3., 7.).to_position();
+    ///
+    /// assert_approx_eq!(position, CordinateVec::new(0., 3., 7.));
+    /// ```
+    pub fn to_position(self) -> CordinateVec {
+        CordinateVec {
+            x: self.radius * cos(self.azmut),
+            y: self.radius * sin(self.azmut),
+            z: self.z,
+        }
+    }
+
+    /// Converts cylindrical coordinates to spherical ones, the azimuth stays as it is and the
+    /// polar angle is from 0 to PI like [`CordinateVec::polar`]
+    ///
+    /// A negative radius is on the other side of the z axis, the azimuth turns half a turn
+    /// for it
+    pub fn to_sphere(self) -> SphereVec {
+        SphereVec {
+            azmut: if self.radius < 0. {
+                self.azmut + PI
+            } else {
+                self.azmut
+            },
+            polar: atan2(self.radius.abs(), self.z),
+            distance: sqrt(powi(self.radius, 2) + powi(self.z, 2)),
+            flat_distance: self.radius.abs(),
+        }
+    }
+}
+
+/// Angle of the wrist in degrees that points the claw `claw_pitch` degrees from the z axis
+///
+/// Measured like the elbow, between the lower arm and the claw and 180 in line with the lower
+/// arm. So the shoulder, elbow and wrist add up to the pitch and a whole turn, for the elbow
+/// bent either way
+///
+/// # Arguments
+/// * `shoulder` - shoulder angle in degrees
+/// * `elbow` - angle between the upper and lower arm in degrees
+/// * `claw_pitch` - Angle of the claw from the z axis in degrees, 90 holds it level
+pub fn wrist_angle(shoulder: f64, elbow: f64, claw_pitch: f64) -> f64 {
+    claw_pitch + 360. - shoulder - elbow
+}
+
+/// From the wrist to the tip of the claw, pointing `claw_pitch` degrees from the z axis in the
+/// vertical plane at `azmut` radians
+fn claw(azmut: f64, wrist_length: f64, claw_pitch: f64) -> CordinateVec {
+    SphereVec::new(azmut, claw_pitch.to_radians(), wrist_length).to_position()
+}
+
+/// `angle` turned whole turns into `(-π, π]`
+fn wrap_angle(angle: f64) -> f64 {
+    let past = (PI - angle) % TAU;
+    PI - if past < 0. { past + TAU } else { past }
+}
+
+impl From<SphereVec> for CordinateVec {
+    /// Same as [`SphereVec::to_position`]
+    fn from(value: SphereVec) -> Self {
+        value.to_position()
+    }
+}
+
+impl From<CordinateVec> for SphereVec {
+    /// Same as [`CordinateVec::to_sphere`]
+    fn from(value: CordinateVec) -> Self {
+        value.to_sphere()
+    }
+}
+
+impl From<CylinderVec> for CordinateVec {
+    /// Same as [`CylinderVec::to_position`]
+    fn from(value: CylinderVec) -> Self {
+        value.to_position()
+    }
+}
+
+impl From<CordinateVec> for CylinderVec {
+    /// Same as [`CordinateVec::to_cylinder`]
+    fn from(value: CordinateVec) -> Self {
+        value.to_cylinder()
+    }
+}
+
+impl From<CylinderVec> for SphereVec {
+    /// Same as [`CylinderVec::to_sphere`]
+    fn from(value: CylinderVec) -> Self {
+        value.to_sphere()
+    }
+}
+
+impl From<SphereVec> for CylinderVec {
+    /// Same as [`SphereVec::to_cylinder`]
+    fn from(value: SphereVec) -> Self {
+        value.to_cylinder()
+    }
+}
+
+impl Default for CordinateVec {
+    fn default() -> Self {
+        Self {
+            x: 0.,
+            y: 0.,
+            z: 0.,
+        }
+    }
+}
+
+impl Sub for CordinateVec {
+    type Output = CordinateVec;
+
+    fn sub(self, rhs: CordinateVec) -> Self::Output {
+        CordinateVec {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+            z: self.z - rhs.z,
+        }
+    }
+}
+
+impl Add for CordinateVec {
+    type Output = CordinateVec;
+
+    fn add(self, rhs: CordinateVec) -> Self::Output {
+        CordinateVec {
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z,
+        }
+    }
+}
+
+impl AddAssign for CordinateVec {
+    fn add_assign(&mut self, rhs: CordinateVec) {
+        *self = CordinateVec {
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z,
+        }
+    }
+}
+
+impl SubAssign for CordinateVec {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = Self {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+            z: self.z - rhs.z,
+        }
+    }
+}
+
+impl Eq for CordinateVec {}
+impl PartialEq for CordinateVec {
+    fn eq(&self, other: &Self) -> bool {
+        self.x == other.x && self.y == other.y && self.z == other.z
+    }
+}
+
+impl Mul<f64> for CordinateVec {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self {
+            x: self.x * rhs,
+            y: self.y * rhs,
+            z: self.z * rhs,
+        }
+    }
+}
+
+impl Mul<CordinateVec> for CordinateVec {
+    type Output = Self;
+
+    fn mul(self, rhs: CordinateVec) -> Self::Output {
+        Self {
+            x: self.x * rhs.x,
+            y: self.y * rhs.y,
+            z: self.z * rhs.z,
+        }
+    }
+}
+
+impl Mul<CordinateVec> for f64 {
+    type Output = CordinateVec;
+
+    fn mul(self, rhs: CordinateVec) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl MulAssign<f64> for CordinateVec {
+    fn mul_assign(&mut self, rhs: f64) {
+        *self = *self * rhs
+    }
+}
+
+/// Component-wise, dividing by zero gives infinite or NaN components like f64 does
+impl Div<f64> for CordinateVec {
+    type Output = Self;
+
+    fn div(self, rhs: f64) -> Self::Output {
+        Self {
+            x: self.x / rhs,
+            y: self.y / rhs,
+            z: self.z / rhs,
+        }
+    }
+}
+
+/// Component-wise, dividing by zero gives infinite or NaN components like f64 does
+impl Div<CordinateVec> for CordinateVec {
+    type Output = Self;
+
+    fn div(self, rhs: CordinateVec) -> Self::Output {
+        Self {
+            x: self.x / rhs.x,
+            y: self.y / rhs.y,
+            z: self.z / rhs.z,
+        }
+    }
+}
+
+impl DivAssign<f64> for CordinateVec {
+    fn div_assign(&mut self, rhs: f64) {
+        *self = *self / rhs
+    }
+}
+
+impl Neg for CordinateVec {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
+/// Scales the distance, the direction stays the same
+impl Mul<f64> for SphereVec {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self {
+            distance: self.distance * rhs,
+            flat_distance: self.flat_distance * rhs,
+            ..self
+        }
+    }
+}
+
+/// Scales the distance, the direction stays the same
+impl Div<f64> for SphereVec {
+    type Output = Self;
+
+    fn div(self, rhs: f64) -> Self::Output {
+        self * (1. / rhs)
+    }
+}
+
+/// Adds each of the azimuth, polar angle and distance, not the positions, like turning a
+/// position round the base by an angular offset
+impl Add for SphereVec {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.azmut + rhs.azmut,
+            self.polar + rhs.polar,
+            self.distance + rhs.distance,
+        )
+    }
+}
+
+/// Subtracts each of the azimuth, polar angle and distance, see [`SphereVec::add`]
+impl Sub for SphereVec {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.azmut - rhs.azmut,
+            self.polar - rhs.polar,
+            self.distance - rhs.distance,
+        )
+    }
+}
+
+/// Scales the radius and height, the azimuth stays the same
+impl Mul<f64> for CylinderVec {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self::new(self.azmut, self.radius * rhs, self.z * rhs)
+    }
+}
+
+/// Scales the radius and height, the azimuth stays the same
+impl Div<f64> for CylinderVec {
+    type Output = Self;
+
+    fn div(self, rhs: f64) -> Self::Output {
+        Self::new(self.azmut, self.radius / rhs, self.z / rhs)
+    }
+}
+
+/// Adds each of the azimuth, radius and height, not the positions, like turning a position
+/// round the base and moving it out and up
+impl Add for CylinderVec {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.azmut + rhs.azmut,
+            self.radius + rhs.radius,
+            self.z + rhs.z,
+        )
+    }
+}
+
+/// Subtracts each of the azimuth, radius and height, see [`CylinderVec::add`]
+impl Sub for CylinderVec {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.azmut - rhs.azmut,
+            self.radius - rhs.radius,
+            self.z - rhs.z,
+        )
+    }
+}
+
+impl AddAssign for CylinderVec {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for CylinderVec {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl fmt::Display for JointName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JointName::Base => write!(f, "base"),
+            JointName::Shoulder => write!(f, "shoulder"),
+            JointName::Elbow => write!(f, "elbow"),
+            JointName::Wrist => write!(f, "wrist"),
+        }
+    }
+}
+
+impl fmt::Display for KinematicsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KinematicsError::OutOfReach { distance, max } => {
+                write!(
+                    f,
+                    "{distance:.1} away is out of the arm's reach of {max:.1}"
+                )
+            }
+            KinematicsError::BelowMinReach => {
+                write!(f, "too close to the shoulder for the arm to fold up to")
+            }
+            KinematicsError::InvalidTriangle => {
+                write!(f, "the arm lengths and the distance make no triangle")
+            }
+            KinematicsError::JointLimit(joint) => {
+                write!(f, "the {joint} would have to go out of its range")
+            }
+            KinematicsError::Degenerate => {
+                write!(f, "the position isn't finite or an arm has no length")
+            }
+            KinematicsError::ToolOffset => {
+                write!(f, "too close to the z axis for the tool to reach across to")
+            }
+        }
+    }
+}
+
+impl core::error::Error for KinematicsError {}
+
+#[cfg(test)]
+mod cordinate_vec {
+
+    use core::f64::consts::{FRAC_PI_2, PI, SQRT_2};
+
+    use crate::{
+        assert_approx_eq,
+        position::{wrist_angle, CordinateVec, IkLimits, JointName, KinematicsError, SphereVec},
+        segments::Segments,
+    };
+
+    #[test]
+    fn to_sphere() {
+        let expected = CordinateVec::new(-1., -1., -1.);
+        let actual = expected.to_sphere().to_position();
+
+        assert_eq!(expected.x, libm::round(actual.x));
+        assert_eq!(expected.y, libm::round(actual.y));
+        assert_eq!(expected.z, libm::round(actual.z));
+    }
+
+    #[test]
+    fn to_sphere_axes() {
+        // on the axes, where dividing by x or z used to come out as 0 or NaN
+        for (position, azmut, polar) in [
+            (CordinateVec::new(0., 5., 0.), FRAC_PI_2, FRAC_PI_2),
+            (CordinateVec::new(0., -5., 0.), -FRAC_PI_2, FRAC_PI_2),
+            (CordinateVec::new(5., 0., 0.), 0., FRAC_PI_2),
+            (CordinateVec::new(-5., 0., 0.), PI, FRAC_PI_2),
+            (CordinateVec::new(0., 0., 3.), 0., 0.),
+            (CordinateVec::new(0., 0., -3.), 0., PI),
+            (CordinateVec::new(0., 4., -4.), FRAC_PI_2, 3. * PI / 4.),
+        ] {
+            let sphere = position.to_sphere();
+            assert_approx_eq!(sphere.azmut, azmut, 1e-12, "{position:?}");
+            assert_approx_eq!(sphere.polar, polar, 1e-12, "{position:?}");
+            assert_approx_eq!(sphere.to_position(), position);
+        }
+
+        let origin = CordinateVec::default().to_sphere();
+        assert_eq!((origin.azmut, origin.polar, origin.distance), (0., 0., 0.));
+        assert_eq!(origin.to_position(), CordinateVec::default());
+    }
+
+    #[test]
+    fn to_sphere_quadrants() {
+        for (x, y) in [(3., 4.), (-3., 4.), (-3., -4.), (3., -4.)] {
+            for z in [2., -2.] {
+                let expected = CordinateVec::new(x, y, z);
+                let sphere = expected.to_sphere();
+
+                // the polar angle only depends on which side of the shoulder z is, whichever
+                // way y points
+                assert_eq!(sphere.polar < FRAC_PI_2, z > 0., "{sphere:?}");
+                assert_eq!(sphere.flat_distance, 5.);
+
+                let actual = sphere.to_position();
+                assert_approx_eq!(expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_kinematics() {
+        let mut position = CordinateVec::new(SQRT_2, 0., 0.);
+
+        let actual = position.inverse_kinematics(1., 1.).unwrap();
+
+        // level with the shoulder the upper arm leans 45° past the line to the head
+        assert_approx_eq!(actual, (90., 135., 90.));
+
+        let mut position = CordinateVec::new(0., 0., 0.);
+
+        let actual = position.inverse_kinematics(0., 0.);
+
+        assert_eq!(actual, Err(KinematicsError::Degenerate));
+
+        let mut far = CordinateVec::new(0., 300., 0.);
+        assert_eq!(
+            far.inverse_kinematics(100., 100.),
+            Err(KinematicsError::OutOfReach {
+                distance: 300.,
+                max: 200.
+            })
+        );
+
+        let mut close = CordinateVec::new(0., 10., 0.);
+        assert_eq!(
+            close.inverse_kinematics(100., 60.),
+            Err(KinematicsError::BelowMinReach)
+        );
+
+        let mut nowhere = CordinateVec::new(f64::NAN, 10., 0.);
+        assert_eq!(
+            nowhere.inverse_kinematics(100., 100.),
+            Err(KinematicsError::Degenerate)
+        );
+    }
+
+    #[test]
+    fn forward_kinematics() {
+        for expected in [
+            CordinateVec::new(20., 20., 150.),
+            CordinateVec::new(40., 30., 120.),
+            CordinateVec::new(10., 60., 140.),
+        ] {
+            let (base, shoulder, elbow) = expected.clone().inverse_kinematics(100., 100.).unwrap();
+            let actual = CordinateVec::forward_kinematics(base, shoulder, elbow, 100., 100.);
+
+            assert_approx_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn forward_kinematics_sweep() {
+        // out to nearly full reach and from above the shoulder to below it, through where the
+        // line to the head and the upper arm's lead on it add up to 90°
+        for distance in (2..40).map(|step| step as f64 * 5.) {
+            for polar in (1..180).map(|step| (step as f64).to_radians()) {
+                let expected = CordinateVec::new(
+                    distance * libm::sin(polar) * 0.6,
+                    distance * libm::sin(polar) * 0.8,
+                    distance * libm::cos(polar),
+                );
+                let (base, shoulder, elbow) =
+                    expected.clone().inverse_kinematics(100., 100.).unwrap();
+                let actual = CordinateVec::forward_kinematics(base, shoulder, elbow, 100., 100.);
+
+                assert_approx_eq!(expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn ik_solutions() {
+        // low and out in front, the primary solution leans the upper arm down past the line to
+        // the head and the mirrored one holds it up over it
+        let position = CordinateVec::new(0., 120., -60.);
+        let solutions = position.ik_solutions(100., 100.).unwrap();
+        let (base, shoulder, elbow) = solutions.primary;
+        let (mirrored_base, mirrored_shoulder, mirrored_elbow) = solutions.mirrored.unwrap();
+
+        assert_eq!(base, mirrored_base);
+        assert!(shoulder - mirrored_shoulder > 90., "{solutions:?}");
+        assert!((elbow + mirrored_elbow - 360.).abs() < 1e-9);
+
+        // both reach the position
+        for (base, shoulder, elbow) in solutions.iter() {
+            let actual = CordinateVec::forward_kinematics(base, shoulder, elbow, 100., 100.);
+            assert_approx_eq!(position, actual);
+        }
+
+        // the arm keeps whichever way it's bent
+        assert_eq!(solutions.closest_to((90., 150., 90.)), solutions.primary);
+        assert_eq!(
+            solutions.closest_to((90., 30., 250.)),
+            solutions.mirrored.unwrap()
+        );
+
+        // stretched out there's only one way
+        let stretched = CordinateVec::new(0., 200., 0.)
+            .ik_solutions(100., 100.)
+            .unwrap();
+        assert_eq!(stretched.mirrored, None);
+        assert_eq!(stretched.iter().count(), 1);
+    }
+
+    #[test]
+    fn inverse_kinematics_4dof() {
+        for tip in [
+            CordinateVec::new(0., 150., 20.),
+            CordinateVec::new(-80., 60., 100.),
+            CordinateVec::new(30., -120., -40.),
+        ] {
+            for pitch in [90., 45., 135., 180.] {
+                let (base, shoulder, elbow, wrist) =
+                    tip.inverse_kinematics_4dof(100., 100., 40., pitch).unwrap();
+
+                // the claw lands on the tip, pointing the way it was asked to
+                let wrist_point =
+                    CordinateVec::forward_kinematics(base, shoulder, elbow, 100., 100.);
+                assert_approx_eq!(wrist_point.claw_tip(40., pitch), tip, 1e-9, "{pitch}°");
+                let claw = (tip - wrist_point).to_sphere();
+                assert_approx_eq!(claw.distance, 40.);
+                assert_approx_eq!(claw.polar.to_degrees(), pitch, 1e-9);
+
+                assert_approx_eq!(shoulder + elbow + wrist, pitch + 360., 1e-9);
+            }
+        }
+
+        // the wrist holds the claw level off the lower arm, bent either way
+        let wrist_point = CordinateVec::new(0., 150., 20.).wrist_point(40., 90.);
+        for (base, shoulder, elbow) in wrist_point.ik_solutions(100., 100.).unwrap().iter() {
+            let segments = Segments::from_angles(base, shoulder, elbow, 100., 100.);
+            let lower_arm = (segments.head - segments.elbow).to_sphere().polar;
+            let lower_arm = lower_arm.to_degrees();
+            let wrist = wrist_angle(shoulder, elbow, 90.);
+            assert_approx_eq!(lower_arm + wrist - 180., 90., 1e-9, "elbow at {elbow}°");
+        }
+
+        // without a wrist length it's the 3 joint solution
+        let tip = CordinateVec::new(-80., 60., 100.);
+        let (base, shoulder, elbow, _) = tip.inverse_kinematics_4dof(100., 100., 0., 90.).unwrap();
+        let primary = tip.ik_solutions(100., 100.).unwrap().primary;
+        assert_eq!((base, shoulder, elbow), primary);
+
+        assert!(matches!(
+            CordinateVec::new(0., 300., 0.).inverse_kinematics_4dof(100., 100., 40., 90.),
+            Err(KinematicsError::OutOfReach { .. })
+        ));
+        assert_eq!(
+            tip.inverse_kinematics_4dof(100., 100., 40., f64::NAN),
+            Err(KinematicsError::Degenerate)
+        );
+    }
+
+    #[test]
+    fn tool_offset() {
+        let offsets = [
+            CordinateVec::new(40., 0., 0.),
+            CordinateVec::new(40., 0., -15.),
+            CordinateVec::new(30., 10., 5.),
+            CordinateVec::new(-20., -25., 0.),
+        ];
+        let tips = [
+            CordinateVec::new(0., 150., 20.),
+            CordinateVec::new(-80., 60., 100.),
+            CordinateVec::new(30., -120., -40.),
+            CordinateVec::new(-50., -50., 0.),
+        ];
+        for offset in offsets {
+            for tip in tips {
+                let mount = tip.tool_mount(offset).unwrap();
+                assert_approx_eq!(mount.tool_tip(offset), tip, 1e-9, "{offset:?} to {tip:?}");
+                assert_approx_eq!((tip - mount).dst(), offset.dst(), 1e-9);
+                assert_approx_eq!(tip.z - mount.z, offset.z);
+            }
+        }
+
+        // straight out from the mount
+        let mount = CordinateVec::new(-80., 60., 100.)
+            .tool_mount(CordinateVec::new(40., 0., 0.))
+            .unwrap();
+        assert_approx_eq!(mount, CordinateVec::new(-48., 36., 100.));
+
+        // without an offset the tip is the mount, exactly
+        for tip in tips {
+            assert_eq!(tip.tool_mount(CordinateVec::default()), Some(tip));
+        }
+
+        // the tip can't be closer in than the offset is across
+        let across = CordinateVec::new(0., 30., 0.);
+        assert_eq!(CordinateVec::new(10., 10., 0.).tool_mount(across), None);
+    }
+
+    #[test]
+    fn inverse_kinematics_limited() {
+        let limits = IkLimits {
+            base: 0.0..=180.,
+            shoulder: 0.0..=180.,
+            elbow: 0.0..=180.,
+        };
+
+        // straight out along y the base is right on its limit, which is in range
+        let on_limit = CordinateVec::new(0., 100., 50.);
+        let solutions = on_limit
+            .inverse_kinematics_limited(100., 100., &limits)
+            .unwrap();
+        assert_eq!(solutions.primary.0, 180.);
+        assert_eq!(solutions.mirrored, None);
+
+        // a hair past it isn't
+        let past = CordinateVec::new(-1e-6, 100., 50.);
+        assert_eq!(
+            past.inverse_kinematics_limited(100., 100., &limits),
+            Err(KinematicsError::JointLimit(JointName::Base))
+        );
+
+        // low and out in front the shoulder leans past 180° unless the elbow bends the other way
+        let low = CordinateVec::new(0., 60., -150.);
+        let (_, shoulder, _) = low.ik_solutions(100., 100.).unwrap().primary;
+        assert!(shoulder > 180.);
+        assert_eq!(
+            low.inverse_kinematics_limited(100., 100., &limits),
+            Err(KinematicsError::JointLimit(JointName::Shoulder))
+        );
+        let both_ways = IkLimits {
+            elbow: 0.0..=360.,
+            ..limits.clone()
+        };
+        let solutions = low
+            .inverse_kinematics_limited(100., 100., &both_ways)
+            .unwrap();
+        assert!(solutions.primary.2 > 180.);
+        assert_eq!(solutions.mirrored, None);
+
+        // out of reach
+        assert_eq!(
+            CordinateVec::new(0., 300., 0.).inverse_kinematics_limited(100., 100., &limits),
+            Err(KinematicsError::OutOfReach {
+                distance: 300.,
+                max: 200.
+            })
+        );
+    }
+
+    #[test]
+    fn shoulder_continuous() {
+        // the shoulder used to be mirrored back once it leaned past 90°, jumping by twice the
+        // overshoot between neighbouring positions
+        for distance in [50., 100., 141.4, 180., 199.] {
+            let shoulder = |polar: f64| {
+                let polar = polar.to_radians();
+                let mut position =
+                    CordinateVec::new(0., distance * libm::sin(polar), distance * libm::cos(polar));
+                position.inverse_kinematics(100., 100.).unwrap().1
+            };
+
+            // at a fixed distance the shoulder follows the line to the head degree for degree
+            let mut last = shoulder(1.);
+            for step in 1..1780 {
+                let angle = shoulder(1. + step as f64 / 10.);
+                assert!(
+                    (angle - last - 0.1).abs() < 1e-6,
+                    "{last}° to {angle}° at {distance} out"
+                );
+                last = angle;
+            }
+        }
+
+        // right where the old branch flipped, 100 and 100 long arms reaching 141.4 level with
+        // the shoulder
+        let mut below = CordinateVec::new(0., 141.4, -0.01);
+        let mut above = CordinateVec::new(0., 141.4, 0.01);
+        let below = below.inverse_kinematics(100., 100.).unwrap().1;
+        let above = above.inverse_kinematics(100., 100.).unwrap().1;
+        assert!(
+            below > 134.9 && below - above < 0.01,
+            "{above}° to {below}°"
+        );
+    }
+
+    #[test]
+    fn is_finite() {
+        assert!(CordinateVec::new(1., -2., 3.).is_finite());
+        assert!(!CordinateVec::new(1., f64::NAN, 3.).is_finite());
+        assert!(!CordinateVec::new(f64::INFINITY, 2., 3.).is_finite());
+        assert!(!CordinateVec::new(1., 2., f64::NEG_INFINITY).is_finite());
+    }
+
+    #[test]
+    fn dst() {
+        assert_eq!(CordinateVec::new(2., 3., 6.).dst(), 7.);
+        assert_eq!(CordinateVec::new(2., 6., 3.).dst(), 7.);
+        assert_eq!(CordinateVec::new(3., 4., 12.).f_dst(), 5.);
+    }
+
+    #[test]
+    fn scalar_operators() {
+        let a = CordinateVec::new(1., -2., 4.);
+
+        assert_eq!(-a, CordinateVec::new(-1., 2., -4.));
+        assert_eq!(2. * a, a * 2.);
+        assert_eq!(a / 2., CordinateVec::new(0.5, -1., 2.));
+        assert_eq!(a / CordinateVec::new(1., 4., -2.), CordinateVec::new(1., -0.5, -2.));
+
+        let mut b = a;
+        b *= 3.;
+        assert_eq!(b, CordinateVec::new(3., -6., 12.));
+        b /= 3.;
+        assert_eq!(b, a);
+    }
+
+    #[test]
+    fn rotated_z() {
+        let position = CordinateVec::new(3., 4., -2.);
+
+        // a quarter turn at a time
+        for (turns, expected) in [
+            (1., CordinateVec::new(-4., 3., -2.)),
+            (2., CordinateVec::new(-3., -4., -2.)),
+            (3., CordinateVec::new(4., -3., -2.)),
+            (4., position),
+            (-1., CordinateVec::new(4., -3., -2.)),
+        ] {
+            let turned = position.rotated_z(turns * FRAC_PI_2);
+            assert_approx_eq!(turned, expected, 1e-12, "{turns} quarter turns");
+        }
+
+        // turning the same way the azimuth counts
+        let turned = position.rotated_z(0.5);
+        assert_approx_eq!(turned.azmut(), position.azmut() + 0.5, 1e-12);
+
+        for (a, b) in [(0.3, 1.1), (-2., 4.5), (PI, -PI / 3.)] {
+            assert_approx_eq!(position.rotated_z(a).rotated_z(-a), position, 1e-12);
+            assert_approx_eq!(
+                position.rotated_z(a).rotated_z(b),
+                position.rotated_z(a + b),
+                1e-12
+            );
+        }
+    }
+
+    #[test]
+    fn rotated_about() {
+        let position = CordinateVec::new(3., 4., -2.);
+        let z = CordinateVec::new(0., 0., 2.);
+
+        // the same as turning about z, whatever the length of the axis
+        for radians in [0.3, FRAC_PI_2, -2., PI] {
+            assert_approx_eq!(
+                position.rotated_about(z, radians),
+                position.rotated_z(radians),
+                1e-12
+            );
+        }
+
+        let x = CordinateVec::new(1., 0., 0.);
+        let y = CordinateVec::new(0., 1., 0.);
+        assert_approx_eq!(
+            position.rotated_about(x, FRAC_PI_2),
+            CordinateVec::new(3., 2., 4.),
+            1e-12
+        );
+        assert_approx_eq!(
+            position.rotated_about(y, FRAC_PI_2),
+            CordinateVec::new(-2., 4., -3.),
+            1e-12
+        );
+
+        // a third of a turn about the diagonal swaps the axes around
+        let diagonal = CordinateVec::new(1., 1., 1.);
+        assert_approx_eq!(
+            position.rotated_about(diagonal, 2. * PI / 3.),
+            CordinateVec::new(-2., 3., 4.),
+            1e-12
+        );
+
+        let axis = CordinateVec::new(-1., 2., 0.5);
+        for (a, b) in [(0.3, 1.1), (-2., 4.5)] {
+            let turned = position.rotated_about(axis, a);
+            assert_approx_eq!(turned.dst(), position.dst(), 1e-12);
+            assert_approx_eq!(turned.rotated_about(axis, -a), position, 1e-12);
+            assert_approx_eq!(
+                turned.rotated_about(axis, b),
+                position.rotated_about(axis, a + b),
+                1e-12
+            );
+        }
+
+        let zero = CordinateVec::default();
+        assert_eq!(position.rotated_about(zero, 1.), position);
+    }
+
+    #[test]
+    fn clamp_vec() {
+        let mut position = CordinateVec::new(-12., 9., -50.);
+        position.clamp_vec(
+            CordinateVec::new(-10., -20., -40.),
+            CordinateVec::new(-5., -15., -30.),
+        );
+        assert_eq!(position, CordinateVec::new(-10., -15., -40.));
+
+        // an axis with its range down to a single value
+        let mut position = CordinateVec::new(1., 2., 3.);
+        position.clamp_vec(CordinateVec::new(0., 0., 0.), CordinateVec::new(5., 5., 0.));
+        assert_eq!(position, CordinateVec::new(1., 2., 0.));
+
+        let a = CordinateVec::new(1., -2., 3.);
+        let b = CordinateVec::new(-1., 2., 3.);
+        assert_eq!(a.component_min(&b), CordinateVec::new(-1., -2., 3.));
+        assert_eq!(a.component_max(&b), CordinateVec::new(1., 2., 3.));
+        assert_eq!(a.abs(), CordinateVec::new(1., 2., 3.));
+    }
+
+    #[test]
+    #[should_panic]
+    fn clamp_vec_inverted() {
+        // the range of y is upside down
+        let (min, max) = (CordinateVec::new(0., 1., 0.), CordinateVec::new(1., 0., 1.));
+        CordinateVec::default().clamp_vec(min, max);
+    }
+
+    #[test]
+    fn divide_by_zero() {
+        // components go infinite or NaN like f64 does, and are caught as not finite
+        let divided = CordinateVec::new(1., -2., 0.) / 0.;
+        assert_eq!(divided.x, f64::INFINITY);
+        assert_eq!(divided.y, f64::NEG_INFINITY);
+        assert!(divided.z.is_nan());
+        assert!(!divided.is_finite());
+
+        let divided = CordinateVec::new(1., 2., 3.) / CordinateVec::new(1., 0., 1.);
+        assert_eq!(divided.y, f64::INFINITY);
+        assert!(!divided.is_finite());
+    }
+
+    #[test]
+    fn lerp() {
+        let from = CordinateVec::new(0.1, -0.0, 1e300);
+        let to = CordinateVec::new(0.7, 3.3, -1e300);
+
+        // bit for bit, going through the difference would round the ends
+        for (t, expected) in [(0., from), (1., to)] {
+            let actual = from.lerp(&to, t);
+            assert_eq!(
+                [actual.x, actual.y, actual.z].map(f64::to_bits),
+                [expected.x, expected.y, expected.z].map(f64::to_bits)
+            );
+        }
+
+        let halfway = CordinateVec::new(1., 2., 3.).lerp(&CordinateVec::new(3., 2., 1.), 0.5);
+        assert_eq!(halfway, CordinateVec::new(2., 2., 2.));
+    }
+
+    #[test]
+    fn move_towards() {
+        let target = CordinateVec::new(10.3, -7.1, 2.9);
+        let mut position = CordinateVec::new(-1., 0.5, 0.);
+        let mut last = (target - position).dst();
+
+        for _ in 0..100 {
+            position = position.move_towards(&target, 0.7);
+            let left = (target - position).dst();
+
+            // closer every step, never past it
+            assert!(left < last || left == 0.);
+            assert!(last - left <= 0.7 + 1e-12);
+            last = left;
+        }
+        assert_eq!(position, target);
+        assert_eq!(target.move_towards(&target, 0.7), target);
+    }
+
+    #[test]
+    fn cross() {
+        let vectors = [
+            CordinateVec::new(1., 2., 3.),
+            CordinateVec::new(-4., 0.5, 2.),
+            CordinateVec::new(0., -3., 7.),
+        ];
+        for a in vectors {
+            for b in vectors {
+                let cross = a.cross(&b);
+
+                // at right angles to both, and the other way round the other way
+                assert!(cross.dot(&a).abs() < 1e-9);
+                assert!(cross.dot(&b).abs() < 1e-9);
+                assert_eq!(b.cross(&a), -cross);
+            }
+            assert_eq!(a.cross(&a), CordinateVec::default());
+        }
+    }
+
+    #[test]
+    fn distance_to() {
+        let a = CordinateVec::new(1., -2., 3.);
+        let b = CordinateVec::new(-3., 2., 5.);
+
+        assert_eq!(a.distance_to(&b), 6.);
+        assert_eq!(b.distance_to(&a), 6.);
+        assert_approx_eq!(a + a.direction_to(&b).unwrap() * 6., b);
+        assert_approx_eq!(b.direction_to(&a).unwrap(), -a.direction_to(&b).unwrap());
+
+        // the same point has no direction
+        assert_eq!(a.distance_to(&a), 0.);
+        assert_eq!(a.direction_to(&a), None);
+        assert_eq!(a.direction_to(&CordinateVec::new(f64::NAN, 0., 0.)), None);
+    }
+
+    #[test]
+    fn normalized() {
+        for vector in [
+            CordinateVec::new(1., 2., 3.),
+            CordinateVec::new(-400., 0.5, 2.),
+            CordinateVec::new(0., 0., -1e-6),
+        ] {
+            let unit = vector.normalized().unwrap();
+            assert!((unit.length() - 1.).abs() < 1e-12);
+            assert!((unit.cross(&vector)).length() < 1e-9);
+            assert!(unit.dot(&vector) > 0.);
+
+            assert!((vector.scale_to(7.).length() - 7.).abs() < 1e-12);
+        }
+
+        assert_eq!(CordinateVec::default().normalized(), None);
+        assert_eq!(CordinateVec::new(f64::INFINITY, 0., 0.).normalized(), None);
+        assert_eq!(CordinateVec::default().scale_to(3.), CordinateVec::default());
+    }
+
+    #[test]
+    fn flat_distance() {
+        // the old copy of the kinematics took the flat distance with the cosine of the polar
+        // angle when going one way and the sine the other
+        let position = CordinateVec::new(30., 40., 120.);
+        let sphere = position.to_sphere();
+        let rebuilt = SphereVec::new(sphere.azmut, sphere.polar, sphere.distance);
+
+        assert!((sphere.flat_distance - 50.).abs() < 1e-9);
+        assert!((rebuilt.flat_distance - sphere.flat_distance).abs() < 1e-9);
+        assert_approx_eq!(rebuilt.to_position(), position);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn old_names() {
+        let position: super::Vec3D = CordinateVec::new(1., 2., 3.);
+        let _: super::SpherePos = position.to_sphere();
+    }
+
+    #[test]
+    fn addition() {
+        let a = CordinateVec::new(1., 2., 3.);
+        let mut b = CordinateVec::new(2., 2., 2.);
+        let c = CordinateVec::new(3., 2., 1.);
+
+        assert_eq!(a + b, CordinateVec::new(3., 4., 5.));
+        assert_eq!(a + c, CordinateVec::new(4., 4., 4.));
+
+        b += c;
+        assert_eq!(b, CordinateVec::new(5., 4., 3.));
+    }
+
+    #[test]
+    fn subtraction() {
+        let a = CordinateVec::new(1., 2., 3.);
+        let mut b = CordinateVec::new(2., 2., 2.);
+        let c = CordinateVec::new(3., 2., 1.);
+
+        assert_eq!(a - b, CordinateVec::new(-1., 0., 1.));
+        assert_eq!(a - c, CordinateVec::new(-2., 0., 2.));
+
+        b -= c;
+
+        assert_eq!(b, CordinateVec::new(-1., 0., 1.));
+    }
+}
+
+#[cfg(test)]
+mod sphere_pos {
+    use crate::{
+        assert_approx_eq,
+        position::{CordinateVec, SphereVec},
+    };
+    use core::f64::consts::{PI, SQRT_2};
+
+    #[test]
+    fn to_position() {
+        let pos = SphereVec {
+            azmut: 1.,
+            polar: 1.,
+            flat_distance: 0.,
+            distance: 0.,
+        };
+
+        let actual = pos.to_position();
+        let expected = CordinateVec::new(0., 0., 0.);
+
+        assert_eq!(actual, expected);
+
+        let pos = SphereVec::new(PI / 4., PI / 2., SQRT_2);
+        let actual = pos.to_position();
+
+        assert_eq!(libm::round(actual.x), 1.);
+        assert_eq!(libm::round(actual.y), 1.);
+        assert_eq!(libm::round(actual.z), 0.);
+    }
+
+    #[test]
+    fn scale() {
+        let pos = SphereVec::new(PI / 4., PI / 3., 4.);
+
+        let doubled = pos * 2.;
+        assert_eq!(doubled.distance, 8.);
+        assert_eq!(doubled.flat_distance, pos.flat_distance * 2.);
+        assert_eq!((doubled.azmut, doubled.polar), (pos.azmut, pos.polar));
+        assert_eq!((pos / 4.).distance, 1.);
+    }
+
+    #[test]
+    fn canonicalize() {
+        // every quadrant, whole turns either way, the polar angle past both ends and the
+        // distance negative
+        for azmut in (-12..=12).map(|step| step as f64 * PI / 4. + 0.1) {
+            for polar in (-6..=6).map(|step| step as f64 * PI / 4. + 0.2) {
+                for distance in [3., -3.] {
+                    let pos = SphereVec::new(azmut, polar, distance);
+                    let canonical = pos.canonicalize();
+
+                    assert!(
+                        -PI < canonical.azmut && canonical.azmut <= PI,
+                        "{canonical:?}"
+                    );
+                    assert!((0. ..=PI).contains(&canonical.polar), "{canonical:?}");
+                    assert_eq!(canonical.distance, 3.);
+                    assert!(canonical.flat_distance >= 0.);
+
+                    let (before, after) = (pos.to_position(), canonical.to_position());
+                    assert_approx_eq!(before, after, 1e-9, "{pos:?} to {canonical:?}");
+
+                    // the same as going through the cartesian position
+                    let sphere = before.to_sphere();
+                    assert!((sphere.azmut - canonical.azmut).abs() < 1e-9);
+                    assert!((sphere.polar - canonical.polar).abs() < 1e-9);
+                }
+            }
+        }
+
+        // on the ends of the ranges
+        assert_eq!(SphereVec::new(-PI, 0., 1.).canonicalize().azmut, PI);
+        assert_eq!(SphereVec::new(PI, PI, 1.).canonicalize().polar, PI);
+        assert_eq!(SphereVec::new(0., 0., 0.).canonicalize().distance, 0.);
+    }
+
+    #[test]
+    fn round_trip() {
+        // off the axes, where the angles are ambiguous
+        let steps = [-90., -35., -2.5, 1., 20., 75.];
+        for x in steps {
+            for y in steps {
+                for z in steps {
+                    let position = CordinateVec::new(x, y, z);
+                    assert_approx_eq!(position.to_sphere().to_position(), position);
+                }
+            }
+        }
+
+        for azmut in (-12..=12).map(|step| step as f64 * PI / 4. + 0.1) {
+            for polar in (-6..=6).map(|step| step as f64 * PI / 4. + 0.2) {
+                for distance in [0.5, 40., -40.] {
+                    let sphere = SphereVec::new(azmut, polar, distance);
+                    let rebuilt = sphere.to_position().to_sphere();
+                    assert_approx_eq!(rebuilt, sphere.canonicalize(), 1e-9, "{sphere:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn update_dst() {
+        let position = CordinateVec::new(-30., 40., 25.);
+        let sphere = position.to_sphere();
+
+        for dst in [0.5, 10., sphere.distance, 300.] {
+            let mut scaled = sphere;
+            scaled.update_dst(dst);
+
+            // still pointing the same way
+            assert_eq!((scaled.azmut, scaled.polar), (sphere.azmut, sphere.polar));
+            assert_eq!(scaled.distance, dst);
+            assert_approx_eq!(
+                scaled.to_position(),
+                position * (dst / sphere.distance),
+                1e-9,
+                "scaled to {dst}"
+            );
+        }
+    }
+
+    #[test]
+    fn angular_offset() {
+        let pos = SphereVec::new(PI / 4., PI / 3., 4.);
+        let offset = SphereVec::new(PI / 2., -PI / 6., 1.);
+
+        let moved = pos + offset;
+        assert_eq!(moved.azmut, 3. * PI / 4.);
+        assert_eq!(moved.polar, PI / 6.);
+        assert_eq!(moved.distance, 5.);
+        assert_eq!(moved.flat_distance, 5. * libm::sin(PI / 6.));
+
+        let back = moved - offset;
+        assert_approx_eq!(back.to_position(), pos.to_position());
+
+        // the builders keep the flat distance in step
+        let tilted = pos.with_polar(PI / 2.);
+        assert_eq!(tilted.flat_distance, 4.);
+        assert_eq!(tilted.azmut, pos.azmut);
+        let turned = pos.with_azimuth(-PI);
+        assert_eq!(turned.flat_distance, pos.flat_distance);
+        assert_eq!((turned.polar, turned.distance), (pos.polar, pos.distance));
+    }
+}
+
+#[cfg(test)]
+mod cylinder_vec {
+    use crate::{
+        assert_approx_eq,
+        position::{CordinateVec, CylinderVec, SphereVec},
+    };
+    use core::f64::consts::{FRAC_PI_2, PI};
+
+    #[test]
+    fn round_trip() {
+        // every quadrant, the axes between them, above and below
+        let steps = [-90., -35., 0., 1., 75.];
+        for x in steps {
+            for y in steps {
+                for z in steps {
+                    let position = CordinateVec::new(x, y, z);
+                    let cylinder = position.to_cylinder();
+                    assert!(-PI <= cylinder.azmut && cylinder.azmut <= PI);
+                    assert!(cylinder.radius >= 0.);
+                    assert_approx_eq!(cylinder.to_position(), position);
+                    assert_approx_eq!(CordinateVec::from(cylinder), position);
+
+                    // the same as going through spherical coordinates
+                    let sphere = position.to_sphere();
+                    assert_approx_eq!(cylinder.to_sphere(), sphere, 1e-9, "{position:?}");
+                    assert_approx_eq!(sphere.to_cylinder(), cylinder, 1e-9, "{position:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zero_radius() {
+        // on the z axis the azimuth is 0 and stays whatever it was
+        let up = CordinateVec::new(0., 0., 5.).to_cylinder();
+        assert_eq!(up, CylinderVec::new(0., 0., 5.));
+        assert_eq!(up.to_sphere().polar, 0.);
+
+        let down = CylinderVec::new(1.2, 0., -5.);
+        assert_eq!(down.to_position(), CordinateVec::new(0., 0., -5.));
+        assert_eq!(down.to_sphere().polar, PI);
+        assert_eq!(down.to_sphere().azmut, 1.2);
+
+        let origin = CylinderVec::new(0., 0., 0.);
+        assert_eq!(origin.to_position(), CordinateVec::default());
+        assert_eq!(CordinateVec::default().to_cylinder(), origin);
+        assert_eq!(origin.to_sphere().distance, 0.);
+    }
+
+    #[test]
+    fn negative_radius() {
+        let behind = CylinderVec::new(FRAC_PI_2, -4., 3.);
+
+        assert_approx_eq!(behind.to_position(), CordinateVec::new(0., -4., 3.));
+        assert_approx_eq!(behind.to_sphere().to_position(), behind.to_position());
+        assert_eq!(behind.to_sphere().flat_distance, 4.);
+    }
+
+    #[test]
+    fn operators() {
+        let position = CylinderVec::new(0.5, 100., 20.);
+
+        assert_eq!(position * 2., CylinderVec::new(0.5, 200., 40.));
+        assert_eq!(position / 4., CylinderVec::new(0.5, 25., 5.));
+        assert_approx_eq!((position * 2.).to_position(), position.to_position() * 2.);
+
+        let offset = CylinderVec::new(FRAC_PI_2, 10., -5.);
+        let mut moved = position + offset;
+        assert_eq!(moved, CylinderVec::new(0.5 + FRAC_PI_2, 110., 15.));
+        assert_eq!(moved - offset, position);
+
+        moved -= offset;
+        assert_eq!(moved, position);
+        moved += offset;
+        assert_eq!(moved, position + offset);
+
+        let mut clamped = position;
+        clamped.clamp_radius(120., 150.);
+        assert_eq!(clamped, CylinderVec::new(0.5, 120., 20.));
+    }
+
+    #[test]
+    fn sphere() {
+        let sphere = SphereVec::new(-2., PI / 3., 10.);
+        let cylinder = CylinderVec::from(sphere);
+
+        assert_eq!(cylinder.azmut, -2.);
+        assert_approx_eq!(cylinder.radius, 10. * libm::sin(PI / 3.));
+        assert_approx_eq!(cylinder.z, 5.);
+        assert_approx_eq!(SphereVec::from(cylinder), sphere);
+    }
+}
@@ -0,0 +1,129 @@
+//! Where the ends of the arm's segments are, for drawing the arm
+
+use crate::{
+    float::{cos, sin},
+    position::CordinateVec,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The joints of the arm in 3d, from the base to the head
+///
+/// The base turns about the z axis through the shoulder, so the shoulder is the base's pivot
+/// and always at the origin
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Segments {
+    pub shoulder: CordinateVec,
+
+    /// End of the upper arm
+    pub elbow: CordinateVec,
+
+    /// End of the lower arm, where the claw is
+    pub head: CordinateVec,
+}
+
+impl Segments {
+    /// Calculates the segment ends for the given angles, like
+    /// [`CordinateVec::forward_kinematics`] with the shoulder measured from the z axis
+    ///
+    /// # Arguments
+    /// * `base` - base angle in degrees
+    /// * `shoulder` - shoulder angle in degrees
+    /// * `elbow` - angle between the upper and lower arm in degrees
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    ///
+    /// # Examples
+    /// ```rust
+    /// use kinematics_core::{assert_approx_eq, position::CordinateVec, segments::Segments};
+    ///
+    /// // straight up
+    /// let segments = Segments::from_angles(90., 0., 180., 10., 5.);
+    ///
+    /// assert_eq!(segments.elbow, CordinateVec::new(0., 0., 10.));
+    /// assert_approx_eq!(segments.head, CordinateVec::new(0., 0., 15.));
+    /// ```
+    pub fn from_angles(
+        base: f64,
+        shoulder: f64,
+        elbow: f64,
+        upper_arm: f64,
+        lower_arm: f64,
+    ) -> Self {
+        let azmut = (base - 90.).to_radians();
+
+        // the upper arm leans further out than the head, the lower arm turns back towards the z
+        // axis by what the elbow is short of straight
+        let upper = shoulder.to_radians();
+        let lower = (shoulder - (180. - elbow)).to_radians();
+        let along = |polar: f64, length: f64| CordinateVec {
+            x: length * sin(polar) * cos(azmut),
+            y: length * sin(polar) * sin(azmut),
+            z: length * cos(polar),
+        };
+
+        let elbow = along(upper, upper_arm);
+        Self {
+            shoulder: CordinateVec::new(0., 0., 0.),
+            elbow,
+            head: elbow + along(lower, lower_arm),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assert_approx_eq;
+
+    fn close(actual: CordinateVec, expected: CordinateVec) {
+        assert_approx_eq!(actual, expected);
+    }
+
+    #[test]
+    fn straight_up() {
+        let segments = Segments::from_angles(90., 0., 180., 100., 80.);
+        assert_eq!(segments.shoulder, CordinateVec::new(0., 0., 0.));
+        close(segments.elbow, CordinateVec::new(0., 0., 100.));
+        close(segments.head, CordinateVec::new(0., 0., 180.));
+    }
+
+    #[test]
+    fn fully_extended() {
+        // flat along y with the base turned a quarter
+        let segments = Segments::from_angles(180., 90., 180., 100., 80.);
+        close(segments.elbow, CordinateVec::new(0., 100., 0.));
+        close(segments.head, CordinateVec::new(0., 180., 0.));
+    }
+
+    #[test]
+    fn right_angle_elbow() {
+        // upper arm flat along x, the lower arm straight up
+        let segments = Segments::from_angles(90., 90., 90., 100., 80.);
+        close(segments.elbow, CordinateVec::new(100., 0., 0.));
+        close(segments.head, CordinateVec::new(100., 0., 80.));
+
+        // upper arm down at 45°, the head level with the shoulder
+        let segments = Segments::from_angles(90., 135., 90., 1., 1.);
+        close(
+            segments.elbow,
+            CordinateVec::new(0.5f64.sqrt(), 0., -(0.5f64.sqrt())),
+        );
+        close(segments.head, CordinateVec::new(2f64.sqrt(), 0., 0.));
+    }
+
+    #[test]
+    fn matches_forward_kinematics() {
+        let (base, shoulder, elbow) = CordinateVec::new(40., 30., 120.)
+            .inverse_kinematics(100., 100.)
+            .unwrap();
+        let segments = Segments::from_angles(base, shoulder, elbow, 100., 100.);
+        close(
+            segments.head,
+            CordinateVec::forward_kinematics(base, shoulder, elbow, 100., 100.),
+        );
+        assert!(((segments.elbow - segments.shoulder).dst() - 100.).abs() < 1e-9);
+        assert!(((segments.head - segments.elbow).dst() - 100.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,60 @@
+use crate::float::{acos, cos, sin, sqrt};
+
+/// The angle for the corner between a and b in radians
+///
+/// x = -c^2 + a^2 + b^2
+/// y = 2ab
+///
+/// arccos(x/y)
+pub fn a_from_lengths(a: f64, b: f64, c: f64) -> f64 {
+    let x = -(c * c) + a * a + b * b;
+    let y = 2. * a * b;
+    acos(x / y)
+}
+
+/// The length of the side opposite of the angle
+///
+/// x = a - cos(angle) * a
+/// y = sin(angle) * b
+///
+/// sqrt(x^2 + y^2)
+pub fn length_from_two_lengths_and_angle(angle: f64, a: f64, b: f64) -> f64 {
+    let x = a - cos(angle) * a;
+    let y = sin(angle) * b;
+
+    sqrt(x * x + y * y)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::triangle;
+
+    #[test]
+    fn a_from_lengths() {
+        assert_eq!(triangle::a_from_lengths(3., 4., 5.).to_degrees(), 90.00);
+        assert_eq!(
+            libm::round(triangle::a_from_lengths(2., 2., 2.).to_degrees()),
+            60.00
+        );
+    }
+
+    #[test]
+    fn length_from_two_lengths_and_angle() {
+        assert_eq!(
+            libm::round(triangle::length_from_two_lengths_and_angle(
+                90f64.to_radians(),
+                3.,
+                4.
+            )),
+            5.
+        );
+        assert_eq!(
+            libm::round(triangle::length_from_two_lengths_and_angle(
+                60f64.to_radians(),
+                2.,
+                2.
+            )),
+            2.
+        );
+    }
+}
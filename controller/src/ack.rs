@@ -0,0 +1,99 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Maximum number of unacknowledged frames remembered, older ones are forgotten
+pub const MAX_PENDING: usize = 32;
+
+/// Keeps track of which sent frames the arduino has acknowledged
+#[derive(Debug, Default)]
+pub struct AckTracker {
+    /// Sequence numbers of sent frames waiting for an ACK and when they were sent, oldest first
+    pub pending: VecDeque<(u8, Instant)>,
+
+    /// When the last ACK was received
+    pub last_ack: Option<Instant>,
+
+    /// Round trip time of the last acknowledged frame
+    pub last_rtt: Option<Duration>,
+
+    /// ACKs matching a pending frame
+    pub acked: u64,
+
+    /// ACKs for a sequence number that wasn't pending
+    pub unmatched: u64,
+}
+
+impl AckTracker {
+    /// Call after a frame has been sent
+    pub fn sent(&mut self, seq: u8, now: Instant) {
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.pop_front();
+        }
+
+        self.pending.push_back((seq, now));
+    }
+
+    /// Call when an ACK frame is received
+    ///
+    /// # Returns
+    /// The round trip time if `seq` belongs to a pending frame
+    pub fn received(&mut self, seq: u8, now: Instant) -> Option<Duration> {
+        self.last_ack = Some(now);
+
+        let Some(index) = self.pending.iter().position(|(pending, _)| *pending == seq) else {
+            self.unmatched += 1;
+            return None;
+        };
+
+        let (_, sent) = self.pending.remove(index)?;
+        let rtt = now.saturating_duration_since(sent);
+        self.acked += 1;
+        self.last_rtt = Some(rtt);
+        Some(rtt)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn match_acks() {
+        let start = Instant::now();
+        let mut acks = AckTracker::default();
+
+        acks.sent(1, start);
+        acks.sent(2, start + Duration::from_millis(5));
+
+        // acks don't have to arrive in order
+        assert_eq!(
+            acks.received(2, start + Duration::from_millis(20)),
+            Some(Duration::from_millis(15))
+        );
+        assert_eq!(acks.received(2, start + Duration::from_millis(21)), None);
+        assert_eq!(
+            acks.received(1, start + Duration::from_millis(30)),
+            Some(Duration::from_millis(30))
+        );
+
+        assert!(acks.pending.is_empty());
+        assert_eq!(acks.acked, 2);
+        assert_eq!(acks.unmatched, 1);
+        assert_eq!(acks.last_ack, Some(start + Duration::from_millis(30)));
+    }
+
+    #[test]
+    fn pending_is_bounded() {
+        let start = Instant::now();
+        let mut acks = AckTracker::default();
+
+        for seq in 0..MAX_PENDING as u8 + 4 {
+            acks.sent(seq, start);
+        }
+
+        assert_eq!(acks.pending.len(), MAX_PENDING);
+        assert_eq!(acks.pending.front().map(|(seq, _)| *seq), Some(4));
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::kinematics::position::CordinateVec;
+
+/// Maps the internal units the kinematics work in to measured real world coordinates
+///
+/// `world = rotate(internal * scale) + offset`, the rotation is around the base (the z axis)
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    /// Real world length of one internal unit
+    pub scale: f64,
+
+    /// Rotation around the z axis in degrees, counter clockwise seen from above
+    pub rotation: f64,
+
+    /// Where the internal origin is in the real world
+    pub offset: CordinateVec,
+}
+
+/// A head position paired with where it was measured to be
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReferencePoint {
+    pub internal: CordinateVec,
+    pub world: CordinateVec,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CalibrationError {
+    /// At least two reference points are needed
+    TooFewPoints,
+
+    /// The reference points are all at the same internal position
+    Degenerate,
+}
+
+impl Calibration {
+    /// Fit a calibration to measured reference points with least squares
+    ///
+    /// # Arguments
+    /// * `points` - two or more reference points, more points average out measuring errors
+    /// * `rotate` - also fit a rotation around the base, otherwise only scale and offset
+    pub fn fit(points: &[ReferencePoint], rotate: bool) -> Result<Self, CalibrationError> {
+        if points.len() < 2 {
+            return Err(CalibrationError::TooFewPoints);
+        }
+
+        let count = points.len() as f64;
+        let centroid = |position: fn(&ReferencePoint) -> CordinateVec| {
+            points
+                .iter()
+                .fold(CordinateVec::default(), |sum, point| sum + position(point))
+                * (1. / count)
+        };
+        let internal_center = centroid(|point| point.internal);
+        let world_center = centroid(|point| point.world);
+
+        let centered = points.iter().map(|point| {
+            (
+                point.internal - internal_center,
+                point.world - world_center,
+            )
+        });
+
+        // the rotation that best lines up the points seen from above
+        let rotation = if rotate {
+            let (cross, dot) = centered.clone().fold((0., 0.), |(cross, dot), (i, w)| {
+                (cross + i.x * w.y - i.y * w.x, dot + i.x * w.x + i.y * w.y)
+            });
+            cross.atan2(dot).to_degrees()
+        } else {
+            0.
+        };
+
+        let rotated = |position| Self::rotate(position, rotation);
+        let (along, spread) = centered.fold((0., 0.), |(along, spread), (i, w)| {
+            let i = rotated(i);
+            (
+                along + i.x * w.x + i.y * w.y + i.z * w.z,
+                spread + i.x * i.x + i.y * i.y + i.z * i.z,
+            )
+        });
+        if spread == 0. {
+            return Err(CalibrationError::Degenerate);
+        }
+
+        let scale = along / spread;
+        Ok(Self {
+            scale,
+            rotation,
+            offset: world_center - rotated(internal_center) * scale,
+        })
+    }
+
+    /// Real world position of an internal position
+    pub fn to_world(self, internal: CordinateVec) -> CordinateVec {
+        Self::rotate(internal * self.scale, self.rotation) + self.offset
+    }
+
+    /// Internal position of a real world position
+    pub fn to_internal(self, world: CordinateVec) -> CordinateVec {
+        Self::rotate(world - self.offset, -self.rotation) * (1. / self.scale)
+    }
+
+    /// True if this isn't the identity, so coordinates are in real world units
+    pub fn calibrated(self) -> bool {
+        self != Self::default()
+    }
+
+    fn rotate(position: CordinateVec, degrees: f64) -> CordinateVec {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        CordinateVec::new(
+            position.x * cos - position.y * sin,
+            position.x * sin + position.y * cos,
+            position.z,
+        )
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            scale: 1.,
+            rotation: 0.,
+            offset: CordinateVec::default(),
+        }
+    }
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::TooFewPoints => write!(f, "at least two reference points are needed"),
+            CalibrationError::Degenerate => write!(f, "reference points are all at the same place"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: CordinateVec, b: CordinateVec) -> bool {
+        (a - b).dst() < 1e-6
+    }
+
+    /// Reference points measured with a known calibration
+    fn points(calibration: &Calibration, internal: &[CordinateVec]) -> Vec<ReferencePoint> {
+        internal
+            .iter()
+            .map(|&internal| ReferencePoint {
+                internal,
+                world: calibration.to_world(internal),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let calibration = Calibration {
+            scale: 1.3,
+            rotation: 4.,
+            offset: CordinateVec::new(12., -7., 30.),
+        };
+
+        let internal = CordinateVec::new(40., 60., 80.);
+        assert!(close(calibration.to_internal(calibration.to_world(internal)), internal));
+        assert!(calibration.calibrated());
+        assert!(!Calibration::default().calibrated());
+    }
+
+    #[test]
+    fn fit_scale_and_offset() {
+        let truth = Calibration {
+            scale: 0.8,
+            rotation: 0.,
+            offset: CordinateVec::new(5., 10., -20.),
+        };
+        let reference = points(
+            &truth,
+            &[CordinateVec::new(20., 50., 50.), CordinateVec::new(80., 100., 120.)],
+        );
+
+        let fitted = Calibration::fit(&reference, false).unwrap();
+        assert!((fitted.scale - truth.scale).abs() < 1e-9);
+        assert!(close(fitted.offset, truth.offset));
+        assert_eq!(fitted.rotation, 0.);
+    }
+
+    #[test]
+    fn fit_rotation() {
+        let truth = Calibration {
+            scale: 1.25,
+            rotation: -6.,
+            offset: CordinateVec::new(-30., 15., 2.),
+        };
+        let reference = points(
+            &truth,
+            &[
+                CordinateVec::new(20., 50., 50.),
+                CordinateVec::new(80., 100., 120.),
+                CordinateVec::new(-40., 90., 60.),
+            ],
+        );
+
+        let fitted = Calibration::fit(&reference, true).unwrap();
+        assert!((fitted.scale - truth.scale).abs() < 1e-9);
+        assert!((fitted.rotation - truth.rotation).abs() < 1e-9);
+        assert!(close(fitted.offset, truth.offset));
+
+        // without rotation the fit is off but still the best it can do
+        let unrotated = Calibration::fit(&reference, false).unwrap();
+        assert_eq!(unrotated.rotation, 0.);
+        assert!(!close(unrotated.offset, truth.offset));
+    }
+
+    #[test]
+    fn fit_errors() {
+        let point = ReferencePoint {
+            internal: CordinateVec::new(1., 2., 3.),
+            world: CordinateVec::new(4., 5., 6.),
+        };
+
+        assert_eq!(Calibration::fit(&[point], false), Err(CalibrationError::TooFewPoints));
+        assert_eq!(
+            Calibration::fit(&[point, point], true),
+            Err(CalibrationError::Degenerate)
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Source of time steps for driving the robot
+pub trait Clock {
+    /// Wait until the next step is due
+    ///
+    /// # Returns
+    /// Seconds since the previous step
+    fn tick(&mut self) -> f64;
+}
+
+/// Real time clock that sleeps so steps are at least `period` apart
+#[derive(Debug)]
+pub struct PacedClock {
+    pub period: Duration,
+    last: Instant,
+}
+
+/// Simulated clock that advances a fixed step without waiting, for tests and simulations
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone)]
+pub struct SimClock {
+    /// Seconds per step
+    pub step: f64,
+}
+
+impl PacedClock {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            last: Instant::now(),
+        }
+    }
+}
+
+impl Clock for PacedClock {
+    fn tick(&mut self) -> f64 {
+        let next = self.last + self.period;
+        let now = Instant::now();
+        if next > now {
+            sleep(next - now);
+        }
+
+        let now = Instant::now();
+        let delta = now - self.last;
+        self.last = now;
+        delta.as_secs_f64()
+    }
+}
+
+impl Clock for SimClock {
+    fn tick(&mut self) -> f64 {
+        self.step
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paced_waits() {
+        let mut clock = PacedClock::new(Duration::from_millis(5));
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(clock.tick() >= 0.005);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(15));
+    }
+}
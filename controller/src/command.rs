@@ -0,0 +1,1950 @@
+use std::{
+    fmt,
+    io::{self, BufRead},
+    path::PathBuf,
+    sync::{
+        atomic::AtomicBool,
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    calibration::{Calibration, CalibrationError, ReferencePoint},
+    clock::Clock,
+    communication::ComError,
+    config::{self, ConfigError},
+    display,
+    kinematics::{operator, position::CordinateVec},
+    logging::info,
+    program::{Program, ProgramError, Waypoint},
+    recording::{Recording, RecordingError, Transform},
+    robot::{
+        arm::JointAngles,
+        boundary::{BoundaryError, BoundaryPolicy},
+        bundle::BundleError,
+        coordinator::Coordinator,
+        envelope::{EnvelopeError, EnvelopeMode},
+        goto::{GotoOutcome, GotoResult},
+        grip::GripError,
+        journal::JournalError,
+        limits::{LimitError, MAX_SEARCH_SPEED},
+        payload::PayloadError,
+        segment_stream::SegmentStream,
+        servo_override::OverrideError,
+        shutdown::ShutdownReason,
+        workspace::WorkspaceError,
+        Robot, UpdateReport,
+    },
+    session::{self, SessionHeader},
+};
+
+/// A command typed by the operator
+///
+/// Syntax, one command per line:
+/// * `replay <file> [offset <x> <y> <z>] [rotate <degrees>] [mirror]`
+/// * `replay <file> joints`, replay a joint space recording
+/// * `record start`
+/// * `record stop <file> [cartesian]`, save the joint angles or the head positions
+/// * `cartesian <joints file> <file>`, convert a joint space recording to head positions
+/// * `joints <file> <joints file> [claw <file>]`, convert head positions to joint angles taking
+///   the claw from another joint space recording
+/// * `joints`, show the joint angles the way they are measured on the arm, see
+///   [`crate::kinematics::operator`]
+/// * `goto <x> <y> <z>`, in real world coordinates once calibrated and in the operator's
+///   frame, see [`Robot::calibration`] and [`Robot::mirror`]. A move in progress carries on
+///   from its velocity, see [`Robot::retarget`]
+/// * `goto <x> <y> <z> wait <seconds> [speed <units/s>]`, the same and wait until the head gets
+///   there, see [`Command::run`]
+/// * `together <x> <y> <z> <x> <y> <z>`, move the heads of both arms so they start and end
+///   together, the second position is the second arm's, see
+///   [`crate::robot::coordinator::Coordinator::move_together`]
+/// * `program load <file>`, replace the pose bank and waypoints, see [`crate::program`]
+/// * `program run`, go through the waypoints, see [`Robot::run_program`]
+/// * `program stop`
+/// * `program skip`, head for the next waypoint right away, see [`Robot::skip_waypoint`]
+/// * `pose <name>`, add the head position to the pose bank
+/// * `waypoint <pose>`, queue a waypoint to a pose from the bank
+/// * `grip save <name> [speed <percent/s>] [replace]`, keep how far the claw is closed for an
+///   object, see [`Robot::save_grip`]
+/// * `grip use <name>`, turn the claw to a grip until it's there or closes on the object, see
+///   [`Robot::use_grip`]
+/// * `export program <file>`, write the pose bank and waypoints as a program
+/// * `calibrate point <x> <y> <z>`, the measured real world position of the head
+/// * `calibrate fit [rotate]`, fit the calibration to the points, with a rotation around the base
+/// * `calibrate clear`, forget the points and the calibration
+/// * `mirror <on|off>`
+/// * `payload <grams>`, what the claw carries, the reach and acceleration are derated for it,
+///   see [`Robot::set_payload`]
+/// * `output every <ticks>`, send the servo positions every nth tick
+/// * `output rate <hz|off>`, most servo frames sent per second
+/// * `segments <file|udp://host:port> [every <ticks>]`, stream where the arm's segments are
+///   every nth tick, see [`SegmentStream`]
+/// * `segments off`
+/// * `servo <joint> <µs>`, drive a joint to a raw pulse width for a while, the inverse
+///   kinematics leave it alone meanwhile, see [`Robot::override_servo`]
+/// * `servo off`, hand the joint back to normal control
+/// * `joint <joint> <degrees>`, like `servo` but to an angle measured on the arm, base 0°
+///   straight ahead, shoulder 0° horizontal and elbow 0° straight, see
+///   [`Robot::override_joint`]
+/// * `workspace <name|none>`, switch workspace profile, see [`Robot::select_workspace`]
+/// * `envelope <learn|enforce|off>`, teach the arm where it may go by driving it around, then
+///   keep it there, see [`Robot::set_envelope_mode`]
+/// * `envelope clear`, forget the taught envelope
+/// * `boundary <hard|soft|off>`, stop dead at the edge of where the head may go, slow down
+///   before it or only keep to the reach of the arm, see [`Robot::set_boundary_policy`]
+/// * `resume`, continue after a stall, see [`Robot::resume`]
+/// * `release`, let the arm move again after an emergency stop, see
+///   [`Robot::release_emergency_stop`]
+/// * `limits find <joint>`, search for the end stops of a joint once confirmed with
+///   `limits confirm <joint>`, see [`Robot::find_limits`]
+/// * `limits abort`, stop searching
+/// * `limits save <file>`, write the joint limits into a config file
+/// * `undo`, revert the latest change to a pose, a grip, the joint limits or the calibration, see
+///   [`Robot::undo`]
+/// * `check [file]`, audit the arm's motions, servo mapping and kinematics and optionally save
+///   the report, see [`crate::robot::audit`]
+/// * `check program`, dry run the loaded program without moving, see [`Robot::dry_run`]
+/// * `version`, print the build and settings of this session, see [`SessionHeader`]
+/// * `stats`, print the frame counters, loop timing and link quality
+/// * `stats reset`, count the stats from 0 again
+/// * `bundle now [dir]`, write every log of the session into a directory, by default a new
+///   one in the `bundle_on_exit` directory, see [`Robot::write_bundle`]
+/// * `quit`, shut the arm down as `shutdown` says and end the session, bundling the logs if
+///   `bundle_on_exit` is set
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Replay a recording moved by `transform`
+    Replay { path: String, transform: Transform },
+
+    /// Replay a joint space recording
+    ReplayJoints(String),
+
+    RecordStart,
+
+    /// Stop recording and save, as head positions if `cartesian` is set
+    RecordStop { path: String, cartesian: bool },
+
+    /// Convert a joint space recording to head positions
+    ToCartesian { from: String, to: String },
+
+    /// Convert head positions to a joint space recording
+    ToJoints {
+        from: String,
+        to: String,
+        claw: Option<String>,
+    },
+
+    /// Show the joint angles in operator angles
+    Joints,
+
+    /// Move the head to a position
+    Goto(CordinateVec),
+
+    /// Move the head to a position and wait until it gets there, see [`Robot::goto_blocking`]
+    GotoWait {
+        target: CordinateVec,
+        timeout: Duration,
+        speed: Option<f64>,
+    },
+
+    /// Move the heads of both arms at once, the leader's first
+    Together([CordinateVec; 2]),
+
+    /// Replace the program with one from a file
+    LoadProgram(String),
+
+    RunProgram,
+    StopProgram,
+    SkipWaypoint,
+
+    /// Add the head position to the pose bank under a name
+    SavePose(String),
+
+    /// Queue a waypoint to a pose from the bank
+    AddWaypoint(String),
+
+    /// Save how far the claw is closed as a grip
+    GripSave {
+        name: String,
+        speed: Option<f64>,
+        replace: bool,
+    },
+
+    /// Turn the claw to a grip
+    GripUse(String),
+
+    /// Write the program to a file
+    ExportProgram(String),
+
+    /// The head is measured to be at this real world position
+    CalibratePoint(CordinateVec),
+
+    /// Fit the calibration to the reference points
+    CalibrateFit { rotate: bool },
+
+    CalibrateClear,
+
+    /// Turn mirror mode on or off
+    Mirror(bool),
+
+    /// Grams the claw carries
+    Payload(f64),
+
+    /// Send the servo positions every nth tick
+    OutputDivider(u32),
+
+    /// Most servo frames per second, `None` for no limit
+    OutputRate(Option<f64>),
+
+    /// Stream the segments of the arm to a file or UDP address
+    SegmentsStart { target: String, every: u32 },
+
+    SegmentsStop,
+
+    /// Drive a joint to a raw pulse width in µs
+    Servo { joint: String, pulse: u16 },
+
+    /// Drive a joint to an operator angle in degrees
+    Joint { joint: String, angle: f64 },
+
+    ServoOff,
+
+    /// Select a workspace profile, `None` to drop all limits
+    Workspace(Option<String>),
+
+    /// Learn, enforce or ignore the taught envelope
+    Envelope(EnvelopeMode),
+
+    EnvelopeClear,
+
+    /// Change what the head does at the boundary
+    Boundary(BoundaryPolicy),
+
+    /// Continue after a stall
+    Resume,
+
+    /// Release the emergency stop
+    Release,
+
+    /// Search for the end stops of a joint
+    LimitsFind(String),
+
+    /// Let the joint waiting for the search move
+    LimitsConfirm(String),
+
+    LimitsAbort,
+
+    /// Write the joint limits into a config file
+    LimitsSave(String),
+
+    /// Revert the latest change to persisted state
+    Undo,
+
+    /// Audit the arm, saving the report if a file is given
+    Check(Option<String>),
+
+    /// Dry run the loaded program
+    CheckProgram,
+
+    /// Print the session header
+    Version,
+
+    /// Print the stats table, see [`display::stats_table`]
+    Stats,
+
+    StatsReset,
+
+    /// Bundle the logs into a directory, `None` for a snapshot in the exit bundle directory
+    Bundle(Option<String>),
+
+    /// End the session, `main` stops once the arm is shut down, see [`Robot::begin_shutdown`]
+    Quit,
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    /// The first word isn't a known command
+    Unknown(String),
+
+    /// A required argument is missing
+    Missing(&'static str),
+
+    /// An argument isn't a valid number
+    InvalidNumber(String),
+
+    /// An argument the command doesn't take
+    Unexpected(String),
+
+    /// `record stop` without `record start`
+    NotRecording,
+
+    Recording(RecordingError),
+    Calibration(CalibrationError),
+    Workspace(WorkspaceError),
+    Envelope(EnvelopeError),
+    Boundary(BoundaryError),
+
+    /// The audit report couldn't be saved
+    Report(io::Error),
+
+    /// The segment stream couldn't be opened or written
+    Stream(io::Error),
+
+    /// A target the head can't be at, see [`Robot::is_reachable`]
+    Unreachable(CordinateVec),
+
+    /// `release` while the emergency stop is still braking
+    Braking,
+
+    Limits(LimitError),
+
+    /// `limits abort` without a search
+    NotSearching,
+
+    /// The config file couldn't be read or written
+    Config(ConfigError),
+
+    Program(ProgramError),
+
+    /// A `goto` beyond what the arm can hold with its payload
+    Payload(PayloadError),
+
+    /// `program stop` or `program skip` without a running program
+    NotRunning,
+
+    /// A `goto ... wait` timed out or was cancelled
+    NotReached(GotoResult),
+
+    /// The connection failed while waiting for a `goto ... wait`
+    Connection(ComError),
+
+    Journal(JournalError),
+    Grip(GripError),
+    Override(OverrideError),
+    Bundle(BundleError),
+
+    /// `servo off` without an overridden joint
+    NotOverridden,
+
+    /// `together` without a second arm
+    SingleArm,
+}
+
+impl Command {
+    /// Parse a line of input
+    ///
+    /// # Returns
+    /// `Ok(None)` if the line is empty
+    pub fn parse(line: &str) -> Result<Option<Self>, CommandError> {
+        let mut words = line.split_whitespace();
+
+        let Some(name) = words.next() else {
+            return Ok(None);
+        };
+
+        match name {
+            "replay" => {
+                let path = word(words.next(), "file")?;
+                let mut transform = Transform::default();
+
+                let mut words = words.peekable();
+                if words.next_if_eq(&"joints").is_some() {
+                    end(words)?;
+                    return Ok(Some(Command::ReplayJoints(path)));
+                }
+
+                while let Some(word) = words.next() {
+                    match word {
+                        "offset" => {
+                            transform.offset = CordinateVec::new(
+                                number(words.next(), "x")?,
+                                number(words.next(), "y")?,
+                                number(words.next(), "z")?,
+                            )
+                        }
+                        "rotate" => transform.rotation = number(words.next(), "degrees")?,
+                        "mirror" => transform.mirror = true,
+                        word => return Err(CommandError::Unexpected(word.to_string())),
+                    }
+                }
+
+                Ok(Some(Command::Replay { path, transform }))
+            }
+            "record" => match words.next() {
+                Some("start") => {
+                    end(words)?;
+                    Ok(Some(Command::RecordStart))
+                }
+                Some("stop") => {
+                    let path = word(words.next(), "file")?;
+                    let cartesian = match words.next() {
+                        Some("cartesian") => true,
+                        Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                        None => false,
+                    };
+                    end(words)?;
+                    Ok(Some(Command::RecordStop { path, cartesian }))
+                }
+                Some(word) => Err(CommandError::Unexpected(word.to_string())),
+                None => Err(CommandError::Missing("start|stop")),
+            },
+            "cartesian" => {
+                let from = word(words.next(), "joints file")?;
+                let to = word(words.next(), "file")?;
+                end(words)?;
+                Ok(Some(Command::ToCartesian { from, to }))
+            }
+            "joints" => {
+                let Some(from) = words.next() else {
+                    return Ok(Some(Command::Joints));
+                };
+                let from = from.to_string();
+                let to = word(words.next(), "joints file")?;
+                let claw = match words.next() {
+                    Some("claw") => Some(word(words.next(), "claw file")?),
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => None,
+                };
+                end(words)?;
+                Ok(Some(Command::ToJoints { from, to, claw }))
+            }
+            "goto" => {
+                let target = CordinateVec::new(
+                    number(words.next(), "x")?,
+                    number(words.next(), "y")?,
+                    number(words.next(), "z")?,
+                );
+                match words.next() {
+                    Some("wait") => {}
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => return Ok(Some(Command::Goto(target))),
+                }
+
+                let seconds = number(words.next(), "seconds")?;
+                let timeout = Duration::try_from_secs_f64(seconds)
+                    .map_err(|_| CommandError::InvalidNumber(seconds.to_string()))?;
+                let speed = match words.next() {
+                    Some("speed") => Some(number(words.next(), "units/s")?),
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => None,
+                };
+                end(words)?;
+                Ok(Some(Command::GotoWait {
+                    target,
+                    timeout,
+                    speed,
+                }))
+            }
+            "together" => {
+                let mut target = || -> Result<CordinateVec, CommandError> {
+                    Ok(CordinateVec::new(
+                        number(words.next(), "x")?,
+                        number(words.next(), "y")?,
+                        number(words.next(), "z")?,
+                    ))
+                };
+                let targets = [target()?, target()?];
+                end(words)?;
+                Ok(Some(Command::Together(targets)))
+            }
+            "program" => {
+                let command = match words.next() {
+                    Some("load") => Command::LoadProgram(word(words.next(), "file")?),
+                    Some("run") => Command::RunProgram,
+                    Some("stop") => Command::StopProgram,
+                    Some("skip") => Command::SkipWaypoint,
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => return Err(CommandError::Missing("load|run|stop|skip")),
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "pose" => {
+                let name = word(words.next(), "name")?;
+                end(words)?;
+                Ok(Some(Command::SavePose(name)))
+            }
+            "waypoint" => {
+                let pose = word(words.next(), "pose")?;
+                end(words)?;
+                Ok(Some(Command::AddWaypoint(pose)))
+            }
+            "grip" => match words.next() {
+                Some("save") => {
+                    let name = word(words.next(), "name")?;
+                    let mut speed = None;
+                    let mut replace = false;
+                    while let Some(word) = words.next() {
+                        match word {
+                            "speed" => speed = Some(number(words.next(), "percent/s")?),
+                            "replace" => replace = true,
+                            word => return Err(CommandError::Unexpected(word.to_string())),
+                        }
+                    }
+                    Ok(Some(Command::GripSave {
+                        name,
+                        speed,
+                        replace,
+                    }))
+                }
+                Some("use") => {
+                    let name = word(words.next(), "name")?;
+                    end(words)?;
+                    Ok(Some(Command::GripUse(name)))
+                }
+                Some(word) => Err(CommandError::Unexpected(word.to_string())),
+                None => Err(CommandError::Missing("save|use")),
+            },
+            "export" => {
+                let command = match words.next() {
+                    Some("program") => Command::ExportProgram(word(words.next(), "file")?),
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => return Err(CommandError::Missing("program")),
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "calibrate" => {
+                let command = match words.next() {
+                    Some("point") => Command::CalibratePoint(CordinateVec::new(
+                        number(words.next(), "x")?,
+                        number(words.next(), "y")?,
+                        number(words.next(), "z")?,
+                    )),
+                    Some("fit") => {
+                        let rotate = match words.next() {
+                            Some("rotate") => true,
+                            Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                            None => false,
+                        };
+                        Command::CalibrateFit { rotate }
+                    }
+                    Some("clear") => Command::CalibrateClear,
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => return Err(CommandError::Missing("point|fit|clear")),
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "mirror" => {
+                let on = match words.next() {
+                    Some("on") => true,
+                    Some("off") => false,
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => return Err(CommandError::Missing("on|off")),
+                };
+                end(words)?;
+                Ok(Some(Command::Mirror(on)))
+            }
+            "payload" => {
+                let grams = number(words.next(), "grams")?;
+                if grams < 0. {
+                    return Err(CommandError::InvalidNumber(grams.to_string()));
+                }
+                end(words)?;
+                Ok(Some(Command::Payload(grams)))
+            }
+            "output" => {
+                let command = match words.next() {
+                    Some("every") => Command::OutputDivider(ticks(words.next())?),
+                    Some("rate") => match words.next() {
+                        Some("off") => Command::OutputRate(None),
+                        word => {
+                            let hz = number(word, "hz")?;
+                            if hz <= 0. || !hz.is_finite() {
+                                return Err(CommandError::InvalidNumber(hz.to_string()));
+                            }
+                            Command::OutputRate(Some(hz))
+                        }
+                    },
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => return Err(CommandError::Missing("every|rate")),
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "segments" => {
+                let command = match words.next() {
+                    Some("off") => Command::SegmentsStop,
+                    Some(target) => {
+                        let every = match words.next() {
+                            Some("every") => ticks(words.next())?,
+                            Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                            None => 1,
+                        };
+                        Command::SegmentsStart {
+                            target: target.to_string(),
+                            every,
+                        }
+                    }
+                    None => return Err(CommandError::Missing("file|udp://host:port|off")),
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "servo" => {
+                let command = match word(words.next(), "joint|off")?.as_str() {
+                    "off" => Command::ServoOff,
+                    joint => {
+                        let pulse = number(words.next(), "µs")?;
+                        if !(0. ..=u16::MAX as f64).contains(&pulse) || pulse.fract() != 0. {
+                            return Err(CommandError::InvalidNumber(pulse.to_string()));
+                        }
+                        Command::Servo {
+                            joint: joint.to_string(),
+                            pulse: pulse as u16,
+                        }
+                    }
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "joint" => {
+                let joint = word(words.next(), "joint")?;
+                let angle = number(words.next(), "degrees")?;
+                end(words)?;
+                Ok(Some(Command::Joint { joint, angle }))
+            }
+            "workspace" => {
+                let name = match word(words.next(), "name|none")?.as_str() {
+                    "none" => None,
+                    name => Some(name.to_string()),
+                };
+                end(words)?;
+                Ok(Some(Command::Workspace(name)))
+            }
+            "envelope" => {
+                let command = match words.next() {
+                    Some("learn") => Command::Envelope(EnvelopeMode::Learn),
+                    Some("enforce") => Command::Envelope(EnvelopeMode::Enforce),
+                    Some("off") => Command::Envelope(EnvelopeMode::Off),
+                    Some("clear") => Command::EnvelopeClear,
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => return Err(CommandError::Missing("learn|enforce|off|clear")),
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "boundary" => {
+                let policy = match word(words.next(), "hard|soft|off")?.as_str() {
+                    "hard" => BoundaryPolicy::HardClamp,
+                    "soft" => BoundaryPolicy::SoftMargin,
+                    "off" => BoundaryPolicy::Off,
+                    word => return Err(CommandError::Unexpected(word.to_string())),
+                };
+                end(words)?;
+                Ok(Some(Command::Boundary(policy)))
+            }
+            "resume" => {
+                end(words)?;
+                Ok(Some(Command::Resume))
+            }
+            "release" => {
+                end(words)?;
+                Ok(Some(Command::Release))
+            }
+            "limits" => {
+                let command = match words.next() {
+                    Some("find") => Command::LimitsFind(word(words.next(), "joint")?),
+                    Some("confirm") => Command::LimitsConfirm(word(words.next(), "joint")?),
+                    Some("abort") => Command::LimitsAbort,
+                    Some("save") => Command::LimitsSave(word(words.next(), "file")?),
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => return Err(CommandError::Missing("find|confirm|abort|save")),
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "undo" => {
+                end(words)?;
+                Ok(Some(Command::Undo))
+            }
+            "check" => {
+                let command = match words.next() {
+                    Some("program") => Command::CheckProgram,
+                    path => Command::Check(path.map(str::to_string)),
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "version" => {
+                end(words)?;
+                Ok(Some(Command::Version))
+            }
+            "stats" => {
+                let command = match words.next() {
+                    Some("reset") => Command::StatsReset,
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => Command::Stats,
+                };
+                end(words)?;
+                Ok(Some(command))
+            }
+            "bundle" => {
+                match words.next() {
+                    Some("now") => {}
+                    Some(word) => return Err(CommandError::Unexpected(word.to_string())),
+                    None => return Err(CommandError::Missing("now")),
+                }
+                let dir = words.next().map(str::to_string);
+                end(words)?;
+                Ok(Some(Command::Bundle(dir)))
+            }
+            "quit" => {
+                end(words)?;
+                Ok(Some(Command::Quit))
+            }
+            name => Err(CommandError::Unknown(name.to_string())),
+        }
+    }
+
+    /// Run the command like [`Command::execute`], waiting for a `goto ... wait` to end
+    ///
+    /// The robot is updated while waiting, see [`Robot::goto_blocking`] for the arguments
+    pub fn run(
+        &self,
+        robot: &mut Robot,
+        clock: &mut impl Clock,
+        cancel: &AtomicBool,
+        on_update: impl FnMut(&mut Robot, &UpdateReport, f64),
+    ) -> Result<(), CommandError> {
+        let Command::GotoWait {
+            target,
+            timeout,
+            speed,
+        } = self
+        else {
+            return self.execute(robot);
+        };
+
+        robot.check_payload(*target).map_err(CommandError::Payload)?;
+        robot.stop_program();
+        let result = robot
+            .goto_blocking(*target, *speed, *timeout, clock, cancel, on_update)
+            .map_err(CommandError::Connection)?;
+        if result.outcome != GotoOutcome::Reached {
+            return Err(CommandError::NotReached(result));
+        }
+
+        info(&format!(
+            "Reached {target:?} after {:.1}s",
+            result.elapsed.as_secs_f64()
+        ));
+        Ok(())
+    }
+
+    /// Run the command with a second arm following `robot`, only `together` needs it
+    pub fn execute_with(
+        &self,
+        robot: &mut Robot,
+        follower: &mut Robot,
+        coordinator: &mut Coordinator,
+    ) -> Result<(), CommandError> {
+        let Command::Together([leading, following]) = *self else {
+            return self.execute(robot);
+        };
+
+        robot.check_payload(leading).map_err(CommandError::Payload)?;
+        follower.check_payload(following).map_err(CommandError::Payload)?;
+        robot.stop_program();
+        follower.stop_program();
+        coordinator.move_together(robot, follower, [leading, following]);
+        Ok(())
+    }
+
+    /// Run the command on the robot
+    ///
+    /// A `goto ... wait` doesn't wait here, see [`Command::run`]
+    pub fn execute(&self, robot: &mut Robot) -> Result<(), CommandError> {
+        match self {
+            Command::Replay { path, transform } => {
+                let recording = Recording::load(path).map_err(CommandError::Recording)?;
+                robot
+                    .start_replay(&recording, transform)
+                    .map_err(CommandError::Recording)
+            }
+            Command::ReplayJoints(path) => {
+                let recording = Recording::load(path).map_err(CommandError::Recording)?;
+                robot
+                    .start_joint_replay(&recording)
+                    .map_err(CommandError::Recording)
+            }
+            Command::RecordStart => {
+                robot.start_recording();
+                Ok(())
+            }
+            Command::RecordStop { path, cartesian } => {
+                let recording = robot.stop_recording().ok_or(CommandError::NotRecording)?;
+                let saved = if *cartesian {
+                    recording
+                        .to_cartesian(robot.upper_arm, robot.lower_arm)
+                        .save(path)
+                } else {
+                    recording.save(path)
+                };
+                saved.map_err(CommandError::Recording)
+            }
+            Command::ToCartesian { from, to } => {
+                let joints: Recording<JointAngles> =
+                    Recording::load(from).map_err(CommandError::Recording)?;
+                joints
+                    .to_cartesian(robot.upper_arm, robot.lower_arm)
+                    .save(to)
+                    .map_err(CommandError::Recording)
+            }
+            Command::ToJoints { from, to, claw } => {
+                let cartesian: Recording = Recording::load(from).map_err(CommandError::Recording)?;
+                let claw: Option<Recording<JointAngles>> = match claw {
+                    Some(path) => Some(Recording::load(path).map_err(CommandError::Recording)?),
+                    None => None,
+                };
+                cartesian
+                    .to_joints(robot.upper_arm, robot.lower_arm, claw.as_ref())
+                    .and_then(|joints| joints.save(to))
+                    .map_err(CommandError::Recording)
+            }
+            Command::Joints => {
+                let angles = operator::to_operator(robot.arm.angles());
+                info(&format!(
+                    "base {:.1}°, shoulder {:.1}°, elbow {:.1}°, claw {:.1}°",
+                    angles.base, angles.shoulder, angles.elbow, angles.claw
+                ));
+                Ok(())
+            }
+            Command::Goto(target) | Command::GotoWait { target, .. } => {
+                robot.check_payload(*target).map_err(CommandError::Payload)?;
+                robot.stop_program();
+                if !robot.retarget(*target) {
+                    return Err(CommandError::Unreachable(*target));
+                }
+                Ok(())
+            }
+            Command::Together(_) => Err(CommandError::SingleArm),
+            Command::LoadProgram(path) => {
+                let program = Program::load(path).map_err(CommandError::Program)?;
+                robot.stop_program();
+                info(&format!(
+                    "Loaded {} poses and {} waypoints from {path}",
+                    program.poses.len(),
+                    program.waypoints.len()
+                ));
+                robot.program = program;
+                Ok(())
+            }
+            Command::RunProgram => robot.run_program().map_err(CommandError::Program),
+            Command::StopProgram => {
+                if !robot.stop_program() {
+                    return Err(CommandError::NotRunning);
+                }
+                Ok(())
+            }
+            Command::SkipWaypoint => {
+                if !robot.skip_waypoint() {
+                    return Err(CommandError::NotRunning);
+                }
+                Ok(())
+            }
+            Command::SavePose(name) => {
+                robot.save_pose(name);
+                Ok(())
+            }
+            Command::AddWaypoint(pose) => {
+                let program = &mut robot.program;
+                if !program.poses.contains_key(pose) {
+                    return Err(CommandError::Program(ProgramError::UnknownPose {
+                        step: program.waypoints.len(),
+                        name: pose.clone(),
+                    }));
+                }
+                program.waypoints.push(Waypoint {
+                    pose: Some(pose.clone()),
+                    ..Default::default()
+                });
+                Ok(())
+            }
+            Command::GripSave {
+                name,
+                speed,
+                replace,
+            } => {
+                let grip = robot
+                    .save_grip(name, *speed, *replace)
+                    .map_err(CommandError::Grip)?;
+                info(&format!(
+                    "Saved grip `{name}` at {:.0}% closed, {}%/s",
+                    grip.percent, grip.speed
+                ));
+                Ok(())
+            }
+            Command::GripUse(name) => robot.use_grip(name).map_err(CommandError::Grip),
+            Command::ExportProgram(path) => robot.program.save(path).map_err(CommandError::Program),
+            Command::CalibratePoint(world) => {
+                robot.calibration_points.push(ReferencePoint {
+                    internal: robot.position,
+                    world: *world,
+                });
+                Ok(())
+            }
+            Command::CalibrateFit { rotate } => {
+                let calibration = Calibration::fit(&robot.calibration_points, *rotate)
+                    .map_err(CommandError::Calibration)?;
+                robot.set_calibration(calibration);
+                robot.calibration_points.clear();
+
+                // so it can be copied into the config file
+                if let Ok(json) = serde_json::to_string(&calibration) {
+                    info(&format!("\"calibration\": {json}"));
+                }
+                Ok(())
+            }
+            Command::CalibrateClear => {
+                robot.set_calibration(Calibration::default());
+                robot.calibration_points.clear();
+                Ok(())
+            }
+            Command::Mirror(on) => {
+                robot.mirror = *on;
+                Ok(())
+            }
+            Command::Payload(grams) => {
+                robot.set_payload(*grams);
+                Ok(())
+            }
+            Command::SegmentsStart { target, every } => {
+                robot.segment_stream =
+                    Some(SegmentStream::open(target, *every).map_err(CommandError::Stream)?);
+                Ok(())
+            }
+            Command::SegmentsStop => match robot.segment_stream.take() {
+                Some(mut stream) => stream.flush().map_err(CommandError::Stream),
+                None => Ok(()),
+            },
+            Command::Servo { joint, pulse } => robot
+                .override_servo(joint, *pulse)
+                .map_err(CommandError::Override),
+            Command::Joint { joint, angle } => robot
+                .override_joint(joint, *angle)
+                .map_err(CommandError::Override),
+            Command::ServoOff => match robot.end_servo_override() {
+                true => Ok(()),
+                false => Err(CommandError::NotOverridden),
+            },
+            Command::OutputDivider(ticks) => {
+                robot.output.divider = *ticks;
+                Ok(())
+            }
+            Command::OutputRate(hz) => {
+                robot.output.max_hz = *hz;
+                Ok(())
+            }
+            Command::Workspace(name) => robot
+                .select_workspace(name.as_deref())
+                .map_err(CommandError::Workspace),
+            Command::Envelope(mode) => robot
+                .set_envelope_mode(*mode)
+                .map_err(CommandError::Envelope),
+            Command::EnvelopeClear => {
+                robot.envelope.clear();
+                // nothing left to enforce
+                if robot.envelope_mode == EnvelopeMode::Enforce {
+                    robot.envelope_mode = EnvelopeMode::Off;
+                }
+                Ok(())
+            }
+            Command::Boundary(policy) => robot
+                .set_boundary_policy(*policy)
+                .map_err(CommandError::Boundary),
+            Command::Resume => {
+                robot.resume();
+                Ok(())
+            }
+            Command::Release => {
+                if !robot.release_emergency_stop() {
+                    return Err(CommandError::Braking);
+                }
+                Ok(())
+            }
+            Command::LimitsFind(joint) => {
+                robot.find_limits(joint).map_err(CommandError::Limits)?;
+                info(&format!(
+                    "The {joint} will turn to both of its end stops at {}°/s, start with \
+                     `limits confirm {joint}`",
+                    robot.limit_search.speed.clamp(1., MAX_SEARCH_SPEED)
+                ));
+                Ok(())
+            }
+            Command::LimitsConfirm(joint) => robot
+                .confirm_limit_search(joint)
+                .map_err(CommandError::Limits),
+            Command::LimitsAbort => {
+                if !robot.abort_limit_search() {
+                    return Err(CommandError::NotSearching);
+                }
+                Ok(())
+            }
+            Command::LimitsSave(path) => {
+                config::write_joint_limits(path, &robot.joint_limits).map_err(CommandError::Config)
+            }
+            Command::Undo => {
+                let change = robot.undo().map_err(CommandError::Journal)?;
+                info(&format!("Reverted the last change to the {change}"));
+                Ok(())
+            }
+            Command::Check(path) => {
+                let report = robot.audit();
+                report.log();
+                match path {
+                    Some(path) => report.save(path).map_err(CommandError::Report),
+                    None => Ok(()),
+                }
+            }
+            Command::CheckProgram => {
+                if robot.program.waypoints.is_empty() {
+                    return Err(CommandError::Program(ProgramError::Empty));
+                }
+                robot.dry_run(&robot.program).log();
+                Ok(())
+            }
+            Command::Version => {
+                info(&format!("session {}", SessionHeader::new(robot)));
+                Ok(())
+            }
+            Command::Stats => {
+                info(&format!("stats\n{}", display::stats_table(&robot.state())));
+                Ok(())
+            }
+            Command::StatsReset => {
+                robot.reset_stats();
+                Ok(())
+            }
+            Command::Bundle(dir) => {
+                let dir = match dir {
+                    Some(dir) => PathBuf::from(dir),
+                    None if !robot.bundle.on_exit.is_empty() => {
+                        let millis = (session::clock().now() * 1000.) as u64;
+                        PathBuf::from(&robot.bundle.on_exit).join(format!("snapshot-{millis}"))
+                    }
+                    None => return Err(CommandError::Missing("dir")),
+                };
+                let manifest = robot.write_bundle(&dir).map_err(CommandError::Bundle)?;
+                info(&format!(
+                    "bundled {} files into {}",
+                    manifest.files.len() + 1,
+                    dir.display()
+                ));
+                Ok(())
+            }
+            Command::Quit => {
+                robot.begin_shutdown(ShutdownReason::Quit);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn word(word: Option<&str>, name: &'static str) -> Result<String, CommandError> {
+    word.map(str::to_string).ok_or(CommandError::Missing(name))
+}
+
+fn number(word: Option<&str>, name: &'static str) -> Result<f64, CommandError> {
+    let word = word.ok_or(CommandError::Missing(name))?;
+    word.parse()
+        .map_err(|_| CommandError::InvalidNumber(word.to_string()))
+}
+
+/// A whole number of ticks, at least 1
+fn ticks(word: Option<&str>) -> Result<u32, CommandError> {
+    let ticks = number(word, "ticks")?;
+    if ticks < 1. || ticks.fract() != 0. || ticks > u32::MAX as f64 {
+        return Err(CommandError::InvalidNumber(ticks.to_string()));
+    }
+    Ok(ticks as u32)
+}
+
+/// Fails if there are arguments left
+fn end<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<(), CommandError> {
+    match words.next() {
+        Some(word) => Err(CommandError::Unexpected(word.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Read lines from stdin on a separate thread so the control loop never blocks on input
+pub fn spawn_stdin() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Unknown(name) => write!(f, "unknown command `{name}`"),
+            CommandError::Missing(name) => write!(f, "missing argument <{name}>"),
+            CommandError::InvalidNumber(word) => write!(f, "`{word}` is not a number"),
+            CommandError::Unexpected(word) => write!(f, "unexpected argument `{word}`"),
+            CommandError::NotRecording => write!(f, "not recording"),
+            CommandError::Recording(err) => write!(f, "{err}"),
+            CommandError::Calibration(err) => write!(f, "{err}"),
+            CommandError::Workspace(err) => write!(f, "{err}"),
+            CommandError::Envelope(err) => write!(f, "{err}"),
+            CommandError::Boundary(err) => write!(f, "{err}"),
+            CommandError::Report(err) => write!(f, "{err}"),
+            CommandError::Stream(err) => write!(f, "{err}"),
+            CommandError::Unreachable(target) => write!(f, "the head can't be at {target:?}"),
+            CommandError::Braking => write!(f, "still braking, release once stopped"),
+            CommandError::Limits(err) => write!(f, "{err}"),
+            CommandError::NotSearching => write!(f, "not searching for limits"),
+            CommandError::Config(err) => write!(f, "{err}"),
+            CommandError::Program(err) => write!(f, "{err}"),
+            CommandError::Payload(err) => write!(f, "{err}"),
+            CommandError::NotRunning => write!(f, "no program running"),
+            CommandError::NotReached(result) => write!(
+                f,
+                "{} {:.1} from the target after {:.1}s",
+                match result.outcome {
+                    GotoOutcome::Reached => "reached",
+                    GotoOutcome::TimedOut => "timed out",
+                    GotoOutcome::Cancelled => "cancelled",
+                    GotoOutcome::Unreachable => "out of reach",
+                },
+                result.error,
+                result.elapsed.as_secs_f64()
+            ),
+            CommandError::Connection(err) => write!(f, "{err}"),
+            CommandError::Journal(err) => write!(f, "{err}"),
+            CommandError::Grip(err) => write!(f, "{err}"),
+            CommandError::Override(err) => write!(f, "{err}"),
+            CommandError::NotOverridden => write!(f, "no joint overridden"),
+            CommandError::SingleArm => write!(f, "there is no second arm"),
+            CommandError::Bundle(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        clock::SimClock,
+        kinematics::joints::{DirectDrive, Joint},
+        robot::torque::TorqueLimit,
+    };
+    use kinematics_core::assert_approx_eq;
+
+    #[test]
+    fn parse_replay() {
+        assert_eq!(
+            Command::parse("replay pick.json offset 0 -80 0").unwrap(),
+            Some(Command::Replay {
+                path: "pick.json".to_string(),
+                transform: Transform {
+                    offset: CordinateVec::new(0., -80., 0.),
+                    rotation: 0.,
+                    mirror: false,
+                },
+            })
+        );
+
+        assert_eq!(
+            Command::parse("  replay pick.json rotate 45 offset 1 2 3").unwrap(),
+            Some(Command::Replay {
+                path: "pick.json".to_string(),
+                transform: Transform {
+                    offset: CordinateVec::new(1., 2., 3.),
+                    rotation: 45.,
+                    mirror: false,
+                },
+            })
+        );
+
+        assert_eq!(
+            Command::parse("replay pick.json mirror").unwrap(),
+            Some(Command::Replay {
+                path: "pick.json".to_string(),
+                transform: Transform {
+                    mirror: true,
+                    ..Default::default()
+                },
+            })
+        );
+
+        assert_eq!(Command::parse("   ").unwrap(), None);
+    }
+
+    #[test]
+    fn parse_joint_space() {
+        assert_eq!(
+            Command::parse("replay pick.json joints").unwrap(),
+            Some(Command::ReplayJoints("pick.json".to_string()))
+        );
+        assert_eq!(Command::parse("record start").unwrap(), Some(Command::RecordStart));
+        assert_eq!(
+            Command::parse("record stop pick.json cartesian").unwrap(),
+            Some(Command::RecordStop {
+                path: "pick.json".to_string(),
+                cartesian: true
+            })
+        );
+        assert_eq!(
+            Command::parse("cartesian a.json b.json").unwrap(),
+            Some(Command::ToCartesian {
+                from: "a.json".to_string(),
+                to: "b.json".to_string()
+            })
+        );
+        assert_eq!(
+            Command::parse("joints b.json c.json claw a.json").unwrap(),
+            Some(Command::ToJoints {
+                from: "b.json".to_string(),
+                to: "c.json".to_string(),
+                claw: Some("a.json".to_string())
+            })
+        );
+
+        // transforms only apply to cartesian recordings
+        assert!(matches!(
+            Command::parse("replay pick.json joints offset 1 2 3"),
+            Err(CommandError::Unexpected(word)) if word == "offset"
+        ));
+        assert!(matches!(
+            Command::execute(&Command::RecordStop { path: String::new(), cartesian: false }, &mut Robot::default()),
+            Err(CommandError::NotRecording)
+        ));
+    }
+
+    #[test]
+    fn parse_goto_and_mirror() {
+        assert_eq!(
+            Command::parse("goto 10 -20 30").unwrap(),
+            Some(Command::Goto(CordinateVec::new(10., -20., 30.)))
+        );
+        assert_eq!(Command::parse("mirror on").unwrap(), Some(Command::Mirror(true)));
+        assert_eq!(Command::parse("mirror off").unwrap(), Some(Command::Mirror(false)));
+        assert!(matches!(
+            Command::parse("mirror"),
+            Err(CommandError::Missing(_))
+        ));
+        assert!(matches!(
+            Command::parse("goto 1 2 3 4"),
+            Err(CommandError::Unexpected(word)) if word == "4"
+        ));
+    }
+
+    #[test]
+    fn together() {
+        let command = Command::parse("together 0 100 50 10 100 50").unwrap().unwrap();
+        assert_eq!(
+            command,
+            Command::Together([
+                CordinateVec::new(0., 100., 50.),
+                CordinateVec::new(10., 100., 50.)
+            ])
+        );
+        assert!(matches!(
+            Command::parse("together 0 100 50 10 100"),
+            Err(CommandError::Missing("z"))
+        ));
+
+        // it takes a second arm
+        let mut robot = Robot::default();
+        assert!(matches!(
+            command.execute(&mut robot),
+            Err(CommandError::SingleArm)
+        ));
+        let mut follower = Robot::default();
+        let mut coordinator = Coordinator::new(Default::default());
+        command
+            .execute_with(&mut robot, &mut follower, &mut coordinator)
+            .unwrap();
+        assert_eq!(robot.target_position, Some(CordinateVec::new(0., 100., 50.)));
+        assert_eq!(follower.target_position, Some(CordinateVec::new(10., 100., 50.)));
+    }
+
+    #[test]
+    fn goto_wait() {
+        assert_eq!(
+            Command::parse("goto 10 -20 30 wait 5 speed 40").unwrap(),
+            Some(Command::GotoWait {
+                target: CordinateVec::new(10., -20., 30.),
+                timeout: Duration::from_secs(5),
+                speed: Some(40.),
+            })
+        );
+        assert!(matches!(
+            Command::parse("goto 1 2 3 wait -1"),
+            Err(CommandError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            Command::parse("goto 1 2 3 wait"),
+            Err(CommandError::Missing("seconds"))
+        ));
+
+        // the target is in the operator's frame like a plain goto
+        let mut robot = Robot {
+            position: CordinateVec::new(-20., 50., 50.),
+            mirror: true,
+            ..Default::default()
+        };
+        let mut updates = 0;
+        let goto = Command::parse("goto 30 60 40 wait 10").unwrap().unwrap();
+        goto.run(
+            &mut robot,
+            &mut SimClock { step: 0.01 },
+            &AtomicBool::new(false),
+            |_, _, _| updates += 1,
+        )
+        .unwrap();
+        assert_eq!(robot.position, CordinateVec::new(-30., 60., 40.));
+        assert!(updates > 0);
+
+        let goto = Command::parse("goto 60 90 120 wait 0.5 speed 5").unwrap().unwrap();
+        let err = goto
+            .run(
+                &mut robot,
+                &mut SimClock { step: 0.1 },
+                &AtomicBool::new(false),
+                |_, _, _| {},
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CommandError::NotReached(GotoResult {
+                outcome: GotoOutcome::TimedOut,
+                ..
+            })
+        ));
+        assert_eq!(robot.target_position, None);
+    }
+
+    #[test]
+    fn payload() {
+        assert_eq!(Command::parse("payload 400").unwrap(), Some(Command::Payload(400.)));
+        assert!(matches!(
+            Command::parse("payload -5"),
+            Err(CommandError::InvalidNumber(_))
+        ));
+        assert!(matches!(Command::parse("payload"), Err(CommandError::Missing(_))));
+
+        // a goto past the derated reach is turned down, naming the limit
+        let mut robot = Robot {
+            torque_limit: Some(TorqueLimit {
+                enabled: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let far = Command::Goto(CordinateVec::new(0., 170., 0.));
+        far.execute(&mut robot).unwrap();
+        robot.target_position = None;
+
+        Command::Payload(400.).execute(&mut robot).unwrap();
+        let err = far.execute(&mut robot).unwrap_err();
+        let radius = robot.derating.radius.unwrap();
+        assert!(err
+            .to_string()
+            .contains(&format!("beyond the {radius:.1} the arm can hold with 400g")));
+        assert_eq!(robot.target_position, None);
+        Command::Goto(CordinateVec::new(0., radius - 10., 0.))
+            .execute(&mut robot)
+            .unwrap();
+        assert!(robot.target_position.is_some());
+    }
+
+    #[test]
+    fn parse_output() {
+        assert_eq!(Command::parse("output every 4").unwrap(), Some(Command::OutputDivider(4)));
+        assert_eq!(
+            Command::parse("output rate 50").unwrap(),
+            Some(Command::OutputRate(Some(50.)))
+        );
+        assert_eq!(Command::parse("output rate off").unwrap(), Some(Command::OutputRate(None)));
+
+        for line in ["output every 0", "output every 2.5", "output rate 0", "output rate -5"] {
+            assert!(matches!(Command::parse(line), Err(CommandError::InvalidNumber(_))), "{line}");
+        }
+
+        let mut robot = Robot::default();
+        Command::OutputDivider(4).execute(&mut robot).unwrap();
+        Command::OutputRate(Some(50.)).execute(&mut robot).unwrap();
+        assert_eq!(robot.output.divider, 4);
+        assert_eq!(robot.output.max_hz, Some(50.));
+    }
+
+    #[test]
+    fn segments() {
+        assert_eq!(
+            Command::parse("segments udp://127.0.0.1:9000 every 5").unwrap(),
+            Some(Command::SegmentsStart {
+                target: "udp://127.0.0.1:9000".to_string(),
+                every: 5,
+            })
+        );
+        assert_eq!(Command::parse("segments off").unwrap(), Some(Command::SegmentsStop));
+        assert!(matches!(
+            Command::parse("segments arm.csv every 0"),
+            Err(CommandError::InvalidNumber(_))
+        ));
+
+        let mut robot = Robot::default();
+        assert!(matches!(
+            Command::SegmentsStart {
+                target: "/no/such/dir/arm.csv".to_string(),
+                every: 1,
+            }
+            .execute(&mut robot),
+            Err(CommandError::Stream(_))
+        ));
+        assert!(robot.segment_stream.is_none());
+        Command::SegmentsStop.execute(&mut robot).unwrap();
+    }
+
+    #[test]
+    fn servo() {
+        assert_eq!(
+            Command::parse("servo elbow 1500").unwrap(),
+            Some(Command::Servo {
+                joint: "elbow".to_string(),
+                pulse: 1500,
+            })
+        );
+        assert_eq!(Command::parse("servo off").unwrap(), Some(Command::ServoOff));
+        assert!(matches!(
+            Command::parse("servo elbow 1500.5"),
+            Err(CommandError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            Command::parse("servo elbow"),
+            Err(CommandError::Missing("µs"))
+        ));
+
+        let mut robot = Robot::default();
+        assert!(matches!(
+            Command::ServoOff.execute(&mut robot),
+            Err(CommandError::NotOverridden)
+        ));
+        assert!(matches!(
+            Command::Servo {
+                joint: "wrist".to_string(),
+                pulse: 1500,
+            }
+            .execute(&mut robot),
+            Err(CommandError::Override(OverrideError::UnknownJoint(_)))
+        ));
+        Command::Servo {
+            joint: "claw".to_string(),
+            pulse: 1000,
+        }
+        .execute(&mut robot)
+        .unwrap();
+        Command::ServoOff.execute(&mut robot).unwrap();
+        assert!(robot.servo_override.unwrap().returning);
+    }
+
+    #[test]
+    fn joint() {
+        assert_eq!(Command::parse("joints").unwrap(), Some(Command::Joints));
+        assert_eq!(
+            Command::parse("joint shoulder 45").unwrap(),
+            Some(Command::Joint {
+                joint: "shoulder".to_string(),
+                angle: 45.,
+            })
+        );
+        assert!(matches!(
+            Command::parse("joint shoulder"),
+            Err(CommandError::Missing("degrees"))
+        ));
+
+        // raising the shoulder 45° over horizontal is 45° from straight up
+        let mut robot = Robot::default();
+        robot.arm.shoulder = Joint::new(0., 180., DirectDrive::new());
+        Command::parse("joint shoulder 45")
+            .unwrap()
+            .unwrap()
+            .execute(&mut robot)
+            .unwrap();
+        let target = robot.servo_override.unwrap().target;
+        assert!((target - 45.).abs() < 0.5, "{target}");
+        Command::parse("joint shoulder 60")
+            .unwrap()
+            .unwrap()
+            .execute(&mut robot)
+            .unwrap();
+        let target = robot.servo_override.unwrap().target;
+        assert!((target - 30.).abs() < 0.5, "{target}");
+        assert!(matches!(
+            Command::Joint {
+                joint: "wrist".to_string(),
+                angle: 0.,
+            }
+            .execute(&mut robot),
+            Err(CommandError::Override(OverrideError::UnknownJoint(_)))
+        ));
+        Command::Joints.execute(&mut robot).unwrap();
+    }
+
+    #[test]
+    fn workspace() {
+        assert_eq!(
+            Command::parse("workspace desk").unwrap(),
+            Some(Command::Workspace(Some("desk".to_string())))
+        );
+        assert_eq!(Command::parse("workspace none").unwrap(), Some(Command::Workspace(None)));
+        assert!(matches!(
+            Command::parse("workspace"),
+            Err(CommandError::Missing("name|none"))
+        ));
+
+        let mut robot = Robot::default();
+        assert!(matches!(
+            Command::Workspace(Some("desk".to_string())).execute(&mut robot),
+            Err(CommandError::Workspace(WorkspaceError::Unknown(name))) if name == "desk"
+        ));
+    }
+
+    #[test]
+    fn envelope() {
+        assert_eq!(
+            Command::parse("envelope learn").unwrap(),
+            Some(Command::Envelope(EnvelopeMode::Learn))
+        );
+        assert_eq!(Command::parse("envelope clear").unwrap(), Some(Command::EnvelopeClear));
+        assert!(matches!(
+            Command::parse("envelope"),
+            Err(CommandError::Missing(_))
+        ));
+
+        // nothing to enforce until something is learned
+        let mut robot = Robot::default();
+        assert!(matches!(
+            Command::Envelope(EnvelopeMode::Enforce).execute(&mut robot),
+            Err(CommandError::Envelope(EnvelopeError::Empty))
+        ));
+
+        robot.envelope.learn(robot.position);
+        Command::Envelope(EnvelopeMode::Enforce).execute(&mut robot).unwrap();
+        Command::EnvelopeClear.execute(&mut robot).unwrap();
+        assert!(robot.envelope.is_empty());
+        assert_eq!(robot.envelope_mode, EnvelopeMode::Off);
+    }
+
+    #[test]
+    fn boundary() {
+        assert_eq!(
+            Command::parse("boundary soft").unwrap(),
+            Some(Command::Boundary(BoundaryPolicy::SoftMargin))
+        );
+        assert!(matches!(
+            Command::parse("boundary loose"),
+            Err(CommandError::Unexpected(_))
+        ));
+
+        // not off without a speed cap
+        let mut robot = Robot::default();
+        assert!(matches!(
+            Command::Boundary(BoundaryPolicy::Off).execute(&mut robot),
+            Err(CommandError::Boundary(BoundaryError::NoSpeedCap))
+        ));
+
+        robot.boundary.max_speed = Some(5.);
+        Command::Boundary(BoundaryPolicy::Off).execute(&mut robot).unwrap();
+        assert_eq!(robot.boundary.policy, BoundaryPolicy::Off);
+    }
+
+    #[test]
+    fn resume() {
+        assert_eq!(Command::parse("resume").unwrap(), Some(Command::Resume));
+        assert!(matches!(
+            Command::parse("resume now"),
+            Err(CommandError::Unexpected(word)) if word == "now"
+        ));
+
+        let mut robot = Robot {
+            stalled: Some("claw"),
+            ..Default::default()
+        };
+        Command::Resume.execute(&mut robot).unwrap();
+        assert_eq!(robot.stalled, None);
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(
+            Command::parse("limits find shoulder").unwrap(),
+            Some(Command::LimitsFind("shoulder".to_string()))
+        );
+        assert_eq!(
+            Command::parse("limits confirm shoulder").unwrap(),
+            Some(Command::LimitsConfirm("shoulder".to_string()))
+        );
+        assert_eq!(Command::parse("limits abort").unwrap(), Some(Command::LimitsAbort));
+        assert_eq!(
+            Command::parse("limits save rac.json").unwrap(),
+            Some(Command::LimitsSave("rac.json".to_string()))
+        );
+        assert!(matches!(
+            Command::parse("limits find"),
+            Err(CommandError::Missing("joint"))
+        ));
+        assert!(matches!(
+            Command::parse("limits confirm shoulder now"),
+            Err(CommandError::Unexpected(word)) if word == "now"
+        ));
+
+        let mut robot = Robot::default();
+        assert!(matches!(
+            Command::LimitsFind("wrist".to_string()).execute(&mut robot),
+            Err(CommandError::Limits(LimitError::UnknownJoint(_)))
+        ));
+        assert!(matches!(
+            Command::LimitsAbort.execute(&mut robot),
+            Err(CommandError::NotSearching)
+        ));
+        Command::LimitsFind("elbow".to_string())
+            .execute(&mut robot)
+            .unwrap();
+        Command::LimitsConfirm("elbow".to_string())
+            .execute(&mut robot)
+            .unwrap();
+        Command::LimitsAbort.execute(&mut robot).unwrap();
+        assert_eq!(robot.limit_finder, None);
+    }
+
+    #[test]
+    fn program() {
+        assert_eq!(
+            Command::parse("program load pick.json").unwrap(),
+            Some(Command::LoadProgram("pick.json".to_string()))
+        );
+        assert_eq!(Command::parse("program run").unwrap(), Some(Command::RunProgram));
+        assert_eq!(Command::parse("program skip").unwrap(), Some(Command::SkipWaypoint));
+        assert_eq!(
+            Command::parse("pose home").unwrap(),
+            Some(Command::SavePose("home".to_string()))
+        );
+        assert_eq!(
+            Command::parse("export program out.json").unwrap(),
+            Some(Command::ExportProgram("out.json".to_string()))
+        );
+        assert!(matches!(
+            Command::parse("export"),
+            Err(CommandError::Missing("program"))
+        ));
+        assert!(matches!(
+            Command::parse("program start"),
+            Err(CommandError::Unexpected(word)) if word == "start"
+        ));
+
+        // teach two poses, queue them and write them out
+        let mut robot = Robot::default();
+        assert!(matches!(
+            Command::AddWaypoint("home".to_string()).execute(&mut robot),
+            Err(CommandError::Program(ProgramError::UnknownPose { step: 0, .. }))
+        ));
+        robot.position = CordinateVec::new(0., 100., 50.);
+        Command::SavePose("home".to_string()).execute(&mut robot).unwrap();
+        robot.position = CordinateVec::new(30., 90., 40.);
+        Command::SavePose("pick".to_string()).execute(&mut robot).unwrap();
+        for pose in ["pick", "home"] {
+            Command::AddWaypoint(pose.to_string())
+                .execute(&mut robot)
+                .unwrap();
+        }
+
+        let path = std::env::temp_dir().join(format!("rac-export-{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        Command::ExportProgram(path.clone())
+            .execute(&mut robot)
+            .unwrap();
+        let loaded = Program::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, robot.program);
+        assert_eq!(loaded.target(0), Some(CordinateVec::new(30., 90., 40.)));
+
+        assert!(matches!(
+            Command::StopProgram.execute(&mut robot),
+            Err(CommandError::NotRunning)
+        ));
+        assert!(matches!(
+            Command::SkipWaypoint.execute(&mut robot),
+            Err(CommandError::NotRunning)
+        ));
+        Command::RunProgram.execute(&mut robot).unwrap();
+        assert_eq!(robot.target_position, Some(CordinateVec::new(30., 90., 40.)));
+        Command::StopProgram.execute(&mut robot).unwrap();
+        assert_eq!(robot.program_run, None);
+
+        // skipping heads for the next waypoint, and past the last one ends the program
+        Command::RunProgram.execute(&mut robot).unwrap();
+        Command::SkipWaypoint.execute(&mut robot).unwrap();
+        assert_eq!(robot.target_position, Some(CordinateVec::new(0., 100., 50.)));
+        Command::SkipWaypoint.execute(&mut robot).unwrap();
+        assert_eq!(robot.program_run, None);
+        assert_eq!(robot.target_position, None);
+
+        assert_eq!(Command::parse("check program").unwrap(), Some(Command::CheckProgram));
+        Command::CheckProgram.execute(&mut robot).unwrap();
+        assert!(matches!(
+            Command::CheckProgram.execute(&mut Robot::default()),
+            Err(CommandError::Program(ProgramError::Empty))
+        ));
+    }
+
+    #[test]
+    fn grip() {
+        assert_eq!(
+            Command::parse("grip save bottle speed 30 replace").unwrap(),
+            Some(Command::GripSave {
+                name: "bottle".to_string(),
+                speed: Some(30.),
+                replace: true,
+            })
+        );
+        assert_eq!(
+            Command::parse("grip use bottle").unwrap(),
+            Some(Command::GripUse("bottle".to_string()))
+        );
+        assert!(matches!(
+            Command::parse("grip save"),
+            Err(CommandError::Missing("name"))
+        ));
+        assert!(matches!(
+            Command::parse("grip save bottle tight"),
+            Err(CommandError::Unexpected(word)) if word == "tight"
+        ));
+
+        let mut robot = Robot::default();
+        robot.arm.claw.angle = 90.;
+        let save = |replace| Command::GripSave {
+            name: "bottle".to_string(),
+            speed: None,
+            replace,
+        };
+        save(false).execute(&mut robot).unwrap();
+        assert!(matches!(
+            save(false).execute(&mut robot),
+            Err(CommandError::Grip(GripError::Exists(name))) if name == "bottle"
+        ));
+        save(true).execute(&mut robot).unwrap();
+
+        // exported with the pose bank and back after loading
+        let path = std::env::temp_dir().join(format!("rac-grips-{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        Command::ExportProgram(path.clone())
+            .execute(&mut robot)
+            .unwrap();
+        let mut loaded = Robot::default();
+        let result = Command::LoadProgram(path.clone()).execute(&mut loaded);
+        let _ = std::fs::remove_file(&path);
+        result.unwrap();
+        assert_eq!(loaded.program.grips["bottle"].percent, 50.);
+
+        Command::GripUse("bottle".to_string())
+            .execute(&mut loaded)
+            .unwrap();
+        assert!(loaded.grip.is_some());
+        assert!(matches!(
+            Command::GripUse("jar".to_string()).execute(&mut loaded),
+            Err(CommandError::Grip(GripError::Unknown(_)))
+        ));
+    }
+
+    #[test]
+    fn release() {
+        assert_eq!(Command::parse("release").unwrap(), Some(Command::Release));
+
+        let mut robot = Robot::default();
+        robot.emergency_stop();
+        assert!(matches!(
+            Command::Release.execute(&mut robot),
+            Err(CommandError::Braking)
+        ));
+
+        robot.tick(0.01);
+        Command::Release.execute(&mut robot).unwrap();
+        assert!(!robot.estop.active());
+    }
+
+    #[test]
+    fn check() {
+        assert_eq!(Command::parse("check").unwrap(), Some(Command::Check(None)));
+        assert_eq!(
+            Command::parse("check audit.json").unwrap(),
+            Some(Command::Check(Some("audit.json".to_string())))
+        );
+        assert!(matches!(
+            Command::parse("check a.json b.json"),
+            Err(CommandError::Unexpected(word)) if word == "b.json"
+        ));
+
+        let path = std::env::temp_dir().join(format!("rac-audit-{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        Command::Check(Some(path.clone())).execute(&mut Robot::default()).unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(report["checked"].as_u64().unwrap() > 0);
+        std::fs::remove_file(path).unwrap();
+
+        assert!(matches!(
+            Command::Check(Some("/no/such/dir/audit.json".to_string())).execute(&mut Robot::default()),
+            Err(CommandError::Report(_))
+        ));
+    }
+
+    #[test]
+    fn version() {
+        assert_eq!(Command::parse("version").unwrap(), Some(Command::Version));
+        assert!(matches!(
+            Command::parse("version full"),
+            Err(CommandError::Unexpected(word)) if word == "full"
+        ));
+        Command::Version.execute(&mut Robot::default()).unwrap();
+    }
+
+    #[test]
+    fn stats() {
+        assert_eq!(Command::parse("stats").unwrap(), Some(Command::Stats));
+        assert_eq!(Command::parse("stats reset").unwrap(), Some(Command::StatsReset));
+        assert!(matches!(
+            Command::parse("stats clear"),
+            Err(CommandError::Unexpected(word)) if word == "clear"
+        ));
+
+        let mut robot = Robot::default();
+        robot.connection.stats.confirmed_baud = Some(115200);
+        robot.connection.stats.framing_errors = 40;
+        robot.link.last = robot.connection.stats;
+        robot.loop_stats.tick(0.01);
+
+        Command::Stats.execute(&mut robot).unwrap();
+        Command::StatsReset.execute(&mut robot).unwrap();
+        assert_eq!(robot.connection.stats.framing_errors, 0);
+        assert_eq!(robot.connection.stats.confirmed_baud, Some(115200));
+        assert_eq!(robot.loop_stats.ticks, 0);
+
+        // the link policy doesn't see the reset as a negative number of errors
+        robot.update(0.01).unwrap();
+        assert_eq!(robot.link.quality, 1.);
+    }
+
+    #[test]
+    fn bundle() {
+        assert_eq!(Command::parse("bundle now").unwrap(), Some(Command::Bundle(None)));
+        assert_eq!(
+            Command::parse("bundle now logs").unwrap(),
+            Some(Command::Bundle(Some("logs".to_string())))
+        );
+        assert!(matches!(
+            Command::parse("bundle"),
+            Err(CommandError::Missing("now"))
+        ));
+        assert_eq!(Command::parse("quit").unwrap(), Some(Command::Quit));
+
+        // a snapshot needs somewhere to go
+        let mut robot = Robot::default();
+        assert!(matches!(
+            Command::Bundle(None).execute(&mut robot),
+            Err(CommandError::Missing("dir"))
+        ));
+
+        let dir = std::env::temp_dir().join(format!("rac-snapshots-{}", std::process::id()));
+        robot.bundle.on_exit = dir.to_str().unwrap().to_string();
+        let result = Command::Bundle(None).execute(&mut robot);
+        let snapshots = std::fs::read_dir(&dir).map(|entries| entries.count());
+        let _ = std::fs::remove_dir_all(&dir);
+        result.unwrap();
+        assert_eq!(snapshots.unwrap(), 1);
+    }
+
+    #[test]
+    fn calibrate() {
+        assert_eq!(
+            Command::parse("calibrate point 1 2 3").unwrap(),
+            Some(Command::CalibratePoint(CordinateVec::new(1., 2., 3.)))
+        );
+        assert_eq!(
+            Command::parse("calibrate fit rotate").unwrap(),
+            Some(Command::CalibrateFit { rotate: true })
+        );
+        assert!(matches!(
+            Command::parse("calibrate fit sideways"),
+            Err(CommandError::Unexpected(word)) if word == "sideways"
+        ));
+
+        let mut robot = Robot::default();
+        assert!(matches!(
+            Command::CalibrateFit { rotate: false }.execute(&mut robot),
+            Err(CommandError::Calibration(CalibrationError::TooFewPoints))
+        ));
+
+        // real world is twice the internal units, moved up by 10
+        for (internal, world) in [
+            (CordinateVec::new(20., 50., 50.), CordinateVec::new(40., 100., 110.)),
+            (CordinateVec::new(60., 80., 100.), CordinateVec::new(120., 160., 210.)),
+        ] {
+            robot.position = internal;
+            Command::CalibratePoint(world).execute(&mut robot).unwrap();
+        }
+        Command::CalibrateFit { rotate: false }.execute(&mut robot).unwrap();
+        assert!(robot.calibration_points.is_empty());
+
+        // goto takes real world coordinates now
+        Command::Goto(CordinateVec::new(80., 120., 150.)).execute(&mut robot).unwrap();
+        let target = robot.target_position.unwrap();
+        assert_approx_eq!(target, CordinateVec::new(40., 60., 70.));
+
+        Command::CalibrateClear.execute(&mut robot).unwrap();
+        assert!(!robot.calibration.calibrated());
+
+        // the fit comes back, then the calibration before it
+        assert_eq!(Command::parse("undo").unwrap(), Some(Command::Undo));
+        Command::Undo.execute(&mut robot).unwrap();
+        assert!(robot.calibration.calibrated());
+        Command::Undo.execute(&mut robot).unwrap();
+        assert!(!robot.calibration.calibrated());
+        assert!(matches!(
+            Command::Undo.execute(&mut robot),
+            Err(CommandError::Journal(JournalError::NothingToUndo))
+        ));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            Command::parse("jump"),
+            Err(CommandError::Unknown(name)) if name == "jump"
+        ));
+        assert!(matches!(
+            Command::parse("replay"),
+            Err(CommandError::Missing("file"))
+        ));
+        assert!(matches!(
+            Command::parse("replay pick.json offset 0 -80"),
+            Err(CommandError::Missing("z"))
+        ));
+        assert!(matches!(
+            Command::parse("replay pick.json offset 0 left 0"),
+            Err(CommandError::InvalidNumber(word)) if word == "left"
+        ));
+        assert!(matches!(
+            Command::parse("replay pick.json flip"),
+            Err(CommandError::Unexpected(word)) if word == "flip"
+        ));
+    }
+}
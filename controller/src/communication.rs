@@ -1,74 +1,145 @@
 use std::{
     collections::VecDeque,
+    thread::sleep,
     time::{Duration, Instant},
 };
 
-use crate::logging::*;
+use crate::{
+    ack::AckTracker,
+    logging::*,
+    protocol::{
+        Checksum, ChecksumMode, Frame, FrameReader, ProtocolError, ServoEncoding, MAX_FRAME_LEN,
+        PREFIX,
+    },
+    ring_buffer::RingBuffer,
+    stats::ConnectionStats,
+};
 use serialport::{Error, SerialPort};
 
-/// Indicates a new message
-const PREFIX: u8 = b'\r';
+/// Size of the buffer between the serial port and the frame reader
+pub const RING_SIZE: usize = 512;
+
+/// Frames waiting in `msg_buf` before the oldest ones are dropped
+pub const MAX_QUEUED_FRAMES: usize = 128;
+
+/// Framing errors in a row before moving on to the next candidate baud rate
+pub const BAUD_ERROR_LIMIT: u32 = 10;
 
 #[derive(Debug)]
 pub struct Connection {
-    pub port: &'static str,
+    pub port: String,
+
+    /// Baud rate currently in use
     pub baud: u32,
 
+    /// Candidate baud rates, tried in order until one completes the handshake
+    pub bauds: Vec<u32>,
+
+    /// How long to wait for the arduino to answer the handshake
+    pub handshake_timeout: Duration,
+
+    /// Framing errors since the last valid frame
+    pub errors_since_valid: u32,
+
+    /// How the checksum algorithm is picked
+    pub checksum_mode: ChecksumMode,
+
+    /// Checksum algorithm currently in use for both directions
+    pub checksum: Checksum,
+
+    /// How servo values are represented in both directions, a firmware that asks for an
+    /// encoding in the handshake gets it
+    pub servo_encoding: ServoEncoding,
+
+    /// Sequence number of the next frame sent by [`Connection::send`]
+    pub tx_seq: u8,
+
+    /// Frames sent by [`Connection::send`] that are waiting for an ACK
+    pub acks: AckTracker,
+
     /// Serial connection to arduino
     pub con: Option<Box<dyn SerialPort>>,
 
     /// Instant of last write
+    #[allow(dead_code)]
     pub last_write: Instant,
 
-    /// buffer for reading messages into
-    pub read_buf: Vec<u8>,
+    /// Raw bytes read from the serial port that haven't been framed yet
+    pub ring: RingBuffer<RING_SIZE>,
+
+    /// Framing state machine consuming `ring`
+    pub reader: FrameReader,
+
+    /// Bufer of frames that haven't been handled yet
+    pub msg_buf: VecDeque<Frame>,
 
-    /// Bufer of messages that haven't been handled yet
-    pub msg_buf: VecDeque<Message>,
+    /// Outgoing messages are built here so writing them doesn't allocate
+    pub scratch: Vec<u8>,
 
     /// If this value is true any operation that will require the arduino to be
     /// connected will be ignored. Usefull for debugging and testing
     pub no_connect: bool,
+
+    pub stats: ConnectionStats,
 }
 
 #[derive(Debug)]
 pub enum ComError {
     NotConnected,
+    HandshakeTimeout,
     Error(std::io::Error),
 }
 
-pub type Message = Vec<u8>;
-
 impl Default for Connection {
     fn default() -> Self {
         Self {
-            port: "",
+            port: String::new(),
             baud: 0,
+            bauds: Vec::new(),
+            handshake_timeout: Duration::from_secs(3),
+            errors_since_valid: 0,
+            checksum_mode: ChecksumMode::Auto,
+            checksum: Checksum::Xor,
+            servo_encoding: ServoEncoding::MicrosecondsU16,
+            tx_seq: 0,
+            acks: AckTracker::default(),
             con: None,
             last_write: Instant::now(),
-            read_buf: Vec::new(),
+            ring: RingBuffer::new(),
+            reader: FrameReader::new(),
             msg_buf: VecDeque::new(),
+            scratch: Vec::with_capacity(MAX_FRAME_LEN + 1),
             no_connect: true,
+            stats: ConnectionStats::default(),
         }
     }
 }
 
 impl Connection {
-    pub fn new(port: &'static str, baud: u32) -> Self {
+    #[allow(dead_code)]
+    pub fn new(port: &str, baud: u32) -> Self {
+        Self::with_bauds(port, vec![baud])
+    }
+
+    /// Create a connection that falls back through several baud rates
+    ///
+    /// # Arguments
+    /// * `port` - Serial port the arduino is connected to
+    /// * `bauds` - Candidate baud rates in the order they should be tried
+    pub fn with_bauds(port: &str, bauds: Vec<u32>) -> Self {
         Self {
-            port,
-            baud,
-            con: None,
-            last_write: Instant::now(),
-            read_buf: Vec::new(),
-            msg_buf: VecDeque::new(),
-            no_connect: true,
+            port: port.to_string(),
+            baud: bauds.first().copied().unwrap_or(0),
+            bauds,
+            ..Default::default()
         }
     }
 
     /// Connect to arduino
     ///
-    /// Attempts to open a serial connection to the arduino
+    /// Opens the serial port at each candidate baud rate in turn, starting with the current one,
+    /// until the arduino answers the handshake. If no rate gets an answer the connection is left
+    /// open at the first rate that was tried.
     ///
     /// # Returns
     /// `Ok` if a connection gets established `Err` otherwise
@@ -79,15 +150,118 @@ impl Connection {
             return Ok(());
         }
 
-        // connect arduino
+        let first = self.baud;
+        for _ in 0..self.bauds.len().max(1) {
+            self.open()?;
+
+            match self.handshake() {
+                Ok(()) => {
+                    info(&format!("Handshake completed at {} baud", self.baud));
+                    self.stats.confirmed_baud = Some(self.baud);
+                    return Ok(());
+                }
+                Err(err) => warn(&format!("No handshake at {} baud: {err}", self.baud)),
+            }
+
+            self.next_baud();
+        }
+
+        warn(&format!("Arduino never answered the handshake, assuming {first} baud"));
+        self.baud = first;
+        self.open()
+    }
+
+    /// Open the serial port at the current baud rate, dropping any buffered data
+    fn open(&mut self) -> Result<(), Error> {
+        self.con = None;
+        self.ring.clear();
+        self.reader.reset();
+        self.msg_buf.clear();
+        self.errors_since_valid = 0;
+
         self.con = Some(
-            serialport::new(self.port, self.baud)
+            serialport::new(self.port.as_str(), self.baud)
                 .timeout(Duration::from_millis(100))
                 .open()?,
         );
         Ok(())
     }
 
+    /// Send a handshake and wait for the arduino to answer with its own
+    ///
+    /// The capabilities in the answer decide which checksum is used from then on, see
+    /// [`ChecksumMode::negotiate`], and may ask for a [`ServoEncoding`]. Other frames received
+    /// while waiting are discarded
+    pub fn handshake(&mut self) -> Result<(), ComError> {
+        self.checksum = self.checksum_mode.handshake();
+        self.send(&Frame::Hello {
+            capabilities: self.checksum_mode.capabilities() | self.servo_encoding.capability(),
+        })?;
+
+        let start = Instant::now();
+        while start.elapsed() < self.handshake_timeout {
+            match self.read()? {
+                Some(Frame::Hello { capabilities }) => {
+                    self.checksum = self.checksum_mode.negotiate(capabilities);
+                    info(&format!("Using {:?} checksum", self.checksum));
+                    self.accept_encoding(capabilities);
+                    return Ok(());
+                }
+                Some(_) => {}
+                None => sleep(Duration::from_millis(10)),
+            }
+        }
+
+        Err(ComError::HandshakeTimeout)
+    }
+
+    /// Switch to the servo encoding the arduino asked for in its handshake, if any
+    fn accept_encoding(&mut self, capabilities: u8) {
+        if let Some(encoding) = ServoEncoding::from_capabilities(capabilities) {
+            if encoding != self.servo_encoding {
+                info(&format!("Arduino asked for {encoding:?} servo values"));
+            }
+            self.servo_encoding = encoding;
+        }
+    }
+
+    /// Move on to the next candidate baud rate without reconnecting
+    fn next_baud(&mut self) {
+        if self.bauds.is_empty() {
+            return;
+        }
+
+        let index = self.bauds.iter().position(|&baud| baud == self.baud);
+        let next = index.map_or(0, |index| (index + 1) % self.bauds.len());
+        self.baud = self.bauds[next];
+    }
+
+    /// True if enough framing errors have happened in a row that the baud rate is likely wrong
+    pub fn baud_mismatch_suspected(&self) -> bool {
+        self.errors_since_valid >= BAUD_ERROR_LIMIT
+    }
+
+    /// Reconnect at the next candidate baud rate if the current one looks wrong
+    ///
+    /// Does nothing unless more than one baud rate is configured
+    pub fn check_baud(&mut self) -> Result<(), Error> {
+        if self.bauds.len() < 2 || !self.baud_mismatch_suspected() {
+            return Ok(());
+        }
+
+        let previous = self.baud;
+        self.next_baud();
+        self.stats.baud_switches += 1;
+        self.stats.confirmed_baud = None;
+        self.errors_since_valid = 0;
+        warn(&format!(
+            "Probable baud mismatch at {previous}, switching to {}",
+            self.baud
+        ));
+
+        self.connect()
+    }
+
     /// Write raw bytes with no preprocessing
     ///
     /// For the communication to work properly it is required to add a `\r` before
@@ -119,12 +293,27 @@ impl Connection {
             Some(port) => port,
         };
 
-        match port.write(data) {
+        match port.write_all(data) {
             Ok(_) => Ok(()),
             Err(err) => Err(ComError::Error(err)),
         }
     }
 
+    /// Encode and send a frame using the current checksum and the next sequence number
+    ///
+    /// # Returns
+    /// The sequence number the frame was sent with
+    pub fn send(&mut self, frame: &Frame) -> Result<u8, ComError> {
+        let seq = self.tx_seq;
+        self.tx_seq = self.tx_seq.wrapping_add(1);
+
+        let (checksum, encoding) = (self.checksum, self.servo_encoding);
+        self.write_scratch(|data| frame.encode_into(checksum, encoding, seq, data))?;
+        self.acks.sent(seq, Instant::now());
+        self.stats.sent.count(frame);
+        Ok(seq)
+    }
+
     /// Writes the given data to the ardunio
     ///
     /// # Arguments
@@ -132,14 +321,8 @@ impl Connection {
     ///
     /// # Returns
     /// `Ok` if the data was transmitted successfully `Err` otherwise
+    #[allow(dead_code)]
     pub fn write(&mut self, data: &[u8], allow_drooped: bool) -> Result<(), ComError> {
-        let mut message: Vec<u8> = Vec::with_capacity(data.len() + 2);
-
-        message.push(b'\r');
-        for byte in data.into_iter() {
-            message.push(*byte);
-        }
-
         if !allow_drooped {
             unreachable!("im to lazy to make it work otherwise");
         }
@@ -150,49 +333,423 @@ impl Connection {
         //     println!("Ratelimiting ({}s left)", (Instant::now() - self.last_write).as_secs_f32());
         //     Err(ComError::Ratelimit)
         // }
-        self.write_raw(message.as_slice())
+        self.write_with(|message| message.extend_from_slice(data))
     }
 
-    /// Read from serial buffer and return if a valid message was recived
+    /// Same as [`Connection::write`] with the data appended to the message by `encode`, so
+    /// it can be encoded straight into the reused buffer without allocating
+    pub fn write_with(&mut self, encode: impl FnOnce(&mut Vec<u8>)) -> Result<(), ComError> {
+        self.write_scratch(|message| {
+            message.push(PREFIX);
+            encode(message);
+        })?;
+        self.stats.servo_writes += 1;
+        Ok(())
+    }
+
+    /// Build a message in the reused buffer with `build` and write it with
+    /// [`Connection::write_raw`]
+    fn write_scratch(&mut self, build: impl FnOnce(&mut Vec<u8>)) -> Result<(), ComError> {
+        let mut message = std::mem::take(&mut self.scratch);
+        message.clear();
+        build(&mut message);
+        let result = self.write_raw(&message);
+        self.scratch = message;
+        result
+    }
+
+    /// Read from serial buffer and return if a valid frame was recived
     ///
-    /// A valid message is defined as a `\r` with 8 bytes after it
+    /// Only the bytes that are already available are read so this never blocks.
+    /// Frames are classified by their type byte, types that aren't known are returned as
+    /// [`Frame::Unknown`] so callers can still handle them
     ///
     /// # Returns
     /// `Ok` If no error occured while reading
-    /// `Ok(None)` If no message was recived
-    /// `Ok(Some(Message))` where the `Message` contains the data
-    #[allow(dead_code)]
-    pub fn read(&mut self) -> Result<Option<Message>, ComError> {
-        // do nothing if no_connect is true
+    /// `Ok(None)` If no frame was recived
+    /// `Ok(Some(Frame))` the oldest frame that hasn't been returned yet
+    pub fn read(&mut self) -> Result<Option<Frame>, ComError> {
+        // only frames that are already buffered can be returned if no_connect is true
         if self.no_connect {
             debug("Not reading due to no_connect flag");
-            return Ok(None);
+            return Ok(self.msg_buf.pop_front());
         }
 
-        let port: &mut Box<dyn SerialPort> = match &mut self.con {
+        let mut available = match &self.con {
             None => return Err(ComError::NotConnected),
-            Some(port) => port,
+            Some(port) => match port.bytes_to_read() {
+                Ok(available) => available as usize,
+                Err(err) => return Err(ComError::Error(err.into())),
+            },
         };
 
-        let mut buf: Vec<u8> = Vec::new();
-        match port.read_to_end(&mut buf) {
-            Ok(_) => {}
-            Err(err) => return Err(ComError::Error(err)),
-        }
-
-        for byte in buf {
-            match byte {
-                PREFIX => self.read_buf.clear(),
-                byte => {
-                    if self.read_buf.len() == 8 {
-                        self.msg_buf.push_back(self.read_buf.clone());
-                        self.read_buf.clear()
-                    }
-                    self.read_buf.push(byte)
-                }
+        let mut chunk = [0u8; 64];
+        while available > 0 {
+            let len = available.min(chunk.len());
+            let read = match &mut self.con {
+                None => return Err(ComError::NotConnected),
+                Some(port) => match port.read(&mut chunk[..len]) {
+                    Ok(read) => read,
+                    Err(err) => return Err(ComError::Error(err)),
+                },
+            };
+
+            if read == 0 {
+                break;
             }
+
+            available -= read;
+            self.fill(&chunk[..read]);
         }
 
+        self.process();
+
         Ok(self.msg_buf.pop_front())
     }
+
+    /// Add recived bytes to the ring buffer without framing them
+    ///
+    /// If the ring buffer is full the oldest data is dropped, the partial frame it belonged to
+    /// is discarded and the reader resynchronizes on the next prefix
+    pub fn fill(&mut self, data: &[u8]) {
+        self.stats.bytes_received += data.len() as u64;
+
+        let dropped = self.ring.push_slice(data);
+        if dropped > 0 {
+            self.stats.overflow_bytes += dropped as u64;
+            self.reader.reset();
+            warn(&format!("Read buffer overflow, dropped {dropped} bytes"));
+        }
+    }
+
+    /// Run all buffered bytes through the framing and queue complete frames in `msg_buf`
+    ///
+    /// Frames that fail to decode are dropped and counted in the stats
+    pub fn process(&mut self) {
+        while let Some(byte) = self.ring.pop() {
+            let mut result = self.reader.push(byte, self.checksum, self.servo_encoding);
+            // a rejected frame can have hidden more than one behind it
+            while let Some(frame) = result {
+                self.accept(frame);
+                result = self.reader.next(self.checksum, self.servo_encoding);
+            }
+        }
+    }
+
+    /// Queue a frame from the reader, or count why it was rejected
+    fn accept(&mut self, frame: Result<Frame, ProtocolError>) {
+        match frame {
+            Ok(frame) => {
+                self.stats.frames_received += 1;
+                self.stats.received.count(&frame);
+                self.errors_since_valid = 0;
+
+                if self.msg_buf.len() >= MAX_QUEUED_FRAMES {
+                    self.msg_buf.pop_front();
+                    self.stats.frames_dropped += 1;
+                }
+                self.msg_buf.push_back(frame);
+            }
+            Err(err) => {
+                self.stats.framing_errors += 1;
+                match self.reader.last_kind {
+                    Some(kind) => self.stats.errors.count_kind(kind),
+                    None => self.stats.errors.unknown += 1,
+                }
+                self.errors_since_valid += 1;
+                warn(&format!("Dropping frame: {err}"));
+            }
+        }
+    }
+
+    /// Same as calling [`Connection::fill`] followed by [`Connection::process`]
+    #[allow(dead_code)]
+    pub fn receive(&mut self, data: &[u8]) {
+        self.fill(data);
+        self.process();
+    }
+}
+
+impl std::fmt::Display for ComError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ComError::NotConnected => write!(f, "not connected"),
+            ComError::HandshakeTimeout => write!(f, "handshake timed out"),
+            ComError::Error(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::protocol::{capability, Feedback};
+
+    #[test]
+    fn receive_frames() {
+        let mut con = Connection::default();
+        let feedback = Frame::Feedback(Feedback {
+            pulses: [1, 2, 3, PREFIX as u16],
+            millivolts: 4800,
+            status: Feedback::STALLED,
+        });
+        let unknown = Frame::Unknown {
+            kind: 0x42,
+            payload: vec![PREFIX],
+        };
+
+        // garbage before the first frame should be ignored
+        let mut data = vec![1, 2, 3];
+        data.extend(feedback.encode(Checksum::Xor, 0));
+        data.extend(unknown.encode(Checksum::Xor, 0));
+
+        con.receive(&data);
+
+        assert_eq!(con.msg_buf.pop_front(), Some(feedback));
+        assert_eq!(con.msg_buf.pop_front(), Some(unknown));
+        assert_eq!(con.msg_buf.pop_front(), None);
+    }
+
+    #[test]
+    fn receive_drops_corrupt_frames() {
+        let mut con = Connection::default();
+        let frame = Frame::Feedback(Feedback::default());
+
+        let mut corrupt = frame.encode(Checksum::Xor, 0);
+        corrupt[5] ^= 0x10;
+
+        con.receive(&corrupt);
+        con.receive(&frame.encode(Checksum::Xor, 0));
+
+        assert_eq!(con.msg_buf.pop_front(), Some(frame));
+        assert!(con.msg_buf.is_empty());
+        assert_eq!(con.stats.framing_errors, 1);
+        assert_eq!(con.stats.frames_received, 1);
+
+        // counted by type on both sides
+        assert_eq!(con.stats.errors.feedback, 1);
+        assert_eq!(con.stats.received.feedback, 1);
+
+        // a version we don't know doesn't tell us the type
+        con.receive(&[PREFIX, 0xEE]);
+        assert_eq!(con.stats.errors.unknown, 1);
+        assert_eq!(con.stats.framing_errors, 2);
+    }
+
+    #[test]
+    fn receive_behind_cut_frame() {
+        let mut con = Connection::default();
+        let frames = [Frame::Ack { seq: 1 }, Frame::Detach, Frame::Ack { seq: 2 }];
+
+        // the cut frame takes all of the next ones as its payload
+        let mut data = Frame::Unknown {
+            kind: 0x42,
+            payload: vec![0; 40],
+        }
+        .encode(Checksum::Xor, 0)[..8]
+            .to_vec();
+        for frame in &frames {
+            data.extend(frame.encode(Checksum::Xor, 0));
+        }
+        data.extend([0; 40]);
+        con.receive(&data);
+
+        assert_eq!(con.msg_buf, frames);
+        assert_eq!(con.stats.framing_errors, 1);
+    }
+
+    fn stream() -> (Vec<u8>, Vec<Frame>) {
+        let frames = vec![
+            Frame::Feedback(Feedback {
+                pulses: [1500, 1500, 1500, 1500],
+                millivolts: 5000,
+                status: 0,
+            }),
+            Frame::Unknown {
+                kind: 0x10,
+                payload: vec![PREFIX, PREFIX, 0],
+            },
+            Frame::Feedback(Feedback {
+                pulses: [250, 2400, 13, 0],
+                millivolts: 4321,
+                status: Feedback::MOVING,
+            }),
+            Frame::Unknown {
+                kind: 0x11,
+                payload: vec![],
+            },
+        ];
+
+        let mut data = vec![0xFF, 0x00];
+        for frame in &frames {
+            data.extend(frame.encode(Checksum::Xor, 0));
+        }
+
+        (data, frames)
+    }
+
+    #[test]
+    fn chunked_reads() {
+        let (data, frames) = stream();
+
+        for size in [1, 2, 3, 5, 7, 13, data.len()] {
+            let mut con = Connection::default();
+
+            for chunk in data.chunks(size) {
+                con.fill(chunk);
+                con.process();
+            }
+
+            assert_eq!(Vec::from(con.msg_buf.clone()), frames, "chunk size {size}");
+            assert_eq!(con.stats.framing_errors, 0);
+            assert_eq!(con.stats.bytes_received, data.len() as u64);
+        }
+    }
+
+    #[test]
+    fn split_frame_boundary() {
+        let (data, frames) = stream();
+        let mut con = Connection::default();
+
+        // split inside the header of the second frame
+        let split = 2 + frames[0].encode(Checksum::Xor, 0).len() + 2;
+        con.receive(&data[..split]);
+        assert_eq!(con.msg_buf.len(), 1);
+
+        con.receive(&data[split..]);
+        assert_eq!(Vec::from(con.msg_buf.clone()), frames);
+    }
+
+    #[test]
+    fn send_sequence() {
+        let mut con = Connection {
+            tx_seq: u8::MAX,
+            ..Default::default()
+        };
+
+        assert_eq!(con.send(&Frame::StatusRequest).unwrap(), u8::MAX);
+        assert_eq!(con.send(&Frame::StatusRequest).unwrap(), 0);
+        assert_eq!(con.tx_seq, 1);
+
+        // both frames wait for an ACK
+        assert_eq!(con.acks.pending.len(), 2);
+        assert_eq!(con.stats.sent.status_request, 2);
+
+        con.write(&[1, 2, 3], true).unwrap();
+        assert_eq!(con.stats.servo_writes, 1);
+    }
+
+    #[test]
+    fn servo_encoding() {
+        let mut con = Connection {
+            servo_encoding: ServoEncoding::DegreesU8,
+            ..Default::default()
+        };
+
+        // firmware that doesn't ask keeps the configured encoding
+        con.accept_encoding(capability::XOR);
+        assert_eq!(con.servo_encoding, ServoEncoding::DegreesU8);
+
+        con.accept_encoding(capability::XOR | capability::CENTIDEGREES_U16);
+        assert_eq!(con.servo_encoding, ServoEncoding::CentidegreesU16);
+
+        // and feedback is read with it
+        let feedback = Feedback {
+            pulses: [250, 1325, 2400, 1325],
+            millivolts: 5000,
+            status: 0,
+        };
+        let frame = Frame::Feedback(feedback);
+        con.receive(&frame.encode_with(Checksum::Xor, ServoEncoding::CentidegreesU16, 0));
+        assert_eq!(con.msg_buf.pop_front(), Some(frame));
+        assert_eq!(con.stats.framing_errors, 0);
+    }
+
+    #[test]
+    fn queue_is_bounded() {
+        let mut con = Connection::default();
+        let extra = 5;
+
+        for seq in 0..MAX_QUEUED_FRAMES + extra {
+            con.receive(&Frame::Ack { seq: seq as u8 }.encode(Checksum::Xor, 0));
+        }
+
+        assert_eq!(con.msg_buf.len(), MAX_QUEUED_FRAMES);
+        assert_eq!(con.msg_buf.front(), Some(&Frame::Ack { seq: extra as u8 }));
+        assert_eq!(con.stats.frames_dropped, extra as u64);
+        assert_eq!(con.stats.received.ack, (MAX_QUEUED_FRAMES + extra) as u64);
+    }
+
+    #[test]
+    fn baud_fallback() {
+        let mut con = Connection::with_bauds("", vec![115_200, 57_600, 9_600]);
+        assert_eq!(con.baud, 115_200);
+
+        let mut garbage = Frame::Feedback(Feedback::default()).encode(Checksum::Xor, 0);
+        *garbage.last_mut().unwrap() ^= 0x01;
+
+        // a few bad frames are not enough to give up on the current rate
+        for _ in 0..BAUD_ERROR_LIMIT - 1 {
+            con.receive(&garbage);
+        }
+        con.check_baud().unwrap();
+        assert_eq!(con.baud, 115_200);
+
+        // a valid frame resets the count
+        con.receive(&Frame::Hello { capabilities: 0 }.encode(Checksum::Xor, 0));
+        con.receive(&garbage);
+        con.check_baud().unwrap();
+        assert_eq!(con.baud, 115_200);
+
+        for _ in 0..BAUD_ERROR_LIMIT {
+            con.receive(&garbage);
+        }
+        con.check_baud().unwrap();
+        assert_eq!(con.baud, 57_600);
+        assert_eq!(con.stats.baud_switches, 1);
+
+        // cycles back to the start after the last candidate
+        for baud in [9_600, 115_200] {
+            for _ in 0..BAUD_ERROR_LIMIT {
+                con.receive(&garbage);
+            }
+            con.check_baud().unwrap();
+            assert_eq!(con.baud, baud);
+        }
+    }
+
+    #[test]
+    fn single_baud_never_switches() {
+        let mut con = Connection::new("", 115_200);
+        let mut garbage = Frame::Hello { capabilities: 0 }.encode(Checksum::Xor, 0);
+        *garbage.last_mut().unwrap() ^= 0x01;
+
+        for _ in 0..BAUD_ERROR_LIMIT * 2 {
+            con.receive(&garbage);
+        }
+        con.check_baud().unwrap();
+
+        assert_eq!(con.baud, 115_200);
+        assert_eq!(con.stats.baud_switches, 0);
+        assert!(con.stats.probable_baud_mismatch());
+    }
+
+    #[test]
+    fn overflow_resynchronizes() {
+        let mut con = Connection::default();
+        let frame = Frame::Feedback(Feedback::default());
+        let encoded = frame.encode(Checksum::Xor, 0);
+
+        // start a frame, then fall behind enough that its start gets overwritten
+        con.receive(&encoded[..4]);
+
+        let mut data = vec![0; RING_SIZE];
+        data.extend(&encoded);
+        con.fill(&data);
+        con.process();
+
+        assert_eq!(con.stats.overflow_bytes, encoded.len() as u64);
+        assert_eq!(Vec::from(con.msg_buf.clone()), vec![frame]);
+        assert_eq!(con.stats.framing_errors, 0);
+    }
 }
@@ -0,0 +1,1175 @@
+use std::{collections::BTreeMap, fmt, fs, io, path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    calibration::Calibration,
+    communication::Connection,
+    input::{AxisMapping, StickCalibration},
+    kinematics::position::CordinateVec,
+    protocol::ServoEncoding,
+    robot::{
+        arm::{Arm, ClawPresets, InvertedJoints, JointAngles, SoftMargins},
+        boundary::{BoundaryConfig, BoundaryPolicy},
+        bundle::Bundle,
+        coordinator::CoordinationConfig,
+        envelope::{Envelope, EnvelopeMode},
+        correction::{CorrectionConfig, FeedbackCorrection},
+        estop::{EmergencyStop, EmergencyStopConfig},
+        idle::IdlePolicy,
+        jog::JogOverride,
+        limits::{JointLimits, LimitSearchConfig},
+        output::{Dither, OutputRate},
+        shutdown::ShutdownConfig,
+        soft_start::SoftStart,
+        stall::{StallConfig, StallDetector},
+        startup::{StartupConfig, StartupError, StartupMode},
+        status::StatusPoller,
+        supply::{SupplyConfig, SupplyMonitor},
+        torque::TorqueLimit,
+        workspace::{Workspace, Workspaces},
+        Robot,
+    },
+    sim::SimConfig,
+};
+
+/// Everything about the controller that can be changed without recompiling
+///
+/// Each setting is taken from the command line if it's given there, otherwise from the config
+/// file and otherwise from [`Config::default`], see [`Config::resolve`]. The arm geometry
+/// (joint limits and motions) isn't part of it and stays in `main`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    /// Serial port the arduino is connected to
+    pub port: String,
+
+    /// Candidate baud rates in the order they are tried
+    pub bauds: Vec<u32>,
+
+    /// How the firmware wants servo values, unless it asks for another one in the handshake
+    pub servo_encoding: ServoEncoding,
+
+    /// Maximum acceleration of the head in units/s^2
+    pub acceleration: f64,
+
+    /// Longest physics step in seconds, 0 to take every tick in one step
+    pub max_substep: f64,
+
+    /// Maximum velocity the gamepad can command in units/s
+    pub max_velocity: CordinateVec,
+
+    /// Which stick axis jogs along which world axis, the name of a preset or a list of
+    /// bindings, see [`AxisMapping`]
+    pub axis_mapping: AxisMapping,
+
+    /// Where the sticks rest, written by `calibrate_sticks`
+    pub stick_calibration: StickCalibration,
+
+    /// Measure where the sticks rest at startup and save it to the config file, the sticks
+    /// must not be touched meanwhile
+    pub calibrate_sticks: bool,
+
+    /// Jog with keys typed in the terminal as well as the gamepad, commands are then typed
+    /// after a `:`, see [`crate::input::KeyboardSource`]
+    pub keyboard: bool,
+
+    /// Json file of input states played back one per tick ahead of the other inputs, empty for
+    /// none, see [`crate::input::ScriptedSource::load`]
+    pub input_script: String,
+
+    pub upper_arm: f64,
+    pub lower_arm: f64,
+
+    /// Length from the wrist to the tip of the claw, 0 for an arm without a wrist servo, see
+    /// [`crate::robot::arm::Arm::wrist`]
+    pub wrist_length: f64,
+
+    /// Angle the wrist holds the claw at from the z axis in degrees, 90 is level, see
+    /// [`Robot::claw_pitch`]
+    pub claw_pitch: f64,
+
+    /// Claw angles for open and closed, see [`Robot::claw_open`]
+    pub claw_presets: ClawPresets,
+
+    /// Where the gripper grips from the end of the arm, x straight out from the z axis, y
+    /// across and z up, see [`Robot::end_effector_offset`]
+    pub end_effector_offset: CordinateVec,
+
+    /// Seconds between firmware status queries, 0 to only query when requested
+    pub status_interval: f64,
+
+    /// Seconds after which a firmware status is shown as stale
+    pub status_stale_after: f64,
+
+    /// Send the servo positions every nth tick
+    pub output_divider: u32,
+
+    /// Most servo frames per second, 0 for no limit
+    pub output_max_hz: f64,
+
+    /// Carry the fraction of a µs the servo positions are rounded off by into the next frame,
+    /// off for firmwares that filter out changes of a single µs, see
+    /// [`crate::robot::output::Dither`]
+    pub output_dither: bool,
+
+    /// Shortest time the soft start ramp takes in seconds
+    pub soft_start_duration: f64,
+
+    /// Fastest a joint may turn during the soft start ramp in degrees/s
+    pub soft_start_rate: f64,
+
+    /// Seconds the arm rests before the servos are detached, 0 to never detach
+    pub idle_detach_after: f64,
+
+    /// Seconds to wait after attaching the servos before driving them
+    pub idle_settle: f64,
+
+    /// Maps internal units to real world coordinates, see `calibrate` in [`crate::command`]
+    pub calibration: Calibration,
+
+    /// Where state that outlives a session is kept
+    pub state_file: String,
+
+    /// Where changes to poses, joint limits and the calibration are journaled, empty to only
+    /// keep them for the session. `undo` reverts changes of the session either way, see
+    /// [`crate::robot::journal`]
+    pub journal_file: String,
+
+    /// Servo model for running without hardware
+    pub sim: SimConfig,
+
+    /// Named workspace profiles, see `workspace` in [`crate::command`]
+    pub workspaces: BTreeMap<String, Workspace>,
+
+    /// Profile selected at startup, empty for none
+    pub workspace: String,
+
+    /// Slow down in poses that load the servos heavily
+    pub torque_limit: TorqueLimit,
+
+    /// Grams the claw carries at startup, see `payload` in [`crate::command`]
+    pub payload: f64,
+
+    /// How the servos start out, where the head goes and what the claw does once connected
+    pub startup: StartupConfig,
+
+    /// Learn or enforce the taught envelope from the start, see `envelope` in
+    /// [`crate::command`]
+    pub envelope_mode: EnvelopeMode,
+
+    /// Edge length of the voxels of a newly taught envelope, a saved one keeps its own
+    pub envelope_voxel: f64,
+
+    /// Where the taught envelope is kept
+    pub envelope_file: String,
+
+    /// What the head does at the edge of where it may go, see `boundary` in
+    /// [`crate::command`]
+    pub boundary: BoundaryConfig,
+
+    /// Lowest the head may go, where the table is, see [`Robot::min_z`]
+    pub min_z: f64,
+
+    /// When a joint that doesn't follow its commanded angle pauses the arm
+    pub stall: StallConfig,
+
+    /// Closed loop trim of what is sent so the servos report the commanded angles
+    pub feedback_correction: CorrectionConfig,
+
+    /// How the emergency stop brakes and how long it may brake before detaching the servos
+    pub emergency_stop: EmergencyStopConfig,
+
+    /// When the servo supply is low enough to slow down or shut down the arm
+    pub supply: SupplyConfig,
+
+    /// What the arm does on `quit`, Ctrl-C, a critical supply or the link staying down
+    pub shutdown: ShutdownConfig,
+
+    /// How the end stops of the joints are searched for, see `limits` in [`crate::command`]
+    pub limit_search: LimitSearchConfig,
+
+    /// Ranges the joints are kept in, as found by the limit search
+    pub joint_limits: JointLimits,
+
+    /// Where the startup audit of the arm is written as json, empty to only log it, see
+    /// [`crate::robot::audit`]
+    pub audit_report: String,
+
+    /// Program loaded at startup, empty for none, see [`crate::program`]
+    pub program: String,
+
+    /// Run the program as soon as the arm is ready
+    pub run_program: bool,
+
+    /// The base turns all the way round on a slip ring, its servo covers 0 to 360°, see
+    /// [`crate::kinematics::joints::Joint::continuous`]
+    pub continuous_base: bool,
+
+    /// Joints whose servo is mounted mirrored, see
+    /// [`crate::kinematics::joints::Joint::inverted`]
+    pub inverted_joints: InvertedJoints,
+
+    /// Degrees from the ends of their range within which the joints warn they're about to
+    /// hit their limits, see [`crate::kinematics::joints::Joint::soft_margin_deg`]
+    pub soft_margins: SoftMargins,
+
+    /// Grips of the program used by pressing the D-pad up, right, down and left, empty for
+    /// none, see `grip` in [`crate::command`]
+    pub grip_buttons: [String; 4],
+
+    /// What jogging does to a target position, replay or program in progress
+    pub jog_override: JogOverride,
+
+    /// Directory the logs of the session are bundled into when it ends with `quit`, empty for
+    /// none, see [`crate::robot::bundle`]
+    pub bundle_on_exit: String,
+
+    /// File the telemetry of every frame is recorded to for `--view`, empty for none, see
+    /// [`crate::robot::telemetry`]
+    pub telemetry: String,
+
+    /// A second arm next to this one, see [`crate::robot::coordinator`]
+    pub coordination: CoordinationConfig,
+}
+
+/// Settings from one source, `None` for the ones the source doesn't set
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigOverrides {
+    pub port: Option<String>,
+    pub bauds: Option<Vec<u32>>,
+    pub servo_encoding: Option<ServoEncoding>,
+    pub acceleration: Option<f64>,
+    pub max_substep: Option<f64>,
+    pub max_velocity: Option<CordinateVec>,
+    pub axis_mapping: Option<AxisMapping>,
+    pub stick_calibration: Option<StickCalibration>,
+    pub calibrate_sticks: Option<bool>,
+    pub keyboard: Option<bool>,
+    pub input_script: Option<String>,
+    pub upper_arm: Option<f64>,
+    pub lower_arm: Option<f64>,
+    pub wrist_length: Option<f64>,
+    pub claw_pitch: Option<f64>,
+    pub claw_presets: Option<ClawPresets>,
+    pub end_effector_offset: Option<CordinateVec>,
+    pub status_interval: Option<f64>,
+    pub status_stale_after: Option<f64>,
+    pub output_divider: Option<u32>,
+    pub output_max_hz: Option<f64>,
+    pub output_dither: Option<bool>,
+    pub soft_start_duration: Option<f64>,
+    pub soft_start_rate: Option<f64>,
+    pub idle_detach_after: Option<f64>,
+    pub idle_settle: Option<f64>,
+    pub calibration: Option<Calibration>,
+    pub state_file: Option<String>,
+    pub journal_file: Option<String>,
+    pub sim: Option<SimConfig>,
+    pub workspaces: Option<BTreeMap<String, Workspace>>,
+    pub workspace: Option<String>,
+    pub torque_limit: Option<TorqueLimit>,
+    pub payload: Option<f64>,
+    pub startup: Option<StartupConfig>,
+    pub envelope_mode: Option<EnvelopeMode>,
+    pub envelope_voxel: Option<f64>,
+    pub envelope_file: Option<String>,
+    pub boundary: Option<BoundaryConfig>,
+    pub min_z: Option<f64>,
+    pub stall: Option<StallConfig>,
+    pub feedback_correction: Option<CorrectionConfig>,
+    pub emergency_stop: Option<EmergencyStopConfig>,
+    pub supply: Option<SupplyConfig>,
+    pub shutdown: Option<ShutdownConfig>,
+    pub limit_search: Option<LimitSearchConfig>,
+    pub joint_limits: Option<JointLimits>,
+    pub audit_report: Option<String>,
+    pub program: Option<String>,
+    pub run_program: Option<bool>,
+    pub continuous_base: Option<bool>,
+    pub inverted_joints: Option<InvertedJoints>,
+    pub soft_margins: Option<SoftMargins>,
+    pub grip_buttons: Option<[String; 4]>,
+    pub jog_override: Option<JogOverride>,
+    pub bundle_on_exit: Option<String>,
+    pub telemetry: Option<String>,
+    pub coordination: Option<CoordinationConfig>,
+}
+
+/// What `main` was asked to do on the command line
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Args {
+    /// Config file given with `--config <file>`
+    pub config_file: Option<String>,
+
+    /// `--dump-config`, print the resolved config and exit
+    pub dump_config: bool,
+
+    /// `--check <file>`, dry run a program and exit, see [`crate::robot::dry_run`]
+    pub check: Option<String>,
+
+    /// `--view <file>`, play back recorded telemetry and exit, see [`crate::viewer`]
+    pub view: Option<String>,
+
+    /// Every other `--<setting> <value>`
+    pub overrides: ConfigOverrides,
+}
+
+/// The config as written by `--dump-config`, with its hash so it can be matched to logs
+#[derive(Debug, Serialize)]
+struct Dump<'a> {
+    config_hash: String,
+
+    #[serde(flatten)]
+    config: &'a Config,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+
+    /// A command line argument that isn't understood
+    Argument(String),
+
+    /// The selected workspace isn't one of the profiles
+    UnknownWorkspace(String),
+
+    /// The arm can't start like `startup` says
+    Startup(StartupError),
+}
+
+impl Config {
+    /// Merge the sources, the command line wins over the file and the file over the defaults
+    pub fn resolve(cli: ConfigOverrides, file: ConfigOverrides) -> Self {
+        let default = Self::default();
+
+        Self {
+            port: cli.port.or(file.port).unwrap_or(default.port),
+            bauds: cli.bauds.or(file.bauds).unwrap_or(default.bauds),
+            servo_encoding: cli
+                .servo_encoding
+                .or(file.servo_encoding)
+                .unwrap_or(default.servo_encoding),
+            acceleration: cli
+                .acceleration
+                .or(file.acceleration)
+                .unwrap_or(default.acceleration),
+            max_substep: cli
+                .max_substep
+                .or(file.max_substep)
+                .unwrap_or(default.max_substep),
+            max_velocity: cli
+                .max_velocity
+                .or(file.max_velocity)
+                .unwrap_or(default.max_velocity),
+            axis_mapping: cli
+                .axis_mapping
+                .or(file.axis_mapping)
+                .unwrap_or(default.axis_mapping),
+            stick_calibration: cli
+                .stick_calibration
+                .or(file.stick_calibration)
+                .unwrap_or(default.stick_calibration),
+            calibrate_sticks: cli
+                .calibrate_sticks
+                .or(file.calibrate_sticks)
+                .unwrap_or(default.calibrate_sticks),
+            keyboard: cli.keyboard.or(file.keyboard).unwrap_or(default.keyboard),
+            input_script: cli
+                .input_script
+                .or(file.input_script)
+                .unwrap_or(default.input_script),
+            upper_arm: cli.upper_arm.or(file.upper_arm).unwrap_or(default.upper_arm),
+            lower_arm: cli.lower_arm.or(file.lower_arm).unwrap_or(default.lower_arm),
+            wrist_length: cli
+                .wrist_length
+                .or(file.wrist_length)
+                .unwrap_or(default.wrist_length),
+            claw_pitch: cli
+                .claw_pitch
+                .or(file.claw_pitch)
+                .unwrap_or(default.claw_pitch),
+            claw_presets: cli
+                .claw_presets
+                .or(file.claw_presets)
+                .unwrap_or(default.claw_presets),
+            end_effector_offset: cli
+                .end_effector_offset
+                .or(file.end_effector_offset)
+                .unwrap_or(default.end_effector_offset),
+            status_interval: cli
+                .status_interval
+                .or(file.status_interval)
+                .unwrap_or(default.status_interval),
+            status_stale_after: cli
+                .status_stale_after
+                .or(file.status_stale_after)
+                .unwrap_or(default.status_stale_after),
+            output_divider: cli
+                .output_divider
+                .or(file.output_divider)
+                .unwrap_or(default.output_divider),
+            output_max_hz: cli
+                .output_max_hz
+                .or(file.output_max_hz)
+                .unwrap_or(default.output_max_hz),
+            output_dither: cli
+                .output_dither
+                .or(file.output_dither)
+                .unwrap_or(default.output_dither),
+            soft_start_duration: cli
+                .soft_start_duration
+                .or(file.soft_start_duration)
+                .unwrap_or(default.soft_start_duration),
+            soft_start_rate: cli
+                .soft_start_rate
+                .or(file.soft_start_rate)
+                .unwrap_or(default.soft_start_rate),
+            idle_detach_after: cli
+                .idle_detach_after
+                .or(file.idle_detach_after)
+                .unwrap_or(default.idle_detach_after),
+            idle_settle: cli
+                .idle_settle
+                .or(file.idle_settle)
+                .unwrap_or(default.idle_settle),
+            calibration: cli
+                .calibration
+                .or(file.calibration)
+                .unwrap_or(default.calibration),
+            state_file: cli.state_file.or(file.state_file).unwrap_or(default.state_file),
+            journal_file: cli
+                .journal_file
+                .or(file.journal_file)
+                .unwrap_or(default.journal_file),
+            sim: cli.sim.or(file.sim).unwrap_or(default.sim),
+            workspaces: cli
+                .workspaces
+                .or(file.workspaces)
+                .unwrap_or(default.workspaces),
+            workspace: cli.workspace.or(file.workspace).unwrap_or(default.workspace),
+            torque_limit: cli
+                .torque_limit
+                .or(file.torque_limit)
+                .unwrap_or(default.torque_limit),
+            payload: cli.payload.or(file.payload).unwrap_or(default.payload),
+            startup: cli.startup.or(file.startup).unwrap_or(default.startup),
+            envelope_mode: cli
+                .envelope_mode
+                .or(file.envelope_mode)
+                .unwrap_or(default.envelope_mode),
+            envelope_voxel: cli
+                .envelope_voxel
+                .or(file.envelope_voxel)
+                .unwrap_or(default.envelope_voxel),
+            envelope_file: cli
+                .envelope_file
+                .or(file.envelope_file)
+                .unwrap_or(default.envelope_file),
+            boundary: cli.boundary.or(file.boundary).unwrap_or(default.boundary),
+            min_z: cli.min_z.or(file.min_z).unwrap_or(default.min_z),
+            stall: cli.stall.or(file.stall).unwrap_or(default.stall),
+            feedback_correction: cli
+                .feedback_correction
+                .or(file.feedback_correction)
+                .unwrap_or(default.feedback_correction),
+            emergency_stop: cli
+                .emergency_stop
+                .or(file.emergency_stop)
+                .unwrap_or(default.emergency_stop),
+            supply: cli.supply.or(file.supply).unwrap_or(default.supply),
+            shutdown: cli.shutdown.or(file.shutdown).unwrap_or(default.shutdown),
+            limit_search: cli
+                .limit_search
+                .or(file.limit_search)
+                .unwrap_or(default.limit_search),
+            joint_limits: cli
+                .joint_limits
+                .or(file.joint_limits)
+                .unwrap_or(default.joint_limits),
+            audit_report: cli
+                .audit_report
+                .or(file.audit_report)
+                .unwrap_or(default.audit_report),
+            program: cli.program.or(file.program).unwrap_or(default.program),
+            run_program: cli
+                .run_program
+                .or(file.run_program)
+                .unwrap_or(default.run_program),
+            continuous_base: cli
+                .continuous_base
+                .or(file.continuous_base)
+                .unwrap_or(default.continuous_base),
+            inverted_joints: cli
+                .inverted_joints
+                .or(file.inverted_joints)
+                .unwrap_or(default.inverted_joints),
+            soft_margins: cli
+                .soft_margins
+                .or(file.soft_margins)
+                .unwrap_or(default.soft_margins),
+            grip_buttons: cli
+                .grip_buttons
+                .or(file.grip_buttons)
+                .unwrap_or(default.grip_buttons),
+            jog_override: cli
+                .jog_override
+                .or(file.jog_override)
+                .unwrap_or(default.jog_override),
+            bundle_on_exit: cli
+                .bundle_on_exit
+                .or(file.bundle_on_exit)
+                .unwrap_or(default.bundle_on_exit),
+            telemetry: cli.telemetry.or(file.telemetry).unwrap_or(default.telemetry),
+            coordination: cli
+                .coordination
+                .or(file.coordination)
+                .unwrap_or(default.coordination),
+        }
+    }
+
+    /// Resolve the config from the command line and the config file it names
+    pub fn load(args: &Args) -> Result<Self, ConfigError> {
+        let file = match &args.config_file {
+            Some(path) => {
+                let data = fs::read_to_string(path).map_err(ConfigError::Io)?;
+                serde_json::from_str(&data).map_err(ConfigError::Parse)?
+            }
+            None => ConfigOverrides::default(),
+        };
+
+        let config = Self::resolve(args.overrides.clone(), file);
+        if !config.workspace.is_empty() && !config.workspaces.contains_key(&config.workspace) {
+            return Err(ConfigError::UnknownWorkspace(config.workspace));
+        }
+        config.check_startup().map_err(ConfigError::Startup)?;
+
+        Ok(config)
+    }
+
+    /// Check that the arm can start like [`Config::startup`] says
+    ///
+    /// The target has to be within reach, within the joint limits and allowed by the selected
+    /// workspace
+    fn check_startup(&self) -> Result<(), StartupError> {
+        let startup = &self.startup;
+        if startup.mode == StartupMode::Detached && !startup.holds() {
+            return Err(StartupError::Detached);
+        }
+        let Some(target) = startup.target else {
+            return Ok(());
+        };
+        if startup.home {
+            return Err(StartupError::TargetAndHome);
+        }
+
+        let internal = self.calibration.to_internal(target);
+        let reach = self.upper_arm + self.lower_arm;
+        if internal.dst() > reach {
+            return Err(StartupError::OutOfReach { target, reach });
+        }
+        let mut solved = internal;
+        let (base, shoulder, elbow) = solved
+            .inverse_kinematics(self.upper_arm, self.lower_arm)
+            .map_err(|_| StartupError::OutOfReach { target, reach })?;
+
+        // the claw is set apart from the target
+        let limits = JointLimits {
+            claw: None,
+            ..self.joint_limits
+        };
+        let angles = JointAngles {
+            base,
+            shoulder,
+            elbow,
+            claw: 0.,
+        };
+        if !limits.allows(angles) {
+            return Err(StartupError::JointLimits(target));
+        }
+
+        match self.workspaces.get(&self.workspace) {
+            Some(workspace) if !workspace.allows(internal) => Err(StartupError::Workspace {
+                target,
+                workspace: self.workspace.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Short fingerprint of the config, the same config always gives the same hash
+    ///
+    /// FNV-1a over the compact json, which has its fields in a fixed order
+    pub fn hash(&self) -> u64 {
+        // serializing plain data to a string can't fail
+        let json = serde_json::to_string(self).unwrap_or_default();
+
+        json.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+
+    /// The config and its hash as pretty json, for `--dump-config`
+    pub fn dump(&self) -> String {
+        let dump = Dump {
+            config_hash: format!("{:016x}", self.hash()),
+            config: self,
+        };
+
+        serde_json::to_string_pretty(&dump).unwrap_or_default()
+    }
+
+    /// Build the second arm of [`Config::coordination`] like [`Config::robot`], on its own
+    /// port
+    pub fn follower(&self, arm: Arm) -> Robot {
+        Config {
+            port: self.coordination.port.clone(),
+            ..self.clone()
+        }
+        .robot(arm)
+    }
+
+    /// Build a robot with this config and the given arm
+    pub fn robot(&self, arm: Arm) -> Robot {
+        let status_interval =
+            (self.status_interval > 0.).then(|| Duration::from_secs_f64(self.status_interval));
+        let output_max_hz = (self.output_max_hz > 0.).then_some(self.output_max_hz);
+        let max_substep = (self.max_substep > 0.).then_some(self.max_substep);
+        let idle_detach_after = if self.idle_detach_after > 0. {
+            self.idle_detach_after
+        } else {
+            f64::INFINITY
+        };
+        let soft_start = SoftStart::new(
+            self.soft_start_duration,
+            self.soft_start_rate,
+            JointAngles {
+                base: 90.,
+                shoulder: 90.,
+                elbow: 90.,
+                claw: 90.,
+            },
+        );
+
+        let mut robot = Robot {
+            acceleration: self.acceleration,
+            max_substep,
+            max_velocity: self.max_velocity,
+            upper_arm: self.upper_arm,
+            lower_arm: self.lower_arm,
+            wrist_length: self.wrist_length,
+            claw_pitch: self.claw_pitch,
+            claw_presets: self.claw_presets,
+            end_effector_offset: self.end_effector_offset,
+            arm,
+            connection: Connection {
+                // the simulator answers instead of the arduino
+                no_connect: self.sim.enabled,
+                servo_encoding: self.servo_encoding,
+                ..Connection::with_bauds(&self.port, self.bauds.clone())
+            },
+            status: StatusPoller::new(
+                status_interval,
+                Duration::from_secs_f64(self.status_stale_after),
+            ),
+            output: OutputRate::new(self.output_divider, output_max_hz),
+            dither: self.output_dither.then(Dither::default),
+            soft_start: Some(soft_start),
+            idle: IdlePolicy {
+                detach_after: idle_detach_after,
+                settle: self.idle_settle,
+                resume: soft_start,
+                ..Default::default()
+            },
+            calibration: self.calibration,
+            // the head may start out violating the profile, it's enforced once it is clear
+            workspaces: Workspaces {
+                profiles: self.workspaces.clone(),
+                pending: (!self.workspace.is_empty()).then(|| self.workspace.clone()),
+                ..Default::default()
+            },
+            torque_limit: self.torque_limit.enabled.then_some(self.torque_limit),
+            // the mode is set once the saved envelope is loaded, it can't be enforced before
+            envelope: Envelope::new(self.envelope_voxel),
+            // the policy is set on its own, turning the boundary off may be refused
+            boundary: BoundaryConfig {
+                policy: BoundaryPolicy::HardClamp,
+                ..self.boundary
+            },
+            min_z: Some(self.min_z),
+            stall: self.stall.enabled.then(|| StallDetector::new(self.stall)),
+            correction: self
+                .feedback_correction
+                .enabled
+                .then(|| FeedbackCorrection::new(self.feedback_correction)),
+            estop: EmergencyStop::new(self.emergency_stop),
+            supply: self
+                .supply
+                .enabled
+                .then(|| SupplyMonitor::new(self.supply.clone())),
+            on_shutdown: self.shutdown.clone(),
+            limit_search: self.limit_search,
+            joint_limits: self.joint_limits,
+            grip_buttons: self.grip_buttons.clone(),
+            jog_override: self.jog_override,
+            soft_margin_slow_down: self.soft_margins.slow_down,
+            bundle: Bundle {
+                on_exit: self.bundle_on_exit.clone(),
+                config: self.dump(),
+            },
+            config_hash: self.hash(),
+            ..Default::default()
+        };
+        robot.set_payload(self.payload);
+        robot.prepare_startup(self.startup);
+        robot
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: "/dev/ttyACM0".to_string(),
+            bauds: vec![115_200],
+            servo_encoding: ServoEncoding::MicrosecondsU16,
+            acceleration: 100.,
+            max_substep: 0.001,
+            max_velocity: CordinateVec::new(10., 10., 10.),
+            axis_mapping: AxisMapping::default(),
+            stick_calibration: StickCalibration::default(),
+            calibrate_sticks: false,
+            keyboard: false,
+            input_script: String::new(),
+            upper_arm: 100.,
+            lower_arm: 100.,
+            wrist_length: 0.,
+            claw_pitch: 90.,
+            claw_presets: ClawPresets::default(),
+            end_effector_offset: CordinateVec::default(),
+            status_interval: 5.,
+            status_stale_after: 15.,
+            output_divider: 1,
+            output_max_hz: 50.,
+            output_dither: false,
+            soft_start_duration: 2.,
+            soft_start_rate: 30.,
+            idle_detach_after: 60.,
+            idle_settle: 0.2,
+            calibration: Calibration::default(),
+            state_file: "rac_state.json".to_string(),
+            journal_file: "rac_journal.jsonl".to_string(),
+            sim: SimConfig::default(),
+            workspaces: BTreeMap::new(),
+            workspace: String::new(),
+            torque_limit: TorqueLimit::default(),
+            payload: 0.,
+            startup: StartupConfig::default(),
+            envelope_mode: EnvelopeMode::Off,
+            envelope_voxel: 10.,
+            envelope_file: "rac_envelope.json".to_string(),
+            boundary: BoundaryConfig::default(),
+            min_z: 0.,
+            stall: StallConfig::default(),
+            feedback_correction: CorrectionConfig::default(),
+            emergency_stop: EmergencyStopConfig::default(),
+            supply: SupplyConfig::default(),
+            shutdown: ShutdownConfig::default(),
+            limit_search: LimitSearchConfig::default(),
+            joint_limits: JointLimits::default(),
+            audit_report: String::new(),
+            program: String::new(),
+            run_program: false,
+            continuous_base: false,
+            inverted_joints: InvertedJoints::default(),
+            soft_margins: SoftMargins::default(),
+            grip_buttons: Default::default(),
+            jog_override: JogOverride::Cancel,
+            bundle_on_exit: String::new(),
+            telemetry: String::new(),
+            coordination: CoordinationConfig::default(),
+        }
+    }
+}
+
+/// Set `joint_limits` in a config file, see [`write_setting`]
+pub fn write_joint_limits(path: impl AsRef<Path>, limits: &JointLimits) -> Result<(), ConfigError> {
+    write_setting(path, "joint_limits", limits)
+}
+
+/// Set one setting in a config file, everything else in it is kept as it is
+///
+/// The file is created if it doesn't exist
+pub fn write_setting(
+    path: impl AsRef<Path>,
+    name: &str,
+    value: &impl Serialize,
+) -> Result<(), ConfigError> {
+    let path = path.as_ref();
+    let mut config = match fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data).map_err(ConfigError::Parse)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Map::new(),
+        Err(err) => return Err(ConfigError::Io(err)),
+    };
+
+    let value = serde_json::to_value(value).map_err(ConfigError::Parse)?;
+    config.insert(name.to_string(), value);
+    let data = serde_json::to_string_pretty(&config).map_err(ConfigError::Parse)?;
+    fs::write(path, data).map_err(ConfigError::Io)
+}
+
+impl Args {
+    /// Parse the command line, without the program name
+    ///
+    /// Settings are given as `--<setting> <value>` with the setting named like the field of
+    /// [`Config`] with `-` for `_`. Values are read as json, and as a plain string if they
+    /// aren't valid json, so `--port /dev/ttyUSB0` and `--bauds [115200,9600]` both work
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut parsed = Self::default();
+        let mut overrides = Map::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                return Err(ConfigError::Argument(arg));
+            };
+
+            match name {
+                "dump-config" => parsed.dump_config = true,
+                "config" => {
+                    let path = args.next().ok_or(ConfigError::Argument(arg))?;
+                    parsed.config_file = Some(path);
+                }
+                "check" => {
+                    let path = args.next().ok_or(ConfigError::Argument(arg))?;
+                    parsed.check = Some(path);
+                }
+                "view" => {
+                    let path = args.next().ok_or(ConfigError::Argument(arg))?;
+                    parsed.view = Some(path);
+                }
+                name => {
+                    let value = args.next().ok_or_else(|| ConfigError::Argument(arg.clone()))?;
+                    let value =
+                        serde_json::from_str(&value).unwrap_or(Value::String(value));
+                    overrides.insert(name.replace('-', "_"), value);
+                }
+            }
+        }
+
+        parsed.overrides =
+            serde_json::from_value(Value::Object(overrides)).map_err(ConfigError::Parse)?;
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "{err}"),
+            ConfigError::Parse(err) => write!(f, "invalid config: {err}"),
+            ConfigError::Argument(arg) => write!(f, "unexpected argument `{arg}`"),
+            ConfigError::UnknownWorkspace(name) => write!(f, "unknown workspace `{name}`"),
+            ConfigError::Startup(err) => write!(f, "invalid startup: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{input::AxisCenter, program::ClawAction, robot::limits::JointRange};
+
+    fn args(line: &str) -> Result<Args, ConfigError> {
+        Args::parse(line.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn precedence() {
+        let file: ConfigOverrides =
+            serde_json::from_str(r#"{ "port": "/dev/ttyUSB0", "upper_arm": 120, "output_divider": 2 }"#)
+                .unwrap();
+        let cli = args("--upper-arm 130 --bauds [57600,9600] --servo-encoding degrees_u8")
+            .unwrap()
+            .overrides;
+
+        let config = Config::resolve(cli, file);
+        let default = Config::default();
+
+        // command line over file over default
+        assert_eq!(config.upper_arm, 130.);
+        assert_eq!(config.port, "/dev/ttyUSB0");
+        assert_eq!(config.output_divider, 2);
+        assert_eq!(config.bauds, [57_600, 9_600]);
+        assert_eq!(config.servo_encoding, ServoEncoding::DegreesU8);
+        assert_eq!(config.lower_arm, default.lower_arm);
+
+        // nothing given is the default
+        assert_eq!(
+            Config::resolve(ConfigOverrides::default(), ConfigOverrides::default()),
+            default
+        );
+    }
+
+    #[test]
+    fn inverted_joints() {
+        let parsed = args(r#"--inverted-joints {"elbow":true}"#).unwrap();
+        let config = Config::resolve(parsed.overrides, ConfigOverrides::default());
+        assert_eq!(
+            config.inverted_joints,
+            InvertedJoints {
+                elbow: true,
+                ..Default::default()
+            }
+        );
+
+        assert!(args(r#"--inverted-joints {"knee":true}"#).is_err());
+    }
+
+    #[test]
+    fn claw_presets() {
+        let parsed = args(r#"--claw-presets {"open_angle":150,"closed_angle":30}"#).unwrap();
+        let config = Config::resolve(parsed.overrides, ConfigOverrides::default());
+        let presets = ClawPresets {
+            open_angle: 150.,
+            closed_angle: 30.,
+        };
+        assert_eq!(config.claw_presets, presets);
+        assert_eq!(config.robot(Arm::default()).claw_presets, presets);
+
+        // a gripper that only needs a different open angle
+        let parsed = args(r#"--claw-presets {"open_angle":120}"#).unwrap();
+        let config = Config::resolve(parsed.overrides, ConfigOverrides::default());
+        assert_eq!(config.claw_presets.closed_angle, 0.);
+    }
+
+    #[test]
+    fn soft_margins() {
+        let parsed = args(r#"--soft-margins {"elbow":5,"slow_down":true}"#).unwrap();
+        let config = Config::resolve(parsed.overrides, ConfigOverrides::default());
+        assert_eq!(config.soft_margins.elbow, 5.);
+        assert_eq!(config.soft_margins.shoulder, 0.);
+        assert!(config.robot(Arm::default()).soft_margin_slow_down);
+        let robot = Config::default().robot(Arm::default());
+        assert!(!robot.soft_margin_slow_down);
+    }
+
+    #[test]
+    fn arguments() {
+        let parsed = args("--dump-config --config rac.json --port /dev/ttyUSB1").unwrap();
+        assert!(parsed.dump_config);
+        assert_eq!(parsed.config_file.as_deref(), Some("rac.json"));
+        assert_eq!(parsed.overrides.port.as_deref(), Some("/dev/ttyUSB1"));
+        assert_eq!(parsed.check, None);
+        assert_eq!(
+            args("--check pick.json").unwrap().check.as_deref(),
+            Some("pick.json")
+        );
+        assert_eq!(
+            args("--view telemetry.csv").unwrap().view.as_deref(),
+            Some("telemetry.csv")
+        );
+
+        assert!(matches!(args("--config"), Err(ConfigError::Argument(_))));
+        assert!(matches!(args("--check"), Err(ConfigError::Argument(_))));
+        assert!(matches!(args("--view"), Err(ConfigError::Argument(_))));
+        assert!(matches!(args("stray"), Err(ConfigError::Argument(_))));
+        assert!(matches!(args("--no-such-setting 1"), Err(ConfigError::Parse(_))));
+        assert!(matches!(args("--upper-arm long"), Err(ConfigError::Parse(_))));
+
+        // settings in the file are checked just as strictly
+        assert!(serde_json::from_str::<ConfigOverrides>(r#"{ "uper_arm": 1 }"#).is_err());
+    }
+
+    #[test]
+    fn dump_and_hash() {
+        let config = Config::default();
+        let other = Config {
+            output_divider: 4,
+            ..Default::default()
+        };
+        assert_eq!(config.hash(), Config::default().hash());
+        assert_ne!(config.hash(), other.hash());
+
+        // the dump reads back as the same config
+        let dump: Value = serde_json::from_str(&config.dump()).unwrap();
+        assert_eq!(dump["config_hash"], format!("{:016x}", config.hash()));
+
+        let mut fields = dump.as_object().unwrap().clone();
+        fields.remove("config_hash");
+        let read: ConfigOverrides = serde_json::from_value(Value::Object(fields)).unwrap();
+        assert_eq!(Config::resolve(ConfigOverrides::default(), read), config);
+    }
+
+    #[test]
+    fn workspaces() {
+        let file: ConfigOverrides = serde_json::from_str(
+            r#"{ "workspaces": { "desk": { "floor": 20 }, "table": {} }, "workspace": "desk" }"#,
+        )
+        .unwrap();
+        let config = Config::resolve(ConfigOverrides::default(), file);
+        assert_eq!(config.workspaces["desk"].floor, Some(20.));
+
+        // selected once the head is clear of the floor
+        let robot = config.robot(Arm::default());
+        assert_eq!(robot.workspaces.pending.as_deref(), Some("desk"));
+        assert_eq!(robot.workspaces.profiles.len(), 2);
+
+        let missing = Args {
+            overrides: args("--workspace garage").unwrap().overrides,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Config::load(&missing),
+            Err(ConfigError::UnknownWorkspace(name)) if name == "garage"
+        ));
+    }
+
+    #[test]
+    fn startup() {
+        let load = |line: &str| {
+            Config::load(&Args {
+                overrides: args(line).unwrap().overrides,
+                ..Default::default()
+            })
+        };
+        let rejected = |line: &str| match load(line) {
+            Err(ConfigError::Startup(err)) => err,
+            other => panic!("{other:?}"),
+        };
+
+        let target = r#"--startup {"target":{"x":50,"y":100,"z":80},"claw":"open"}"#;
+        let config = load(target).unwrap();
+        assert_eq!(
+            config.startup.target,
+            Some(CordinateVec::new(50., 100., 80.))
+        );
+        assert_eq!(config.startup.claw, Some(ClawAction::Open));
+        assert!(load(r#"--startup {"mode":"detached","claw":{"angle":20}}"#).is_ok());
+
+        assert_eq!(
+            rejected(r#"--startup {"mode":"detached","home":true}"#),
+            StartupError::Detached
+        );
+        assert_eq!(
+            rejected(r#"--startup {"target":{"x":50,"y":100,"z":80},"home":true}"#),
+            StartupError::TargetAndHome
+        );
+        assert!(matches!(
+            rejected(r#"--startup {"target":{"x":0,"y":150,"z":150}}"#),
+            StartupError::OutOfReach { reach, .. } if reach == 200.
+        ));
+        assert_eq!(
+            rejected(&format!(
+                r#"{target} --joint-limits {{"base":{{"min":0,"max":140}}}}"#
+            )),
+            StartupError::JointLimits(CordinateVec::new(50., 100., 80.))
+        );
+        assert!(matches!(
+            rejected(&format!(
+                r#"{target} --workspaces {{"desk":{{"floor":100}}}} --workspace desk"#
+            )),
+            StartupError::Workspace { workspace, .. } if workspace == "desk"
+        ));
+
+        // in real world coordinates once calibrated
+        let calibrated = r#"--startup {"target":{"x":0,"y":150,"z":150}}
+            --calibration {"scale":1,"rotation":0,"offset":{"x":0,"y":0,"z":100}}"#;
+        assert!(load(calibrated).is_ok());
+    }
+
+    #[test]
+    fn robot() {
+        let config = Config {
+            status_interval: 0.,
+            output_max_hz: 0.,
+            idle_detach_after: 0.,
+            max_substep: 0.,
+            ..Default::default()
+        };
+        let robot = config.robot(Arm::default());
+        assert_eq!(robot.idle.detach_after, f64::INFINITY);
+        assert_eq!(robot.max_substep, None);
+        assert_eq!(robot.torque_limit, None);
+        assert_eq!(robot.supply, None);
+        assert_eq!(robot.stall.unwrap().config, config.stall);
+        assert_eq!(robot.correction, None);
+        assert_eq!(robot.estop.config, config.emergency_stop);
+        assert_eq!(robot.joint_limits, config.joint_limits);
+        assert_eq!(robot.envelope.voxel, config.envelope_voxel);
+
+        assert_eq!(robot.status.interval, None);
+        assert_eq!(robot.output, OutputRate::new(1, None));
+        assert_eq!(robot.connection.port, config.port);
+        assert_eq!(robot.connection.servo_encoding, config.servo_encoding);
+        assert!(!robot.connection.no_connect);
+        assert_eq!(robot.config_hash, config.hash());
+        assert_eq!(robot.bundle.config, config.dump());
+
+        let simulated = Config {
+            sim: SimConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..config
+        };
+        assert!(simulated.robot(Arm::default()).connection.no_connect);
+    }
+
+    #[test]
+    fn joint_limits() {
+        let path = std::env::temp_dir().join(format!("rac-limits-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{ "port": "/dev/ttyUSB0", "joint_limits": { "base": { "min": 5, "max": 170 } } }"#,
+        )
+        .unwrap();
+
+        let limits = JointLimits {
+            shoulder: Some(JointRange {
+                min: 33.,
+                max: 147.,
+            }),
+            ..Default::default()
+        };
+        write_joint_limits(&path, &limits).unwrap();
+
+        // replaces the limits and keeps the rest of the file
+        let args = Args {
+            config_file: Some(path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let config = Config::load(&args).unwrap();
+        assert_eq!(config.joint_limits, limits);
+        assert_eq!(config.port, "/dev/ttyUSB0");
+
+        // a new file has just the limits
+        fs::remove_file(&path).unwrap();
+        write_joint_limits(&path, &limits).unwrap();
+        let config = Config::load(&args).unwrap();
+        assert_eq!(config.joint_limits, limits);
+        assert_eq!(config.port, Config::default().port);
+
+        // other settings are written the same way
+        let sticks = StickCalibration {
+            left_x: Some(AxisCenter {
+                offset: 0.06,
+                noise: 0.01,
+            }),
+            ..Default::default()
+        };
+        write_setting(&path, "stick_calibration", &sticks).unwrap();
+        let config = Config::load(&args).unwrap();
+        assert_eq!(config.stick_calibration, sticks);
+        assert_eq!(config.joint_limits, limits);
+        fs::remove_file(&path).unwrap();
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            write_joint_limits(&path, &limits),
+            Err(ConfigError::Parse(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+
+/// Gains of a [`Pid`], as they are configured
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Gains {
+    /// Output per unit of error
+    pub kp: f64,
+
+    /// Output per unit of error and second it lasted
+    pub ki: f64,
+
+    /// Output per unit of error change per second
+    pub kd: f64,
+}
+
+/// Proportional, integral and derivative controller driving a measurement to a setpoint
+///
+/// * The output is clamped to `min..=max`, and the error is only integrated until the output
+///   reaches a limit, so the integral doesn't wind up during saturation and the output comes
+///   off the limit as soon as the error turns
+/// * The derivative is taken of the measurement rather than the error, so a setpoint change
+///   doesn't kick the output, and low pass filtered with [`Pid::derivative_filter`]
+/// * The integral is kept as its share of the output, so changing the gains doesn't bump it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pid {
+    pub gains: Gains,
+
+    /// Smallest and largest output
+    pub min: f64,
+    pub max: f64,
+
+    /// Time constant of the low pass on the derivative in seconds, 0 to not filter
+    pub derivative_filter: f64,
+
+    /// Integral share of the output
+    integral: f64,
+
+    /// Filtered rate of change of the measurement per second
+    derivative: f64,
+
+    /// Measurement of the previous update, `None` right after a reset
+    last: Option<f64>,
+}
+
+impl Pid {
+    /// An unlimited controller, see [`Pid::limits`]
+    pub fn new(gains: Gains) -> Self {
+        Self {
+            gains,
+            min: f64::NEG_INFINITY,
+            max: f64::INFINITY,
+            derivative_filter: 0.,
+            integral: 0.,
+            derivative: 0.,
+            last: None,
+        }
+    }
+
+    /// Clamp the output to `min..=max`
+    pub fn limits(self, min: f64, max: f64) -> Self {
+        Self { min, max, ..self }
+    }
+
+    /// Filter the derivative with a time constant of `seconds`
+    pub fn filtered(self, seconds: f64) -> Self {
+        Self {
+            derivative_filter: seconds,
+            ..self
+        }
+    }
+
+    /// Step the controller `delta` seconds on
+    ///
+    /// # Returns
+    /// The output, between [`Pid::min`] and [`Pid::max`]
+    pub fn update(&mut self, setpoint: f64, measurement: f64, delta: f64) -> f64 {
+        let error = setpoint - measurement;
+        if delta > 0. {
+            if let Some(last) = self.last {
+                let rate = (measurement - last) / delta;
+                let alpha = delta / (self.derivative_filter + delta);
+                self.derivative += alpha * (rate - self.derivative);
+            }
+        }
+        self.last = Some(measurement);
+
+        let proportional = self.gains.kp * error;
+        let derivative = -self.gains.kd * self.derivative;
+        let integral = self.integral + self.gains.ki * error * delta.max(0.);
+
+        // only integrate as far as it takes the output to a limit, further would wind it up
+        let rest = proportional + derivative;
+        self.integral = if integral > self.integral {
+            integral.min((self.max - rest).max(self.integral))
+        } else {
+            integral.max((self.min - rest).min(self.integral))
+        };
+
+        (proportional + self.integral + derivative).clamp(self.min, self.max)
+    }
+
+    /// Forget the integral and derivative, as if it was just created
+    pub fn reset(&mut self) {
+        self.integral = 0.;
+        self.derivative = 0.;
+        self.last = None;
+    }
+
+    /// Take over from something else that was driving the output, the first update carries
+    /// on from `output` instead of jumping to what the controller would have done
+    pub fn resume(&mut self, output: f64, setpoint: f64, measurement: f64) {
+        self.reset();
+        self.last = Some(measurement);
+        self.integral = output.clamp(self.min, self.max) - self.gains.kp * (setpoint - measurement);
+    }
+
+    /// Integral share of the output
+    #[cfg(test)]
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DELTA: f64 = 0.001;
+
+    fn gains(kp: f64, ki: f64, kd: f64) -> Gains {
+        Gains { kp, ki, kd }
+    }
+
+    /// Drive a plant whose measurement changes by the output per second to `setpoint`
+    ///
+    /// # Returns
+    /// The measurement after every second
+    fn integrator(pid: &mut Pid, setpoint: f64, seconds: usize) -> Vec<f64> {
+        let mut measurement = 0.;
+        let mut samples = Vec::new();
+        for step in 1..=seconds * 1000 {
+            measurement += pid.update(setpoint, measurement, DELTA) * DELTA;
+            if step % 1000 == 0 {
+                samples.push(measurement);
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn proportional() {
+        // x' = kp (r - x) settles as r (1 - e^(-kp t))
+        let mut pid = Pid::new(gains(2., 0., 0.));
+        for (second, measurement) in integrator(&mut pid, 10., 3).into_iter().enumerate() {
+            let expected = 10. * (1. - (-2. * (second + 1) as f64).exp());
+            assert!(
+                (measurement - expected).abs() < 0.02,
+                "{measurement} {expected}"
+            );
+        }
+
+        // one update is just the error scaled, clamped to the limits
+        let mut pid = Pid::new(gains(2., 0., 0.)).limits(-5., 5.);
+        assert_eq!(pid.update(2., 0., DELTA), 4.);
+        assert_eq!(pid.update(10., 0., DELTA), 5.);
+        assert_eq!(pid.update(-10., 0., DELTA), -5.);
+        assert_eq!(pid.integral(), 0.);
+    }
+
+    #[test]
+    fn proportional_integral_clamped() {
+        // clamped at 2 it ramps at 2 per second until the error is small enough to let go
+        let mut pid = Pid::new(gains(1., 0.5, 0.)).limits(-2., 2.);
+        let samples = integrator(&mut pid, 10., 20);
+        assert!((samples[0] - 2.).abs() < 1e-9);
+        assert!((samples[3] - 8.).abs() < 1e-9);
+
+        // then the integral takes out what's left without overshooting much
+        assert!(samples.iter().all(|&measurement| measurement < 10.5));
+        assert!((samples[19] - 10.).abs() < 0.01);
+
+        // and holds it against a constant disturbance, which a plain proportional can't
+        let mut pid = Pid::new(gains(1., 0.5, 0.));
+        let mut measurement = 0.;
+        for _ in 0..40_000 {
+            measurement += (pid.update(10., measurement, DELTA) - 1.) * DELTA;
+        }
+        assert!((measurement - 10.).abs() < 1e-3);
+        assert!((pid.integral() - 1.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn anti_windup() {
+        // the plant is stuck far off for a while, the output sits at its limit
+        let mut pid = Pid::new(gains(1., 5., 0.)).limits(-1., 1.);
+        for _ in 0..10_000 {
+            assert_eq!(pid.update(100., 0., DELTA), 1.);
+        }
+        assert!(pid.integral() <= 1.);
+
+        // it comes off the limit as soon as the error turns instead of unwinding first
+        assert!(pid.update(100., 100.5, DELTA) < 1.);
+        assert!(pid.update(100., 102., DELTA) < 0.);
+
+        // the measurement sitting past the setpoint doesn't wind it up the other way either
+        for _ in 0..10_000 {
+            pid.update(0., 100., DELTA);
+        }
+        assert!(pid.integral() >= -1.);
+        assert!(pid.update(0., -1.5, DELTA) > 0.);
+
+        // the integral still takes an output the proportional share keeps short all the way
+        let mut pid = Pid::new(gains(0.2, 2., 0.)).limits(-5., 5.);
+        for _ in 0..10_000 {
+            pid.update(15., 0., DELTA);
+        }
+        assert_eq!(pid.update(15., 0., DELTA), 5.);
+        assert!((pid.integral() - 2.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn derivative() {
+        // acts against the measurement changing, not the setpoint
+        let mut pid = Pid::new(gains(0., 0., 1.));
+        assert_eq!(pid.update(0., 0., DELTA), 0.);
+        assert_eq!(pid.update(100., 0., DELTA), 0.);
+        assert!((pid.update(100., 0.001, DELTA) + 1.).abs() < 1e-9);
+
+        // a filtered step eases in over the time constant
+        let mut pid = Pid::new(gains(0., 0., 1.)).filtered(0.01);
+        pid.update(0., 0., DELTA);
+        let kick = pid.update(0., 0.001, DELTA);
+        assert!(kick > -0.1 && kick < 0.);
+        let mut measurement = 0.001;
+        let mut output = kick;
+        for _ in 0..50 {
+            measurement += 0.001;
+            output = pid.update(0., measurement, DELTA);
+        }
+        assert!((output + 1.).abs() < 0.01);
+    }
+
+    #[test]
+    fn reset_and_resume() {
+        let mut pid = Pid::new(gains(1., 1., 1.)).limits(-10., 10.);
+        for _ in 0..1000 {
+            pid.update(5., 0., DELTA);
+        }
+        pid.reset();
+        assert_eq!(pid.integral(), 0.);
+        assert_eq!(pid.update(5., 0., DELTA), 5. + 5. * DELTA);
+
+        // taking over from an output of 3 carries on from 3, not from kp * error
+        let mut pid = Pid::new(gains(2., 1., 1.)).limits(-10., 10.);
+        pid.resume(3., 5., 4.);
+        let output = pid.update(5., 4., DELTA);
+        assert!((output - 3.).abs() < 0.01, "{output}");
+
+        // an output past the limits resumes from the limit
+        pid.resume(20., 5., 4.);
+        assert!((pid.update(5., 4., DELTA) - 10.).abs() < 0.01);
+    }
+}
@@ -0,0 +1,384 @@
+use std::fmt::Write;
+
+use crate::{
+    kinematics::operator,
+    robot::{
+        boundary::BoundaryPolicy,
+        envelope::EnvelopeMode,
+        estop::StopStage,
+        limits::{End, SearchStage},
+        status::FirmwareStatusView,
+        supply::SupplyLevel,
+        RobotState,
+    },
+};
+
+/// Render the robot state as text for the terminal
+pub fn render(state: &RobotState) -> String {
+    let mut out = String::new();
+    render_into(state, &mut out);
+    out
+}
+
+/// Same as [`render`] but into `out`, replacing what it held
+///
+/// Reusing the string each frame keeps the display from allocating once it has grown to fit
+pub fn render_into(state: &RobotState, out: &mut String) {
+    out.clear();
+
+    // writing to a string can't fail
+    let _ = writeln!(out, "cfg: {:016x}", state.config_hash);
+    let _ = writeln!(out, "pos: {:?}", state.position);
+    if state.calibration.calibrated() {
+        let world = state.calibration.to_world(state.position);
+        let _ = writeln!(out, "wld: ({:.1}, {:.1}, {:.1})", world.x, world.y, world.z);
+    }
+    let _ = writeln!(out, "trg: {:?}", state.target_position);
+    let _ = writeln!(out, "vel: {:?}", state.velocity);
+    let _ = writeln!(out, "tve: {:?}", state.target_velocity);
+    let angles = operator::to_operator(state.angles);
+    let _ = writeln!(
+        out,
+        "jnt: base {:.1}°, shoulder {:.1}°, elbow {:.1}°, claw {:.1}°",
+        angles.base, angles.shoulder, angles.elbow, angles.claw
+    );
+    let _ = writeln!(out, "com: {:?}", state.connection);
+    let _ = writeln!(
+        out,
+        "lnk: quality {:.2}, speed {:.0}%",
+        state.link_quality,
+        state.speed_scale * 100.
+    );
+    let _ = writeln!(
+        out,
+        "odo: base {:.0}°, shoulder {:.0}°, elbow {:.0}°, claw {:.0}° (total {:.0}°, {:.0}°, {:.0}°, {:.0}°)",
+        state.travel.base,
+        state.travel.shoulder,
+        state.travel.elbow,
+        state.travel.claw,
+        state.total_travel.base,
+        state.total_travel.shoulder,
+        state.total_travel.elbow,
+        state.total_travel.claw,
+    );
+    match state.output.max_hz {
+        Some(hz) => {
+            let _ = writeln!(out, "out: every {} ticks, at most {hz}Hz", state.output.divider);
+        }
+        None => {
+            let _ = writeln!(out, "out: every {} ticks", state.output.divider);
+        }
+    }
+    if state.torque_scale < 1. {
+        let _ = writeln!(
+            out,
+            "trq: slowed to {:.0}% for the load on the servos",
+            state.torque_scale * 100.
+        );
+    }
+    if state.payload > 0. {
+        let _ = writeln!(
+            out,
+            "pay: {:.0}g, reach {:.1}, accelerating at {:.0}%",
+            state.payload,
+            state.reach,
+            state.acceleration_scale * 100.
+        );
+    }
+    match state.emergency_stop {
+        Some(StopStage::Braking) => {
+            let _ = writeln!(out, "stp: emergency stop, braking");
+        }
+        Some(StopStage::Halted) => {
+            let _ = writeln!(out, "stp: emergency stop, halted until released");
+        }
+        Some(StopStage::Detached) => {
+            let _ = writeln!(out, "stp: emergency stop, servos detached until released");
+        }
+        None => {}
+    }
+    match state.limit_search {
+        Some((joint, SearchStage::Confirm)) => {
+            let _ = writeln!(
+                out,
+                "lim: {joint} waiting, `limits confirm {joint}` to search"
+            );
+        }
+        Some((joint, SearchStage::Seek(End::Min) | SearchStage::BackOff { end: End::Min, .. })) => {
+            let _ = writeln!(out, "lim: searching the {joint} minimum");
+        }
+        Some((joint, _)) => {
+            let _ = writeln!(out, "lim: searching the {joint} maximum");
+        }
+        None => {}
+    }
+    match state.servo_override {
+        Some(manual) if manual.returning => {
+            let _ = writeln!(out, "ovr: {} manual, back to normal control", manual.joint);
+        }
+        Some(manual) => {
+            let _ = writeln!(
+                out,
+                "ovr: {} MANUAL at {}µs, {:.0}s left, `servo off` to end",
+                manual.joint, manual.pulse, manual.remaining
+            );
+        }
+        None => {}
+    }
+    if let Some((step, steps)) = state.program {
+        let _ = writeln!(out, "prg: waypoint {} of {steps}", step + 1);
+    }
+    if let Some(joint) = state.stalled {
+        let _ = writeln!(out, "stl: {joint} stalled, paused until resumed");
+    }
+    if let Some(joint) = state.soft_margin {
+        let _ = writeln!(out, "mrg: {joint} NEAR ITS LIMIT");
+    }
+    if state.jog_paused {
+        let _ = writeln!(out, "jog: move paused, carries on once the sticks are let go");
+    }
+    match state.supply {
+        Some((SupplyLevel::Low, millivolts)) => {
+            let volts = millivolts as f64 / 1000.;
+            let _ = writeln!(out, "sup: low at {volts:.2}V, accelerating gently");
+        }
+        Some((SupplyLevel::Critical, millivolts)) => {
+            let volts = millivolts as f64 / 1000.;
+            let _ = writeln!(out, "sup: critical at {volts:.2}V, shut down until it recovers");
+        }
+        _ => {}
+    }
+    if let Some(shutdown) = state.shutdown {
+        let progress = if shutdown.done { "done" } else { "in progress" };
+        let _ = writeln!(
+            out,
+            "sdn: {} ({}), {progress}",
+            shutdown.stage, shutdown.reason
+        );
+    }
+    if state.detached {
+        let _ = writeln!(out, "idl: servos detached");
+    }
+    if let Some(event) = state.last_event {
+        let _ = writeln!(out, "evt: {}", event.event);
+    }
+    match (&state.workspace, &state.pending_workspace) {
+        (active, Some(pending)) => {
+            let active = active.as_deref().unwrap_or("none");
+            let _ = writeln!(out, "wsp: {active}, switching to {pending} once clear");
+        }
+        (Some(active), None) => {
+            let _ = writeln!(out, "wsp: {active}");
+        }
+        (None, None) => {}
+    }
+    if state.envelope_mode != EnvelopeMode::Off {
+        let _ = write!(
+            out,
+            "env: {}, {} voxels",
+            state.envelope_mode, state.envelope_voxels
+        );
+        if state.envelope_overflowed > 0 {
+            let _ = write!(out, ", full, {} positions not learned", state.envelope_overflowed);
+        }
+        let _ = writeln!(out);
+    }
+    match state.boundary.policy {
+        BoundaryPolicy::HardClamp => {
+            let _ = writeln!(out, "bnd: hard clamp");
+        }
+        BoundaryPolicy::SoftMargin => {
+            let _ = writeln!(out, "bnd: soft margin of {:.0}", state.boundary.margin);
+        }
+        BoundaryPolicy::Off => {
+            let cap = state.boundary.max_speed.unwrap_or_default();
+            let _ = writeln!(out, "bnd: OFF, at most {cap:.0} units/s");
+        }
+    }
+    if state.faults > 0 {
+        let _ = writeln!(out, "flt: {} non-finite values caught", state.faults);
+    }
+    if state.mirror {
+        let _ = writeln!(out, "mir: x axis mirrored");
+    }
+    if state.connection.probable_baud_mismatch() {
+        let _ = writeln!(out, "probable baud mismatch at {} baud", state.baud);
+    }
+
+    diagnostics_into(state, out);
+}
+
+/// Diagnostics panel showing what the firmware reports about itself
+#[cfg(test)]
+pub fn diagnostics(state: &RobotState) -> String {
+    let mut out = String::new();
+    diagnostics_into(state, &mut out);
+    out
+}
+
+/// Append the diagnostics panel to `out`
+fn diagnostics_into(state: &RobotState, out: &mut String) {
+    let _ = match state.firmware {
+        FirmwareStatusView::Missing => writeln!(out, "fw:  no status received"),
+        FirmwareStatusView::Stale { age } => {
+            writeln!(out, "fw:  status stale ({:.0}s old)", age.as_secs_f64())
+        }
+        FirmwareStatusView::Current(status) => writeln!(
+            out,
+            "fw:  uptime {:.1}s, supply {:.2}V, loop {}Hz, last seq {}",
+            status.uptime_ms as f64 / 1000.,
+            status.millivolts as f64 / 1000.,
+            status.loop_hz,
+            status.last_seq,
+        ),
+    };
+}
+
+/// Table of the connection, loop and link stats for the `stats` command
+pub fn stats_table(state: &RobotState) -> String {
+    let stats = &state.connection;
+    let rows = [
+        ("feedback", stats.sent.feedback, stats.received.feedback, stats.errors.feedback),
+        ("hello", stats.sent.hello, stats.received.hello, stats.errors.hello),
+        (
+            "status request",
+            stats.sent.status_request,
+            stats.received.status_request,
+            stats.errors.status_request,
+        ),
+        ("status", stats.sent.status, stats.received.status, stats.errors.status),
+        ("ack", stats.sent.ack, stats.received.ack, stats.errors.ack),
+        ("detach", stats.sent.detach, stats.received.detach, stats.errors.detach),
+        ("attach", stats.sent.attach, stats.received.attach, stats.errors.attach),
+        ("unknown", stats.sent.unknown, stats.received.unknown, stats.errors.unknown),
+    ];
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{:<16}{:>10}{:>10}{:>10}", "frame", "sent", "received", "errors");
+    for (name, sent, received, errors) in rows {
+        let _ = writeln!(out, "{name:<16}{sent:>10}{received:>10}{errors:>10}");
+    }
+    let _ = writeln!(
+        out,
+        "servo writes {}, bytes received {}, overflowed {}, frames dropped {}",
+        stats.servo_writes, stats.bytes_received, stats.overflow_bytes, stats.frames_dropped,
+    );
+    let _ = writeln!(
+        out,
+        "loop: {} ticks, mean {:.1}ms, max {:.1}ms",
+        state.loop_stats.ticks,
+        state.loop_stats.mean() * 1000.,
+        state.loop_stats.max * 1000.,
+    );
+    let _ = writeln!(
+        out,
+        "link: quality {:.2}, speed {:.0}%, {} baud, {} switches, {:.0}% framing errors",
+        state.link_quality,
+        state.speed_scale * 100.,
+        state.baud,
+        stats.baud_switches,
+        stats.error_ratio() * 100.,
+    );
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        protocol::FirmwareStatus,
+        robot::{arm::JointAngles, Robot},
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn firmware_panel() {
+        let mut state = Robot::default().state();
+
+        state.firmware = FirmwareStatusView::Missing;
+        assert_eq!(diagnostics(&state), "fw:  no status received\n");
+
+        state.firmware = FirmwareStatusView::Stale {
+            age: Duration::from_secs(20),
+        };
+        assert_eq!(diagnostics(&state), "fw:  status stale (20s old)\n");
+
+        state.firmware = FirmwareStatusView::Current(FirmwareStatus {
+            uptime_ms: 61_500,
+            millivolts: 4870,
+            loop_hz: 850,
+            last_seq: 17,
+        });
+        assert_eq!(
+            diagnostics(&state),
+            "fw:  uptime 61.5s, supply 4.87V, loop 850Hz, last seq 17\n"
+        );
+    }
+
+    #[test]
+    fn operator_angles() {
+        let mut robot = Robot::default();
+        let angles = JointAngles {
+            base: 120.,
+            shoulder: 30.,
+            elbow: 100.,
+            claw: 45.,
+        };
+        robot.arm.interpolate(angles, angles, 0.);
+
+        // base 0° straight ahead, shoulder 0° horizontal, elbow 0° straight
+        assert!(render(&robot.state())
+            .contains("jnt: base 30.0°, shoulder 60.0°, elbow 80.0°, claw 45.0°\n"));
+    }
+
+    #[test]
+    fn soft_margin() {
+        let mut state = Robot::default().state();
+        assert!(!render(&state).contains("mrg:"));
+
+        state.soft_margin = Some("elbow");
+        assert!(render(&state).contains("mrg: elbow NEAR ITS LIMIT\n"));
+    }
+
+    #[test]
+    fn boundary() {
+        let mut robot = Robot::default();
+        assert!(render(&robot.state()).contains("bnd: hard clamp\n"));
+
+        robot.boundary.max_speed = Some(20.);
+        robot.set_boundary_policy(BoundaryPolicy::Off).unwrap();
+        assert!(render(&robot.state()).contains("bnd: OFF, at most 20 units/s\n"));
+    }
+
+    #[test]
+    fn stats() {
+        let mut robot = Robot::default();
+        robot.connection.stats.sent.status_request = 3;
+        robot.connection.stats.received.feedback = 120;
+        robot.connection.stats.errors.feedback = 2;
+        robot.connection.stats.frames_received = 120;
+        robot.connection.stats.framing_errors = 2;
+        robot.connection.stats.servo_writes = 118;
+        for delta in [0.004, 0.006] {
+            robot.loop_stats.tick(delta);
+        }
+
+        let table = stats_table(&robot.state());
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines[0],
+            "frame                 sent  received    errors"
+        );
+        assert_eq!(
+            lines[1],
+            "feedback                 0       120         2"
+        );
+        assert_eq!(
+            lines[3],
+            "status request           3         0         0"
+        );
+        assert!(lines[9].starts_with("servo writes 118,"));
+        assert_eq!(lines[10], "loop: 2 ticks, mean 5.0ms, max 6.0ms");
+        assert!(lines[11].ends_with("2% framing errors"));
+    }
+}
@@ -0,0 +1,1052 @@
+use std::{
+    collections::VecDeque,
+    fmt, fs,
+    io::{self, Read},
+    path::Path,
+    process::Command,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use gilrs::{Axis, Button, Gilrs};
+use serde::{Deserialize, Serialize};
+
+use crate::kinematics::position::CordinateVec;
+
+/// How long a key counts as held after it was typed
+///
+/// Terminals only report key presses, holding a key repeats it faster than this
+pub const KEY_HOLD: Duration = Duration::from_millis(150);
+
+/// Furthest from 0 a stick may read while calibrating before it counts as pushed
+pub const MAX_REST: f64 = 0.25;
+
+/// How many times the noise at rest a calibrated axis' deadzone is
+const NOISE_MARGIN: f64 = 2.;
+
+/// Smallest deadzone of a calibrated axis, even one without any noise
+const MIN_DEADZONE: f64 = 0.02;
+
+/// Logical buttons, whatever physical button or key they are on
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Buttons {
+    /// Toggle mirror mode, see [`crate::robot::Robot::mirror`]
+    pub mirror: bool,
+
+    /// Select the next workspace profile
+    pub next_workspace: bool,
+
+    /// Ask the firmware for its status
+    pub status: bool,
+
+    /// Stop everything
+    pub emergency_stop: bool,
+
+    /// Use the grip bound to the D-pad up, right, down and left, see
+    /// [`crate::robot::Robot::grip_buttons`]
+    pub grips: [bool; 4],
+}
+
+/// What the operator is asking for right now, the same whatever the input came from
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputState {
+    /// Jog velocity as a share of the maximum on every axis, -1 to 1 after the [`AxisCurve`]
+    pub jog: CordinateVec,
+
+    /// Buttons held down
+    pub held: Buttons,
+
+    /// Buttons that went down since the last state, only filled in by [`Inputs::poll`]
+    pub pressed: Buttons,
+
+    /// Any source is connected, jogging stops when none is
+    #[serde(default = "connected")]
+    pub connected: bool,
+}
+
+/// Maps a raw stick value to a jog value
+///
+/// Values within the deadzone are 0 and the rest is rescaled so it starts at 0 at the edge of
+/// the deadzone instead of jumping to it, then raised to `exponent` for finer control of slow
+/// moves
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AxisCurve {
+    pub deadzone: f64,
+    pub exponent: f64,
+}
+
+/// Where a stick axis rests and how much it jitters there
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AxisCenter {
+    /// Value the axis reads at rest
+    pub offset: f64,
+
+    /// Furthest from the offset the axis read at rest
+    pub noise: f64,
+}
+
+/// Rest positions of the stick axes, measured by [`GamepadSource::calibrate`]
+///
+/// Calibrated axes are read relative to where they rest, with a deadzone just wide enough for
+/// their noise instead of the [`AxisCurve`]'s. Axes that were never calibrated use the curve
+/// as it is
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StickCalibration {
+    pub left_x: Option<AxisCenter>,
+    pub left_y: Option<AxisCenter>,
+    pub right_x: Option<AxisCenter>,
+    pub right_y: Option<AxisCenter>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StickCalibrationError {
+    /// An axis read further than [`MAX_REST`] from the middle, someone is holding the stick
+    Deflected { axis: StickAxis, value: f64 },
+
+    /// Nothing was sampled, there is no gamepad
+    NoSamples,
+}
+
+#[derive(Debug)]
+pub enum InputScriptError {
+    Io(io::Error),
+
+    /// Not a json list of input states
+    Parse(serde_json::Error),
+}
+
+/// A logical stick axis, up and right are positive
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StickAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+/// An axis of the robot's frame, see [`CordinateVec`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorldAxis {
+    X,
+    Y,
+    Z,
+}
+
+/// A stick axis jogging along a world axis
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AxisBinding {
+    pub stick: StickAxis,
+    pub world: WorldAxis,
+
+    /// Pushing the stick up or right jogs towards the negative end of the world axis
+    #[serde(default)]
+    pub inverted: bool,
+}
+
+/// Which stick axis jogs along which world axis
+///
+/// Configured either as the name of a preset, see [`AxisMapping::PRESETS`], or as a list of
+/// bindings. Every world axis is driven by at most one stick axis, world axes nothing is
+/// bound to aren't jogged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "StoredMapping", into = "Vec<AxisBinding>")]
+pub struct AxisMapping {
+    bindings: Vec<AxisBinding>,
+}
+
+/// How an [`AxisMapping`] is configured
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum StoredMapping {
+    Preset(String),
+    Bindings(Vec<AxisBinding>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AxisMappingError {
+    /// Not one of [`AxisMapping::PRESETS`]
+    UnknownPreset(String),
+
+    /// More than one stick axis is bound to the world axis
+    Conflict(WorldAxis),
+}
+
+/// Somewhere input comes from
+pub trait InputSource {
+    /// The current state, sources leave [`InputState::pressed`] empty
+    fn poll(&mut self, now: Instant) -> InputState;
+}
+
+/// Gamepads through gilrs, the first connected gamepad is used
+pub struct GamepadSource {
+    pub gilrs: Gilrs,
+    pub curve: AxisCurve,
+    pub mapping: AxisMapping,
+    pub calibration: StickCalibration,
+}
+
+/// Keys typed on a keyboard
+///
+/// `w`/`s` jog along y, `a`/`d` along x and `r`/`f` along z. `m` toggles mirror mode, `n`
+/// selects the next workspace, `?` asks for the status, space is the emergency stop and `1` to
+/// `4` use the grips bound to the D-pad
+///
+/// Keys come from the terminal once [`KeyboardSource::spawn`]ed, or from
+/// [`KeyboardSource::key`]
+#[derive(Debug, Default)]
+pub struct KeyboardSource {
+    /// Keys and when they were last typed
+    keys: Vec<(char, Instant)>,
+
+    /// Keys read from the terminal
+    typed: Option<Receiver<char>>,
+}
+
+/// States played back one per poll, for tests and scripted demos
+///
+/// Once it runs out it keeps reporting the last state
+#[derive(Debug, Default)]
+pub struct ScriptedSource {
+    pub states: VecDeque<InputState>,
+    last: InputState,
+}
+
+/// All input sources merged into one [`InputState`]
+///
+/// Sources are in order of priority, the first one that jogs decides the jog. Buttons are
+/// held if they are held on any source, and an emergency stop from any source wins over
+/// everything and stops jogging
+pub struct Inputs {
+    pub sources: Vec<Box<dyn InputSource>>,
+
+    /// Buttons held at the last poll, to find the ones that were pressed since
+    held: Buttons,
+}
+
+impl Buttons {
+    fn or(self, other: Self) -> Self {
+        Self {
+            mirror: self.mirror || other.mirror,
+            next_workspace: self.next_workspace || other.next_workspace,
+            status: self.status || other.status,
+            emergency_stop: self.emergency_stop || other.emergency_stop,
+            grips: std::array::from_fn(|i| self.grips[i] || other.grips[i]),
+        }
+    }
+
+    /// Buttons held now that weren't held in `before`
+    fn pressed_since(self, before: Self) -> Self {
+        Self {
+            mirror: self.mirror && !before.mirror,
+            next_workspace: self.next_workspace && !before.next_workspace,
+            status: self.status && !before.status,
+            emergency_stop: self.emergency_stop && !before.emergency_stop,
+            grips: std::array::from_fn(|i| self.grips[i] && !before.grips[i]),
+        }
+    }
+}
+
+impl InputState {
+    /// Merge states in order of priority, see [`Inputs`]
+    pub fn merge(states: impl IntoIterator<Item = InputState>) -> Self {
+        let mut merged = Self::default();
+
+        for state in states {
+            if !state.connected {
+                continue;
+            }
+
+            merged.connected = true;
+            merged.held = merged.held.or(state.held);
+            if merged.jog == CordinateVec::default() {
+                merged.jog = state.jog;
+            }
+        }
+
+        if merged.held.emergency_stop {
+            merged.jog = CordinateVec::default();
+        }
+
+        merged
+    }
+}
+
+impl AxisCurve {
+    pub fn apply(&self, input: f64) -> f64 {
+        if input.abs() < self.deadzone || !input.is_finite() {
+            return 0.;
+        }
+
+        let scaled = (input.abs() - self.deadzone) / (1. - self.deadzone);
+        input.signum() * scaled.min(1.).powf(self.exponent)
+    }
+}
+
+impl AxisCenter {
+    /// Jog value for a raw stick value, see [`StickCalibration`]
+    ///
+    /// The value is rescaled so the stick still reaches 1 on the side it rests towards
+    pub fn apply(&self, curve: &AxisCurve, input: f64) -> f64 {
+        let centered = input - self.offset;
+        let range = if centered >= 0. {
+            1. - self.offset
+        } else {
+            1. + self.offset
+        };
+
+        let curve = AxisCurve {
+            deadzone: self.deadzone(),
+            ..*curve
+        };
+        curve.apply(centered / range)
+    }
+
+    pub fn deadzone(&self) -> f64 {
+        (self.noise * NOISE_MARGIN).max(MIN_DEADZONE)
+    }
+}
+
+impl StickCalibration {
+    /// How long the sticks are sampled for
+    pub const DURATION: Duration = Duration::from_secs(1);
+
+    /// Find where the sticks rest from samples taken while nobody touches them
+    ///
+    /// # Arguments
+    /// * `samples` - raw values of the axes in the order of [`StickAxis::ALL`]
+    pub fn from_samples(samples: &[[f64; 4]]) -> Result<Self, StickCalibrationError> {
+        if samples.is_empty() {
+            return Err(StickCalibrationError::NoSamples);
+        }
+
+        let mut calibration = Self::default();
+        for (i, axis) in StickAxis::ALL.into_iter().enumerate() {
+            let values = samples.iter().map(|sample| sample[i]);
+            if let Some(value) = values.clone().find(|value| value.abs() > MAX_REST || value.is_nan()) {
+                return Err(StickCalibrationError::Deflected { axis, value });
+            }
+
+            let offset = values.clone().sum::<f64>() / samples.len() as f64;
+            let noise = values.fold(0., |noise: f64, value| noise.max((value - offset).abs()));
+            *calibration.get_mut(axis) = Some(AxisCenter { offset, noise });
+        }
+
+        Ok(calibration)
+    }
+
+    pub fn get(&self, axis: StickAxis) -> Option<AxisCenter> {
+        match axis {
+            StickAxis::LeftX => self.left_x,
+            StickAxis::LeftY => self.left_y,
+            StickAxis::RightX => self.right_x,
+            StickAxis::RightY => self.right_y,
+        }
+    }
+
+    fn get_mut(&mut self, axis: StickAxis) -> &mut Option<AxisCenter> {
+        match axis {
+            StickAxis::LeftX => &mut self.left_x,
+            StickAxis::LeftY => &mut self.left_y,
+            StickAxis::RightX => &mut self.right_x,
+            StickAxis::RightY => &mut self.right_y,
+        }
+    }
+}
+
+impl Default for AxisCurve {
+    /// Linear with a 0.2 deadzone
+    fn default() -> Self {
+        Self {
+            deadzone: 0.2,
+            exponent: 1.,
+        }
+    }
+}
+
+impl AxisMapping {
+    /// Names of the mappings that ship with the controller
+    ///
+    /// * `default` - the left stick jogs along x and y like seen from above, the right stick
+    ///   up and down
+    /// * `flight` - like the sticks of a drone, the left stick up and down and the right stick
+    ///   along x and y
+    pub const PRESETS: [&'static str; 2] = ["default", "flight"];
+
+    /// Check the bindings, see [`AxisMappingError`]
+    pub fn new(bindings: Vec<AxisBinding>) -> Result<Self, AxisMappingError> {
+        for (i, binding) in bindings.iter().enumerate() {
+            if bindings[..i]
+                .iter()
+                .any(|other| other.world == binding.world)
+            {
+                return Err(AxisMappingError::Conflict(binding.world));
+            }
+        }
+
+        Ok(Self { bindings })
+    }
+
+    /// One of [`AxisMapping::PRESETS`]
+    pub fn preset(name: &str) -> Result<Self, AxisMappingError> {
+        let bind = |stick, world| AxisBinding {
+            stick,
+            world,
+            inverted: false,
+        };
+        let bindings = match name {
+            "default" => return Ok(Self::default()),
+            "flight" => vec![
+                bind(StickAxis::RightX, WorldAxis::X),
+                bind(StickAxis::RightY, WorldAxis::Y),
+                bind(StickAxis::LeftY, WorldAxis::Z),
+            ],
+            name => return Err(AxisMappingError::UnknownPreset(name.to_string())),
+        };
+
+        Self::new(bindings)
+    }
+
+    /// Jog along the world axes for the stick values
+    ///
+    /// # Arguments
+    /// * `stick` - value of a stick axis, after the [`AxisCurve`]
+    pub fn apply(&self, stick: impl Fn(StickAxis) -> f64) -> CordinateVec {
+        let mut jog = CordinateVec::default();
+
+        for binding in &self.bindings {
+            let value = if binding.inverted {
+                -stick(binding.stick)
+            } else {
+                stick(binding.stick)
+            };
+            match binding.world {
+                WorldAxis::X => jog.x = value,
+                WorldAxis::Y => jog.y = value,
+                WorldAxis::Z => jog.z = value,
+            }
+        }
+
+        jog
+    }
+}
+
+impl Default for AxisMapping {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                AxisBinding {
+                    stick: StickAxis::LeftX,
+                    world: WorldAxis::X,
+                    inverted: false,
+                },
+                AxisBinding {
+                    stick: StickAxis::LeftY,
+                    world: WorldAxis::Y,
+                    inverted: false,
+                },
+                AxisBinding {
+                    stick: StickAxis::RightY,
+                    world: WorldAxis::Z,
+                    inverted: false,
+                },
+            ],
+        }
+    }
+}
+
+impl TryFrom<StoredMapping> for AxisMapping {
+    type Error = AxisMappingError;
+
+    fn try_from(stored: StoredMapping) -> Result<Self, Self::Error> {
+        match stored {
+            StoredMapping::Preset(name) => Self::preset(&name),
+            StoredMapping::Bindings(bindings) => Self::new(bindings),
+        }
+    }
+}
+
+impl From<AxisMapping> for Vec<AxisBinding> {
+    fn from(mapping: AxisMapping) -> Self {
+        mapping.bindings
+    }
+}
+
+impl StickAxis {
+    pub const ALL: [StickAxis; 4] = [
+        StickAxis::LeftX,
+        StickAxis::LeftY,
+        StickAxis::RightX,
+        StickAxis::RightY,
+    ];
+
+    fn gilrs(self) -> Axis {
+        match self {
+            StickAxis::LeftX => Axis::LeftStickX,
+            StickAxis::LeftY => Axis::LeftStickY,
+            StickAxis::RightX => Axis::RightStickX,
+            StickAxis::RightY => Axis::RightStickY,
+        }
+    }
+}
+
+impl fmt::Display for AxisMappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AxisMappingError::UnknownPreset(name) => write!(
+                f,
+                "unknown axis mapping `{name}`, expected one of {}",
+                AxisMapping::PRESETS.join(", ")
+            ),
+            AxisMappingError::Conflict(world) => {
+                write!(f, "more than one stick axis drives the {world:?} axis")
+            }
+        }
+    }
+}
+
+impl fmt::Display for StickCalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StickCalibrationError::Deflected { axis, value } => write!(
+                f,
+                "{axis:?} reads {value:.2}, let go of the sticks while calibrating"
+            ),
+            StickCalibrationError::NoSamples => write!(f, "no gamepad connected"),
+        }
+    }
+}
+
+impl fmt::Display for InputScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputScriptError::Io(err) => write!(f, "{err}"),
+            InputScriptError::Parse(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Inputs {
+    /// Merge `sources`, the most important one first
+    pub fn new(sources: Vec<Box<dyn InputSource>>) -> Self {
+        Self {
+            sources,
+            held: Buttons::default(),
+        }
+    }
+
+    /// Poll every source and merge them, with the buttons pressed since the last poll
+    pub fn poll(&mut self, now: Instant) -> InputState {
+        let mut state = InputState::merge(self.sources.iter_mut().map(|source| source.poll(now)));
+
+        state.pressed = state.held.pressed_since(self.held);
+        self.held = state.held;
+        state
+    }
+}
+
+impl GamepadSource {
+    pub fn new(gilrs: Gilrs, mapping: AxisMapping) -> Self {
+        Self {
+            gilrs,
+            curve: AxisCurve::default(),
+            mapping,
+            calibration: StickCalibration::default(),
+        }
+    }
+
+    /// Sample the sticks at rest for `duration` and use the calibration from then on
+    ///
+    /// Blocks for the whole duration, only meant for startup
+    pub fn calibrate(
+        &mut self,
+        duration: Duration,
+    ) -> Result<StickCalibration, StickCalibrationError> {
+        let mut samples = Vec::new();
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            while self.gilrs.next_event().is_some() {}
+            if let Some((_, gamepad)) = self.gilrs.gamepads().next() {
+                samples.push(StickAxis::ALL.map(|axis| gamepad.value(axis.gilrs()) as f64));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        self.calibration = StickCalibration::from_samples(&samples)?;
+        Ok(self.calibration)
+    }
+}
+
+impl InputSource for GamepadSource {
+    fn poll(&mut self, _now: Instant) -> InputState {
+        // gilrs only updates the gamepad state while handing out events
+        while self.gilrs.next_event().is_some() {}
+
+        let Some((_, gamepad)) = self.gilrs.gamepads().next() else {
+            return InputState::default();
+        };
+        let axis = |axis: StickAxis| {
+            let value = gamepad.value(axis.gilrs()) as f64;
+            match self.calibration.get(axis) {
+                Some(center) => center.apply(&self.curve, value),
+                None => self.curve.apply(value),
+            }
+        };
+
+        InputState {
+            jog: self.mapping.apply(axis),
+            held: Buttons {
+                // both bumpers, both sticks, so neither is hit by accident
+                mirror: gamepad.is_pressed(Button::LeftTrigger)
+                    && gamepad.is_pressed(Button::RightTrigger),
+                next_workspace: gamepad.is_pressed(Button::LeftThumb)
+                    && gamepad.is_pressed(Button::RightThumb),
+                status: gamepad.is_pressed(Button::Select),
+                emergency_stop: gamepad.is_pressed(Button::Start),
+                grips: [
+                    Button::DPadUp,
+                    Button::DPadRight,
+                    Button::DPadDown,
+                    Button::DPadLeft,
+                ]
+                .map(|button| gamepad.is_pressed(button)),
+            },
+            pressed: Buttons::default(),
+            connected: true,
+        }
+    }
+}
+
+impl KeyboardSource {
+    /// Read keys from the terminal on a separate thread
+    ///
+    /// The terminal stops buffering lines so keys arrive as they are typed, until
+    /// [`KeyboardSource::restore_terminal`]. Stdin is taken, so commands are typed after a `:`
+    /// and sent to `commands` on enter
+    pub fn spawn(commands: Sender<String>) -> io::Result<Self> {
+        stty("-icanon")?;
+
+        let (sender, typed) = mpsc::channel();
+        thread::spawn(move || {
+            // the command being typed after a `:`
+            let mut line: Option<String> = None;
+            for byte in io::stdin().lock().bytes() {
+                let Ok(byte) = byte else {
+                    break;
+                };
+
+                let sent = match (&mut line, byte as char) {
+                    (None, ':') => {
+                        line = Some(String::new());
+                        true
+                    }
+                    (None, key) => sender.send(key).is_ok(),
+                    (Some(typing), '\n') => commands.send(std::mem::take(typing)).is_ok(),
+                    // backspace
+                    (Some(typing), '\x7f') => {
+                        typing.pop();
+                        true
+                    }
+                    (Some(typing), key) => {
+                        typing.push(key);
+                        true
+                    }
+                };
+                if !sent {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            keys: Vec::new(),
+            typed: Some(typed),
+        })
+    }
+
+    /// Buffer lines in the terminal again after [`KeyboardSource::spawn`]
+    pub fn restore_terminal() -> io::Result<()> {
+        stty("icanon")
+    }
+
+    /// A key was typed
+    pub fn key(&mut self, key: char, now: Instant) {
+        let key = key.to_ascii_lowercase();
+        self.keys.retain(|(held, _)| *held != key);
+        self.keys.push((key, now));
+    }
+}
+
+impl InputSource for KeyboardSource {
+    fn poll(&mut self, now: Instant) -> InputState {
+        while let Some(key) = self.typed.as_ref().and_then(|typed| typed.try_recv().ok()) {
+            self.key(key, now);
+        }
+        self.keys
+            .retain(|(_, typed)| now.saturating_duration_since(*typed) < KEY_HOLD);
+        let held = |key| self.keys.iter().any(|(held, _)| *held == key);
+        let axis = |plus, minus| held(plus) as i8 as f64 - held(minus) as i8 as f64;
+
+        InputState {
+            jog: CordinateVec::new(axis('d', 'a'), axis('w', 's'), axis('r', 'f')),
+            held: Buttons {
+                mirror: held('m'),
+                next_workspace: held('n'),
+                status: held('?'),
+                emergency_stop: held(' '),
+                grips: ['1', '2', '3', '4'].map(held),
+            },
+            pressed: Buttons::default(),
+            connected: true,
+        }
+    }
+}
+
+impl ScriptedSource {
+    pub fn new(states: impl IntoIterator<Item = InputState>) -> Self {
+        Self {
+            states: states.into_iter().collect(),
+            last: InputState::default(),
+        }
+    }
+
+    /// Read the states from a json list of [`InputState`]s, fields that are left out are
+    /// released and `connected` is true
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, InputScriptError> {
+        let data = fs::read_to_string(path).map_err(InputScriptError::Io)?;
+        let states: Vec<InputState> =
+            serde_json::from_str(&data).map_err(InputScriptError::Parse)?;
+        Ok(Self::new(states))
+    }
+}
+
+impl InputSource for ScriptedSource {
+    fn poll(&mut self, _now: Instant) -> InputState {
+        if let Some(state) = self.states.pop_front() {
+            self.last = state;
+        }
+        self.last
+    }
+}
+
+/// Input states in a script are connected unless they say otherwise
+fn connected() -> bool {
+    true
+}
+
+/// Change the settings of the terminal on stdin
+fn stty(setting: &str) -> io::Result<()> {
+    let status = Command::new("stty").arg(setting).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "stty {setting} failed with {status}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::robot::Robot;
+
+    fn jog(x: f64, y: f64, z: f64) -> InputState {
+        jog_state(CordinateVec::new(x, y, z))
+    }
+
+    fn jog_state(jog: CordinateVec) -> InputState {
+        InputState {
+            jog,
+            connected: true,
+            ..Default::default()
+        }
+    }
+
+    fn holding(buttons: Buttons) -> InputState {
+        InputState {
+            held: buttons,
+            connected: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn curve() {
+        let linear = AxisCurve::default();
+        assert_eq!(0., linear.apply(0.1));
+        assert_eq!(0., linear.apply(-0.19));
+        assert_eq!(0., linear.apply(0.2));
+        assert_eq!(1., linear.apply(1.));
+        assert_eq!(-1., linear.apply(-1.));
+        assert!((linear.apply(0.6) - 0.5).abs() < 1e-9);
+        assert_eq!(0., linear.apply(f64::NAN));
+
+        let squared = AxisCurve {
+            exponent: 2.,
+            ..linear
+        };
+        assert!((squared.apply(-0.6) + 0.25).abs() < 1e-9);
+        assert_eq!(1., squared.apply(1.5));
+    }
+
+    #[test]
+    fn stick_calibration() {
+        // the left stick rests off center, the right one is just noisy
+        let samples: Vec<[f64; 4]> = (0..100)
+            .map(|i| {
+                let noise = (i as f64 * 1.3).sin();
+                [0.06 + noise * 0.01, -0.04 - noise * 0.02, noise * 0.03, 0.]
+            })
+            .collect();
+        let calibration = StickCalibration::from_samples(&samples).unwrap();
+
+        let left_x = calibration.left_x.unwrap();
+        assert!((left_x.offset - 0.06).abs() < 1e-3, "{left_x:?}");
+        assert!((left_x.noise - 0.01).abs() < 1e-3, "{left_x:?}");
+        let left_y = calibration.left_y.unwrap();
+        assert!((left_y.offset + 0.04).abs() < 1e-3, "{left_y:?}");
+        assert!((left_y.deadzone() - 0.04).abs() < 2e-3, "{left_y:?}");
+        assert!((calibration.right_x.unwrap().deadzone() - 0.06).abs() < 3e-3);
+        assert_eq!(calibration.right_y.unwrap().deadzone(), MIN_DEADZONE);
+
+        // resting is still, and both ends are still reached
+        let curve = AxisCurve::default();
+        for sample in &samples {
+            assert_eq!(left_x.apply(&curve, sample[0]), 0.);
+            assert_eq!(left_y.apply(&curve, sample[1]), 0.);
+        }
+        assert_eq!(left_x.apply(&curve, 1.), 1.);
+        assert_eq!(left_x.apply(&curve, -1.), -1.);
+        // a little past the rest position moves, well inside the fixed deadzone
+        assert!(left_x.apply(&curve, 0.1) > 0.);
+        assert_eq!(curve.apply(0.1), 0.);
+
+        let mut held = samples.clone();
+        held[50][2] = 0.8;
+        assert_eq!(
+            StickCalibration::from_samples(&held),
+            Err(StickCalibrationError::Deflected {
+                axis: StickAxis::RightX,
+                value: 0.8
+            })
+        );
+        assert_eq!(
+            StickCalibration::from_samples(&[]),
+            Err(StickCalibrationError::NoSamples)
+        );
+
+        // stored in the config as is
+        let json = serde_json::to_string(&calibration).unwrap();
+        assert_eq!(serde_json::from_str::<StickCalibration>(&json).unwrap(), calibration);
+        assert_eq!(
+            serde_json::from_str::<StickCalibration>("{}").unwrap(),
+            StickCalibration::default()
+        );
+    }
+
+    #[test]
+    fn merge_priority() {
+        let mirror = Buttons {
+            mirror: true,
+            ..Default::default()
+        };
+
+        // the first source that jogs wins, buttons come from all of them
+        let merged = InputState::merge([jog(0., 0., 0.), jog(0.5, 0., 0.), jog(0., 1., 0.)]);
+        assert_eq!(merged.jog, CordinateVec::new(0.5, 0., 0.));
+        let merged = InputState::merge([jog(1., 0., 0.), holding(mirror)]);
+        assert_eq!(merged.jog, CordinateVec::new(1., 0., 0.));
+        assert!(merged.held.mirror);
+
+        // disconnected sources don't count
+        let merged = InputState::merge([
+            InputState {
+                connected: false,
+                ..jog(1., 1., 1.)
+            },
+            jog(0., 0., 0.5),
+        ]);
+        assert_eq!(merged.jog, CordinateVec::new(0., 0., 0.5));
+        assert!(merged.connected);
+        assert!(!InputState::merge([]).connected);
+
+        // an emergency stop from the least important source still stops everything
+        let stop = holding(Buttons {
+            emergency_stop: true,
+            ..Default::default()
+        });
+        let merged = InputState::merge([jog(1., 1., 1.), stop]);
+        assert_eq!(merged.jog, CordinateVec::default());
+        assert!(merged.held.emergency_stop);
+    }
+
+    #[test]
+    fn edges() {
+        let status = Buttons {
+            status: true,
+            ..Default::default()
+        };
+        let mut inputs = Inputs::new(vec![Box::new(ScriptedSource::new([
+            holding(status),
+            holding(status),
+            jog(0., 0., 0.),
+            holding(status),
+        ]))]);
+        let now = Instant::now();
+
+        let pressed: Vec<bool> = (0..5).map(|_| inputs.poll(now).pressed.status).collect();
+        assert_eq!(pressed, [true, false, false, true, false]);
+    }
+
+    /// Stick values for the left and right stick
+    fn sticks(left: (f64, f64), right: (f64, f64)) -> impl Fn(StickAxis) -> f64 {
+        move |axis| match axis {
+            StickAxis::LeftX => left.0,
+            StickAxis::LeftY => left.1,
+            StickAxis::RightX => right.0,
+            StickAxis::RightY => right.1,
+        }
+    }
+
+    #[test]
+    fn axis_mapping() {
+        let default = AxisMapping::preset("default").unwrap();
+        assert_eq!(default, AxisMapping::default());
+        assert_eq!(
+            default.apply(sticks((0.5, -1.), (0.3, 0.25))),
+            CordinateVec::new(0.5, -1., 0.25)
+        );
+
+        let flight = AxisMapping::preset("flight").unwrap();
+        assert_eq!(
+            flight.apply(sticks((0.5, -1.), (0.3, 0.25))),
+            CordinateVec::new(0.3, 0.25, -1.)
+        );
+
+        // unbound world axes aren't jogged
+        let inverted = AxisMapping::new(vec![AxisBinding {
+            stick: StickAxis::LeftY,
+            world: WorldAxis::Z,
+            inverted: true,
+        }])
+        .unwrap();
+        assert_eq!(
+            inverted.apply(sticks((1., 0.5), (1., 1.))),
+            CordinateVec::new(0., 0., -0.5)
+        );
+
+        // and the robot jogs at that share of its maximum velocity
+        let mut robot = Robot {
+            max_velocity: CordinateVec::new(10., 20., 40.),
+            ..Default::default()
+        };
+        robot.apply_input(&jog_state(flight.apply(sticks((0., 0.5), (-1., 0.)))));
+        assert_eq!(robot.target_velocity, CordinateVec::new(-10., 0., 20.));
+    }
+
+    #[test]
+    fn axis_mapping_config() {
+        let parse = |json| serde_json::from_str::<AxisMapping>(json);
+
+        assert_eq!(
+            parse(r#""flight""#).unwrap(),
+            AxisMapping::preset("flight").unwrap()
+        );
+        let mapping = parse(
+            r#"[{ "stick": "right_y", "world": "z", "inverted": true }, { "stick": "left_x", "world": "x" }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            mapping.apply(sticks((0.5, 0.), (0., 1.))),
+            CordinateVec::new(0.5, 0., -1.)
+        );
+
+        // saved as the bindings so it reads back the same
+        let saved = serde_json::to_string(&mapping).unwrap();
+        assert_eq!(parse(&saved).unwrap(), mapping);
+
+        assert!(parse(r#""helicopter""#).is_err());
+        assert_eq!(
+            AxisMapping::new(vec![
+                AxisBinding {
+                    stick: StickAxis::LeftY,
+                    world: WorldAxis::Y,
+                    inverted: false,
+                },
+                AxisBinding {
+                    stick: StickAxis::RightY,
+                    world: WorldAxis::Y,
+                    inverted: true,
+                },
+            ]),
+            Err(AxisMappingError::Conflict(WorldAxis::Y))
+        );
+        assert!(parse(
+            r#"[{ "stick": "left_x", "world": "x" }, { "stick": "left_y", "world": "x" }]"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn keyboard() {
+        let start = Instant::now();
+        let mut keyboard = KeyboardSource::default();
+
+        keyboard.key('W', start);
+        keyboard.key('a', start);
+        keyboard.key(' ', start);
+        let state = keyboard.poll(start);
+        assert_eq!(state.jog, CordinateVec::new(-1., 1., 0.));
+        assert!(state.held.emergency_stop);
+
+        // opposite keys cancel out
+        keyboard.key('s', start);
+        assert_eq!(keyboard.poll(start).jog.y, 0.);
+
+        // released once it isn't repeated
+        keyboard.key('r', start + KEY_HOLD / 2);
+        let state = keyboard.poll(start + KEY_HOLD);
+        assert_eq!(state.jog, CordinateVec::new(0., 0., 1.));
+        assert!(!state.held.emergency_stop);
+
+        // keys read from the terminal are taken at the next poll
+        let (sender, typed) = mpsc::channel();
+        let mut keyboard = KeyboardSource {
+            typed: Some(typed),
+            ..Default::default()
+        };
+        sender.send('f').unwrap();
+        assert_eq!(keyboard.poll(start).jog, CordinateVec::new(0., 0., -1.));
+    }
+
+    #[test]
+    fn script() {
+        let path = std::env::temp_dir().join(format!("rac-inputs-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"[{"jog": {"x": 1, "y": 0, "z": 0}}, {"held": {"emergency_stop": true}}]"#,
+        )
+        .unwrap();
+        let mut script = ScriptedSource::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let now = Instant::now();
+        assert_eq!(script.poll(now), jog(1., 0., 0.));
+        let state = script.poll(now);
+        assert!(state.held.emergency_stop && state.connected);
+        assert_eq!(state.jog, CordinateVec::default());
+        assert_eq!(script.poll(now), state);
+
+        assert!(matches!(
+            ScriptedSource::load("/no/such/script.json"),
+            Err(InputScriptError::Io(_))
+        ));
+    }
+}
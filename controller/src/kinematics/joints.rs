@@ -1,200 +1,253 @@
 
-use crate::kinematics::triangle;
-use core::{fmt::Debug, f64::consts::PI};
+pub use kinematics_core::motion::{
+    BeltDrive, Chained, DirectDrive, DirectDriveOffset, DoubleLinkage, GearDrive, JointConfigError,
+    Motion, MotionError, MotionKind, MotionStage,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// A arm joint with limits and functions for calculating pivot angle
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Joint {
     pub angle: f64,
+
+    /// The range of the angle, on the arm side of the motion like the angle itself
+    ///
+    /// The servo is set up with the ends of its range at the pivot angles these turn it to
     pub min: f64,
     pub max: f64,
-    pub motion: MotionField,
-}
 
-/// Type association for Motion trait that implements debug
-pub type MotionField = Box<dyn Motion>;
+    /// Fastest the joint may turn in degrees/s, infinite for no limit
+    pub max_velocity_dps: f64,
 
-/// A double linkage based motion system
-///
-/// The controlled angle is connected to the arm using two rods.
-/// One of the rods is tied to the controlled pivot point.
-/// the other is connected between the first rod and the arm
-pub struct DoubleLinkage {
-    /// Distance from the pivot to the connection point
-    pub connection_radial_offset: f64,
-
-    /// distance from the centerline of the arm to the connection point
-    pub connection_linear_offset: f64,
-
-    /// how far behind the controlled pivot is from the arm pivot
-    pub controll_pivot_horizontal_offset: f64,
-    /// how far above the controlled pivot is from the arm pivot
-    pub controll_pivot_vertical_offset: f64,
-
-    /// Length or rod connected to controller pivot
-    pub controller_pivot_rod_length: f64,
-
-    /// Length of rod connecting `controller_pivot_rod_length` to connection
-    pub connection_rod_length: f64,
-}
+    /// Turns all the way round, like a base on a slip ring
+    ///
+    /// The angle isn't clamped to `min..max`, that range is one full turn and angles outside
+    /// it wrap around into it
+    pub continuous: bool,
 
-/// A direct drive based motion system
-///
-/// The controlled angle is directly connected to the arm
-pub struct DirectDrive {}
+    /// The servo is mounted mirrored, a growing angle shrinks the pulse width
+    ///
+    /// Flips the pulse over the servo's range after the motion and clamping, so it works the
+    /// same with any motion
+    pub inverted: bool,
 
-/// A direct drive motions system with a offset
-///
-/// The controlled angle is directly connected to the arm but with a offset
-pub struct DirectDriveOffset {
-    pub offset: f64,
+    /// Degrees from either end of the range within which the joint is about to hit its
+    /// limit, 0 for none
+    ///
+    /// The robot warns about it and can slow the head down, see [`margin_scale`]
+    pub soft_margin_deg: f64,
+
+    pub motion: MotionKind,
 }
 
-/// A gear drive based motion system
+/// Slowest a joint turns towards its limit within its soft margin, as a share of the speed
+/// it's asked for, so it still gets there
+pub const MIN_MARGIN_SCALE: f64 = 0.1;
+
+/// A [`Joint`] as it's written in a file
 ///
-/// The controlled angle is connected to the arm and a gear ratio is used when calculating the
-/// controlled angle
-pub struct GearDrive {
-    pub gear_ratio: f64,
-}
+/// The joint turns as fast as it likes once built, the speed limit is configured on its own
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JointConfig {
+    pub angle: f64,
+    pub min: f64,
+    pub max: f64,
 
-/// Trait for join motion
-pub trait Motion {
-    fn get_pivot_angle(&self, target: f64) -> f64;
-}
+    #[serde(default)]
+    pub continuous: bool,
 
-impl DirectDrive {
-    pub fn new() -> DirectDrive {
-        DirectDrive {}
-    }
-}
+    #[serde(default)]
+    pub inverted: bool,
 
-impl DoubleLinkage {
-    pub fn new(
-        connection_radial_offset: f64,
-        connection_linear_offset: f64,
-        controll_pivot_horizontal_offset: f64,
-        controll_pivot_vertical_offset: f64,
-        controller_pivot_rod_length: f64,
-        connection_rod_length: f64,
-    ) -> Self {
-        Self {
-            connection_radial_offset,
-            connection_linear_offset,
-            controll_pivot_horizontal_offset,
-            controll_pivot_vertical_offset,
-            controller_pivot_rod_length,
-            connection_rod_length,
-        }
-    }
+    #[serde(default)]
+    pub soft_margin_deg: f64,
 
-    /// Calculate the angle and distance between the arm pivot and the arm connection point
-    ///
-    /// # Returns
-    /// (angle, distance)
-    /// angle in radians
-    ///
-    /// # Examples
-    /// ```rust
-    /// use robot::kinematics::DoubleLinkage;
-    /// let linkage = DoubleLinkage::new(1., 1., 1., 1., 1., 1.);
-    /// let (angle, distance) = linkage.connection_offset();
-    /// ```
-    pub fn connection_offset(&self) -> (f64, f64) {
-        let angle = (self.connection_radial_offset / self.connection_linear_offset).atan();
-        let distance =
-            (self.connection_radial_offset.powi(2) + self.connection_linear_offset.powi(2)).sqrt();
+    pub motion: MotionKind,
+}
 
-        (angle, distance)
-    }
+/// An angle past the end of a joint's range, see [`Joint::set_angle`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LimitHit {
+    /// The end of the range the angle was past
+    pub limit: f64,
 
-    /// Calculate the angle and distance between the arm pivot and the arm connection point
-    ///
-    /// # Returns
-    /// (angle, distance)
-    /// angle in radians
+    /// How far past the limit the angle was in degrees, negative below `min`
+    pub overshoot: f64,
+}
+
+impl Joint {
+    /// A joint at 0° that may turn from `min` to `max`, as fast as it likes, with any of the
+    /// motions in [`MotionKind`]
     ///
     /// # Examples
     /// ```rust
-    /// use robot::kinematics::DoubleLinkage;
+    /// use controller::kinematics::joints::{DoubleLinkage, Joint};
+    ///
+    /// let mut elbow = Joint::new(0., 180., DoubleLinkage::new(1., 10., 10., 1., 10., 20.));
+    /// elbow.max_velocity_dps = 90.;
+    /// elbow.step_towards(120., 0.5);
     ///
-    /// let linkage = DoubleLinkage::new(1., 1., 1., 1., 1., 1.);
-    /// let (angle, distance) = linkage.controller_offset();
+    /// assert_eq!(elbow.angle, 45.);
     /// ```
-    pub fn controller_offset(&self) -> (f64, f64) {
-        let angle =
-            (self.controll_pivot_horizontal_offset / self.controll_pivot_vertical_offset).atan();
-        let distance = (self.controll_pivot_horizontal_offset.powi(2)
-            + self.controll_pivot_vertical_offset.powi(2))
-        .sqrt();
-
-        (angle, distance)
-    }
-}
-
-impl Joint {
-    pub fn new(min: f64, max: f64, motion: MotionField) -> Self {
+    pub fn new(min: f64, max: f64, motion: impl Into<MotionKind>) -> Self {
         Self {
             angle: 0.,
             min,
             max,
-            motion,
+            max_velocity_dps: f64::INFINITY,
+            continuous: false,
+            inverted: false,
+            soft_margin_deg: 0.,
+            motion: motion.into(),
         }
     }
 }
 
-impl Motion for DirectDrive {
-    fn get_pivot_angle(&self, target: f64) -> f64 {
-        target
+impl Joint {
+    /// Turn towards `target` without going faster than [`Joint::max_velocity_dps`]
+    ///
+    /// # Arguments
+    /// * `target` - angle to turn to in degrees
+    /// * `delta` - seconds since the last step
+    ///
+    /// Continuous joints take the shorter way round
+    pub fn step_towards(&mut self, target: f64, delta: f64) {
+        let max_step = self.max_velocity_dps * delta;
+        self.angle += (self.nearest(target) - self.angle).clamp(-max_step, max_step);
+    }
+
+    /// The angle that points the same way as `angle` closest to the current one
+    ///
+    /// Only continuous joints can turn a full turn further, for the others this is `angle`
+    pub fn nearest(&self, angle: f64) -> f64 {
+        if !self.continuous {
+            return angle;
+        }
+
+        angle + ((self.angle - angle) / 360.).round() * 360.
+    }
+
+    /// Turn to `angle`, held at the end of the range if it's past it
+    ///
+    /// Continuous joints aren't held, their range wraps around
+    ///
+    /// # Errors
+    /// The limit the joint is held at and how far past it `angle` was
+    pub fn set_angle(&mut self, angle: f64) -> Result<(), LimitHit> {
+        let hit = self.limit_hit(angle);
+        self.angle = hit.map_or(angle, |hit| hit.limit);
+        hit.map_or(Ok(()), Err)
     }
-}
 
-impl Motion for DoubleLinkage {
-    fn get_pivot_angle(&self, target: f64) -> f64 {
-        let connection = self.connection_offset();
-        let controller = self.controller_offset();
+    /// Same as [`Joint::set_angle`], but the joint stays where it is if `angle` is past the
+    /// range
+    pub fn try_set_angle(&mut self, angle: f64) -> Result<(), LimitHit> {
+        match self.limit_hit(angle) {
+            Some(hit) => Err(hit),
+            None => {
+                self.angle = angle;
+                Ok(())
+            }
+        }
+    }
 
-        let inner_target_angle = PI - target - connection.0;
+    /// How far the joint is from the limit it turns towards at `rate`, `None` further than
+    /// [`Joint::soft_margin_deg`] from it, without a margin or for a continuous joint
+    pub fn soft_margin_left(&self, rate: f64) -> Option<f64> {
+        if self.continuous || self.soft_margin_deg <= 0. {
+            return None;
+        }
 
-        let connection_to_controller = triangle::length_from_two_lengths_and_angle(
-            inner_target_angle,
-            connection.1,
-            controller.1,
-        );
+        let left = match rate {
+            rate if rate > 0. => self.max - self.angle,
+            rate if rate < 0. => self.angle - self.min,
+            _ => return None,
+        };
+        (left < self.soft_margin_deg).then_some(left)
+    }
 
-        let angle = {
-            let x = triangle::a_from_lengths(
-                connection_to_controller,
-                self.controller_pivot_rod_length,
-                self.connection_rod_length,
-            );
+    /// True within [`Joint::soft_margin_deg`] of either end of the range
+    pub fn in_soft_margin(&self) -> bool {
+        [1., -1.]
+            .into_iter()
+            .any(|rate| self.soft_margin_left(rate).is_some())
+    }
 
-            let y = triangle::a_from_lengths(connection_to_controller, controller.1, connection.1);
+    /// What turning at `rate` is scaled by for the margin left towards the limit, see
+    /// [`margin_scale`]
+    pub fn soft_margin_scale(&self, rate: f64) -> f64 {
+        self.soft_margin_left(rate)
+            .map_or(1., |left| margin_scale(left, self.soft_margin_deg))
+    }
 
-            x + y
+    /// The limit `angle` is past, `None` within the range
+    fn limit_hit(&self, angle: f64) -> Option<LimitHit> {
+        let limit = match angle {
+            _ if self.continuous => return None,
+            angle if angle < self.min => self.min,
+            angle if angle > self.max => self.max,
+            _ => return None,
         };
 
-        angle.to_degrees()
+        Some(LimitHit {
+            limit,
+            overshoot: angle - limit,
+        })
     }
 }
 
-impl Motion for DirectDriveOffset {
-    fn get_pivot_angle(&self, target: f64) -> f64 {
-        target + self.offset
+impl Joint {
+    /// The joint as it's written in a file
+    pub fn config(&self) -> JointConfig {
+        JointConfig {
+            angle: self.angle,
+            min: self.min,
+            max: self.max,
+            continuous: self.continuous,
+            inverted: self.inverted,
+            soft_margin_deg: self.soft_margin_deg,
+            motion: self.motion,
+        }
     }
 }
 
-impl Motion for GearDrive {
-    fn get_pivot_angle(&self, target: f64) -> f64 {
-        target * self.gear_ratio
+/// Share of its speed a joint keeps `left` degrees from its limit with a soft margin of
+/// `margin` degrees
+///
+/// Falls off in proportion to the margin left, from the full speed where the margin starts so
+/// it doesn't slow down all at once, to [`MIN_MARGIN_SCALE`] at the limit. Always 1 without a
+/// margin
+pub fn margin_scale(left: f64, margin: f64) -> f64 {
+    if margin <= 0. {
+        return 1.;
+    }
+
+    (left / margin).clamp(MIN_MARGIN_SCALE, 1.)
+}
+
+impl fmt::Display for LimitHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1}° past its limit at {:.1}°",
+            self.overshoot.abs(),
+            self.limit
+        )
     }
 }
 
-impl Debug for MotionField {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("MotionField")
-            .field("motion", &self.get_pivot_angle(0.))
-            .finish()
+impl std::error::Error for LimitHit {}
+
+impl From<JointConfig> for Joint {
+    fn from(config: JointConfig) -> Self {
+        Self {
+            angle: config.angle,
+            continuous: config.continuous,
+            inverted: config.inverted,
+            soft_margin_deg: config.soft_margin_deg,
+            ..Joint::new(config.min, config.max, config.motion)
+        }
     }
 }
 
@@ -204,7 +257,11 @@ impl Default for Joint {
             angle: 0.,
             min: 0.,
             max: 180.,
-            motion: Box::new(DirectDrive::new()),
+            max_velocity_dps: f64::INFINITY,
+            continuous: false,
+            inverted: false,
+            soft_margin_deg: 0.,
+            motion: DirectDrive::new().into(),
         }
     }
 }
@@ -0,0 +1,112 @@
+//! Joint angles the way an operator measures them on the arm with a protractor
+//!
+//! The kinematics keep the base at 90° pointing straight ahead, the shoulder measured from
+//! straight up and the elbow as the angle between the arm segments. Operator angles are
+//!
+//! * base - 0° straight ahead along x, growing towards y
+//! * shoulder - 0° with the upper arm horizontal, growing as it's raised
+//! * elbow - 0° with the arm straight, growing as it's bent
+//! * claw - as it is
+
+use crate::robot::arm::JointAngles;
+
+/// The operator angle of the joint with this name, `None` for an unknown joint
+pub fn joint_to_operator(joint: &str, angle: f64) -> Option<f64> {
+    match joint {
+        "base" => Some(angle - 90.),
+        "shoulder" => Some(90. - angle),
+        "elbow" => Some(180. - angle),
+        "claw" => Some(angle),
+        _ => None,
+    }
+}
+
+/// The angle the kinematics use for an operator angle, the reverse of [`joint_to_operator`]
+pub fn joint_from_operator(joint: &str, angle: f64) -> Option<f64> {
+    match joint {
+        "base" => Some(angle + 90.),
+        // both mirror around a fixed angle, so they are their own inverse
+        "shoulder" | "elbow" | "claw" => joint_to_operator(joint, angle),
+        _ => None,
+    }
+}
+
+/// Every joint of `angles` in operator angles
+pub fn to_operator(angles: JointAngles) -> JointAngles {
+    map(angles, joint_to_operator)
+}
+
+/// Every joint of operator `angles` in the angles the kinematics use
+pub fn from_operator(angles: JointAngles) -> JointAngles {
+    map(angles, joint_from_operator)
+}
+
+fn map(angles: JointAngles, convert: fn(&str, f64) -> Option<f64>) -> JointAngles {
+    let convert = |joint, angle| convert(joint, angle).unwrap_or(angle);
+    JointAngles {
+        base: convert("base", angles.base),
+        shoulder: convert("shoulder", angles.shoulder),
+        elbow: convert("elbow", angles.elbow),
+        claw: convert("claw", angles.claw),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kinematics::position::CordinateVec;
+    use kinematics_core::assert_approx_eq;
+
+    fn angles(base: f64, shoulder: f64, elbow: f64, claw: f64) -> JointAngles {
+        JointAngles {
+            base,
+            shoulder,
+            elbow,
+            claw,
+        }
+    }
+
+    #[test]
+    fn pairs() {
+        // the neutral pose points straight ahead with the upper arm up and the elbow square
+        assert_eq!(
+            to_operator(angles(90., 0., 90., 45.)),
+            angles(0., 90., 90., 45.)
+        );
+        // reaching out flat along y with the arm straight
+        assert_eq!(
+            to_operator(angles(180., 90., 180., 0.)),
+            angles(90., 0., 0., 0.)
+        );
+        // the upper arm hanging down and folded all the way
+        assert_eq!(
+            to_operator(angles(0., 180., 0., 90.)),
+            angles(-90., -90., 180., 90.)
+        );
+
+        for internal in [angles(90., 0., 90., 45.), angles(12.5, 133., 27., 3.)] {
+            assert_eq!(from_operator(to_operator(internal)), internal);
+        }
+        assert_eq!(joint_to_operator("wrist", 10.), None);
+        assert_eq!(joint_from_operator("wrist", 10.), None);
+    }
+
+    #[test]
+    fn measured() {
+        // straight ahead with the upper arm raised 30° and bent 90° at the elbow
+        let internal = from_operator(angles(0., 30., 90., 0.));
+        let head = CordinateVec::forward_kinematics(
+            internal.base,
+            internal.shoulder,
+            internal.elbow,
+            100.,
+            100.,
+        );
+
+        // the elbow is 100 out at 30° up, the lower arm bends up from there to 120°
+        let (sin, cos) = 30f64.to_radians().sin_cos();
+        let (bent_sin, bent_cos) = 120f64.to_radians().sin_cos();
+        let expected = CordinateVec::new(100. * (cos + bent_cos), 0., 100. * (sin + bent_sin));
+        assert_approx_eq!(head, expected);
+    }
+}
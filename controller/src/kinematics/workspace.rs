@@ -0,0 +1,168 @@
+//! Everywhere the arm can put the head, sampled by sweeping the joints through their ranges
+//!
+//! For plotting what the arm reaches with its joint ranges and linkages, written out as CSV or
+//! as a PLY point cloud
+
+use std::io::{self, Write};
+
+use crate::{
+    kinematics::{
+        joints::{Joint, Motion},
+        position::CordinateVec,
+    },
+    robot::arm::Arm,
+};
+
+/// Header of the CSV [`write_csv`] writes
+pub const CSV_HEADER: &str = "x,y,z";
+
+/// Where the head is for every combination of base, shoulder and elbow angles `resolution`
+/// degrees apart within their ranges
+///
+/// Angles the joint's motion can't turn to, where a linkage doesn't close or the pivot angle
+/// is out of the joint's range, are left out. So are poses forward kinematics can't place,
+/// like equally long arms folded onto the shoulder. The ends of each range are always sampled
+///
+/// # Arguments
+/// * `arm` - The joints to sweep
+/// * `upper_arm` - The length of the upper Arm
+/// * `lower_arm` - The length of the lower Arm
+/// * `resolution` - Degrees between the angles sampled of each joint
+pub fn sample_workspace(
+    arm: &Arm,
+    upper_arm: f64,
+    lower_arm: f64,
+    resolution: f64,
+) -> Vec<CordinateVec> {
+    let bases = angles(&arm.base, resolution);
+    let shoulders = angles(&arm.shoulder, resolution);
+    let elbows = angles(&arm.elbow, resolution);
+
+    let mut points = Vec::with_capacity(bases.len() * shoulders.len() * elbows.len());
+    for &base in &bases {
+        for &shoulder in &shoulders {
+            for &elbow in &elbows {
+                let point =
+                    CordinateVec::forward_kinematics(base, shoulder, elbow, upper_arm, lower_arm);
+                if point.is_finite() {
+                    points.push(point);
+                }
+            }
+        }
+    }
+
+    points
+}
+
+/// The angles of `joint` `resolution` degrees apart its motion can turn to
+fn angles(joint: &Joint, resolution: f64) -> Vec<f64> {
+    let span = joint.max - joint.min;
+    let steps = if resolution > 0. && span > 0. {
+        (span / resolution).ceil() as usize
+    } else {
+        0
+    };
+
+    (0..=steps)
+        .map(|step| (joint.min + step as f64 * resolution).min(joint.max))
+        .filter(|&angle| joint.motion.try_pivot_angle(angle).is_ok())
+        .collect()
+}
+
+/// Write the points as CSV, one `x,y,z` row per point under [`CSV_HEADER`]
+pub fn write_csv(points: &[CordinateVec], mut out: impl Write) -> io::Result<()> {
+    writeln!(out, "{CSV_HEADER}")?;
+    for point in points {
+        writeln!(out, "{},{},{}", point.x, point.y, point.z)?;
+    }
+
+    Ok(())
+}
+
+/// Write the points as an ASCII PLY point cloud, which most 3d viewers open
+pub fn write_ply(points: &[CordinateVec], mut out: impl Write) -> io::Result<()> {
+    writeln!(out, "ply")?;
+    writeln!(out, "format ascii 1.0")?;
+    writeln!(out, "element vertex {}", points.len())?;
+    writeln!(out, "property double x")?;
+    writeln!(out, "property double y")?;
+    writeln!(out, "property double z")?;
+    writeln!(out, "end_header")?;
+    for point in points {
+        writeln!(out, "{} {} {}", point.x, point.y, point.z)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        kinematics::joints::{DirectDriveOffset, DoubleLinkage},
+        robot::Robot,
+    };
+
+    #[test]
+    fn sample_workspace() {
+        let robo = Robot::default();
+        let points = super::sample_workspace(&robo.arm, robo.upper_arm, robo.lower_arm, 10.);
+
+        // every combination of 19 angles each, but for the arm folded onto the shoulder
+        assert_eq!(robo.upper_arm, robo.lower_arm);
+        assert_eq!(points.len(), 19 * 19 * 18);
+
+        // stretched out right on the edge of the reach, give or take rounding
+        for point in &points {
+            assert!(
+                robo.is_reachable(*point * (1. - 1e-12)),
+                "{point:?} can't be reached"
+            );
+        }
+
+        // stretched out it reaches as far as the arms are long
+        let furthest = points.iter().map(|point| point.dst()).fold(0., f64::max);
+        let reach = robo.upper_arm + robo.lower_arm;
+        assert!((furthest - reach).abs() < 1e-9, "{furthest} of {reach}");
+    }
+
+    #[test]
+    fn linkage_range() {
+        let mut arm = Arm::default();
+        arm.elbow.motion = DoubleLinkage::new(20., 20., 20., 20., 30., 40.).into();
+        let direct = angles(&Arm::default().elbow, 1.);
+        let linked = angles(&arm.elbow, 1.);
+
+        // the linkage only turns the elbow through part of its range
+        assert_eq!(direct.len(), 181);
+        assert!(
+            !linked.is_empty() && linked.len() < direct.len(),
+            "{linked:?}"
+        );
+
+        let points = super::sample_workspace(&arm, 100., 100., 10.);
+        assert!(points.len() < 19 * 19 * 18);
+
+        // the range is in arm angles, an offset doesn't take any of it away
+        arm.base.motion = DirectDriveOffset { offset: 90. }.into();
+        assert_eq!(angles(&arm.base, 1.).len(), 181);
+    }
+
+    #[test]
+    fn export() {
+        let points = [
+            CordinateVec::new(1., 2., 3.),
+            CordinateVec::new(-4., 5.5, 0.),
+        ];
+
+        let mut csv = Vec::new();
+        write_csv(&points, &mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "x,y,z\n1,2,3\n-4,5.5,0\n");
+
+        let mut ply = Vec::new();
+        write_ply(&points, &mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+        assert!(ply.starts_with("ply\nformat ascii 1.0\nelement vertex 2\n"));
+        assert!(ply.ends_with("end_header\n1 2 3\n-4 5.5 0\n"));
+    }
+}
@@ -0,0 +1,27 @@
+//! Controller for the arm: the kinematics, the robot and its link to the firmware
+//!
+//! The binary only builds the [`robot::Robot`] from the config and runs the loop, everything
+//! else is here so other crates can use it without the gamepad loop
+//!
+//! There is no unsafe code, the messages to the firmware are written byte by byte
+#![deny(unsafe_code)]
+pub mod ack;
+pub mod calibration;
+pub mod clock;
+pub mod command;
+pub mod communication;
+pub mod config;
+pub mod control;
+pub mod display;
+pub mod input;
+pub mod kinematics;
+pub mod logging;
+pub mod program;
+pub mod protocol;
+pub mod recording;
+pub mod ring_buffer;
+pub mod robot;
+pub mod session;
+pub mod sim;
+pub mod stats;
+pub mod viewer;
@@ -1,5 +1,9 @@
 #![allow(dead_code)]
 
+use std::{collections::VecDeque, sync::Mutex};
+
+use crate::session;
+
 /// Logging level all levels include the ones before
 /// 0 = no logs
 /// 1 = errors
@@ -9,42 +13,69 @@
 /// 5 = verbose
 pub const LOG_LEVEL: u8 = 3;
 
-pub fn error(message: &'static str) {
+/// Most logged lines kept for [`history`], older ones are dropped first
+pub const MAX_HISTORY: usize = 4096;
+
+static HISTORY: Mutex<VecDeque<(f64, String)>> = Mutex::new(VecDeque::new());
+
+pub fn error(message: &str) {
     if LOG_LEVEL < 1 {
         return;
     }
 
-    println!("ERRO: {}", message);
+    log("ERRO", message);
 }
 
-pub fn warn(message: &'static str) {
+pub fn warn(message: &str) {
     if LOG_LEVEL < 2 {
         return;
     }
 
-    println!("WARN: {}", message);
+    log("WARN", message);
 }
 
-pub fn info(message: &'static str) {
+pub fn info(message: &str) {
     if LOG_LEVEL < 3 {
         return;
     }
 
-    println!("INFO: {}", message);
+    log("INFO", message);
 }
 
-pub fn debug(message: &'static str) {
+pub fn debug(message: &str) {
     if LOG_LEVEL < 4 {
         return;
     }
 
-    println!("DEBG: {}", message);
+    log("DEBG", message);
 }
 
-pub fn verbose(message: &'static str) {
+pub fn verbose(message: &str) {
     if LOG_LEVEL < 5 {
         return;
     }
 
-    println!("VERB: {}", message);
+    log("VERB", message);
+}
+
+/// The lines logged so far with the session time they were logged at, oldest first, see
+/// [`session::clock`]
+pub fn history() -> Vec<(f64, String)> {
+    HISTORY
+        .lock()
+        .map(|history| history.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn log(level: &str, message: &str) {
+    let line = format!("{level}: {message}");
+    println!("{line}");
+
+    let time = session::clock().now();
+    if let Ok(mut history) = HISTORY.lock() {
+        if history.len() >= MAX_HISTORY {
+            history.pop_front();
+        }
+        history.push_back((time, line));
+    }
 }
@@ -1,76 +1,382 @@
-use crate::{
-    arm::Arm,
-    kinematics::{
-        joints::{DirectDrive, DirectDriveOffset, DoubleLinkage, Joint},
-        position::CordinateVec,
-    },
+use controller::{
+    command, config, display, input, logging, program, robot, session, sim, viewer,
+};
+use controller::{
+    robot::arm::Arm,
+    protocol::Frame,
+    clock::{Clock, PacedClock},
+    kinematics::joints::{DirectDrive, DirectDriveOffset, DoubleLinkage, Joint},
 };
 use std::{
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
     thread::sleep,
     time::{Duration, Instant},
 };
 
 use gilrs::Gilrs;
+use input::{GamepadSource, InputSource, Inputs, KeyboardSource, ScriptedSource};
 
-use crate::robot::*;
+use controller::robot::{shutdown::ShutdownReason, *};
 
-mod communication;
-mod kinematics;
-mod logging;
-mod robot;
+/// How often the state file is written
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(10);
 
 fn main() {
-    let mut robot = Robot {
-        acceleration: 100.,
-        max_velocity: CordinateVec::new(10., 10., 10.),
-        upper_arm: 100.,
-        lower_arm: 100.,
-        arm: Arm {
-            base: Joint::new(0., 180., Box::new(DirectDriveOffset { offset: 90. })),
-            claw: Joint::new(0., 180., Box::new(DirectDrive::new())),
-            shoulder: Joint::new(
-                0.,
-                180.,
-                Box::new(DoubleLinkage::new(1., 10., 10., 1., 10., 20.)),
-            ),
-            elbow: Joint::new(
-                0.,
-                180.,
-                Box::new(DoubleLinkage::new(1., 10., 10., 1., 10., 20.)),
-            ),
-        },
-        position: CordinateVec::new(0., 0., 0.),
-        velocity: CordinateVec::new(0., 0., 0.),
-        target_position: Some(CordinateVec::new(50., 50., 50.)),
-        target_velocity: CordinateVec::new(0., 0., 0.),
-        claw_open: false,
-        connection: communication::Connection::new("/dev/ttyACM0", 115_200),
+    // every log of the session is timed from here
+    session::clock();
+    let config = config::Args::parse(std::env::args().skip(1)).and_then(|args| {
+        let config = config::Config::load(&args)?;
+        if args.dump_config {
+            println!("{}", config.dump());
+            std::process::exit(0);
+        }
+        Ok((config, args))
+    });
+    let (config, args) = match config {
+        Ok(config) => config,
+        Err(err) => {
+            logging::error(&format!("Could not load config: {err}"));
+            std::process::exit(1);
+        }
     };
+    logging::info(&format!("config {:016x}", config.hash()));
+
+    let mut robot = config.robot(arm(&config));
 
-    let mut gilrs = Gilrs::new().expect("Could not setup gilrs");
+    let state_file = &config.state_file;
+    if let Err(err) = robot.odometer.load(state_file) {
+        logging::warn(&format!("Could not load {state_file}: {err}"));
+    }
+    let journal_file = &config.journal_file;
+    if !journal_file.is_empty() {
+        match robot::journal::Journal::open(journal_file) {
+            Ok(journal) => {
+                if journal.skipped > 0 {
+                    logging::warn(&format!(
+                        "Skipped {} unreadable lines of {journal_file}",
+                        journal.skipped
+                    ));
+                }
+                robot.journal = journal;
+            }
+            Err(err) => logging::warn(&format!("Could not load {journal_file}: {err}")),
+        }
+    }
+    let envelope_file = &config.envelope_file;
+    if let Err(err) = robot.envelope.load(envelope_file) {
+        logging::warn(&format!("Could not load {envelope_file}: {err}"));
+    }
+    if let Err(err) = robot.set_envelope_mode(config.envelope_mode) {
+        logging::warn(&format!("Envelope not {}: {err}", config.envelope_mode));
+    }
+    if let Err(err) = robot.set_boundary_policy(config.boundary.policy) {
+        logging::warn(&format!("Boundary not {}: {err}", config.boundary.policy));
+    }
+    if let Some(path) = args.check {
+        match program::Program::load(&path) {
+            Ok(program) => {
+                let report = robot.dry_run(&program);
+                report.log();
+                std::process::exit(if report.violations.is_empty() { 0 } else { 1 });
+            }
+            Err(err) => {
+                logging::error(&format!("Could not load {path}: {err}"));
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = args.view {
+        std::process::exit(if viewer::run(&path, config.hash()) { 0 } else { 1 });
+    }
+    let audit = robot.audit();
+    audit.log();
+    if !config.audit_report.is_empty() {
+        if let Err(err) = audit.save(&config.audit_report) {
+            logging::warn(&format!("Could not save {}: {err}", config.audit_report));
+        }
+    }
+    if !config.program.is_empty() {
+        match program::Program::load(&config.program) {
+            Ok(program) => robot.program = program,
+            Err(err) => {
+                logging::error(&format!("Could not load {}: {err}", config.program));
+                std::process::exit(1);
+            }
+        }
+        if config.run_program {
+            if let Err(err) = robot.run_program() {
+                logging::error(&format!("Could not run {}: {err}", config.program));
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut last_save = Instant::now();
+
+    // stands in for the arduino, the servos start where the soft start ramp begins
+    let mut simulator = config
+        .sim
+        .enabled
+        .then(|| sim::Simulator::new(&config.sim, robot.arm.to_servos()));
+
+    // a second arm next to this one follows it, with its own simulator
+    let mut pair = config.coordination.enabled.then(|| {
+        let follower = config.follower(arm(&config));
+        let simulator = config
+            .sim
+            .enabled
+            .then(|| sim::Simulator::new(&config.sim, follower.arm.to_servos()));
+        (
+            robot::coordinator::Coordinator::new(config.coordination.clone()),
+            follower,
+            simulator,
+        )
+    });
+
+    let gilrs = Gilrs::new().expect("Could not setup gilrs");
+    let mut gamepad = GamepadSource::new(gilrs, config.axis_mapping.clone());
+    gamepad.calibration = config.stick_calibration;
+    if config.calibrate_sticks {
+        logging::info("Calibrating the sticks, don't touch them");
+        match gamepad.calibrate(input::StickCalibration::DURATION) {
+            Ok(calibration) => match &args.config_file {
+                Some(path) => {
+                    if let Err(err) = config::write_setting(path, "stick_calibration", &calibration) {
+                        logging::warn(&format!("Could not save the stick calibration: {err}"));
+                    }
+                }
+                None => logging::warn("Stick calibration not saved, there is no config file"),
+            },
+            Err(err) => logging::warn(&format!("Could not calibrate the sticks: {err}")),
+        }
+    }
+
+    // a script plays ahead of the keys, which go ahead of the gamepad
+    let mut sources: Vec<Box<dyn InputSource>> = Vec::new();
+    if !config.input_script.is_empty() {
+        match ScriptedSource::load(&config.input_script) {
+            Ok(script) => sources.push(Box::new(script)),
+            Err(err) => logging::warn(&format!("Could not load {}: {err}", config.input_script)),
+        }
+    }
+    let mut commands = None;
+    if config.keyboard {
+        let (sender, receiver) = mpsc::channel();
+        match KeyboardSource::spawn(sender) {
+            Ok(keyboard) => {
+                sources.push(Box::new(keyboard));
+                commands = Some(receiver);
+            }
+            Err(err) => logging::warn(&format!("Could not read keys from the terminal: {err}")),
+        }
+    }
+    let commands = commands.unwrap_or_else(command::spawn_stdin);
+    sources.push(Box::new(gamepad));
+    let mut inputs = Inputs::new(sources);
+    if !config.telemetry.is_empty() {
+        match robot::telemetry::TelemetryWriter::create(&config.telemetry) {
+            Ok(writer) => robot.telemetry = Some(writer),
+            Err(err) => logging::warn(&format!("Could not record to {}: {err}", config.telemetry)),
+        }
+    }
     // open serial connection
     robot.connection.connect().expect("Could not connect");
+    if let Some((_, follower, _)) = &mut pair {
+        follower
+            .connection
+            .connect()
+            .expect("Could not connect to the second arm");
+    }
+    logging::info(&format!("session {}", session::SessionHeader::new(&robot)));
 
     sleep(Duration::from_secs(2));
 
-    let mut prev = Instant::now();
+    // the first Ctrl-C shuts the arm down like `quit`, a second one exits right away
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler = interrupted.clone();
+    let keyboard = config.keyboard;
+    if let Err(err) = ctrlc::set_handler(move || {
+        if handler.swap(true, Ordering::SeqCst) {
+            if keyboard {
+                let _ = KeyboardSource::restore_terminal();
+            }
+            process::exit(130);
+        }
+    }) {
+        logging::warn(&format!("Could not handle Ctrl-C: {err}"));
+    }
+
+    let mut clock = PacedClock::new(Duration::from_millis(10));
+    let mut screen = String::new();
 
-    loop {
-        let delta: Duration = dbg!(Instant::now() - prev);
-        prev = Instant::now();
+    'control: loop {
+        let delta = dbg!(clock.tick());
 
         clearscreen::clear().unwrap();
 
-        if let Some(event) = gilrs.next_event() {
-            let gamepad = gilrs.gamepad(event.id);
-            robot.update_gamepad(&gamepad);
+        robot.apply_input(&inputs.poll(Instant::now()));
+        if interrupted.load(Ordering::SeqCst) {
+            robot.begin_shutdown(ShutdownReason::Quit);
+            if let Some((_, follower, _)) = &mut pair {
+                follower.begin_shutdown(ShutdownReason::Quit);
+            }
+        }
+
+        while let Ok(line) = commands.try_recv() {
+            match command::Command::parse(&line) {
+                Ok(Some(command)) => {
+                    let result = match &mut pair {
+                        Some((coordinator, follower, _))
+                            if matches!(command, command::Command::Together(_)) =>
+                        {
+                            command.execute_with(&mut robot, follower, coordinator)
+                        }
+                        _ => command.run(
+                            &mut robot,
+                            &mut clock,
+                            &interrupted,
+                            |robot, report, delta| {
+                                step_simulator(&mut simulator, robot, report, delta)
+                            },
+                        ),
+                    };
+                    if let Err(err) = result {
+                        logging::warn(&format!("{line}: {err}"));
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => logging::warn(&format!("{line}: {err}")),
+            }
+        }
+
+        if let Some((coordinator, follower, _)) = &mut pair {
+            coordinator.coordinate(&mut robot, follower);
+        }
+        match robot.update(delta) {
+            Ok(report) => {
+                for event in robot.take_events() {
+                    logging::info(&event.event.to_string());
+                }
+                step_simulator(&mut simulator, &mut robot, &report, delta);
+            }
+            Err(err) => logging::warn(&format!("Update failed: {err}")),
+        }
+        if let Some((_, follower, simulator)) = &mut pair {
+            match follower.update(delta) {
+                Ok(report) => {
+                    for event in follower.take_events() {
+                        logging::info(&format!("second arm: {}", event.event));
+                    }
+                    step_simulator(simulator, follower, &report, delta);
+                }
+                Err(err) => logging::warn(&format!("Update of the second arm failed: {err}")),
+            }
+        }
+        // the session ends once the arm rests, holds or is detached
+        if robot
+            .shutdown
+            .is_some_and(|shutdown| shutdown.reason == ShutdownReason::Quit && shutdown.done)
+        {
+            break 'control;
         }
 
-        let _ = robot.update(delta.as_secs_f64());
-        println!("pos: {:?}", robot.position);
-        println!("trg: {:?}", robot.target_position);
-        println!("vel: {:?}", robot.velocity);
-        println!("tve: {:?}", robot.target_velocity);
+        if let Err(err) = robot.connection.check_baud() {
+            logging::error(&format!("Could not reconnect: {err}"));
+        }
+        if last_save.elapsed() >= STATE_SAVE_INTERVAL {
+            if let Err(err) = robot.odometer.save(state_file) {
+                logging::warn(&format!("Could not save {state_file}: {err}"));
+            }
+            if robot.envelope.changed {
+                if let Err(err) = robot.envelope.save(envelope_file) {
+                    logging::warn(&format!("Could not save {envelope_file}: {err}"));
+                }
+            }
+            last_save = Instant::now();
+        }
+
+        display::render_into(&robot.state(), &mut screen);
+        print!("{screen}");
         println!("ang: {:#?}", robot.arm);
     }
+
+    if let Err(err) = robot.odometer.save(state_file) {
+        logging::warn(&format!("Could not save {state_file}: {err}"));
+    }
+    if robot.envelope.changed {
+        if let Err(err) = robot.envelope.save(envelope_file) {
+            logging::warn(&format!("Could not save {envelope_file}: {err}"));
+        }
+    }
+    if !config.bundle_on_exit.is_empty() {
+        logging::info(&format!("session ended, bundling into {}", config.bundle_on_exit));
+        if let Err(err) = robot.write_bundle(&config.bundle_on_exit) {
+            logging::warn(&format!("Could not bundle into {}: {err}", config.bundle_on_exit));
+        }
+    }
+    if let Some(mut stream) = robot.segment_stream.take() {
+        if let Err(err) = stream.flush() {
+            logging::warn(&format!("Could not flush the segment stream: {err}"));
+        }
+    }
+    if let Some(mut writer) = robot.telemetry.take() {
+        if let Err(err) = writer.flush() {
+            logging::warn(&format!("Could not flush the telemetry: {err}"));
+        }
+    }
+    if config.keyboard {
+        if let Err(err) = KeyboardSource::restore_terminal() {
+            logging::warn(&format!("Could not restore the terminal: {err}"));
+        }
+    }
+}
+
+/// The joints of the arm as it's built
+fn arm(config: &config::Config) -> Arm {
+    let mut arm = Arm {
+        base: if config.continuous_base {
+            Joint {
+                continuous: true,
+                ..Joint::new(0., 360., DirectDriveOffset { offset: 90. })
+            }
+        } else {
+            Joint::new(0., 180., DirectDriveOffset { offset: 90. })
+        },
+        claw: Joint::new(0., 180., DirectDrive::new()),
+        // the rods of the linkages only reach the arm up to about 113°
+        shoulder: Joint::new(0., 110., DoubleLinkage::new(1., 10., 10., 1., 10., 20.)),
+        elbow: Joint::new(0., 110., DoubleLinkage::new(1., 10., 10., 1., 10., 20.)),
+        // 180 is in line with the lower arm, the wrist bends both ways from there
+        wrist: (config.wrist_length > 0.).then(|| Joint::new(0., 360., DirectDrive::new())),
+    };
+    arm.invert(config.inverted_joints);
+    arm.set_soft_margins(config.soft_margins);
+    arm
+}
+
+/// Let the simulator follow an update of the robot and report back like the arduino would
+fn step_simulator(
+    simulator: &mut Option<sim::Simulator>,
+    robot: &mut Robot,
+    report: &UpdateReport,
+    delta: f64,
+) {
+    let Some(simulator) = simulator else {
+        return;
+    };
+
+    if report.transmitted {
+        simulator.command(robot.arm.to_servos());
+    }
+    simulator.step(delta);
+
+    let feedback = Frame::Feedback(simulator.feedback()).encode_with(
+        robot.connection.checksum,
+        robot.connection.servo_encoding,
+        0,
+    );
+    robot.connection.receive(&feedback);
 }
@@ -0,0 +1,514 @@
+//! Programs, named poses and the waypoints to go through, written by hand or generated by
+//! another tool
+//!
+//! A program is a json document:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "metadata": { "name": "pick", "source": "vision.py" },
+//!   "poses": {
+//!     "home": { "x": 0, "y": 150, "z": 100 },
+//!     "bin": { "x": 80, "y": 120, "z": 40 }
+//!   },
+//!   "grips": {
+//!     "bottle": { "percent": 60, "speed": 40 }
+//!   },
+//!   "waypoints": [
+//!     { "position": { "x": 20, "y": 160, "z": 30 }, "speed": 50, "claw": "close", "dwell": 0.5 },
+//!     { "pose": "bin", "claw": "open" },
+//!     { "pose": "home" }
+//!   ]
+//! }
+//! ```
+//!
+//! * `version` - required, documents newer than [`VERSION`] are rejected
+//! * `metadata` - optional `name`, `description` and `source`, kept but not used
+//! * `poses` - head positions by name, in the operator's frame and real world coordinates
+//!   once calibrated like `goto`
+//! * `grips` - claw settings by object, `percent` from 0 open to 100 closed and the `speed` in
+//!   percent/s the claw turns there at, see [`crate::robot::grip`]
+//! * `waypoints` - gone through in order, each moves to either a named `pose` or a `position`
+//!   * `speed` - optional cruise speed in units/s, above 0
+//!   * `dwell` - optional seconds to wait once there, at least 0
+//!   * `claw` - optional `"open"`, `"close"` or `{ "angle": <degrees> }`, set once there
+//!     before dwelling
+//!
+//! Unknown fields are rejected everywhere so a typo doesn't silently do nothing
+
+use std::{collections::BTreeMap, fmt, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::kinematics::{joints::Joint, position::CordinateVec};
+
+/// Newest program schema version this controller understands
+pub const VERSION: u32 = 1;
+
+/// File extensions of G-code
+const GCODE: [&str; 4] = ["gcode", "g", "nc", "ngc"];
+
+/// A pose bank and the waypoints to go through, see the [module docs](self) for the schema
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Program {
+    pub version: u32,
+
+    #[serde(default)]
+    pub metadata: Metadata,
+
+    /// Head positions by name
+    #[serde(default)]
+    pub poses: BTreeMap<String, CordinateVec>,
+
+    /// Claw settings by name
+    #[serde(default)]
+    pub grips: BTreeMap<String, Grip>,
+
+    /// Gone through in order by [`crate::robot::Robot::run_program`]
+    #[serde(default)]
+    pub waypoints: Vec<Waypoint>,
+}
+
+/// Where a program came from, for people reading it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// The tool or person that wrote the program
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// A step of a program, exactly one of `pose` and `position` is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Waypoint {
+    /// Name of a pose in [`Program::poses`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pose: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<CordinateVec>,
+
+    /// Cruise speed in units/s, `None` for the robot's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f64>,
+
+    /// Seconds to wait once there
+    pub dwell: f64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claw: Option<ClawAction>,
+}
+
+/// What to do with the claw at a waypoint
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum ClawAction {
+    /// Turn the claw to its max angle
+    Open,
+
+    /// Turn the claw to its min angle
+    Close,
+
+    /// Turn the claw to an angle in degrees
+    Angle(f64),
+}
+
+/// How far to close the claw for one kind of object and how fast
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Grip {
+    /// 0 for open at the claw's max angle to 100 for closed at its min
+    pub percent: f64,
+
+    /// Percent/s the claw turns at
+    pub speed: f64,
+}
+
+/// How far a robot got through a program
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ProgramRun {
+    /// Index of the waypoint being moved to or dwelt at
+    pub step: usize,
+
+    /// Seconds left to dwell, `None` while still moving
+    pub dwelling: Option<f64>,
+
+    /// Cruise speed from before the run, put back once it ends
+    pub cruise_speed: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum ProgramError {
+    Io(io::Error),
+
+    /// Not json or not a program, the message names the field and where it is
+    Parse(serde_json::Error),
+
+    /// A schema version this controller doesn't know
+    Version(u32),
+
+    /// A waypoint names a pose the program doesn't have
+    UnknownPose {
+        step: usize,
+        name: String,
+    },
+
+    /// A waypoint has both or neither of a pose and a position
+    Target(usize),
+
+    /// A number is outside of what it may be
+    OutOfRange {
+        at: String,
+        value: f64,
+        expected: &'static str,
+    },
+
+    /// A waypoint is out of reach of the arm
+    Unreachable {
+        step: usize,
+        position: CordinateVec,
+    },
+
+    /// Running a program without waypoints
+    Empty,
+
+    /// A file that isn't a json program, like G-code
+    Format(String),
+}
+
+impl Program {
+    /// Read a program from a json file, see [`Program::parse`]
+    ///
+    /// G-code files are turned away by their extension, there is no G-code interpreter
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProgramError> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if let Some(extension) = extension.filter(|extension| GCODE.contains(extension)) {
+            return Err(ProgramError::Format(extension.to_string()));
+        }
+
+        let data = fs::read_to_string(path).map_err(ProgramError::Io)?;
+        Self::parse(&data)
+    }
+
+    /// Read a program from json and validate it
+    pub fn parse(data: &str) -> Result<Self, ProgramError> {
+        // the version first, a newer document may well have fields this one doesn't know
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+        let header: Header = serde_json::from_str(data).map_err(ProgramError::Parse)?;
+        if header.version == 0 || header.version > VERSION {
+            return Err(ProgramError::Version(header.version));
+        }
+
+        let program: Self = serde_json::from_str(data).map_err(ProgramError::Parse)?;
+        program.validate()?;
+        Ok(program)
+    }
+
+    /// Write the program to a json file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProgramError> {
+        let data = serde_json::to_string_pretty(self).map_err(ProgramError::Parse)?;
+        fs::write(path, data).map_err(ProgramError::Io)
+    }
+
+    /// Check every value the schema can't
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        for (name, position) in &self.poses {
+            finite(&format!("pose `{name}`"), *position)?;
+        }
+        for (name, grip) in &self.grips {
+            if !(0. ..=100.).contains(&grip.percent) {
+                return Err(ProgramError::OutOfRange {
+                    at: format!("grip `{name}` percent"),
+                    value: grip.percent,
+                    expected: "0 to 100",
+                });
+            }
+            if !(grip.speed > 0. && grip.speed.is_finite()) {
+                return Err(ProgramError::OutOfRange {
+                    at: format!("grip `{name}` speed"),
+                    value: grip.speed,
+                    expected: "above 0",
+                });
+            }
+        }
+
+        for (step, waypoint) in self.waypoints.iter().enumerate() {
+            match (&waypoint.pose, waypoint.position) {
+                (Some(name), None) if !self.poses.contains_key(name) => {
+                    return Err(ProgramError::UnknownPose {
+                        step,
+                        name: name.clone(),
+                    })
+                }
+                (Some(_), None) => {}
+                (None, Some(position)) => finite(&format!("waypoint {step} position"), position)?,
+                _ => return Err(ProgramError::Target(step)),
+            }
+
+            if let Some(speed) = waypoint.speed {
+                if !(speed > 0. && speed.is_finite()) {
+                    return Err(ProgramError::OutOfRange {
+                        at: format!("waypoint {step} speed"),
+                        value: speed,
+                        expected: "above 0",
+                    });
+                }
+            }
+            if !(waypoint.dwell >= 0. && waypoint.dwell.is_finite()) {
+                return Err(ProgramError::OutOfRange {
+                    at: format!("waypoint {step} dwell"),
+                    value: waypoint.dwell,
+                    expected: "at least 0",
+                });
+            }
+            if let Some(ClawAction::Angle(angle)) = waypoint.claw {
+                if !angle.is_finite() {
+                    return Err(ProgramError::OutOfRange {
+                        at: format!("waypoint {step} claw angle"),
+                        value: angle,
+                        expected: "finite",
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Position waypoint `step` goes to, in the operator's frame
+    pub fn target(&self, step: usize) -> Option<CordinateVec> {
+        let waypoint = self.waypoints.get(step)?;
+        match &waypoint.pose {
+            Some(name) => self.poses.get(name).copied(),
+            None => waypoint.position,
+        }
+    }
+}
+
+/// Every coordinate of a position has to be finite
+fn finite(at: &str, position: CordinateVec) -> Result<(), ProgramError> {
+    for (axis, value) in [("x", position.x), ("y", position.y), ("z", position.z)] {
+        if !value.is_finite() {
+            return Err(ProgramError::OutOfRange {
+                at: format!("{at} {axis}"),
+                value,
+                expected: "finite",
+            });
+        }
+    }
+
+    Ok(())
+}
+
+impl ClawAction {
+    /// Angle to turn `claw` to
+    pub fn angle(self, claw: &Joint) -> f64 {
+        match self {
+            ClawAction::Open => claw.max,
+            ClawAction::Close => claw.min,
+            ClawAction::Angle(angle) => angle,
+        }
+    }
+}
+
+impl ProgramRun {
+    pub fn new(cruise_speed: Option<f64>) -> Self {
+        Self {
+            step: 0,
+            dwelling: None,
+            cruise_speed,
+        }
+    }
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            metadata: Metadata::default(),
+            poses: BTreeMap::new(),
+            grips: BTreeMap::new(),
+            waypoints: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramError::Io(err) => write!(f, "{err}"),
+            ProgramError::Parse(err) => write!(f, "invalid program: {err}"),
+            ProgramError::Version(version) if *version > VERSION => write!(
+                f,
+                "program version {version} is newer than this controller supports ({VERSION})"
+            ),
+            ProgramError::Version(version) => write!(f, "unknown program version {version}"),
+            ProgramError::UnknownPose { step, name } => {
+                write!(f, "waypoint {step} goes to unknown pose `{name}`")
+            }
+            ProgramError::Target(step) => {
+                write!(f, "waypoint {step} needs either a pose or a position")
+            }
+            ProgramError::OutOfRange {
+                at,
+                value,
+                expected,
+            } => write!(f, "{at} is {value}, expected {expected}"),
+            ProgramError::Unreachable { step, position } => write!(
+                f,
+                "waypoint {step} at ({:.1}, {:.1}, {:.1}) is out of reach",
+                position.x, position.y, position.z
+            ),
+            ProgramError::Empty => write!(f, "program has no waypoints"),
+            ProgramError::Format(extension) => {
+                write!(f, "`.{extension}` files aren't supported, only json programs")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PICK: &str = r#"{
+        "version": 1,
+        "metadata": { "name": "pick", "source": "vision.py" },
+        "poses": {
+            "home": { "x": 0, "y": 150, "z": 100 },
+            "bin": { "x": 80, "y": 120, "z": 40 }
+        },
+        "grips": {
+            "bottle": { "percent": 60, "speed": 40 }
+        },
+        "waypoints": [
+            { "position": { "x": 20, "y": 160, "z": 30 }, "speed": 50, "claw": "close", "dwell": 0.5 },
+            { "pose": "bin", "claw": "open" },
+            { "pose": "home", "claw": { "angle": 30 } }
+        ]
+    }"#;
+
+    fn rejected(json: &str) -> String {
+        Program::parse(json).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parse() {
+        let program = Program::parse(PICK).unwrap();
+        assert_eq!(program.metadata.name.as_deref(), Some("pick"));
+        assert_eq!(program.poses.len(), 2);
+        assert_eq!(
+            program.grips["bottle"],
+            Grip {
+                percent: 60.,
+                speed: 40.
+            }
+        );
+        assert_eq!(
+            program.waypoints[0],
+            Waypoint {
+                position: Some(CordinateVec::new(20., 160., 30.)),
+                speed: Some(50.),
+                dwell: 0.5,
+                claw: Some(ClawAction::Close),
+                ..Default::default()
+            }
+        );
+        assert_eq!(program.waypoints[2].claw, Some(ClawAction::Angle(30.)));
+
+        assert_eq!(program.target(0), Some(CordinateVec::new(20., 160., 30.)));
+        assert_eq!(program.target(1), Some(CordinateVec::new(80., 120., 40.)));
+        assert_eq!(program.target(3), None);
+
+        // everything but the version is optional
+        assert_eq!(
+            Program::parse(r#"{"version": 1}"#).unwrap(),
+            Program::default()
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let program = Program::parse(PICK).unwrap();
+        let json = serde_json::to_string_pretty(&program).unwrap();
+        assert_eq!(Program::parse(&json).unwrap(), program);
+
+        let path = std::env::temp_dir().join(format!("rac-program-{}.json", std::process::id()));
+        program.save(&path).unwrap();
+        let loaded = Program::load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.unwrap(), program);
+    }
+
+    #[test]
+    fn future_version() {
+        // newer documents are rejected before their unknown fields are
+        let error = rejected(r#"{"version": 2, "waypoints": [], "loops": 3}"#);
+        assert_eq!(
+            error,
+            "program version 2 is newer than this controller supports (1)"
+        );
+        assert!(matches!(
+            Program::parse(r#"{"version": 0}"#),
+            Err(ProgramError::Version(0))
+        ));
+        assert!(rejected(r#"{"waypoints": []}"#).contains("missing field `version`"));
+    }
+
+    #[test]
+    fn invalid() {
+        let error = rejected(r#"{"version": 1, "waypoints": [{"pose": "a", "speeed": 1}]}"#);
+        assert!(error.contains("unknown field `speeed`"), "{error}");
+        assert!(error.contains("line 1"), "{error}");
+
+        let error = rejected(r#"{"version": 1, "waypoints": [{"pose": "a"}]}"#);
+        assert_eq!(error, "waypoint 0 goes to unknown pose `a`");
+
+        let error = rejected(
+            r#"{"version": 1, "waypoints": [{"position": {"x": 1, "y": 2, "z": 3}, "speed": -5}]}"#,
+        );
+        assert_eq!(error, "waypoint 0 speed is -5, expected above 0");
+
+        let error = rejected(
+            r#"{"version": 1, "poses": {"a": {"x": 1, "y": 2, "z": 3}},
+                "waypoints": [{"pose": "a"}, {"pose": "a", "dwell": -1}]}"#,
+        );
+        assert_eq!(error, "waypoint 1 dwell is -1, expected at least 0");
+
+        let both = r#"{"version": 1, "poses": {"a": {"x": 1, "y": 2, "z": 3}},
+            "waypoints": [{"pose": "a", "position": {"x": 1, "y": 2, "z": 3}}]}"#;
+        assert_eq!(
+            rejected(both),
+            "waypoint 0 needs either a pose or a position"
+        );
+        assert_eq!(
+            rejected(r#"{"version": 1, "waypoints": [{"dwell": 1}]}"#),
+            "waypoint 0 needs either a pose or a position"
+        );
+
+        let error = rejected(r#"{"version": 1, "grips": {"cup": {"percent": 120, "speed": 5}}}"#);
+        assert_eq!(error, "grip `cup` percent is 120, expected 0 to 100");
+
+        let error = rejected(r#"{"version": 1, "waypoints": [{"pose": "a", "claw": "shut"}]}"#);
+        assert!(error.contains("unknown variant `shut`"), "{error}");
+
+        // turned away before reading, G-code isn't json
+        let error = Program::load("/no/such/dir/part.gcode").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`.gcode` files aren't supported, only json programs"
+        );
+    }
+}
@@ -0,0 +1,1386 @@
+use std::{collections::VecDeque, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// Indicates a new frame
+pub const PREFIX: u8 = b'\r';
+
+/// Bytes in a frame before the payload (version, kind, sequence and length), excluding the prefix
+pub const HEADER_LEN: usize = 4;
+
+/// Longest frame excluding the prefix, the header, a full payload and the longest checksum
+pub const MAX_FRAME_LEN: usize = HEADER_LEN + u8::MAX as usize + 2;
+
+/// Pulse width range in microseconds the angle based [`ServoEncoding`]s map 0 to 180 degrees
+/// onto, the same range the joints are mapped onto
+pub const MIN_PULSE: u16 = 250;
+pub const MAX_PULSE: u16 = 2400;
+
+/// Frame type bytes
+pub mod kind {
+    /// Servo feedback reported by the arduino
+    pub const FEEDBACK: u8 = 0x01;
+
+    /// Handshake, sent by the controller after connecting and echoed by the arduino
+    pub const HELLO: u8 = 0x02;
+
+    /// Ask the arduino for a [`super::FirmwareStatus`]
+    pub const STATUS_REQUEST: u8 = 0x03;
+
+    /// Answer to a status request
+    pub const STATUS: u8 = 0x04;
+
+    /// Acknowledges a frame by its sequence number
+    pub const ACK: u8 = 0x05;
+
+    /// Stop driving the servos so they go limp
+    pub const DETACH: u8 = 0x06;
+
+    /// Drive the servos again after a [`DETACH`]
+    pub const ATTACH: u8 = 0x07;
+}
+
+/// Capability bits exchanged in the handshake
+pub mod capability {
+    /// Supports [`super::Checksum::Xor`], every firmware does
+    pub const XOR: u8 = 0b0000_0001;
+
+    /// Supports [`super::Checksum::Crc16`]
+    pub const CRC16: u8 = 0b0000_0010;
+
+    /// Wants servo values as [`super::ServoEncoding::DegreesU8`]
+    pub const DEGREES_U8: u8 = 0b0000_0100;
+
+    /// Wants servo values as [`super::ServoEncoding::CentidegreesU16`]
+    pub const CENTIDEGREES_U16: u8 = 0b0000_1000;
+}
+
+/// A decoded frame
+///
+/// On the wire a frame looks like this
+/// `PREFIX | version | kind | seq | len | payload[len] | checksum`
+/// where the version byte selects the checksum algorithm, see [`Checksum`], and seq is a
+/// wrapping counter of frames sent by each side. The checksum covers everything after the prefix.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Feedback(Feedback),
+
+    /// Handshake carrying the supported [`capability`] bits of the sender
+    Hello { capabilities: u8 },
+
+    StatusRequest,
+    Status(FirmwareStatus),
+
+    /// Acknowledges the frame sent with sequence number `seq`
+    Ack { seq: u8 },
+
+    Detach,
+    Attach,
+
+    /// A frame with a valid checksum but a type we don't know how to decode
+    Unknown { kind: u8, payload: Vec<u8> },
+}
+
+/// Checksum algorithm used to verify frames
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    /// Xor of all bytes, protocol version 1
+    Xor,
+
+    /// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF), protocol version 2
+    Crc16,
+}
+
+/// How the checksum algorithm is chosen
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChecksumMode {
+    /// Use the best algorithm both sides support according to the handshake
+    Auto,
+
+    /// Always use the given algorithm, nothing selects it yet
+    #[allow(dead_code)]
+    Fixed(Checksum),
+}
+
+/// How servo values are represented on the wire, both in the servo positions we send and in
+/// the [`Feedback`] we receive
+///
+/// Inside the controller servo values are always pulse widths in microseconds, the angle based
+/// encodings map [`MIN_PULSE`]..=[`MAX_PULSE`] onto 0 to 180 degrees
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServoEncoding {
+    /// Pulse widths in microseconds, little endian u16, what this repo's firmware expects
+    #[default]
+    MicrosecondsU16,
+
+    /// Whole degrees in a single byte, what the arduino `Servo::write` takes
+    DegreesU8,
+
+    /// Hundredths of a degree, little endian u16
+    CentidegreesU16,
+}
+
+/// Actual servo state as reported by the arduino
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Feedback {
+    /// Pulse widths in microseconds, in the same order as [`crate::robot::Servos`], whatever
+    /// [`ServoEncoding`] they were sent with
+    pub pulses: [u16; 4],
+
+    /// Servo supply voltage in millivolts
+    pub millivolts: u16,
+
+    /// Bit flags, see [`Feedback::MOVING`] and [`Feedback::STALLED`]
+    pub status: u8,
+}
+
+/// What the firmware reports about itself when asked
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FirmwareStatus {
+    /// Time since the arduino booted
+    pub uptime_ms: u32,
+
+    /// Servo supply voltage in millivolts
+    pub millivolts: u16,
+
+    /// How many times per second the firmware main loop runs
+    pub loop_hz: u16,
+
+    /// Sequence number of the last frame the arduino received from us
+    pub last_seq: u8,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// Not enough bytes for the frame or payload
+    Truncated { expected: usize, actual: usize },
+
+    /// More bytes than the longest payload
+    Oversized { max: usize, actual: usize },
+
+    /// The checksum in the frame does not match the contents
+    BadChecksum { expected: u16, actual: u16 },
+
+    /// The frame was sent with a different protocol version than the decoder expects
+    VersionMismatch { expected: u8, actual: u8 },
+
+    /// The protocol version byte isn't one we know
+    UnknownVersion(u8),
+}
+
+impl Checksum {
+    /// Protocol version byte for frames using this checksum
+    pub fn version(self) -> u8 {
+        match self {
+            Checksum::Xor => 1,
+            Checksum::Crc16 => 2,
+        }
+    }
+
+    pub fn from_version(version: u8) -> Option<Self> {
+        match version {
+            1 => Some(Checksum::Xor),
+            2 => Some(Checksum::Crc16),
+            _ => None,
+        }
+    }
+
+    /// Capability bit advertising support for this checksum
+    pub fn capability(self) -> u8 {
+        match self {
+            Checksum::Xor => capability::XOR,
+            Checksum::Crc16 => capability::CRC16,
+        }
+    }
+
+    /// Number of checksum bytes at the end of a frame
+    pub fn size(self) -> usize {
+        match self {
+            Checksum::Xor => 1,
+            Checksum::Crc16 => 2,
+        }
+    }
+
+    pub fn compute(self, data: &[u8]) -> u16 {
+        match self {
+            Checksum::Xor => xor(data) as u16,
+            Checksum::Crc16 => crc16(data),
+        }
+    }
+
+    /// Append the checksum of `data` to `data`, little endian
+    fn append(self, data: &mut Vec<u8>, from: usize) {
+        let sum = self.compute(&data[from..]);
+        data.extend_from_slice(&sum.to_le_bytes()[..self.size()]);
+    }
+
+    /// Read a checksum written by [`Checksum::append`]
+    fn read(self, data: &[u8]) -> u16 {
+        match self {
+            Checksum::Xor => data[0] as u16,
+            Checksum::Crc16 => u16::from_le_bytes([data[0], data[1]]),
+        }
+    }
+}
+
+impl ChecksumMode {
+    /// Pick the checksum to use after a handshake
+    ///
+    /// # Arguments
+    /// * `capabilities` - capability bits reported by the arduino
+    pub fn negotiate(self, capabilities: u8) -> Checksum {
+        match self {
+            ChecksumMode::Fixed(checksum) => checksum,
+            ChecksumMode::Auto if capabilities & capability::CRC16 != 0 => Checksum::Crc16,
+            ChecksumMode::Auto => Checksum::Xor,
+        }
+    }
+
+    /// Checksum used for the handshake itself
+    ///
+    /// When negotiating the handshake uses xor since every firmware supports it
+    pub fn handshake(self) -> Checksum {
+        match self {
+            ChecksumMode::Fixed(checksum) => checksum,
+            ChecksumMode::Auto => Checksum::Xor,
+        }
+    }
+
+    /// Capability bits to advertise in our own handshake
+    pub fn capabilities(self) -> u8 {
+        match self {
+            ChecksumMode::Fixed(checksum) => checksum.capability(),
+            ChecksumMode::Auto => capability::XOR | capability::CRC16,
+        }
+    }
+}
+
+impl ServoEncoding {
+    /// Number of bytes per servo
+    pub fn size(self) -> usize {
+        match self {
+            ServoEncoding::DegreesU8 => 1,
+            ServoEncoding::MicrosecondsU16 | ServoEncoding::CentidegreesU16 => 2,
+        }
+    }
+
+    /// Capability bit asking for this encoding, microseconds predate the bits and have none
+    pub fn capability(self) -> u8 {
+        match self {
+            ServoEncoding::MicrosecondsU16 => 0,
+            ServoEncoding::DegreesU8 => capability::DEGREES_U8,
+            ServoEncoding::CentidegreesU16 => capability::CENTIDEGREES_U16,
+        }
+    }
+
+    /// The encoding asked for in a handshake, `None` if it doesn't ask for one
+    ///
+    /// # Arguments
+    /// * `capabilities` - capability bits reported by the arduino
+    pub fn from_capabilities(capabilities: u8) -> Option<Self> {
+        if capabilities & capability::CENTIDEGREES_U16 != 0 {
+            Some(ServoEncoding::CentidegreesU16)
+        } else if capabilities & capability::DEGREES_U8 != 0 {
+            Some(ServoEncoding::DegreesU8)
+        } else {
+            None
+        }
+    }
+
+    /// Append a pulse width in microseconds to `data`
+    ///
+    /// Pulses outside of [`MIN_PULSE`]..=[`MAX_PULSE`] are clamped to 0 and 180 degrees by the
+    /// angle based encodings
+    pub fn encode(self, pulse: u16, data: &mut Vec<u8>) {
+        let degrees = || {
+            let range = (MAX_PULSE - MIN_PULSE) as f64;
+            ((pulse as f64 - MIN_PULSE as f64) / range * 180.).clamp(0., 180.)
+        };
+
+        match self {
+            ServoEncoding::MicrosecondsU16 => data.extend_from_slice(&pulse.to_le_bytes()),
+            ServoEncoding::DegreesU8 => data.push(degrees().round() as u8),
+            ServoEncoding::CentidegreesU16 => {
+                data.extend_from_slice(&((degrees() * 100.).round() as u16).to_le_bytes())
+            }
+        }
+    }
+
+    /// Read a pulse width in microseconds written by [`ServoEncoding::encode`]
+    ///
+    /// # Arguments
+    /// * `data` - starts with the servo value, anything after [`ServoEncoding::size`] bytes is
+    ///   ignored
+    ///
+    /// # Returns
+    /// `Err(ProtocolError::Truncated)` if there are fewer bytes than that
+    pub fn decode(self, data: &[u8]) -> Result<u16, ProtocolError> {
+        let pulse = |degrees: f64| {
+            let range = (MAX_PULSE - MIN_PULSE) as f64;
+            (MIN_PULSE as f64 + degrees / 180. * range).round() as u16
+        };
+        let value = match *data {
+            [low, high, ..] if self.size() == 2 => u16::from_le_bytes([low, high]),
+            [byte, ..] if self.size() == 1 => byte as u16,
+            _ => {
+                return Err(ProtocolError::Truncated {
+                    expected: self.size(),
+                    actual: data.len(),
+                })
+            }
+        };
+
+        Ok(match self {
+            ServoEncoding::MicrosecondsU16 => value,
+            ServoEncoding::DegreesU8 => pulse(value as f64),
+            ServoEncoding::CentidegreesU16 => pulse(value as f64 / 100.),
+        })
+    }
+}
+
+impl Feedback {
+    /// At least one servo has not reached its commanded position
+    pub const MOVING: u8 = 0b0000_0001;
+
+    /// At least one servo is not moving even though it should be
+    pub const STALLED: u8 = 0b0000_0010;
+
+    #[allow(dead_code)]
+    pub fn moving(&self) -> bool {
+        self.status & Self::MOVING != 0
+    }
+
+    pub fn stalled(&self) -> bool {
+        self.status & Self::STALLED != 0
+    }
+
+    /// Length of an encoded feedback payload, the servo values followed by the voltage and
+    /// the status
+    pub fn len(encoding: ServoEncoding) -> usize {
+        4 * encoding.size() + 3
+    }
+
+    /// Encode into a payload, all values are little endian
+    #[cfg(test)]
+    pub fn encode(&self, encoding: ServoEncoding) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(Self::len(encoding));
+        self.encode_into(encoding, &mut payload);
+        payload
+    }
+
+    /// Append the payload of [`Feedback::encode`] to `data`
+    pub fn encode_into(&self, encoding: ServoEncoding, data: &mut Vec<u8>) {
+        for pulse in self.pulses {
+            encoding.encode(pulse, data);
+        }
+        data.extend_from_slice(&self.millivolts.to_le_bytes());
+        data.push(self.status);
+    }
+
+    /// Decode a payload created by [`Feedback::encode`]
+    ///
+    /// # Returns
+    /// `Err(ProtocolError::Truncated)` if the payload has the wrong length
+    pub fn decode(payload: &[u8], encoding: ServoEncoding) -> Result<Self, ProtocolError> {
+        if payload.len() != Self::len(encoding) {
+            return Err(ProtocolError::Truncated {
+                expected: Self::len(encoding),
+                actual: payload.len(),
+            });
+        }
+
+        let size = encoding.size();
+        let pulse = |servo: usize| encoding.decode(&payload[servo * size..]);
+        let rest = &payload[4 * size..];
+
+        Ok(Self {
+            pulses: [pulse(0)?, pulse(1)?, pulse(2)?, pulse(3)?],
+            millivolts: u16::from_le_bytes([rest[0], rest[1]]),
+            status: rest[2],
+        })
+    }
+}
+
+impl FirmwareStatus {
+    /// Length of an encoded status payload
+    pub const LEN: usize = 9;
+
+    /// Encode into a payload, all values are little endian
+    #[cfg(test)]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(Self::LEN);
+        self.encode_into(&mut payload);
+        payload
+    }
+
+    /// Append the payload of [`FirmwareStatus::encode`] to `data`
+    pub fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.uptime_ms.to_le_bytes());
+        data.extend_from_slice(&self.millivolts.to_le_bytes());
+        data.extend_from_slice(&self.loop_hz.to_le_bytes());
+        data.push(self.last_seq);
+    }
+
+    /// Decode a payload created by [`FirmwareStatus::encode`]
+    ///
+    /// # Returns
+    /// `Err(ProtocolError::Truncated)` if the payload has the wrong length
+    pub fn decode(payload: &[u8]) -> Result<Self, ProtocolError> {
+        if payload.len() != Self::LEN {
+            return Err(ProtocolError::Truncated {
+                expected: Self::LEN,
+                actual: payload.len(),
+            });
+        }
+
+        Ok(Self {
+            uptime_ms: u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]),
+            millivolts: u16::from_le_bytes([payload[4], payload[5]]),
+            loop_hz: u16::from_le_bytes([payload[6], payload[7]]),
+            last_seq: payload[8],
+        })
+    }
+}
+
+impl Frame {
+    /// The type byte used for this frame on the wire
+    pub fn kind(&self) -> u8 {
+        match self {
+            Frame::Feedback(_) => kind::FEEDBACK,
+            Frame::Hello { .. } => kind::HELLO,
+            Frame::StatusRequest => kind::STATUS_REQUEST,
+            Frame::Status(_) => kind::STATUS,
+            Frame::Ack { .. } => kind::ACK,
+            Frame::Detach => kind::DETACH,
+            Frame::Attach => kind::ATTACH,
+            Frame::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// Same as [`Frame::encode_with`] with servo values in microseconds
+    #[cfg(test)]
+    pub fn encode(&self, checksum: Checksum, seq: u8) -> Vec<u8> {
+        self.encode_with(checksum, ServoEncoding::MicrosecondsU16, seq)
+    }
+
+    /// Encode the frame, including the prefix
+    ///
+    /// # Arguments
+    /// * `checksum` - algorithm to protect the frame with, also selects the version byte
+    /// * `encoding` - how servo values in the payload are represented
+    /// * `seq` - sequence number of the frame
+    pub fn encode_with(&self, checksum: Checksum, encoding: ServoEncoding, seq: u8) -> Vec<u8> {
+        let mut data = Vec::with_capacity(MAX_FRAME_LEN + 1);
+        self.encode_into(checksum, encoding, seq, &mut data);
+        data
+    }
+
+    /// Append the frame of [`Frame::encode_with`] to `data`
+    ///
+    /// Doesn't allocate as long as `data` has room for it, so a buffer can be reused for every
+    /// frame
+    pub fn encode_into(
+        &self,
+        checksum: Checksum,
+        encoding: ServoEncoding,
+        seq: u8,
+        data: &mut Vec<u8>,
+    ) {
+        let start = data.len();
+        data.push(PREFIX);
+        data.push(checksum.version());
+        data.push(self.kind());
+        data.push(seq);
+        // the length goes in once the payload is written
+        data.push(0);
+        match self {
+            Frame::Feedback(feedback) => feedback.encode_into(encoding, data),
+            Frame::Hello { capabilities } => data.push(*capabilities),
+            Frame::StatusRequest => {}
+            Frame::Status(status) => status.encode_into(data),
+            Frame::Ack { seq } => data.push(*seq),
+            Frame::Detach | Frame::Attach => {}
+            Frame::Unknown { payload, .. } => data.extend_from_slice(payload),
+        }
+        data[start + HEADER_LEN] = (data.len() - start - 1 - HEADER_LEN) as u8;
+        checksum.append(data, start + 1);
+    }
+
+    /// Total length of a frame excluding the prefix
+    ///
+    /// # Arguments
+    /// * `header` - at least the first [`HEADER_LEN`] bytes of the frame
+    ///
+    /// # Returns
+    /// `None` if the header is incomplete or the version is unknown
+    pub fn total_len(header: &[u8]) -> Option<usize> {
+        if header.len() < HEADER_LEN {
+            return None;
+        }
+
+        let checksum = Checksum::from_version(header[0])?;
+        Some(HEADER_LEN + header[3] as usize + checksum.size())
+    }
+
+    /// Same as [`Frame::decode_with`] with servo values in microseconds
+    #[cfg(test)]
+    pub fn decode(data: &[u8], checksum: Checksum) -> Result<Self, ProtocolError> {
+        Self::decode_with(data, checksum, ServoEncoding::MicrosecondsU16)
+    }
+
+    /// Decode a frame without the prefix
+    ///
+    /// # Arguments
+    /// * `data` - version, kind, sequence, length, payload and checksum
+    /// * `checksum` - algorithm the frame is expected to use
+    /// * `encoding` - how servo values in the payload are represented
+    ///
+    /// # Returns
+    /// The decoded frame, unknown kinds are returned as [`Frame::Unknown`]
+    pub fn decode_with(
+        data: &[u8],
+        checksum: Checksum,
+        encoding: ServoEncoding,
+    ) -> Result<Self, ProtocolError> {
+        if data.len() < HEADER_LEN + checksum.size() {
+            return Err(ProtocolError::Truncated {
+                expected: HEADER_LEN + checksum.size(),
+                actual: data.len(),
+            });
+        }
+
+        if data[0] != checksum.version() {
+            return Err(match Checksum::from_version(data[0]) {
+                Some(_) => ProtocolError::VersionMismatch {
+                    expected: checksum.version(),
+                    actual: data[0],
+                },
+                None => ProtocolError::UnknownVersion(data[0]),
+            });
+        }
+
+        let len = data[3] as usize;
+        let expected = HEADER_LEN + len + checksum.size();
+        if data.len() != expected {
+            return Err(ProtocolError::Truncated {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        let (body, sum) = data.split_at(HEADER_LEN + len);
+        let expected = checksum.compute(body);
+        let actual = checksum.read(sum);
+        if actual != expected {
+            return Err(ProtocolError::BadChecksum { expected, actual });
+        }
+
+        let payload = &body[HEADER_LEN..];
+        match data[1] {
+            kind::FEEDBACK => Ok(Frame::Feedback(Feedback::decode(payload, encoding)?)),
+            kind::HELLO => match payload {
+                [capabilities] => Ok(Frame::Hello {
+                    capabilities: *capabilities,
+                }),
+                _ => Err(ProtocolError::Truncated {
+                    expected: 1,
+                    actual: payload.len(),
+                }),
+            },
+            kind::STATUS_REQUEST => Ok(Frame::StatusRequest),
+            kind::STATUS => Ok(Frame::Status(FirmwareStatus::decode(payload)?)),
+            kind::ACK => match payload {
+                [seq] => Ok(Frame::Ack { seq: *seq }),
+                _ => Err(ProtocolError::Truncated {
+                    expected: 1,
+                    actual: payload.len(),
+                }),
+            },
+            kind::DETACH => Ok(Frame::Detach),
+            kind::ATTACH => Ok(Frame::Attach),
+            kind => Ok(Frame::Unknown {
+                kind,
+                payload: payload.to_vec(),
+            }),
+        }
+    }
+}
+
+/// Framing state machine
+///
+/// Bytes are fed one at a time, everything before a prefix is ignored and once a prefix is
+/// seen bytes are collected until the length in the header is satisfied
+///
+/// A prefix in the noise or a frame cut short takes the bytes after it as its own, possibly
+/// swallowing the start of a real frame. So when a frame turns out not to be one, because
+/// its version is unknown or it fails its checksum, the bytes after its prefix are searched
+/// again from the next prefix. A frame that only uses the other checksum is kept as it is.
+/// At most one frame and what follows it is ever buffered
+#[derive(Debug)]
+pub struct FrameReader {
+    /// Frame currently being received, excluding the prefix
+    buf: Vec<u8>,
+
+    /// True if a prefix has been seen and `buf` is collecting a frame
+    in_frame: bool,
+
+    /// Bytes of a rejected frame that are searched again, and the bytes pushed after them
+    backlog: VecDeque<u8>,
+
+    /// Type byte of the last complete frame, decoded or not, `None` if its version was unknown
+    pub last_kind: Option<u8>,
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::with_capacity(MAX_FRAME_LEN),
+            in_frame: false,
+            backlog: VecDeque::with_capacity(MAX_FRAME_LEN + 1),
+            last_kind: None,
+        }
+    }
+
+    /// Feed a single byte
+    ///
+    /// # Arguments
+    /// * `byte` - next byte from the serial port
+    /// * `checksum` - algorithm frames are expected to use
+    /// * `encoding` - how servo values in the payloads are represented
+    ///
+    /// # Returns
+    /// `None` if the frame isn't complete yet
+    /// `Some(Result)` once a complete frame has been received, decoded or not. Searching a
+    /// rejected frame again can complete more than one, take the rest with
+    /// [`FrameReader::next`]
+    pub fn push(
+        &mut self,
+        byte: u8,
+        checksum: Checksum,
+        encoding: ServoEncoding,
+    ) -> Option<Result<Frame, ProtocolError>> {
+        self.backlog.push_back(byte);
+        self.next(checksum, encoding)
+    }
+
+    /// The next frame completed by bytes that were already pushed
+    pub fn next(
+        &mut self,
+        checksum: Checksum,
+        encoding: ServoEncoding,
+    ) -> Option<Result<Frame, ProtocolError>> {
+        while let Some(byte) = self.backlog.pop_front() {
+            if let Some(result) = self.step(byte, checksum, encoding) {
+                return Some(result);
+            }
+        }
+
+        None
+    }
+
+    /// Bytes held on to, a partial frame and what is left to search again
+    #[cfg(test)]
+    pub fn buffered(&self) -> usize {
+        self.buf.len() + self.backlog.len()
+    }
+
+    fn step(
+        &mut self,
+        byte: u8,
+        checksum: Checksum,
+        encoding: ServoEncoding,
+    ) -> Option<Result<Frame, ProtocolError>> {
+        if !self.in_frame {
+            self.in_frame = byte == PREFIX;
+            self.buf.clear();
+            return None;
+        }
+
+        self.buf.push(byte);
+
+        // an unknown version means we can't know the length, give up on this frame
+        if Checksum::from_version(self.buf[0]).is_none() {
+            let version = self.buf[0];
+            self.rescan();
+            self.in_frame = false;
+            self.buf.clear();
+            self.last_kind = None;
+            return Some(Err(ProtocolError::UnknownVersion(version)));
+        }
+
+        // the header is needed before we know how long the frame is
+        match Frame::total_len(&self.buf) {
+            Some(len) if self.buf.len() >= len => {}
+            _ => return None,
+        }
+
+        let frame = Frame::decode_with(&self.buf, checksum, encoding);
+        self.last_kind = Some(self.buf[1]);
+        if !self.checksum_valid() {
+            self.rescan();
+        }
+        self.in_frame = false;
+        self.buf.clear();
+        Some(frame)
+    }
+
+    /// A complete frame in `buf` passes the checksum of its own version, whichever is in use
+    fn checksum_valid(&self) -> bool {
+        Checksum::from_version(self.buf[0]).is_some_and(|checksum| {
+            let (body, sum) = self.buf.split_at(self.buf.len() - checksum.size());
+            checksum.compute(body) == checksum.read(sum)
+        })
+    }
+
+    /// Search the bytes of a rejected frame again from the first prefix after its own
+    fn rescan(&mut self) {
+        if let Some(start) = self.buf.iter().position(|&byte| byte == PREFIX) {
+            for &byte in self.buf[start..].iter().rev() {
+                self.backlog.push_front(byte);
+            }
+        }
+    }
+
+    /// Drop any partial frame and wait for the next prefix
+    pub fn reset(&mut self) {
+        self.in_frame = false;
+        self.buf.clear();
+        self.backlog.clear();
+    }
+}
+
+impl Default for FrameReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Xor of all the bytes
+pub fn xor(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum ^ byte)
+}
+
+/// CRC-16/CCITT-FALSE of all the bytes
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Truncated { expected, actual } => {
+                write!(f, "truncated frame, expected {expected} bytes got {actual}")
+            }
+            ProtocolError::Oversized { max, actual } => {
+                write!(f, "oversized payload, expected at most {max} bytes got {actual}")
+            }
+            ProtocolError::BadChecksum { expected, actual } => {
+                write!(f, "bad checksum, expected {expected:#06x} got {actual:#06x}")
+            }
+            ProtocolError::VersionMismatch { expected, actual } => {
+                write!(f, "expected protocol version {expected} got {actual}")
+            }
+            ProtocolError::UnknownVersion(version) => {
+                write!(f, "unknown protocol version {version}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BOTH: [Checksum; 2] = [Checksum::Xor, Checksum::Crc16];
+
+    fn feedback() -> Feedback {
+        Feedback {
+            pulses: [1500, 250, 2400, 1000],
+            millivolts: 5012,
+            status: Feedback::MOVING,
+        }
+    }
+
+    #[test]
+    fn known_vectors() {
+        assert_eq!(xor(b"123456789"), 0x31);
+        assert_eq!(xor(&[]), 0x00);
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+        assert_eq!(crc16(b"A"), 0xB915);
+    }
+
+    #[test]
+    fn feedback_round_trip() {
+        for checksum in BOTH {
+            let frame = Frame::Feedback(feedback());
+            let data = frame.encode(checksum, 0);
+
+            assert_eq!(data[0], PREFIX);
+            assert_eq!(data[1], checksum.version());
+            assert_eq!(
+                data.len(),
+                1 + HEADER_LEN + Feedback::len(ServoEncoding::MicrosecondsU16) + checksum.size()
+            );
+            assert_eq!(Frame::total_len(&data[1..]), Some(data.len() - 1));
+            assert_eq!(Frame::decode(&data[1..], checksum), Ok(frame));
+        }
+    }
+
+    #[test]
+    fn encode_into() {
+        // appends behind whatever is already in the buffer, checksummed on its own
+        let frames = [
+            Frame::Feedback(feedback()),
+            Frame::StatusRequest,
+            Frame::Ack { seq: 7 },
+        ];
+        for checksum in BOTH {
+            let mut data = vec![1, 2, 3];
+            for frame in &frames {
+                data.truncate(3);
+                frame.encode_into(checksum, ServoEncoding::MicrosecondsU16, 9, &mut data);
+                assert_eq!(data[..4], [1, 2, 3, PREFIX]);
+                assert_eq!(Frame::total_len(&data[4..]), Some(data.len() - 4));
+                assert_eq!(Frame::decode(&data[4..], checksum), Ok(frame.clone()));
+                assert_eq!(data[6], 9);
+            }
+        }
+    }
+
+    #[test]
+    fn servo_encodings() {
+        let encode = |encoding: ServoEncoding, pulse: u16| {
+            let mut data = Vec::new();
+            encoding.encode(pulse, &mut data);
+            data
+        };
+
+        // microseconds go through untouched
+        let micros = ServoEncoding::MicrosecondsU16;
+        assert_eq!(encode(micros, 1500), vec![0xDC, 0x05]);
+        assert_eq!(micros.decode(&[0xDC, 0x05]), Ok(1500));
+        assert_eq!(micros.decode(&encode(micros, 13)), Ok(13));
+
+        // the pulse range is 0 to 180 degrees
+        let degrees = ServoEncoding::DegreesU8;
+        assert_eq!(encode(degrees, MIN_PULSE), vec![0]);
+        assert_eq!(encode(degrees, MAX_PULSE), vec![180]);
+        assert_eq!(encode(degrees, 1325), vec![90]);
+        assert_eq!(degrees.decode(&[90]), Ok(1325));
+        assert_eq!(degrees.decode(&[180]), Ok(MAX_PULSE));
+
+        // and anything outside of it is clamped instead of wrapping around the byte
+        assert_eq!(encode(degrees, 0), vec![0]);
+        assert_eq!(encode(degrees, 3000), vec![180]);
+        assert_eq!(encode(degrees, u16::MAX), vec![180]);
+
+        // a degree is about 12µs, so a round trip is only that close
+        for pulse in (MIN_PULSE..=MAX_PULSE).step_by(7) {
+            assert!(degrees.decode(&encode(degrees, pulse)).unwrap().abs_diff(pulse) <= 6);
+        }
+
+        let centi = ServoEncoding::CentidegreesU16;
+        assert_eq!(encode(centi, MAX_PULSE), 18_000u16.to_le_bytes().to_vec());
+        assert_eq!(encode(centi, 1325), 9_000u16.to_le_bytes().to_vec());
+        assert_eq!(encode(centi, 100), vec![0, 0]);
+        assert_eq!(encode(centi, 2500), 18_000u16.to_le_bytes().to_vec());
+        for pulse in MIN_PULSE..=MAX_PULSE {
+            assert_eq!(centi.decode(&encode(centi, pulse)), Ok(pulse));
+        }
+    }
+
+    #[test]
+    fn feedback_encodings() {
+        let feedback = Feedback {
+            pulses: [MIN_PULSE, 1325, MAX_PULSE, 1325],
+            ..feedback()
+        };
+
+        for (encoding, len) in [
+            (ServoEncoding::MicrosecondsU16, 11),
+            (ServoEncoding::DegreesU8, 7),
+            (ServoEncoding::CentidegreesU16, 11),
+        ] {
+            let payload = feedback.encode(encoding);
+            assert_eq!(payload.len(), len);
+            assert_eq!(Feedback::len(encoding), len);
+            assert_eq!(Feedback::decode(&payload, encoding), Ok(feedback));
+
+            let data = Frame::Feedback(feedback).encode_with(Checksum::Crc16, encoding, 0);
+            assert_eq!(Frame::total_len(&data[1..]), Some(data.len() - 1));
+            assert_eq!(
+                Frame::decode_with(&data[1..], Checksum::Crc16, encoding),
+                Ok(Frame::Feedback(feedback))
+            );
+        }
+
+        // degrees are shorter, so decoding them as microseconds doesn't line up
+        let data =
+            Frame::Feedback(feedback).encode_with(Checksum::Xor, ServoEncoding::DegreesU8, 0);
+        assert_eq!(
+            Frame::decode(&data[1..], Checksum::Xor),
+            Err(ProtocolError::Truncated {
+                expected: 11,
+                actual: 7
+            })
+        );
+
+        assert_eq!(
+            ServoEncoding::from_capabilities(capability::XOR | capability::DEGREES_U8),
+            Some(ServoEncoding::DegreesU8)
+        );
+        assert_eq!(ServoEncoding::from_capabilities(capability::CRC16), None);
+    }
+
+    #[test]
+    fn truncated() {
+        for checksum in BOTH {
+            let data = Frame::Feedback(feedback()).encode(checksum, 0);
+
+            assert_eq!(
+                Frame::decode(&data[1..8], checksum),
+                Err(ProtocolError::Truncated {
+                    expected: HEADER_LEN
+                        + Feedback::len(ServoEncoding::MicrosecondsU16)
+                        + checksum.size(),
+                    actual: 7
+                })
+            );
+            assert!(Frame::decode(&data[1..2], checksum).is_err());
+
+            // a valid frame with a payload that is too short for feedback
+            let short = Frame::Unknown {
+                kind: kind::FEEDBACK,
+                payload: vec![1, 2, 3],
+            }
+            .encode(checksum, 0);
+            assert_eq!(
+                Frame::decode(&short[1..], checksum),
+                Err(ProtocolError::Truncated {
+                    expected: Feedback::len(ServoEncoding::MicrosecondsU16),
+                    actual: 3
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn bad_checksum() {
+        for checksum in BOTH {
+            let mut data = Frame::Feedback(feedback()).encode(checksum, 0);
+            data[6] ^= 0xFF;
+
+            assert!(matches!(
+                Frame::decode(&data[1..], checksum),
+                Err(ProtocolError::BadChecksum { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn mixed_modes_rejected() {
+        let crc = Frame::Feedback(feedback()).encode(Checksum::Crc16, 0);
+        let xor = Frame::Feedback(feedback()).encode(Checksum::Xor, 0);
+
+        assert_eq!(
+            Frame::decode(&crc[1..], Checksum::Xor),
+            Err(ProtocolError::VersionMismatch {
+                expected: 1,
+                actual: 2
+            })
+        );
+        assert_eq!(
+            Frame::decode(&xor[1..], Checksum::Crc16),
+            Err(ProtocolError::VersionMismatch {
+                expected: 2,
+                actual: 1
+            })
+        );
+
+        // even with a forged version byte the checksum doesn't line up
+        let mut forged = xor.clone();
+        forged[1] = Checksum::Crc16.version();
+        forged.push(0);
+        assert!(matches!(
+            Frame::decode(&forged[1..], Checksum::Crc16),
+            Err(ProtocolError::BadChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn reader_mixed_modes() {
+        let mut reader = FrameReader::new();
+        let mut results = Vec::new();
+
+        let mut data = Frame::Feedback(feedback()).encode(Checksum::Crc16, 0);
+        data.extend(Frame::Feedback(feedback()).encode(Checksum::Xor, 0));
+
+        for byte in data {
+            if let Some(result) = reader.push(byte, Checksum::Xor, ServoEncoding::MicrosecondsU16) {
+                results.push(result);
+            }
+        }
+
+        // the crc frame is still framed correctly so the next frame isn't lost
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_err());
+        assert_eq!(results[1], Ok(Frame::Feedback(feedback())));
+    }
+
+    #[test]
+    fn negotiate() {
+        let both = capability::XOR | capability::CRC16;
+
+        assert_eq!(ChecksumMode::Auto.negotiate(both), Checksum::Crc16);
+        assert_eq!(ChecksumMode::Auto.negotiate(capability::XOR), Checksum::Xor);
+        assert_eq!(ChecksumMode::Auto.negotiate(0), Checksum::Xor);
+        assert_eq!(
+            ChecksumMode::Fixed(Checksum::Xor).negotiate(both),
+            Checksum::Xor
+        );
+        assert_eq!(ChecksumMode::Auto.handshake(), Checksum::Xor);
+        assert_eq!(
+            ChecksumMode::Fixed(Checksum::Crc16).handshake(),
+            Checksum::Crc16
+        );
+    }
+
+    #[test]
+    fn status_round_trip() {
+        let status = FirmwareStatus {
+            uptime_ms: 0x0102_0304,
+            millivolts: 4950,
+            loop_hz: 833,
+            last_seq: 200,
+        };
+
+        for checksum in BOTH {
+            let data = Frame::Status(status).encode(checksum, 7);
+            assert_eq!(data[2], kind::STATUS);
+            assert_eq!(data[3], 7);
+            assert_eq!(Frame::decode(&data[1..], checksum), Ok(Frame::Status(status)));
+
+            let data = Frame::StatusRequest.encode(checksum, 0);
+            assert_eq!(Frame::decode(&data[1..], checksum), Ok(Frame::StatusRequest));
+        }
+
+        assert_eq!(&status.encode()[..4], &[0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(
+            FirmwareStatus::decode(&status.encode()[..8]),
+            Err(ProtocolError::Truncated {
+                expected: FirmwareStatus::LEN,
+                actual: 8
+            })
+        );
+    }
+
+    #[test]
+    fn ack_round_trip() {
+        for checksum in BOTH {
+            let data = Frame::Ack { seq: 42 }.encode(checksum, 3);
+            assert_eq!(data[2], kind::ACK);
+            assert_eq!(Frame::decode(&data[1..], checksum), Ok(Frame::Ack { seq: 42 }));
+        }
+    }
+
+    #[test]
+    fn detach_round_trip() {
+        for checksum in BOTH {
+            for frame in [Frame::Detach, Frame::Attach] {
+                let data = frame.encode(checksum, 0);
+                assert_eq!(data.len(), 1 + HEADER_LEN + checksum.size());
+                assert_eq!(Frame::decode(&data[1..], checksum), Ok(frame));
+            }
+        }
+    }
+
+    #[test]
+    fn flags() {
+        let mut feedback = feedback();
+        assert!(feedback.moving());
+        assert!(!feedback.stalled());
+
+        feedback.status = Feedback::STALLED | 0b1000_0000;
+        assert!(!feedback.moving());
+        assert!(feedback.stalled());
+
+        feedback.status = 0;
+        assert!(!feedback.moving());
+        assert!(!feedback.stalled());
+    }
+
+    #[test]
+    fn unknown_kind() {
+        let frame = Frame::Unknown {
+            kind: 0x7F,
+            payload: vec![9, 8, 7],
+        };
+        let data = frame.encode(Checksum::Crc16, 0);
+
+        assert_eq!(Frame::decode(&data[1..], Checksum::Crc16), Ok(frame));
+    }
+
+    /// Small seeded generator so a failing fuzz case can be run again
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            // xorshift64*
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn byte(&mut self) -> u8 {
+            self.next() as u8
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.byte()).collect()
+        }
+    }
+
+    /// Run `case` for a range of seeds, or only for the one in `RAC_FUZZ_SEED`
+    fn fuzz(name: &str, case: impl Fn(&mut Rng)) {
+        let seeds = match std::env::var("RAC_FUZZ_SEED") {
+            Ok(seed) => {
+                let seed: u64 = seed.parse().expect("RAC_FUZZ_SEED is not a number");
+                seed..=seed
+            }
+            Err(_) => 1..=200,
+        };
+
+        for seed in seeds {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                // xorshift never leaves zero
+                case(&mut Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1))
+            }));
+            if result.is_err() {
+                panic!("{name} failed, run again with RAC_FUZZ_SEED={seed}");
+            }
+        }
+    }
+
+    fn random_frame(rng: &mut Rng) -> Frame {
+        match rng.below(6) {
+            0 => Frame::Feedback(Feedback {
+                pulses: [0; 4].map(|_| rng.next() as u16),
+                millivolts: rng.next() as u16,
+                status: rng.byte(),
+            }),
+            1 => Frame::Status(FirmwareStatus {
+                uptime_ms: rng.next() as u32,
+                millivolts: rng.next() as u16,
+                loop_hz: rng.next() as u16,
+                last_seq: rng.byte(),
+            }),
+            2 => Frame::Ack { seq: rng.byte() },
+            3 => Frame::Hello {
+                capabilities: rng.byte(),
+            },
+            4 => Frame::Detach,
+            _ => Frame::Unknown {
+                kind: 0x40 | rng.byte(),
+                payload: {
+                    let len = rng.below(256);
+                    rng.bytes(len)
+                },
+            },
+        }
+    }
+
+    /// Line noise, each kind the reader has to get through
+    fn garbage(rng: &mut Rng, checksum: Checksum) -> Vec<u8> {
+        match rng.below(6) {
+            // random bytes with more prefixes than chance would give
+            0 => {
+                let len = rng.below(64);
+                (0..len)
+                    .map(|_| match rng.below(8) {
+                        0 => PREFIX,
+                        _ => rng.byte(),
+                    })
+                    .collect()
+            }
+            // a frame cut short
+            1 => {
+                let data = random_frame(rng).encode(checksum, rng.byte());
+                let len = 1 + rng.below(data.len() - 1);
+                data[..len].to_vec()
+            }
+            // a header promising more than is sent
+            2 => {
+                let mut data = vec![PREFIX, checksum.version(), rng.byte(), rng.byte(), 0xFF];
+                let len = rng.below(32);
+                data.extend(rng.bytes(len));
+                data
+            }
+            3 => vec![PREFIX; 1 + rng.below(8)],
+            4 => vec![0xFF; rng.below(600)],
+            // well framed, but for the other checksum
+            _ => {
+                let other = match checksum {
+                    Checksum::Xor => Checksum::Crc16,
+                    Checksum::Crc16 => Checksum::Xor,
+                };
+                random_frame(rng).encode(other, rng.byte())
+            }
+        }
+    }
+
+    /// Everything the reader made of `data`, checking it never holds on to too much
+    fn read_all(data: &[u8], checksum: Checksum) -> Vec<Result<Frame, ProtocolError>> {
+        let mut reader = FrameReader::new();
+        let mut results = Vec::new();
+        for &byte in data {
+            let mut result = reader.push(byte, checksum, ServoEncoding::MicrosecondsU16);
+            while let Some(frame) = result {
+                results.push(frame);
+                result = reader.next(checksum, ServoEncoding::MicrosecondsU16);
+            }
+            assert!(reader.buffered() <= MAX_FRAME_LEN, "{}", reader.buffered());
+        }
+        results
+    }
+
+    #[test]
+    fn fuzz_decoders() {
+        const ENCODINGS: [ServoEncoding; 3] = [
+            ServoEncoding::MicrosecondsU16,
+            ServoEncoding::DegreesU8,
+            ServoEncoding::CentidegreesU16,
+        ];
+
+        fuzz("fuzz_decoders", |rng| {
+            let len = rng.below(MAX_FRAME_LEN + 8);
+            let mut data = rng.bytes(len);
+            // mostly valid headers so the decoders get past the first checks
+            if rng.below(2) == 0 && data.len() >= HEADER_LEN {
+                data[0] = BOTH[rng.below(2)].version();
+            }
+
+            for checksum in BOTH {
+                for encoding in ENCODINGS {
+                    let _ = Frame::decode_with(&data, checksum, encoding);
+                    let _ = Feedback::decode(&data, encoding);
+                    let _ = encoding.decode(&data);
+                }
+            }
+            let _ = FirmwareStatus::decode(&data);
+            let _ = Frame::total_len(&data);
+        });
+    }
+
+    #[test]
+    fn fuzz_reader() {
+        fuzz("fuzz_reader", |rng| {
+            for checksum in BOTH {
+                let len = rng.below(4096);
+                let noise = rng.bytes(len);
+                read_all(&noise, checksum);
+
+                let mut data = Vec::new();
+                while data.len() < 4096 {
+                    data.extend(garbage(rng, checksum));
+                }
+                read_all(&data, checksum);
+            }
+        });
+    }
+
+    /// Length of the longest common subsequence
+    fn common(a: &[Frame], b: &[Frame]) -> usize {
+        let mut row = vec![0; b.len() + 1];
+        for x in a {
+            let mut diagonal = 0;
+            for (j, y) in b.iter().enumerate() {
+                let above = row[j + 1];
+                row[j + 1] = if x == y {
+                    diagonal + 1
+                } else {
+                    above.max(row[j])
+                };
+                diagonal = above;
+            }
+        }
+        row[b.len()]
+    }
+
+    #[test]
+    fn fuzz_recovery() {
+        fuzz("fuzz_recovery", |rng| {
+            for checksum in BOTH {
+                let mut data = Vec::new();
+                let mut sent = Vec::new();
+                for seq in 0..10 {
+                    data.extend(garbage(rng, checksum));
+                    let frame = random_frame(rng);
+                    data.extend(frame.encode(checksum, seq));
+                    sent.push(frame);
+                }
+                // a header in the noise can still be waiting on bytes past the last frame
+                data.extend([0; MAX_FRAME_LEN]);
+
+                let received: Vec<Frame> = read_all(&data, checksum)
+                    .into_iter()
+                    .filter_map(Result::ok)
+                    .collect();
+
+                // the frames come through in order, only a frame made up by the noise that
+                // passed the checksum can swallow real ones, which the xor lets through
+                let matched = common(&sent, &received);
+                let (lost, forged) = (sent.len() - matched, received.len() - matched);
+                if forged == 0 {
+                    assert_eq!(lost, 0);
+                }
+                if checksum == Checksum::Crc16 {
+                    assert_eq!(forged, 0);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn stray_prefix() {
+        let frame = Frame::Feedback(feedback());
+        for checksum in BOTH {
+            // a prefix in the noise takes the real frame's prefix as its version
+            let mut data = vec![PREFIX, PREFIX];
+            data.extend(frame.encode(checksum, 1));
+            assert_eq!(read_all(&data, checksum).last(), Some(&Ok(frame.clone())));
+
+            // a frame cut short swallows the start of the next one
+            let cut = Frame::Unknown {
+                kind: 0x7F,
+                payload: vec![1; 20],
+            }
+            .encode(checksum, 0);
+            let mut data = cut[..10].to_vec();
+            data.extend(frame.encode(checksum, 2));
+            data.extend(frame.encode(checksum, 3));
+            let results = read_all(&data, checksum);
+            assert!(results[0].is_err());
+            assert_eq!(results[1..], [Ok(frame.clone()), Ok(frame.clone())]);
+        }
+    }
+}
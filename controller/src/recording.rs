@@ -0,0 +1,491 @@
+use std::{fmt, fs, io, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{kinematics::position::CordinateVec, robot::arm::JointAngles};
+
+/// Something that can be recorded and replayed, a head position or the joint angles
+pub trait Pose: Copy {
+    /// The pose `factor` of the way from `self` to `other`
+    fn lerp(self, other: Self, factor: f64) -> Self;
+}
+
+/// A pose at a point in time
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample<P = CordinateVec> {
+    /// Seconds since the start of the recording
+    pub time: f64,
+
+    pub pose: P,
+}
+
+/// A motion stored as poses over time, saved as json
+///
+/// Cartesian recordings store head positions and are replayed through inverse kinematics,
+/// joint space recordings store the joint angles and are replayed as they are
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recording<P = CordinateVec> {
+    /// Samples ordered by time
+    pub samples: Vec<Sample<P>>,
+}
+
+/// Moves a recording to a new place in the workspace
+///
+/// The recording is mirrored first, then rotated around the base and finally the offset is added
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    /// Added to every position, in units
+    pub offset: CordinateVec,
+
+    /// Rotation around the base (the z axis) in degrees, counter clockwise seen from above
+    pub rotation: f64,
+
+    /// Negate x, turning a motion taught for one side into the same motion for the other side
+    pub mirror: bool,
+}
+
+/// Steps through a recording as time passes
+#[derive(Debug)]
+pub struct Replay<P = CordinateVec> {
+    pub recording: Recording<P>,
+
+    /// Seconds since the replay started
+    pub elapsed: f64,
+}
+
+/// Collects samples as time passes
+#[derive(Debug, Default)]
+pub struct Recorder<P = CordinateVec> {
+    pub recording: Recording<P>,
+
+    /// Seconds since recording started
+    pub elapsed: f64,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+
+    /// The recording has no samples
+    Empty,
+
+    /// A sample is outside of the workspace
+    Unreachable { index: usize, position: CordinateVec },
+}
+
+impl Pose for CordinateVec {
+    fn lerp(self, other: Self, factor: f64) -> Self {
+        CordinateVec::lerp(&self, &other, factor)
+    }
+}
+
+impl Pose for JointAngles {
+    fn lerp(self, other: Self, factor: f64) -> Self {
+        let lerp = |from: f64, to: f64| from + (to - from) * factor;
+
+        Self {
+            base: lerp(self.base, other.base),
+            shoulder: lerp(self.shoulder, other.shoulder),
+            elbow: lerp(self.elbow, other.elbow),
+            claw: lerp(self.claw, other.claw),
+        }
+    }
+}
+
+impl<P: Pose> Recording<P> {
+    /// Read a recording from a json file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RecordingError>
+    where
+        P: DeserializeOwned,
+    {
+        let data = fs::read_to_string(path).map_err(RecordingError::Io)?;
+        serde_json::from_str(&data).map_err(RecordingError::Parse)
+    }
+
+    /// Write the recording to a json file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RecordingError>
+    where
+        P: Serialize,
+    {
+        let data = serde_json::to_string_pretty(self).map_err(RecordingError::Parse)?;
+        fs::write(path, data).map_err(RecordingError::Io)
+    }
+
+    /// Pose at the given time, interpolated between samples
+    ///
+    /// # Returns
+    /// `None` if the time is past the end of the recording
+    pub fn pose_at(&self, time: f64) -> Option<P> {
+        let next = self.samples.iter().position(|sample| sample.time >= time)?;
+        if next == 0 {
+            return Some(self.samples[0].pose);
+        }
+
+        let (from, to) = (self.samples[next - 1], self.samples[next]);
+        let factor = (time - from.time) / (to.time - from.time);
+        Some(from.pose.lerp(to.pose, factor))
+    }
+
+    /// The pose of the last sample
+    pub fn last(&self) -> Option<P> {
+        self.samples.last().map(|sample| sample.pose)
+    }
+}
+
+impl Recording {
+    /// Copy of the recording with every position transformed
+    pub fn transformed(&self, transform: &Transform) -> Self {
+        Self {
+            samples: self
+                .samples
+                .iter()
+                .map(|sample| Sample {
+                    time: sample.time,
+                    pose: transform.apply(sample.pose),
+                })
+                .collect(),
+        }
+    }
+
+    /// Check that every sample can be reached
+    ///
+    /// # Arguments
+    /// * `reachable` - returns true if the head can be moved to the position
+    ///
+    /// # Returns
+    /// `Err(RecordingError::Unreachable)` with the first sample that can't be reached
+    pub fn validate(&self, reachable: impl Fn(CordinateVec) -> bool) -> Result<(), RecordingError> {
+        if self.samples.is_empty() {
+            return Err(RecordingError::Empty);
+        }
+
+        match self
+            .samples
+            .iter()
+            .position(|sample| !reachable(sample.pose))
+        {
+            Some(index) => Err(RecordingError::Unreachable {
+                index,
+                position: self.samples[index].pose,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Convert to a joint space recording using inverse kinematics
+    ///
+    /// # Arguments
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    /// * `claw` - recording to take the claw angles from, cartesian recordings don't have them.
+    ///   The claw stays at 0 if this is `None`
+    pub fn to_joints(
+        &self,
+        upper_arm: f64,
+        lower_arm: f64,
+        claw: Option<&Recording<JointAngles>>,
+    ) -> Result<Recording<JointAngles>, RecordingError> {
+        let mut samples = Vec::with_capacity(self.samples.len());
+
+        for (index, sample) in self.samples.iter().enumerate() {
+            let (base, shoulder, elbow) = sample
+                .pose
+                .clone()
+                .inverse_kinematics(upper_arm, lower_arm)
+                .map_err(|_| RecordingError::Unreachable {
+                    index,
+                    position: sample.pose,
+                })?;
+
+            let claw = claw
+                .and_then(|claw| claw.pose_at(sample.time).or(claw.last()))
+                .map_or(0., |angles| angles.claw);
+
+            samples.push(Sample {
+                time: sample.time,
+                pose: JointAngles {
+                    base,
+                    shoulder,
+                    elbow,
+                    claw,
+                },
+            });
+        }
+
+        Ok(Recording { samples })
+    }
+}
+
+impl Recording<JointAngles> {
+    /// Convert to a cartesian recording using forward kinematics, the claw angles are dropped
+    ///
+    /// # Arguments
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    pub fn to_cartesian(&self, upper_arm: f64, lower_arm: f64) -> Recording {
+        Recording {
+            samples: self
+                .samples
+                .iter()
+                .map(|sample| Sample {
+                    time: sample.time,
+                    pose: CordinateVec::forward_kinematics(
+                        sample.pose.base,
+                        sample.pose.shoulder,
+                        sample.pose.elbow,
+                        upper_arm,
+                        lower_arm,
+                    ),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Transform {
+    pub fn apply(&self, mut position: CordinateVec) -> CordinateVec {
+        if self.mirror {
+            position.x = -position.x;
+        }
+
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let rotated = CordinateVec::new(
+            position.x * cos - position.y * sin,
+            position.x * sin + position.y * cos,
+            position.z,
+        );
+
+        rotated + self.offset
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            offset: CordinateVec::default(),
+            rotation: 0.,
+            mirror: false,
+        }
+    }
+}
+
+impl<P: Pose> Replay<P> {
+    pub fn new(recording: Recording<P>) -> Self {
+        Self {
+            recording,
+            elapsed: 0.,
+        }
+    }
+
+    /// Move the replay forward
+    ///
+    /// # Arguments
+    /// * `delta` - seconds since the last call
+    ///
+    /// # Returns
+    /// The pose to move to, `None` once the replay is finished
+    pub fn advance(&mut self, delta: f64) -> Option<P> {
+        self.elapsed += delta;
+        self.recording.pose_at(self.elapsed)
+    }
+}
+
+impl<P: Pose> Recorder<P> {
+    /// Add a sample
+    ///
+    /// # Arguments
+    /// * `delta` - seconds since the last sample
+    /// * `pose` - pose to record
+    pub fn record(&mut self, delta: f64, pose: P) {
+        if !self.recording.samples.is_empty() {
+            self.elapsed += delta;
+        }
+
+        self.recording.samples.push(Sample {
+            time: self.elapsed,
+            pose,
+        });
+    }
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io(err) => write!(f, "{err}"),
+            RecordingError::Parse(err) => write!(f, "invalid recording: {err}"),
+            RecordingError::Empty => write!(f, "recording has no samples"),
+            RecordingError::Unreachable { index, position } => write!(
+                f,
+                "sample {index} at ({:.1}, {:.1}, {:.1}) is out of reach",
+                position.x, position.y, position.z
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn recording() -> Recording {
+        Recording {
+            samples: vec![
+                Sample {
+                    time: 0.,
+                    pose: CordinateVec::new(100., 0., 50.),
+                },
+                Sample {
+                    time: 1.,
+                    pose: CordinateVec::new(100., 20., 30.),
+                },
+            ],
+        }
+    }
+
+    fn close(a: CordinateVec, b: CordinateVec) -> bool {
+        let delta = a - b;
+        [delta.x, delta.y, delta.z].iter().all(|d| d.abs() < 1e-9)
+    }
+
+    #[test]
+    fn offset_and_rotation() {
+        let shifted = recording().transformed(&Transform {
+            offset: CordinateVec::new(0., -80., 0.),
+            rotation: 0.,
+            mirror: false,
+        });
+        assert_eq!(shifted.samples[0].pose, CordinateVec::new(100., -80., 50.));
+        assert_eq!(shifted.samples[1].pose, CordinateVec::new(100., -60., 30.));
+        assert_eq!(shifted.samples[1].time, 1.);
+
+        let rotated = recording().transformed(&Transform {
+            offset: CordinateVec::new(0., 0., 10.),
+            rotation: 90.,
+            mirror: false,
+        });
+        assert!(close(rotated.samples[0].pose, CordinateVec::new(0., 100., 60.)));
+        assert!(close(rotated.samples[1].pose, CordinateVec::new(-20., 100., 40.)));
+
+        // mirrored before rotating
+        let mirrored = recording().transformed(&Transform {
+            rotation: 90.,
+            mirror: true,
+            ..Default::default()
+        });
+        assert!(close(mirrored.samples[0].pose, CordinateVec::new(0., -100., 50.)));
+        assert!(close(mirrored.samples[1].pose, CordinateVec::new(-20., -100., 30.)));
+    }
+
+    #[test]
+    fn reject_unreachable() {
+        let shifted = recording().transformed(&Transform {
+            offset: CordinateVec::new(0., 0., -40.),
+            rotation: 0.,
+            mirror: false,
+        });
+
+        // nothing may go below z = 0
+        match shifted.validate(|position| position.z >= 0.) {
+            Err(RecordingError::Unreachable { index, position }) => {
+                assert_eq!(index, 1);
+                assert_eq!(position, CordinateVec::new(100., 20., -10.));
+            }
+            other => panic!("expected an unreachable sample, got {other:?}"),
+        }
+
+        assert!(recording().validate(|position| position.z >= 0.).is_ok());
+        assert!(matches!(
+            Recording::default().validate(|_| true),
+            Err(RecordingError::Empty)
+        ));
+    }
+
+    #[test]
+    fn replay() {
+        let mut replay = Replay::new(recording());
+
+        assert_eq!(replay.advance(0.), Some(CordinateVec::new(100., 0., 50.)));
+        assert_eq!(replay.advance(0.5), Some(CordinateVec::new(100., 10., 40.)));
+        assert_eq!(replay.advance(0.5), Some(CordinateVec::new(100., 20., 30.)));
+        assert_eq!(replay.advance(0.1), None);
+    }
+
+    fn angles(base: f64, shoulder: f64, elbow: f64, claw: f64) -> JointAngles {
+        JointAngles {
+            base,
+            shoulder,
+            elbow,
+            claw,
+        }
+    }
+
+    #[test]
+    fn joint_space_round_trip() {
+        let joints = Recording {
+            samples: vec![
+                Sample {
+                    time: 0.,
+                    pose: angles(120., 70., 80., 10.),
+                },
+                Sample {
+                    time: 0.5,
+                    pose: angles(135., 60., 100., 45.),
+                },
+                Sample {
+                    time: 1.,
+                    pose: angles(100., 75., 60., 90.),
+                },
+            ],
+        };
+
+        let cartesian = joints.to_cartesian(100., 100.);
+        assert_eq!(cartesian.samples.len(), 3);
+        assert_eq!(cartesian.samples[1].time, 0.5);
+
+        let back = cartesian.to_joints(100., 100., Some(&joints)).unwrap();
+        for (expected, actual) in joints.samples.iter().zip(&back.samples) {
+            let (expected, actual) = (expected.pose, actual.pose);
+            assert!((expected.base - actual.base).abs() < 1e-9);
+            assert!((expected.shoulder - actual.shoulder).abs() < 1e-9);
+            assert!((expected.elbow - actual.elbow).abs() < 1e-9);
+            assert_eq!(expected.claw, actual.claw);
+        }
+
+        // without a claw recording the claw stays closed
+        let back = cartesian.to_joints(100., 100., None).unwrap();
+        assert!(back.samples.iter().all(|sample| sample.pose.claw == 0.));
+
+        // too far away for inverse kinematics
+        let far = Recording {
+            samples: vec![Sample {
+                time: 0.,
+                pose: CordinateVec::new(300., 0., 0.),
+            }],
+        };
+        assert!(matches!(
+            far.to_joints(100., 100., None),
+            Err(RecordingError::Unreachable { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn record() {
+        let mut recorder = Recorder::default();
+
+        // the first sample starts the recording no matter how long ago the last tick was
+        recorder.record(0.75, angles(0., 0., 0., 0.));
+        recorder.record(0.25, angles(10., 0., 0., 0.));
+        recorder.record(0.5, angles(20., 0., 0., 0.));
+
+        let times: Vec<f64> = recorder.recording.samples.iter().map(|sample| sample.time).collect();
+        assert_eq!(times, vec![0., 0.25, 0.75]);
+        assert_eq!(recorder.recording.pose_at(0.5), Some(angles(15., 0., 0., 0.)));
+    }
+
+    #[test]
+    fn json() {
+        let json = serde_json::to_string(&recording()).unwrap();
+        assert_eq!(serde_json::from_str::<Recording>(&json).unwrap(), recording());
+    }
+}
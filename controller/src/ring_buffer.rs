@@ -0,0 +1,117 @@
+/// Fixed size byte queue that overwrites the oldest data when full
+///
+/// Used between the serial port and the frame reader so reading never allocates
+#[derive(Debug)]
+pub struct RingBuffer<const N: usize> {
+    buf: [u8; N],
+
+    /// Index of the oldest byte
+    head: usize,
+
+    /// Number of bytes currently stored
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    pub fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append bytes, dropping the oldest data if there isn't enough room
+    ///
+    /// # Arguments
+    /// * `data` - bytes to append
+    ///
+    /// # Returns
+    /// The number of bytes that were dropped to make room
+    pub fn push_slice(&mut self, data: &[u8]) -> usize {
+        let mut dropped = 0;
+
+        for &byte in data {
+            if self.len == N {
+                // overwrite the oldest byte
+                self.head = (self.head + 1) % N;
+                self.len -= 1;
+                dropped += 1;
+            }
+
+            self.buf[(self.head + self.len) % N] = byte;
+            self.len += 1;
+        }
+
+        dropped
+    }
+
+    /// Remove and return the oldest byte
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let byte = self.buf[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fifo() {
+        let mut ring = RingBuffer::<4>::new();
+
+        assert_eq!(ring.push_slice(&[1, 2, 3]), 0);
+        assert_eq!(ring.pop(), Some(1));
+        assert_eq!(ring.push_slice(&[4, 5]), 0);
+
+        assert_eq!(ring.len(), 4);
+        assert_eq!(ring.pop(), Some(2));
+        assert_eq!(ring.pop(), Some(3));
+        assert_eq!(ring.pop(), Some(4));
+        assert_eq!(ring.pop(), Some(5));
+        assert_eq!(ring.pop(), None);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn overflow_drops_oldest() {
+        let mut ring = RingBuffer::<4>::new();
+
+        assert_eq!(ring.push_slice(&[1, 2, 3]), 0);
+        assert_eq!(ring.push_slice(&[4, 5, 6]), 2);
+
+        let mut out = Vec::new();
+        while let Some(byte) = ring.pop() {
+            out.push(byte);
+        }
+        assert_eq!(out, vec![3, 4, 5, 6]);
+    }
+}
@@ -1,8 +1,24 @@
-use crate::{Joint, Servos};
+use crate::{
+    kinematics::{
+        approx::ApproxEq,
+        joints::{Joint, LimitHit},
+        position::CordinateVec,
+        segments::Segments,
+    },
+    recording::Pose,
+    robot::Servos,
+};
+use serde::{Deserialize, Serialize};
+
+/// Joint names in the order of [`JointAngles`] and [`Servos`]
+pub const JOINTS: [&str; 4] = ["base", "shoulder", "elbow", "claw"];
+
+/// Degrees each joint is turned either way by to take the [`Arm::jacobian`]
+const JACOBIAN_STEP: f64 = 1e-4;
 
 /// Defines the arm of the robot
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Arm {
     /// Horizontal rotation (or azmut)
     pub base: Joint,
@@ -16,14 +32,120 @@ pub struct Arm {
 
     /// Claw joint for opening and closing the claw
     pub claw: Joint,
+
+    /// Joint between the lower arm and the claw that pitches the claw, for arms built with one
+    ///
+    /// It holds the claw at [`crate::robot::Robot::claw_pitch`] and the head is the tip of the
+    /// claw, see [`CordinateVec::inverse_kinematics_4dof`]. Not in [`JOINTS`] or
+    /// [`JointAngles`], joint space moves leave it where it is
+    pub wrist: Option<Joint>,
 }
 
+/// Every joint at exactly the same angle, see [`ApproxEq`] for within a tolerance
 impl PartialEq for Arm {
     fn eq(&self, other: &Self) -> bool {
         self.base == other.base
             && self.shoulder == other.shoulder
             && self.elbow == other.elbow
             && self.claw == other.claw
+            && self.wrist == other.wrist
+    }
+}
+
+/// Every joint at the same angle within the tolerance
+impl ApproxEq for Arm {
+    fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        self.base.approx_eq(&other.base, eps)
+            && self.shoulder.approx_eq(&other.shoulder, eps)
+            && self.elbow.approx_eq(&other.elbow, eps)
+            && self.claw.approx_eq(&other.claw, eps)
+            && match (&self.wrist, &other.wrist) {
+                (Some(wrist), Some(other)) => wrist.approx_eq(other, eps),
+                (wrist, other) => wrist.is_none() && other.is_none(),
+            }
+    }
+}
+
+/// Joints whose servo is mounted mirrored, see [`Joint::inverted`]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InvertedJoints {
+    pub base: bool,
+    pub shoulder: bool,
+    pub elbow: bool,
+    pub claw: bool,
+
+    /// Ignored for an arm without a [`Arm::wrist`]
+    pub wrist: bool,
+}
+
+/// Soft margin of every joint in degrees, see [`Joint::soft_margin_deg`]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoftMargins {
+    pub base: f64,
+    pub shoulder: f64,
+    pub elbow: f64,
+    pub claw: f64,
+
+    /// Ignored for an arm without a [`Arm::wrist`]
+    pub wrist: f64,
+
+    /// Slow the head down as the base, shoulder or elbow turn into their margin, see
+    /// [`crate::robot::Robot::soft_margin_velocity`]
+    pub slow_down: bool,
+}
+
+/// Claw angles in degrees for open and closed, so a different gripper only needs a different
+/// config, see [`Arm::set_claw`]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClawPresets {
+    pub open_angle: f64,
+    pub closed_angle: f64,
+}
+
+/// Angle of every joint in degrees
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JointAngles {
+    pub base: f64,
+    pub shoulder: f64,
+    pub elbow: f64,
+    pub claw: f64,
+}
+
+impl JointAngles {
+    /// Angle of the joint with this name, see [`JOINTS`]
+    pub fn get(&self, name: &str) -> Option<f64> {
+        match name {
+            "base" => Some(self.base),
+            "shoulder" => Some(self.shoulder),
+            "elbow" => Some(self.elbow),
+            "claw" => Some(self.claw),
+            _ => None,
+        }
+    }
+}
+
+impl ClawPresets {
+    /// The open or closed angle
+    pub fn angle(&self, open: bool) -> f64 {
+        if open {
+            self.open_angle
+        } else {
+            self.closed_angle
+        }
+    }
+}
+
+/// Open at the end of the claw's usual range and closed at the start, like
+/// [`crate::robot::Robot::claw_percent`]
+impl Default for ClawPresets {
+    fn default() -> Self {
+        Self {
+            open_angle: 180.,
+            closed_angle: 0.,
+        }
     }
 }
 
@@ -35,6 +157,7 @@ impl Default for Arm {
             shoulder: Joint::default(),
             elbow: Joint::default(),
             claw: Joint::default(),
+            wrist: None,
         }
     }
 }
@@ -43,10 +166,456 @@ impl Default for Arm {
 impl Arm {
     pub fn to_servos(&self) -> Servos {
         Servos {
-            base: self.base.into_servo(),
-            shoulder: self.shoulder.into_servo(),
-            elbow: self.elbow.into_servo(),
-            claw: self.claw.into_servo(),
+            base: self.base.to_servo(),
+            shoulder: self.shoulder.to_servo(),
+            elbow: self.elbow.to_servo(),
+            claw: self.claw.to_servo(),
+            wrist: self.wrist.as_ref().map(Joint::to_servo),
+        }
+    }
+
+    /// Same as [`Arm::to_servos`] with every joint turned `trim` further, `None` if any pulse
+    /// width would be NaN, infinite or out of range so it's never cast into a pulse that slams
+    /// a servo to one end
+    pub fn checked_servos(&self, trim: JointAngles) -> Option<Servos> {
+        let [base, shoulder, elbow, claw] = self.checked_pulses(trim)?.map(|pulse| pulse as u16);
+        Some(Servos {
+            base,
+            shoulder,
+            elbow,
+            claw,
+            wrist: self.checked_wrist()?,
+        })
+    }
+
+    /// Pulse width of the [`Arm::wrist`], untrimmed
+    ///
+    /// # Returns
+    /// `Some(None)` without a wrist, `None` if the pulse width isn't valid like
+    /// [`Arm::checked_servos`]
+    pub fn checked_wrist(&self) -> Option<Option<u16>> {
+        let Some(wrist) = &self.wrist else {
+            return Some(None);
+        };
+        let pulse = wrist.pulse_at(wrist.angle);
+        (pulse.is_finite() && (0. ..=u16::MAX as f64).contains(&pulse))
+            .then_some(Some(pulse as u16))
+    }
+
+    /// Pulse widths before they are cast to whole µs, `None` like [`Arm::checked_servos`]
+    pub fn checked_pulses(&self, trim: JointAngles) -> Option<[f64; 4]> {
+        let pulses = [
+            (&self.base, trim.base),
+            (&self.shoulder, trim.shoulder),
+            (&self.elbow, trim.elbow),
+            (&self.claw, trim.claw),
+        ]
+        .map(|(joint, trim)| joint.pulse_at(joint.angle + trim));
+        pulses
+            .iter()
+            .all(|pulse| pulse.is_finite() && (0. ..=u16::MAX as f64).contains(pulse))
+            .then_some(pulses)
+    }
+
+    /// The first joint, the wrist last, whose servo is held short of its angle because the
+    /// angle is past the joint's range or the motion doesn't reach that far, see
+    /// [`Joint::pulse_at`]
+    pub fn saturated(&self) -> Option<&'static str> {
+        JOINTS
+            .into_iter()
+            .chain(self.wrist.is_some().then_some("wrist"))
+            .find(|&name| {
+                let joint = self.joint(name).expect("every name in JOINTS is a joint");
+                joint.saturates(joint.angle)
+            })
+    }
+
+    /// Set [`Joint::inverted`] of every joint
+    pub fn invert(&mut self, inverted: InvertedJoints) {
+        self.base.inverted = inverted.base;
+        self.shoulder.inverted = inverted.shoulder;
+        self.elbow.inverted = inverted.elbow;
+        self.claw.inverted = inverted.claw;
+        if let Some(wrist) = &mut self.wrist {
+            wrist.inverted = inverted.wrist;
+        }
+    }
+
+    /// Turn the claw to its open or closed preset, see [`Joint::set_angle`]
+    ///
+    /// # Errors
+    /// The preset is past the claw's range, it's held at the limit
+    pub fn set_claw(&mut self, presets: ClawPresets, open: bool) -> Result<(), LimitHit> {
+        self.claw.set_angle(presets.angle(open))
+    }
+
+    /// Set [`Joint::soft_margin_deg`] of every joint
+    pub fn set_soft_margins(&mut self, margins: SoftMargins) {
+        self.base.soft_margin_deg = margins.base;
+        self.shoulder.soft_margin_deg = margins.shoulder;
+        self.elbow.soft_margin_deg = margins.elbow;
+        self.claw.soft_margin_deg = margins.claw;
+        if let Some(wrist) = &mut self.wrist {
+            wrist.soft_margin_deg = margins.wrist;
+        }
+    }
+
+    /// The first joint, the wrist last, within its soft margin, see [`Joint::in_soft_margin`]
+    pub fn in_soft_margin(&self) -> Option<&'static str> {
+        JOINTS
+            .into_iter()
+            .chain(self.wrist.is_some().then_some("wrist"))
+            .find(|&name| {
+                let joint = self.joint(name).expect("every name in JOINTS is a joint");
+                joint.in_soft_margin()
+            })
+    }
+
+    /// The joint with this name, see [`JOINTS`]
+    pub fn joint(&self, name: &str) -> Option<&Joint> {
+        match name {
+            "base" => Some(&self.base),
+            "shoulder" => Some(&self.shoulder),
+            "elbow" => Some(&self.elbow),
+            "claw" => Some(&self.claw),
+            "wrist" => self.wrist.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn joint_mut(&mut self, name: &str) -> Option<&mut Joint> {
+        match name {
+            "base" => Some(&mut self.base),
+            "shoulder" => Some(&mut self.shoulder),
+            "elbow" => Some(&mut self.elbow),
+            "claw" => Some(&mut self.claw),
+            "wrist" => self.wrist.as_mut(),
+            _ => None,
         }
     }
+
+    pub fn angles(&self) -> JointAngles {
+        JointAngles {
+            base: self.base.angle,
+            shoulder: self.shoulder.angle,
+            elbow: self.elbow.angle,
+            claw: self.claw.angle,
+        }
+    }
+
+    /// Commanded angles clamped to each joint's range, which is what the servos actually do
+    ///
+    /// Continuous joints have no range to clamp to
+    pub fn clamped_angles(&self) -> JointAngles {
+        let clamped = |joint: &Joint| {
+            if joint.continuous {
+                joint.angle
+            } else {
+                joint.angle.clamp(joint.min, joint.max)
+            }
+        };
+        JointAngles {
+            base: clamped(&self.base),
+            shoulder: clamped(&self.shoulder),
+            elbow: clamped(&self.elbow),
+            claw: clamped(&self.claw),
+        }
+    }
+
+    /// Where the head is with the joints at their angles, the reverse of
+    /// [`CordinateVec::inverse_kinematics`]
+    ///
+    /// # Arguments
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    pub fn forward_kinematics(&self, upper_arm: f64, lower_arm: f64) -> CordinateVec {
+        CordinateVec::forward_kinematics(
+            self.base.angle,
+            self.shoulder.angle,
+            self.elbow.angle,
+            upper_arm,
+            lower_arm,
+        )
+    }
+
+    /// How fast the head moves for each of the base, shoulder and elbow turning at 1°/s, in
+    /// units/s
+    ///
+    /// Taken by turning each joint a little either way with [`Arm::forward_kinematics`]
+    ///
+    /// # Arguments
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    pub fn jacobian(&self, upper_arm: f64, lower_arm: f64) -> [CordinateVec; 3] {
+        let at = |base, shoulder, elbow| {
+            CordinateVec::forward_kinematics(base, shoulder, elbow, upper_arm, lower_arm)
+        };
+        let (base, shoulder, elbow) = (self.base.angle, self.shoulder.angle, self.elbow.angle);
+        let step = JACOBIAN_STEP;
+
+        [
+            (at(base + step, shoulder, elbow) - at(base - step, shoulder, elbow)) / (2. * step),
+            (at(base, shoulder + step, elbow) - at(base, shoulder - step, elbow)) / (2. * step),
+            (at(base, shoulder, elbow + step) - at(base, shoulder, elbow - step)) / (2. * step),
+        ]
+    }
+
+    /// How fast the base, shoulder and elbow have to turn in °/s for the head to move at
+    /// `velocity`, the [`Arm::jacobian`] solved for it
+    ///
+    /// Near a singular pose, the arm stretched out or folded up or the head right above the
+    /// base, a small velocity takes the joints very fast and they go infinite on it. Right on
+    /// it the head can't move in some directions at all, and the joint speeds aren't finite
+    /// even for the ones it can
+    ///
+    /// # Arguments
+    /// * `velocity` - Velocity of the head in units/s
+    /// * `upper_arm` - The length of the upper Arm
+    /// * `lower_arm` - The length of the lower Arm
+    pub fn joint_velocities(
+        &self,
+        velocity: CordinateVec,
+        upper_arm: f64,
+        lower_arm: f64,
+    ) -> (f64, f64, f64) {
+        let [base, shoulder, elbow] = self.jacobian(upper_arm, lower_arm);
+
+        // Cramer's rule, each joint's column swapped for the velocity
+        let det = base.dot(&shoulder.cross(&elbow));
+        (
+            velocity.dot(&shoulder.cross(&elbow)) / det,
+            base.dot(&velocity.cross(&elbow)) / det,
+            base.dot(&shoulder.cross(&velocity)) / det,
+        )
+    }
+
+    /// Same as [`Arm::forward_kinematics`] with where the elbow is too, for drawing the arm
+    pub fn segments(&self, upper_arm: f64, lower_arm: f64) -> Segments {
+        Segments::from_angles(
+            self.base.angle,
+            self.shoulder.angle,
+            self.elbow.angle,
+            upper_arm,
+            lower_arm,
+        )
+    }
+
+    /// Estimate the joint angles from servo pulse widths, the reverse of [`Arm::to_servos`]
+    ///
+    /// # Arguments
+    /// * `pulses` - pulse widths in microseconds, in the same order as [`Servos`]
+    pub fn angles_from_servos(&self, pulses: [u16; 4]) -> JointAngles {
+        JointAngles {
+            base: self.base.angle_for_servo(pulses[0]),
+            shoulder: self.shoulder.angle_for_servo(pulses[1]),
+            elbow: self.elbow.angle_for_servo(pulses[2]),
+            claw: self.claw.angle_for_servo(pulses[3]),
+        }
+    }
+
+    /// Set every joint `factor` of the way from `from` to `to`
+    ///
+    /// `factor` is clamped to 0..=1 so the joints never overshoot
+    pub fn interpolate(&mut self, from: JointAngles, to: JointAngles, factor: f64) {
+        let angles = from.lerp(to, factor.clamp(0., 1.));
+
+        self.base.angle = angles.base;
+        self.shoulder.angle = angles.shoulder;
+        self.elbow.angle = angles.elbow;
+        self.claw.angle = angles.claw;
+    }
+
+    /// Move every joint towards its angle in `target`, each limited by its own maximum velocity
+    ///
+    /// # Arguments
+    /// * `target` - angles to move to
+    /// * `delta` - seconds since the last step
+    pub fn step_towards(&mut self, target: JointAngles, delta: f64) {
+        self.base.step_towards(target.base, delta);
+        self.shoulder.step_towards(target.shoulder, delta);
+        self.elbow.step_towards(target.elbow, delta);
+        self.claw.step_towards(target.claw, delta);
+    }
+
+    /// Scale the change of every joint since `from` down by the same factor, so none of them
+    /// turns faster than its [`Joint::max_velocity_dps`] over `delta`
+    ///
+    /// Unlike [`Arm::step_towards`] the joints stay in step, the arm moves the same way only
+    /// slower
+    ///
+    /// # Arguments
+    /// * `from` - angles before the change
+    /// * `from_wrist` - angle of the [`Arm::wrist`] before the change
+    /// * `delta` - seconds since the last step
+    ///
+    /// # Returns
+    /// The factor the change was divided by, 1 if no joint was too fast
+    pub fn limit_velocity(
+        &mut self,
+        from: JointAngles,
+        from_wrist: Option<f64>,
+        delta: f64,
+    ) -> f64 {
+        // no allocation, this runs every tick
+        let joints = [
+            Some((&mut self.base, from.base)),
+            Some((&mut self.shoulder, from.shoulder)),
+            Some((&mut self.elbow, from.elbow)),
+            Some((&mut self.claw, from.claw)),
+            self.wrist.as_mut().zip(from_wrist),
+        ];
+
+        let factor = joints
+            .iter()
+            .flatten()
+            .map(|(joint, from)| {
+                let change = (joint.angle - from).abs();
+                if change == 0. {
+                    0.
+                } else {
+                    change / (joint.max_velocity_dps * delta)
+                }
+            })
+            .fold(1., f64::max);
+        if factor > 1. {
+            for (joint, from) in joints.into_iter().flatten() {
+                joint.angle = from + (joint.angle - from) / factor;
+            }
+        }
+
+        factor
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kinematics::joints::{DirectDrive, DirectDriveOffset, Motion, MotionKind};
+    use kinematics_core::assert_approx_eq;
+
+    /// An arm with the angles inverse kinematics gives for `position`
+    fn reaching(mut position: CordinateVec) -> Arm {
+        let (base, shoulder, elbow) = position.inverse_kinematics(100., 100.).unwrap();
+        let mut arm = Arm::default();
+        arm.base.angle = base;
+        arm.shoulder.angle = shoulder;
+        arm.elbow.angle = elbow;
+        arm
+    }
+
+    #[test]
+    fn approx_eq() {
+        let arm = reaching(CordinateVec::new(60., 80., 40.));
+        let mut nudged = reaching(CordinateVec::new(60., 80., 40.));
+        nudged.elbow.angle += 1e-12;
+
+        // equality is exact, a rounding error is only within a tolerance
+        assert_ne!(arm, nudged);
+        assert_approx_eq!(arm, nudged);
+
+        nudged.elbow.angle += 0.01;
+        assert!(!arm.approx_eq(&nudged, 1e-3));
+    }
+
+    #[test]
+    fn clone() {
+        let arm = reaching(CordinateVec::new(60., 80., 40.));
+        let mut copy = arm.clone();
+        assert_eq!(copy, arm);
+
+        // the copy turns and moves on its own
+        copy.elbow.angle += 10.;
+        copy.shoulder.motion = DirectDriveOffset { offset: 30. }.into();
+        copy.wrist = Some(Joint::new(0., 360., DirectDrive::new()));
+        assert_ne!(copy, arm);
+        let direct = MotionKind::DirectDrive(DirectDrive::new());
+        assert_eq!(arm.shoulder.motion, direct);
+        assert_eq!(arm.shoulder.motion.get_pivot_angle(40.), 40.);
+        assert!(arm.wrist.is_none());
+    }
+
+    #[test]
+    fn jacobian() {
+        for position in [
+            CordinateVec::new(60., 80., 40.),
+            CordinateVec::new(-120., 30., -30.),
+            CordinateVec::new(0., -150., 60.),
+            CordinateVec::new(20., 40., 160.),
+        ] {
+            let arm = reaching(position);
+            let [base, shoulder, elbow] = arm.jacobian(100., 100.);
+
+            // turning the base swings the head round the z axis
+            let around = CordinateVec::new(-position.y, position.x, 0.) * 1f64.to_radians();
+            assert_approx_eq!(base, around, 1e-6, "at {position:?}");
+
+            // against a coarser difference over a tenth of a degree
+            let moved = |angles: (f64, f64, f64)| {
+                CordinateVec::forward_kinematics(angles.0, angles.1, angles.2, 100., 100.)
+                    - position
+            };
+            let a = (arm.base.angle, arm.shoulder.angle, arm.elbow.angle);
+            for (column, turned) in [
+                (shoulder, (a.0, a.1 + 0.1, a.2)),
+                (elbow, (a.0, a.1, a.2 + 0.1)),
+            ] {
+                let coarse = moved(turned) / 0.1;
+                assert!(
+                    (column - coarse).dst() < 0.01 * column.dst(),
+                    "{column:?} and {coarse:?} at {position:?}"
+                );
+            }
+
+            // turning the joints as fast as it says moves the head at the velocity
+            let velocity = CordinateVec::new(10., -20., 5.);
+            let (base, shoulder, elbow) = arm.joint_velocities(velocity, 100., 100.);
+            let delta = 1e-4;
+            let actual = moved((
+                a.0 + base * delta,
+                a.1 + shoulder * delta,
+                a.2 + elbow * delta,
+            )) / delta;
+            assert_approx_eq!(actual, velocity, 1e-3, "at {position:?}");
+        }
+
+        // stretched out the elbow runs away, the closer the faster
+        let speeds: Vec<f64> = [190., 199., 199.9]
+            .into_iter()
+            .map(|out| {
+                let arm = reaching(CordinateVec::new(0., out, 0.));
+                arm.joint_velocities(CordinateVec::new(0., -1., 0.), 100., 100.)
+                    .2
+                    .abs()
+            })
+            .collect();
+        assert!(speeds[0] < speeds[1] && speeds[1] < speeds[2], "{speeds:?}");
+    }
+
+    #[test]
+    fn forward_kinematics() {
+        // every quadrant and the axes between them, out to nearly full reach
+        for azmut in [30., 90., 135., 180., 210., 270., 300., 360.] {
+            let (y, x) = f64::to_radians(azmut).sin_cos();
+            for (out, z) in [(60., 40.), (120., -30.), (199.9, 0.), (100., 100.)] {
+                let expected = CordinateVec::new(x * out, y * out, z);
+                let arm = reaching(expected);
+
+                let actual = arm.forward_kinematics(100., 100.);
+                assert_approx_eq!(expected, actual, 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn segments() {
+        // nearly straight out along y, the elbow halfway there
+        let expected = CordinateVec::new(0., 199.9, 0.);
+        let segments = reaching(expected).segments(100., 100.);
+
+        assert_approx_eq!(segments.head, expected, 1e-6);
+        assert!((segments.elbow.dst() - 100.).abs() < 1e-9);
+        assert!(((segments.elbow - segments.head).dst() - 100.).abs() < 1e-9);
+        assert!((segments.elbow.y - 199.9 / 2.).abs() < 0.1);
+        assert!(segments.elbow.x.abs() < 1e-9);
+    }
 }
@@ -0,0 +1,388 @@
+use std::{fmt, fs, io, path::Path};
+
+use serde::Serialize;
+
+use super::{arm::Arm, Robot};
+use crate::{
+    kinematics::{
+        joints::{Joint, Motion},
+        position::CordinateVec,
+    },
+    logging::{info, warn},
+};
+
+/// Checks that the configured arm is consistent with itself before it's driven
+///
+/// Wrong linkage dimensions don't fail loudly, the arm just moves a little off. This samples
+/// every joint's range and looks for the places where the motion model, the servo mapping and
+/// the kinematics disagree
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Audit {
+    /// Angles sampled across the range of every joint, the kinematics grid is this cubed
+    pub samples: usize,
+
+    /// Largest round trip error in degrees that isn't a violation
+    pub tolerance: f64,
+}
+
+/// What a [`Violation`] was found by
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// The joint's range is empty or not finite, the error is how far min is above max
+    Limits,
+
+    /// The inverse of the motion doesn't lead back to the angle, the error is in degrees
+    Motion,
+
+    /// The pulse width turns back against the rest of the range, the error is in µs
+    Servo,
+
+    /// Inverse kinematics of the forward kinematics position don't lead back to the angle,
+    /// the error is in degrees
+    Kinematics,
+}
+
+/// The worst sample a check failed on for a joint
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub check: Check,
+    pub joint: &'static str,
+
+    /// Joint angle in degrees the error is largest at
+    pub angle: f64,
+
+    /// Magnitude of the error, infinite if there was no finite result at all
+    pub error: f64,
+
+    /// Samples the check failed on for this joint
+    pub count: usize,
+}
+
+/// Result of [`Audit::run`], saved as json
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditReport {
+    /// Samples checked over all joints and checks
+    pub checked: usize,
+
+    pub violations: Vec<Violation>,
+}
+
+impl Audit {
+    /// Check the arm with the given arm lengths
+    pub fn run(&self, arm: &Arm, upper_arm: f64, lower_arm: f64) -> AuditReport {
+        let mut report = AuditReport {
+            checked: 0,
+            violations: Vec::new(),
+        };
+
+        let joints = [
+            ("base", &arm.base),
+            ("shoulder", &arm.shoulder),
+            ("elbow", &arm.elbow),
+            ("claw", &arm.claw),
+        ];
+        for (name, joint) in joints {
+            report.checked += 1;
+            if !(joint.min < joint.max && joint.max.is_finite() && joint.min.is_finite()) {
+                report.add(Check::Limits, name, joint.min, joint.min - joint.max);
+                continue;
+            }
+
+            self.motion(&mut report, name, joint);
+            self.servo(&mut report, name, joint);
+        }
+
+        // the claw has nothing to do with where the head is
+        if joints[..3].iter().all(|(_, joint)| joint.min < joint.max) {
+            self.kinematics(&mut report, arm, upper_arm, lower_arm);
+        }
+
+        report
+    }
+
+    /// Angles spread over the joint's range, at the middle of equal slices so the singular
+    /// poses at the very ends aren't sampled
+    fn angles(&self, joint: &Joint) -> impl Iterator<Item = f64> + Clone {
+        let (min, step) = (joint.min, (joint.max - joint.min) / self.samples as f64);
+        (0..self.samples).map(move |i| min + (i as f64 + 0.5) * step)
+    }
+
+    /// The motion followed by its inverse has to land on the angle it started from
+    fn motion(&self, report: &mut AuditReport, name: &'static str, joint: &Joint) {
+        for angle in self.angles(joint) {
+            report.checked += 1;
+            let pivot = joint.motion.get_pivot_angle(angle);
+            let back = joint.motion.get_target_angle(pivot, joint.min, joint.max);
+
+            // the search for the inverse always ends somewhere in the range, even for NaN
+            let error = if pivot.is_finite() {
+                (back - angle).abs()
+            } else {
+                f64::INFINITY
+            };
+            if error > self.tolerance {
+                report.add(Check::Motion, name, angle, error);
+            }
+        }
+    }
+
+    /// The pulse width has to move the same way over the whole range
+    fn servo(&self, report: &mut AuditReport, name: &'static str, joint: &Joint) {
+        let pulses: Vec<(f64, f64)> = self
+            .angles(joint)
+            .map(|angle| (angle, joint.pulse_at(angle)))
+            .collect();
+
+        // the way most of the range goes, a NaN at one end shouldn't flip it
+        let mut finite = pulses
+            .iter()
+            .map(|&(_, pulse)| pulse)
+            .filter(|pulse| pulse.is_finite());
+        let first = finite.next().unwrap_or(f64::NAN);
+        let direction = (finite.next_back().unwrap_or(first) - first).signum();
+
+        for pair in pulses.windows(2) {
+            let ((_, last), (angle, pulse)) = (pair[0], pair[1]);
+            report.checked += 1;
+
+            let step = (pulse - last) * direction;
+            if step.is_nan() || step <= 0. {
+                report.add(Check::Servo, name, angle, step.abs());
+            }
+        }
+    }
+
+    /// Every reachable pose has to come back out of the inverse kinematics
+    fn kinematics(&self, report: &mut AuditReport, arm: &Arm, upper_arm: f64, lower_arm: f64) {
+        for base in self.angles(&arm.base) {
+            for shoulder in self.angles(&arm.shoulder) {
+                for elbow in self.angles(&arm.elbow) {
+                    report.checked += 1;
+                    let mut position = CordinateVec::forward_kinematics(
+                        base, shoulder, elbow, upper_arm, lower_arm,
+                    );
+
+                    let errors = match position.inverse_kinematics(upper_arm, lower_arm) {
+                        Ok(back) => [
+                            (back.0 - base).abs(),
+                            (back.1 - shoulder).abs(),
+                            (back.2 - elbow).abs(),
+                        ],
+                        Err(_) => [f64::INFINITY; 3],
+                    };
+
+                    // only the joint that is off the most, the others usually follow from it
+                    let worst = [("base", base), ("shoulder", shoulder), ("elbow", elbow)]
+                        .into_iter()
+                        .zip(errors.map(|error| if error.is_nan() { f64::INFINITY } else { error }))
+                        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+                    if let Some(((name, angle), error)) = worst {
+                        if error > self.tolerance {
+                            report.add(Check::Kinematics, name, angle, error);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Robot {
+    /// Run the default [`Audit`] on the arm
+    pub fn audit(&self) -> AuditReport {
+        Audit::default().run(&self.arm, self.upper_arm, self.lower_arm)
+    }
+}
+
+impl AuditReport {
+    /// Warn about every violation and sum up
+    pub fn log(&self) {
+        for violation in &self.violations {
+            warn(&format!("audit: {violation}"));
+        }
+        info(&format!(
+            "audit: {} samples checked, {} violations",
+            self.checked,
+            self.violations.len()
+        ));
+    }
+
+    /// Write the report as json
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(path, data)
+    }
+
+    /// Count a failed sample, keeping the largest error per check and joint
+    fn add(&mut self, check: Check, joint: &'static str, angle: f64, error: f64) {
+        let error = if error.is_nan() { f64::INFINITY } else { error };
+
+        let existing = self
+            .violations
+            .iter_mut()
+            .find(|violation| violation.check == check && violation.joint == joint);
+        match existing {
+            Some(violation) => {
+                violation.count += 1;
+                if error > violation.error {
+                    violation.angle = angle;
+                    violation.error = error;
+                }
+            }
+            None => self.violations.push(Violation {
+                check,
+                joint,
+                angle,
+                error,
+                count: 1,
+            }),
+        }
+    }
+}
+
+impl Default for Audit {
+    fn default() -> Self {
+        Self {
+            samples: 18,
+            tolerance: 0.01,
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Check::Limits => write!(f, "limits"),
+            Check::Motion => write!(f, "motion"),
+            Check::Servo => write!(f, "servo mapping"),
+            Check::Kinematics => write!(f, "kinematics"),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: off by {:.3} at {:.1}° ({} samples)",
+            self.joint, self.check, self.error, self.angle, self.count
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kinematics::joints::{DirectDrive, DirectDriveOffset, DoubleLinkage};
+
+    /// An arm that stays within what the inverse kinematics cover, the head never leans past
+    /// the z axis or the shoulder past 90°
+    fn arm() -> Arm {
+        Arm {
+            base: Joint::new(90., 180., DirectDriveOffset { offset: 10. }),
+            shoulder: Joint::new(45., 85., DoubleLinkage::new(1., 10., 10., 1., 10., 20.)),
+            elbow: Joint::new(100., 170., DirectDrive::new()),
+            claw: Joint::default(),
+            wrist: None,
+        }
+    }
+
+    fn found(report: &AuditReport, check: Check, joint: &str) -> Option<Violation> {
+        report
+            .violations
+            .iter()
+            .find(|violation| violation.check == check && violation.joint == joint)
+            .copied()
+    }
+
+    #[test]
+    fn consistent_arm_passes() {
+        let report = Audit::default().run(&arm(), 100., 100.);
+        assert!(report.violations.is_empty(), "{:?}", report.violations);
+        assert!(report.checked > 18usize.pow(3));
+    }
+
+    #[test]
+    fn broken_linkage() {
+        // the rods are too short to reach the arm over most of the range
+        let mut arm = arm();
+        arm.shoulder.motion = DoubleLinkage::new(1., 10., 10., 1., 4., 4.).into();
+
+        let report = Audit::default().run(&arm, 100., 100.);
+        let motion = found(&report, Check::Motion, "shoulder").unwrap();
+        assert!((45. ..=85.).contains(&motion.angle));
+        assert_eq!(motion.error, f64::INFINITY);
+        assert!(found(&report, Check::Servo, "shoulder").is_some());
+
+        // the other joints and the kinematics don't depend on the linkage
+        assert!(report
+            .violations
+            .iter()
+            .all(|violation| violation.joint == "shoulder"));
+        assert!(found(&report, Check::Kinematics, "shoulder").is_none());
+    }
+
+    #[test]
+    fn linkage_past_its_reach() {
+        // fine at first, the rods stop reaching the arm past about 110°
+        let mut arm = arm();
+        arm.claw = Joint::new(0., 180., DoubleLinkage::new(1., 10., 10., 1., 10., 20.));
+
+        let report = Audit::default().run(&arm, 100., 100.);
+        let motion = found(&report, Check::Motion, "claw").unwrap();
+        let servo = found(&report, Check::Servo, "claw").unwrap();
+        assert!(motion.angle > 110.);
+        assert!(servo.angle > 110.);
+        assert!(servo.count < 18);
+    }
+
+    #[test]
+    fn limits_and_kinematics() {
+        let mut arm = arm();
+        arm.claw = Joint::new(90., 10., DirectDrive::new());
+        // leaning past 90° the shoulder still comes back the same from the inverse kinematics
+        arm.shoulder = Joint::new(45., 135., DirectDrive::new());
+
+        let report = Audit::default().run(&arm, 100., 100.);
+        assert!(report.violations.contains(&Violation {
+            check: Check::Limits,
+            joint: "claw",
+            angle: 90.,
+            error: 80.,
+            count: 1,
+        }));
+
+        assert_eq!(found(&report, Check::Kinematics, "shoulder"), None);
+    }
+
+    #[test]
+    fn elbow_past_straight() {
+        // the inverse kinematics bend it back the other way
+        let mut arm = arm();
+        arm.elbow = Joint::new(90., 270., DirectDrive::new());
+        let report = Audit::default().run(&arm, 100., 100.);
+        let kinematics = found(&report, Check::Kinematics, "elbow").unwrap();
+        assert!(kinematics.angle > 180.);
+        assert!(kinematics.error > 1.);
+    }
+
+    #[test]
+    fn report_json() {
+        let mut report = AuditReport {
+            checked: 3,
+            violations: Vec::new(),
+        };
+        report.add(Check::Motion, "elbow", 40., 0.5);
+        report.add(Check::Motion, "elbow", 60., f64::NAN);
+        report.add(Check::Motion, "elbow", 80., 2.);
+
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].count, 3);
+        assert_eq!(report.violations[0].angle, 60.);
+
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["violations"][0]["check"], "motion");
+        assert_eq!(json["violations"][0]["joint"], "elbow");
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{envelope::EnvelopeMode, Robot};
+use crate::kinematics::{
+    constraint::{resolve, Floor, MinReach, Reach, WorkspaceConstraint},
+    position::CordinateVec,
+};
+
+/// Slowest a soft margin lets the head approach the boundary, as a share of the speed it's
+/// commanded, so it still gets there
+pub const MIN_MARGIN_SCALE: f64 = 0.1;
+
+/// What the head does at the edge of where it may go, see [`Robot::clamp`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryPolicy {
+    /// Go full speed up to the boundary and stop dead on it
+    #[default]
+    HardClamp,
+
+    /// Slow down within [`BoundaryConfig::margin`] of the boundary, then stop on it
+    SoftMargin,
+
+    /// Only the reach of the arm holds the head, at no more than
+    /// [`BoundaryConfig::max_speed`], for calibrating
+    Off,
+}
+
+/// How the head is kept where it may go
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BoundaryConfig {
+    pub policy: BoundaryPolicy,
+
+    /// Distance from the boundary in units from where a soft margin slows the head down
+    pub margin: f64,
+
+    /// Fastest the head may go in units/s with the boundary off, which can't be turned off
+    /// without it
+    pub max_speed: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoundaryError {
+    /// Turning the boundary off without [`BoundaryConfig::max_speed`]
+    NoSpeedCap,
+}
+
+impl Robot {
+    /// Change what the head does at the boundary
+    pub fn set_boundary_policy(&mut self, policy: BoundaryPolicy) -> Result<(), BoundaryError> {
+        if policy == BoundaryPolicy::Off && self.boundary.max_speed.is_none() {
+            return Err(BoundaryError::NoSpeedCap);
+        }
+
+        self.boundary.policy = policy;
+        Ok(())
+    }
+
+    /// Closest position to `position` the head may be at
+    ///
+    /// Within the reach of the arm for the payload and out of the dead zone around the
+    /// shoulder, above [`Robot::min_z`], out of the floor, keep out zones and obstacles, inside
+    /// the taught envelope once the head got there and within the custom constraints. With the
+    /// boundary off only the reach and [`Robot::min_z`] are left
+    pub fn clamp(&self, position: CordinateVec) -> CordinateVec {
+        let reach = Reach {
+            radius: self.reach(),
+        };
+        let min_reach = MinReach {
+            radius: self.min_reach(),
+        };
+        let min_z = self.min_z.map(|z| Floor { z });
+        let arm: [Option<&dyn WorkspaceConstraint>; 3] = [
+            Some(&reach),
+            Some(&min_reach),
+            min_z.as_ref().map(|floor| floor as &dyn WorkspaceConstraint),
+        ];
+        if self.boundary.policy == BoundaryPolicy::Off {
+            return resolve(arm.into_iter().flatten(), position);
+        }
+
+        let enforce_envelope = self.envelope_mode == EnvelopeMode::Enforce && self.envelope_entered;
+        let builtin: [Option<&dyn WorkspaceConstraint>; 2] = [
+            self.workspaces
+                .current()
+                .map(|workspace| workspace as &dyn WorkspaceConstraint),
+            enforce_envelope.then_some(&self.envelope as &dyn WorkspaceConstraint),
+        ];
+        let obstacles = self
+            .obstacles
+            .iter()
+            .map(|zone| zone as &dyn WorkspaceConstraint);
+        let custom = self
+            .constraints
+            .iter()
+            .map(|constraint| constraint.as_ref());
+        resolve(
+            arm.into_iter()
+                .chain(builtin)
+                .flatten()
+                .chain(obstacles)
+                .chain(custom),
+            position,
+        )
+    }
+
+    /// `velocity` as the boundary policy lets the head go from where it is
+    ///
+    /// A soft margin looks [`BoundaryConfig::margin`] ahead along the velocity and slows down
+    /// by however much of that is past the boundary, down to [`MIN_MARGIN_SCALE`]. Heading away
+    /// from the boundary isn't slowed
+    pub fn boundary_velocity(&self, velocity: CordinateVec) -> CordinateVec {
+        let speed = velocity.dst();
+        match self.boundary.policy {
+            BoundaryPolicy::HardClamp => velocity,
+            BoundaryPolicy::SoftMargin if speed > 0. && self.boundary.margin > 0. => {
+                let margin = self.boundary.margin;
+                let ahead = self.position + velocity.scale_to(margin);
+                let past = (ahead - self.clamp(ahead)).dst();
+                velocity * (1. - past / margin).clamp(MIN_MARGIN_SCALE, 1.)
+            }
+            BoundaryPolicy::SoftMargin => velocity,
+            BoundaryPolicy::Off => match self.boundary.max_speed {
+                Some(cap) if speed > cap => velocity.scale_to(cap),
+                _ => velocity,
+            },
+        }
+    }
+}
+
+impl Default for BoundaryConfig {
+    fn default() -> Self {
+        Self {
+            policy: BoundaryPolicy::HardClamp,
+            margin: 20.,
+            max_speed: None,
+        }
+    }
+}
+
+impl fmt::Display for BoundaryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoundaryPolicy::HardClamp => write!(f, "hard"),
+            BoundaryPolicy::SoftMargin => write!(f, "soft"),
+            BoundaryPolicy::Off => write!(f, "off"),
+        }
+    }
+}
+
+impl fmt::Display for BoundaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoundaryError::NoSpeedCap => {
+                write!(f, "the boundary can't be turned off without a max_speed")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::robot::workspace::{Workspace, Workspaces};
+
+    /// Above a floor at 0, coming down at 20 units/s
+    fn descending(policy: BoundaryPolicy) -> Robot {
+        let mut robo = Robot {
+            position: CordinateVec::new(100., 50., 40.),
+            workspaces: Workspaces {
+                profiles: [(
+                    "bench".to_string(),
+                    Workspace {
+                        floor: Some(0.),
+                        ..Default::default()
+                    },
+                )]
+                .into(),
+                active: Some("bench".to_string()),
+                ..Default::default()
+            },
+            boundary: BoundaryConfig {
+                max_speed: Some(5.),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        robo.set_boundary_policy(policy).unwrap();
+        robo.command_velocity(CordinateVec::new(0., 0., -20.));
+        robo
+    }
+
+    /// Heights every 0.1s for `seconds`
+    fn heights(robo: &mut Robot, seconds: usize) -> Vec<f64> {
+        (0..seconds * 10)
+            .map(|_| {
+                for _ in 0..10 {
+                    robo.tick(0.01);
+                }
+                robo.position.z
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hard_clamp() {
+        let mut robo = descending(BoundaryPolicy::HardClamp);
+        let heights = heights(&mut robo, 4);
+
+        // up to speed in 0.2s, then full speed until the floor and it stays on it
+        assert!(
+            (heights[9] - (40. - 0.2 * 20. / 2. - 0.8 * 20.)).abs() < 1e-6,
+            "{heights:?}"
+        );
+        assert!((heights[19] - 2.).abs() < 1e-6);
+        assert_eq!(heights[20], 0.);
+        assert!(heights.iter().all(|&z| z >= 0.));
+        assert_eq!(robo.position.z, 0.);
+    }
+
+    #[test]
+    fn soft_margin() {
+        let mut robo = descending(BoundaryPolicy::SoftMargin);
+        let heights = heights(&mut robo, 8);
+
+        // full speed until the margin
+        assert!(
+            (heights[9] - (40. - 0.2 * 20. / 2. - 0.8 * 20.)).abs() < 1e-6,
+            "{heights:?}"
+        );
+
+        // slower and slower within it, never past the floor
+        let speed = |from: usize| (heights[from] - heights[from + 1]) * 10.;
+        let near = heights.iter().position(|&z| z < 10.).unwrap();
+        assert!(speed(near) < 12., "{heights:?}");
+        assert!(speed(near + 3) < speed(near));
+        assert!(heights.iter().all(|&z| z >= 0.));
+
+        // creeping in at the slowest it gets there
+        assert_eq!(robo.position.z, 0.);
+
+        // going back up isn't slowed
+        robo.command_velocity(CordinateVec::new(0., 0., 20.));
+        let heights = self::heights(&mut robo, 1);
+        assert!((heights[9] - heights[8] - 2.).abs() < 1e-6, "{heights:?}");
+    }
+
+    #[test]
+    fn off() {
+        let mut robo = descending(BoundaryPolicy::Off);
+        let heights = heights(&mut robo, 12);
+
+        // through the floor at the speed cap
+        assert!((heights[10] - heights[20] - 5.).abs() < 1e-6, "{heights:?}");
+        assert!(robo.position.z < -10.);
+
+        // but not past the reach of the arm
+        robo.command_velocity(CordinateVec::new(20., 0., 0.));
+        self::heights(&mut robo, 60);
+        assert!((robo.position.dst() - robo.reach()).abs() < 1e-6);
+
+        // back on it pushes out of the floor
+        robo.set_boundary_policy(BoundaryPolicy::HardClamp).unwrap();
+        robo.tick(0.01);
+        assert!(robo.position.z >= 0.);
+
+        // it can't be turned off without a speed cap
+        robo.boundary.max_speed = None;
+        assert_eq!(
+            robo.set_boundary_policy(BoundaryPolicy::Off),
+            Err(BoundaryError::NoSpeedCap)
+        );
+        assert_eq!(robo.boundary.policy, BoundaryPolicy::HardClamp);
+    }
+}
@@ -0,0 +1,201 @@
+//! Every log of a session in one directory, for attaching to a bug report
+//!
+//! A bundle holds:
+//! * [`CONFIG`], the resolved config with its hash, see [`crate::config::Config::dump`]
+//! * [`LOG`], everything logged so far, see [`crate::logging::history`]
+//! * [`SEGMENTS`], a copy of the segment stream when streaming to a file, see
+//!   [`super::segment_stream`]
+//! * [`MANIFEST`], written last, see [`Manifest`]
+//!
+//! Every file carries the session id and its times are seconds on the session clock, so the
+//! logs line up with each other, see [`crate::session::clock`]
+
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::Robot;
+use crate::{
+    logging,
+    session::{self, SessionHeader},
+};
+
+pub const MANIFEST: &str = "manifest.json";
+pub const CONFIG: &str = "config.json";
+pub const LOG: &str = "log.txt";
+pub const SEGMENTS: &str = "segments.csv";
+
+/// Where the logs are bundled at the end of the session and what goes in them besides the
+/// robot's own state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bundle {
+    /// Directory bundled into when the session ends, empty for none
+    pub on_exit: String,
+
+    /// The config the robot was built from as written by [`crate::config::Config::dump`]
+    pub config: String,
+}
+
+/// What is in a bundle
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Manifest {
+    pub session_id: String,
+    pub config_hash: String,
+    pub header: SessionHeader,
+
+    /// Session time the bundle was written at
+    pub written_at: f64,
+
+    pub files: Vec<BundleFile>,
+}
+
+/// One file of a bundle and the session times of its first and last line
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BundleFile {
+    pub name: &'static str,
+    pub first: Option<f64>,
+    pub last: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl Robot {
+    /// Write every log of the session into `dir`, creating it if needed
+    pub fn write_bundle(&mut self, dir: impl AsRef<Path>) -> Result<Manifest, BundleError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(BundleError::Io)?;
+        let session_id = session::clock().id.clone();
+        let mut files = Vec::new();
+
+        let mut config = match serde_json::from_str(&self.bundle.config) {
+            Ok(Value::Object(config)) => config,
+            _ => Map::new(),
+        };
+        config.insert("session_id".to_string(), Value::String(session_id.clone()));
+        write_json(&dir.join(CONFIG), &config)?;
+        files.push(BundleFile {
+            name: CONFIG,
+            first: None,
+            last: None,
+        });
+
+        let history = logging::history();
+        let mut log = format!("# session {session_id}\n");
+        for (time, line) in &history {
+            log += &format!("{time:.3} {line}\n");
+        }
+        fs::write(dir.join(LOG), log).map_err(BundleError::Io)?;
+        files.push(BundleFile {
+            name: LOG,
+            first: history.first().map(|(time, _)| *time),
+            last: history.last().map(|(time, _)| *time),
+        });
+
+        if let Some(stream) = &mut self.segment_stream {
+            if let Some(path) = stream.path.clone() {
+                stream.flush().map_err(BundleError::Io)?;
+                fs::copy(path, dir.join(SEGMENTS)).map_err(BundleError::Io)?;
+                files.push(BundleFile {
+                    name: SEGMENTS,
+                    first: stream.first,
+                    last: stream.last,
+                });
+            }
+        }
+
+        let manifest = Manifest {
+            session_id,
+            config_hash: format!("{:016x}", self.config_hash),
+            header: SessionHeader::new(self),
+            written_at: session::clock().now(),
+            files,
+        };
+        write_json(&dir.join(MANIFEST), &manifest)?;
+        Ok(manifest)
+    }
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> Result<(), BundleError> {
+    let json = serde_json::to_string_pretty(value).map_err(BundleError::Parse)?;
+    let mut file = fs::File::create(path).map_err(BundleError::Io)?;
+    writeln!(file, "{json}").map_err(BundleError::Io)
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::Io(err) => write!(f, "{err}"),
+            BundleError::Parse(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{logging::info, robot::segment_stream::SegmentStream};
+
+    #[test]
+    fn same_session() {
+        let root = std::env::temp_dir().join(format!("rac-bundle-{}", std::process::id()));
+        let stream = SegmentStream::open(root.with_extension("csv").to_str().unwrap(), 1);
+        let mut robo = Robot {
+            config_hash: 0xabc,
+            bundle: Bundle {
+                config: r#"{"config_hash": "0000000000000abc", "upper_arm": 100.0}"#.to_string(),
+                ..Default::default()
+            },
+            segment_stream: Some(stream.unwrap()),
+            ..Default::default()
+        };
+
+        // the segments and the log interleave on the session clock
+        let segments = robo.segments();
+        let stream = robo.segment_stream.as_mut().unwrap();
+        stream.frame(session::clock().now(), &segments).unwrap();
+        info("bundle test");
+        stream.frame(session::clock().now(), &segments).unwrap();
+
+        let manifest = robo.write_bundle(root.join("bundle"));
+        let read = |name| fs::read_to_string(root.join("bundle").join(name));
+        let (config, log, copied, written) =
+            (read(CONFIG), read(LOG), read(SEGMENTS), read(MANIFEST));
+        let _ = fs::remove_dir_all(&root);
+        let _ = fs::remove_file(root.with_extension("csv"));
+
+        let manifest = manifest.unwrap();
+        let id = &session::clock().id;
+        assert_eq!(&manifest.session_id, id);
+        assert_eq!(manifest.config_hash, "0000000000000abc");
+        assert_eq!(&manifest.header.session_id, id);
+        let names: Vec<_> = manifest.files.iter().map(|file| file.name).collect();
+        assert_eq!(names, [CONFIG, LOG, SEGMENTS]);
+
+        // every file names the session
+        let written: Value = serde_json::from_str(&written.unwrap()).unwrap();
+        assert_eq!(written["session_id"], id.as_str());
+        let config: Value = serde_json::from_str(&config.unwrap()).unwrap();
+        assert_eq!(config["session_id"], id.as_str());
+        assert_eq!(config["upper_arm"], 100.);
+        let session = format!("# session {id}");
+        let log = log.unwrap();
+        assert_eq!(log.lines().next(), Some(session.as_str()));
+        assert!(log.contains(" INFO: bundle test\n"));
+        assert_eq!(copied.unwrap().lines().next(), Some(session.as_str()));
+
+        // and the times overlap
+        let (log, segments) = (&manifest.files[1], &manifest.files[2]);
+        assert!(log.first.unwrap() <= segments.last.unwrap());
+        assert!(segments.first.unwrap() <= log.last.unwrap());
+        assert!(segments.last.unwrap() <= manifest.written_at);
+    }
+}
@@ -0,0 +1,401 @@
+//! Two arms working next to each other
+//!
+//! The first arm leads, its frame is the one positions of both are given in. The second one
+//! follows, its shoulder sits at [`CoordinationConfig::offset`] in that frame facing the same
+//! way. Each arm's head is kept out of a box around the other arm with the keep out machinery,
+//! see [`Robot::obstacles`]
+
+use serde::{Deserialize, Serialize};
+
+use super::{workspace::KeepOut, Robot};
+use crate::kinematics::position::CordinateVec;
+
+/// What the follower does, see [`Coordinator::coordinate`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoordinationMode {
+    /// Each arm does what it's told, only kept out of the other one
+    #[default]
+    Independent,
+
+    /// The follower copies the motion of the leader reflected in
+    /// [`CoordinationConfig::plane`]
+    Mirror,
+}
+
+/// Plane through `point` at right angles to `normal`, in the leader's frame
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReflectionPlane {
+    pub point: CordinateVec,
+    pub normal: CordinateVec,
+}
+
+/// How a second arm works with the first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoordinationConfig {
+    /// Drive a second arm at all
+    pub enabled: bool,
+
+    /// Serial port the follower's arduino is connected to, at the leader's baud rates
+    pub port: String,
+
+    pub mode: CoordinationMode,
+
+    /// Where the follower's shoulder is in the leader's frame
+    pub offset: CordinateVec,
+
+    /// What the leader is mirrored in, halfway between the arms by default
+    pub plane: ReflectionPlane,
+
+    /// How far each head stays from the box around the other arm
+    pub clearance: f64,
+}
+
+/// Keeps two arms out of each other and moves them together
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coordinator {
+    pub config: CoordinationConfig,
+
+    /// Cruise speed and acceleration of both arms before [`Coordinator::move_together`]
+    /// stretched one of them, until both arrived
+    stretched: Option<[(Option<f64>, f64); 2]>,
+}
+
+impl ReflectionPlane {
+    /// `position` mirrored to the other side of the plane, as it is without a normal
+    pub fn reflect(&self, position: CordinateVec) -> CordinateVec {
+        let length = self.normal.dst();
+        if length == 0. {
+            return position;
+        }
+
+        let normal = self.normal * (1. / length);
+        position - normal * (2. * (position - self.point).dot(&normal))
+    }
+}
+
+impl Coordinator {
+    pub fn new(config: CoordinationConfig) -> Self {
+        Self {
+            config,
+            stretched: None,
+        }
+    }
+
+    /// Keep the heads out of each other and mirror the leader, call before every update of
+    /// the arms
+    ///
+    /// A mirroring follower is sent where the leader is headed, or where it is without a
+    /// target, so both take the same path once they are mirrored. Nothing is mirrored during
+    /// [`Coordinator::move_together`]
+    pub fn coordinate(&mut self, leader: &mut Robot, follower: &mut Robot) {
+        if self.stretched.is_some() {
+            if leader.target_position.is_none() && follower.target_position.is_none() {
+                self.restore(leader, follower);
+            }
+        } else if self.config.mode == CoordinationMode::Mirror {
+            let leading = leader.target_position.unwrap_or(leader.position);
+            follower.target_position =
+                Some(self.config.plane.reflect(leading) - self.config.offset);
+        }
+
+        let offset = self.config.offset;
+        let clearance = self.config.clearance;
+        keep_out(
+            follower,
+            volume(leader, CordinateVec::default() - offset, clearance),
+        );
+        keep_out(leader, volume(follower, offset, clearance));
+    }
+
+    /// Move both arms from rest to their targets so they start and end together
+    ///
+    /// The arm that would get there first has its cruise speed scaled by how much sooner and
+    /// its acceleration by the square of it, which stretches its whole motion to take as long
+    /// as the other's. Both get their own back once they arrived, see
+    /// [`Coordinator::coordinate`]
+    ///
+    /// # Arguments
+    /// * `targets` - of the leader and the follower, each in its own frame like
+    ///   [`Robot::retarget`]
+    pub fn move_together(
+        &mut self,
+        leader: &mut Robot,
+        follower: &mut Robot,
+        targets: [CordinateVec; 2],
+    ) {
+        self.restore(leader, follower);
+        self.stretched = Some([
+            (leader.cruise_speed, leader.acceleration),
+            (follower.cruise_speed, follower.acceleration),
+        ]);
+        leader.retarget(targets[0]);
+        follower.retarget(targets[1]);
+
+        let durations = [&*leader, &*follower].map(|arm| {
+            let distance = arm
+                .target_position
+                .map_or(0., |target| arm.position.distance_to(&target));
+            profile_duration(
+                distance,
+                arm.cruise_speed.unwrap_or(f64::INFINITY),
+                arm.acceleration,
+            )
+        });
+        let slowest = durations[0].max(durations[1]);
+        for (arm, duration) in [leader, follower].into_iter().zip(durations) {
+            if duration > 0. && duration < slowest {
+                let scale = duration / slowest;
+                arm.cruise_speed = arm.cruise_speed.map(|speed| speed * scale);
+                arm.acceleration *= scale * scale;
+            }
+        }
+    }
+
+    /// Give the arms back the cruise speed and acceleration a move together stretched
+    fn restore(&mut self, leader: &mut Robot, follower: &mut Robot) {
+        if let Some(
+            [(leader_speed, leader_acceleration), (follower_speed, follower_acceleration)],
+        ) = self.stretched.take()
+        {
+            leader.cruise_speed = leader_speed;
+            leader.acceleration = leader_acceleration;
+            follower.cruise_speed = follower_speed;
+            follower.acceleration = follower_acceleration;
+        }
+    }
+}
+
+/// Seconds a move of `distance` takes from rest to rest, cruising at `speed` and speeding up
+/// and braking at `acceleration`
+///
+/// Only exact along an axis, the acceleration of [`Robot::update_velocity`] is per axis
+pub fn profile_duration(distance: f64, speed: f64, acceleration: f64) -> f64 {
+    if distance <= 0. {
+        return 0.;
+    }
+
+    // distance it takes to get up to speed and back to rest
+    let ramps = speed * speed / acceleration;
+    if distance >= ramps {
+        distance / speed + speed / acceleration
+    } else {
+        2. * (distance / acceleration).sqrt()
+    }
+}
+
+/// Box around the segments of `robot` grown by `clearance`, moved by `offset`
+fn volume(robot: &Robot, offset: CordinateVec, clearance: f64) -> KeepOut {
+    let segments = robot.segments();
+    let points = [segments.shoulder, segments.elbow, segments.head];
+    let corner = |pick: fn(f64, f64) -> f64| {
+        points
+            .into_iter()
+            .reduce(|a, b| CordinateVec::new(pick(a.x, b.x), pick(a.y, b.y), pick(a.z, b.z)))
+            .unwrap_or_default()
+            + offset
+    };
+    let margin = CordinateVec::new(clearance, clearance, clearance);
+
+    KeepOut {
+        min: corner(f64::min) - margin,
+        max: corner(f64::max) + margin,
+    }
+}
+
+/// Make `zone` the only obstacle of `robot`, without allocating once it has one
+fn keep_out(robot: &mut Robot, zone: KeepOut) {
+    robot.obstacles.clear();
+    robot.obstacles.push(zone);
+}
+
+impl Default for CoordinationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: String::new(),
+            mode: CoordinationMode::Independent,
+            offset: CordinateVec::new(0., 300., 0.),
+            plane: ReflectionPlane {
+                point: CordinateVec::new(0., 150., 0.),
+                normal: CordinateVec::new(0., 1., 0.),
+            },
+            clearance: 20.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kinematics_core::assert_approx_eq;
+
+    fn at(position: CordinateVec) -> Robot {
+        Robot {
+            position,
+            cruise_speed: Some(50.),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reflect() {
+        let plane = CoordinationConfig::default().plane;
+        assert_eq!(
+            plane.reflect(CordinateVec::new(20., 100., 30.)),
+            CordinateVec::new(20., 200., 30.)
+        );
+
+        // any length of normal, through any point of the plane
+        let plane = ReflectionPlane {
+            point: CordinateVec::new(0., 10., 0.),
+            normal: CordinateVec::new(0., -3., 0.),
+        };
+        assert_eq!(
+            plane.reflect(CordinateVec::new(1., 25., 2.)),
+            CordinateVec::new(1., -5., 2.)
+        );
+        let flat = ReflectionPlane {
+            normal: CordinateVec::default(),
+            ..plane
+        };
+        assert_eq!(
+            flat.reflect(CordinateVec::new(1., 2., 3.)),
+            CordinateVec::new(1., 2., 3.)
+        );
+    }
+
+    #[test]
+    fn mirror() {
+        let mut coordinator = Coordinator::new(CoordinationConfig {
+            enabled: true,
+            mode: CoordinationMode::Mirror,
+            offset: CordinateVec::new(300., 0., 0.),
+            plane: ReflectionPlane {
+                point: CordinateVec::new(200., 0., 0.),
+                normal: CordinateVec::new(1., 0., 0.),
+            },
+            ..Default::default()
+        });
+        let config = coordinator.config.clone();
+        let mirrored = |leader: &Robot| config.plane.reflect(leader.position) - config.offset;
+
+        let mut leader = at(CordinateVec::new(40., 50., 80.));
+        let mut follower = at(CordinateVec::new(60., 50., 80.));
+        leader.retarget(CordinateVec::new(80., 100., 60.));
+
+        // the follower takes the same path on the other side of the plane, at the same time
+        for _ in 0..500 {
+            coordinator.coordinate(&mut leader, &mut follower);
+            leader.tick(0.01);
+            follower.tick(0.01);
+            assert!(
+                (follower.position - mirrored(&leader)).dst() < 1e-9,
+                "{:?} {:?}",
+                leader.position,
+                follower.position
+            );
+        }
+        assert_eq!(leader.target_position, None);
+        assert_eq!(follower.position, CordinateVec::new(20., 100., 60.));
+
+        // and follows a jog the same way
+        leader.command_velocity(CordinateVec::new(0., 0., 10.));
+        for _ in 0..100 {
+            coordinator.coordinate(&mut leader, &mut follower);
+            leader.tick(0.01);
+            follower.tick(0.01);
+        }
+        for _ in 0..300 {
+            coordinator.coordinate(&mut leader, &mut follower);
+            follower.tick(0.01);
+        }
+        assert!(leader.position.z > 65.);
+        assert_approx_eq!(follower.position, mirrored(&leader), 1e-3);
+    }
+
+    #[test]
+    fn keep_out_of_each_other() {
+        let mut coordinator = Coordinator::new(CoordinationConfig {
+            enabled: true,
+            offset: CordinateVec::new(150., -100., 0.),
+            ..Default::default()
+        });
+        let mut leader = at(CordinateVec::new(100., 20., 60.));
+        let mut follower = at(CordinateVec::new(30., 150., 60.));
+        // the boxes are around where the joints are
+        leader.tick(0.01);
+        follower.tick(0.01);
+
+        // the leader reaches for where the follower's head is, it stops at the box around it
+        // and slides along it
+        leader.retarget(CordinateVec::new(170., 50., 60.));
+        for _ in 0..500 {
+            coordinator.coordinate(&mut leader, &mut follower);
+            leader.tick(0.01);
+            let zone = leader.obstacles[0];
+            assert!(!zone.contains(leader.position), "{:?} {zone:?}", leader.position);
+        }
+        let zone = leader.obstacles[0];
+        assert!(leader.target_position.is_some());
+        assert!((leader.position.x - zone.min.x).abs() < 1e-9, "{:?}", leader.position);
+        assert!((leader.position.y - 50.).abs() < 0.5, "{:?}", leader.position);
+        assert!(zone.min.x <= 150. - 20.);
+
+        // the box around the leader keeps the follower out the same way
+        let zone = follower.obstacles[0];
+        assert!(zone.max.x >= leader.position.x + 20. - 150. - 1e-9);
+        assert!(!zone.contains(follower.position));
+    }
+
+    #[test]
+    fn together() {
+        assert_eq!(profile_duration(100., 50., 100.), 2.5);
+        assert_eq!(profile_duration(25., 50., 100.), 1.);
+        assert_eq!(profile_duration(4., f64::INFINITY, 100.), 0.4);
+        assert_eq!(profile_duration(0., 50., 100.), 0.);
+
+        let mut coordinator = Coordinator::new(CoordinationConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        let mut leader = at(CordinateVec::new(20., 100., 50.));
+        let mut follower = at(CordinateVec::new(20., 100., 50.));
+
+        // 2.5s for the leader, the follower would be done in 1s on its own
+        coordinator.move_together(
+            &mut leader,
+            &mut follower,
+            [
+                CordinateVec::new(120., 100., 50.),
+                CordinateVec::new(45., 100., 50.),
+            ],
+        );
+        assert_eq!(follower.cruise_speed, Some(20.));
+        assert!((follower.acceleration - 16.).abs() < 1e-9);
+
+        let mut arrived = [None, None];
+        for tick in 1..=400 {
+            coordinator.coordinate(&mut leader, &mut follower);
+            leader.tick(0.01);
+            follower.tick(0.01);
+            for (arrived, arm) in arrived.iter_mut().zip([&leader, &follower]) {
+                if arm.target_position.is_none() {
+                    arrived.get_or_insert(tick as f64 * 0.01);
+                }
+            }
+        }
+        let [Some(leader_time), Some(follower_time)] = arrived else {
+            panic!("{arrived:?}");
+        };
+        // the last bit of braking takes a little longer than the profile
+        assert!(leader_time > 2.5 && leader_time < 3., "{arrived:?}");
+        assert!((leader_time - follower_time).abs() < 0.1, "{arrived:?}");
+
+        // both are back to their own speed and acceleration
+        assert_eq!(follower.cruise_speed, Some(50.));
+        assert_eq!(follower.acceleration, 100.);
+        assert_eq!(leader.acceleration, 100.);
+    }
+}
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+
+use super::arm::JointAngles;
+use crate::control::{Gains, Pid};
+
+/// How hard the servos are pushed to the commanded angles, see [`FeedbackCorrection`]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorrectionConfig {
+    /// Correct at all, the robot only gets a [`FeedbackCorrection`] if this is set
+    pub enabled: bool,
+
+    /// Degrees of trim per degree the reported angle is off
+    pub gains: Gains,
+
+    /// Largest trim either way in degrees
+    pub max_trim: f64,
+
+    /// Time constant of the low pass on the derivative in seconds
+    ///
+    /// Feedback pulse widths are whole µs, without it the derivative is mostly noise
+    pub derivative_filter: f64,
+}
+
+/// Trims the angles sent to the servos so the angles they report end up where they are
+/// commanded
+///
+/// A servo sagging under load or with its horn a tooth off settles short of the pulse width
+/// it's sent. A [`Pid`] per joint turns how far the reported angle is off into a trim added to
+/// the commanded angle when it's sent. With an integral gain that settles on the commanded
+/// angle, and a servo that does what it's sent ends up without a trim
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedbackCorrection {
+    pub config: CorrectionConfig,
+
+    /// One per joint, in the order of [`JointAngles`]
+    pids: [Pid; 4],
+
+    /// Reported angles waiting for the next [`FeedbackCorrection::update`]
+    pending: Option<JointAngles>,
+
+    /// Seconds since the previous report, `None` until the first one after a reset
+    elapsed: Option<f64>,
+
+    /// Degrees every joint is sent past its commanded angle
+    pub trim: JointAngles,
+}
+
+impl FeedbackCorrection {
+    pub fn new(config: CorrectionConfig) -> Self {
+        let pid = Pid::new(config.gains)
+            .limits(-config.max_trim, config.max_trim)
+            .filtered(config.derivative_filter);
+        Self {
+            config,
+            pids: std::array::from_fn(|_| pid.clone()),
+            pending: None,
+            elapsed: None,
+            trim: JointAngles::default(),
+        }
+    }
+
+    /// The feedback reported the joints at these angles
+    pub fn report(&mut self, angles: JointAngles) {
+        self.pending = Some(angles);
+    }
+
+    /// Correct the trims for the latest report
+    ///
+    /// Ticks without a new report keep the trims and only add to the time between reports, so
+    /// a quiet arduino doesn't wind them up on a stale angle. The first report after a reset
+    /// only takes over the trims as they are, they ease in from there instead of jumping to
+    /// how far off the servos happen to be
+    ///
+    /// # Arguments
+    /// * `commanded` - the angles the joints are commanded to, clamped to their range
+    /// * `delta` - seconds since the last call
+    pub fn update(&mut self, commanded: JointAngles, delta: f64) {
+        if let Some(elapsed) = &mut self.elapsed {
+            *elapsed += delta;
+        }
+        let Some(reported) = self.pending.take() else {
+            return;
+        };
+        let Some(elapsed) = self.elapsed.replace(0.) else {
+            let [base, shoulder, elbow, claw] = &mut self.pids;
+            base.resume(self.trim.base, commanded.base, reported.base);
+            shoulder.resume(self.trim.shoulder, commanded.shoulder, reported.shoulder);
+            elbow.resume(self.trim.elbow, commanded.elbow, reported.elbow);
+            claw.resume(self.trim.claw, commanded.claw, reported.claw);
+            return;
+        };
+
+        let [base, shoulder, elbow, claw] = &mut self.pids;
+        self.trim = JointAngles {
+            base: base.update(commanded.base, reported.base, elapsed),
+            shoulder: shoulder.update(commanded.shoulder, reported.shoulder, elapsed),
+            elbow: elbow.update(commanded.elbow, reported.elbow, elapsed),
+            claw: claw.update(commanded.claw, reported.claw, elapsed),
+        };
+    }
+
+    /// Drop the trims, for while the servos don't hold what is sent to them
+    pub fn reset(&mut self) {
+        for pid in &mut self.pids {
+            pid.reset();
+        }
+        self.pending = None;
+        self.elapsed = None;
+        self.trim = JointAngles::default();
+    }
+}
+
+impl Default for CorrectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            gains: Gains {
+                kp: 0.2,
+                ki: 2.,
+                kd: 0.,
+            },
+            max_trim: 5.,
+            derivative_filter: 0.05,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const COMMANDED: JointAngles = JointAngles {
+        base: 90.,
+        shoulder: 60.,
+        elbow: 120.,
+        claw: 30.,
+    };
+
+    /// Servos that settle `sag` short of the angle they are sent, reporting every 10ms
+    fn settle(correction: &mut FeedbackCorrection, sag: f64, seconds: f64) {
+        for _ in 0..(seconds / 0.01) as usize {
+            let trim = correction.trim;
+            correction.report(JointAngles {
+                base: COMMANDED.base + trim.base - sag,
+                shoulder: COMMANDED.shoulder + trim.shoulder - sag,
+                elbow: COMMANDED.elbow + trim.elbow - sag,
+                claw: COMMANDED.claw + trim.claw - sag,
+            });
+            correction.update(COMMANDED, 0.01);
+        }
+    }
+
+    #[test]
+    fn sag() {
+        let mut correction = FeedbackCorrection::new(CorrectionConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        settle(&mut correction, 2., 5.);
+        for trim in [
+            correction.trim.base,
+            correction.trim.shoulder,
+            correction.trim.elbow,
+            correction.trim.claw,
+        ] {
+            assert!((trim - 2.).abs() < 0.01, "{trim}");
+        }
+
+        // servos that do what they are sent lose the trim again
+        settle(&mut correction, 0., 5.);
+        assert!(correction.trim.base.abs() < 0.01, "{:?}", correction.trim);
+
+        // one that can't get there is only pushed as far as the limit
+        settle(&mut correction, 20., 5.);
+        assert_eq!(correction.trim.elbow, 5.);
+
+        // after a reset the trims start out from 0 even with the servos way off
+        correction.reset();
+        assert_eq!(correction.trim, JointAngles::default());
+        correction.report(JointAngles {
+            elbow: COMMANDED.elbow - 3.,
+            ..COMMANDED
+        });
+        correction.update(COMMANDED, 0.01);
+        assert_eq!(correction.trim, JointAngles::default());
+        correction.report(JointAngles {
+            elbow: COMMANDED.elbow - 3.,
+            ..COMMANDED
+        });
+        correction.update(COMMANDED, 0.01);
+        assert!(
+            (correction.trim.elbow - 0.06).abs() < 1e-9,
+            "{:?}",
+            correction.trim
+        );
+    }
+
+    #[test]
+    fn stale() {
+        let mut correction = FeedbackCorrection::new(CorrectionConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        settle(&mut correction, 2., 0.1);
+        let trim = correction.trim;
+
+        // without reports the trims stay put
+        for _ in 0..200 {
+            correction.update(COMMANDED, 0.01);
+        }
+        assert_eq!(correction.trim, trim);
+
+        // the next report integrates over the whole gap, only as far as the limit
+        correction.report(JointAngles {
+            shoulder: COMMANDED.shoulder - 2.,
+            ..COMMANDED
+        });
+        correction.update(COMMANDED, 0.01);
+        assert_eq!(correction.trim.shoulder, 5.);
+        assert!(correction.trim.base < trim.base);
+    }
+}
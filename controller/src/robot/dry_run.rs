@@ -0,0 +1,443 @@
+use std::fmt;
+
+use super::{arm::JointAngles, limits::JointRange, Robot};
+use crate::{
+    kinematics::{
+        joints::Joint,
+        position::{CordinateVec, KinematicsError},
+    },
+    logging::{info, warn},
+    program::{ClawAction, Program},
+};
+
+/// Units between the positions checked along the way to a waypoint
+const PATH_STEP: f64 = 5.;
+
+/// What a [`Violation`] is about
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// Further than the arms reach or without an inverse kinematics solution
+    Reach,
+
+    /// A joint is outside of its range or the limits found for it, the value is its angle
+    JointLimit,
+
+    /// Below the floor or inside a keep out zone of the active workspace
+    KeepOut,
+
+    /// Gravity loads a servo beyond its rating, the value is the share of the rating
+    Torque,
+}
+
+/// The first place on the way to a waypoint a check fails
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Violation {
+    /// Index of the waypoint moved to
+    pub step: usize,
+
+    pub problem: Problem,
+
+    /// The joint for [`Problem::JointLimit`]
+    pub joint: Option<&'static str>,
+
+    /// Where the check first fails, in the robot's frame
+    pub position: CordinateVec,
+
+    pub value: f64,
+}
+
+/// Result of [`Robot::dry_run`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRunReport {
+    /// Waypoints checked
+    pub steps: usize,
+
+    /// Estimated seconds the program takes, dwelling included
+    pub duration: f64,
+
+    pub violations: Vec<Violation>,
+}
+
+impl Robot {
+    /// Check a program without moving the arm or sending anything
+    ///
+    /// Every waypoint and the straight line to it is checked against the reach of the arm,
+    /// the joint ranges and limits, the keep out zones of the active workspace and, with a
+    /// [`Robot::torque_limit`], the servo ratings. The first move starts where the head is
+    /// headed
+    ///
+    /// The duration accelerates and brakes at [`Robot::acceleration`] and cruises like
+    /// [`Robot::run_program`] does, but ignores the torque limit and an unreliable link
+    pub fn dry_run(&self, program: &Program) -> DryRunReport {
+        let mut report = DryRunReport {
+            steps: program.waypoints.len(),
+            duration: 0.,
+            violations: Vec::new(),
+        };
+
+        let mut from = self.target_position.unwrap_or(self.position);
+        for (step, waypoint) in program.waypoints.iter().enumerate() {
+            let Some(target) = program.target(step) else {
+                continue;
+            };
+            let to = self.calibration.to_internal(self.operator_frame(target));
+
+            let distance = from.distance_to(&to);
+            let samples = (distance / PATH_STEP).ceil().max(1.) as usize;
+            for sample in 1..=samples {
+                let position = from + (to - from) * (sample as f64 / samples as f64);
+                self.check_position(&mut report, step, position);
+            }
+
+            if let Some(ClawAction::Angle(angle)) = waypoint.claw {
+                if !in_range(&self.arm.claw, self.joint_limits.claw, angle) {
+                    report.add(step, Problem::JointLimit, Some("claw"), to, angle);
+                }
+            }
+
+            let mut speed = waypoint
+                .speed
+                .or(self.cruise_speed)
+                .unwrap_or(f64::INFINITY);
+            if let Some(max_speed) = self.workspaces.current().and_then(|space| space.max_speed) {
+                speed = speed.min(max_speed);
+            }
+            report.duration += travel_time(distance, speed, self.acceleration) + waypoint.dwell;
+            from = to;
+        }
+
+        report
+    }
+
+    fn check_position(&self, report: &mut DryRunReport, step: usize, position: CordinateVec) {
+        let angles = match self.solve(position) {
+            Ok(angles) if position.dst() <= self.upper_arm + self.lower_arm => angles,
+            // out of range either way the elbow bends, the joints are checked below for which
+            Err(KinematicsError::JointLimit(_)) => {
+                match position.ik_solutions(self.upper_arm, self.lower_arm) {
+                    Ok(solutions) => solutions.primary,
+                    Err(_) => {
+                        report.add(step, Problem::Reach, None, position, 0.);
+                        return;
+                    }
+                }
+            }
+            _ => {
+                report.add(step, Problem::Reach, None, position, 0.);
+                return;
+            }
+        };
+        let (base, shoulder, elbow) = angles;
+
+        let limits = self.joint_limits;
+        let joints = [
+            ("base", &self.arm.base, limits.base, base),
+            ("shoulder", &self.arm.shoulder, limits.shoulder, shoulder),
+            ("elbow", &self.arm.elbow, limits.elbow, elbow),
+        ];
+        for (name, joint, range, angle) in joints {
+            if !in_range(joint, range, angle) {
+                report.add(step, Problem::JointLimit, Some(name), position, angle);
+            }
+        }
+
+        if self
+            .workspaces
+            .current()
+            .is_some_and(|workspace| !workspace.allows(position))
+        {
+            report.add(step, Problem::KeepOut, None, position, 0.);
+        }
+
+        if let Some(torque) = &self.torque_limit {
+            let angles = JointAngles {
+                base,
+                shoulder,
+                elbow,
+                claw: self.arm.claw.angle,
+            };
+            let load = torque.load(angles, self.upper_arm, self.lower_arm);
+            if load > 1. {
+                report.add(step, Problem::Torque, None, position, load);
+            }
+        }
+    }
+}
+
+/// True if `angle` is in the joint's range and the range found for it, continuous joints have
+/// neither
+fn in_range(joint: &Joint, found: Option<JointRange>, angle: f64) -> bool {
+    joint.continuous
+        || ((joint.min..=joint.max).contains(&angle)
+            && found.is_none_or(|range| range.contains(angle)))
+}
+
+/// Seconds to move `distance` from standing to standing, cruising at no more than `speed`
+fn travel_time(distance: f64, speed: f64, acceleration: f64) -> f64 {
+    if distance <= 0. {
+        return 0.;
+    }
+
+    // too short to get up to speed before having to brake
+    if distance <= speed.powi(2) / acceleration {
+        return 2. * (distance / acceleration).sqrt();
+    }
+
+    distance / speed + speed / acceleration
+}
+
+impl DryRunReport {
+    /// Warn about every violation and sum up
+    pub fn log(&self) {
+        for violation in &self.violations {
+            warn(&format!("dry run: {violation}"));
+        }
+        info(&format!(
+            "dry run: {} waypoints, about {:.1}s, {} violations",
+            self.steps,
+            self.duration,
+            self.violations.len()
+        ));
+    }
+
+    /// Keep only the first violation of a kind per waypoint and joint
+    fn add(
+        &mut self,
+        step: usize,
+        problem: Problem,
+        joint: Option<&'static str>,
+        position: CordinateVec,
+        value: f64,
+    ) {
+        let known = self.violations.iter().any(|violation| {
+            violation.step == step && violation.problem == problem && violation.joint == joint
+        });
+        if !known {
+            self.violations.push(Violation {
+                step,
+                problem,
+                joint,
+                position,
+                value,
+            });
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let position = self.position;
+        write!(f, "waypoint {}: ", self.step)?;
+        match self.problem {
+            Problem::Reach => write!(f, "out of reach")?,
+            Problem::JointLimit => write!(
+                f,
+                "{} at {:.1}° is out of its range",
+                self.joint.unwrap_or("joint"),
+                self.value
+            )?,
+            Problem::KeepOut => write!(f, "below the floor or in a keep out zone")?,
+            Problem::Torque => {
+                write!(f, "servo loaded at {:.0}% of its rating", self.value * 100.)?
+            }
+        }
+        write!(
+            f,
+            " at ({:.1}, {:.1}, {:.1})",
+            position.x, position.y, position.z
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        program::Waypoint,
+        robot::{
+            limits::JointLimits,
+            torque::TorqueLimit,
+            workspace::{KeepOut, Workspace},
+        },
+    };
+
+    fn robot() -> Robot {
+        Robot {
+            position: CordinateVec::new(100., 50., 50.),
+            ..Default::default()
+        }
+    }
+
+    fn program(targets: &[CordinateVec]) -> Program {
+        Program {
+            waypoints: targets
+                .iter()
+                .map(|&position| Waypoint {
+                    position: Some(position),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn problems(report: &DryRunReport) -> Vec<(usize, Problem, Option<&'static str>)> {
+        report
+            .violations
+            .iter()
+            .map(|violation| (violation.step, violation.problem, violation.joint))
+            .collect()
+    }
+
+    #[test]
+    fn clean() {
+        let robo = robot();
+        let program = program(&[
+            CordinateVec::new(100., 100., 50.),
+            CordinateVec::new(150., 20., 30.),
+        ]);
+
+        let report = robo.dry_run(&program);
+        assert_eq!(report.steps, 2);
+        assert_eq!(report.violations, []);
+        assert!(report.duration > 0.);
+    }
+
+    #[test]
+    fn reach() {
+        let robo = robot();
+        let program = program(&[
+            CordinateVec::new(100., 250., 50.),
+            CordinateVec::new(100., 50., 50.),
+        ]);
+
+        // once per waypoint, where it starts, the way back starts out of reach too
+        let report = robo.dry_run(&program);
+        assert_eq!(
+            problems(&report),
+            [(0, Problem::Reach, None), (1, Problem::Reach, None)]
+        );
+        assert_eq!(
+            report.violations[0].to_string(),
+            "waypoint 0: out of reach at (100.0, 170.0, 50.0)"
+        );
+        assert_eq!(report.violations[1].position.y, 245.);
+    }
+
+    #[test]
+    fn joint_limits() {
+        let mut robo = robot();
+        robo.joint_limits = JointLimits {
+            base: Some(JointRange {
+                min: 100.,
+                max: 170.,
+            }),
+            ..Default::default()
+        };
+
+        let mut program = program(&[
+            CordinateVec::new(100., 100., 50.),
+            CordinateVec::new(150., 20., 50.),
+        ]);
+        program.waypoints[0].claw = Some(ClawAction::Angle(200.));
+
+        let report = robo.dry_run(&program);
+        assert_eq!(
+            problems(&report),
+            [
+                (0, Problem::JointLimit, Some("claw")),
+                (1, Problem::JointLimit, Some("base"))
+            ]
+        );
+        assert_eq!(report.violations[0].value, 200.);
+        assert!(report.violations[1].value < 100.);
+        assert!(report.violations[1]
+            .to_string()
+            .starts_with("waypoint 1: base at 99."));
+    }
+
+    #[test]
+    fn keep_out() {
+        let mut robo = robot();
+        robo.workspaces.profiles.insert(
+            "desk".to_string(),
+            Workspace {
+                floor: Some(20.),
+                keep_out: vec![KeepOut {
+                    min: CordinateVec::new(90., 60., 0.),
+                    max: CordinateVec::new(110., 80., 100.),
+                }],
+                max_speed: None,
+            },
+        );
+        robo.workspaces.active = Some("desk".to_string());
+
+        // through the zone on the way, then under the floor
+        let program = program(&[
+            CordinateVec::new(100., 100., 50.),
+            CordinateVec::new(150., 120., 10.),
+        ]);
+        let report = robo.dry_run(&program);
+        assert_eq!(
+            problems(&report),
+            [(0, Problem::KeepOut, None), (1, Problem::KeepOut, None)]
+        );
+        assert!((report.violations[0].position.y - 60.).abs() <= PATH_STEP);
+        assert!(report.violations[1].position.z < 20.);
+    }
+
+    #[test]
+    fn torque() {
+        let mut robo = Robot {
+            torque_limit: Some(TorqueLimit {
+                enabled: true,
+                shoulder_rating: 40.,
+                ..Default::default()
+            }),
+            ..robot()
+        };
+
+        // stretched out far from the base loads the shoulder the most
+        let program = program(&[
+            CordinateVec::new(40., 40., 170.),
+            CordinateVec::new(150., 100., 20.),
+        ]);
+        let report = robo.dry_run(&program);
+        assert_eq!(problems(&report), [(1, Problem::Torque, None)]);
+        assert!(report.violations[0].value > 1.);
+
+        robo.torque_limit = None;
+        assert_eq!(robo.dry_run(&program).violations, []);
+    }
+
+    #[test]
+    fn duration() {
+        let mut robo = robot();
+        let mut program = program(&[
+            CordinateVec::new(100., 130., 50.),
+            CordinateVec::new(100., 51., 50.),
+        ]);
+        program.waypoints[0].speed = Some(50.);
+        program.waypoints[0].dwell = 1.5;
+
+        // 80 units at 50 units/s with half a second each speeding up and slowing down, then
+        // too short to get up to full speed
+        let report = robo.dry_run(&program);
+        let second = 2. * (79f64 / 100.).sqrt();
+        assert!((report.duration - (2.1 + 1.5 + second)).abs() < 1e-9);
+
+        // the workspace caps the cruise speed
+        robo.workspaces.profiles.insert(
+            "slow".to_string(),
+            Workspace {
+                max_speed: Some(10.),
+                ..Default::default()
+            },
+        );
+        robo.workspaces.active = Some("slow".to_string());
+        let report = robo.dry_run(&program);
+        assert!((report.duration - (8.1 + 1.5 + 8.)).abs() < 1e-9);
+
+        assert_eq!(travel_time(0., 50., 100.), 0.);
+    }
+}
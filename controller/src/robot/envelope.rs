@@ -0,0 +1,416 @@
+use std::{collections::BTreeSet, fmt, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::kinematics::{constraint::WorkspaceConstraint, position::CordinateVec};
+
+/// Most voxels an envelope holds, positions in new voxels are ignored once it's full
+///
+/// A voxel is stored as three `i32` in a `BTreeSet`, 12 bytes plus the share of its B-tree
+/// node, so a full envelope stays well under 1 MiB. At 10 unit voxels that is a 320 unit cube,
+/// more than the half sphere an arm with 100 unit arms can reach
+pub const MAX_VOXELS: usize = 32_768;
+
+/// What the robot does with its [`Envelope`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvelopeMode {
+    #[default]
+    Off,
+
+    /// Add every position the head visits
+    Learn,
+
+    /// Keep the head inside the learned voxels
+    Enforce,
+}
+
+/// The region the arm was taught to stay in, as the set of voxels the head visited
+///
+/// Space is cut into cubes of [`Envelope::voxel`] units with one corner at the origin, a
+/// position is contained if its cube is
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredEnvelope", into = "StoredEnvelope")]
+pub struct Envelope {
+    /// Edge length of a voxel in units
+    pub voxel: f64,
+
+    /// Indices of the visited voxels
+    voxels: BTreeSet<[i32; 3]>,
+
+    /// Lowest and highest index on every axis, `None` while empty
+    bounds: Option<([i32; 3], [i32; 3])>,
+
+    /// Positions that weren't learned because the envelope was full
+    pub overflowed: u64,
+
+    /// Learned something since it was loaded or saved
+    pub changed: bool,
+}
+
+/// How an [`Envelope`] is saved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredEnvelope {
+    voxel: f64,
+    voxels: Vec<[i32; 3]>,
+}
+
+#[derive(Debug)]
+pub enum EnvelopeError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+
+    /// Enforcing an envelope nothing was learned into yet
+    Empty,
+}
+
+impl Envelope {
+    pub fn new(voxel: f64) -> Self {
+        Self {
+            voxel,
+            voxels: BTreeSet::new(),
+            bounds: None,
+            overflowed: 0,
+            changed: false,
+        }
+    }
+
+    /// Number of learned voxels
+    pub fn len(&self) -> usize {
+        self.voxels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    /// Forget everything learned
+    pub fn clear(&mut self) {
+        self.changed |= !self.is_empty();
+        self.voxels.clear();
+        self.bounds = None;
+        self.overflowed = 0;
+    }
+
+    /// Add the voxel of `position`
+    ///
+    /// # Returns
+    /// `false` if it wasn't added because the envelope is full, see [`MAX_VOXELS`]
+    pub fn learn(&mut self, position: CordinateVec) -> bool {
+        if !position.is_finite() {
+            return true;
+        }
+
+        let index = self.index(position);
+        if self.voxels.contains(&index) {
+            return true;
+        }
+        if self.voxels.len() >= MAX_VOXELS {
+            self.overflowed += 1;
+            return false;
+        }
+
+        self.insert(index);
+        self.changed = true;
+        true
+    }
+
+    /// True if the voxel of `position` was learned
+    pub fn contains(&self, position: CordinateVec) -> bool {
+        self.voxels.contains(&self.index(position))
+    }
+
+    /// Center of the learned voxel whose center is closest to `position`, `None` while empty
+    pub fn nearest(&self, position: CordinateVec) -> Option<CordinateVec> {
+        self.nearest_index(position).map(|index| self.center(index))
+    }
+
+    /// Closest position to `position` inside the learned voxel nearest to it
+    ///
+    /// Unlike snapping to [`Envelope::nearest`] this keeps the motion along the surface of
+    /// the envelope, so the head slides along the boundary instead of jumping between voxel
+    /// centers. Positions that are already contained, and any position while the envelope is
+    /// empty, are left alone
+    pub fn clamp(&self, mut position: CordinateVec) -> CordinateVec {
+        if self.contains(position) {
+            return position;
+        }
+        let Some(index) = self.nearest_index(position) else {
+            return position;
+        };
+
+        let min = |axis: usize| index[axis] as f64 * self.voxel;
+        let max = |axis: usize| (index[axis] + 1) as f64 * self.voxel;
+        position.clamp_vec(
+            CordinateVec::new(min(0), min(1), min(2)),
+            CordinateVec::new(max(0), max(1), max(2)),
+        );
+        position
+    }
+
+    /// Replace the envelope with one saved by [`Envelope::save`]
+    ///
+    /// A missing file is nothing learned yet and leaves the envelope as it is
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), EnvelopeError> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(EnvelopeError::Io(err)),
+        };
+
+        *self = serde_json::from_str(&data).map_err(EnvelopeError::Parse)?;
+        Ok(())
+    }
+
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<(), EnvelopeError> {
+        let data = serde_json::to_string(self).map_err(EnvelopeError::Parse)?;
+        fs::write(path, data).map_err(EnvelopeError::Io)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    fn index(&self, position: CordinateVec) -> [i32; 3] {
+        // the casts saturate, so far away positions end up in the outermost voxels
+        let index = |value: f64| (value / self.voxel).floor() as i32;
+        [index(position.x), index(position.y), index(position.z)]
+    }
+
+    fn center(&self, index: [i32; 3]) -> CordinateVec {
+        let center = |index: i32| (index as f64 + 0.5) * self.voxel;
+        CordinateVec::new(center(index[0]), center(index[1]), center(index[2]))
+    }
+
+    fn insert(&mut self, index: [i32; 3]) {
+        self.voxels.insert(index);
+        self.bounds = Some(match self.bounds {
+            Some((min, max)) => (
+                [0, 1, 2].map(|axis| min[axis].min(index[axis])),
+                [0, 1, 2].map(|axis| max[axis].max(index[axis])),
+            ),
+            None => (index, index),
+        });
+    }
+
+    /// Search shells of voxels around the voxel of `position`, growing outwards until no
+    /// voxel in a further shell can be closer than the best one found
+    fn nearest_index(&self, position: CordinateVec) -> Option<[i32; 3]> {
+        let (min, max) = self.bounds?;
+        let origin = self.index(position);
+        // no learned voxel is closer or further out than these shells
+        let (min, max) = (min.map(i64::from), max.map(i64::from));
+        let origin_at = |axis: usize| origin[axis] as i64;
+        let start = (0..3)
+            .map(|axis| (min[axis] - origin_at(axis)).max(origin_at(axis) - max[axis]).max(0))
+            .max()?;
+        let reach = (0..3)
+            .map(|axis| (origin_at(axis) - min[axis]).max(max[axis] - origin_at(axis)))
+            .max()?;
+
+        let mut best: Option<(f64, [i32; 3])> = None;
+        for radius in start..=reach {
+            // the position is inside the origin voxel, so every center in this shell is at
+            // least half a voxel less than the radius away
+            if best.is_some_and(|(distance, _)| (radius as f64 - 0.5) * self.voxel > distance) {
+                break;
+            }
+
+            for dx in -radius..=radius {
+                for dy in -radius..=radius {
+                    // only the surface of the shell, the inside was searched already
+                    let surface = dx.abs() == radius || dy.abs() == radius;
+                    let step = if surface { 1 } else { 2 * radius };
+
+                    for dz in (-radius..=radius).step_by(step as usize) {
+                        let offset = [dx, dy, dz];
+                        let index = [0, 1, 2].map(|axis| origin_at(axis) + offset[axis]);
+                        if index.iter().any(|&value| i32::try_from(value).is_err()) {
+                            continue;
+                        }
+                        let index = index.map(|value| value as i32);
+                        if !self.voxels.contains(&index) {
+                            continue;
+                        }
+
+                        let distance = position.distance_to(&self.center(index));
+                        if best.is_none_or(|(best, _)| distance < best) {
+                            best = Some((distance, index));
+                        }
+                    }
+                }
+            }
+        }
+
+        best.map(|(_, index)| index)
+    }
+}
+
+impl WorkspaceConstraint for Envelope {
+    /// See [`Envelope::clamp`]
+    fn project(&self, position: CordinateVec) -> CordinateVec {
+        self.clamp(position)
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::new(10.)
+    }
+}
+
+impl From<StoredEnvelope> for Envelope {
+    fn from(stored: StoredEnvelope) -> Self {
+        let mut envelope = Self::new(stored.voxel);
+        for index in stored.voxels {
+            envelope.insert(index);
+        }
+        envelope
+    }
+}
+
+impl From<Envelope> for StoredEnvelope {
+    fn from(envelope: Envelope) -> Self {
+        Self {
+            voxel: envelope.voxel,
+            voxels: envelope.voxels.into_iter().collect(),
+        }
+    }
+}
+
+impl fmt::Display for EnvelopeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeMode::Off => write!(f, "off"),
+            EnvelopeMode::Learn => write!(f, "learning"),
+            EnvelopeMode::Enforce => write!(f, "enforced"),
+        }
+    }
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::Io(err) => write!(f, "{err}"),
+            EnvelopeError::Parse(err) => write!(f, "invalid envelope file: {err}"),
+            EnvelopeError::Empty => write!(f, "nothing learned into the envelope yet"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// An L of three voxels on the floor
+    fn envelope() -> Envelope {
+        let mut envelope = Envelope::new(10.);
+        for position in [(5., 5., 5.), (15., 5., 5.), (15., 15., 5.)] {
+            assert!(envelope.learn(CordinateVec::new(position.0, position.1, position.2)));
+        }
+        envelope
+    }
+
+    #[test]
+    fn learn_and_contain() {
+        let mut envelope = envelope();
+        assert_eq!(envelope.len(), 3);
+        assert!(envelope.changed);
+
+        // the same voxel again doesn't add anything
+        assert!(envelope.learn(CordinateVec::new(19., 1., 9.)));
+        assert_eq!(envelope.len(), 3);
+
+        assert!(envelope.contains(CordinateVec::new(0., 0., 0.)));
+        assert!(envelope.contains(CordinateVec::new(19.9, 19.9, 9.9)));
+        assert!(!envelope.contains(CordinateVec::new(5., 15., 5.)));
+        assert!(!envelope.contains(CordinateVec::new(5., 5., 10.)));
+        assert!(!envelope.contains(CordinateVec::new(-0.1, 5., 5.)));
+
+        envelope.clear();
+        assert!(envelope.is_empty());
+        assert!(!envelope.contains(CordinateVec::new(5., 5., 5.)));
+        assert_eq!(envelope.nearest(CordinateVec::new(5., 5., 5.)), None);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut envelope = Envelope::new(1.);
+        for i in 0..MAX_VOXELS {
+            assert!(envelope.learn(CordinateVec::new(i as f64, 0., 0.)));
+        }
+
+        // full, known voxels are still fine
+        assert!(!envelope.learn(CordinateVec::new(-1., 0., 0.)));
+        assert!(envelope.learn(CordinateVec::new(0., 0., 0.)));
+        assert_eq!(envelope.len(), MAX_VOXELS);
+        assert_eq!(envelope.overflowed, 1);
+    }
+
+    #[test]
+    fn nearest_and_clamp() {
+        let envelope = envelope();
+
+        // inside stays where it is
+        let inside = CordinateVec::new(12., 3., 4.);
+        assert_eq!(envelope.clamp(inside), inside);
+        assert_eq!(envelope.nearest(inside), Some(CordinateVec::new(15., 5., 5.)));
+
+        // in the gap of the L, closest to the corner voxel
+        assert_eq!(
+            envelope.nearest(CordinateVec::new(7., 17., 5.)),
+            Some(CordinateVec::new(15., 15., 5.))
+        );
+
+        // far away
+        assert_eq!(
+            envelope.nearest(CordinateVec::new(-200., 0., 300.)),
+            Some(CordinateVec::new(5., 5., 5.))
+        );
+
+        // above the floor voxels the head is pushed down onto their top face, keeping x and y
+        // so it slides along instead of snapping to the center
+        assert_eq!(
+            envelope.clamp(CordinateVec::new(12., 3., 14.)),
+            CordinateVec::new(12., 3., 10.)
+        );
+        assert_eq!(
+            envelope.clamp(CordinateVec::new(16., 18., 14.)),
+            CordinateVec::new(16., 18., 10.)
+        );
+        assert_eq!(
+            envelope.clamp(CordinateVec::new(7., 17., 5.)),
+            CordinateVec::new(10., 17., 5.)
+        );
+
+        let empty = Envelope::default();
+        assert_eq!(empty.clamp(CordinateVec::new(7., 17., 5.)), CordinateVec::new(7., 17., 5.));
+    }
+
+    #[test]
+    fn persist() {
+        let path = std::env::temp_dir().join(format!("rac-envelope-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut envelope = envelope();
+        envelope.save(&path).unwrap();
+        assert!(!envelope.changed);
+
+        let mut loaded = Envelope::new(3.);
+        loaded.load(&path).unwrap();
+        assert_eq!(loaded, envelope);
+        assert_eq!(loaded.voxel, 10.);
+        assert_eq!(
+            loaded.nearest(CordinateVec::new(7., 17., 5.)),
+            Some(CordinateVec::new(15., 15., 5.))
+        );
+
+        // nothing saved yet is nothing learned yet
+        fs::remove_file(&path).unwrap();
+        let mut fresh = Envelope::new(3.);
+        fresh.load(&path).unwrap();
+        assert_eq!(fresh, Envelope::new(3.));
+
+        fs::write(&path, "[]").unwrap();
+        assert!(matches!(fresh.load(&path), Err(EnvelopeError::Parse(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{kinematics::position::CordinateVec, protocol::Frame};
+
+/// How hard the first stage of the [`EmergencyStop`] brakes and how long it may take
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmergencyStopConfig {
+    /// Deceleration in units/s^2 while braking, several times the normal acceleration
+    pub deceleration: f64,
+
+    /// Seconds braking may take before the servos are detached
+    pub max_time: f64,
+
+    /// Units the head may travel while braking before the servos are detached
+    pub max_distance: f64,
+}
+
+/// Where the [`EmergencyStop`] is at
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopStage {
+    /// Braking at [`EmergencyStopConfig::deceleration`]
+    Braking,
+
+    /// Braked to a standstill, the servos hold the pose
+    Halted,
+
+    /// The servos were detached, on a second press or when braking ran over its budget
+    Detached,
+}
+
+/// Two stage emergency stop
+///
+/// Freezing the commanded pose at speed whips the arm, the servos try to hold it against the
+/// momentum. The first press brakes along the current direction instead, a second press or
+/// braking for longer or further than the budget detaches the servos right away. Nothing else
+/// moves the arm until it's released
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmergencyStop {
+    pub config: EmergencyStopConfig,
+
+    /// `None` unless stopping or stopped
+    pub stage: Option<StopStage>,
+
+    /// Seconds braked and units travelled since the first press
+    pub elapsed: f64,
+    pub travelled: f64,
+
+    /// Frame to send before anything else on the next transmit
+    pub pending: Option<Frame>,
+}
+
+impl EmergencyStop {
+    pub fn new(config: EmergencyStopConfig) -> Self {
+        Self {
+            config,
+            stage: None,
+            elapsed: 0.,
+            travelled: 0.,
+            pending: None,
+        }
+    }
+
+    /// The stop was pressed, start braking or detach if already stopping
+    pub fn press(&mut self) {
+        match self.stage {
+            None => {
+                self.stage = Some(StopStage::Braking);
+                self.elapsed = 0.;
+                self.travelled = 0.;
+            }
+            Some(StopStage::Braking | StopStage::Halted) => self.detach(),
+            Some(StopStage::Detached) => {}
+        }
+    }
+
+    /// Slow `velocity` down for `delta` seconds, detaching once over the budget
+    ///
+    /// Does nothing unless braking
+    pub fn brake(&mut self, velocity: &mut CordinateVec, delta: f64) {
+        if self.stage != Some(StopStage::Braking) {
+            return;
+        }
+
+        let speed = velocity.dst();
+        let slowed = (speed - self.config.deceleration * delta).max(0.);
+        *velocity = if slowed > 0. && speed.is_finite() {
+            *velocity * (slowed / speed)
+        } else {
+            CordinateVec::default()
+        };
+
+        self.elapsed += delta;
+        self.travelled += slowed * delta;
+        if *velocity == CordinateVec::default() {
+            self.stage = Some(StopStage::Halted);
+        } else if self.elapsed >= self.config.max_time || self.travelled >= self.config.max_distance
+        {
+            *velocity = CordinateVec::default();
+            self.detach();
+        }
+    }
+
+    /// Stop stopping, only once the arm stands still
+    ///
+    /// # Returns
+    /// False if still braking
+    pub fn release(&mut self) -> bool {
+        if self.stage == Some(StopStage::Braking) {
+            return false;
+        }
+
+        self.stage = None;
+        true
+    }
+
+    pub fn active(&self) -> bool {
+        self.stage.is_some()
+    }
+
+    fn detach(&mut self) {
+        self.stage = Some(StopStage::Detached);
+        self.pending = Some(Frame::Detach);
+    }
+}
+
+impl Default for EmergencyStopConfig {
+    fn default() -> Self {
+        Self {
+            deceleration: 500.,
+            max_time: 0.5,
+            max_distance: 20.,
+        }
+    }
+}
+
+impl Default for EmergencyStop {
+    fn default() -> Self {
+        Self::new(EmergencyStopConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Brake from `speed` along x at 100Hz until it stops
+    fn brake(stop: &mut EmergencyStop, speed: f64) -> (StopStage, u32) {
+        let mut velocity = CordinateVec::new(speed, 0., 0.);
+        stop.press();
+
+        for tick in 1..=1000 {
+            stop.brake(&mut velocity, 0.01);
+            if stop.stage != Some(StopStage::Braking) {
+                assert_eq!(velocity, CordinateVec::default());
+                return (stop.stage.unwrap(), tick);
+            }
+            assert!(velocity.x > 0. && velocity.x < speed);
+        }
+        panic!("still braking at {velocity:?}");
+    }
+
+    #[test]
+    fn stages() {
+        let mut stop = EmergencyStop::default();
+        assert!(!stop.active());
+        assert!(stop.release());
+
+        stop.press();
+        assert_eq!(stop.stage, Some(StopStage::Braking));
+        assert_eq!(stop.pending, None);
+        assert!(!stop.release());
+
+        // a second press doesn't wait for the braking
+        stop.press();
+        assert_eq!(stop.stage, Some(StopStage::Detached));
+        assert_eq!(stop.pending, Some(Frame::Detach));
+
+        stop.pending = None;
+        stop.press();
+        assert_eq!(stop.pending, None);
+        assert!(stop.release());
+        assert!(!stop.active());
+    }
+
+    #[test]
+    fn budget() {
+        // stopping takes speed / 500 seconds over speed^2 / 1000 units
+        for (speed, stage) in [
+            (10., StopStage::Halted),
+            (50., StopStage::Halted),
+            (100., StopStage::Halted),
+            (140., StopStage::Halted),
+            (150., StopStage::Detached),
+            (400., StopStage::Detached),
+        ] {
+            let mut stop = EmergencyStop::default();
+            let (stopped, ticks) = brake(&mut stop, speed);
+
+            assert_eq!(stopped, stage, "from {speed}");
+            assert!(stop.travelled <= 20. + speed * 0.01, "from {speed}");
+            if stage == StopStage::Halted {
+                assert_eq!(ticks, (speed / 5.).ceil() as u32, "from {speed}");
+                assert!((stop.travelled - speed.powi(2) / 1000.).abs() < speed * 0.01);
+            }
+        }
+
+        // the time budget runs out first with a gentle deceleration
+        let mut stop = EmergencyStop::new(EmergencyStopConfig {
+            deceleration: 10.,
+            max_distance: f64::INFINITY,
+            ..Default::default()
+        });
+        let (stopped, ticks) = brake(&mut stop, 10.);
+        assert_eq!(stopped, StopStage::Detached);
+        assert!((50..=51).contains(&ticks), "after {ticks} ticks");
+        assert_eq!(stop.pending, Some(Frame::Detach));
+    }
+}
@@ -0,0 +1,138 @@
+use std::{collections::VecDeque, fmt, time::Instant};
+
+use super::{
+    estop::StopStage, grip::GripEnd, limits::LimitAbort, shutdown::ShutdownBehavior,
+    supply::SupplyLevel,
+};
+use crate::kinematics::position::CordinateVec;
+
+/// Most events kept until they are taken, older ones are dropped first
+pub const MAX_EVENTS: usize = 64;
+
+/// Something that happened to the robot that callers may want to wait for
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RobotEvent {
+    /// The head arrived at the commanded target
+    TargetReached(CordinateVec),
+
+    /// A replay ran out of samples
+    ReplayFinished,
+
+    /// The last waypoint of the program was done, see [`super::Robot::run_program`]
+    ProgramFinished,
+
+    /// The link got bad enough that the arm stopped, see [`super::link::LinkPolicy`]
+    LinkDown,
+
+    /// The arm can move again after [`RobotEvent::LinkDown`]
+    LinkRestored,
+
+    /// A joint stopped following its commanded angle, see [`super::stall::StallDetector`]
+    StallDetected(&'static str),
+
+    /// The emergency stop finished braking, see [`super::estop::EmergencyStop`]
+    EmergencyStopped(StopStage),
+
+    /// The end stops of a joint were found, see [`super::limits::LimitFinder`]
+    LimitsFound(&'static str),
+
+    LimitSearchAborted(LimitAbort),
+
+    /// The claw got to its grip or closed on the object, see [`super::Robot::use_grip`]
+    Gripped(GripEnd),
+
+    /// The supply voltage crossed a threshold, see [`super::supply::SupplyMonitor`]
+    Supply(SupplyLevel),
+
+    /// The arm rests, holds or is detached for good, see [`super::Robot::begin_shutdown`]
+    ShutDown(ShutdownBehavior),
+}
+
+/// An event and when it happened
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Event {
+    pub at: Instant,
+    pub event: RobotEvent,
+}
+
+/// Bounded queue of events waiting to be taken
+#[derive(Debug, Default)]
+pub struct EventQueue {
+    events: VecDeque<Event>,
+
+    /// Events dropped because nobody took them in time
+    pub dropped: u64,
+
+    /// Latest event, kept after it's taken so it can be displayed
+    pub last: Option<Event>,
+}
+
+impl EventQueue {
+    pub fn push(&mut self, event: RobotEvent, at: Instant) {
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+            self.dropped += 1;
+        }
+
+        let event = Event { at, event };
+        self.events.push_back(event);
+        self.last = Some(event);
+    }
+
+    /// Take all waiting events, oldest first
+    pub fn take(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
+
+impl fmt::Display for RobotEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RobotEvent::TargetReached(position) => write!(
+                f,
+                "target reached at ({:.1}, {:.1}, {:.1})",
+                position.x, position.y, position.z
+            ),
+            RobotEvent::ReplayFinished => write!(f, "replay finished"),
+            RobotEvent::ProgramFinished => write!(f, "program finished"),
+            RobotEvent::LinkDown => write!(f, "link down"),
+            RobotEvent::LinkRestored => write!(f, "link restored"),
+            RobotEvent::StallDetected(joint) => write!(f, "{joint} stalled"),
+            RobotEvent::EmergencyStopped(StopStage::Detached) => {
+                write!(f, "emergency stop over budget, servos detached")
+            }
+            RobotEvent::EmergencyStopped(_) => write!(f, "emergency stop halted"),
+            RobotEvent::LimitsFound(joint) => write!(f, "{joint} limits found"),
+            RobotEvent::LimitSearchAborted(abort) => write!(f, "limit search aborted: {abort}"),
+            RobotEvent::Gripped(GripEnd::Reached) => write!(f, "claw at its grip"),
+            RobotEvent::Gripped(GripEnd::Detected) => write!(f, "claw closed on the object"),
+            RobotEvent::Supply(level) => write!(f, "supply {level}"),
+            RobotEvent::ShutDown(stage) => write!(f, "shut down, {stage}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bounded() {
+        let now = Instant::now();
+        let mut queue = EventQueue::default();
+
+        for _ in 0..MAX_EVENTS {
+            queue.push(RobotEvent::LinkDown, now);
+        }
+        queue.push(RobotEvent::ReplayFinished, now);
+        assert_eq!(queue.dropped, 1);
+
+        let events = queue.take();
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events.last().unwrap().event, RobotEvent::ReplayFinished);
+
+        // taken events are gone but the latest is remembered
+        assert!(queue.take().is_empty());
+        assert_eq!(queue.last.unwrap().event, RobotEvent::ReplayFinished);
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use super::{Robot, UpdateReport};
+use crate::{clock::Clock, communication::ComError, kinematics::position::CordinateVec};
+
+/// How a blocking move ended
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GotoOutcome {
+    Reached,
+    TimedOut,
+    Cancelled,
+
+    /// The head can't be at the target, see [`Robot::is_reachable`]
+    Unreachable,
+}
+
+/// Result of [`Robot::goto_blocking`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GotoResult {
+    pub outcome: GotoOutcome,
+
+    /// Distance between the head and the target when the move ended
+    pub error: f64,
+
+    /// Clock time the move took
+    pub elapsed: Duration,
+}
+
+impl Robot {
+    /// Move the head to a position and wait until it gets there
+    ///
+    /// The target is set with [`Robot::retarget`] like a `goto`, then the robot is updated once
+    /// per tick of `clock` until the target is reached, `timeout` has passed or `cancel` is set.
+    /// The arm is told to stop if the move doesn't finish
+    ///
+    /// # Arguments
+    /// * `target` - position in the operator's frame, see [`Robot::command_target`]
+    /// * `speed` - cruise speed in units/s, `None` to keep [`Robot::cruise_speed`]
+    /// * `timeout` - longest the move may take, measured by `clock`
+    /// * `clock` - paces the updates
+    /// * `cancel` - set from anywhere to stop the move early
+    /// * `on_update` - called after every update with its report and seconds, for whatever has
+    ///   to keep up with the robot like the simulator
+    pub fn goto_blocking(
+        &mut self,
+        target: CordinateVec,
+        speed: Option<f64>,
+        timeout: Duration,
+        clock: &mut impl Clock,
+        cancel: &AtomicBool,
+        mut on_update: impl FnMut(&mut Robot, &UpdateReport, f64),
+    ) -> Result<GotoResult, ComError> {
+        let cruise_speed = self.cruise_speed;
+        self.cruise_speed = speed.or(cruise_speed);
+        let accepted = self.retarget(target);
+
+        // the target in the robot's frame
+        let goal = self.target_position.unwrap_or(target);
+
+        let mut elapsed = Duration::ZERO;
+        let outcome = loop {
+            if !accepted {
+                break GotoOutcome::Unreachable;
+            }
+            if self.target_position.is_none() {
+                break GotoOutcome::Reached;
+            }
+            if cancel.load(Ordering::Relaxed) {
+                break GotoOutcome::Cancelled;
+            }
+            if elapsed >= timeout {
+                break GotoOutcome::TimedOut;
+            }
+
+            let delta = clock.tick();
+            elapsed += Duration::from_secs_f64(delta);
+
+            match self.update(delta) {
+                Ok(report) => on_update(self, &report, delta),
+                Err(err) => {
+                    self.cruise_speed = cruise_speed;
+                    return Err(err);
+                }
+            }
+        };
+
+        self.cruise_speed = cruise_speed;
+        if outcome != GotoOutcome::Reached {
+            self.target_position = None;
+            self.target_velocity = CordinateVec::default();
+        }
+
+        Ok(GotoResult {
+            outcome,
+            error: self.position.distance_to(&goal),
+            elapsed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::SimClock;
+    use std::{sync::Arc, thread};
+
+    fn robot() -> Robot {
+        Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reach_target() {
+        let mut robo = robot();
+        let target = CordinateVec::new(30., 60., 40.);
+
+        let result = robo
+            .goto_blocking(
+                target,
+                Some(20.),
+                Duration::from_secs(10),
+                &mut SimClock { step: 0.01 },
+                &AtomicBool::new(false),
+                |_, _, _| {},
+            )
+            .unwrap();
+
+        assert_eq!(result.outcome, GotoOutcome::Reached);
+        assert!(result.error < 0.04);
+        assert!(result.elapsed < Duration::from_secs(10));
+        assert_eq!(robo.position, target);
+        assert_eq!(robo.cruise_speed, None);
+    }
+
+    #[test]
+    fn timeout() {
+        let mut robo = robot();
+
+        let result = robo
+            .goto_blocking(
+                CordinateVec::new(60., 90., 120.),
+                Some(5.),
+                Duration::from_secs(1),
+                &mut SimClock { step: 0.1 },
+                &AtomicBool::new(false),
+                |_, _, _| {},
+            )
+            .unwrap();
+
+        assert_eq!(result.outcome, GotoOutcome::TimedOut);
+        assert!(result.elapsed >= Duration::from_secs(1));
+        assert!(result.error > 50.);
+        assert_eq!(robo.target_position, None);
+        assert_eq!(robo.target_velocity, CordinateVec::default());
+    }
+
+    #[test]
+    fn cancel() {
+        let mut robo = robot();
+
+        // already cancelled, nothing moves
+        let result = robo
+            .goto_blocking(
+                CordinateVec::new(60., 90., 120.),
+                Some(5.),
+                Duration::from_secs(10),
+                &mut SimClock { step: 0.1 },
+                &AtomicBool::new(true),
+                |_, _, _| {},
+            )
+            .unwrap();
+        assert_eq!(result.outcome, GotoOutcome::Cancelled);
+        assert_eq!(result.elapsed, Duration::ZERO);
+
+        // cancelled from another thread while moving
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            flag.store(true, Ordering::Relaxed);
+        });
+
+        let result = robo
+            .goto_blocking(
+                CordinateVec::new(60., 90., 120.),
+                Some(5.),
+                Duration::from_secs(1000),
+                &mut crate::clock::PacedClock::new(Duration::from_millis(1)),
+                &cancel,
+                |_, _, _| {},
+            )
+            .unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(result.outcome, GotoOutcome::Cancelled);
+        assert!(result.elapsed < Duration::from_secs(1));
+        assert_eq!(robo.target_position, None);
+    }
+}
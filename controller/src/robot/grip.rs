@@ -0,0 +1,274 @@
+use std::fmt;
+
+use super::{journal::Change, limits::turn, Robot};
+use crate::program::Grip;
+
+/// Percent/s a grip saved without a speed turns the claw at
+pub const DEFAULT_SPEED: f64 = 50.;
+
+/// The claw turning to a grip, see [`Robot::use_grip`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GripRamp {
+    /// Claw angle to stop at
+    pub target: f64,
+
+    /// Degrees/s the claw turns at
+    pub speed: f64,
+}
+
+/// How a [`GripRamp`] ended
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GripEnd {
+    /// The claw turned all the way to the grip
+    Reached,
+
+    /// The claw stalled on the object before getting there, see [`Robot::stall`]
+    Detected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GripError {
+    /// No grip with this name
+    Unknown(String),
+
+    /// Saving over a grip without replacing it
+    Exists(String),
+
+    /// The speed isn't above 0
+    Speed(f64),
+}
+
+impl Robot {
+    /// How far the claw is closed, 0 for open at its max angle to 100 for closed at its min
+    pub fn claw_percent(&self) -> f64 {
+        let claw = &self.arm.claw;
+        (claw.max - claw.angle) / (claw.max - claw.min) * 100.
+    }
+
+    /// Save how far the claw is closed as a grip in the program, recorded in the journal
+    ///
+    /// # Arguments
+    /// * `speed` - percent/s, `None` to keep the speed of the grip replaced or use
+    ///   [`DEFAULT_SPEED`]
+    /// * `replace` - overwrite a grip with the same name instead of failing
+    pub fn save_grip(
+        &mut self,
+        name: &str,
+        speed: Option<f64>,
+        replace: bool,
+    ) -> Result<Grip, GripError> {
+        let before = self.program.grips.get(name).copied();
+        if before.is_some() && !replace {
+            return Err(GripError::Exists(name.to_string()));
+        }
+
+        let speed = speed
+            .or(before.map(|grip| grip.speed))
+            .unwrap_or(DEFAULT_SPEED);
+        if !(speed > 0. && speed.is_finite()) {
+            return Err(GripError::Speed(speed));
+        }
+
+        let grip = Grip {
+            percent: self.claw_percent().clamp(0., 100.),
+            speed,
+        };
+        self.program.grips.insert(name.to_string(), grip);
+        self.journal_change(Change::Grip {
+            name: name.to_string(),
+            before,
+            after: Some(grip),
+        });
+        Ok(grip)
+    }
+
+    /// Turn the claw to a grip at its speed
+    ///
+    /// With a [`Robot::stall`] detector the claw stops early once it stalls on the object,
+    /// whichever comes first, and keeps squeezing where it stopped
+    pub fn use_grip(&mut self, name: &str) -> Result<(), GripError> {
+        let grip = self
+            .program
+            .grips
+            .get(name)
+            .ok_or_else(|| GripError::Unknown(name.to_string()))?;
+
+        let claw = &self.arm.claw;
+        let range = claw.max - claw.min;
+        self.claw_open = None;
+        self.grip = Some(GripRamp {
+            target: claw.max - range * grip.percent / 100.,
+            speed: range * grip.speed / 100.,
+        });
+        Ok(())
+    }
+
+    /// Turn the claw towards the grip
+    ///
+    /// # Returns
+    /// True once it's there
+    pub(super) fn grip_update(&mut self, delta: f64) -> bool {
+        let Some(ramp) = self.grip else {
+            return false;
+        };
+
+        let reached = turn(&mut self.arm.claw.angle, ramp.target, ramp.speed * delta);
+        if reached {
+            self.grip = None;
+        }
+        reached
+    }
+}
+
+impl fmt::Display for GripError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GripError::Unknown(name) => write!(f, "unknown grip `{name}`"),
+            GripError::Exists(name) => {
+                write!(f, "grip `{name}` exists, add `replace` to overwrite it")
+            }
+            GripError::Speed(speed) => write!(f, "grip speed is {speed}, expected above 0"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        kinematics::position::CordinateVec,
+        robot::{
+            arm::JointAngles,
+            stall::{StallConfig, StallDetector},
+            UpdateReport,
+        },
+    };
+
+    fn robot() -> Robot {
+        let mut robo = Robot {
+            position: CordinateVec::new(100., 50., 50.),
+            ..Default::default()
+        };
+        robo.arm.claw.angle = 180.;
+        robo
+    }
+
+    #[test]
+    fn presets() {
+        let mut robo = robot();
+        robo.arm.claw.angle = 72.;
+        let bottle = robo.save_grip("bottle", Some(20.), false).unwrap();
+        assert_eq!(
+            bottle,
+            Grip {
+                percent: 60.,
+                speed: 20.
+            }
+        );
+
+        // taken names aren't overwritten by accident, replacing keeps the speed
+        robo.arm.claw.angle = 36.;
+        assert_eq!(
+            robo.save_grip("bottle", None, false),
+            Err(GripError::Exists("bottle".to_string()))
+        );
+        assert_eq!(robo.program.grips["bottle"], bottle);
+        let tighter = robo.save_grip("bottle", None, true).unwrap();
+        assert_eq!(
+            tighter,
+            Grip {
+                percent: 80.,
+                speed: 20.
+            }
+        );
+        assert_eq!(
+            robo.save_grip("cup", Some(0.), false),
+            Err(GripError::Speed(0.))
+        );
+        assert_eq!(
+            robo.save_grip("cup", None, false).unwrap().speed,
+            DEFAULT_SPEED
+        );
+
+        // kept with the program and undone like a pose
+        let program = serde_json::to_string(&robo.program).unwrap();
+        assert!(program.contains(r#""grips":{"bottle":{"percent":80.0,"speed":20.0}"#));
+        robo.undo().unwrap();
+        robo.undo().unwrap();
+        assert_eq!(robo.program.grips["bottle"], bottle);
+
+        assert_eq!(
+            robo.use_grip("jar"),
+            Err(GripError::Unknown("jar".to_string()))
+        );
+    }
+
+    #[test]
+    fn ramp() {
+        let mut robo = robot();
+        robo.program.grips.insert(
+            "bottle".to_string(),
+            Grip {
+                percent: 50.,
+                speed: 25.,
+            },
+        );
+        robo.use_grip("bottle").unwrap();
+
+        // 45°/s from 180 to 90 takes 2s
+        let mut reached = None;
+        for tick in 1..=300 {
+            let report = robo.tick(0.01);
+            if report.grip.is_some() {
+                assert_eq!(report.grip, Some(GripEnd::Reached));
+                reached = Some(tick);
+                break;
+            }
+            assert!(robo.arm.claw.angle > 90.);
+        }
+        assert!((199..=201).contains(&reached.unwrap()));
+        assert_eq!(robo.arm.claw.angle, 90.);
+        assert_eq!(robo.grip, None);
+    }
+
+    #[test]
+    fn detect() {
+        let mut robo = Robot {
+            stall: Some(StallDetector::new(StallConfig::default())),
+            ..robot()
+        };
+        robo.program.grips.insert(
+            "bottle".to_string(),
+            Grip {
+                percent: 100.,
+                speed: 50.,
+            },
+        );
+        robo.use_grip("bottle").unwrap();
+
+        // the claw follows until it closes on the object at 120°
+        let mut report = UpdateReport::default();
+        for _ in 0..200 {
+            let claw = robo.arm.claw.angle.max(120.);
+            let angles = JointAngles {
+                claw,
+                ..robo.arm.angles()
+            };
+            robo.stall.as_mut().unwrap().report(angles);
+            report = robo.tick(0.01);
+            if report.grip.is_some() {
+                break;
+            }
+        }
+        assert_eq!(report.grip, Some(GripEnd::Detected));
+        assert_eq!(report.stalled, None);
+        assert_eq!(robo.stalled, None);
+        assert_eq!(robo.grip, None);
+
+        // squeezing a little past where it stopped, well short of closed
+        let claw = robo.arm.claw.angle;
+        assert!((90. ..120.).contains(&claw), "stopped at {claw}");
+        robo.tick(0.01);
+        assert_eq!(robo.arm.claw.angle, claw);
+    }
+}
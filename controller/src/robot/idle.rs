@@ -0,0 +1,168 @@
+use super::soft_start::SoftStart;
+use crate::protocol::Frame;
+
+/// Detaches the servos after the arm has rested for a while, so they don't draw current and
+/// get warm holding a pose they don't need to hold
+///
+/// The arm only counts as resting in a low torque pose, with the shoulder close to vertical.
+/// Any input attaches the servos again, then waits for them to settle and blends back to the
+/// commanded pose with [`IdlePolicy::resume`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdlePolicy {
+    /// Seconds of rest before detaching, infinite to never detach
+    pub detach_after: f64,
+
+    /// Seconds to wait after attaching before the servos are driven
+    pub settle: f64,
+
+    /// Largest shoulder angle from vertical in degrees that still counts as resting
+    pub rest_tolerance: f64,
+
+    /// Ramp back to the commanded pose once the servos have settled, it starts from the pose
+    /// reported by feedback since the arm may have sagged while detached
+    pub resume: SoftStart,
+
+    pub state: IdleState,
+
+    /// Frame to send on the next transmit
+    pub pending: Option<Frame>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IdleState {
+    /// Servos are driven, `rested` is how many seconds the arm has rested
+    Attached { rested: f64 },
+
+    Detached,
+
+    /// Servos are attached but not driven yet, `left` is the settle time left in seconds
+    Settling { left: f64 },
+}
+
+/// What the robot should do this tick
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IdleStep {
+    /// Run normally
+    Run,
+
+    /// Don't move or drive the servos
+    Hold,
+
+    /// The servos just settled, ramp back with [`IdlePolicy::resume`]
+    Resume,
+}
+
+impl IdlePolicy {
+    /// Advance the state machine
+    ///
+    /// # Arguments
+    /// * `busy` - there is input, a command or a trajectory, paused or not
+    /// * `resting` - the arm is still in a low torque pose
+    /// * `delta` - seconds since the last update
+    pub fn update(&mut self, busy: bool, resting: bool, delta: f64) -> IdleStep {
+        match self.state {
+            IdleState::Attached { rested } => {
+                let rested = if busy || !resting { 0. } else { rested + delta };
+
+                if rested >= self.detach_after {
+                    self.state = IdleState::Detached;
+                    self.pending = Some(Frame::Detach);
+                    return IdleStep::Hold;
+                }
+
+                self.state = IdleState::Attached { rested };
+                IdleStep::Run
+            }
+            IdleState::Detached => {
+                if busy {
+                    self.state = IdleState::Settling { left: self.settle };
+                    self.pending = Some(Frame::Attach);
+                }
+                IdleStep::Hold
+            }
+            IdleState::Settling { left } => {
+                let left = left - delta;
+                if left > 0. {
+                    self.state = IdleState::Settling { left };
+                    return IdleStep::Hold;
+                }
+
+                self.state = IdleState::Attached { rested: 0. };
+                IdleStep::Resume
+            }
+        }
+    }
+
+    /// True unless the servos are attached and driven
+    pub fn holding(&self) -> bool {
+        !matches!(self.state, IdleState::Attached { .. })
+    }
+}
+
+impl Default for IdlePolicy {
+    fn default() -> Self {
+        Self {
+            detach_after: f64::INFINITY,
+            settle: 0.2,
+            rest_tolerance: 15.,
+            resume: SoftStart::new(0.5, 30., Default::default()),
+            state: IdleState::Attached { rested: 0. },
+            pending: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy() -> IdlePolicy {
+        IdlePolicy {
+            detach_after: 1.,
+            settle: 0.5,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn detach_and_resume() {
+        let mut idle = policy();
+
+        for _ in 0..3 {
+            assert_eq!(idle.update(false, true, 0.25), IdleStep::Run);
+        }
+        assert_eq!(idle.pending, None);
+
+        assert_eq!(idle.update(false, true, 0.25), IdleStep::Hold);
+        assert_eq!(idle.state, IdleState::Detached);
+        assert_eq!(idle.pending.take(), Some(Frame::Detach));
+
+        // stays detached without input
+        assert_eq!(idle.update(false, true, 10.), IdleStep::Hold);
+        assert_eq!(idle.pending, None);
+
+        // input attaches right away but waits for the servos to settle
+        assert_eq!(idle.update(true, true, 0.25), IdleStep::Hold);
+        assert_eq!(idle.pending.take(), Some(Frame::Attach));
+        assert_eq!(idle.update(true, true, 0.25), IdleStep::Hold);
+        assert_eq!(idle.update(true, true, 0.25), IdleStep::Resume);
+        assert!(!idle.holding());
+        assert_eq!(idle.update(true, true, 0.25), IdleStep::Run);
+    }
+
+    #[test]
+    fn only_idle_at_rest() {
+        let mut idle = policy();
+
+        // busy or in a high torque pose resets the timer
+        for step in 0..30 {
+            let busy = step % 10 == 9;
+            let resting = step % 10 != 4;
+            assert_eq!(idle.update(busy, resting, 0.1), IdleStep::Run);
+        }
+
+        // never detaches by default
+        let mut idle = IdlePolicy::default();
+        assert_eq!(idle.update(false, true, 1e9), IdleStep::Run);
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+
+use super::Robot;
+use crate::kinematics::position::CordinateVec;
+
+/// What jogging does to a move the operator didn't drive themselves, a target position, a
+/// replay or a program
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JogOverride {
+    /// Any jog takes over and the move is dropped
+    #[default]
+    Cancel,
+
+    /// The jog takes over while the sticks are pushed, the move carries on from wherever the
+    /// arm is once they are let go and it stopped
+    Pause,
+
+    /// Jogs smaller than `below` on every axis are ignored, a stick knocked by accident
+    /// doesn't end the move. Bigger ones cancel it
+    Ignore { below: f64 },
+}
+
+impl Robot {
+    /// A move the operator didn't drive themselves is in progress
+    pub fn automated(&self) -> bool {
+        self.target_position.is_some() || self.replay.is_some() || self.program_run.is_some()
+    }
+
+    /// The jog changed to `jog`, a share of [`Robot::max_velocity`] in the operator's frame
+    pub(super) fn apply_jog(&mut self, jog: CordinateVec) {
+        if !self.automated() {
+            self.command_velocity(self.max_velocity * jog);
+            return;
+        }
+
+        match self.jog_override {
+            JogOverride::Cancel => self.command_velocity(self.max_velocity * jog),
+            JogOverride::Pause => {
+                self.jog_paused |= jog != CordinateVec::default();
+                self.target_velocity = self.operator_frame(self.max_velocity * jog);
+            }
+            JogOverride::Ignore { below } => {
+                let largest = jog.x.abs().max(jog.y.abs()).max(jog.z.abs());
+                if largest >= below {
+                    self.command_velocity(self.max_velocity * jog);
+                }
+            }
+        }
+    }
+
+    /// Carry on with the move paused by jogging once the sticks are let go and the arm stopped
+    pub(super) fn jog_pause_update(&mut self) {
+        let stopped =
+            self.jog == CordinateVec::default() && self.velocity == CordinateVec::default();
+        if self.jog_paused && stopped {
+            self.jog_paused = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::InputState;
+
+    /// On its way to a target a fair way off
+    fn robot(jog_override: JogOverride) -> Robot {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            cruise_speed: Some(20.),
+            jog_override,
+            ..Default::default()
+        };
+        robo.command_target(CordinateVec::new(45., 75., 75.));
+        for _ in 0..10 {
+            robo.tick(0.01);
+        }
+        robo
+    }
+
+    /// The stick pushed along x for `frames` frames and let go
+    fn flick(robo: &mut Robot, jog: f64, frames: usize) {
+        let mut input = InputState {
+            jog: CordinateVec::new(jog, 0., 0.),
+            connected: true,
+            ..Default::default()
+        };
+        for _ in 0..frames {
+            robo.apply_input(&input);
+            robo.tick(0.01);
+        }
+        input.jog = CordinateVec::default();
+        robo.apply_input(&input);
+    }
+
+    fn arrive(robo: &mut Robot) -> bool {
+        for _ in 0..1000 {
+            robo.tick(0.01);
+            if !robo.automated() {
+                return robo.position == CordinateVec::new(45., 75., 75.);
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn cancel() {
+        let mut robo = robot(JogOverride::Cancel);
+        flick(&mut robo, 0.05, 1);
+        assert_eq!(robo.target_position, None);
+        assert_eq!(robo.target_velocity, CordinateVec::default());
+    }
+
+    #[test]
+    fn pause() {
+        let mut robo = robot(JogOverride::Pause);
+        let before = robo.position;
+
+        // the move holds still while the jog backs the arm up
+        let mut input = InputState {
+            jog: CordinateVec::new(-0.3, -0.3, -0.3),
+            connected: true,
+            ..Default::default()
+        };
+        robo.apply_input(&input);
+        for _ in 0..50 {
+            robo.tick(0.01);
+        }
+        assert!(robo.jog_paused);
+        assert!(robo.position.x < before.x - 5.);
+        assert_eq!(robo.target_position, Some(CordinateVec::new(45., 75., 75.)));
+
+        // carries on once the arm stopped
+        input.jog = CordinateVec::default();
+        robo.apply_input(&input);
+        robo.tick(0.01);
+        assert!(robo.jog_paused);
+        assert!(arrive(&mut robo));
+        assert!(!robo.jog_paused);
+    }
+
+    #[test]
+    fn ignore_below() {
+        // flicked by accident during a goto, it still gets there
+        let mut robo = robot(JogOverride::Ignore { below: 0.2 });
+        flick(&mut robo, 0.15, 3);
+        assert!(robo.target_position.is_some());
+        assert!(arrive(&mut robo));
+
+        // pushed on purpose, the operator takes over
+        let mut robo = robot(JogOverride::Ignore { below: 0.2 });
+        flick(&mut robo, -0.5, 1);
+        assert_eq!(robo.target_position, None);
+
+        // nothing to ignore it for, small jogs move the arm
+        let mut robo = Robot {
+            jog_override: JogOverride::Ignore { below: 0.2 },
+            ..Default::default()
+        };
+        robo.apply_input(&InputState {
+            jog: CordinateVec::new(0.1, 0., 0.),
+            connected: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            robo.target_velocity,
+            robo.max_velocity * CordinateVec::new(0.1, 0., 0.)
+        );
+    }
+}
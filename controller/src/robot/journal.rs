@@ -0,0 +1,332 @@
+use std::{
+    collections::BTreeSet,
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{limits::JointRange, Robot};
+use crate::{
+    calibration::Calibration, kinematics::position::CordinateVec, logging::warn, program::Grip,
+};
+
+/// A change to state that is kept across sessions, with what it was before and after
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// A pose was stored, `before` is `None` unless it was overwritten
+    Pose {
+        name: String,
+        before: Option<CordinateVec>,
+        after: Option<CordinateVec>,
+    },
+
+    /// A joint range was learned
+    Limit {
+        joint: String,
+        before: Option<JointRange>,
+        after: Option<JointRange>,
+    },
+
+    Calibration {
+        before: Calibration,
+        after: Calibration,
+    },
+
+    /// A grip was saved, like [`Change::Pose`]
+    Grip {
+        name: String,
+        before: Option<Grip>,
+        after: Option<Grip>,
+    },
+
+    /// The change with this id was reverted
+    Undo { undone: u64 },
+}
+
+/// One line of the [`Journal`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+
+    /// Milliseconds since the unix epoch
+    pub time: u64,
+
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+/// Append only record of every change to persisted state, one JSON object per line
+///
+/// Every entry is synced to disk before the change counts as recorded, so a crash loses at
+/// most the line being written. A torn or otherwise unreadable line is skipped on load and
+/// the rest of the journal still replays
+///
+/// Only changes of this session can be undone. What was changed before is history, the pose
+/// bank, limits and calibration are loaded from files that may not have been saved after
+/// those changes
+#[derive(Debug, Default)]
+pub struct Journal {
+    /// File the entries are appended to, `None` to only keep them in memory
+    path: Option<PathBuf>,
+
+    pub entries: Vec<Entry>,
+
+    /// Entries read on load, from earlier sessions
+    loaded: usize,
+
+    /// Lines that couldn't be read on load
+    pub skipped: usize,
+
+    /// The file doesn't end in a newline, the next entry has to start on a fresh line
+    torn: bool,
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+
+    /// Every change was already undone
+    NothingToUndo,
+}
+
+impl Journal {
+    /// Read the journal at `path` and append to it from now on
+    ///
+    /// A missing file is an empty journal
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        let path = path.as_ref();
+        let mut journal = Self {
+            path: Some(path.to_path_buf()),
+            ..Default::default()
+        };
+
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(journal),
+            Err(err) => return Err(JournalError::Io(err)),
+        };
+        journal.torn = !data.is_empty() && !data.ends_with('\n');
+        for line in data.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(entry) => journal.entries.push(entry),
+                Err(_) => journal.skipped += 1,
+            }
+        }
+        journal.loaded = journal.entries.len();
+
+        Ok(journal)
+    }
+
+    /// Add a change at the end of the journal
+    ///
+    /// The entry is kept even if writing it fails
+    pub fn record(&mut self, change: Change) -> Result<&Entry, JournalError> {
+        let id = self
+            .entries
+            .iter()
+            .map(|entry| entry.id + 1)
+            .max()
+            .unwrap_or(0);
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+        self.entries.push(Entry { id, time, change });
+        let entry = &self.entries[self.entries.len() - 1];
+
+        if let Some(path) = &self.path {
+            let mut line = serde_json::to_string(entry).map_err(JournalError::Parse)?;
+            line.push('\n');
+            if self.torn {
+                line.insert(0, '\n');
+            }
+
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(JournalError::Io)?;
+            file.write_all(line.as_bytes()).map_err(JournalError::Io)?;
+            file.sync_data().map_err(JournalError::Io)?;
+            self.torn = false;
+        }
+
+        Ok(entry)
+    }
+
+    /// The most recent change of this session that wasn't undone yet
+    pub fn last_change(&self) -> Option<&Entry> {
+        let undone: BTreeSet<u64> = self
+            .entries
+            .iter()
+            .filter_map(|entry| match entry.change {
+                Change::Undo { undone } => Some(undone),
+                _ => None,
+            })
+            .collect();
+
+        self.entries[self.loaded..].iter().rev().find(|entry| {
+            !matches!(entry.change, Change::Undo { .. }) && !undone.contains(&entry.id)
+        })
+    }
+}
+
+impl Robot {
+    /// Record a change in the journal, warning if it can't be written
+    pub(super) fn journal_change(&mut self, change: Change) {
+        if let Err(err) = self.journal.record(change) {
+            warn(&format!("Could not write the journal: {err}"));
+        }
+    }
+
+    /// Revert the most recent change to persisted state this session that wasn't undone yet
+    ///
+    /// Like the change itself the revert is only in memory, it's kept by saving the limits,
+    /// exporting the program or copying the calibration into the config as before
+    ///
+    /// # Returns
+    /// The change that was reverted
+    pub fn undo(&mut self) -> Result<Change, JournalError> {
+        let entry = self
+            .journal
+            .last_change()
+            .cloned()
+            .ok_or(JournalError::NothingToUndo)?;
+
+        match &entry.change {
+            Change::Pose { name, before, .. } => match before {
+                Some(position) => {
+                    self.program.poses.insert(name.clone(), *position);
+                }
+                None => {
+                    self.program.poses.remove(name);
+                }
+            },
+            Change::Limit { joint, before, .. } => self.joint_limits.set(joint, *before),
+            Change::Calibration { before, .. } => self.calibration = *before,
+            Change::Grip { name, before, .. } => match before {
+                Some(grip) => {
+                    self.program.grips.insert(name.clone(), *grip);
+                }
+                None => {
+                    self.program.grips.remove(name);
+                }
+            },
+            Change::Undo { .. } => {}
+        }
+
+        self.journal
+            .record(Change::Undo { undone: entry.id })
+            .map(|_| entry.change)
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Pose { name, .. } => write!(f, "pose {name}"),
+            Change::Limit { joint, .. } => write!(f, "{joint} limits"),
+            Change::Calibration { .. } => write!(f, "calibration"),
+            Change::Grip { name, .. } => write!(f, "grip {name}"),
+            Change::Undo { undone } => write!(f, "undo of {undone}"),
+        }
+    }
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::Io(err) => write!(f, "{err}"),
+            JournalError::Parse(err) => write!(f, "invalid journal entry: {err}"),
+            JournalError::NothingToUndo => write!(f, "nothing to undo"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rac_journal_{name}_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn overwrite_then_undo() {
+        let path = path("undo");
+        let mut robo = Robot {
+            journal: Journal::open(&path).unwrap(),
+            position: CordinateVec::new(100., 50., 50.),
+            ..Default::default()
+        };
+
+        robo.save_pose("home");
+        let original = robo.program.poses["home"];
+        robo.position = CordinateVec::new(120., 60., 40.);
+        robo.save_pose("home");
+        assert_ne!(robo.program.poses["home"], original);
+
+        assert!(matches!(robo.undo(), Ok(Change::Pose { .. })));
+        assert_eq!(robo.program.poses["home"], original);
+        assert!(robo.undo().is_ok());
+        assert!(robo.program.poses.is_empty());
+        assert!(matches!(robo.undo(), Err(JournalError::NothingToUndo)));
+
+        // after a restart the changes are history, the pose bank may not have them
+        robo.position = CordinateVec::new(120., 60., 40.);
+        robo.save_pose("home");
+        let mut robo = Robot {
+            journal: Journal::open(&path).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(robo.journal.entries.len(), 5);
+        assert!(robo.journal.last_change().is_none());
+        assert!(matches!(robo.undo(), Err(JournalError::NothingToUndo)));
+        assert!(robo.program.poses.is_empty());
+
+        // changes made since can be undone
+        robo.save_pose("away");
+        assert!(matches!(robo.undo(), Ok(Change::Pose { .. })));
+        assert!(robo.program.poses.is_empty());
+        assert!(matches!(robo.undo(), Err(JournalError::NothingToUndo)));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corrupt() {
+        let path = path("corrupt");
+        let mut journal = Journal::open(&path).unwrap();
+        let change = Change::Calibration {
+            before: Calibration::default(),
+            after: Calibration {
+                scale: 2.,
+                ..Default::default()
+            },
+        };
+        journal.record(change.clone()).unwrap();
+
+        // garbage in the middle and a line torn off by a crash at the end
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"not json\n{\"id\": 7, \"time\": 0, \"kind\": \"po")
+            .unwrap();
+
+        let mut journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.skipped, 2);
+        assert_eq!(journal.entries.len(), 1);
+        assert_eq!(journal.entries[0].change, change);
+
+        // the next entry starts on a line of its own
+        journal.record(change.clone()).unwrap();
+        let journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.skipped, 2);
+        assert_eq!(journal.entries.len(), 2);
+        assert_eq!(journal.entries[1].id, 1);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,511 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    arm::{Arm, JointAngles},
+    stall::{StallConfig, StallDetector},
+};
+
+/// Fastest a limit search turns a joint in degrees/s, whatever is configured
+pub const MAX_SEARCH_SPEED: f64 = 10.;
+
+/// How a [`LimitFinder`] searches for the end stops
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitSearchConfig {
+    /// Degrees/s the joint turns towards its ends, between 1 and [`MAX_SEARCH_SPEED`]
+    pub speed: f64,
+
+    /// Degrees the limit is moved back from where the joint stopped
+    pub margin: f64,
+
+    /// Seconds without feedback before the search is aborted, a stall can't be seen without it
+    pub feedback_timeout: f64,
+}
+
+/// Range of a joint in degrees
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JointRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Ranges found by a [`LimitFinder`], the arm is never moved outside of them
+///
+/// Joints that were never searched have no limits besides their configured range
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JointLimits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<JointRange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shoulder: Option<JointRange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elbow: Option<JointRange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claw: Option<JointRange>,
+}
+
+/// Which end of its range a joint is moving to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum End {
+    Min,
+    Max,
+}
+
+/// Where a [`LimitFinder`] is at
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SearchStage {
+    /// Waiting for the operator to confirm the joint, nothing moves yet
+    Confirm,
+
+    /// Turning towards an end until the joint stalls or the configured limit is reached
+    Seek(End),
+
+    /// Turning back from the end stop to the limit
+    BackOff { end: End, limit: f64 },
+}
+
+/// Why a limit search stopped without a result
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LimitAbort {
+    /// Something else was commanded while searching
+    Interrupted,
+
+    EmergencyStop,
+
+    /// The link to the arduino went down, see [`super::link::LinkPolicy`]
+    LinkDown,
+
+    /// No feedback for [`LimitSearchConfig::feedback_timeout`]
+    NoFeedback,
+
+    /// A joint other than the one searched stalled
+    Stalled(&'static str),
+
+    /// Less than nothing is left between the limits after the margins
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    /// Not the name of a joint
+    UnknownJoint(String),
+
+    /// The arm is moving, stopped or already searching
+    Busy,
+
+    /// Confirmed a joint that isn't waiting for confirmation
+    NotWaiting(String),
+}
+
+/// Finds the end stops of a joint by slowly turning it towards each end until it stalls
+///
+/// The joint first waits for [`LimitFinder::confirm`], then turns towards its minimum, backs
+/// off by the margin from where it stalled and does the same towards its maximum. Ends
+/// without an end stop keep the configured limit. The search is driven by
+/// [`LimitFinder::update`] and needs the feedback passed to [`LimitFinder::report`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LimitFinder {
+    pub joint: &'static str,
+    pub stage: SearchStage,
+    pub config: LimitSearchConfig,
+
+    /// Configured range of the joint, nothing outside of it is searched
+    pub range: JointRange,
+
+    stall: StallDetector,
+
+    /// Angle of the joint in the latest feedback
+    reported: Option<f64>,
+
+    /// Seconds since the latest feedback
+    silent: f64,
+
+    /// Limit found at the minimum end
+    min: Option<f64>,
+}
+
+impl LimitFinder {
+    pub fn new(config: LimitSearchConfig, joint: &'static str, range: JointRange) -> Self {
+        let config = LimitSearchConfig {
+            speed: config.speed.clamp(1., MAX_SEARCH_SPEED),
+            ..config
+        };
+        // a joint stopped against the end stop falls behind by the whole search speed
+        let threshold = config.speed / 2.;
+        let stall = StallDetector::new(StallConfig {
+            enabled: true,
+            threshold: JointAngles {
+                base: threshold,
+                shoulder: threshold,
+                elbow: threshold,
+                claw: threshold,
+            },
+            duration: 0.3,
+            smoothing: 0.1,
+        });
+
+        Self {
+            joint,
+            stage: SearchStage::Confirm,
+            config,
+            range,
+            stall,
+            reported: None,
+            silent: 0.,
+            min: None,
+        }
+    }
+
+    /// The operator confirmed the joint may move, start with the minimum
+    pub fn confirm(&mut self) {
+        if self.stage == SearchStage::Confirm {
+            self.stage = SearchStage::Seek(End::Min);
+            self.silent = 0.;
+            self.stall.reset();
+        }
+    }
+
+    /// The feedback reported the joints at these angles
+    pub fn report(&mut self, angles: JointAngles) {
+        self.stall.report(angles);
+        self.reported = angles.get(self.joint);
+        self.silent = 0.;
+    }
+
+    /// Turn the joint for `delta` seconds
+    ///
+    /// # Returns
+    /// The range found once both ends are done, or why the search stopped
+    pub fn update(&mut self, arm: &mut Arm, delta: f64) -> Option<Result<JointRange, LimitAbort>> {
+        if let SearchStage::Seek(_) = self.stage {
+            self.silent += delta;
+            if self.silent > self.config.feedback_timeout {
+                return Some(Err(self.abort(arm, LimitAbort::NoFeedback)));
+            }
+        }
+
+        let step = self.config.speed * delta;
+        let Some(joint) = arm.joint_mut(self.joint) else {
+            return Some(Err(LimitAbort::Interrupted));
+        };
+
+        match self.stage {
+            SearchStage::Confirm => None,
+            SearchStage::Seek(end) => {
+                let bound = match end {
+                    End::Min => self.range.min,
+                    End::Max => self.range.max,
+                };
+                let reached = turn(&mut joint.angle, bound, step);
+
+                match self.stall.update(arm.angles(), delta) {
+                    Some(stalled) if stalled != self.joint => {
+                        Some(Err(self.abort(arm, LimitAbort::Stalled(stalled))))
+                    }
+                    Some(_) => {
+                        let stop = self.reported.unwrap_or(bound);
+                        let limit = match end {
+                            End::Min => stop + self.config.margin,
+                            End::Max => stop - self.config.margin,
+                        };
+                        self.stage = SearchStage::BackOff { end, limit };
+                        None
+                    }
+                    // no end stop short of the configured limit, which stays as it is
+                    None if reached => self.next(end, bound),
+                    None => None,
+                }
+            }
+            SearchStage::BackOff { end, limit } => {
+                if turn(&mut joint.angle, limit, step) {
+                    return self.next(end, limit);
+                }
+                None
+            }
+        }
+    }
+
+    /// Stop searching, the joint is commanded to where it was reported so it doesn't keep
+    /// pushing against an end stop
+    pub fn abort(&mut self, arm: &mut Arm, reason: LimitAbort) -> LimitAbort {
+        if let (Some(joint), Some(reported)) = (arm.joint_mut(self.joint), self.reported) {
+            joint.angle = reported;
+        }
+
+        reason
+    }
+
+    /// An end is done with its limit at `limit`
+    fn next(&mut self, end: End, limit: f64) -> Option<Result<JointRange, LimitAbort>> {
+        match end {
+            End::Min => {
+                self.min = Some(limit);
+                self.stage = SearchStage::Seek(End::Max);
+                self.stall.reset();
+                None
+            }
+            End::Max => {
+                let min = self.min.unwrap_or(self.range.min);
+                if limit <= min {
+                    return Some(Err(LimitAbort::Empty));
+                }
+
+                Some(Ok(JointRange { min, max: limit }))
+            }
+        }
+    }
+}
+
+/// Turn `angle` by at most `step` towards `target`
+///
+/// # Returns
+/// True once it's there
+pub(super) fn turn(angle: &mut f64, target: f64, step: f64) -> bool {
+    if (target - *angle).abs() <= step {
+        *angle = target;
+        return true;
+    }
+
+    *angle += step.copysign(target - *angle);
+    false
+}
+
+impl JointRange {
+    pub fn contains(&self, angle: f64) -> bool {
+        (self.min..=self.max).contains(&angle)
+    }
+}
+
+impl JointLimits {
+    /// Learned range of the joint with this name
+    pub fn get(&self, joint: &str) -> Option<JointRange> {
+        match joint {
+            "base" => self.base,
+            "shoulder" => self.shoulder,
+            "elbow" => self.elbow,
+            "claw" => self.claw,
+            _ => None,
+        }
+    }
+
+    /// Set or, with `None`, forget the range of the joint with this name
+    pub fn set(&mut self, joint: &str, range: Option<JointRange>) {
+        match joint {
+            "base" => self.base = range,
+            "shoulder" => self.shoulder = range,
+            "elbow" => self.elbow = range,
+            "claw" => self.claw = range,
+            _ => {}
+        }
+    }
+
+    /// True if every joint is within its learned range
+    pub fn allows(&self, angles: JointAngles) -> bool {
+        let allows =
+            |range: Option<JointRange>, angle| range.is_none_or(|range| range.contains(angle));
+
+        allows(self.base, angles.base)
+            && allows(self.shoulder, angles.shoulder)
+            && allows(self.elbow, angles.elbow)
+            && allows(self.claw, angles.claw)
+    }
+}
+
+impl Default for LimitSearchConfig {
+    fn default() -> Self {
+        Self {
+            speed: 5.,
+            margin: 3.,
+            feedback_timeout: 0.5,
+        }
+    }
+}
+
+impl fmt::Display for LimitAbort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitAbort::Interrupted => write!(f, "interrupted by another command"),
+            LimitAbort::EmergencyStop => write!(f, "emergency stop"),
+            LimitAbort::LinkDown => write!(f, "link down"),
+            LimitAbort::NoFeedback => write!(f, "no feedback"),
+            LimitAbort::Stalled(joint) => write!(f, "the {joint} stalled"),
+            LimitAbort::Empty => write!(f, "nothing left between the limits after the margins"),
+        }
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::UnknownJoint(name) => write!(f, "unknown joint `{name}`"),
+            LimitError::Busy => write!(f, "the arm is busy"),
+            LimitError::NotWaiting(name) => {
+                write!(
+                    f,
+                    "the {name} isn't waiting for confirmation, start with `limits find`"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kinematics::joints::{DirectDrive, Joint};
+
+    fn resting() -> Arm {
+        let mut arm = Arm {
+            shoulder: Joint::new(0., 180., DirectDrive::new()),
+            ..Default::default()
+        };
+        arm.shoulder.angle = 90.;
+        arm
+    }
+
+    fn confirmed() -> LimitFinder {
+        let mut finder = LimitFinder::new(
+            LimitSearchConfig::default(),
+            "shoulder",
+            JointRange { min: 0., max: 180. },
+        );
+        finder.confirm();
+        finder
+    }
+
+    /// Search at 100Hz with the shoulder following its commanded angle between `stops`,
+    /// reported every tick
+    fn search(
+        finder: &mut LimitFinder,
+        arm: &mut Arm,
+        stops: (f64, f64),
+    ) -> (Result<JointRange, LimitAbort>, u32) {
+        for tick in 1..=20_000 {
+            let mut reported = arm.angles();
+            reported.shoulder = reported.shoulder.clamp(stops.0, stops.1);
+            finder.report(reported);
+
+            if let Some(result) = finder.update(arm, 0.01) {
+                return (result, tick);
+            }
+        }
+        panic!("never finished, at {:?}", finder.stage);
+    }
+
+    #[test]
+    fn finds_end_stops() {
+        let (mut finder, mut arm) = (confirmed(), resting());
+        let (range, ticks) = search(&mut finder, &mut arm, (20., 150.));
+        let range = range.unwrap();
+
+        // the margin away from the stops, give or take the stall detection lag
+        assert!((range.min - 23.).abs() < 0.01, "{range:?}");
+        assert!((range.max - 147.).abs() < 0.01, "{range:?}");
+        assert_eq!(arm.shoulder.angle, range.max);
+
+        // at no more than the search speed, with a little overshoot into the stops
+        let travel = (90. - 20.) + 3. + (150. - 23.) + 3.;
+        assert!(ticks as f64 * 0.05 >= travel);
+        assert!((ticks as f64 * 0.05) < travel + 12.);
+    }
+
+    #[test]
+    fn no_end_stop() {
+        let (mut finder, mut arm) = (confirmed(), resting());
+        let (range, _) = search(&mut finder, &mut arm, (f64::NEG_INFINITY, 160.));
+        assert_eq!(range, Ok(JointRange { min: 0., max: 157. }));
+    }
+
+    #[test]
+    fn waits_for_confirmation() {
+        let mut finder = LimitFinder::new(
+            LimitSearchConfig {
+                speed: 100.,
+                ..Default::default()
+            },
+            "shoulder",
+            JointRange { min: 0., max: 180. },
+        );
+        let mut arm = resting();
+        for _ in 0..100 {
+            assert_eq!(finder.update(&mut arm, 0.01), None);
+        }
+        assert_eq!(arm.shoulder.angle, 90.);
+        assert_eq!(finder.stage, SearchStage::Confirm);
+
+        // and never goes faster than the cap
+        finder.confirm();
+        finder.report(arm.angles());
+        finder.update(&mut arm, 0.01);
+        assert_eq!(arm.shoulder.angle, 90. - MAX_SEARCH_SPEED * 0.01);
+    }
+
+    #[test]
+    fn aborts() {
+        // without feedback a stall can't be seen
+        let (mut finder, mut arm) = (confirmed(), resting());
+        let result = (0..100).find_map(|_| finder.update(&mut arm, 0.01));
+        assert_eq!(result, Some(Err(LimitAbort::NoFeedback)));
+
+        // margins that overlap
+        let mut finder = LimitFinder::new(
+            LimitSearchConfig {
+                margin: 20.,
+                ..Default::default()
+            },
+            "shoulder",
+            JointRange { min: 0., max: 180. },
+        );
+        finder.confirm();
+        let mut arm = resting();
+        let (result, _) = search(&mut finder, &mut arm, (80., 110.));
+        assert_eq!(result, Err(LimitAbort::Empty));
+
+        // another joint stalls, the searched one stops pushing
+        let (mut finder, mut arm) = (confirmed(), resting());
+        let result = (1..=1000).find_map(|tick| {
+            let mut reported = arm.angles();
+            reported.shoulder = reported.shoulder.max(60.);
+            // the elbow sags away from where it's held
+            reported.elbow = -tick as f64;
+            finder.report(reported);
+            finder.update(&mut arm, 0.01)
+        });
+        assert_eq!(result, Some(Err(LimitAbort::Stalled("elbow"))));
+    }
+
+    #[test]
+    fn limits() {
+        let mut limits = JointLimits::default();
+        let angles = JointAngles {
+            shoulder: 10.,
+            ..Default::default()
+        };
+        assert!(limits.allows(angles));
+
+        limits.set(
+            "shoulder",
+            Some(JointRange {
+                min: 20.,
+                max: 150.,
+            }),
+        );
+        assert!(!limits.allows(angles));
+        assert_eq!(
+            limits.get("shoulder"),
+            Some(JointRange {
+                min: 20.,
+                max: 150.
+            })
+        );
+        assert_eq!(limits.get("elbow"), None);
+
+        // only the learned joints are written
+        let json = serde_json::to_string(&limits).unwrap();
+        assert_eq!(json, r#"{"shoulder":{"min":20.0,"max":150.0}}"#);
+        assert_eq!(serde_json::from_str::<JointLimits>(&json).unwrap(), limits);
+    }
+}
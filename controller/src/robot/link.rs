@@ -0,0 +1,223 @@
+use std::time::{Duration, Instant};
+
+use crate::stats::ConnectionStats;
+
+/// Maps link quality to a speed factor
+///
+/// Below `floor` the arm stops, above `full` it moves at full speed and in between the speed
+/// scales linearly
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpeedCurve {
+    /// Quality below which the arm doesn't move at all
+    pub floor: f64,
+
+    /// Quality from which the arm moves at full speed
+    pub full: f64,
+}
+
+impl SpeedCurve {
+    /// Speed factor between 0 and 1 for the given quality
+    pub fn scale(&self, quality: f64) -> f64 {
+        if quality < self.floor {
+            return 0.;
+        }
+        if quality >= self.full {
+            return 1.;
+        }
+
+        (quality - self.floor) / (self.full - self.floor)
+    }
+}
+
+impl Default for SpeedCurve {
+    fn default() -> Self {
+        Self {
+            floor: 0.3,
+            full: 0.8,
+        }
+    }
+}
+
+/// Scores the link to the arduino and slows the arm down when it gets unreliable
+///
+/// The score is between 0 (unusable) and 1 (perfect) and is built from
+/// * the share of frames lost since the last update (framing errors and dropped frames)
+/// * how long ago the last ACK or feedback frame arrived
+/// * reconnects, which drop the score to 0
+///
+/// Each update only moves the score part of the way towards the new measurement so a single bad
+/// tick doesn't stop the arm and the speed comes back gradually once the link recovers
+#[derive(Debug)]
+pub struct LinkPolicy {
+    pub curve: SpeedCurve,
+
+    /// Silence after which the link counts as dead, the score falls linearly until then
+    pub stale_after: Duration,
+
+    /// How far the score moves towards a new measurement each update, between 0 and 1
+    pub smoothing: f64,
+
+    /// Current score
+    pub quality: f64,
+
+    /// Stats seen on the previous update
+    pub last: ConnectionStats,
+}
+
+impl LinkPolicy {
+    /// # Arguments
+    /// * `curve` - how the score maps to speed
+    /// * `stale_after` - how long the arduino can be silent before the link counts as dead
+    /// * `smoothing` - how fast the score follows new measurements, 1 to follow immediately
+    pub fn new(curve: SpeedCurve, stale_after: Duration, smoothing: f64) -> Self {
+        Self {
+            curve,
+            stale_after,
+            smoothing,
+            quality: 1.,
+            last: ConnectionStats::default(),
+        }
+    }
+
+    /// Quality of the link measured since the previous update, without smoothing
+    ///
+    /// # Arguments
+    /// * `stats` - current connection stats
+    /// * `last_heard` - when the last ACK or feedback frame arrived, `None` if nothing did yet
+    /// * `now` - current time
+    pub fn measure(&self, stats: &ConnectionStats, last_heard: Option<Instant>, now: Instant) -> f64 {
+        if stats.baud_switches > self.last.baud_switches {
+            return 0.;
+        }
+
+        // dropped frames were decoded before being thrown away so they are part of `received`
+        let received = stats.frames_received - self.last.frames_received;
+        let errors = stats.framing_errors - self.last.framing_errors;
+        let dropped = stats.frames_dropped - self.last.frames_dropped;
+        let delivered = match received + errors {
+            0 => 1.,
+            total => 1. - ((errors + dropped) as f64 / total as f64).min(1.),
+        };
+
+        // nothing received yet means there is nothing to judge, not a dead link
+        let fresh = match last_heard {
+            None => 1.,
+            Some(heard) => {
+                let age = now.saturating_duration_since(heard);
+                1. - (age.as_secs_f64() / self.stale_after.as_secs_f64()).min(1.)
+            }
+        };
+
+        delivered * fresh
+    }
+
+    /// Take a new measurement and update the score
+    ///
+    /// # Returns
+    /// The factor to scale the speed of the arm with
+    pub fn update(&mut self, stats: &ConnectionStats, last_heard: Option<Instant>, now: Instant) -> f64 {
+        let measured = self.measure(stats, last_heard, now);
+        self.quality += (measured - self.quality) * self.smoothing;
+        self.last = *stats;
+
+        self.scale()
+    }
+
+    /// Factor to scale the speed of the arm with for the current score
+    pub fn scale(&self) -> f64 {
+        self.curve.scale(self.quality)
+    }
+}
+
+impl Default for LinkPolicy {
+    fn default() -> Self {
+        Self::new(SpeedCurve::default(), Duration::from_secs(1), 0.25)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn curve() {
+        let curve = SpeedCurve {
+            floor: 0.2,
+            full: 0.6,
+        };
+
+        assert_eq!(curve.scale(0.1), 0.);
+        assert_eq!(curve.scale(0.2), 0.);
+        assert!((curve.scale(0.4) - 0.5).abs() < 1e-9);
+        assert_eq!(curve.scale(0.6), 1.);
+        assert_eq!(curve.scale(1.), 1.);
+    }
+
+    #[test]
+    fn degrade_and_recover() {
+        let now = Instant::now();
+        let mut policy = LinkPolicy::default();
+        // healthy link
+        let mut stats = ConnectionStats {
+            frames_received: 50,
+            ..Default::default()
+        };
+        assert_eq!(policy.update(&stats, Some(now), now), 1.);
+
+        // most frames lost, speed drops over a few updates without jumping straight to 0
+        let mut scales = Vec::new();
+        for _ in 0..10 {
+            stats.frames_received += 1;
+            stats.framing_errors += 9;
+            scales.push(policy.update(&stats, Some(now), now));
+        }
+        assert!(scales[0] > 0.);
+        assert!(scales.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(*scales.last().unwrap(), 0.);
+
+        // link recovers, so does the speed
+        for _ in 0..30 {
+            stats.frames_received += 10;
+            policy.update(&stats, Some(now), now);
+        }
+        assert_eq!(policy.scale(), 1.);
+    }
+
+    #[test]
+    fn stale_link_stops() {
+        let now = Instant::now();
+        let mut policy = LinkPolicy {
+            smoothing: 1.,
+            ..Default::default()
+        };
+        let stats = ConnectionStats::default();
+
+        // never heard anything, nothing to judge
+        assert_eq!(policy.update(&stats, None, now), 1.);
+
+        let heard = now - Duration::from_millis(500);
+        assert!((policy.measure(&stats, Some(heard), now) - 0.5).abs() < 1e-9);
+        assert!((policy.update(&stats, Some(heard), now) - 0.4).abs() < 1e-9);
+
+        let heard = now - Duration::from_secs(2);
+        assert_eq!(policy.update(&stats, Some(heard), now), 0.);
+    }
+
+    #[test]
+    fn reconnect_drops_quality() {
+        let now = Instant::now();
+        let mut policy = LinkPolicy {
+            smoothing: 1.,
+            ..Default::default()
+        };
+        let stats = ConnectionStats {
+            baud_switches: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.update(&stats, Some(now), now), 0.);
+
+        // no new reconnects on the next update
+        assert_eq!(policy.update(&stats, Some(now), now), 1.);
+    }
+}
@@ -1,13 +1,101 @@
-use std::cmp::PartialEq;
+use std::{cmp::PartialEq, time::Instant};
 use crate::{
+    calibration::{Calibration, ReferencePoint},
     communication::{ComError, Connection},
-    kinematics::position::CordinateVec,
-    kinematics::joints::Joint,
-    logging::warn,
+    input::InputState,
+    kinematics::approx::ApproxEq,
+    kinematics::constraint::{resolve, Floor, MinReach, Reach, WorkspaceConstraint},
+    kinematics::position::{wrist_angle, CordinateVec, IkLimits, JointName, KinematicsError},
+    kinematics::joints::{Joint, Motion, MotionError},
+    logging::{info, warn},
+    program::{Program, ProgramError, ProgramRun},
+    protocol::{Feedback, Frame, ProtocolError, ServoEncoding, MAX_PULSE, MIN_PULSE},
+    recording::{Recorder, Recording, RecordingError, Replay, Transform},
+    session,
+    stats::{ConnectionStats, LoopStats},
 };
 
-use gilrs::{Axis, Button, Gamepad};
+use arm::{ClawPresets, JointAngles};
+use boundary::BoundaryConfig;
+use bundle::Bundle;
+use envelope::{Envelope, EnvelopeError, EnvelopeMode};
+use estop::{EmergencyStop, StopStage};
+use grip::{GripEnd, GripRamp};
+use events::{Event, EventQueue, RobotEvent};
+use idle::{IdlePolicy, IdleState, IdleStep};
+use jog::JogOverride;
+use journal::{Change, Journal};
+use limits::{
+    JointLimits, JointRange, LimitAbort, LimitError, LimitFinder, LimitSearchConfig, SearchStage,
+};
+use link::LinkPolicy;
+use correction::FeedbackCorrection;
+use odometer::Odometer;
+use output::{Dither, OutputRate};
+use payload::Derating;
+use segment_stream::SegmentStream;
+use servo_override::ServoOverride;
+use shutdown::{Shutdown, ShutdownConfig, ShutdownStep};
+use supply::{SupplyLevel, SupplyMonitor};
+use telemetry::TelemetryWriter;
+use soft_start::SoftStart;
+use startup::StartupConfig;
+use stall::StallDetector;
+use status::{FirmwareStatusView, StatusPoller};
+use torque::TorqueLimit;
+use workspace::{KeepOut, WorkspaceError, Workspaces};
 pub mod arm;
+pub mod audit;
+pub mod boundary;
+pub mod bundle;
+pub mod coordinator;
+pub mod correction;
+pub mod dry_run;
+pub mod envelope;
+pub mod estop;
+pub mod events;
+pub mod goto;
+pub mod grip;
+pub mod idle;
+pub mod jog;
+pub mod journal;
+pub mod limits;
+pub mod link;
+pub mod odometer;
+pub mod output;
+pub mod payload;
+pub mod segment_stream;
+pub mod servo_override;
+pub mod shutdown;
+pub mod soft_start;
+pub mod stall;
+pub mod startup;
+pub mod status;
+pub mod supply;
+pub mod telemetry;
+pub mod torque;
+pub mod workspace;
+
+/// Maximum number of received frames handled in one call to [`Robot::update`]
+///
+/// Anything beyond this stays queued for the next update so a chatty arduino can't stall the loop
+pub const MAX_FRAMES_PER_TICK: usize = 16;
+
+/// Most physics substeps in one tick, a tick after a long stall only catches up on this many
+/// so it can't hang the loop, see [`Robot::max_substep`]
+pub const MAX_SUBSTEPS: u32 = 100;
+
+/// Speed in units/s across the way to a new target below which [`Robot::retarget`] is done
+/// braking it away, as slow as the head may be going when it counts as arrived
+const RETARGET_TOLERANCE: f64 = 0.07;
+
+/// Distance from the target within which the head settles on it once it's slow enough, see
+/// [`RETARGET_TOLERANCE`]
+const ARRIVED_DISTANCE: f64 = 0.04;
+
+/// Seconds between warnings about a joint that stays within its soft margin, see
+/// [`Robot::soft_margin`]
+const SOFT_MARGIN_WARN_INTERVAL: f64 = 5.;
 
 /// Defines a robot and its physical properties
 #[derive(Debug)]
@@ -45,216 +133,4488 @@ pub struct Robot {
     pub arm: arm::Arm,
     pub upper_arm: f64,
     pub lower_arm: f64,
-    pub claw_open: bool,
+
+    /// Length from the [`arm::Arm::wrist`] to the tip of the claw
+    pub wrist_length: f64,
+
+    /// Angle the [`arm::Arm::wrist`] holds the claw at from the z axis in degrees, 90 is level
+    pub claw_pitch: f64,
+
+    /// Where the gripper grips from where the inverse kinematics put the claw, turned with
+    /// the base, see [`CordinateVec::tool_mount`]. The head is the gripper
+    pub end_effector_offset: CordinateVec,
+
+    /// Hold the claw open or closed at [`Robot::claw_presets`] every tick, `None` to leave it
+    /// where grips, programs and joint replays turn it. Each of those sets it back to `None`
+    pub claw_open: Option<bool>,
+    pub claw_presets: ClawPresets,
     pub connection: Connection,
+
+    /// Latest servo feedback reported by the arduino, `None` until the first feedback frame
+    pub feedback: Option<Feedback>,
+
+    /// Firmware status queries and the latest answer
+    pub status: StatusPoller,
+
+    /// When the last feedback or ACK frame arrived, `None` if none did yet
+    pub last_heard: Option<Instant>,
+
+    /// Slows the arm down when the link to the arduino degrades
+    pub link: LinkPolicy,
+
+    /// Recording currently being replayed, drives [`Robot::target_position`] while set
+    pub replay: Option<Replay>,
+
+    /// Joint space recording currently being replayed, drives the joints directly while set
+    pub joint_replay: Option<Replay<JointAngles>>,
+
+    /// Records the joint angles every tick while set
+    pub recorder: Option<Recorder<JointAngles>>,
+
+    /// Mirror mode for an operator standing on the other side of the arm
+    ///
+    /// Negates x of everything the operator commands, see [`Robot::command_velocity`] and
+    /// [`Robot::command_target`]. Positions are always stored and replayed unmirrored so
+    /// recordings work the same in either mode
+    pub mirror: bool,
+
+    /// Jog last commanded by [`Robot::apply_input`], as a share of [`Robot::max_velocity`]
+    pub jog: CordinateVec,
+
+    /// What jogging does to a target position, replay or program in progress
+    pub jog_override: JogOverride,
+
+    /// The operator is jogging over a move under [`JogOverride::Pause`], it carries on once
+    /// the sticks are let go
+    pub jog_paused: bool,
+
+    /// Speed in units/s when moving to [`Robot::target_position`], `None` to only be limited
+    /// by the acceleration
+    pub cruise_speed: Option<f64>,
+
+    /// The target changed while the head was moving, the velocity across the way to it is
+    /// braked away before heading there, see [`Robot::retarget`]
+    pub retargeting: bool,
+
+    /// Ramps the servos to the first pose after connecting, all input is ignored until it's done
+    pub soft_start: Option<SoftStart>,
+
+    /// How the arm starts, taken once the first soft start begins, see
+    /// [`Robot::prepare_startup`]
+    pub startup: Option<StartupConfig>,
+
+    /// How far every joint turned
+    pub odometer: Odometer,
+
+    /// Changes to persisted state, for undoing them
+    pub journal: Journal,
+
+    /// How often the servo positions are sent, see [`Robot::transmit`]
+    pub output: OutputRate,
+
+    /// Dithers the servo positions sent if set, see [`Dither`]
+    pub dither: Option<Dither>,
+
+    /// Hash of the config the robot was built from, see [`crate::config::Config::hash`]
+    pub config_hash: u64,
+
+    /// Where and with what config the logs are bundled, see [`Robot::write_bundle`]
+    pub bundle: Bundle,
+
+    /// Detaches the servos while the arm rests
+    pub idle: IdlePolicy,
+
+    /// Maps internal units to real world coordinates for everything the operator enters
+    pub calibration: Calibration,
+
+    /// Reference points collected for the next [`Calibration::fit`]
+    pub calibration_points: Vec<ReferencePoint>,
+
+    /// Timing of [`Robot::update`]
+    pub loop_stats: LoopStats,
+
+    /// Number of times a NaN or infinity was caught before it reached the servos, see [`Fault`]
+    pub faults: u64,
+
+    /// Longest physics step in seconds, longer ticks are split into equal substeps. `None` to
+    /// take the whole tick in one step
+    pub max_substep: Option<f64>,
+
+    /// Events from [`Robot::update`] waiting for [`Robot::take_events`]
+    pub events: EventQueue,
+
+    /// Floor, keep out zones and speed cap for where the arm is used
+    pub workspaces: Workspaces,
+
+    /// Slows the arm down in poses that load the servos heavily, `None` to never slow down
+    pub torque_limit: Option<TorqueLimit>,
+
+    /// Factor the [`Robot::torque_limit`] scales acceleration and speed with this tick
+    pub torque_scale: f64,
+
+    /// Grams the claw carries, see [`Robot::set_payload`]
+    pub payload: f64,
+
+    /// Reach and acceleration left with the [`Robot::payload`]
+    pub derating: Derating,
+
+    /// Region taught by driving the head around it, see [`Robot::set_envelope_mode`]
+    pub envelope: Envelope,
+    pub envelope_mode: EnvelopeMode,
+
+    /// The head was inside the envelope since it started being enforced, it's only kept in
+    /// from then on so it can first be moved in from wherever it was
+    pub envelope_entered: bool,
+
+    /// Notices joints that stop following their commanded angles, `None` to never check
+    pub stall: Option<StallDetector>,
+
+    /// Trims what is sent so the servos report the commanded angles, `None` to send them as
+    /// they are
+    pub correction: Option<FeedbackCorrection>,
+
+    /// Joint that stalled, the replay and target position are paused until
+    /// [`Robot::resume`]
+    pub stalled: Option<&'static str>,
+
+    /// Joint whose servo is held at the end of its range, see [`arm::Arm::saturated`]. Warned
+    /// about once when it starts
+    pub saturated: Option<&'static str>,
+
+    /// Joint within its soft margin, see [`arm::Arm::in_soft_margin`]. Warned about when it
+    /// gets there and every [`SOFT_MARGIN_WARN_INTERVAL`] while it stays
+    pub soft_margin: Option<&'static str>,
+
+    /// [`session::clock`] seconds of the last warning about [`Robot::soft_margin`]
+    pub soft_margin_warned: Option<f64>,
+
+    /// Slow the head down as joints turn into their soft margin, see
+    /// [`Robot::soft_margin_velocity`]
+    pub soft_margin_slow_down: bool,
+
+    /// Brakes and then detaches the servos, nothing else moves the arm while it's active
+    pub estop: EmergencyStop,
+
+    /// How [`Robot::find_limits`] searches for the end stops
+    pub limit_search: LimitSearchConfig,
+
+    /// Search for the end stops of a joint in progress, nothing else moves the arm meanwhile
+    pub limit_finder: Option<LimitFinder>,
+
+    /// Ranges the joints are kept in, found by [`Robot::find_limits`] or configured
+    pub joint_limits: JointLimits,
+
+    /// The pose bank and waypoint queue, see [`Robot::run_program`]
+    pub program: Program,
+
+    /// Progress through [`Robot::program`] while it runs, drives [`Robot::target_position`]
+    pub program_run: Option<ProgramRun>,
+
+    /// The claw turning to a grip, see [`Robot::use_grip`]
+    pub grip: Option<GripRamp>,
+
+    /// Grips used by pressing the D-pad up, right, down and left, empty for none
+    pub grip_buttons: [String; 4],
+
+    /// Streams the segments of the arm every frame or every few, see [`Robot::update`]
+    pub segment_stream: Option<SegmentStream>,
+
+    /// Records what the arm does every frame for viewing later, see [`crate::viewer`]
+    pub telemetry: Option<TelemetryWriter>,
+
+    /// A joint driven to a raw pulse width, see [`Robot::override_servo`]
+    pub servo_override: Option<ServoOverride>,
+
+    /// Limits of the rig on where the head may go, applied in order after the reach of the
+    /// arm, the workspace profile and the envelope, see [`Robot::update_position`]
+    pub constraints: Vec<Box<dyn WorkspaceConstraint>>,
+
+    /// Boxes the head is kept out of that move around, like the other arm of a
+    /// [`coordinator::Coordinator`]. Applied with the keep out zones of the workspace profile
+    pub obstacles: Vec<KeepOut>,
+
+    /// What the head does at the edge of where it may go, see [`Robot::set_boundary_policy`]
+    pub boundary: BoundaryConfig,
+
+    /// Lowest the head may go whatever the workspace profile, like the table the arm stands
+    /// on, `None` for no limit. The head slides along it instead of pushing into it
+    pub min_z: Option<f64>,
+
+    /// Why the inverse kinematics failed on the last [`Robot::update_ik`], `None` once they
+    /// succeed again
+    pub ik_error: Option<KinematicsError>,
+
+    /// Slows the arm down on a low supply and shuts it down on a critical one, `None` to
+    /// ignore the supply
+    pub supply: Option<SupplyMonitor>,
+
+    /// What shutting the arm down does, see [`Robot::begin_shutdown`]
+    pub on_shutdown: ShutdownConfig,
+
+    /// Shutdown in progress or done, nothing but the shutdown moves the arm meanwhile
+    pub shutdown: Option<Shutdown>,
+
+    /// Since when the link is down, see [`ShutdownConfig::link_timeout`]
+    pub link_down_since: Option<Instant>,
+}
+
+/// What happened during a [`Robot::tick`]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct UpdateReport {
+    /// The target position was reached
+    pub target_reached: bool,
+
+    /// The replay ran out of samples
+    pub replay_finished: bool,
+
+    /// The last waypoint of the program was done
+    pub program_finished: bool,
+
+    /// No inverse kinematics solution was found, the joints kept their previous angles
+    pub ik_failed: bool,
+
+    /// A joint passed its travel warning threshold, see [`Odometer::worn`]
+    pub worn: bool,
+
+    /// A joint stopped following its commanded angle, see [`Robot::stall`]
+    pub stalled: Option<&'static str>,
+
+    /// The emergency stop finished braking, halted or ran over its budget and detached
+    pub stopped: Option<StopStage>,
+
+    /// The limit search finished with the limits of this joint, see [`Robot::joint_limits`],
+    /// or was aborted
+    pub limits: Option<Result<&'static str, LimitAbort>>,
+
+    /// The servo positions were sent, only set by [`Robot::update`]
+    pub transmitted: bool,
+
+    /// A non-finite value was caught, the robot held its last good state for this tick
+    pub fault: Option<Fault>,
+
+    /// The claw got to its grip or closed on the object, see [`Robot::use_grip`]
+    pub grip: Option<GripEnd>,
+
+    /// The shutdown escalated or got done, see [`Robot::shutdown`]
+    pub shutdown: Option<ShutdownStep>,
+}
+
+/// Where in the control pipeline a NaN or infinity was caught
+///
+/// A single bad value would otherwise spread through velocity and position into the joint
+/// angles, and a NaN cast to a pulse width is 0 which slams the servo to one end
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The time step itself
+    Delta,
+
+    /// After integrating the acceleration, usually from a bad target velocity
+    Velocity,
+
+    /// After integrating the velocity
+    Position,
+
+    /// In the pulse widths about to be sent
+    Servos,
+}
+
+/// Snapshot of the robot for displaying
+#[derive(Debug, Clone)]
+pub struct RobotState {
+    pub position: CordinateVec,
+    pub target_position: Option<CordinateVec>,
+    pub velocity: CordinateVec,
+    pub target_velocity: CordinateVec,
+    pub firmware: FirmwareStatusView,
+    pub connection: ConnectionStats,
+    pub baud: u32,
+    pub link_quality: f64,
+    pub speed_scale: f64,
+    pub mirror: bool,
+
+    /// Commanded joint angles as the kinematics use them, see
+    /// [`crate::kinematics::operator`] for how they are shown
+    pub angles: JointAngles,
+
+    /// Joint travel this session and over all sessions in degrees
+    pub travel: JointAngles,
+    pub total_travel: JointAngles,
+
+    pub output: OutputRate,
+    pub config_hash: u64,
+
+    /// The servos are detached or still settling after attaching
+    pub detached: bool,
+
+    pub calibration: Calibration,
+    pub loop_stats: LoopStats,
+    pub faults: u64,
+    pub last_event: Option<Event>,
+
+    /// Enforced workspace profile and the one waiting for the head to move clear
+    pub workspace: Option<String>,
+    pub pending_workspace: Option<String>,
+
+    /// Below 1 while the arm is slowed down for the load on the servos
+    pub torque_scale: f64,
+
+    /// Grams the claw carries and the reach and acceleration left with them
+    pub payload: f64,
+    pub reach: f64,
+    pub acceleration_scale: f64,
+
+    pub envelope_mode: EnvelopeMode,
+    pub envelope_voxels: usize,
+
+    pub boundary: BoundaryConfig,
+
+    /// Positions not learned because the envelope is full
+    pub envelope_overflowed: u64,
+
+    pub stalled: Option<&'static str>,
+
+    /// Joint within its soft margin, see [`Robot::soft_margin`]
+    pub soft_margin: Option<&'static str>,
+
+    pub jog_paused: bool,
+    pub emergency_stop: Option<StopStage>,
+
+    /// Joint searched for its end stops and how far along
+    pub limit_search: Option<(&'static str, SearchStage)>,
+
+    pub servo_override: Option<ServoOverride>,
+
+    /// Level of the supply and the latest voltage reported in millivolts, `None` until
+    /// reported or when it's not monitored
+    pub supply: Option<(SupplyLevel, u16)>,
+
+    pub shutdown: Option<Shutdown>,
+
+    /// Waypoint the program is at and how many it has
+    pub program: Option<(usize, usize)>,
 }
 
 impl Robot {
-    /// handles input from a gamepad axis
+    /// Act on the operator's input, see [`crate::input`] for where it comes from
     ///
-    /// Returns 0 if the axis is within the deadzone
-    /// the output value is scaled to be between -1 and 1
-    /// when the axis is on the edge of the deadzone the output should not be deadzone + x but 0 + x
-    pub fn parse_gamepad_axis(&mut self, input: f64, deadzone: f64) -> f64 {
-        if input.abs() < deadzone {
-            return 0.;
+    /// The velocity is only commanded when the jog changes, so letting go of the sticks stops
+    /// the arm but leaving them alone doesn't cancel a target position. What a jog does to a
+    /// target position is up to [`Robot::jog_override`]. Everything but the emergency stop is
+    /// ignored while it's active
+    pub fn apply_input(&mut self, input: &InputState) {
+        if input.pressed.emergency_stop {
+            self.emergency_stop();
+        }
+
+        let jog = if input.connected {
+            input.jog
+        } else {
+            CordinateVec::default()
+        };
+        // the jog held through the stop doesn't move the arm once it's released
+        if self.estop.active() {
+            self.jog = jog;
+            return;
+        }
+        if jog != self.jog {
+            self.jog = jog;
+            self.apply_jog(jog);
         }
 
-        input.signum() * {
-            let input = input.abs() - deadzone;
-            input / (1. - deadzone)
+        if input.pressed.mirror {
+            self.mirror = !self.mirror;
+        }
+        if input.pressed.next_workspace {
+            if let Some(next) = self.workspaces.next().map(str::to_string) {
+                // the name comes from the profiles so it can't be unknown
+                let _ = self.select_workspace(Some(&next));
+            }
+        }
+        if input.pressed.status {
+            self.status.request();
+        }
+        for (i, pressed) in input.pressed.grips.into_iter().enumerate() {
+            if pressed && !self.grip_buttons[i].is_empty() {
+                let name = self.grip_buttons[i].clone();
+                if let Err(err) = self.use_grip(&name) {
+                    warn(&format!("D-pad grip: {err}"));
+                }
+            }
         }
     }
 
-    /// Handles input, updating the relevant values. Change this function to add controller
-    /// functionality
-    pub fn update_gamepad(&mut self, gamepad: &Gamepad) {
-        let right_axis_y = gamepad.value(Axis::RightStickY) as f64;
-        let left_axis_x = gamepad.value(Axis::LeftStickX) as f64;
-        let left_axis_y = gamepad.value(Axis::LeftStickY) as f64;
+    /// Convert between the operator's frame and the robot's frame, see [`Robot::mirror`]
+    ///
+    /// Mirroring is its own inverse so this works in both directions
+    pub fn operator_frame(&self, mut position: CordinateVec) -> CordinateVec {
+        if self.mirror {
+            position.x = -position.x;
+        }
+
+        position
+    }
 
+    /// Move with a velocity given in the operator's frame, cancels any target position and
+    /// stops the program
+    pub fn command_velocity(&mut self, velocity: CordinateVec) {
+        self.stop_program();
         self.target_position = None;
+        self.jog_paused = false;
+        self.target_velocity = self.operator_frame(velocity);
+    }
 
-        self.target_velocity = self.max_velocity
-            * CordinateVec {
-                x: self.parse_gamepad_axis(left_axis_x, 0.2),
-                y: self.parse_gamepad_axis(left_axis_y, 0.2),
-                z: self.parse_gamepad_axis(right_axis_y, 0.2),
-            };
+    /// Move to a position given in the operator's frame, in real world coordinates once
+    /// calibrated, see [`Robot::calibration`]
+    ///
+    /// A target below [`Robot::min_z`] is raised onto it
+    ///
+    /// # Returns
+    /// `false` if the head can't be there, see [`Robot::is_reachable`], the target is left as
+    /// it was
+    pub fn command_target(&mut self, target: CordinateVec) -> bool {
+        let mut internal = self.calibration.to_internal(self.operator_frame(target));
+        if let Some(z) = self.min_z.filter(|&z| internal.z < z) {
+            warn(&format!("{target:?} is below the lowest the head may go, going to {z} instead"));
+            internal.z = z;
+        }
+        if !self.is_reachable(internal) {
+            warn(&format!(
+                "Not moving to {target:?}, the head can't be there, closest is {:?}",
+                self.nearest_reachable(internal)
+            ));
+            return false;
+        }
 
-        if gamepad.is_pressed(Button::Start) {
-            panic!("Start button pressed, there is only death now");
+        self.target_position = Some(internal);
+        true
+    }
+
+    /// Move to a new position like [`Robot::command_target`] from wherever the head is and
+    /// however fast it's going, dropping a replay that would take the target over
+    ///
+    /// A move in progress isn't planned again from rest. The part of the velocity towards the
+    /// new target carries on while the part across the way there is braked away within the
+    /// acceleration, then the head heads for the target as usual, see
+    /// [`Robot::target_position_update`]
+    pub fn retarget(&mut self, target: CordinateVec) -> bool {
+        if !self.command_target(target) {
+            return false;
         }
+
+        self.replay = None;
+        self.joint_replay = None;
+        self.retargeting = self.velocity != CordinateVec::default();
+        true
     }
 
     /// Set target velocity if a target position is set
     ///
-    /// Accelerate towards the target position until within the distance required to stop
+    /// Accelerate towards the target position until within the distance required to stop,
+    /// then brake so the head comes to rest on it. While [`Robot::retargeting`] the velocity
+    /// across the way to the target is braked away first, keeping what goes towards it
     ///
     /// If the target position is reached, set target position to None
-    pub fn target_position_update(&mut self, target: CordinateVec) {
-        let delta = target - self.position;
-        let mut sphere = delta.to_sphere();
-        let acceleration = CordinateVec::new(self.acceleration, self.acceleration, self.acceleration);
+    ///
+    /// # Arguments
+    /// * `target` - position to move to
+    /// * `delta` - seconds until the next update
+    pub fn target_position_update(&mut self, target: CordinateVec, delta: f64) {
+        let distance = self.position.distance_to(&target);
+        let acceleration = self.acceleration * self.acceleration_scale();
         let velocity = self.velocity.dst();
 
-        // distance needed to stop at current velocity
-        let breaking_distance = dbg!(velocity.powi(2) / (2. * acceleration.dst()));
-
-        // conntineously accelerate until we reach the breaking point
-        if sphere.distance < breaking_distance {
-            // breake
+        if velocity < RETARGET_TOLERANCE && distance < ARRIVED_DISTANCE {
+            // we have reached the target
+            self.position = target;
+            self.velocity = CordinateVec::new(0., 0., 0.);
             self.target_velocity = CordinateVec::new(0., 0., 0.);
+            self.target_position = None;
+            self.retargeting = false;
+            return;
+        }
+
+        // unit vector towards the target, none once on it
+        let direction = self.position.direction_to(&target).unwrap_or_default();
 
-            if sphere.distance < 0.04 && velocity < 0.07 {
-                // we have reached the target
-                self.position = target;
-                self.velocity = CordinateVec::new(0., 0., 0.);
-                self.target_velocity = CordinateVec::new(0., 0., 0.);
-                self.target_position = None;
+        // fastest the head can go and still stop at the target, braking at the acceleration
+        // every axis gets, and no further than the target in one update
+        let speed = (2. * acceleration * distance)
+            .sqrt()
+            .min(distance / delta)
+            .min(self.cruise_speed.unwrap_or(10000.));
+
+        if self.retargeting {
+            let along = self.velocity.dot(&direction);
+            let across = self.velocity - along * direction;
+            if across.dst() > RETARGET_TOLERANCE {
+                self.target_velocity = along.clamp(0., speed) * direction;
+                return;
             }
-        } else {
-            // accelerate
-            sphere.update_dst(10000.);
-            self.target_velocity = sphere.to_position();
+            self.retargeting = false;
         }
+
+        self.target_velocity = speed * direction;
     }
 
     /// Update velocity based on acceleration and target velocity
+    ///
+    /// The target velocity is scaled down by the [`LinkPolicy`] when the link is unreliable,
+    /// and both the target velocity and the acceleration by [`Robot::torque_scale`]. The
+    /// acceleration is also scaled down for the [`Robot::payload`] and while the
+    /// [`Robot::supply`] is low. Near the boundary it's slowed down as the
+    /// [`Robot::boundary`] policy says, and near the joint limits by their soft margins
     pub fn update_velocity(&mut self, delta: f64) {
         // actual acceleration for this update step
-        let acceleration = self.acceleration * delta;
+        let acceleration = self.acceleration * self.acceleration_scale() * delta;
+
+        let mut target_velocity = self.target_velocity * self.link.scale() * self.torque_scale;
+        if let Some(workspace) = self.workspaces.current() {
+            target_velocity = workspace.cap_speed(target_velocity);
+        }
+        let target_velocity = self.boundary_velocity(target_velocity);
+        let target_velocity = self.soft_margin_velocity(target_velocity);
 
         // the changle in velocity we need
-        let mut delta_velocity = self.target_velocity - self.velocity;
+        let mut delta_velocity = target_velocity - self.velocity;
 
-        // limit change to maximum acceleration
-        delta_velocity.cube_clamp(-acceleration, acceleration);
+        // limit change to maximum acceleration on every axis
+        let acceleration = CordinateVec::new(acceleration, acceleration, acceleration);
+        delta_velocity.clamp_vec(-acceleration, acceleration);
 
         // update position and velocity
         self.velocity += delta_velocity;
     }
 
-    /// Use current velocity to update position
-    pub fn update_position(&mut self, delta: f64) {
-        self.position += self.velocity * delta;
+    /// What [`Robot::acceleration`] is scaled by for the load on the servos, the payload and
+    /// the supply
+    fn acceleration_scale(&self) -> f64 {
+        self.torque_scale * self.derating.acceleration * self.supply_scale()
+    }
+
+    /// Move the head for `delta` seconds while the velocity went from `start` to the current
+    /// one, see [`integrate`], then back within the constraints, see [`Robot::clamp`]
+    pub fn update_position(&mut self, start: CordinateVec, delta: f64) {
+        self.position = integrate(self.position, start, self.velocity, delta);
 
-        // limit position to not be outside of the range of motion
-        let mut sphere = self.position.to_sphere();
+        self.position = self.clamp(self.position);
 
-        // clamp distance from origin
-        if sphere.distance >= self.upper_arm + self.lower_arm {
-            sphere.update_dst(self.upper_arm + self.lower_arm);
-            self.position = sphere.to_position();
+        // slide along the floor instead of pushing into it
+        if self.min_z.is_some_and(|z| self.position.z <= z) && self.velocity.z < 0. {
+            self.velocity.z = 0.;
+        }
+
+        if self.envelope_mode == EnvelopeMode::Enforce && !self.envelope_entered {
+            self.envelope_entered = self.envelope.contains(self.position);
         }
     }
 
-    pub fn update_ik(&mut self) {
-        let angles = self
-            .position
-            .inverse_kinematics(self.upper_arm, self.lower_arm);
+    /// Set the joint angles for the current position
+    ///
+    /// # Returns
+    /// `false` if there is no solution, the joints keep their previous angles and
+    /// [`Robot::ik_error`] says why
+    pub fn update_ik(&mut self) -> bool {
+        let angles = match self.solve(self.position) {
+            Ok(angles) => angles,
+            Err(err) => {
+                self.ik_error = Some(err);
+                return false;
+            }
+        };
+        self.ik_error = None;
+
+        let held = self.arm.angles();
+        let mut hits = [
+            ("base", self.arm.base.set_angle(angles.0)),
+            ("shoulder", self.arm.shoulder.set_angle(angles.1)),
+            ("elbow", self.arm.elbow.set_angle(angles.2)),
+            ("wrist", Ok(())),
+        ];
+        if let Ok(Some(angle)) = self.wrist_for(angles) {
+            if let Some(wrist) = &mut self.arm.wrist {
+                hits[3].1 = wrist.set_angle(angle);
+            }
+        }
+        for (joint, hit) in hits {
+            if let Err(hit) = hit {
+                warn(&format!("The {joint} was asked to turn {hit}"));
+            }
+        }
+        self.warn_soft_margin();
 
-        match angles {
-            Ok(angles) => {
-                self.arm.base.angle = angles.0;
-                self.arm.shoulder.angle = angles.1;
-                self.arm.elbow.angle = angles.2;
+        // a joint under manual override doesn't follow the head
+        if let Some(manual) = self.servo_override {
+            if let (Some(joint), Some(angle)) =
+                (self.arm.joint_mut(manual.joint), held.get(manual.joint))
+            {
+                joint.angle = angle;
             }
+        }
+        true
+    }
 
-            Err(()) => warn("Could not calculate inverse kinematics"),
+    /// Slow the joints down to their [`Joint::max_velocity_dps`] after [`Robot::update_ik`]
+    ///
+    /// Near full reach a short move of the head swings the shoulder a long way, faster than
+    /// its servo turns. The change since `from` is scaled down for every joint alike, see
+    /// [`arm::Arm::limit_velocity`], and the head is put where they hold it so the move goes
+    /// on from there
+    ///
+    /// # Returns
+    /// True if a joint was too fast
+    pub fn apply_joint_limits(
+        &mut self,
+        from: JointAngles,
+        from_wrist: Option<f64>,
+        delta: f64,
+    ) -> bool {
+        if self.arm.limit_velocity(from, from_wrist, delta) <= 1. {
+            return false;
         }
+
+        self.position = self.head_at(self.arm.forward_kinematics(self.upper_arm, self.lower_arm));
+        true
     }
 
-    /// Runs all of the necessary function in order to update controller and move the robot
-    pub fn update(&mut self, delta: f64) -> Result<(), ComError> {
-        match self.target_position {
-            Some(target) => self.target_position_update(target),
-            None => {}
+    /// `velocity` slowed down for the joints it turns into their soft margin, if
+    /// [`Robot::soft_margin_slow_down`]
+    ///
+    /// The velocity is split into what each of the base, shoulder and elbow turning gives,
+    /// see [`arm::Arm::joint_velocities`], and each part is scaled by
+    /// [`Joint::soft_margin_scale`]. Near a singular pose it's left as it is
+    pub fn soft_margin_velocity(&self, velocity: CordinateVec) -> CordinateVec {
+        if !self.soft_margin_slow_down {
+            return velocity;
         }
 
-        self.update_velocity(delta);
-        self.update_position(delta);
-        self.update_ik();
+        let (upper, lower) = (self.upper_arm, self.lower_arm);
+        let (base, shoulder, elbow) = self.arm.joint_velocities(velocity, upper, lower);
+        let rates = [base, shoulder, elbow];
+        if !rates.iter().all(|rate| rate.is_finite()) {
+            return velocity;
+        }
 
-        let data = self.arm.to_servos().to_message();
-        self.connection.write(&data, true)
+        let joints = [&self.arm.base, &self.arm.shoulder, &self.arm.elbow];
+        let columns = self.arm.jacobian(upper, lower);
+        let mut scaled = CordinateVec::default();
+        for ((joint, rate), column) in joints.into_iter().zip(rates).zip(columns) {
+            scaled += column * (rate * joint.soft_margin_scale(rate));
+        }
+        scaled
     }
-}
 
-// microseconds for arduino
-const MAX_SERVO: u16 = 2400;
-const MIN_SERVO: u16 = 250;
-/// quirky arm
-#[derive(Debug, Copy, Clone)]
-pub struct Servos {
-    pub base: u16,
-    pub shoulder: u16,
-    pub elbow: u16,
-    pub claw: u16,
-}
+    /// Warn about the joint within its soft margin when it gets there, and again every
+    /// [`SOFT_MARGIN_WARN_INTERVAL`] while it stays
+    fn warn_soft_margin(&mut self) {
+        let margin = self.arm.in_soft_margin();
+        if let Some(name) = margin {
+            let now = session::clock().now();
+            let due = margin != self.soft_margin
+                || self
+                    .soft_margin_warned
+                    .is_none_or(|warned| now - warned >= SOFT_MARGIN_WARN_INTERVAL);
+            if let Some(joint) = self.arm.joint(name).filter(|_| due) {
+                warn(&format!(
+                    "The {name} is at {:.1}°, within {:.1}° of its limit",
+                    joint.angle, joint.soft_margin_deg
+                ));
+                self.soft_margin_warned = Some(now);
+            }
+        }
+        self.soft_margin = margin;
+    }
+
+    /// Joint angles for the head at `position`, with every joint in its range and the range
+    /// found for it in [`Robot::joint_limits`]
+    ///
+    /// Whichever way the elbow bends the joints travel the least to from where they are, so the
+    /// arm doesn't flip over mid move
+    ///
+    /// A continuous base gets the angle closest to where it is, so crossing behind it doesn't
+    /// unwind the whole way round, and is never out of range
+    ///
+    /// With a [`arm::Arm::wrist`] the head is the tip of the claw, the angles put the wrist
+    /// where it holds the claw at [`Robot::claw_pitch`], see [`Robot::wrist_for`]. The head is
+    /// [`Robot::end_effector_offset`] further on from there
+    fn solve(&self, position: CordinateVec) -> Result<(f64, f64, f64), KinematicsError> {
+        let position = self.ik_point(position)?;
+        let range = |joint: &Joint, found: Option<JointRange>| {
+            if joint.continuous {
+                return f64::NEG_INFINITY..=f64::INFINITY;
+            }
+            match found {
+                Some(found) => joint.min.max(found.min)..=joint.max.min(found.max),
+                None => joint.min..=joint.max,
+            }
+        };
+        let limits = IkLimits {
+            base: range(&self.arm.base, self.joint_limits.base),
+            shoulder: range(&self.arm.shoulder, self.joint_limits.shoulder),
+            elbow: range(&self.arm.elbow, self.joint_limits.elbow),
+        };
+        let mut solutions =
+            position.inverse_kinematics_limited(self.upper_arm, self.lower_arm, &limits)?;
+        if self.arm.base.continuous {
+            let base = self
+                .arm
+                .base
+                .nearest(position.y.atan2(position.x).to_degrees() + 90.);
+            solutions.primary.0 = base;
+            if let Some(mirrored) = &mut solutions.mirrored {
+                mirrored.0 = base;
+            }
+        }
 
+        let arm = &self.arm;
+        let angles = solutions.closest_to((arm.base.angle, arm.shoulder.angle, arm.elbow.angle));
+        self.wrist_for(angles)?;
+        Ok(angles)
+    }
 
-/// convert servo position represented as an angle into values understod by the servo
-impl Joint {
-    fn into_servo(&self) -> u16 {
-        let factor = (self.motion.get_pivot_angle(self.angle) - self.min) / self.max;
-        ((MAX_SERVO - MIN_SERVO) as f64 * factor + self.min as f64) as u16
+    /// Where the end of the lower arm has to be for the head to be at `position`, the wrist
+    /// for an arm with one, and without the [`Robot::end_effector_offset`]
+    fn ik_point(&self, position: CordinateVec) -> Result<CordinateVec, KinematicsError> {
+        let mount = position
+            .tool_mount(self.end_effector_offset)
+            .ok_or(KinematicsError::ToolOffset)?;
+        Ok(match self.arm.wrist {
+            Some(_) => mount.wrist_point(self.wrist_length, self.claw_pitch),
+            None => mount,
+        })
     }
-}
 
-impl PartialEq for Joint {
-    fn eq(&self, other: &Self) -> bool {
-        let left = (self.angle * 10.0f64.powi(4)).round() / 10.0f64.powi(4);
-        let right = (other.angle * 10.0f64.powi(4)).round() / 10.0f64.powi(4);
-        left == right
+    /// Where the head is with the end of the lower arm at `point`, the reverse of
+    /// [`Robot::ik_point`]
+    fn head_at(&self, point: CordinateVec) -> CordinateVec {
+        let mount = match self.arm.wrist {
+            Some(_) => point.claw_tip(self.wrist_length, self.claw_pitch),
+            None => point,
+        };
+        mount.tool_tip(self.end_effector_offset)
     }
-}
 
+    /// Angle of the [`arm::Arm::wrist`] that holds the claw at [`Robot::claw_pitch`] with the
+    /// shoulder and elbow of `angles`, `None` without a wrist
+    fn wrist_for(&self, angles: (f64, f64, f64)) -> Result<Option<f64>, KinematicsError> {
+        let Some(wrist) = &self.arm.wrist else {
+            return Ok(None);
+        };
+        let angle = wrist_angle(angles.1, angles.2, self.claw_pitch);
+        if wrist.continuous || (wrist.min..=wrist.max).contains(&angle) {
+            Ok(Some(angle))
+        } else {
+            Err(KinematicsError::JointLimit(JointName::Wrist))
+        }
+    }
 
-impl Servos {
-    pub fn to_message(&self) -> Vec<u8> {
-        unsafe { std::mem::transmute::<Box<Servos>, &[u8; 8]>(Box::new(*self)) }.to_vec()
+    /// True if the head can be at the position at all: no further out than the
+    /// [`Robot::reach`], not so close to the shoulder that the arm can't fold up that far and
+    /// not below the [`Robot::floor`]
+    ///
+    /// Only the geometry of the arm is checked, see [`Robot::in_reach`] for the joint limits
+    pub fn is_reachable(&self, position: CordinateVec) -> bool {
+        let distance = position.dst();
+        (self.min_reach()..=self.reach()).contains(&distance)
+            && self.floor().is_none_or(|floor| position.z >= floor)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::arm::Arm;
-    use super::*;
+    /// Lowest the head may go, the higher of [`Robot::min_z`] and the floor of the workspace
+    /// profile
+    pub fn floor(&self) -> Option<f64> {
+        let profile = self.workspaces.current().and_then(|workspace| workspace.floor);
+        match (self.min_z, profile) {
+            (Some(min_z), Some(profile)) => Some(min_z.max(profile)),
+            (min_z, profile) => min_z.or(profile),
+        }
+    }
 
-    #[test]
-    pub fn servos_to_message() {
-        let servos = Servos {
-            base: 100,
-            shoulder: 200,
-            elbow: 50,
-            claw: 1,
+    /// Closest position to `position` that [`Robot::is_reachable`]
+    ///
+    /// A position right on the shoulder has no direction to go out in, it goes out along y
+    pub fn nearest_reachable(&self, position: CordinateVec) -> CordinateVec {
+        let floor = self.floor().map(|z| Floor { z });
+        let constraints: [Option<&dyn WorkspaceConstraint>; 3] = [
+            Some(&Reach {
+                radius: self.reach(),
+            }),
+            Some(&MinReach {
+                radius: self.min_reach(),
+            }),
+            floor.as_ref().map(|floor| floor as &dyn WorkspaceConstraint),
+        ];
+        resolve(constraints.into_iter().flatten(), position)
+    }
+
+    /// Closest the head can come to the shoulder, with the lower arm folded back along the
+    /// upper one
+    pub fn min_reach(&self) -> f64 {
+        (self.upper_arm - self.lower_arm).abs()
+    }
+
+    /// True if the head can be moved to the position with what the claw carries
+    pub fn in_reach(&self, position: CordinateVec) -> bool {
+        self.is_reachable(position) && self.solve(position).is_ok()
+    }
+
+    /// Start replaying a recording moved by `transform`
+    ///
+    /// The whole transformed recording is checked against the workspace first, nothing is
+    /// replayed if any sample is out of reach
+    pub fn start_replay(
+        &mut self,
+        recording: &Recording,
+        transform: &Transform,
+    ) -> Result<(), RecordingError> {
+        let recording = recording.transformed(transform);
+        recording.validate(|position| self.in_reach(position))?;
+
+        self.joint_replay = None;
+        self.stop_program();
+        self.replay = Some(Replay::new(recording));
+        Ok(())
+    }
+
+    /// Start replaying a joint space recording
+    ///
+    /// The joints follow the recorded angles without going faster than their
+    /// [`Joint::max_velocity_dps`], the replay finishes once the last pose is reached
+    pub fn start_joint_replay(
+        &mut self,
+        recording: &Recording<JointAngles>,
+    ) -> Result<(), RecordingError> {
+        if recording.samples.is_empty() {
+            return Err(RecordingError::Empty);
+        }
+
+        self.replay = None;
+        self.stop_program();
+        self.target_position = None;
+        self.claw_open = None;
+        self.joint_replay = Some(Replay::new(recording.clone()));
+        Ok(())
+    }
+
+    /// Start recording the joint angles, see [`Robot::stop_recording`]
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(Recorder::default());
+    }
+
+    /// Stop recording
+    ///
+    /// # Returns
+    /// Everything recorded since [`Robot::start_recording`], `None` if nothing was being recorded
+    pub fn stop_recording(&mut self) -> Option<Recording<JointAngles>> {
+        self.recorder.take().map(|recorder| recorder.recording)
+    }
+
+    /// Start going through the waypoints of [`Robot::program`]
+    ///
+    /// Every waypoint is checked to be in reach first, nothing moves if one isn't. The arm
+    /// moves to each waypoint, sets the claw and dwells before moving on
+    pub fn run_program(&mut self) -> Result<(), ProgramError> {
+        if self.program.waypoints.is_empty() {
+            return Err(ProgramError::Empty);
+        }
+        for step in 0..self.program.waypoints.len() {
+            let Some(position) = self.program.target(step) else {
+                return Err(ProgramError::Target(step));
+            };
+            let internal = self.calibration.to_internal(self.operator_frame(position));
+            if !self.in_reach(internal) {
+                return Err(ProgramError::Unreachable { step, position });
+            }
+        }
+
+        self.stop_program();
+        self.replay = None;
+        self.joint_replay = None;
+        self.program_run = Some(ProgramRun::new(self.cruise_speed));
+        self.start_waypoint(0);
+        Ok(())
+    }
+
+    /// Stop the program where it is, the head still moves to the waypoint it was going to
+    ///
+    /// # Returns
+    /// False if no program was running
+    pub fn stop_program(&mut self) -> bool {
+        let Some(run) = self.program_run.take() else {
+            return false;
         };
 
-        let actual = servos.to_message();
-        let expected: Vec<u8> = vec![100, 0, 200, 0, 50, 0, 1, 0];
+        self.cruise_speed = run.cruise_speed;
+        true
+    }
 
-        assert_eq!(actual, expected);
+    /// Add the head position to the pose bank, in the operator's frame like
+    /// [`Robot::command_target`]
+    pub fn save_pose(&mut self, name: &str) {
+        let position = self.operator_frame(self.calibration.to_world(self.position));
+        let before = self.program.poses.insert(name.to_string(), position);
+        self.journal_change(Change::Pose {
+            name: name.to_string(),
+            before,
+            after: Some(position),
+        });
     }
 
-    #[test]
-    pub fn parse_gamepad() {
+    /// Replace the calibration, recorded in the journal
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        let before = self.calibration;
+        self.calibration = calibration;
+        self.journal_change(Change::Calibration {
+            before,
+            after: calibration,
+        });
+    }
+
+    /// Move to waypoint `step` of the running program
+    fn start_waypoint(&mut self, step: usize) {
+        let Some(run) = &mut self.program_run else {
+            return;
+        };
+        run.step = step;
+        run.dwelling = None;
+
+        let speed = self.program.waypoints[step].speed;
+        self.cruise_speed = speed.or(run.cruise_speed);
+        if let Some(target) = self.program.target(step) {
+            self.retarget(target);
+        }
+    }
+
+    /// Give up on the waypoint the running program is going to and head for the next one from
+    /// wherever the head is, see [`Robot::retarget`]
+    ///
+    /// Skipping the last waypoint ends the program and brakes the head to a stop
+    ///
+    /// # Returns
+    /// False if no program was running
+    pub fn skip_waypoint(&mut self) -> bool {
+        let Some(run) = &self.program_run else {
+            return false;
+        };
+
+        let next = run.step + 1;
+        if next < self.program.waypoints.len() {
+            self.start_waypoint(next);
+        } else {
+            self.stop_program();
+            self.target_position = None;
+            self.retargeting = false;
+            self.target_velocity = CordinateVec::default();
+        }
+        true
+    }
+
+    /// Set the claw and dwell once a waypoint is reached, then move on to the next
+    ///
+    /// # Returns
+    /// True once the last waypoint is done
+    fn program_update(&mut self, delta: f64) -> bool {
+        let Some(run) = &mut self.program_run else {
+            return false;
+        };
+
+        let waypoint = &self.program.waypoints[run.step];
+        match &mut run.dwelling {
+            None if self.target_position.is_some() => return false,
+            None => {
+                if let Some(claw) = waypoint.claw {
+                    self.arm.claw.angle = claw.angle(&self.arm.claw);
+                    self.claw_open = None;
+                }
+                run.dwelling = Some(waypoint.dwell);
+            }
+            Some(left) => *left -= delta,
+        }
+        if run.dwelling.is_some_and(|left| left > 0.) {
+            return false;
+        }
+
+        let next = run.step + 1;
+        if next < self.program.waypoints.len() {
+            self.start_waypoint(next);
+            return false;
+        }
+        self.stop_program();
+        true
+    }
+
+    /// Follow the joint space replay
+    ///
+    /// # Returns
+    /// True once the replay is finished
+    fn joint_replay_update(&mut self, delta: f64) -> bool {
+        let Some(replay) = &mut self.joint_replay else {
+            return false;
+        };
+
+        let target = replay.advance(delta);
+        let past_end = target.is_none();
+        let Some(target) = target.or(replay.recording.last()) else {
+            return true;
+        };
+
+        self.arm.step_towards(target, delta);
+        self.follow_joints();
+
+        past_end && self.arm.angles() == target
+    }
+
+    /// Set the position to where the joints put the head after moving them directly, so
+    /// control continues smoothly from there
+    fn follow_joints(&mut self) {
+        self.position = self.head_at(self.arm.forward_kinematics(self.upper_arm, self.lower_arm));
+        self.velocity = CordinateVec::default();
+    }
+
+    /// Start searching for the end stops of a joint, see [`LimitFinder`]
+    ///
+    /// The joint only moves once confirmed with [`Robot::confirm_limit_search`], and only
+    /// while the arm is otherwise at rest
+    pub fn find_limits(&mut self, joint: &str) -> Result<(), LimitError> {
+        let Some(name) = arm::JOINTS.into_iter().find(|name| *name == joint) else {
+            return Err(LimitError::UnknownJoint(joint.to_string()));
+        };
+
+        let busy = self.target_velocity != CordinateVec::default()
+            || self.velocity != CordinateVec::default()
+            || self.target_position.is_some()
+            || self.replay.is_some()
+            || self.joint_replay.is_some()
+            || self.program_run.is_some()
+            || self.soft_start.is_some()
+            || self.idle.holding()
+            || self.stalled.is_some()
+            || self.estop.active()
+            || self.limit_finder.is_some();
+        if busy {
+            return Err(LimitError::Busy);
+        }
+
+        let range = self
+            .arm
+            .joint(name)
+            .map_or(JointRange { min: 0., max: 0. }, |joint| JointRange {
+                min: joint.min,
+                max: joint.max,
+            });
+        self.limit_finder = Some(LimitFinder::new(self.limit_search, name, range));
+        Ok(())
+    }
+
+    /// Let the joint waiting in [`Robot::find_limits`] move, it has to be named again
+    pub fn confirm_limit_search(&mut self, joint: &str) -> Result<(), LimitError> {
+        match &mut self.limit_finder {
+            Some(finder) if finder.joint == joint && finder.stage == SearchStage::Confirm => {
+                finder.confirm();
+                Ok(())
+            }
+            _ => Err(LimitError::NotWaiting(joint.to_string())),
+        }
+    }
+
+    /// Stop searching for end stops
+    ///
+    /// # Returns
+    /// False if there was no search
+    pub fn abort_limit_search(&mut self) -> bool {
+        let Some(mut finder) = self.limit_finder.take() else {
+            return false;
+        };
+
+        finder.abort(&mut self.arm, LimitAbort::Interrupted);
+        self.follow_joints();
+        true
+    }
+
+    /// Move the joint searched for its end stops, aborting on anything unexpected
+    ///
+    /// # Returns
+    /// The joint once its limits are found, or why the search was aborted
+    fn limit_search_update(&mut self, delta: f64) -> Option<Result<&'static str, LimitAbort>> {
+        let finder = self.limit_finder.as_mut()?;
+
+        let interrupted = self.target_velocity != CordinateVec::default()
+            || self.target_position.is_some()
+            || self.replay.is_some()
+            || self.joint_replay.is_some();
+        let abort = if self.link.scale() <= 0. {
+            Some(LimitAbort::LinkDown)
+        } else if let Some(joint) = self.stalled {
+            Some(LimitAbort::Stalled(joint))
+        } else if interrupted {
+            Some(LimitAbort::Interrupted)
+        } else {
+            None
+        };
+        let outcome = match abort {
+            Some(abort) => Some(Err(finder.abort(&mut self.arm, abort))),
+            None => finder.update(&mut self.arm, delta),
+        };
+        let joint = finder.joint;
+        self.follow_joints();
+
+        let outcome = outcome?;
+        self.limit_finder = None;
+        match outcome {
+            Ok(range) => {
+                let before = self.joint_limits.get(joint);
+                self.joint_limits.set(joint, Some(range));
+                self.journal_change(Change::Limit {
+                    joint: joint.to_string(),
+                    before,
+                    after: Some(range),
+                });
+                Some(Ok(joint))
+            }
+            Err(abort) => Some(Err(abort)),
+        }
+    }
+
+    /// Handles a feedback frame reported by the arduino
+    pub fn process_feedback(&mut self, feedback: Feedback) {
+        if feedback.stalled() {
+            warn("Arduino reports a stalled servo");
+        }
+        let angles = self.arm.angles_from_servos(feedback.pulses);
+        if let Some(stall) = &mut self.stall {
+            stall.report(angles);
+        }
+        if let Some(correction) = &mut self.correction {
+            correction.report(angles);
+        }
+        if let Some(finder) = &mut self.limit_finder {
+            finder.report(angles);
+        }
+        self.supply_report(feedback.millivolts);
+
+        self.feedback = Some(feedback);
+    }
+
+    /// Hand a received frame to whatever handles its type
+    ///
+    /// # Returns
+    /// The frame if the robot doesn't handle it itself
+    pub fn dispatch(&mut self, frame: Frame, now: Instant) -> Option<Frame> {
+        match frame {
+            Frame::Feedback(feedback) => {
+                self.last_heard = Some(now);
+                self.process_feedback(feedback);
+            }
+            Frame::Status(status) => {
+                self.supply_report(status.millivolts);
+                self.status.received(status, now);
+            }
+            Frame::Ack { seq } => {
+                self.last_heard = Some(now);
+                self.connection.acks.received(seq, now);
+            }
+            frame => return Some(frame),
+        }
+
+        None
+    }
+
+    /// Read and handle up to `max` frames, anything after that is left for the next call
+    ///
+    /// Frames the robot doesn't handle itself are dropped, they still show up in the
+    /// connection stats
+    ///
+    /// # Returns
+    /// The number of frames read
+    pub fn poll(&mut self, max: usize) -> Result<usize, ComError> {
+        let now = Instant::now();
+
+        for read in 0..max {
+            let Some(frame) = self.connection.read()? else {
+                return Ok(read);
+            };
+
+            self.dispatch(frame, now);
+        }
+
+        Ok(max)
+    }
+
+    /// Send a status query if one is due, see [`StatusPoller`]
+    pub fn query_status(&mut self, now: Instant) -> Result<(), ComError> {
+        if !self.status.due(now) {
+            return Ok(());
+        }
+
+        self.connection.send(&Frame::StatusRequest)?;
+        self.status.queried(now);
+        Ok(())
+    }
+
+    /// Snapshot of the current state
+    pub fn state(&self) -> RobotState {
+        RobotState {
+            position: self.position,
+            target_position: self.target_position,
+            velocity: self.velocity,
+            target_velocity: self.target_velocity,
+            firmware: self.status.view(Instant::now()),
+            connection: self.connection.stats,
+            baud: self.connection.baud,
+            link_quality: self.link.quality,
+            speed_scale: self.link.scale(),
+            mirror: self.mirror,
+            angles: self.arm.angles(),
+            travel: self.odometer.session,
+            total_travel: self.odometer.total(),
+            output: self.output,
+            config_hash: self.config_hash,
+            detached: self.idle.holding(),
+            calibration: self.calibration,
+            loop_stats: self.loop_stats,
+            faults: self.faults,
+            last_event: self.events.last,
+            workspace: self.workspaces.active.clone(),
+            pending_workspace: self.workspaces.pending.clone(),
+            torque_scale: self.torque_scale,
+            payload: self.payload,
+            reach: self.reach(),
+            acceleration_scale: self.derating.acceleration,
+            envelope_mode: self.envelope_mode,
+            envelope_voxels: self.envelope.len(),
+            boundary: self.boundary,
+            envelope_overflowed: self.envelope.overflowed,
+            stalled: self.stalled,
+            soft_margin: self.soft_margin,
+            jog_paused: self.jog_paused,
+            emergency_stop: self.estop.stage,
+            limit_search: self.limit_finder.map(|finder| (finder.joint, finder.stage)),
+            servo_override: self.servo_override,
+            supply: self
+                .supply
+                .as_ref()
+                .and_then(|supply| Some((supply.level, supply.millivolts?))),
+            shutdown: self.shutdown,
+            program: self
+                .program_run
+                .map(|run| (run.step, self.program.waypoints.len())),
+        }
+    }
+
+    /// Switch to another workspace profile, `None` to drop all limits
+    ///
+    /// If the head violates the new profile it first moves to the closest point that complies,
+    /// the old profile is enforced until it gets there
+    pub fn select_workspace(&mut self, name: Option<&str>) -> Result<(), WorkspaceError> {
+        if let Some(clear) = self.workspaces.select(name, self.position)? {
+            self.target_position = Some(clear);
+        }
+
+        Ok(())
+    }
+
+    /// Start learning, enforcing or ignoring the [`Robot::envelope`]
+    ///
+    /// If the head is outside when enforcing starts it moves to the nearest learned voxel
+    /// first, nothing keeps it in until it gets there
+    pub fn set_envelope_mode(&mut self, mode: EnvelopeMode) -> Result<(), EnvelopeError> {
+        if mode == EnvelopeMode::Enforce && self.envelope.is_empty() {
+            return Err(EnvelopeError::Empty);
+        }
+
+        self.envelope_mode = mode;
+        self.envelope_entered = self.envelope.contains(self.position);
+        if mode == EnvelopeMode::Enforce && !self.envelope_entered {
+            self.target_position = self.envelope.nearest(self.position);
+        }
+
+        Ok(())
+    }
+
+    /// Start braking, or detach the servos right away if already stopping, see
+    /// [`EmergencyStop`]
+    ///
+    /// Whatever the arm was doing is dropped, it doesn't continue after the release
+    pub fn emergency_stop(&mut self) {
+        if let Some(mut finder) = self.limit_finder.take() {
+            finder.abort(&mut self.arm, LimitAbort::EmergencyStop);
+            warn(&format!(
+                "Limit search of the {} aborted by the emergency stop",
+                finder.joint
+            ));
+        }
+        self.estop.press();
+        self.stop_everything();
+        self.end_servo_override();
+        self.hard_stop_update();
+    }
+
+    /// Let the arm move again after an emergency stop
+    ///
+    /// Detached servos stay detached until there is input, then attach and ramp back like
+    /// after resting, see [`Robot::idle`]
+    ///
+    /// # Returns
+    /// False if the arm is still braking
+    pub fn release_emergency_stop(&mut self) -> bool {
+        self.estop.release()
+    }
+
+    /// Drop every target, replay and ramp
+    fn stop_everything(&mut self) {
+        self.target_position = None;
+        self.retargeting = false;
+        self.target_velocity = CordinateVec::default();
+        self.replay = None;
+        self.joint_replay = None;
+        self.soft_start = None;
+        self.jog_paused = false;
+        self.stop_program();
+    }
+
+    /// Hand detached servos over to the [`Robot::idle`] policy, it attaches them again
+    fn hard_stop_update(&mut self) {
+        if self.estop.stage == Some(StopStage::Detached) {
+            self.idle.state = IdleState::Detached;
+            self.idle.pending = None;
+            self.velocity = CordinateVec::default();
+        }
+    }
+
+    /// Brake for the emergency stop, in the same substeps as [`Robot::step`]
+    ///
+    /// # Returns
+    /// The stage the stop ended up at if braking finished this tick
+    fn emergency_stop_update(&mut self, delta: f64) -> Option<StopStage> {
+        // commanded while stopping, dropped rather than saved up for the release
+        self.stop_everything();
+        if self.estop.stage != Some(StopStage::Braking) {
+            return None;
+        }
+
+        let (substeps, substep) = self.substeps(delta);
+        for _ in 0..substeps {
+            let start = self.velocity;
+            self.estop.brake(&mut self.velocity, substep);
+            self.update_position(start, substep);
+        }
+        self.hard_stop_update();
+        if self.estop.stage == Some(StopStage::Halted) {
+            self.update_ik();
+        }
+
+        self.estop
+            .stage
+            .filter(|stage| *stage != StopStage::Braking)
+    }
+
+    /// Split a tick into substeps no longer than [`Robot::max_substep`]
+    ///
+    /// There are never more than [`MAX_SUBSTEPS`], the physics of a longer tick only run for
+    /// that many of the longest substeps and the rest of it is dropped
+    ///
+    /// # Returns
+    /// The number of substeps and their length in seconds
+    fn substeps(&self, delta: f64) -> (u32, f64) {
+        let Some(max) = self.max_substep.filter(|max| *max > 0.) else {
+            return (1, delta);
+        };
+
+        let substeps = (delta / max).ceil().clamp(1., MAX_SUBSTEPS as f64);
+        (substeps as u32, (delta / substeps).min(max))
+    }
+
+    /// Continue the replay or move to the target position after a stall
+    pub fn resume(&mut self) {
+        self.stalled = None;
+        if let Some(stall) = &mut self.stall {
+            stall.reset();
+        }
+    }
+
+    /// Take the events that happened since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.events.take()
+    }
+
+    /// Start counting the connection and loop stats from 0 again
+    ///
+    /// The confirmed baud rate is kept since the handshake isn't redone
+    pub fn reset_stats(&mut self) {
+        self.connection.stats = ConnectionStats {
+            confirmed_baud: self.connection.stats.confirmed_baud,
+            ..Default::default()
+        };
+        // the link policy measures against the previous stats, it would underflow otherwise
+        self.link.last = self.connection.stats;
+        self.loop_stats = LoopStats::default();
+    }
+
+    /// Advance the simulation of the robot by `delta` seconds without any IO
+    ///
+    /// Applies the replay and target position, then updates velocity and position, in
+    /// substeps no longer than [`Robot::max_substep`], and then the joint angles once. A joint
+    /// space replay sets the joint angles directly instead, and nothing but the
+    /// [`SoftStart`] ramp runs until it's done. Nothing is read, written or logged and nothing
+    /// is allocated unless recording, see [`Robot::update`] for the version that talks to the
+    /// arduino
+    ///
+    /// The commanded joint angles are added to [`Robot::odometer`] and checked against the
+    /// reported ones for a stall afterwards, see [`Robot::stall`]
+    pub fn tick(&mut self, delta: f64) -> UpdateReport {
+        let mut report = self.step(delta);
+        report.worn = self.odometer.record(&self.arm);
+        let gripping = self.grip.is_some();
+        report.stalled = self.stall_update(delta);
+        if gripping && self.grip.is_none() {
+            report.grip = Some(GripEnd::Detected);
+        }
+        report
+    }
+
+    /// Check for a joint that stopped following, and stop moving if one did
+    ///
+    /// # Returns
+    /// The joint if it only just stalled
+    fn stall_update(&mut self, delta: f64) -> Option<&'static str> {
+        let stall = self.stall.as_mut()?;
+
+        // the servos don't follow the commanded angles while ramping up or detached, and lag
+        // behind while braking hard
+        if self.soft_start.is_some()
+            || self.idle.holding()
+            || self.estop.active()
+            || self.limit_finder.is_some()
+            || !delta.is_finite()
+        {
+            stall.reset();
+            return None;
+        }
+
+        let joint = stall.update(self.arm.clamped_angles(), delta)?;
+        // the claw stopping short of a grip has closed on the object
+        if joint == "claw" && self.grip.take().is_some() {
+            stall.reset();
+            return None;
+        }
+        if self.stalled.is_some() {
+            return None;
+        }
+        self.stalled = Some(joint);
+        self.target_velocity = CordinateVec::default();
+        Some(joint)
+    }
+
+    fn step(&mut self, delta: f64) -> UpdateReport {
+        let mut report = UpdateReport::default();
+
+        if !delta.is_finite() {
+            report.fault = Some(self.fault(Fault::Delta, self.position));
+            return report;
+        }
+
+        if self.estop.active() {
+            report.stopped = self.emergency_stop_update(delta);
+            return report;
+        }
+
+        if self.soft_start.is_some() {
+            self.soft_start_update(delta);
+            return report;
+        }
+
+        let (step, detached) = self.shutdown_update(delta);
+        report.shutdown = step;
+        if detached {
+            return report;
+        }
+
+        // a replay counts as busy even when it's paused, so it can pick up where it was
+        let busy = self.target_velocity != CordinateVec::default()
+            || self.velocity != CordinateVec::default()
+            || self.target_position.is_some()
+            || self.replay.is_some()
+            || self.joint_replay.is_some()
+            || self.program_run.is_some()
+            || self.limit_finder.is_some()
+            || self.grip.is_some()
+            || self.servo_override.is_some()
+            || self.shutdown.is_some();
+        let resting = self.arm.shoulder.angle.abs() <= self.idle.rest_tolerance;
+        match self.idle.update(busy, resting, delta) {
+            IdleStep::Run => {}
+            IdleStep::Hold => return report,
+            IdleStep::Resume => {
+                self.soft_start = Some(self.idle.resume);
+                self.soft_start_update(delta);
+                return report;
+            }
+        }
+
+        if self.limit_finder.is_some() {
+            report.limits = self.limit_search_update(delta);
+            return report;
+        }
+
+        // a joint space replay has nothing else to move, it just holds while paused
+        if self.stalled.is_some() && self.joint_replay.is_some() {
+            return report;
+        }
+        if self.joint_replay.is_some() {
+            if self.joint_replay_update(delta) {
+                self.joint_replay = None;
+                report.replay_finished = true;
+            }
+
+            self.record(delta);
+            return report;
+        }
+
+        // worked out once per tick from the pose the servos are holding
+        self.torque_scale = match self.torque_limit {
+            Some(limit) => limit.carrying(self.payload).scale(
+                self.arm.angles(),
+                self.upper_arm,
+                self.lower_arm,
+            ),
+            None => 1.,
+        };
+
+        // a single long step with a high acceleration changes the velocity in one jump, so the
+        // physics run in substeps and only the joints are solved once per tick
+        let (substeps, substep) = self.substeps(delta);
+
+        // the joints only ever get angles from a finite position, inverse kinematics rejects
+        // non-finite solutions itself
+        let held = self.position;
+        // paused after a stall, only what the operator commands since moves the arm
+        let paused = self.stalled.is_some();
+        // the automated moves also wait while the operator jogs over them
+        self.jog_pause_update();
+        let waiting = paused || self.jog_paused;
+        if !waiting {
+            report.program_finished = self.program_update(delta);
+        }
+        for _ in 0..substeps {
+            if let Some(replay) = self.replay.as_mut().filter(|_| !waiting) {
+                self.target_position = replay.advance(substep);
+                if self.target_position.is_none() {
+                    self.replay = None;
+                    report.replay_finished = true;
+                }
+            }
+
+            if let Some(target) = self.target_position.filter(|_| !waiting) {
+                self.target_position_update(target, substep);
+                report.target_reached |= self.target_position.is_none();
+            }
+
+            let start = self.velocity;
+            self.update_velocity(substep);
+            if !self.velocity.is_finite() {
+                report.fault = Some(self.fault(Fault::Velocity, held));
+                return report;
+            }
+            self.update_position(start, substep);
+            if !self.position.is_finite() {
+                report.fault = Some(self.fault(Fault::Position, held));
+                return report;
+            }
+        }
+        // a newly selected workspace is enforced once the head is clear of it
+        if let Some(clear) = self.workspaces.settle(self.position) {
+            if self.target_position.is_none() {
+                self.target_position = Some(clear);
+            }
+        }
+        if self.envelope_mode == EnvelopeMode::Learn {
+            self.envelope.learn(self.position);
+        }
+        let (from, from_wrist) = (self.arm.angles(), self.arm.wrist.as_ref().map(|w| w.angle));
+        report.ik_failed = !self.update_ik();
+        self.apply_joint_limits(from, from_wrist, delta);
+        if let Some(open) = self.claw_open {
+            // a preset past the claw's range holds it at the limit
+            let _ = self.arm.set_claw(self.claw_presets, open);
+        }
+        if !paused && self.grip_update(delta) {
+            report.grip = Some(GripEnd::Reached);
+        }
+        self.servo_override_update(delta);
+
+        self.record(delta);
+        report
+    }
+
+    /// Hold the last good state after a non-finite value was caught
+    ///
+    /// The head stops at `held` and targets that aren't finite are dropped so they don't
+    /// cause the same fault again on the next tick
+    fn fault(&mut self, fault: Fault, held: CordinateVec) -> Fault {
+        self.faults += 1;
+        self.position = held;
+        self.velocity = CordinateVec::default();
+
+        if !self.target_velocity.is_finite() {
+            self.target_velocity = CordinateVec::default();
+        }
+        if self.target_position.is_some_and(|target| !target.is_finite()) {
+            self.target_position = None;
+        }
+
+        fault
+    }
+
+    /// Step the soft start ramp towards the joint angles for the current position
+    fn soft_start_update(&mut self, delta: f64) {
+        let Some(soft_start) = self.soft_start else {
+            return;
+        };
+        if soft_start.waiting() {
+            self.hold_reported();
+        }
+
+        let mut to = self.arm.angles();
+        if let Ok((base, shoulder, elbow)) = self.solve(self.position) {
+            to = JointAngles {
+                base,
+                shoulder,
+                elbow,
+                ..to
+            };
+        }
+        let from = self
+            .feedback
+            .map(|feedback| self.arm.angles_from_servos(feedback.pulses));
+
+        // input is dropped rather than saved up for after the ramp
+        self.target_velocity = CordinateVec::default();
+
+        let Some(soft_start) = &mut self.soft_start else {
+            return;
+        };
+        let done = soft_start.step(&mut self.arm, from, to, delta);
+        // started without feedback, the servos are taken to be in the neutral pose
+        if !soft_start.waiting() {
+            self.startup = None;
+        }
+        if done {
+            self.soft_start = None;
+        }
+    }
+
+    fn record(&mut self, delta: f64) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(delta, self.arm.angles());
+        }
+    }
+
+    /// Send everything that is due to the arduino, the status query and the servo positions
+    ///
+    /// The servo positions are only sent when [`Robot::output`] says so and never while
+    /// [`Robot::idle`] has the servos detached. Status queries and attach or detach frames are
+    /// sent whenever they are due. The connection is owned by the robot so this writes through
+    /// [`Robot::connection`]
+    ///
+    /// # Arguments
+    /// * `delta` - seconds since the last call
+    ///
+    /// # Returns
+    /// True if the servo positions were sent
+    pub fn transmit(&mut self, delta: f64) -> Result<bool, ComError> {
+        // the hard stop goes out before anything else that could fail
+        if let Some(frame) = self.estop.pending.take() {
+            self.connection.send(&frame)?;
+        }
+        self.query_status(Instant::now())?;
+
+        if let Some(frame) = self.idle.pending.take() {
+            self.connection.send(&frame)?;
+        }
+        let mut trim = JointAngles::default();
+        if let Some(correction) = &mut self.correction {
+            // detached servos go wherever they are pushed, there is nothing to correct
+            if self.idle.holding() {
+                correction.reset();
+            } else {
+                correction.update(self.arm.clamped_angles(), delta);
+            }
+            trim = correction.trim;
+        }
+        let waiting = self
+            .soft_start
+            .is_some_and(|soft_start| soft_start.waiting());
+        if self.idle.holding() || waiting || !self.output.due(delta) {
+            return Ok(false);
+        }
+
+        let servos = match &mut self.dither {
+            Some(dither) => self
+                .arm
+                .checked_pulses(trim)
+                .zip(self.arm.checked_wrist())
+                .map(|(pulses, wrist)| Servos {
+                    wrist,
+                    ..dither.apply(pulses)
+                }),
+            None => self.arm.checked_servos(trim),
+        };
+        let Some(servos) = servos else {
+            self.faults += 1;
+            warn(&format!(
+                "Not sending servo positions, caught a {} from joint angles {:?}",
+                Fault::Servos,
+                self.arm.angles()
+            ));
+            return Ok(false);
+        };
+        let encoding = self.connection.servo_encoding;
+        self.connection
+            .write_with(|message| servos.encode_into(encoding, message))?;
+        Ok(true)
+    }
+
+    /// Runs all of the necessary function in order to update controller and move the robot
+    ///
+    /// Handles received frames, [`Robot::tick`] and [`Robot::transmit`], and queues a
+    /// [`RobotEvent`] for anything callers may be waiting for
+    pub fn update(&mut self, delta: f64) -> Result<UpdateReport, ComError> {
+        self.poll(MAX_FRAMES_PER_TICK)?;
+        let now = Instant::now();
+        let link_was_up = self.link.scale() > 0.;
+        let link_up = self.link.update(&self.connection.stats, self.last_heard, now) > 0.;
+        match (link_was_up, link_up) {
+            (true, false) => self.events.push(RobotEvent::LinkDown, now),
+            (false, true) => self.events.push(RobotEvent::LinkRestored, now),
+            _ => {}
+        }
+        self.link_watchdog(link_up, now);
+
+        let report = self.tick(delta);
+        if let Some(mut stream) = self.segment_stream.take() {
+            match stream.frame(session::clock().now(), &self.segments()) {
+                Ok(()) => self.segment_stream = Some(stream),
+                Err(err) => warn(&format!("Stopped streaming the segments: {err}")),
+            }
+        }
+        self.record_telemetry(report.ik_failed);
+        // a replay moves through its samples as targets, only the end of it counts
+        if report.target_reached && self.replay.is_none() && !report.replay_finished {
+            self.events
+                .push(RobotEvent::TargetReached(self.position), now);
+        }
+        if report.replay_finished {
+            self.events.push(RobotEvent::ReplayFinished, now);
+        }
+        if report.program_finished {
+            self.events.push(RobotEvent::ProgramFinished, now);
+        }
+        if let Some(joint) = report.stalled {
+            warn(&format!("The {joint} stopped following, pausing until resumed"));
+            self.events.push(RobotEvent::StallDetected(joint), now);
+        }
+        let saturated = self.arm.saturated();
+        if let Some(joint) = saturated.filter(|_| saturated != self.saturated) {
+            warn(&format!(
+                "The {joint} can't turn as far as it's asked to, holding its servo where it stops"
+            ));
+        }
+        self.saturated = saturated;
+        if let Some(stage) = report.stopped {
+            if stage == StopStage::Detached {
+                warn(&format!(
+                    "Braking took longer than the emergency stop allows, detached the servos \
+                     after {:.2}s and {:.1} units",
+                    self.estop.elapsed, self.estop.travelled
+                ));
+            }
+            self.events.push(RobotEvent::EmergencyStopped(stage), now);
+        }
+        match report.limits {
+            Some(Ok(joint)) => {
+                if let Some(range) = self.joint_limits.get(joint) {
+                    info(&format!(
+                        "Found the {joint} limits at {:.1}° to {:.1}°, write them to the config \
+                         with `limits save <file>`",
+                        range.min, range.max
+                    ));
+                }
+                self.events.push(RobotEvent::LimitsFound(joint), now);
+            }
+            Some(Err(abort)) => {
+                warn(&format!("Limit search aborted: {abort}"));
+                self.events.push(RobotEvent::LimitSearchAborted(abort), now);
+            }
+            None => {}
+        }
+        if let Some(end) = report.grip {
+            self.events.push(RobotEvent::Gripped(end), now);
+        }
+        if let (Some(step), Some(shutdown)) = (report.shutdown, self.shutdown) {
+            match step {
+                ShutdownStep::Escalated(stage) => warn(&format!(
+                    "Shutting down took longer than {}s, going on to {stage} ({})",
+                    self.on_shutdown.budget, shutdown.reason
+                )),
+                ShutdownStep::Done(stage) => {
+                    info(&format!("Shut down ({}), {stage}", shutdown.reason));
+                    self.events.push(RobotEvent::ShutDown(stage), now);
+                }
+            }
+        }
+        if let Some(fault) = report.fault {
+            warn(&format!(
+                "Caught a {fault}, holding position {:?} (delta {delta})",
+                self.position
+            ));
+        }
+        // a bad time step would stay in the output rate and loop stats for good
+        let delta = if delta.is_finite() { delta } else { 0. };
+        self.loop_stats.tick(delta);
+        if let Some(err) = self.ik_error.filter(|_| report.ik_failed) {
+            warn(&format!("Could not calculate inverse kinematics: {err}"));
+        }
+        if report.worn {
+            for joint in self.odometer.worn() {
+                warn(&format!("The {joint} servo travelled past its warning threshold"));
+            }
+        }
+
+        let transmitted = self.transmit(delta)?;
+        Ok(UpdateReport {
+            transmitted,
+            ..report
+        })
+    }
+}
+
+impl Default for Robot {
+    fn default() -> Self {
+        Self {
+            position: CordinateVec::default(),
+            target_position: None,
+            velocity: CordinateVec::default(),
+            max_velocity: CordinateVec::new(100., 100., 100.),
+            target_velocity: CordinateVec::default(),
+            acceleration: 100.,
+            arm: arm::Arm::default(),
+            upper_arm: 100.,
+            lower_arm: 100.,
+            wrist_length: 0.,
+            claw_pitch: 90.,
+            end_effector_offset: CordinateVec::default(),
+            claw_open: None,
+            claw_presets: ClawPresets::default(),
+            connection: Connection::default(),
+            feedback: None,
+            status: StatusPoller::default(),
+            last_heard: None,
+            link: LinkPolicy::default(),
+            replay: None,
+            joint_replay: None,
+            recorder: None,
+            mirror: false,
+            jog: CordinateVec::default(),
+            jog_override: JogOverride::Cancel,
+            jog_paused: false,
+            cruise_speed: None,
+            retargeting: false,
+            soft_start: None,
+            startup: None,
+            odometer: Odometer::default(),
+            journal: Journal::default(),
+            output: OutputRate::default(),
+            dither: None,
+            config_hash: 0,
+            bundle: Bundle::default(),
+            idle: IdlePolicy::default(),
+            calibration: Calibration::default(),
+            calibration_points: Vec::new(),
+            loop_stats: LoopStats::default(),
+            faults: 0,
+            max_substep: None,
+            events: EventQueue::default(),
+            workspaces: Workspaces::default(),
+            torque_limit: None,
+            torque_scale: 1.,
+            payload: 0.,
+            derating: Derating::none(),
+            envelope: Envelope::default(),
+            envelope_mode: EnvelopeMode::Off,
+            envelope_entered: false,
+            stall: None,
+            correction: None,
+            stalled: None,
+            saturated: None,
+            soft_margin: None,
+            soft_margin_warned: None,
+            soft_margin_slow_down: false,
+            estop: EmergencyStop::default(),
+            limit_search: LimitSearchConfig::default(),
+            limit_finder: None,
+            joint_limits: JointLimits::default(),
+            program: Program::default(),
+            program_run: None,
+            grip: None,
+            grip_buttons: Default::default(),
+            segment_stream: None,
+            telemetry: None,
+            servo_override: None,
+            constraints: Vec::new(),
+            obstacles: Vec::new(),
+            boundary: BoundaryConfig::default(),
+            min_z: None,
+            ik_error: None,
+            supply: None,
+            on_shutdown: ShutdownConfig::default(),
+            shutdown: None,
+            link_down_since: None,
+        }
+    }
+}
+
+/// Where a head at `position` is after `delta` seconds while its velocity changed from `start`
+/// to `end`
+///
+/// Velocity Verlet, half the change in velocity is applied before moving and half after. This
+/// is exact for a constant acceleration whatever the length of the step, where moving at
+/// either the start or the end velocity is off by half the change times `delta`
+pub fn integrate(
+    position: CordinateVec,
+    start: CordinateVec,
+    end: CordinateVec,
+    delta: f64,
+) -> CordinateVec {
+    position + (start + end) * (0.5 * delta)
+}
+
+// microseconds for arduino
+const MAX_SERVO: u16 = MAX_PULSE;
+const MIN_SERVO: u16 = MIN_PULSE;
+/// quirky arm
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Servos {
+    pub base: u16,
+    pub shoulder: u16,
+    pub elbow: u16,
+    pub claw: u16,
+
+    /// Fifth channel for arms with a [`arm::Arm::wrist`], left out of the message without one
+    pub wrist: Option<u16>,
+}
+
+
+/// convert servo position represented as an angle into values understod by the servo
+impl Joint {
+    fn to_servo(&self) -> u16 {
+        self.servo_at(self.angle)
+    }
+
+    fn servo_at(&self, angle: f64) -> u16 {
+        self.pulse_at(angle) as u16
+    }
+
+    /// Pulse width for `angle`, wrapped into the joint's turn for continuous joints
+    ///
+    /// The servo is set up so the ends of the joint's range are the ends of its range, in
+    /// between the pulse follows the pivot angle the motion turns it to. An angle past the
+    /// joint's range, or past what a linkage reaches, saturates at the end of the servo's
+    /// range, see [`Joint::saturates`]
+    fn pulse_at(&self, angle: f64) -> f64 {
+        if !angle.is_finite() {
+            return f64::NAN;
+        }
+
+        let (low, high) = self.pivot_span();
+        let pivot = self.reachable_pivot(self.held_in_range(angle));
+
+        // max and min rather than clamp, they let a NaN through instead of panicking
+        self.unwrapped_pulse_at(pivot.max(low).min(high))
+    }
+
+    /// `angle` wrapped around the range for continuous joints, held in it for the others
+    fn held_in_range(&self, angle: f64) -> f64 {
+        if self.continuous {
+            self.min + (angle - self.min).rem_euclid(self.max - self.min)
+        } else {
+            angle.max(self.min).min(self.max)
+        }
+    }
+
+    /// Pivot angle for `angle`, or for the nearest angle the motion reaches if it doesn't
+    /// reach that far. NaN if it has none
+    fn reachable_pivot(&self, angle: f64) -> f64 {
+        match self.motion.try_pivot_angle(angle) {
+            Ok(pivot) => pivot,
+            Err(MotionError::OutOfReach { min, max }) => {
+                self.motion.get_pivot_angle(angle.clamp(min, max))
+            }
+            Err(MotionError::NoSolution) => f64::NAN,
+        }
+    }
+
+    /// The pivot angles the ends of the joint's range turn the servo to, lowest first
+    ///
+    /// Those are the ends of the servo's range, a motion that turns the pivot backwards
+    /// runs the servo backwards
+    fn pivot_span(&self) -> (f64, f64) {
+        let start = self.reachable_pivot(self.min);
+        let end = self.reachable_pivot(self.max);
+        (start.min(end), start.max(end))
+    }
+
+    /// Pulse width for `pivot` spread over the servo's range as [`Joint::pivot_span`] is, not
+    /// clamped
+    ///
+    /// The low end is at [`MAX_SERVO`] and the high end at [`MIN_SERVO`] for an inverted joint
+    fn unwrapped_pulse_at(&self, pivot: f64) -> f64 {
+        let (low, high) = self.pivot_span();
+        let mut factor = (pivot - low) / (high - low);
+        if self.inverted {
+            factor = 1. - factor;
+        }
+        MIN_SERVO as f64 + (MAX_SERVO - MIN_SERVO) as f64 * factor
+    }
+
+    /// The servo stops short of `angle`, it's past the joint's range, the motion turns the
+    /// pivot past the ends of the servo's range or doesn't reach that far
+    fn saturates(&self, angle: f64) -> bool {
+        if !self.continuous && (angle < self.min || angle > self.max) {
+            return true;
+        }
+
+        let (low, high) = self.pivot_span();
+        match self.motion.try_pivot_angle(self.held_in_range(angle)) {
+            Ok(pivot) => pivot < low || pivot > high,
+            Err(err) => matches!(err, MotionError::OutOfReach { .. }),
+        }
+    }
+
+    /// Find the angle that [`Joint::to_servo`] turns into `pulse`
+    ///
+    /// The motion has no inverse so this searches the joint's range, assuming the pulse only
+    /// ever grows or only ever shrinks with the angle. Continuous joints search a turn either
+    /// side without the wrap and end up at the angle closest to the current one
+    fn angle_for_servo(&self, pulse: u16) -> f64 {
+        let (mut low, mut high) = (self.min, self.max);
+        let servo_at = |angle| {
+            if self.continuous {
+                self.unwrapped_pulse_at(self.motion.get_pivot_angle(angle))
+            } else {
+                self.servo_at(angle) as f64
+            }
+        };
+        if self.continuous {
+            let turn = self.max - self.min;
+            (low, high) = (low - turn, high + turn);
+        }
+
+        let rising = servo_at(high) >= servo_at(low);
+        for _ in 0..48 {
+            let middle = (low + high) / 2.;
+            if (servo_at(middle) < pulse as f64) == rising {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        // the end that turns into at least `pulse`, the middle can round down to the one below
+        self.nearest(if rising { high } else { low })
+    }
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::Delta => write!(f, "non-finite time step"),
+            Fault::Velocity => write!(f, "non-finite velocity"),
+            Fault::Position => write!(f, "non-finite position"),
+            Fault::Servos => write!(f, "non-finite servo position"),
+        }
+    }
+}
+
+/// Joints at exactly the same angle, see [`ApproxEq`] for within a tolerance
+impl PartialEq for Joint {
+    fn eq(&self, other: &Self) -> bool {
+        self.angle == other.angle
+    }
+}
+
+/// Joints at the same angle within the tolerance
+impl ApproxEq for Joint {
+    fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        self.angle.approx_eq(&other.angle, eps)
+    }
+}
+
+
+impl Servos {
+    /// The servo values as the firmware expects them
+    ///
+    /// The base, shoulder, elbow and claw, then the wrist if there is one, each written by
+    /// [`ServoEncoding::encode`], the two byte encodings little endian. With
+    /// [`ServoEncoding::MicrosecondsU16`] that's `u16::to_le_bytes` of every pulse width
+    pub fn to_message(self, encoding: ServoEncoding) -> Vec<u8> {
+        let mut message = Vec::with_capacity(5 * encoding.size());
+        self.encode_into(encoding, &mut message);
+        message
+    }
+
+    /// Append the message of [`Servos::to_message`] to `data`
+    pub fn encode_into(self, encoding: ServoEncoding, data: &mut Vec<u8>) {
+        for pulse in [self.base, self.shoulder, self.elbow, self.claw] {
+            encoding.encode(pulse, data);
+        }
+        if let Some(wrist) = self.wrist {
+            encoding.encode(wrist, data);
+        }
+    }
+
+    /// Read a message written by [`Servos::to_message`], with a wrist if it's five servos long
+    ///
+    /// The angle based encodings round the pulse widths to what they can hold
+    ///
+    /// # Returns
+    /// `Err(ProtocolError::Truncated)` if the message is short of four or five servos,
+    /// `Err(ProtocolError::Oversized)` if it's longer than five
+    pub fn from_message(message: &[u8], encoding: ServoEncoding) -> Result<Self, ProtocolError> {
+        let size = encoding.size();
+        let wrist = match message.len() {
+            len if len == 4 * size => false,
+            len if len == 5 * size => true,
+            actual if actual > 5 * size => {
+                return Err(ProtocolError::Oversized {
+                    max: 5 * size,
+                    actual,
+                })
+            }
+            actual => {
+                let servos = if actual < 4 * size { 4 } else { 5 };
+                return Err(ProtocolError::Truncated {
+                    expected: servos * size,
+                    actual,
+                });
+            }
+        };
+
+        let pulse = |servo: usize| encoding.decode(&message[servo * size..]);
+        Ok(Self {
+            base: pulse(0)?,
+            shoulder: pulse(1)?,
+            elbow: pulse(2)?,
+            claw: pulse(3)?,
+            wrist: wrist.then(|| pulse(4)).transpose()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        kinematics::{
+            joints::{
+                margin_scale, DirectDrive, DirectDriveOffset, DoubleLinkage, GearDrive,
+                MIN_MARGIN_SCALE,
+            },
+            position::JointName,
+        },
+        protocol::{Checksum, FirmwareStatus},
+        recording::Sample,
+    };
+    use arm::SoftMargins;
+    use std::time::Duration;
+    use super::*;
+    use crate::input::Buttons;
+    use crate::sim::{SimConfig, Simulator};
+    use envelope::{EnvelopeError, EnvelopeMode};
+    use kinematics_core::assert_approx_eq;
+    use limits::{JointLimits, LimitAbort, LimitError};
+    use stall::StallConfig;
+    use torque::TorqueLimit;
+    use workspace::Workspace;
+
+    #[test]
+    pub fn servos_to_message() {
+        let servos = Servos {
+            base: 100,
+            shoulder: 200,
+            elbow: 50,
+            claw: 1,
+            wrist: None,
+        };
+
+        let actual = servos.to_message(ServoEncoding::MicrosecondsU16);
+        let expected: Vec<u8> = vec![100, 0, 200, 0, 50, 0, 1, 0];
+
+        assert_eq!(actual, expected);
+
+        // a byte each, clamped to the range of the servos
+        let servos = Servos {
+            base: 250,
+            shoulder: 1325,
+            elbow: 2400,
+            claw: 3000,
+            wrist: None,
+        };
+        assert_eq!(
+            servos.to_message(ServoEncoding::DegreesU8),
+            vec![0, 90, 180, 180]
+        );
+        assert_eq!(servos.to_message(ServoEncoding::CentidegreesU16).len(), 8);
+
+        // the wrist goes last
+        let servos = Servos {
+            wrist: Some(1500),
+            ..servos
+        };
+        assert_eq!(
+            servos.to_message(ServoEncoding::MicrosecondsU16),
+            vec![250, 0, 45, 5, 96, 9, 184, 11, 220, 5]
+        );
+        assert_eq!(
+            servos.to_message(ServoEncoding::DegreesU8),
+            vec![0, 90, 180, 180, 105]
+        );
+    }
+
+    #[test]
+    pub fn servos_from_message() {
+        let servos = Servos {
+            base: 250,
+            shoulder: 1325,
+            elbow: 2400,
+            claw: 1000,
+            wrist: None,
+        };
+        let with_wrist = Servos {
+            wrist: Some(1500),
+            ..servos
+        };
+
+        // back to the same pulses, with the wrist only if it was sent
+        for servos in [servos, with_wrist] {
+            for encoding in [ServoEncoding::MicrosecondsU16, ServoEncoding::CentidegreesU16] {
+                let message = servos.to_message(encoding);
+                assert_eq!(Servos::from_message(&message, encoding), Ok(servos));
+            }
+        }
+        let message = with_wrist.to_message(ServoEncoding::DegreesU8);
+        let read = Servos::from_message(&message, ServoEncoding::DegreesU8).unwrap();
+        assert_eq!(read.to_message(ServoEncoding::DegreesU8), message);
+        assert!(read.wrist.is_some_and(|wrist| wrist.abs_diff(1500) <= 6));
+
+        // the bytes of the firmware's layout
+        let micros = ServoEncoding::MicrosecondsU16;
+        let read = Servos::from_message(&[100, 0, 200, 0, 50, 0, 1, 0], micros);
+        assert_eq!(
+            read.map(|servos| [servos.base, servos.shoulder, servos.elbow, servos.claw]),
+            Ok([100, 200, 50, 1])
+        );
+
+        // neither four nor five servos
+        let truncated = |expected, actual| Err(ProtocolError::Truncated { expected, actual });
+        assert_eq!(Servos::from_message(&[0; 7], micros), truncated(8, 7));
+        assert_eq!(Servos::from_message(&[0; 9], micros), truncated(10, 9));
+        assert_eq!(Servos::from_message(&[], micros), truncated(8, 0));
+        assert_eq!(
+            Servos::from_message(&[0; 12], micros),
+            Err(ProtocolError::Oversized {
+                max: 10,
+                actual: 12
+            })
+        );
+    }
+
+    #[test]
+    pub fn read_feedback() {
+        let mut robo = Robot::default();
+
+        let feedback = Feedback {
+            pulses: [1000, 1100, 1200, 1300],
+            millivolts: 5000,
+            status: 0,
+        };
+        let unknown = Frame::Unknown {
+            kind: 0x33,
+            payload: vec![],
+        };
+        let now = Instant::now();
+
+        // feedback is handled by the robot, unknown frames are passed on
+        assert_eq!(robo.dispatch(Frame::Feedback(feedback), now), None);
+        assert_eq!(robo.feedback, Some(feedback));
+        assert_eq!(robo.dispatch(unknown.clone(), now), Some(unknown));
+    }
+
+    #[test]
+    pub fn read_status() {
+        let mut robo = Robot::default();
+        let status = FirmwareStatus {
+            uptime_ms: 12_000,
+            millivolts: 5100,
+            loop_hz: 900,
+            last_seq: 4,
+        };
+
+        assert_eq!(robo.state().firmware, FirmwareStatusView::Missing);
+
+        robo.connection.receive(&Frame::Status(status).encode(Checksum::Xor, 0));
+        assert_eq!(robo.poll(MAX_FRAMES_PER_TICK).unwrap(), 1);
+        assert_eq!(robo.state().firmware, FirmwareStatusView::Current(status));
+    }
+
+    #[test]
+    pub fn status_query_schedule() {
+        let mut robo = Robot {
+            status: StatusPoller::new(Some(Duration::from_secs(2)), Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+
+        robo.query_status(start).unwrap();
+        assert_eq!(robo.connection.tx_seq, 1);
+
+        robo.query_status(start + Duration::from_secs(1)).unwrap();
+        assert_eq!(robo.connection.tx_seq, 1);
+
+        robo.query_status(start + Duration::from_secs(2)).unwrap();
+        assert_eq!(robo.connection.tx_seq, 2);
+    }
+
+    #[test]
+    pub fn poll_routing() {
+        let mut robo = Robot::default();
+        let seq = robo.connection.send(&Frame::StatusRequest).unwrap();
+        let feedback = Feedback {
+            pulses: [1500; 4],
+            millivolts: 4900,
+            status: Feedback::MOVING,
+        };
+        let status = FirmwareStatus {
+            uptime_ms: 500,
+            millivolts: 4900,
+            loop_hz: 800,
+            last_seq: seq,
+        };
+
+        let frames = [
+            Frame::Feedback(feedback),
+            Frame::Ack { seq },
+            Frame::Status(status),
+            Frame::Unknown {
+                kind: 0x33,
+                payload: vec![1],
+            },
+        ];
+        for frame in &frames {
+            robo.connection.receive(&frame.encode(Checksum::Xor, 0));
+        }
+
+        assert_eq!(robo.poll(MAX_FRAMES_PER_TICK).unwrap(), frames.len());
+        assert_eq!(robo.feedback, Some(feedback));
+        assert_eq!(robo.state().firmware, FirmwareStatusView::Current(status));
+        assert!(robo.connection.acks.pending.is_empty());
+        assert_eq!(robo.connection.acks.acked, 1);
+
+        let received = robo.connection.stats.received;
+        assert_eq!(
+            (received.feedback, received.ack, received.status, received.unknown),
+            (1, 1, 1, 1)
+        );
+    }
+
+    #[test]
+    pub fn frame_flood() {
+        let mut robo = Robot {
+            target_velocity: CordinateVec::new(10., 0., 0.),
+            ..Default::default()
+        };
+        let flood = 3 * MAX_FRAMES_PER_TICK + 2;
+        let feedback = Frame::Feedback(Feedback {
+            pulses: [1500; 4],
+            millivolts: 5000,
+            status: 0,
+        });
+
+        for _ in 0..flood {
+            robo.connection.receive(&feedback.encode(Checksum::Xor, 0));
+        }
+
+        // only a bounded number of frames is handled, the rest of the update still runs
+        robo.update(0.1).unwrap();
+        assert_eq!(robo.connection.msg_buf.len(), flood - MAX_FRAMES_PER_TICK);
+        assert!(robo.position.x > 0.);
+        assert_eq!(robo.connection.tx_seq, 1);
+
+        // the backlog drains over the following updates
+        for _ in 0..3 {
+            robo.update(0.1).unwrap();
+        }
+        assert!(robo.connection.msg_buf.is_empty());
+        assert_eq!(robo.connection.stats.received.feedback, flood as u64);
+    }
+
+    #[test]
+    pub fn unreliable_link_slows_down() {
+        let mut robo = Robot {
+            target_velocity: CordinateVec::new(10., 0., 0.),
+            link: LinkPolicy {
+                smoothing: 1.,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        robo.update(0.01).unwrap();
+        let full = robo.velocity.x;
+        assert!(full > 0.);
+
+        // every frame since the last update failed to decode
+        robo.connection.stats.framing_errors += 10;
+        robo.update(0.01).unwrap();
+        assert_eq!(robo.state().speed_scale, 0.);
+        assert!(robo.velocity.x < full);
+    }
+
+    #[test]
+    pub fn replay_offset() {
+        let mut robo = Robot::default();
+        let recording = Recording {
+            samples: vec![
+                Sample {
+                    time: 0.,
+                    pose: CordinateVec::new(50., 80., 10.),
+                },
+                Sample {
+                    time: 1.,
+                    pose: CordinateVec::new(50., 20., 50.),
+                },
+            ],
+        };
+
+        // raised this much the second sample is out of reach
+        let err = robo
+            .start_replay(
+                &recording,
+                &Transform {
+                    offset: CordinateVec::new(0., 0., 150.),
+                    rotation: 0.,
+                    mirror: false,
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, RecordingError::Unreachable { index: 1, .. }));
+        assert!(robo.replay.is_none());
+
+        robo.start_replay(
+            &recording,
+            &Transform {
+                offset: CordinateVec::new(0., -10., 0.),
+                rotation: 0.,
+                mirror: false,
+            },
+        )
+        .unwrap();
+        assert!(!robo.tick(0.5).replay_finished);
+        assert_eq!(robo.target_position, Some(CordinateVec::new(50., 40., 30.)));
+
+        assert!(robo.tick(1.).replay_finished);
+        assert!(robo.replay.is_none());
+        assert_eq!(robo.target_position, None);
+    }
+
+    #[test]
+    pub fn mirrored_motion() {
+        let mut normal = Robot::default();
+        let mut mirrored = Robot {
+            mirror: true,
+            ..Default::default()
+        };
+
+        for robo in [&mut normal, &mut mirrored] {
+            robo.position = CordinateVec::new(0., 50., 50.);
+            robo.command_velocity(CordinateVec::new(20., 10., 0.));
+            for _ in 0..10 {
+                robo.tick(0.05);
+            }
+        }
+
+        assert!(normal.position.x > 0.);
+        assert_eq!(mirrored.position.x, -normal.position.x);
+        assert_eq!(mirrored.position.y, normal.position.y);
+        assert_eq!(mirrored.position.z, normal.position.z);
+
+        // targets are mirrored too, stored positions are not
+        normal.command_target(CordinateVec::new(30., 60., 40.));
+        mirrored.command_target(CordinateVec::new(30., 60., 40.));
+        assert_eq!(normal.target_position, Some(CordinateVec::new(30., 60., 40.)));
+        assert_eq!(mirrored.target_position, Some(CordinateVec::new(-30., 60., 40.)));
+    }
+
+    #[test]
+    pub fn joint_velocity_limits() {
+        // from stretched out nearly all the way to folded up, the shoulder swings a long way
+        let target = CordinateVec::new(0., 60., 120.);
+        let fast = || {
+            let mut robo = Robot {
+                position: CordinateVec::new(0., 195., 10.),
+                target_position: Some(target),
+                max_velocity: CordinateVec::new(400., 400., 400.),
+                acceleration: 4000.,
+                ..Default::default()
+            };
+            robo.update_ik();
+            robo
+        };
+        let delta = 0.02;
+        let mut robo = fast();
+        let unlimited = (0..1000)
+            .take_while(|_| {
+                robo.tick(delta);
+                robo.target_position.is_some()
+            })
+            .count();
+
+        let mut robo = fast();
+        robo.arm.base.max_velocity_dps = 90.;
+        robo.arm.shoulder.max_velocity_dps = 60.;
+        robo.arm.elbow.max_velocity_dps = 120.;
+        let mut limited = 0;
+        for _ in 0..1000 {
+            let from = robo.arm.angles();
+            robo.tick(delta);
+            let to = robo.arm.angles();
+
+            for (name, limit) in [("base", 90.), ("shoulder", 60.), ("elbow", 120.)] {
+                let (from, to) = (from.get(name).unwrap(), to.get(name).unwrap());
+                assert!(
+                    (to - from).abs() <= limit * delta + 1e-9,
+                    "{name} from {from} to {to}"
+                );
+            }
+            assert_approx_eq!(robo.position, robo.arm.forward_kinematics(100., 100.), 1e-6);
+            if robo.target_position.is_none() {
+                break;
+            }
+            limited += 1;
+            assert_ne!(from, to);
+        }
+        assert!(limited > unlimited + 20, "{limited} and {unlimited}");
+        assert_approx_eq!(robo.position, target, 1e-3);
+
+        // every joint scaled the same, the arm moves on the same line in joint space
+        let mut arm = arm::Arm::default();
+        arm.shoulder.max_velocity_dps = 10.;
+        let from = arm.angles();
+        arm.shoulder.angle = 40.;
+        arm.elbow.angle = 20.;
+        assert_eq!(arm.limit_velocity(from, None, 1.), 4.);
+        assert_eq!((arm.shoulder.angle, arm.elbow.angle), (10., 5.));
+        let from = arm.angles();
+        arm.elbow.angle = 6.;
+        assert_eq!(arm.limit_velocity(from, None, 1.), 1.);
+        assert_eq!(arm.elbow.angle, 6.);
+    }
+
+    #[test]
+    pub fn joint_replay_limits() {
+        let mut robo = Robot::default();
+        robo.arm.base.max_velocity_dps = 90.;
+        robo.arm.shoulder.max_velocity_dps = 30.;
+
+        let target = JointAngles {
+            base: 90.,
+            shoulder: 60.,
+            elbow: 90.,
+            claw: 45.,
+        };
+        let recording = Recording {
+            samples: vec![
+                Sample {
+                    time: 0.,
+                    pose: JointAngles::default(),
+                },
+                Sample {
+                    time: 0.5,
+                    pose: target,
+                },
+            ],
+        };
+        robo.start_joint_replay(&recording).unwrap();
+
+        let delta = 0.05;
+        let mut previous = robo.arm.angles();
+        let mut ticks = 0;
+        while !robo.tick(delta).replay_finished {
+            let angles = robo.arm.angles();
+            assert!((angles.base - previous.base).abs() <= 90. * delta + 1e-9);
+            assert!((angles.shoulder - previous.shoulder).abs() <= 30. * delta + 1e-9);
+            previous = angles;
+
+            ticks += 1;
+            assert!(ticks < 100, "replay never finished");
+        }
+
+        // the shoulder is slowest, 60 degrees at 30 degrees/s
+        assert!((ticks as f64 * delta - (2. - delta)).abs() < 1e-9);
+        assert_eq!(robo.arm.angles(), target);
+        assert!(robo.joint_replay.is_none());
+    }
+
+    #[test]
+    pub fn record_joints() {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            target_velocity: CordinateVec::new(10., 0., 0.),
+            ..Default::default()
+        };
+
+        assert_eq!(robo.stop_recording(), None);
+        robo.start_recording();
+        for _ in 0..4 {
+            robo.tick(0.25);
+        }
+
+        let recording = robo.stop_recording().unwrap();
+        assert_eq!(recording.samples.len(), 4);
+        assert_eq!(recording.samples[3].time, 0.75);
+        assert_eq!(recording.last(), Some(robo.arm.angles()));
+        assert!(robo.recorder.is_none());
+    }
+
+    #[test]
+    pub fn servo_mapping() {
+        let mut joint = Joint::new(0., 180., DirectDrive::new());
+        assert_eq!(joint.servo_at(0.), MIN_SERVO);
+        assert_eq!(joint.servo_at(180.), MAX_SERVO);
+        assert_eq!(joint.servo_at(90.), (MIN_SERVO + MAX_SERVO) / 2);
+
+        // a range that doesn't start at 0 spreads over the same pulses
+        joint.min = 30.;
+        joint.max = 120.;
+        assert_eq!(joint.servo_at(30.), MIN_SERVO);
+        assert_eq!(joint.servo_at(120.), MAX_SERVO);
+        assert_eq!(joint.servo_at(75.), (MIN_SERVO + MAX_SERVO) / 2);
+
+        // past the range saturates instead of running off the end
+        for (angle, pulse) in [(10., MIN_SERVO), (200., MAX_SERVO)] {
+            assert!(joint.saturates(angle));
+            assert_eq!(joint.servo_at(angle), pulse);
+        }
+        assert!(!joint.saturates(120.));
+        assert!(!joint.saturates(f64::NAN));
+        assert!(joint.pulse_at(f64::NAN).is_nan());
+
+        // the ends of the range are the ends of the servo's, wherever the motion turns them
+        let base = Joint::new(0., 180., DirectDriveOffset { offset: 90. });
+        assert_eq!(base.servo_at(0.), MIN_SERVO);
+        assert_eq!(base.servo_at(180.), MAX_SERVO);
+        assert!(!base.saturates(150.));
+
+        // a gear turning the other way runs the servo backwards
+        let geared = Joint::new(0., 90., GearDrive::new(-2.).unwrap());
+        assert_eq!(geared.servo_at(0.), MAX_SERVO);
+        assert_eq!(geared.servo_at(90.), MIN_SERVO);
+        assert_eq!(geared.servo_at(45.), (MIN_SERVO + MAX_SERVO) / 2);
+    }
+
+    #[test]
+    pub fn set_angle() {
+        let mut joint = Joint::new(30., 120., DirectDrive::new());
+        assert_eq!(joint.set_angle(45.), Ok(()));
+        assert_eq!(joint.angle, 45.);
+
+        // right on a limit is still in range
+        assert_eq!(joint.set_angle(120.), Ok(()));
+        assert_eq!(joint.angle, 120.);
+        assert_eq!(joint.try_set_angle(30.), Ok(()));
+        assert_eq!(joint.angle, 30.);
+
+        // past it the joint is held at the limit, or stays where it is
+        let hit = joint.set_angle(135.).unwrap_err();
+        assert_eq!((hit.limit, hit.overshoot), (120., 15.));
+        assert_eq!(joint.angle, 120.);
+        assert_eq!(hit.to_string(), "15.0° past its limit at 120.0°");
+        let hit = joint.try_set_angle(20.).unwrap_err();
+        assert_eq!((hit.limit, hit.overshoot), (30., -10.));
+        assert_eq!(joint.angle, 120.);
+        assert_eq!(joint.set_angle(20.), Err(hit));
+        assert_eq!(joint.angle, 30.);
+
+        // a continuous joint wraps around instead
+        joint.continuous = true;
+        assert_eq!(joint.set_angle(400.), Ok(()));
+        assert_eq!(joint.angle, 400.);
+    }
+
+    #[test]
+    pub fn claw_presets() {
+        let mut robo = Robot {
+            position: CordinateVec::new(0., 100., 50.),
+            claw_open: Some(true),
+            ..Default::default()
+        };
+        robo.arm.claw = Joint::new(0., 180., DirectDrive::new());
+        let claw = |robo: &Robot| {
+            let message = robo.arm.to_servos().to_message(ServoEncoding::MicrosecondsU16);
+            u16::from_le_bytes([message[6], message[7]])
+        };
+
+        // the claw channel follows the flag every tick
+        robo.tick(0.01);
+        assert_eq!(claw(&robo), MAX_SERVO);
+        robo.claw_open = Some(false);
+        robo.tick(0.01);
+        assert_eq!(claw(&robo), MIN_SERVO);
+        robo.arm.claw.angle = 90.;
+        robo.tick(0.01);
+        assert_eq!(robo.arm.claw.angle, 0.);
+
+        // presets for another gripper
+        robo.claw_presets = ClawPresets {
+            open_angle: 135.,
+            closed_angle: 45.,
+        };
+        robo.tick(0.01);
+        assert_eq!(claw(&robo), robo.arm.claw.servo_at(45.));
+        robo.claw_open = Some(true);
+        robo.tick(0.01);
+        assert_eq!(claw(&robo), robo.arm.claw.servo_at(135.));
+
+        // left alone without the flag
+        robo.claw_open = None;
+        robo.arm.claw.angle = 90.;
+        robo.tick(0.01);
+        assert_eq!(claw(&robo), (MIN_SERVO + MAX_SERVO) / 2);
+    }
+
+    #[test]
+    pub fn soft_margin_curve() {
+        // full speed where the margin starts, slower the less is left, never quite stopped
+        assert_eq!(margin_scale(10., 10.), 1.);
+        assert_eq!(margin_scale(25., 10.), 1.);
+        assert!((margin_scale(10. - 1e-9, 10.) - 1.).abs() < 1e-9);
+        assert_eq!(margin_scale(5., 10.), 0.5);
+        assert_eq!(margin_scale(2.5, 10.), 0.25);
+        assert_eq!(margin_scale(0., 10.), MIN_MARGIN_SCALE);
+        assert_eq!(margin_scale(-3., 10.), MIN_MARGIN_SCALE);
+
+        let mut joint = Joint::new(0., 180., DirectDrive::new());
+        joint.angle = 175.;
+        joint.soft_margin_deg = 10.;
+        assert!(joint.in_soft_margin());
+        assert_eq!(joint.soft_margin_left(1.), Some(5.));
+        assert_eq!(joint.soft_margin_scale(1.), 0.5);
+
+        // turning away from the limit isn't slowed
+        assert_eq!(joint.soft_margin_left(-1.), None);
+        assert_eq!(joint.soft_margin_scale(-1.), 1.);
+        assert_eq!(joint.soft_margin_scale(0.), 1.);
+    }
+
+    #[test]
+    pub fn no_soft_margin() {
+        for left in [-5., 0., 0.5, 10., 100.] {
+            assert_eq!(margin_scale(left, 0.), 1.);
+        }
+
+        let mut joint = Joint::new(0., 180., DirectDrive::new());
+        for angle in [0., 90., 180.] {
+            joint.angle = angle;
+            assert!(!joint.in_soft_margin());
+            assert_eq!(joint.soft_margin_scale(1.), 1.);
+            assert_eq!(joint.soft_margin_scale(-1.), 1.);
+        }
+
+        // nor for a joint that turns all the way round
+        joint.soft_margin_deg = 10.;
+        joint.continuous = true;
+        assert!(!joint.in_soft_margin());
+    }
+
+    #[test]
+    pub fn soft_margin_velocity() {
+        let mut robo = Robot {
+            soft_margin_slow_down: true,
+            ..Default::default()
+        };
+        robo.arm.set_soft_margins(SoftMargins {
+            shoulder: 10.,
+            ..Default::default()
+        });
+        robo.arm.interpolate(
+            JointAngles {
+                base: 90.,
+                shoulder: 175.,
+                elbow: 90.,
+                claw: 90.,
+            },
+            JointAngles::default(),
+            0.,
+        );
+        let [_, shoulder, elbow] = robo.arm.jacobian(robo.upper_arm, robo.lower_arm);
+
+        // only the part turning the shoulder further towards its limit is slowed down
+        assert_approx_eq!(robo.soft_margin_velocity(shoulder), shoulder * 0.5);
+        assert_approx_eq!(robo.soft_margin_velocity(-shoulder), -shoulder);
+        assert_approx_eq!(robo.soft_margin_velocity(elbow), elbow);
+        assert_approx_eq!(
+            robo.soft_margin_velocity(shoulder + elbow),
+            shoulder * 0.5 + elbow
+        );
+
+        robo.soft_margin_slow_down = false;
+        assert_eq!(robo.soft_margin_velocity(shoulder), shoulder);
+        robo.soft_margin_slow_down = true;
+        robo.arm.set_soft_margins(SoftMargins::default());
+        assert_approx_eq!(robo.soft_margin_velocity(shoulder), shoulder);
+
+        // flagged for the display once the inverse kinematics set the joints
+        robo.arm.set_soft_margins(SoftMargins {
+            claw: 10.,
+            ..Default::default()
+        });
+        robo.position = CordinateVec::new(0., 100., 50.);
+        robo.arm.claw.angle = 175.;
+        assert!(robo.update_ik());
+        assert_eq!(robo.soft_margin, Some("claw"));
+        assert!(robo.soft_margin_warned.is_some());
+        robo.arm.claw.angle = 90.;
+        assert!(robo.update_ik());
+        assert_eq!(robo.state().soft_margin, None);
+    }
+
+    #[test]
+    pub fn inverted() {
+        let mut joint = Joint {
+            inverted: true,
+            ..Joint::new(30., 120., DirectDrive::new())
+        };
+        assert_eq!(joint.servo_at(30.), MAX_SERVO);
+        assert_eq!(joint.servo_at(120.), MIN_SERVO);
+        assert_eq!(joint.servo_at(75.), (MIN_SERVO + MAX_SERVO) / 2);
+
+        // flipped after clamping, past the range still saturates at the right end
+        assert_eq!(joint.servo_at(10.), MAX_SERVO);
+        assert_eq!(joint.servo_at(200.), MIN_SERVO);
+
+        // after the motion, the pulse is the plain one mirrored whatever the motion does
+        joint.motion = DirectDriveOffset { offset: 15. }.into();
+        let plain = Joint::new(30., 120., DirectDriveOffset { offset: 15. });
+        for angle in [30., 50., 90.] {
+            let mirrored = (MIN_SERVO + MAX_SERVO) as f64 - plain.pulse_at(angle);
+            assert!((joint.pulse_at(angle) - mirrored).abs() < 1e-9, "{angle}");
+            let back = joint.angle_for_servo(joint.servo_at(angle));
+            assert!((back - angle).abs() < 0.1, "{angle}");
+        }
+
+        let mut arm = arm::Arm::default();
+        arm.invert(arm::InvertedJoints {
+            elbow: true,
+            wrist: true,
+            ..Default::default()
+        });
+        assert!(arm.elbow.inverted && !arm.base.inverted);
+        assert_eq!(arm.to_servos().elbow, MAX_SERVO);
+        assert_eq!(arm.to_servos().base, MIN_SERVO);
+    }
+
+    #[test]
+    pub fn saturated() {
+        let mut arm = arm::Arm::default();
+        assert_eq!(arm.saturated(), None);
+
+        // the servo is set up for the range the offset turns it through
+        arm.elbow.motion = DirectDriveOffset { offset: 20. }.into();
+        arm.elbow.angle = 170.;
+        assert_eq!(arm.saturated(), None);
+        arm.elbow.angle = 190.;
+        assert_eq!(arm.saturated(), Some("elbow"));
+        assert_eq!(arm.to_servos().elbow, MAX_SERVO);
+
+        arm.elbow.angle = 150.;
+        arm.wrist = Some(Joint::new(0., 360., DirectDrive::new()));
+        arm.wrist.as_mut().unwrap().angle = -5.;
+        assert_eq!(arm.saturated(), Some("wrist"));
+    }
+
+    #[test]
+    pub fn linkage_out_of_reach() {
+        // the rods stop reaching the arm at about 113°, past that it's held there, not NaN
+        let linkage = DoubleLinkage::new(1., 10., 10., 1., 10., 20.);
+        let (_, reach) = linkage.achievable_range().unwrap();
+        // the servo's range ends where the reach does
+        let mut joint = Joint::new(0., 180., linkage);
+        assert!(joint.motion.get_pivot_angle(150.).is_nan());
+        assert!(joint.saturates(150.));
+        assert!(!joint.saturates(reach - 1.));
+        assert_eq!(joint.pulse_at(150.), joint.pulse_at(reach));
+        assert_eq!(joint.servo_at(reach), MAX_SERVO);
+        assert_eq!(joint.servo_at(170.), joint.servo_at(reach));
+
+        // the arm warns about it like a joint past its range
+        let mut arm = arm::Arm {
+            shoulder: joint,
+            ..Default::default()
+        };
+        arm.shoulder.angle = 150.;
+        assert_eq!(arm.saturated(), Some("shoulder"));
+        assert!(arm.checked_servos(JointAngles::default()).is_some());
+
+        // a geometry that never closes and angles that aren't finite still have no pulse
+        joint = Joint::new(0., 180., DoubleLinkage::new(1., 2., 10., 1., 0.5, 0.5));
+        assert!(joint.pulse_at(90.).is_nan());
+        assert!(!joint.saturates(90.));
+        arm.shoulder = joint;
+        assert!(arm.checked_servos(JointAngles::default()).is_none());
+        assert!(Joint::new(0., 180., linkage)
+            .pulse_at(f64::INFINITY)
+            .is_nan());
+    }
+
+    #[test]
+    pub fn servo_round_trip() {
+        let joint = Joint::new(0., 180., DirectDriveOffset { offset: 30. });
+
+        for angle in [20., 65., 110.] {
+            let pulse = joint.servo_at(angle);
+            assert_eq!(joint.servo_at(joint.angle_for_servo(pulse)), pulse);
+        }
+    }
+
+    #[test]
+    pub fn elbow_keeps_bending_its_way() {
+        // low and out in front, where bending the elbow the other way holds the upper arm up
+        // instead of swinging the shoulder down past 90°
+        let target = CordinateVec::new(0., 120., -60.);
+        let solutions = target.ik_solutions(100., 100.).unwrap();
+        let mirrored = solutions.mirrored.unwrap();
+
+        // the elbow can't bend past 180° so it's the primary one either way
+        let pose = |robo: &mut Robot, (base, shoulder, elbow)| {
+            robo.arm.base.angle = base;
+            robo.arm.shoulder.angle = shoulder;
+            robo.arm.elbow.angle = elbow;
+        };
+        let mut robo = Robot::default();
+        pose(&mut robo, mirrored);
+        robo.position = target;
+        assert!(robo.update_ik());
+        assert_eq!(robo.arm.elbow.angle, solutions.primary.2);
+
+        // an elbow that can bends the way it already is on the way down
+        robo.arm.elbow.max = 360.;
+        pose(&mut robo, mirrored);
+        for step in 0..=10 {
+            robo.position = target + CordinateVec::new(0., 0., 6. - step as f64 * 0.6);
+            assert!(robo.update_ik());
+            assert!(robo.arm.elbow.angle > 180., "{:?}", robo.arm.angles());
+        }
+
+        // and the other way round from the primary one
+        pose(&mut robo, solutions.primary);
+        robo.position = target + CordinateVec::new(0., 0., 3.);
+        assert!(robo.update_ik());
+        assert!(robo.arm.elbow.angle < 180., "{:?}", robo.arm.angles());
+    }
+
+    #[test]
+    pub fn ik_out_of_range() {
+        let mut robo = Robot {
+            position: CordinateVec::new(0., 100., 50.),
+            ..Default::default()
+        };
+        assert!(robo.update_ik());
+        let held = robo.arm.angles();
+
+        // low and close in the shoulder would have to lean past 180° with the elbow bent
+        // either way, the arm stays where it was
+        robo.position = CordinateVec::new(0., 60., -150.);
+        assert!(!robo.update_ik());
+        assert_eq!(robo.arm.angles(), held);
+        assert_eq!(
+            robo.ik_error,
+            Some(KinematicsError::JointLimit(JointName::Shoulder))
+        );
+
+        // and says why when it's out of reach
+        robo.position = CordinateVec::new(0., 300., 0.);
+        assert!(!robo.update_ik());
+        assert!(matches!(
+            robo.ik_error,
+            Some(KinematicsError::OutOfReach { max, .. }) if max == robo.upper_arm + robo.lower_arm
+        ));
+
+        // until it's back
+        robo.position = CordinateVec::new(0., 100., 50.);
+        assert!(robo.update_ik());
+        assert_eq!(robo.ik_error, None);
+    }
+
+    #[test]
+    pub fn wrist() {
+        let tip = CordinateVec::new(0., 150., 20.);
+        let mut robo = Robot {
+            position: tip,
+            wrist_length: 40.,
+            ..Default::default()
+        };
+        robo.arm.wrist = Some(Joint {
+            max: 360.,
+            ..Joint::default()
+        });
+        assert!(robo.update_ik());
+
+        // the tip of the claw is on the head, held level
+        let arm = &robo.arm;
+        let wrist = arm.wrist.as_ref().unwrap().angle;
+        let wrist_point = arm.forward_kinematics(robo.upper_arm, robo.lower_arm);
+        assert_approx_eq!(wrist_point.claw_tip(40., 90.), tip);
+        assert_approx_eq!(arm.shoulder.angle + arm.elbow.angle + wrist, 90. + 360.);
+
+        // sent on a fifth channel
+        let servos = arm.checked_servos(JointAngles::default()).unwrap();
+        assert_eq!(servos.wrist, Some(arm.wrist.as_ref().unwrap().to_servo()));
+        assert_eq!(servos.to_message(ServoEncoding::MicrosecondsU16).len(), 10);
+
+        // moving the joints directly leaves the head on the tip of the claw
+        robo.follow_joints();
+        assert_approx_eq!(robo.position, tip);
+
+        // the wrist can't bend that far
+        robo.arm.wrist.as_mut().unwrap().max = wrist - 1.;
+        robo.arm.wrist.as_mut().unwrap().angle = 0.;
+        assert!(!robo.update_ik());
+        assert_eq!(robo.ik_error, Some(KinematicsError::JointLimit(JointName::Wrist)));
+        assert_eq!(robo.arm.wrist.as_ref().unwrap().angle, 0.);
+        assert!(!robo.in_reach(tip));
+    }
+
+    #[test]
+    pub fn end_effector_offset() {
+        let offset = CordinateVec::new(40., 5., -10.);
+        let mut robo = Robot {
+            position: CordinateVec::new(50., 50., 0.),
+            end_effector_offset: offset,
+            ..Default::default()
+        };
+
+        // the gripper lands on the target, not the end of the arm
+        let target = CordinateVec::new(60., 120., 20.);
+        assert!(robo.command_target(target));
+        for _ in 0..2000 {
+            robo.tick(0.01);
+        }
+        assert_eq!(robo.position, target);
+        let end = robo.arm.forward_kinematics(robo.upper_arm, robo.lower_arm);
+        assert_approx_eq!(end.tool_tip(offset), target);
+
+        // with the gripper and the arm as one, with the wrist
+        robo.arm.wrist = Some(Joint {
+            max: 360.,
+            ..Joint::default()
+        });
+        robo.wrist_length = 30.;
+        assert!(robo.update_ik());
+        let end = robo.arm.forward_kinematics(robo.upper_arm, robo.lower_arm);
+        assert_approx_eq!(end.claw_tip(30., 90.).tool_tip(offset), target);
+
+        // moving the joints directly keeps the head on the gripper
+        robo.follow_joints();
+        assert_approx_eq!(robo.position, target);
+
+        // the gripper can't reach across to the z axis
+        robo.position = CordinateVec::new(0., 2., 20.);
+        assert!(!robo.update_ik());
+        assert_eq!(robo.ik_error, Some(KinematicsError::ToolOffset));
+    }
+
+    #[test]
+    pub fn continuous_base() {
+        let mut robo = Robot {
+            joint_limits: JointLimits {
+                base: Some(JointRange { min: 0., max: 180. }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        robo.arm.base = Joint {
+            continuous: true,
+            ..Joint::new(0., 360., DirectDriveOffset { offset: 90. })
+        };
+
+        // twice round one way and three times back, crossing behind the base every turn
+        let mut heading = 0f64;
+        let mut last: Option<f64> = None;
+        for turn in [1., 1., -1., -1., -1.] {
+            for _ in 0..180 {
+                heading += 2. * turn;
+                let (sin, cos) = heading.to_radians().sin_cos();
+                robo.position = CordinateVec::new(80. * cos, 80. * sin, 50.);
+                assert!(robo.in_reach(robo.position), "at {heading}°");
+                assert!(robo.update_ik(), "at {heading}°");
+
+                let base = robo.arm.base.angle;
+                if let Some(last) = last {
+                    assert!((base - last - 2. * turn).abs() < 1e-6, "{last}° to {base}°");
+                }
+                last = Some(base);
+
+                let pulse = robo.arm.checked_servos(JointAngles::default()).unwrap().base;
+                assert!(pulse <= MAX_SERVO, "{pulse} at {base}°");
+                let read = robo.arm.base.angle_for_servo(pulse);
+                assert!((read - base).abs() < 0.5, "{read}° for {base}°");
+                assert_eq!(robo.arm.clamped_angles().base, base);
+            }
+        }
+        assert!((robo.arm.base.angle - (90. - 360.)).abs() < 1e-6);
+
+        // the joint steps the short way round too
+        robo.arm.base.angle = 350.;
+        robo.arm.base.step_towards(10., 1.);
+        assert_eq!(robo.arm.base.angle, 370.);
+    }
+
+    #[test]
+    pub fn soft_start_holds_input() {
+        let mut robo = Robot {
+            position: CordinateVec::new(40., 30., 120.),
+            soft_start: Some(SoftStart::new(0.5, 60., JointAngles::default())),
+            ..Default::default()
+        };
+        let servos = robo.arm.to_servos();
+        let pulses = [servos.base, servos.shoulder, servos.elbow, servos.claw];
+        robo.feedback = Some(Feedback {
+            pulses,
+            ..Default::default()
+        });
+        let start = robo.arm.angles_from_servos(pulses);
+        robo.target_velocity = CordinateVec::new(10., 0., 0.);
+
+        let delta = 0.01;
+        let mut previous = start;
+        let mut ticks = 0;
+        while robo.soft_start.is_some() {
+            robo.tick(delta);
+
+            let angles = robo.arm.angles();
+            assert!((angles.base - previous.base).abs() <= 60. * delta + 1e-9);
+            assert!((angles.shoulder - previous.shoulder).abs() <= 60. * delta + 1e-9);
+            assert!((angles.elbow - previous.elbow).abs() <= 60. * delta + 1e-9);
+            previous = angles;
+
+            // the head doesn't move until the ramp is done
+            assert_eq!(robo.position, CordinateVec::new(40., 30., 120.));
+            assert_eq!(robo.target_velocity, CordinateVec::default());
+
+            ticks += 1;
+            assert!(ticks < 1000, "soft start never finished");
+        }
+
+        robo.update_ik();
+        let expected = robo.arm.angles();
+        assert!((previous.shoulder - expected.shoulder).abs() < 1e-9);
+        assert!((previous.elbow - expected.elbow).abs() < 1e-9);
+        assert_eq!(previous.base, expected.base);
+
+        // normal input works again
+        robo.target_velocity = CordinateVec::new(10., 0., 0.);
+        robo.tick(delta);
+        assert_ne!(robo.position, CordinateVec::new(40., 30., 120.));
+    }
+
+    #[test]
+    pub fn output_rate() {
+        let mut robo = Robot {
+            position: CordinateVec::new(40., 30., 120.),
+            output: OutputRate::new(1, Some(50.)),
+            ..Default::default()
+        };
+        robo.status.interval = None;
+
+        // a second of physics at 200 Hz
+        let mut sent = 0;
+        for _ in 0..200 {
+            if robo.update(0.005).unwrap().transmitted {
+                sent += 1;
+            }
+        }
+        assert_eq!(sent, 50);
+
+        // status queries don't wait for the divider
+        robo.status.requested = true;
+        assert!(!robo.update(0.005).unwrap().transmitted);
+        assert!(!robo.status.requested);
+    }
+
+    #[test]
+    pub fn idle_detach() {
+        // shoulder close to vertical
+        let rest = CordinateVec::new(5., 5., 198.);
+        let mut robo = Robot {
+            position: rest,
+            idle: IdlePolicy {
+                detach_after: 1.,
+                settle: 0.1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        robo.update_ik();
+        // exact in binary so the rest time adds up to exactly a second
+        let delta = 1. / 64.;
+        let sent = |robo: &Robot| robo.connection.stats.sent;
+
+        // resting long enough detaches once and stops driving the servos
+        let mut transmitted = 0;
+        for _ in 0..100 {
+            if robo.update(delta).unwrap().transmitted {
+                transmitted += 1;
+            }
+        }
+        assert_eq!(transmitted, 63);
+        assert_eq!(sent(&robo).detach, 1);
+        assert!(robo.state().detached);
+
+        // input attaches right away, the arm only moves after settling and ramping back
+        robo.command_velocity(CordinateVec::new(5., 5., 0.));
+        assert!(!robo.update(delta).unwrap().transmitted);
+        assert_eq!(sent(&robo).attach, 1);
+        assert_eq!(robo.position, rest);
+
+        let mut ticks = 0;
+        while robo.idle.holding() || robo.soft_start.is_some() {
+            robo.update(delta).unwrap();
+            ticks += 1;
+            assert!(ticks < 1000, "never resumed");
+        }
+        assert_eq!(robo.position, rest);
+        assert!(robo.update(delta).unwrap().transmitted);
+
+        robo.command_velocity(CordinateVec::new(5., 5., 0.));
+        robo.update(delta).unwrap();
+        assert_ne!(robo.position, rest);
+        assert_eq!(sent(&robo).detach, 1);
+    }
+
+    #[test]
+    pub fn idle_needs_rest() {
+        let mut robo = Robot {
+            position: CordinateVec::new(40., 30., 120.),
+            idle: IdlePolicy {
+                detach_after: 1.,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        robo.update_ik();
+
+        // the shoulder leans too far to rest
+        for _ in 0..200 {
+            robo.update(0.01).unwrap();
+        }
+        assert!(!robo.idle.holding());
+
+        // a replay counts as busy, paused or not
+        let mut robo = Robot {
+            position: CordinateVec::new(5., 5., 198.),
+            idle: robo.idle,
+            replay: Some(Replay::new(Recording {
+                samples: vec![Sample {
+                    time: 1000.,
+                    pose: CordinateVec::new(5., 5., 198.),
+                }],
+            })),
+            ..Default::default()
+        };
+        robo.update_ik();
+        for _ in 0..200 {
+            robo.update(0.01).unwrap();
+        }
+        assert!(!robo.idle.holding());
+        assert_eq!(robo.connection.stats.sent.detach, 0);
+    }
+
+    #[test]
+    pub fn tick_report() {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            ..Default::default()
+        };
+        robo.target_position = Some(CordinateVec::new(20.02, 50.02, 50.));
+
+        // close enough and not moving, nothing is sent
+        let report = robo.tick(0.01);
+        assert!(report.target_reached);
+        assert!(!report.ik_failed);
+        assert_eq!(robo.connection.tx_seq, 0);
+
+        // out of reach
+        robo.position = CordinateVec::new(0., 0., 0.);
+        assert!(robo.tick(0.01).ik_failed);
+    }
+
+    #[test]
+    pub fn non_finite_faults() {
+        let robot = || {
+            let mut robo = Robot {
+                position: CordinateVec::new(20., 50., 50.),
+                ..Default::default()
+            };
+            robo.update_ik();
+            robo
+        };
+
+        // each stage holds the last good state and the held pose is what gets sent
+        let mut robo = robot();
+        let angles = robo.arm.angles();
+        robo.target_velocity = CordinateVec::new(f64::NAN, 0., 0.);
+        let report = robo.update(0.01).unwrap();
+        assert_eq!(report.fault, Some(Fault::Velocity));
+        assert!(report.transmitted);
+        assert_eq!(robo.arm.angles(), angles);
+        assert_eq!(robo.position, CordinateVec::new(20., 50., 50.));
+        assert_eq!(robo.target_velocity, CordinateVec::default());
+
+        // the bad target is gone, the next tick is fine again
+        assert_eq!(robo.update(0.01).unwrap().fault, None);
+        assert_eq!(robo.faults, 1);
+
+        let mut robo = robot();
+        robo.position.z = f64::NAN;
+        assert_eq!(robo.tick(0.01).fault, Some(Fault::Position));
+        assert_eq!(robo.arm.angles(), angles);
+
+        let mut robo = robot();
+        assert_eq!(robo.update(f64::NAN).unwrap().fault, Some(Fault::Delta));
+        assert_eq!(robo.arm.angles(), angles);
+        assert_eq!(robo.loop_stats.total, 0.);
+
+        // a non-finite solution counts as no solution
+        let mut robo = robot();
+        robo.upper_arm = f64::NAN;
+        assert!(robo.tick(0.01).ik_failed);
+        assert_eq!(robo.arm.angles(), angles);
+
+        // whatever sets the joints, a non-finite angle is never cast into a pulse width
+        let mut robo = robot();
+        robo.arm.elbow.angle = f64::NAN;
+        assert!(!robo.transmit(0.01).unwrap());
+        robo.arm.elbow.angle = f64::INFINITY;
+        assert!(!robo.transmit(0.01).unwrap());
+        assert_eq!(robo.connection.stats.servo_writes, 0);
+        assert_eq!(robo.faults, 2);
+    }
+
+    #[test]
+    pub fn events() {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            link: LinkPolicy {
+                smoothing: 1.,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // arriving at a goto target
+        robo.command_target(CordinateVec::new(24., 54., 54.));
+        let mut ticks = 0;
+        while robo.target_position.is_some() {
+            robo.update(0.01).unwrap();
+            ticks += 1;
+            assert!(ticks < 1000, "never arrived");
+        }
+        let events = robo.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].event,
+            RobotEvent::TargetReached(CordinateVec::new(24., 54., 54.))
+        );
+
+        // the samples of a replay don't count as arrivals, only its end
+        let recording = Recording {
+            samples: vec![
+                Sample {
+                    time: 0.,
+                    pose: CordinateVec::new(24., 54., 54.),
+                },
+                Sample {
+                    time: 0.5,
+                    pose: CordinateVec::new(26., 54., 54.),
+                },
+            ],
+        };
+        robo.start_replay(&recording, &Transform::default()).unwrap();
+        while robo.replay.is_some() {
+            robo.update(0.01).unwrap();
+        }
+        let events: Vec<_> = robo.take_events().into_iter().map(|event| event.event).collect();
+        assert_eq!(events, [RobotEvent::ReplayFinished]);
+
+        // the arduino goes quiet and comes back
+        robo.last_heard = Some(Instant::now() - Duration::from_secs(5));
+        robo.update(0.01).unwrap();
+        robo.update(0.01).unwrap();
+        robo.last_heard = Some(Instant::now());
+        robo.update(0.01).unwrap();
+        let events: Vec<_> = robo.take_events().into_iter().map(|event| event.event).collect();
+        assert_eq!(events, [RobotEvent::LinkDown, RobotEvent::LinkRestored]);
+        assert_eq!(robo.state().last_event.unwrap().event, RobotEvent::LinkRestored);
+    }
+
+    #[test]
+    pub fn program() {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            cruise_speed: Some(30.),
+            ..Default::default()
+        };
+        robo.arm.claw.angle = 90.;
+        robo.program = Program::parse(
+            r#"{
+                "version": 1,
+                "poses": { "home": { "x": 20, "y": 50, "z": 50 } },
+                "waypoints": [
+                    { "position": { "x": 24, "y": 54, "z": 54 }, "speed": 5, "claw": "close", "dwell": 0.5 },
+                    { "pose": "home", "claw": "open" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        robo.run_program().unwrap();
+        assert_eq!(robo.cruise_speed, Some(5.));
+
+        // the claw closes once there and stays for the dwell before moving on
+        let mut arrived = None;
+        let mut left = None;
+        for tick in 0..2000 {
+            robo.update(0.01).unwrap();
+            let step = robo.program_run.map(|run| run.step);
+            if arrived.is_none() && robo.arm.claw.angle == 0. {
+                arrived = Some(tick);
+                assert_eq!(robo.position, CordinateVec::new(24., 54., 54.));
+            }
+            if left.is_none() && step == Some(1) {
+                left = Some(tick);
+                assert_eq!(robo.cruise_speed, Some(30.));
+            }
+            if step.is_none() {
+                break;
+            }
+        }
+        let dwelt = left.unwrap() - arrived.unwrap();
+        assert!((49..=51).contains(&dwelt), "dwelt {dwelt} ticks");
+        assert_eq!(robo.program_run, None);
+        assert_eq!(robo.position, CordinateVec::new(20., 50., 50.));
+        assert_eq!(robo.arm.claw.angle, 180.);
+        assert_eq!(robo.cruise_speed, Some(30.));
+
+        let events: Vec<_> = robo.take_events().into_iter().map(|event| event.event).collect();
+        assert_eq!(events.last(), Some(&RobotEvent::ProgramFinished));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| matches!(event, RobotEvent::TargetReached(_)))
+                .count(),
+            2
+        );
+
+        // the operator takes over from a running program
+        robo.run_program().unwrap();
+        robo.apply_input(&jogging(1., 0., 0.));
+        assert_eq!(robo.program_run, None);
+        assert_eq!(robo.cruise_speed, Some(30.));
+
+        // nothing runs with a waypoint out of reach
+        robo.program.waypoints[0].position = Some(CordinateVec::new(0., 500., 0.));
+        assert!(matches!(
+            robo.run_program(),
+            Err(ProgramError::Unreachable { step: 0, .. })
+        ));
+        assert_eq!(robo.program_run, None);
+    }
+
+    #[test]
+    pub fn workspace_switch() {
+        let bench = Workspace {
+            keep_out: vec![KeepOut {
+                min: CordinateVec::new(-50., -50., -50.),
+                max: CordinateVec::new(30., 100., 100.),
+            }],
+            max_speed: Some(5.),
+            ..Default::default()
+        };
         let mut robo = Robot {
-            position: CordinateVec::new(0., 0., 0.),
-            target_position: None,
-            velocity: CordinateVec::new(0., 0., 0.),
-            max_velocity: CordinateVec::new(100., 100., 100.),
-            target_velocity: CordinateVec::new(0., 0., 0.),
+            position: CordinateVec::new(25., 60., 50.),
+            workspaces: Workspaces {
+                profiles: [("bench".to_string(), bench)].into_iter().collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // inside the keep out zone, so it first moves out of it
+        robo.select_workspace(Some("bench")).unwrap();
+        assert_eq!(robo.target_position, Some(CordinateVec::new(30., 60., 50.)));
+        assert_eq!(robo.workspaces.active, None);
+
+        let mut ticks = 0;
+        while robo.workspaces.pending.is_some() {
+            // not enforced yet, the head would be stuck on the surface of the zone
+            assert!(robo.workspaces.current().is_none());
+            robo.tick(0.01);
+            ticks += 1;
+            assert!(ticks < 1000, "never got clear");
+        }
+        assert_eq!(robo.workspaces.active.as_deref(), Some("bench"));
+        assert!(robo.workspaces.current().unwrap().allows(robo.position));
+
+        // enforced from now on, the head slides along the zone and the speed is capped
+        robo.command_velocity(CordinateVec::new(-20., 20., 0.));
+        for _ in 0..100 {
+            robo.tick(0.01);
+            assert!(robo.position.x >= 30.);
+        }
+        assert!(robo.position.y > 60.);
+        assert!(robo.velocity.dst() <= 5. + 1e-9);
+    }
+
+    #[test]
+    pub fn envelope_teach_and_enforce() {
+        let mut robo = Robot {
+            position: CordinateVec::new(55., 55., 55.),
+            ..Default::default()
+        };
+        assert!(matches!(
+            robo.set_envelope_mode(EnvelopeMode::Enforce),
+            Err(EnvelopeError::Empty)
+        ));
+
+        // drive around a 30 by 30 patch
+        robo.set_envelope_mode(EnvelopeMode::Learn).unwrap();
+        for x in (50..80).step_by(5) {
+            for y in (50..80).step_by(5) {
+                robo.position = CordinateVec::new(x as f64, y as f64, 55.);
+                robo.tick(0.01);
+            }
+        }
+        assert_eq!(robo.envelope.len(), 9);
+        assert_eq!(robo.state().envelope_voxels, 9);
+
+        // outside when enforcing starts, it's brought in first
+        robo.position = CordinateVec::new(45., 45., 45.);
+        robo.set_envelope_mode(EnvelopeMode::Enforce).unwrap();
+        assert_eq!(robo.target_position, Some(CordinateVec::new(55., 55., 55.)));
+        for _ in 0..1000 {
+            robo.tick(0.01);
+        }
+        assert!(robo.envelope_entered);
+        assert!(robo.envelope.contains(robo.position));
+
+        // pushing out of the side it slides along it to the corner
+        robo.command_velocity(CordinateVec::new(20., 10., 0.));
+        for _ in 0..500 {
+            robo.tick(0.01);
+            assert!(robo.position.x <= 80. && robo.position.y <= 80.);
+        }
+        assert_eq!(robo.position, CordinateVec::new(80., 80., 55.));
+
+        // and can leave once it's off
+        robo.set_envelope_mode(EnvelopeMode::Off).unwrap();
+        for _ in 0..10 {
+            robo.tick(0.01);
+        }
+        assert!(robo.position.x > 80.);
+    }
+
+    #[test]
+    pub fn torque_slows_down() {
+        let run = |torque_limit, position| {
+            let mut robo = Robot {
+                position,
+                torque_limit,
+                ..Default::default()
+            };
+            robo.update_ik();
+            robo.command_velocity(CordinateVec::new(50., 50., 0.));
+            robo.tick(0.1);
+            (robo.torque_scale, robo.velocity)
+        };
+        let limit = Some(TorqueLimit {
+            enabled: true,
+            shoulder_rating: 20.,
+            elbow_rating: 20.,
+            ..Default::default()
+        });
+
+        // nearly upright the policy doesn't change anything
+        let near = CordinateVec::new(10., 10., 190.);
+        assert_eq!(run(limit, near), run(None, near));
+        assert_eq!(run(limit, near).0, 1.);
+
+        // far out it accelerates slower
+        let far = CordinateVec::new(100., 100., 30.);
+        let (scale, velocity) = run(limit, far);
+        assert!(scale < 1.);
+        assert_approx_eq!(velocity, run(None, far).1 * scale);
+    }
+
+    #[test]
+    pub fn stall_detection() {
+        let start = JointAngles {
+            base: 90.,
+            shoulder: 40.,
+            elbow: 90.,
+            claw: 90.,
+        };
+        let recording = Recording {
+            samples: vec![
+                Sample {
+                    time: 0.,
+                    pose: start,
+                },
+                Sample {
+                    time: 2.,
+                    pose: JointAngles {
+                        shoulder: 140.,
+                        ..start
+                    },
+                },
+            ],
+        };
+
+        let mut robo = Robot {
+            stall: Some(StallDetector::new(StallConfig::default())),
+            ..Default::default()
+        };
+        robo.arm.interpolate(start, start, 0.);
+        let mut sim = Simulator::new(&SimConfig::default(), robo.arm.to_servos());
+        robo.start_joint_replay(&recording).unwrap();
+
+        // the shoulder gets stuck halfway through the move
+        let mut detected = None;
+        for tick in 1..=150 {
+            let report = robo.tick(0.01);
+            if let Some(joint) = report.stalled {
+                assert_eq!(joint, "shoulder");
+                assert!(tick > 50, "stalled while following at tick {tick}");
+                detected = Some(tick);
+                break;
+            }
+
+            sim.command(robo.arm.to_servos());
+            if tick == 50 {
+                sim.servos[1].stuck = true;
+            }
+            sim.step(0.01);
+            robo.dispatch(Frame::Feedback(sim.feedback()), Instant::now());
+        }
+        let latency = (detected.expect("never detected") - 50) as f64 * 0.01;
+        assert!((0.3..=0.45).contains(&latency), "detected after {latency}s");
+        assert_eq!(robo.stalled, Some("shoulder"));
+
+        // the replay holds until resumed
+        let held = robo.arm.angles();
+        for _ in 0..20 {
+            assert_eq!(robo.tick(0.01).stalled, None);
+        }
+        assert_eq!(robo.arm.angles(), held);
+        assert!(robo.joint_replay.is_some());
+
+        robo.resume();
+        robo.tick(0.01);
+        assert!(robo.arm.shoulder.angle > held.shoulder);
+    }
+
+    #[test]
+    pub fn stall_pauses_target() {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            stalled: Some("base"),
+            ..Default::default()
+        };
+        robo.command_target(CordinateVec::new(40., 50., 50.));
+        for _ in 0..50 {
+            robo.tick(0.01);
+        }
+        assert_eq!(robo.position, CordinateVec::new(20., 50., 50.));
+
+        robo.resume();
+        robo.tick(0.01);
+        assert!(robo.position.x > 20.);
+    }
+
+    /// Robot with the arm at rest in the middle of every joint's range
+    fn resting() -> (Robot, Simulator) {
+        let mut robo = Robot::default();
+        let middle = JointAngles {
+            base: 90.,
+            shoulder: 90.,
+            elbow: 90.,
+            claw: 90.,
+        };
+        robo.arm.interpolate(middle, middle, 0.);
+        robo.follow_joints();
+        let sim = Simulator::new(&SimConfig::default(), robo.arm.to_servos());
+        (robo, sim)
+    }
+
+    /// Tick at 100Hz with the shoulder blocked by end stops at these pulse widths
+    fn search_step(robo: &mut Robot, sim: &mut Simulator, stops: (f64, f64)) -> UpdateReport {
+        let report = robo.tick(0.01);
+        sim.command(robo.arm.to_servos());
+
+        let servo = &mut sim.servos[1];
+        servo.stuck = (servo.position <= stops.0 && servo.target < servo.position)
+            || (servo.position >= stops.1 && servo.target > servo.position);
+        sim.step(0.01);
+        robo.dispatch(Frame::Feedback(sim.feedback()), Instant::now());
+        report
+    }
+
+    #[test]
+    pub fn limit_search() {
+        let (mut robo, mut sim) = resting();
+        // end stops at 30° and 150° of the default mapping
+        let stops = (250. + 2150. / 6., 250. + 2150. * 5. / 6.);
+
+        assert_eq!(
+            robo.confirm_limit_search("shoulder"),
+            Err(LimitError::NotWaiting("shoulder".to_string()))
+        );
+        assert_eq!(
+            robo.find_limits("wrist"),
+            Err(LimitError::UnknownJoint("wrist".to_string()))
+        );
+        robo.find_limits("shoulder").unwrap();
+        assert_eq!(robo.find_limits("elbow"), Err(LimitError::Busy));
+        assert!(robo.confirm_limit_search("elbow").is_err());
+
+        // nothing moves until confirmed
+        for _ in 0..100 {
+            search_step(&mut robo, &mut sim, stops);
+        }
+        assert_eq!(robo.arm.shoulder.angle, 90.);
+        robo.confirm_limit_search("shoulder").unwrap();
+
+        let mut found = None;
+        for _ in 0..10_000 {
+            if let Some(limits) = search_step(&mut robo, &mut sim, stops).limits {
+                found = Some(limits);
+                break;
+            }
+        }
+        assert_eq!(found, Some(Ok("shoulder")));
+        assert_eq!(robo.limit_finder, None);
+
+        // the margin inside of where the servo stopped, within the feedback resolution
+        let range = robo.joint_limits.shoulder.unwrap();
+        assert!((range.min - 33.).abs() < 0.5, "{range:?}");
+        assert!((range.max - 147.).abs() < 0.5, "{range:?}");
+        assert_eq!(robo.joint_limits.elbow, None);
+
+        // the head is where the joints put it, and isn't moved out of the limits
+        let at = |shoulder| CordinateVec::forward_kinematics(120., shoulder, 120., 100., 100.);
+        assert_eq!(robo.position, CordinateVec::forward_kinematics(90., range.max, 90., 100., 100.));
+        assert!(robo.in_reach(at(60.)));
+        assert!(!robo.in_reach(at(31.)));
+    }
+
+    #[test]
+    pub fn limit_search_aborts() {
+        let stops = (2150. / 6., 2150. * 5. / 6.);
+        let search = |robo: &mut Robot, sim: &mut Simulator| {
+            robo.find_limits("shoulder").unwrap();
+            robo.confirm_limit_search("shoulder").unwrap();
+            for _ in 0..100 {
+                search_step(robo, sim, stops);
+            }
+            assert!(robo.arm.shoulder.angle < 90.);
+        };
+
+        // any input
+        let (mut robo, mut sim) = resting();
+        search(&mut robo, &mut sim);
+        robo.apply_input(&jogging(0., 0., 0.5));
+        let report = search_step(&mut robo, &mut sim, stops);
+        assert_eq!(report.limits, Some(Err(LimitAbort::Interrupted)));
+        assert_eq!(robo.joint_limits, JointLimits::default());
+
+        // a goto, which isn't followed until the search is done
+        let (mut robo, mut sim) = resting();
+        search(&mut robo, &mut sim);
+        robo.command_target(CordinateVec::new(0., 50., 150.));
+        let report = search_step(&mut robo, &mut sim, stops);
+        assert_eq!(report.limits, Some(Err(LimitAbort::Interrupted)));
+
+        // the emergency stop
+        let (mut robo, mut sim) = resting();
+        search(&mut robo, &mut sim);
+        robo.emergency_stop();
+        assert_eq!(robo.limit_finder, None);
+
+        // losing the feedback, the shoulder stops where it was last reported
+        let (mut robo, mut sim) = resting();
+        search(&mut robo, &mut sim);
+        let reported = robo
+            .arm
+            .angles_from_servos(sim.feedback().pulses)
+            .shoulder;
+        let report = (0..100)
+            .find_map(|_| robo.tick(0.01).limits)
+            .unwrap();
+        assert_eq!(report, Err(LimitAbort::NoFeedback));
+        assert_eq!(robo.arm.shoulder.angle, reported);
+
+        // and the operator
+        let (mut robo, mut sim) = resting();
+        search(&mut robo, &mut sim);
+        assert!(robo.abort_limit_search());
+        assert!(!robo.abort_limit_search());
+        assert_eq!(robo.limit_finder, None);
+    }
+
+    #[test]
+    pub fn integration_rates() {
+        // a second of flat out acceleration along x, from standing
+        let accelerate = |deltas: &[f64]| {
+            let mut robo = Robot {
+                position: CordinateVec::new(0., 0., 50.),
+                target_velocity: CordinateVec::new(1000., 0., 0.),
+                acceleration: 100.,
+                ..Default::default()
+            };
+            for &delta in deltas {
+                robo.tick(delta);
+            }
+            (robo.position, robo.velocity)
+        };
+
+        // x = a * t^2 / 2 at every rate
+        let irregular: Vec<f64> = [0.003, 0.017, 0.041, 0.009, 0.03]
+            .into_iter()
+            .cycle()
+            .take(10)
+            .chain([0.3, 0.5])
+            .collect();
+        for deltas in [vec![0.02; 50], vec![0.005; 200], irregular] {
+            assert!((deltas.iter().sum::<f64>() - 1.).abs() < 1e-12);
+
+            let (position, velocity) = accelerate(&deltas);
+            assert!((position.x - 50.).abs() < 1e-9, "{position:?}");
+            assert!((velocity.x - 100.).abs() < 1e-9, "{velocity:?}");
+            assert_eq!((position.y, position.z), (0., 50.));
+        }
+
+        // constant velocity and standing still are exact too
+        let start = CordinateVec::new(1., 2., 3.);
+        let velocity = CordinateVec::new(4., 5., 6.);
+        assert_eq!(integrate(start, velocity, velocity, 0.5), CordinateVec::new(3., 4.5, 6.));
+        assert_eq!(integrate(start, CordinateVec::default(), CordinateVec::default(), 1.), start);
+    }
+
+    #[test]
+    pub fn retarget() {
+        // a new target at several points of a move along x, accelerating, cruising and braking
+        for ticks in [10, 50, 150, 195] {
+            let mut robo = Robot {
+                position: CordinateVec::new(0., 100., 50.),
+                acceleration: 100.,
+                cruise_speed: Some(30.),
+                ..Default::default()
+            };
+            robo.command_target(CordinateVec::new(60., 100., 50.));
+            for _ in 0..ticks {
+                robo.tick(0.01);
+            }
+            assert!(robo.velocity.x > 1., "{ticks}: {:?}", robo.velocity);
+
+            let target = CordinateVec::new(20., 140., 80.);
+            robo.retarget(target);
+            assert!(robo.retargeting);
+
+            // the velocity carries on, no axis changes faster than the acceleration allows
+            let mut previous = robo.velocity;
+            let mut reached = false;
+            for _ in 0..1000 {
+                robo.tick(0.01);
+                let change = robo.velocity - previous;
+                for axis in [change.x, change.y, change.z] {
+                    assert!(axis.abs() <= 1. + 1e-9, "{ticks}: {change:?}");
+                }
+                previous = robo.velocity;
+                if robo.target_position.is_none() {
+                    reached = true;
+                    break;
+                }
+            }
+            assert!(reached, "{ticks}: {:?} {:?}", robo.position, robo.velocity);
+            assert_eq!(robo.position, target);
+            assert!(!robo.retargeting);
+        }
+    }
+
+    #[test]
+    pub fn retarget_ahead() {
+        // further along the way it's going, nothing to brake away
+        let mut robo = Robot {
+            position: CordinateVec::new(0., 100., 50.),
             acceleration: 100.,
-            arm: Arm::default(),
+            cruise_speed: Some(30.),
+            ..Default::default()
+        };
+        robo.command_target(CordinateVec::new(40., 100., 50.));
+        for _ in 0..50 {
+            robo.tick(0.01);
+        }
+        let velocity = robo.velocity;
+        robo.retarget(CordinateVec::new(80., 100., 50.));
+        robo.tick(0.01);
+        assert!(!robo.retargeting);
+        assert!(robo.velocity.x >= velocity.x);
+
+        // at rest there is nothing to carry on
+        let mut robo = Robot::default();
+        robo.retarget(CordinateVec::new(10., 100., 50.));
+        assert!(!robo.retargeting);
+
+        // a goto takes over from a replay instead of being overwritten by it
+        robo.replay = Some(Replay::new(Recording {
+            samples: vec![Sample {
+                time: 1.,
+                pose: CordinateVec::new(0., 50., 50.),
+            }],
+        }));
+        robo.retarget(CordinateVec::new(10., 100., 50.));
+        assert!(robo.replay.is_none());
+    }
+
+    #[test]
+    fn reachable() {
+        let mut robo = Robot {
             upper_arm: 100.,
-            lower_arm: 100.,
-            claw_open: false,
-            connection: Connection::default(),
+            lower_arm: 60.,
+            position: CordinateVec::new(60., 60., 60.),
+            ..Default::default()
+        };
+        assert_eq!(robo.min_reach(), 40.);
+
+        // between folded up and stretched out
+        assert!(robo.is_reachable(CordinateVec::new(0., 40., 0.)));
+        assert!(robo.is_reachable(CordinateVec::new(0., 0., 160.)));
+        assert!(!robo.is_reachable(CordinateVec::new(10., 20., 10.)));
+        assert!(!robo.is_reachable(CordinateVec::new(0., 160.1, 0.)));
+
+        // nearest points are on the shells, the origin goes straight up
+        let near = robo.nearest_reachable(CordinateVec::new(0., 10., 0.));
+        assert_approx_eq!(near, CordinateVec::new(0., 40., 0.));
+        let far = robo.nearest_reachable(CordinateVec::new(0., 0., -300.));
+        assert_approx_eq!(far, CordinateVec::new(0., 0., -160.));
+        assert_eq!(
+            robo.nearest_reachable(CordinateVec::default()),
+            CordinateVec::new(0., 40., 0.)
+        );
+
+        // and above the floor of the workspace
+        robo.workspaces = Workspaces {
+            profiles: [(
+                "bench".to_string(),
+                Workspace {
+                    floor: Some(0.),
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            active: Some("bench".to_string()),
+            ..Default::default()
+        };
+        assert!(!robo.is_reachable(CordinateVec::new(0., 100., -1.)));
+        let floor = robo.nearest_reachable(CordinateVec::new(0., 0., -300.));
+        assert!(robo.is_reachable(floor), "{floor:?}");
+        assert_eq!(floor.z, 0.);
+
+        // targets it can't get to aren't chased
+        assert!(!robo.command_target(CordinateVec::new(0., 10., 10.)));
+        assert_eq!(robo.target_position, None);
+        assert!(robo.command_target(CordinateVec::new(0., 100., 10.)));
+        assert!(!robo.retarget(CordinateVec::new(0., 100., -10.)));
+        assert_eq!(robo.target_position, Some(CordinateVec::new(0., 100., 10.)));
+    }
+
+    #[test]
+    fn recorded_trajectories() {
+        // positions every 15 ticks recorded before the distance and direction to the target
+        // were taken with distance_to and direction_to
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            target_position: Some(CordinateVec::new(-60., 120., 20.)),
+            ..Default::default()
+        };
+        let recorded = [
+            CordinateVec::new(15.499999999999998, 54.50000000000001, 45.49999999999999),
+            CordinateVec::new(2., 68., 36.381716516226106),
+            CordinateVec::new(-20.204038272585255, 88.35442679391483, 29.643965934057004),
+            CordinateVec::new(-40.608199537218084, 104.57972583515125, 24.699317587681573),
+        ];
+        for expected in recorded {
+            for _ in 0..15 {
+                robo.tick(0.02);
+            }
+            assert_approx_eq!(robo.position, expected);
+        }
+
+        // turned towards another target on the way
+        let mut robo = Robot {
+            position: CordinateVec::new(0., 150., 40.),
+            target_position: Some(CordinateVec::new(80., 80., 80.)),
+            ..Default::default()
+        };
+        let recorded = [
+            CordinateVec::new(3.060000000000001, 146.94, 43.06),
+            CordinateVec::new(0.3600000000000007, 143.88, 40.36),
+            CordinateVec::new(-11.340000000000002, 132.17999999999998, 29.257747157671375),
+            CordinateVec::new(-26.493907556270948, 115.40132536248097, 19.03300000499372),
+        ];
+        for (tick, expected) in (1..=60).filter(|tick| tick % 15 == 0).zip(recorded) {
+            for tick in tick - 14..=tick {
+                if tick == 10 {
+                    robo.retarget(CordinateVec::new(-40., 100., 10.));
+                }
+                robo.tick(0.02);
+            }
+            assert_approx_eq!(robo.position, expected);
+        }
+    }
+
+    #[test]
+    pub fn substeps() {
+        // distance to the target after every tick of an uneven loop
+        let approach = |max_substep| {
+            let target = CordinateVec::new(45., 75., 75.);
+            let mut robo = Robot {
+                position: CordinateVec::new(20., 50., 50.),
+                target_position: Some(target),
+                acceleration: 5000.,
+                max_substep,
+                ..Default::default()
+            };
+
+            (0..30)
+                .map(|tick| {
+                    robo.tick(if tick % 2 == 0 { 0.05 } else { 0.02 });
+                    (target - robo.position).dst()
+                })
+                .collect::<Vec<_>>()
+        };
+        let growth = |distances: &[f64]| {
+            distances
+                .windows(2)
+                .map(|pair| pair[1] - pair[0])
+                .fold(0., f64::max)
+        };
+
+        // a whole tick per step swings back and forth. The long tick leaves the head just past
+        // the target going about 260/s on every axis, and the short one after it can only take
+        // 5000 * 0.02 = 100/s off that. So the head carries on (260 + 160) / 2 * 0.02 = 4.2 on
+        // every axis, sqrt(3) * 4.2 = 7.3 further from the target
+        let swing = growth(&approach(None));
+        assert!(swing > 5., "{swing}");
+
+        let distances = approach(Some(0.001));
+        assert_eq!(growth(&distances), 0.);
+        assert_eq!(*distances.last().unwrap(), 0.);
+    }
+
+    #[test]
+    pub fn long_stall() {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            target_position: Some(CordinateVec::new(45., 75., 75.)),
+            acceleration: 5000.,
+            max_substep: Some(0.001),
+            ..Default::default()
+        };
+        assert_eq!(robo.substeps(0.0105), (11, 0.0105 / 11.));
+        assert_eq!(robo.substeps(5.), (MAX_SUBSTEPS, 0.001));
+
+        // a multi second tick only runs the capped substeps, the head is still on its way
+        robo.tick(5.);
+        assert!(robo.target_position.is_some());
+        assert!(robo.position.x > 20.);
+        assert!(robo.position.x.is_finite());
+    }
+
+    #[test]
+    pub fn tick_does_not_allocate() {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            target_position: Some(CordinateVec::new(60., 40., 30.)),
+            ..Default::default()
+        };
+        let recording = Recording {
+            samples: vec![
+                Sample {
+                    time: 0.,
+                    pose: CordinateVec::new(20., 50., 50.),
+                },
+                Sample {
+                    time: 10.,
+                    pose: CordinateVec::new(40., 60., 30.),
+                },
+            ],
+        };
+
+        let allocations = alloc_counter::count(|| {
+            for _ in 0..100 {
+                robo.tick(0.01);
+            }
+        });
+        assert_eq!(allocations, 0);
+
+        robo.start_replay(&recording, &Transform::default()).unwrap();
+        let allocations = alloc_counter::count(|| {
+            for _ in 0..100 {
+                robo.tick(0.01);
+            }
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    pub fn transmit_does_not_allocate() {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            target_position: Some(CordinateVec::new(60., 40., 30.)),
+            ..Default::default()
+        };
+        // the first status request and servo message size the buffers they go through
+        for _ in 0..10 {
+            robo.tick(0.01);
+            robo.transmit(0.01).unwrap();
+        }
+
+        let mut sent = 0;
+        let allocations = alloc_counter::count(|| {
+            for _ in 0..100 {
+                robo.tick(0.01);
+                sent += robo.transmit(0.01).unwrap() as u32;
+            }
+        });
+        assert_eq!(allocations, 0);
+        assert_eq!(sent, 100);
+
+        // the angle based encodings are written straight into the buffer as well
+        robo.connection.servo_encoding = ServoEncoding::DegreesU8;
+        robo.transmit(0.01).unwrap();
+        let allocations = alloc_counter::count(|| {
+            for _ in 0..100 {
+                robo.tick(0.01);
+                robo.transmit(0.01).unwrap();
+            }
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    /// Counts allocations made by the current thread
+    #[allow(unsafe_code)]
+    mod alloc_counter {
+        use std::{
+            alloc::{GlobalAlloc, Layout, System},
+            cell::Cell,
+        };
+
+        struct Counting;
+
+        thread_local! {
+            static COUNT: Cell<usize> = const { Cell::new(0) };
+        }
+
+        unsafe impl GlobalAlloc for Counting {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                let _ = COUNT.try_with(|count| count.set(count.get() + 1));
+                System.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static ALLOCATOR: Counting = Counting;
+
+        /// Number of allocations made while running `f`
+        pub fn count(f: impl FnOnce()) -> usize {
+            let before = COUNT.with(Cell::get);
+            f();
+            COUNT.with(Cell::get) - before
+        }
+    }
+
+    fn jogging(x: f64, y: f64, z: f64) -> InputState {
+        InputState {
+            jog: CordinateVec::new(x, y, z),
+            connected: true,
+            ..Default::default()
+        }
+    }
+
+    /// Buttons that were just pressed down
+    fn pressing(buttons: Buttons) -> InputState {
+        InputState {
+            held: buttons,
+            pressed: buttons,
+            connected: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn min_z() {
+        let mut robo = Robot {
+            position: CordinateVec::new(40., 60., 10.),
+            min_z: Some(0.),
+            ..Default::default()
+        };
+
+        // down and out at once
+        robo.command_velocity(CordinateVec::new(5., 5., -10.));
+        for _ in 0..200 {
+            robo.tick(0.01);
+            assert!(robo.position.z >= 0.);
+        }
+
+        // pinned to the floor, still going out along it with nothing pushing down
+        assert_eq!(robo.position.z, 0.);
+        assert_eq!(robo.velocity.z, 0.);
+        let (x, y) = (robo.position.x, robo.position.y);
+        for _ in 0..10 {
+            robo.tick(0.01);
+        }
+        assert!((robo.position.x - x - 0.5).abs() < 1e-9, "{:?}", robo.position);
+        assert!((robo.position.y - y - 0.5).abs() < 1e-9);
+
+        // a target below it is raised onto it
+        assert!(robo.command_target(CordinateVec::new(50., 50., -20.)));
+        assert_eq!(robo.target_position, Some(CordinateVec::new(50., 50., 0.)));
+    }
+
+    #[test]
+    fn jog_into_the_shoulder() {
+        let mut robo = Robot {
+            upper_arm: 100.,
+            lower_arm: 60.,
+            position: CordinateVec::new(0., 100., 20.),
+            max_velocity: CordinateVec::new(50., 50., 50.),
+            ..Default::default()
+        };
+        // folded up, that short a lower arm leans the upper one back round past 180°
+        robo.arm.shoulder.max = 360.;
+
+        // straight at the base, then on through it
+        robo.apply_input(&jogging(0., -1., -0.2));
+        for _ in 0..500 {
+            assert!(!robo.tick(0.01).ik_failed, "at {:?}", robo.position);
+            assert!(robo.position.dst() >= 40. - 1e-9);
+        }
+
+        // sliding round it instead of stopping dead
+        assert!(robo.position.y < 0., "{:?}", robo.position);
+    }
+
+    #[test]
+    pub fn jog_input() {
+        let mut robo = Robot {
+            max_velocity: CordinateVec::new(10., 20., 30.),
+            ..Default::default()
+        };
+
+        robo.apply_input(&jogging(0.5, -1., 1.));
+        assert_eq!(robo.target_velocity, CordinateVec::new(5., -20., 30.));
+
+        // letting go stops
+        robo.apply_input(&jogging(0., 0., 0.));
+        assert_eq!(robo.target_velocity, CordinateVec::default());
+
+        // but leaving the sticks alone doesn't cancel a goto
+        robo.command_target(CordinateVec::new(50., 50., 50.));
+        robo.apply_input(&jogging(0., 0., 0.));
+        assert!(robo.target_position.is_some());
+
+        // and neither does losing the controller while not jogging, unlike while jogging
+        robo.apply_input(&InputState::default());
+        assert!(robo.target_position.is_some());
+        robo.apply_input(&jogging(1., 0., 0.));
+        robo.apply_input(&InputState {
+            connected: false,
+            ..jogging(1., 0., 0.)
+        });
+        assert_eq!(robo.target_velocity, CordinateVec::default());
+    }
+
+    #[test]
+    pub fn button_input() {
+        let mut robo = Robot::default();
+        let mirror = Buttons {
+            mirror: true,
+            ..Default::default()
+        };
+
+        // only a press toggles, holding doesn't toggle again
+        robo.apply_input(&pressing(mirror));
+        assert!(robo.mirror);
+        robo.apply_input(&InputState {
+            pressed: Buttons::default(),
+            ..pressing(mirror)
+        });
+        assert!(robo.mirror);
+
+        robo.apply_input(&pressing(Buttons {
+            status: true,
+            ..Default::default()
+        }));
+        assert!(robo.status.requested);
+    }
+
+    fn stop_button() -> InputState {
+        pressing(Buttons {
+            emergency_stop: true,
+            ..Default::default()
+        })
+    }
+
+    /// Robot moving along y at `speed` through the middle of its reach
+    fn moving(speed: f64) -> Robot {
+        let mut robo = Robot {
+            position: CordinateVec::new(60., -80., 100.),
+            velocity: CordinateVec::new(0., speed, 0.),
+            target_velocity: CordinateVec::new(0., speed, 0.),
+            max_substep: Some(0.001),
+            ..Default::default()
         };
+        robo.status.interval = None;
+        robo.update_ik();
+        robo
+    }
+
+    #[test]
+    pub fn emergency_stop_brakes() {
+        for speed in [10., 50., 100., 140.] {
+            let mut robo = moving(speed);
+            let start = robo.position;
+            robo.apply_input(&stop_button());
+
+            let mut stopped = None;
+            for _ in 0..100 {
+                // the sticks are ignored while stopping
+                robo.apply_input(&jogging(1., 1., 1.));
+                let report = robo.update(0.01).unwrap();
+                if report.stopped.is_some() {
+                    stopped = report.stopped;
+                    break;
+                }
+                assert!(report.transmitted);
+            }
+
+            // stops within speed^2 / 1000 units, a lot shorter than at the normal acceleration
+            assert_eq!(stopped, Some(StopStage::Halted), "from {speed}");
+            assert_eq!(robo.velocity, CordinateVec::default());
+            let travelled = (robo.position - start).dst();
+            assert!(
+                (travelled - speed.powi(2) / 1000.).abs() < 0.1,
+                "{travelled} from {speed}"
+            );
+            assert_eq!(robo.connection.stats.sent.detach, 0);
+            assert!(matches!(
+                robo.take_events().last().unwrap().event,
+                RobotEvent::EmergencyStopped(StopStage::Halted)
+            ));
+
+            // and holds the pose until released
+            let held = robo.position;
+            robo.command_target(CordinateVec::new(0., 0., 150.));
+            assert!(robo.update(0.01).unwrap().transmitted);
+            assert_eq!(robo.position, held);
+            assert_eq!(robo.state().emergency_stop, Some(StopStage::Halted));
+
+            // the jog held through the stop doesn't move it after the release either
+            assert!(robo.release_emergency_stop());
+            robo.apply_input(&jogging(1., 1., 1.));
+            robo.update(0.01).unwrap();
+            assert_eq!(robo.position, held);
+            robo.apply_input(&jogging(0., 0., 1.));
+            robo.update(0.01).unwrap();
+            assert_ne!(robo.position, held);
+        }
+    }
+
+    #[test]
+    pub fn emergency_stop_escalates() {
+        // too fast to stop within 20 units at 500 units/s^2
+        for speed in [150., 300., 1000.] {
+            let mut robo = moving(speed);
+            let start = robo.position;
+            robo.emergency_stop();
+
+            let mut ticks = 0;
+            let stopped = loop {
+                let report = robo.update(0.01).unwrap();
+                if let Some(stopped) = report.stopped {
+                    break stopped;
+                }
+                ticks += 1;
+                assert!(ticks < 100);
+            };
+
+            assert_eq!(stopped, StopStage::Detached, "from {speed}");
+            assert!((robo.position - start).dst() <= 20. + speed * 0.001);
+            assert_eq!(robo.connection.stats.sent.detach, 1);
+            assert!(robo.state().detached);
+
+            // nothing is sent to the limp servos
+            assert!(!robo.update(0.01).unwrap().transmitted);
+        }
+
+        // the time budget catches a slow stop
+        let mut robo = moving(100.);
+        robo.estop.config.deceleration = 100.;
+        robo.estop.config.max_distance = f64::INFINITY;
+        robo.emergency_stop();
+        for _ in 0..49 {
+            assert_eq!(robo.update(0.01).unwrap().stopped, None);
+        }
+        // half a second in, give or take the substeps adding up
+        let stopped = [robo.update(0.01).unwrap(), robo.update(0.01).unwrap()];
+        assert!(stopped
+            .iter()
+            .any(|report| report.stopped == Some(StopStage::Detached)));
+    }
+
+    #[test]
+    pub fn emergency_stop_second_press() {
+        let mut robo = moving(100.);
+        robo.command_target(CordinateVec::new(0., 0., 150.));
+        robo.apply_input(&stop_button());
+        assert_eq!(robo.target_position, None);
+        robo.update(0.01).unwrap();
+        assert_eq!(robo.state().emergency_stop, Some(StopStage::Braking));
+
+        // detaches on the next transmit without braking any further
+        robo.apply_input(&stop_button());
+        let position = robo.position;
+        assert!(!robo.update(0.01).unwrap().transmitted);
+        assert_eq!(robo.connection.stats.sent.detach, 1);
+        assert_eq!(robo.position, position);
+        assert_eq!(robo.velocity, CordinateVec::default());
 
-        assert_eq!(0., robo.parse_gamepad_axis(0.1, 0.2));
-        assert_eq!(0., robo.parse_gamepad_axis(0.2, 0.2));
-        assert_eq!(1., robo.parse_gamepad_axis(1., 0.2));
+        // after the release input attaches the servos again like after resting
+        assert!(robo.release_emergency_stop());
+        robo.update(0.01).unwrap();
+        assert!(robo.idle.holding());
+        robo.command_velocity(CordinateVec::new(5., 0., 0.));
+        robo.update(0.01).unwrap();
+        assert_eq!(robo.connection.stats.sent.attach, 1);
     }
 }
@@ -0,0 +1,238 @@
+use std::{fmt, fs, io, path::Path};
+
+use super::arm::{Arm, JointAngles};
+
+/// Adds up how far every joint turned, to keep an eye on servo wear
+///
+/// Travel is counted in degrees on the commanded angles clamped to each joint's range, which
+/// is what the servo actually does. Angles never wrap since the servos can't, a jump from one
+/// end of the range to the other is travel across the whole range
+#[derive(Debug, Clone, PartialEq)]
+pub struct Odometer {
+    /// Travel since the controller started
+    pub session: JointAngles,
+
+    /// Travel of earlier sessions, loaded from the state file
+    pub previous: JointAngles,
+
+    /// Warn once a joint travelled this far in one session, infinite for no warning
+    pub warn_after: JointAngles,
+
+    /// Angles seen last, `None` before the first
+    last: Option<JointAngles>,
+
+    /// Joints that already passed their warning threshold
+    warned: [bool; 4],
+}
+
+#[derive(Debug)]
+pub enum OdometerError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl Odometer {
+    /// Travel of all sessions, this one included
+    pub fn total(&self) -> JointAngles {
+        JointAngles {
+            base: self.previous.base + self.session.base,
+            shoulder: self.previous.shoulder + self.session.shoulder,
+            elbow: self.previous.elbow + self.session.elbow,
+            claw: self.previous.claw + self.session.claw,
+        }
+    }
+
+    /// Add the travel to the angles the arm is commanded to now
+    ///
+    /// # Returns
+    /// True if a joint passed its warning threshold, see [`Odometer::worn`]
+    pub fn record(&mut self, arm: &Arm) -> bool {
+        let angles = arm.clamped_angles();
+
+        if let Some(last) = self.last {
+            self.session.base += (angles.base - last.base).abs();
+            self.session.shoulder += (angles.shoulder - last.shoulder).abs();
+            self.session.elbow += (angles.elbow - last.elbow).abs();
+            self.session.claw += (angles.claw - last.claw).abs();
+        }
+        self.last = Some(angles);
+
+        let passed = [
+            self.session.base >= self.warn_after.base,
+            self.session.shoulder >= self.warn_after.shoulder,
+            self.session.elbow >= self.warn_after.elbow,
+            self.session.claw >= self.warn_after.claw,
+        ];
+
+        let mut newly = false;
+        for (warned, passed) in self.warned.iter_mut().zip(passed) {
+            newly |= passed && !*warned;
+            *warned |= passed;
+        }
+        newly
+    }
+
+    /// Names of the joints that passed their warning threshold
+    pub fn worn(&self) -> impl Iterator<Item = &'static str> + '_ {
+        ["base", "shoulder", "elbow", "claw"]
+            .into_iter()
+            .zip(self.warned)
+            .filter_map(|(name, warned)| warned.then_some(name))
+    }
+
+    /// Read the travel of earlier sessions from a state file
+    ///
+    /// A missing file is a fresh arm and loads as no travel
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), OdometerError> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(OdometerError::Io(err)),
+        };
+
+        self.previous = serde_json::from_str(&data).map_err(OdometerError::Parse)?;
+        Ok(())
+    }
+
+    /// Write the travel of all sessions to a state file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), OdometerError> {
+        let data = serde_json::to_string_pretty(&self.total()).map_err(OdometerError::Parse)?;
+        fs::write(path, data).map_err(OdometerError::Io)
+    }
+}
+
+impl Default for Odometer {
+    fn default() -> Self {
+        Self {
+            session: JointAngles::default(),
+            previous: JointAngles::default(),
+            warn_after: JointAngles {
+                base: f64::INFINITY,
+                shoulder: f64::INFINITY,
+                elbow: f64::INFINITY,
+                claw: f64::INFINITY,
+            },
+            last: None,
+            warned: [false; 4],
+        }
+    }
+}
+
+impl fmt::Display for OdometerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OdometerError::Io(err) => write!(f, "{err}"),
+            OdometerError::Parse(err) => write!(f, "invalid state file: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn arm(base: f64, shoulder: f64, elbow: f64, claw: f64) -> Arm {
+        let mut arm = Arm::default();
+        arm.base.angle = base;
+        arm.shoulder.angle = shoulder;
+        arm.elbow.angle = elbow;
+        arm.claw.angle = claw;
+        arm
+    }
+
+    #[test]
+    fn accumulate() {
+        let mut odometer = Odometer::default();
+
+        // the first pose is where counting starts
+        odometer.record(&arm(90., 90., 90., 0.));
+        assert_eq!(odometer.session, JointAngles::default());
+
+        odometer.record(&arm(100., 80., 90., 0.));
+        odometer.record(&arm(90., 80., 90., 45.));
+        assert_eq!(
+            odometer.session,
+            JointAngles {
+                base: 20.,
+                shoulder: 10.,
+                elbow: 0.,
+                claw: 45.,
+            }
+        );
+    }
+
+    #[test]
+    fn clamp_and_wrap() {
+        let mut odometer = Odometer::default();
+
+        // commanded past the end of the range, the servo stops at 180
+        odometer.record(&arm(170., 10., 0., 0.));
+        odometer.record(&arm(200., -30., 0., 0.));
+        assert_eq!(odometer.session.base, 10.);
+        assert_eq!(odometer.session.shoulder, 10.);
+
+        // going back from past the end only counts from the end
+        odometer.record(&arm(170., 10., 0., 0.));
+        assert_eq!(odometer.session.base, 20.);
+        assert_eq!(odometer.session.shoulder, 20.);
+
+        // no wrapping, the servo goes all the way around
+        odometer.record(&arm(0., 10., 0., 0.));
+        odometer.record(&arm(180., 10., 0., 0.));
+        assert_eq!(odometer.session.base, 20. + 170. + 180.);
+    }
+
+    #[test]
+    fn warn_once() {
+        let mut odometer = Odometer {
+            warn_after: JointAngles {
+                elbow: 30.,
+                ..Odometer::default().warn_after
+            },
+            ..Default::default()
+        };
+
+        assert!(!odometer.record(&arm(0., 0., 0., 0.)));
+        assert!(!odometer.record(&arm(0., 0., 20., 0.)));
+        assert!(odometer.record(&arm(0., 0., 40., 0.)));
+        assert!(!odometer.record(&arm(0., 0., 60., 0.)));
+        assert_eq!(odometer.worn().collect::<Vec<_>>(), ["elbow"]);
+    }
+
+    #[test]
+    fn persist() {
+        let path = std::env::temp_dir().join(format!("rac-odometer-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        // nothing saved yet
+        let mut odometer = Odometer::default();
+        odometer.load(&path).unwrap();
+        assert_eq!(odometer.previous, JointAngles::default());
+
+        odometer.record(&arm(0., 0., 0., 0.));
+        odometer.record(&arm(30., 0., 10., 0.));
+        odometer.save(&path).unwrap();
+
+        // the next session continues from the saved totals
+        let mut next = Odometer::default();
+        next.load(&path).unwrap();
+        next.record(&arm(0., 0., 0., 0.));
+        next.record(&arm(0., 5., 0., 0.));
+        assert_eq!(
+            next.total(),
+            JointAngles {
+                base: 30.,
+                shoulder: 5.,
+                elbow: 10.,
+                claw: 0.,
+            }
+        );
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            Odometer::default().load(&path),
+            Err(OdometerError::Parse(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
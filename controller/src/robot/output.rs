@@ -0,0 +1,162 @@
+use super::Servos;
+
+/// Limits how often the servo positions are written to the arduino
+///
+/// The robot can tick faster than the firmware refreshes the servos, frames in between would
+/// only be overwritten. Whatever is sent is always the pose of the latest tick
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OutputRate {
+    /// Send on every `divider`th tick, 1 to send every tick
+    pub divider: u32,
+
+    /// Most servo frames per second, `None` for no limit
+    pub max_hz: Option<f64>,
+
+    /// Ticks since the last frame was sent
+    ticks: u32,
+
+    /// Seconds since the last frame was sent
+    since_sent: f64,
+}
+
+impl OutputRate {
+    pub fn new(divider: u32, max_hz: Option<f64>) -> Self {
+        Self {
+            divider,
+            max_hz,
+            ticks: 0,
+            since_sent: 0.,
+        }
+    }
+
+    /// Count a tick
+    ///
+    /// # Arguments
+    /// * `delta` - seconds since the last tick
+    ///
+    /// # Returns
+    /// True if the servo positions should be sent this tick
+    pub fn due(&mut self, delta: f64) -> bool {
+        self.ticks += 1;
+        self.since_sent += delta;
+
+        let divided = self.ticks >= self.divider.max(1);
+        // small margin so float error in the sum of deltas doesn't skip a tick
+        let slow_enough = self
+            .max_hz
+            .is_none_or(|hz| self.since_sent >= 1. / hz - 1e-9);
+
+        if !(divided && slow_enough) {
+            return false;
+        }
+
+        self.ticks = 0;
+        self.since_sent = 0.;
+        true
+    }
+}
+
+/// Spreads the fraction of a µs the servo pulse widths are rounded off by over the following
+/// frames
+///
+/// Slow moves otherwise step by whole µs, the pulse width sent stays the same for several
+/// frames and then jumps. The remainder of every joint is carried into its next frame so the
+/// average of the pulse widths sent follows the real valued one, and each one is still within
+/// 1µs of it
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Dither {
+    /// µs every joint was sent short of its real valued pulse width, in the order of [`Servos`]
+    remainder: [f64; 4],
+}
+
+impl Dither {
+    /// Round real valued pulse widths for the next frame, without the wrist which is sent as
+    /// it is
+    ///
+    /// # Arguments
+    /// * `pulses` - pulse widths in µs, in the order of [`Servos`]
+    pub fn apply(&mut self, pulses: [f64; 4]) -> Servos {
+        let rounded: [u16; 4] = std::array::from_fn(|joint| {
+            let wanted = pulses[joint] + self.remainder[joint];
+            let sent = wanted.round().clamp(0., u16::MAX as f64);
+
+            // within half a µs unless clamped, so the pulse sent is within 1µs of the real one
+            self.remainder[joint] = (wanted - sent).clamp(-0.5, 0.5);
+            sent as u16
+        });
+
+        Servos {
+            base: rounded[0],
+            shoulder: rounded[1],
+            elbow: rounded[2],
+            claw: rounded[3],
+            wrist: None,
+        }
+    }
+}
+
+impl Default for OutputRate {
+    fn default() -> Self {
+        Self::new(1, None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dither() {
+        // a slow ramp over a few µs, every joint starting at a different fraction
+        let mut dither = Dither::default();
+        let (mut ideal, mut sent) = ([0.; 4], [0.; 4]);
+        for frame in 0..1000 {
+            let pulses = [0., 0.25, 0.5, 0.75].map(|start| 1500. + start + frame as f64 * 0.003);
+            let servos = dither.apply(pulses);
+            let servos = [servos.base, servos.shoulder, servos.elbow, servos.claw];
+
+            for joint in 0..4 {
+                assert!((servos[joint] as f64 - pulses[joint]).abs() <= 1.);
+                ideal[joint] += pulses[joint];
+                sent[joint] += servos[joint] as f64;
+            }
+        }
+        for joint in 0..4 {
+            let error = (sent[joint] - ideal[joint]) / 1000.;
+            assert!(error.abs() < 0.1, "{error} on joint {joint}");
+        }
+
+        // held still a quarter of the way between two µs
+        let mut dither = Dither::default();
+        let sent: Vec<u16> = (0..8).map(|_| dither.apply([1500.25; 4]).base).collect();
+        assert_eq!(sent, [1500, 1501, 1500, 1500, 1500, 1501, 1500, 1500]);
+
+        // the ends of the range aren't overshot
+        assert_eq!(Dither::default().apply([u16::MAX as f64; 4]).claw, u16::MAX);
+    }
+
+    /// Number of frames sent in a second of ticks at `tick_hz`
+    fn frames_per_second(output: &mut OutputRate, tick_hz: u32) -> usize {
+        (0..tick_hz)
+            .filter(|_| output.due(1. / tick_hz as f64))
+            .count()
+    }
+
+    #[test]
+    fn divider() {
+        assert_eq!(frames_per_second(&mut OutputRate::default(), 200), 200);
+        assert_eq!(frames_per_second(&mut OutputRate::new(4, None), 200), 50);
+        assert_eq!(frames_per_second(&mut OutputRate::new(0, None), 200), 200);
+    }
+
+    #[test]
+    fn max_rate() {
+        assert_eq!(frames_per_second(&mut OutputRate::new(1, Some(50.)), 200), 50);
+
+        // 60 Hz doesn't divide 200 Hz, every 4th tick is the fastest that stays below it
+        assert_eq!(frames_per_second(&mut OutputRate::new(1, Some(60.)), 200), 50);
+
+        // the slower of the two limits wins
+        assert_eq!(frames_per_second(&mut OutputRate::new(8, Some(50.)), 200), 25);
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt;
+
+use super::{arm::JointAngles, torque::TorqueLimit, Robot};
+use crate::{kinematics::position::CordinateVec, logging::info};
+
+/// Steps the reach is checked in, out to the full length of the arm
+const RADIUS_STEPS: usize = 200;
+
+/// How far out and how hard the arm may move with what the claw carries
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Derating {
+    /// Furthest the head may be from the shoulder, `None` for the full reach of the arm
+    pub radius: Option<f64>,
+
+    /// Factor for the acceleration, 1 to keep it
+    pub acceleration: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PayloadError {
+    /// The head would be `distance` from the shoulder, but with `grams` in the claw it may
+    /// only go `radius` out
+    BeyondReach {
+        distance: f64,
+        radius: f64,
+        grams: f64,
+    },
+}
+
+impl Derating {
+    /// Nothing derated
+    pub fn none() -> Self {
+        Self {
+            radius: None,
+            acceleration: 1.,
+        }
+    }
+
+    /// How far the arm can hold the head out and how much slower it should accelerate with
+    /// `grams` in the claw
+    ///
+    /// The radius is the furthest out the servos hold the head at any elevation without being
+    /// loaded past their rating. The acceleration is scaled by the share of the moving mass
+    /// that is the arm itself, the servos push the same for a heavier load
+    pub fn new(limit: &TorqueLimit, grams: f64, upper_arm: f64, lower_arm: f64) -> Self {
+        let loaded = limit.carrying(grams);
+        let reach = upper_arm + lower_arm;
+
+        // at every degree of elevation from straight down to straight up
+        let overloaded = |radius: f64| {
+            (-90..=90).any(|elevation: i32| {
+                let elevation = (elevation as f64).to_radians();
+                let mut position =
+                    CordinateVec::new(radius * elevation.cos(), 0., radius * elevation.sin());
+                position.inverse_kinematics(upper_arm, lower_arm).is_ok_and(
+                    |(base, shoulder, elbow)| {
+                        let angles = JointAngles {
+                            base,
+                            shoulder,
+                            elbow,
+                            claw: 0.,
+                        };
+                        loaded.load(angles, upper_arm, lower_arm) > 1.
+                    },
+                )
+            })
+        };
+        let radius = (1..=RADIUS_STEPS)
+            .map(|step| reach * step as f64 / RADIUS_STEPS as f64)
+            .find(|&radius| overloaded(radius))
+            .map(|radius| radius - reach / RADIUS_STEPS as f64);
+
+        let arm = limit.upper_mass + limit.lower_mass + limit.payload;
+        let acceleration = if arm > 0. {
+            arm / (arm + grams.max(0.) / 1000.)
+        } else {
+            1.
+        };
+
+        Self {
+            radius,
+            acceleration,
+        }
+    }
+}
+
+impl Robot {
+    /// The claw carries `grams`, derate the reach and the acceleration for it
+    ///
+    /// Only an arm with a [`Robot::torque_limit`] is derated, without one there is nothing to
+    /// tell how much the servos can hold
+    pub fn set_payload(&mut self, grams: f64) {
+        self.payload = grams.max(0.);
+        self.derating = match &self.torque_limit {
+            Some(limit) => Derating::new(limit, self.payload, self.upper_arm, self.lower_arm),
+            None => Derating::none(),
+        };
+
+        if let Some(radius) = self.derating.radius {
+            info(&format!(
+                "Carrying {:.0}g, the head may go {radius:.1} out and accelerates at {:.0}%",
+                self.payload,
+                self.derating.acceleration * 100.
+            ));
+        }
+    }
+
+    /// Furthest the head may be from the shoulder with what the claw carries
+    pub fn reach(&self) -> f64 {
+        let full = self.upper_arm + self.lower_arm;
+        self.derating.radius.map_or(full, |radius| radius.min(full))
+    }
+
+    /// Check that the head can go to `target` in the operator's frame with what the claw
+    /// carries, see [`Robot::command_target`]
+    pub fn check_payload(&self, target: CordinateVec) -> Result<(), PayloadError> {
+        let internal = self.calibration.to_internal(self.operator_frame(target));
+        let distance = internal.dst();
+        match self.derating.radius {
+            Some(radius) if distance > radius => Err(PayloadError::BeyondReach {
+                distance,
+                radius,
+                grams: self.payload,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadError::BeyondReach {
+                distance,
+                radius,
+                grams,
+            } => write!(
+                f,
+                "{distance:.1} out is beyond the {radius:.1} the arm can hold with {grams:.0}g"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limit() -> TorqueLimit {
+        TorqueLimit {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn derating() {
+        // straight out the shoulder carries 60 of its 100 empty, the full rating at 200g
+        let light = Derating::new(&limit(), 150., 100., 100.);
+        assert_eq!(light.radius, None);
+        assert!((light.acceleration - 0.4 / 0.55).abs() < 1e-9);
+        assert_eq!(Derating::new(&limit(), 0., 100., 100.), Derating::none());
+
+        let radii: Vec<f64> = [250., 300., 400., 500.]
+            .iter()
+            .map(|&grams| {
+                let derating = Derating::new(&limit(), grams, 100., 100.);
+                let radius = derating.radius.unwrap();
+
+                // held at every elevation at the radius, but not a step further out
+                let loaded = limit().carrying(grams);
+                let load = |radius: f64| {
+                    (0..=720)
+                        .filter_map(|step| {
+                            let elevation = (step as f64 / 4. - 90.).to_radians();
+                            let mut position = CordinateVec::new(
+                                radius * elevation.cos(),
+                                0.,
+                                radius * elevation.sin(),
+                            );
+                            let (base, shoulder, elbow) =
+                                position.inverse_kinematics(100., 100.).ok()?;
+                            let angles = JointAngles {
+                                base,
+                                shoulder,
+                                elbow,
+                                claw: 0.,
+                            };
+                            Some(loaded.load(angles, 100., 100.))
+                        })
+                        .fold(0., f64::max)
+                };
+                assert!(load(radius) <= 1.001, "{grams}g at {radius}");
+                assert!(load(radius + 2.) > 1., "{grams}g at {radius}");
+                radius
+            })
+            .collect();
+
+        // heavier loads are held closer in
+        assert!(radii.windows(2).all(|pair| pair[1] < pair[0]), "{radii:?}");
+        assert!(radii[0] > 180. && radii[3] < 150., "{radii:?}");
+
+        // past what the elbow holds with the lower arm level it can't go anywhere
+        let heavy = Derating::new(&limit(), 1000., 100., 100.);
+        assert_eq!(heavy.radius, Some(0.));
+
+        // and accelerate slower
+        let slower: Vec<f64> = [0., 400., 1200.]
+            .iter()
+            .map(|&grams| Derating::new(&limit(), grams, 100., 100.).acceleration)
+            .collect();
+        assert_eq!(slower, [1., 0.5, 0.25]);
+    }
+
+    #[test]
+    fn beyond_reach() {
+        let mut robo = Robot {
+            torque_limit: Some(limit()),
+            ..Default::default()
+        };
+        let target = CordinateVec::new(0., 150., 50.);
+        assert_eq!(robo.check_payload(target), Ok(()));
+
+        robo.set_payload(400.);
+        let radius = robo.derating.radius.unwrap();
+        assert_eq!(robo.reach(), radius);
+        match robo.check_payload(target) {
+            Err(PayloadError::BeyondReach {
+                distance,
+                radius: limit,
+                grams,
+            }) => {
+                assert!((distance - target.dst()).abs() < 1e-9);
+                assert_eq!((limit, grams), (radius, 400.));
+            }
+            other => panic!("{other:?}"),
+        }
+        assert!(robo
+            .check_payload(CordinateVec::new(0., radius - 1., 0.))
+            .is_ok());
+
+        // the head is kept within the derated reach
+        robo.position = CordinateVec::new(0., 190., 0.);
+        robo.update_position(robo.position, 0.);
+        assert!((robo.position.dst() - radius).abs() < 1e-9);
+
+        // putting it down gives the full reach back
+        robo.set_payload(0.);
+        assert_eq!(robo.check_payload(target), Ok(()));
+        assert_eq!(robo.reach(), 200.);
+        assert_eq!(robo.derating.acceleration, 1.);
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    net::UdpSocket,
+    path::PathBuf,
+};
+
+use super::Robot;
+use crate::{kinematics::segments::Segments, session};
+
+/// Prefix of a [`SegmentStream`] target that sends to a UDP address instead of a file
+pub const UDP_PREFIX: &str = "udp://";
+
+/// Columns of a [`SegmentStream`] record
+const HEADER: &str =
+    "time,shoulder_x,shoulder_y,shoulder_z,elbow_x,elbow_y,elbow_z,head_x,head_y,head_z";
+
+/// Where a [`SegmentStream`] writes to
+#[derive(Debug)]
+pub enum SegmentSink {
+    /// One line per record after a header line
+    File(BufWriter<File>),
+
+    /// One datagram per record, connected to the receiver
+    Udp(UdpSocket),
+}
+
+/// Streams where the arm's segments are, for animating the linkage in another tool
+///
+/// A record is the session time followed by x, y and z of the shoulder, the elbow and the
+/// head, comma separated. A file starts with a `# session <id>` line before the header, see
+/// [`session::clock`]. The segments are worked out from the commanded joint angles, see
+/// [`Segments::from_angles`]
+#[derive(Debug)]
+pub struct SegmentStream {
+    pub sink: SegmentSink,
+
+    /// Write a record every nth frame
+    pub every: u32,
+
+    /// File streamed to, `None` when sending over UDP
+    pub path: Option<PathBuf>,
+
+    /// Session time of the first and latest record
+    pub first: Option<f64>,
+    pub last: Option<f64>,
+
+    /// Frames since the last record
+    frames: u32,
+}
+
+impl SegmentStream {
+    /// Stream to a file, or to the address after [`UDP_PREFIX`]
+    pub fn open(target: &str, every: u32) -> io::Result<Self> {
+        let (sink, path) = match target.strip_prefix(UDP_PREFIX) {
+            Some(address) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(address)?;
+                (SegmentSink::Udp(socket), None)
+            }
+            None => {
+                let mut file = BufWriter::new(File::create(target)?);
+                writeln!(file, "# session {}", session::clock().id)?;
+                writeln!(file, "{HEADER}")?;
+                (SegmentSink::File(file), Some(PathBuf::from(target)))
+            }
+        };
+
+        Ok(Self {
+            sink,
+            every: every.max(1),
+            path,
+            first: None,
+            last: None,
+            frames: 0,
+        })
+    }
+
+    /// A frame at `time` seconds into the session, written if it's the nth
+    pub fn frame(&mut self, time: f64, segments: &Segments) -> io::Result<()> {
+        self.frames += 1;
+        if self.frames < self.every {
+            return Ok(());
+        }
+        self.frames = 0;
+        self.first = self.first.or(Some(time));
+        self.last = Some(time);
+
+        let mut record = format!("{time:.3}");
+        for point in [segments.shoulder, segments.elbow, segments.head] {
+            record += &format!(",{:.2},{:.2},{:.2}", point.x, point.y, point.z);
+        }
+        match &mut self.sink {
+            SegmentSink::File(file) => writeln!(file, "{record}"),
+            SegmentSink::Udp(socket) => socket.send(record.as_bytes()).map(|_| ()),
+        }
+    }
+
+    /// Write out what is buffered
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            SegmentSink::File(file) => file.flush(),
+            SegmentSink::Udp(_) => Ok(()),
+        }
+    }
+}
+
+impl Robot {
+    /// Where the segments of the arm are at the commanded joint angles
+    pub fn segments(&self) -> Segments {
+        self.arm.segments(self.upper_arm, self.lower_arm)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn file() {
+        let mut robo = Robot {
+            upper_arm: 100.,
+            lower_arm: 80.,
+            ..Default::default()
+        };
+        robo.arm.base.angle = 90.;
+        robo.arm.shoulder.angle = 90.;
+        robo.arm.elbow.angle = 90.;
+
+        let path = std::env::temp_dir().join(format!("rac-segments-{}.csv", std::process::id()));
+        let mut stream = SegmentStream::open(path.to_str().unwrap(), 2).unwrap();
+        for frame in 1..=5 {
+            stream.frame(frame as f64 * 0.01, &robo.segments()).unwrap();
+        }
+        stream.flush().unwrap();
+        let data = fs::read_to_string(&path);
+        let _ = fs::remove_file(&path);
+
+        // every other frame
+        let data = data.unwrap();
+        let lines: Vec<_> = data.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], format!("# session {}", session::clock().id));
+        assert_eq!(lines[1], HEADER);
+        assert_eq!(
+            lines[2],
+            "0.020,0.00,0.00,0.00,100.00,0.00,0.00,100.00,0.00,80.00"
+        );
+        assert!(lines[3].starts_with("0.040,"));
+        assert_eq!((stream.first, stream.last), (Some(0.02), Some(0.04)));
+    }
+
+    #[test]
+    fn udp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = receiver.local_addr().unwrap();
+
+        let mut stream = SegmentStream::open(&format!("{UDP_PREFIX}{address}"), 1).unwrap();
+        stream
+            .frame(0.5, &Segments::from_angles(90., 0., 180., 10., 5.))
+            .unwrap();
+
+        let mut buffer = [0; 256];
+        let length = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buffer[..length]).unwrap(),
+            "0.500,0.00,0.00,0.00,0.00,0.00,10.00,0.00,0.00,15.00"
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt;
+
+use super::{arm::JOINTS, limits::turn, Robot};
+use crate::{
+    kinematics::operator,
+    protocol::{MAX_PULSE, MIN_PULSE},
+};
+
+/// Fastest a joint under manual override turns in degrees/s, slower if its own limit is lower
+pub const RATE: f64 = 30.;
+
+/// Seconds a manual override lasts before the joint goes back to normal control
+pub const TIMEOUT: f64 = 30.;
+
+/// One joint driven to a raw pulse width while the rest stay under normal control, for
+/// bringing up a servo, see [`Robot::override_servo`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ServoOverride {
+    pub joint: &'static str,
+
+    /// Pulse width asked for in µs
+    pub pulse: u16,
+
+    /// Angle that turns the joint to the pulse width
+    pub target: f64,
+
+    /// Seconds left before the joint goes back to normal control
+    pub remaining: f64,
+
+    /// Turning back to where normal control has the joint, the override ends once it's there
+    pub returning: bool,
+
+    /// Angle of the joint when the override started, where the claw goes back to
+    resume: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverrideError {
+    UnknownJoint(String),
+
+    /// The pulse width is outside of the joint's range, or the limits found for it
+    OutOfRange {
+        joint: &'static str,
+        pulse: u16,
+        min: u16,
+        max: u16,
+    },
+
+    /// Something else drives the joints, or another joint is overridden
+    Busy,
+}
+
+impl Robot {
+    /// Drive a joint to a raw pulse width, the inverse kinematics leave it alone meanwhile
+    ///
+    /// The joint turns there at no more than [`RATE`] and goes back the same way after
+    /// [`TIMEOUT`], on [`Robot::end_servo_override`] or when the emergency stop is pressed.
+    /// Only one joint is overridden at a time, overriding it again moves the target and
+    /// restarts the timeout
+    pub fn override_servo(&mut self, name: &str, pulse: u16) -> Result<(), OverrideError> {
+        let joint = *JOINTS
+            .iter()
+            .find(|joint| **joint == name)
+            .ok_or_else(|| OverrideError::UnknownJoint(name.to_string()))?;
+
+        let busy = self.estop.active()
+            || self.soft_start.is_some()
+            || self.limit_finder.is_some()
+            || self.joint_replay.is_some()
+            || self
+                .servo_override
+                .is_some_and(|manual| manual.joint != joint);
+        if busy {
+            return Err(OverrideError::Busy);
+        }
+
+        let (min, max) = self.pulse_range(joint);
+        if !(min..=max).contains(&pulse) {
+            return Err(OverrideError::OutOfRange {
+                joint,
+                pulse,
+                min,
+                max,
+            });
+        }
+
+        let Some(servo) = self.arm.joint(joint) else {
+            return Err(OverrideError::UnknownJoint(name.to_string()));
+        };
+        let resume = match self.servo_override {
+            Some(manual) => manual.resume,
+            None => servo.angle,
+        };
+        self.servo_override = Some(ServoOverride {
+            joint,
+            pulse,
+            target: servo.angle_for_servo(pulse),
+            remaining: TIMEOUT,
+            returning: false,
+            resume,
+        });
+        if joint == "claw" {
+            self.grip = None;
+        }
+        Ok(())
+    }
+
+    /// Drive a joint to an operator angle like [`Robot::override_servo`], see
+    /// [`operator`] for how the angle is measured
+    pub fn override_joint(&mut self, name: &str, angle: f64) -> Result<(), OverrideError> {
+        let unknown = || OverrideError::UnknownJoint(name.to_string());
+        let angle = operator::joint_from_operator(name, angle).ok_or_else(unknown)?;
+        let pulse = self.arm.joint(name).ok_or_else(unknown)?.servo_at(angle);
+        self.override_servo(name, pulse)
+    }
+
+    /// Hand the overridden joint back to normal control, it turns back at [`RATE`]
+    ///
+    /// # Returns
+    /// False if no joint was overridden
+    pub fn end_servo_override(&mut self) -> bool {
+        match &mut self.servo_override {
+            Some(manual) => {
+                manual.returning = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pulse widths the joint may be overridden to, within its range and the limits found for
+    /// it. A continuous joint may go anywhere the servo can
+    fn pulse_range(&self, joint: &str) -> (u16, u16) {
+        let Some(servo) = self.arm.joint(joint).filter(|servo| !servo.continuous) else {
+            return (MIN_PULSE, MAX_PULSE);
+        };
+
+        let (mut low, mut high) = (servo.min, servo.max);
+        if let Some(range) = self.joint_limits.get(joint) {
+            (low, high) = (low.max(range.min), high.min(range.max));
+        }
+        let (low, high) = (servo.servo_at(low), servo.servo_at(high));
+        (low.min(high).max(MIN_PULSE), low.max(high).min(MAX_PULSE))
+    }
+
+    /// Turn the overridden joint towards its target, or back once the override runs out
+    pub(super) fn servo_override_update(&mut self, delta: f64) {
+        let Some(mut manual) = self.servo_override else {
+            return;
+        };
+
+        if !manual.returning {
+            manual.remaining -= delta;
+            manual.returning = manual.remaining <= 0.;
+        }
+        let target = if manual.returning {
+            let solved = self.solve(self.position).ok();
+            match manual.joint {
+                "base" => solved.map(|angles| angles.0),
+                "shoulder" => solved.map(|angles| angles.1),
+                "elbow" => solved.map(|angles| angles.2),
+                _ => None,
+            }
+            .unwrap_or(manual.resume)
+        } else {
+            manual.target
+        };
+
+        let Some(joint) = self.arm.joint_mut(manual.joint) else {
+            return;
+        };
+        let step = RATE.min(joint.max_velocity_dps) * delta;
+        let target = joint.nearest(target);
+        let there = turn(&mut joint.angle, target, step);
+        self.servo_override = (!(there && manual.returning)).then_some(manual);
+    }
+}
+
+impl fmt::Display for OverrideError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverrideError::UnknownJoint(joint) => write!(f, "unknown joint `{joint}`"),
+            OverrideError::OutOfRange {
+                joint,
+                pulse,
+                min,
+                max,
+            } => write!(
+                f,
+                "{pulse}µs is outside of the {joint} range of {min}µs to {max}µs"
+            ),
+            OverrideError::Busy => write!(
+                f,
+                "the joints are busy, end the override of the other joint first"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        kinematics::position::CordinateVec,
+        robot::limits::{JointLimits, JointRange},
+    };
+
+    /// Holding the head still with the joints where the inverse kinematics put them
+    fn robot() -> (Robot, f64) {
+        let mut robo = Robot {
+            position: CordinateVec::new(100., 50., 50.),
+            ..Default::default()
+        };
+        robo.tick(0.01);
+        let shoulder = robo.arm.shoulder.angle;
+        (robo, shoulder)
+    }
+
+    fn ticks(robo: &mut Robot, seconds: f64) {
+        for _ in 0..(seconds * 100.).round() as u32 {
+            robo.tick(0.01);
+        }
+    }
+
+    #[test]
+    fn lifecycle() {
+        let (mut robo, shoulder) = robot();
+        let pulse = robo.arm.shoulder.servo_at(shoulder + 20.);
+        robo.override_servo("shoulder", pulse).unwrap();
+
+        // at the rate limit, the inverse kinematics don't pull it back
+        robo.tick(0.01);
+        assert!((robo.arm.shoulder.angle - (shoulder + 0.3)).abs() < 1e-9);
+        ticks(&mut robo, 1.);
+        let manual = robo.servo_override.unwrap();
+        assert!((robo.arm.shoulder.angle - manual.target).abs() < 1e-9);
+        assert!((manual.target - (shoulder + 20.)).abs() < 0.1);
+        assert!(robo.arm.shoulder.to_servo().abs_diff(pulse) <= 1);
+
+        // runs out and turns back the same way, then it's normal control again
+        ticks(&mut robo, TIMEOUT - 1.);
+        assert!(robo.servo_override.unwrap().returning);
+        ticks(&mut robo, 0.5);
+        assert!(robo.arm.shoulder.angle > shoulder + 1.);
+        ticks(&mut robo, 0.5);
+        assert_eq!(robo.servo_override, None);
+        assert!((robo.arm.shoulder.angle - shoulder).abs() < 1e-9);
+        assert!(!robo.end_servo_override());
+    }
+
+    #[test]
+    fn bounds() {
+        let (mut robo, _) = robot();
+        assert_eq!(
+            robo.override_servo("wrist", 1500),
+            Err(OverrideError::UnknownJoint("wrist".to_string()))
+        );
+        assert!(matches!(
+            robo.override_servo("elbow", MAX_PULSE + 1),
+            Err(OverrideError::OutOfRange { .. })
+        ));
+
+        // the limits found narrow the range
+        robo.joint_limits = JointLimits {
+            claw: Some(JointRange { min: 30., max: 90. }),
+            ..Default::default()
+        };
+        let (min, max) = robo.pulse_range("claw");
+        assert_eq!(min, robo.arm.claw.servo_at(30.));
+        assert_eq!(max, robo.arm.claw.servo_at(90.));
+        assert_eq!(
+            robo.override_servo("claw", max + 1),
+            Err(OverrideError::OutOfRange {
+                joint: "claw",
+                pulse: max + 1,
+                min,
+                max,
+            })
+        );
+
+        // one joint at a time
+        robo.override_servo("claw", max).unwrap();
+        assert_eq!(robo.override_servo("base", 1000), Err(OverrideError::Busy));
+        robo.override_servo("claw", min).unwrap();
+        assert_eq!(robo.servo_override.unwrap().pulse, min);
+    }
+
+    #[test]
+    fn emergency_stop() {
+        let (mut robo, shoulder) = robot();
+        let pulse = robo.arm.shoulder.servo_at(shoulder - 20.);
+        robo.override_servo("shoulder", pulse).unwrap();
+        ticks(&mut robo, 0.2);
+        let stopped_at = robo.arm.shoulder.angle;
+        assert!(stopped_at < shoulder - 5.);
+
+        // nothing moves while stopped, and the override can't be restarted
+        robo.emergency_stop();
+        ticks(&mut robo, 0.5);
+        assert_eq!(robo.arm.shoulder.angle, stopped_at);
+        assert!(robo.servo_override.unwrap().returning);
+        assert_eq!(
+            robo.override_servo("shoulder", pulse),
+            Err(OverrideError::Busy)
+        );
+
+        // back to normal control at the rate limit once released
+        assert!(robo.release_emergency_stop());
+        ticks(&mut robo, 0.1);
+        assert!(robo.arm.shoulder.angle > stopped_at);
+        assert!(robo.arm.shoulder.angle < shoulder);
+        ticks(&mut robo, 1.);
+        assert_eq!(robo.servo_override, None);
+        assert!((robo.arm.shoulder.angle - shoulder).abs() < 1e-9);
+    }
+}
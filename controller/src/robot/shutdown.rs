@@ -0,0 +1,421 @@
+use std::{fmt, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::{idle::IdleState, supply::SupplyLevel, Robot};
+use crate::{
+    kinematics::position::CordinateVec,
+    logging::{info, warn},
+    protocol::Frame,
+};
+
+/// What the arm does when it's shut down, each one safer than the one before
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownBehavior {
+    /// Fold to the [`ShutdownConfig::rest_pose`] and hold it there
+    Rest,
+
+    /// Stop where it is and keep the servos driven, for an arm supporting a fixture
+    #[default]
+    Hold,
+
+    /// Stop and detach the servos
+    Detach,
+}
+
+/// How the arm is shut down
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    pub behavior: ShutdownBehavior,
+
+    /// Seconds a behavior may take before the shutdown goes on to the next safer one
+    pub budget: f64,
+
+    /// Pose of the program [`ShutdownBehavior::Rest`] folds to, the arm holds instead if the
+    /// program has no such pose
+    pub rest_pose: String,
+
+    /// Seconds the link may be down before the arm is shut down, 0 to never
+    pub link_timeout: f64,
+}
+
+/// Why the arm is shut down
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// The session is ending, with `quit` or Ctrl-C
+    Quit,
+
+    /// The link was down for longer than [`ShutdownConfig::link_timeout`]
+    LinkDown,
+
+    /// The supply went critical, see [`super::supply::SupplyMonitor`]
+    Supply,
+}
+
+/// A shutdown in progress, see [`Robot::begin_shutdown`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Shutdown {
+    pub reason: ShutdownReason,
+
+    /// What the arm is doing, starts at the configured behavior
+    pub stage: ShutdownBehavior,
+
+    /// Seconds since the stage started
+    pub elapsed: f64,
+
+    /// The arm rests, holds or is detached and stays that way
+    pub done: bool,
+}
+
+/// How a shutdown got on this tick
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShutdownStep {
+    /// The last stage ran out of time, the shutdown went on to this one
+    Escalated(ShutdownBehavior),
+
+    Done(ShutdownBehavior),
+}
+
+impl ShutdownBehavior {
+    /// The next safer behavior
+    pub fn escalate(self) -> Self {
+        match self {
+            ShutdownBehavior::Rest => ShutdownBehavior::Hold,
+            ShutdownBehavior::Hold | ShutdownBehavior::Detach => ShutdownBehavior::Detach,
+        }
+    }
+}
+
+impl Robot {
+    /// Shut the arm down as [`Robot::on_shutdown`] says, nothing else moves it meanwhile
+    ///
+    /// A shutdown already in progress carries on, quitting only takes it over so it isn't
+    /// ended by whatever started it clearing up
+    pub fn begin_shutdown(&mut self, reason: ShutdownReason) {
+        if let Some(shutdown) = &mut self.shutdown {
+            if reason == ShutdownReason::Quit {
+                shutdown.reason = reason;
+            }
+            return;
+        }
+
+        let behavior = self.on_shutdown.behavior;
+        match reason {
+            ShutdownReason::Quit => info(&format!("Shutting down, {behavior}")),
+            _ => warn(&format!("Shutting down ({reason}), {behavior}")),
+        }
+        self.stop_everything();
+        self.end_servo_override();
+        self.abort_limit_search();
+        self.grip = None;
+        self.shutdown = Some(Shutdown {
+            reason,
+            stage: behavior,
+            elapsed: 0.,
+            done: false,
+        });
+    }
+
+    /// End the shutdown started for `reason` once it cleared up, detached servos attach again
+    /// on the next input
+    ///
+    /// # Returns
+    /// False if the arm wasn't shut down for `reason`
+    pub fn end_shutdown(&mut self, reason: ShutdownReason) -> bool {
+        if self
+            .shutdown
+            .is_none_or(|shutdown| shutdown.reason != reason)
+        {
+            return false;
+        }
+
+        info(&format!("No longer shutting down, {reason} cleared up"));
+        self.shutdown = None;
+        true
+    }
+
+    /// Shut the arm down once the link is down for longer than
+    /// [`ShutdownConfig::link_timeout`], and carry on once it's back
+    pub(super) fn link_watchdog(&mut self, link_up: bool, now: Instant) {
+        if link_up {
+            self.link_down_since = None;
+            self.end_shutdown(ShutdownReason::LinkDown);
+            return;
+        }
+
+        let since = *self.link_down_since.get_or_insert(now);
+        let timeout = self.on_shutdown.link_timeout;
+        if timeout > 0. && now.duration_since(since).as_secs_f64() > timeout {
+            self.begin_shutdown(ShutdownReason::LinkDown);
+        }
+    }
+
+    /// Carry out the shutdown, going on to the next safer behavior whenever one takes longer
+    /// than [`ShutdownConfig::budget`]. A critical supply can't hold anything, the servos are
+    /// detached once the arm rests or holds
+    ///
+    /// # Returns
+    /// How the shutdown got on, and true if the rest of the tick should be skipped since the
+    /// servos are detached
+    pub(super) fn shutdown_update(&mut self, delta: f64) -> (Option<ShutdownStep>, bool) {
+        let Some(mut shutdown) = self.shutdown else {
+            return (None, false);
+        };
+        if self.idle.state == IdleState::Detached {
+            let step = (!shutdown.done).then_some(ShutdownStep::Done(ShutdownBehavior::Detach));
+            shutdown.stage = ShutdownBehavior::Detach;
+            shutdown.done = true;
+            self.shutdown = Some(shutdown);
+            return (step, true);
+        }
+
+        // nothing the operator commands moves the arm
+        self.stop_everything();
+        shutdown.elapsed += delta;
+        let stopped = self.velocity == CordinateVec::default();
+        let weak = self
+            .supply
+            .as_ref()
+            .is_some_and(|supply| supply.level == SupplyLevel::Critical);
+
+        let mut step = None;
+        let arrived = match shutdown.stage {
+            _ if shutdown.done => Some(true),
+            ShutdownBehavior::Rest => {
+                match self.program.poses.get(&self.on_shutdown.rest_pose).copied() {
+                    Some(rest) if self.position == rest && stopped => Some(true),
+                    Some(rest) if shutdown.elapsed < self.on_shutdown.budget => {
+                        self.target_position = Some(rest);
+                        Some(false)
+                    }
+                    _ => None,
+                }
+            }
+            ShutdownBehavior::Hold if stopped => Some(true),
+            ShutdownBehavior::Hold if shutdown.elapsed < self.on_shutdown.budget => Some(false),
+            ShutdownBehavior::Hold => None,
+            ShutdownBehavior::Detach => Some(true),
+        };
+        match arrived {
+            Some(true) if weak && shutdown.stage != ShutdownBehavior::Detach => {
+                shutdown.stage = ShutdownBehavior::Detach;
+                shutdown.done = true;
+                step = Some(ShutdownStep::Done(shutdown.stage));
+            }
+            Some(true) if !shutdown.done => {
+                shutdown.done = true;
+                step = Some(ShutdownStep::Done(shutdown.stage));
+            }
+            Some(_) => {}
+            None => {
+                shutdown.stage = shutdown.stage.escalate();
+                shutdown.elapsed = 0.;
+                step = Some(ShutdownStep::Escalated(shutdown.stage));
+            }
+        }
+
+        let detach = shutdown.stage == ShutdownBehavior::Detach;
+        if detach {
+            self.velocity = CordinateVec::default();
+            self.idle.state = IdleState::Detached;
+            self.idle.pending = Some(Frame::Detach);
+        }
+        self.shutdown = Some(shutdown);
+        (step, detach)
+    }
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            behavior: ShutdownBehavior::Hold,
+            budget: 10.,
+            rest_pose: "rest".to_string(),
+            link_timeout: 0.,
+        }
+    }
+}
+
+impl fmt::Display for ShutdownBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownBehavior::Rest => write!(f, "rest"),
+            ShutdownBehavior::Hold => write!(f, "hold"),
+            ShutdownBehavior::Detach => write!(f, "detach"),
+        }
+    }
+}
+
+impl fmt::Display for ShutdownReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownReason::Quit => write!(f, "quitting"),
+            ShutdownReason::LinkDown => write!(f, "link down"),
+            ShutdownReason::Supply => write!(f, "supply critical"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::robot::{events::RobotEvent, supply::SupplyMonitor};
+
+    fn robot(behavior: ShutdownBehavior) -> Robot {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            on_shutdown: ShutdownConfig {
+                behavior,
+                budget: 0.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        robo.status.interval = None;
+        robo.update_ik();
+        robo
+    }
+
+    /// Update until the shutdown is done, every step it reported on the way
+    fn shut_down(robo: &mut Robot) -> Vec<ShutdownStep> {
+        let mut steps = Vec::new();
+        for _ in 0..1000 {
+            let report = robo.update(0.01).unwrap();
+            steps.extend(report.shutdown);
+            if robo.shutdown.is_some_and(|shutdown| shutdown.done) {
+                return steps;
+            }
+        }
+        panic!("still shutting down after {steps:?}");
+    }
+
+    #[test]
+    fn rest() {
+        let mut robo = robot(ShutdownBehavior::Rest);
+        robo.on_shutdown.budget = 10.;
+        robo.cruise_speed = Some(20.);
+        let rest = CordinateVec::new(45., 75., 75.);
+        robo.program.poses.insert("rest".to_string(), rest);
+
+        robo.begin_shutdown(ShutdownReason::Quit);
+        assert_eq!(
+            shut_down(&mut robo),
+            [ShutdownStep::Done(ShutdownBehavior::Rest)]
+        );
+        assert_eq!(robo.position, rest);
+        assert_eq!(robo.connection.stats.sent.detach, 0);
+        assert!(robo
+            .take_events()
+            .iter()
+            .any(|event| event.event == RobotEvent::ShutDown(ShutdownBehavior::Rest)));
+
+        // the servos keep holding it there, whatever the operator commands
+        robo.command_velocity(CordinateVec::new(10., 0., 0.));
+        for _ in 0..100 {
+            assert!(robo.update(0.01).unwrap().transmitted);
+        }
+        assert_eq!(robo.position, rest);
+        assert_eq!(robo.idle.state, IdleState::Attached { rested: 0. });
+    }
+
+    #[test]
+    fn hold() {
+        let mut robo = robot(ShutdownBehavior::Hold);
+        robo.command_velocity(CordinateVec::new(20., 0., 0.));
+        for _ in 0..20 {
+            robo.update(0.01).unwrap();
+        }
+
+        robo.begin_shutdown(ShutdownReason::Quit);
+        assert_eq!(
+            shut_down(&mut robo),
+            [ShutdownStep::Done(ShutdownBehavior::Hold)]
+        );
+        assert_eq!(robo.velocity, CordinateVec::default());
+        assert_eq!(robo.connection.stats.sent.detach, 0);
+        assert!(robo.update(0.01).unwrap().transmitted);
+    }
+
+    #[test]
+    fn detach() {
+        let mut robo = robot(ShutdownBehavior::Detach);
+        robo.begin_shutdown(ShutdownReason::Quit);
+        assert_eq!(
+            shut_down(&mut robo),
+            [ShutdownStep::Done(ShutdownBehavior::Detach)]
+        );
+        assert_eq!(robo.connection.stats.sent.detach, 1);
+        assert!(robo.state().detached);
+        assert!(!robo.update(0.01).unwrap().transmitted);
+    }
+
+    #[test]
+    fn escalates() {
+        // no rest pose to fold to, it holds instead, but brakes too slowly to stop in time
+        let mut robo = robot(ShutdownBehavior::Rest);
+        robo.acceleration = 1.;
+        robo.velocity = CordinateVec::new(50., 0., 0.);
+        robo.begin_shutdown(ShutdownReason::Quit);
+        for _ in 0..45 {
+            robo.update(0.01).unwrap();
+            assert!(!robo.idle.holding());
+        }
+        assert_eq!(robo.shutdown.unwrap().stage, ShutdownBehavior::Hold);
+
+        assert_eq!(
+            shut_down(&mut robo),
+            [
+                ShutdownStep::Escalated(ShutdownBehavior::Detach),
+                ShutdownStep::Done(ShutdownBehavior::Detach)
+            ]
+        );
+        assert_eq!(robo.velocity, CordinateVec::default());
+        assert_eq!(robo.connection.stats.sent.detach, 1);
+
+        // a critical supply doesn't hold anything, the servos are detached once the arm stopped
+        let mut robo = robot(ShutdownBehavior::Hold);
+        robo.supply = Some(SupplyMonitor::new(Default::default()));
+        robo.supply_report(4000);
+        assert_eq!(robo.shutdown.unwrap().reason, ShutdownReason::Supply);
+        assert_eq!(
+            shut_down(&mut robo),
+            [ShutdownStep::Done(ShutdownBehavior::Detach)]
+        );
+        assert_eq!(robo.connection.stats.sent.detach, 1);
+    }
+
+    #[test]
+    fn link_watchdog() {
+        let mut robo = robot(ShutdownBehavior::Hold);
+        robo.on_shutdown.link_timeout = 1.;
+        let now = Instant::now();
+
+        robo.link_watchdog(false, now);
+        robo.link_watchdog(false, now + Duration::from_millis(900));
+        assert_eq!(robo.shutdown, None);
+        robo.link_watchdog(false, now + Duration::from_millis(1100));
+        assert_eq!(robo.shutdown.unwrap().reason, ShutdownReason::LinkDown);
+
+        // carries on once the link is back
+        robo.link_watchdog(true, now + Duration::from_millis(1200));
+        assert_eq!(robo.shutdown, None);
+        assert_eq!(robo.link_down_since, None);
+
+        // unless the session is ending anyway
+        robo.link_watchdog(false, now);
+        robo.link_watchdog(false, now + Duration::from_secs(2));
+        robo.begin_shutdown(ShutdownReason::Quit);
+        robo.link_watchdog(true, now + Duration::from_secs(3));
+        assert_eq!(robo.shutdown.unwrap().reason, ShutdownReason::Quit);
+
+        // never with the default timeout
+        let mut robo = robot(ShutdownBehavior::Hold);
+        robo.link_watchdog(false, now);
+        robo.link_watchdog(false, now + Duration::from_secs(3600));
+        assert_eq!(robo.shutdown, None);
+    }
+}
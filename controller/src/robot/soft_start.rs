@@ -0,0 +1,209 @@
+use super::arm::{Arm, JointAngles};
+
+/// Longest the ramp waits for the servos to report where they are in seconds, it then starts
+/// from [`SoftStart::neutral`]
+pub const FEEDBACK_WAIT: f64 = 1.;
+
+/// Ramps the servos to the first commanded pose instead of letting them jump there at full speed
+///
+/// The ramp starts from the pose reported by feedback. Until there is any it waits up to
+/// [`FEEDBACK_WAIT`], and then starts from [`SoftStart::neutral`], which is where the servos are
+/// assumed to rest before the first frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SoftStart {
+    /// Shortest time the ramp takes in seconds
+    pub duration: f64,
+
+    /// Fastest any joint may turn during the ramp in degrees/s, the ramp takes longer than
+    /// [`SoftStart::duration`] if needed
+    pub rate_dps: f64,
+
+    /// Pose to start from when there is no feedback
+    pub neutral: JointAngles,
+
+    ramp: Option<Ramp>,
+
+    /// Seconds waited for feedback before the ramp started
+    waited: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Ramp {
+    from: JointAngles,
+    to: JointAngles,
+
+    /// Seconds the whole ramp takes
+    length: f64,
+    elapsed: f64,
+}
+
+impl SoftStart {
+    pub fn new(duration: f64, rate_dps: f64, neutral: JointAngles) -> Self {
+        Self {
+            duration,
+            rate_dps,
+            neutral,
+            ramp: None,
+            waited: 0.,
+        }
+    }
+
+    /// The ramp didn't start yet, nothing should be sent to the servos
+    pub fn waiting(&self) -> bool {
+        self.ramp.is_none()
+    }
+
+    /// Move the arm one step along the ramp
+    ///
+    /// The first step with feedback, or once [`FEEDBACK_WAIT`] is up, fixes where the ramp goes,
+    /// so `from` and `to` are only used then. The arm isn't moved before
+    ///
+    /// # Arguments
+    /// * `arm` - arm to move
+    /// * `from` - reported pose, `None` to start from [`SoftStart::neutral`]
+    /// * `to` - pose to end up in
+    /// * `delta` - seconds since the last step
+    ///
+    /// # Returns
+    /// True once the arm is at `to`
+    pub fn step(
+        &mut self,
+        arm: &mut Arm,
+        from: Option<JointAngles>,
+        to: JointAngles,
+        delta: f64,
+    ) -> bool {
+        if self.ramp.is_none() && from.is_none() && self.waited < FEEDBACK_WAIT {
+            self.waited += delta;
+            return false;
+        }
+
+        let ramp = self.ramp.get_or_insert_with(|| {
+            let from = from.unwrap_or(self.neutral);
+            let furthest = [
+                to.base - from.base,
+                to.shoulder - from.shoulder,
+                to.elbow - from.elbow,
+                to.claw - from.claw,
+            ]
+            .into_iter()
+            .fold(0., |furthest: f64, angle| furthest.max(angle.abs()));
+
+            Ramp {
+                from,
+                to,
+                length: self.duration.max(furthest / self.rate_dps),
+                elapsed: 0.,
+            }
+        });
+
+        ramp.elapsed += delta;
+        let factor = if ramp.length > 0. {
+            ramp.elapsed / ramp.length
+        } else {
+            1.
+        };
+
+        arm.interpolate(ramp.from, ramp.to, factor);
+        factor >= 1.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn angles(base: f64, shoulder: f64, elbow: f64, claw: f64) -> JointAngles {
+        JointAngles {
+            base,
+            shoulder,
+            elbow,
+            claw,
+        }
+    }
+
+    /// Run the ramp to the end and check no joint ever moves faster than `rate_dps`
+    ///
+    /// # Returns
+    /// How long the ramp took in seconds
+    fn ramp(soft_start: &mut SoftStart, from: Option<JointAngles>, to: JointAngles) -> f64 {
+        let delta = 0.01;
+        let mut arm = Arm::default();
+        let mut previous = from.unwrap_or(soft_start.neutral);
+        let mut elapsed = 0.;
+
+        loop {
+            let done = soft_start.step(&mut arm, from, to, delta);
+            elapsed += delta;
+            if soft_start.waiting() {
+                continue;
+            }
+
+            let angles = arm.angles();
+            for step in [
+                angles.base - previous.base,
+                angles.shoulder - previous.shoulder,
+                angles.elbow - previous.elbow,
+                angles.claw - previous.claw,
+            ] {
+                assert!(step.abs() <= soft_start.rate_dps * delta + 1e-9, "{step} too far");
+            }
+            previous = angles;
+
+            if done {
+                assert_eq!(angles, to);
+                return elapsed;
+            }
+            assert!(elapsed < 100., "ramp never finished");
+        }
+    }
+
+    #[test]
+    fn rate_limited() {
+        let neutral = angles(90., 90., 90., 90.);
+
+        // 80 degrees at 20 degrees/s is slower than the duration, after waiting for feedback
+        let mut soft_start = SoftStart::new(1., 20., neutral);
+        let elapsed = ramp(&mut soft_start, None, angles(10., 120., 60., 90.));
+        assert!((elapsed - FEEDBACK_WAIT - 4.).abs() < 0.02);
+
+        // a short move still takes the whole duration
+        let mut soft_start = SoftStart::new(1., 20., neutral);
+        let elapsed = ramp(&mut soft_start, None, angles(95., 90., 90., 90.));
+        assert!((elapsed - FEEDBACK_WAIT - 1.).abs() < 0.02);
+    }
+
+    #[test]
+    fn seeded_from_feedback() {
+        let mut soft_start = SoftStart::new(0.5, 45., angles(90., 90., 90., 90.));
+        let reported = angles(30., 40., 50., 60.);
+
+        // starts where the servos are instead of at neutral
+        let mut arm = Arm::default();
+        soft_start.step(&mut arm, Some(reported), angles(40., 50., 60., 70.), 0.01);
+        assert!((arm.angles().base - reported.base).abs() < 1.);
+
+        let mut soft_start = SoftStart::new(0.5, 45., angles(90., 90., 90., 90.));
+        let elapsed = ramp(&mut soft_start, Some(reported), angles(40., 50., 60., 70.));
+        assert!((elapsed - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn waits_for_feedback() {
+        let mut soft_start = SoftStart::new(0.5, 45., angles(90., 90., 90., 90.));
+        let reported = angles(30., 40., 50., 60.);
+        let mut arm = Arm::default();
+        let before = arm.angles();
+
+        // the arm isn't touched until the servos report
+        for _ in 0..50 {
+            assert!(!soft_start.step(&mut arm, None, reported, 0.01));
+        }
+        assert!(soft_start.waiting());
+        assert_eq!(arm.angles(), before);
+
+        soft_start.step(&mut arm, Some(reported), angles(40., 50., 60., 70.), 0.01);
+        assert!(!soft_start.waiting());
+        assert!((arm.angles().base - reported.base).abs() < 1.);
+    }
+}
@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+
+use super::arm::{JointAngles, JOINTS};
+
+/// When a joint counts as stalled, see [`StallDetector`]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StallConfig {
+    /// Watch for stalls at all, the robot only gets a detector if this is set
+    pub enabled: bool,
+
+    /// How much slower than commanded in degrees/s a joint may turn before it counts as
+    /// stalling, per joint since some see a lot more friction variance than others
+    pub threshold: JointAngles,
+
+    /// Seconds a joint has to keep stalling before it's reported, so the lag of the servo
+    /// when starting a move doesn't count
+    pub duration: f64,
+
+    /// Time constant of the velocity filter in seconds, 0 for no filtering
+    ///
+    /// Feedback pulse widths are whole µs, which is a lot of velocity noise at 10ms apart
+    pub smoothing: f64,
+}
+
+/// Notices when a joint stops following its commanded angle, like when the claw snags on
+/// something
+///
+/// The velocity of the angles the feedback reports is compared to the velocity of the
+/// commanded angles, both filtered the same way so the filter lag cancels out. A joint stalls
+/// once it turns slower than commanded, in the commanded direction, by more than its
+/// threshold for [`StallConfig::duration`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StallDetector {
+    pub config: StallConfig,
+
+    /// Reported angles waiting for the next [`StallDetector::update`]
+    pending: Option<JointAngles>,
+
+    /// Reported and commanded angles at the previous report
+    last: Option<(JointAngles, JointAngles)>,
+
+    /// Seconds since the previous report
+    elapsed: f64,
+
+    /// Filtered reported and commanded velocities in degrees/s
+    reported: [f64; 4],
+    commanded: [f64; 4],
+
+    /// Seconds every joint has been stalling for
+    stalling: [f64; 4],
+}
+
+impl StallDetector {
+    pub fn new(config: StallConfig) -> Self {
+        Self {
+            config,
+            pending: None,
+            last: None,
+            elapsed: 0.,
+            reported: [0.; 4],
+            commanded: [0.; 4],
+            stalling: [0.; 4],
+        }
+    }
+
+    /// The feedback reported the joints at these angles
+    pub fn report(&mut self, angles: JointAngles) {
+        self.pending = Some(angles);
+    }
+
+    /// Compare the latest report against the commanded angles
+    ///
+    /// Ticks without a new report only add to the time between reports, the velocities are
+    /// measured from one report to the next
+    ///
+    /// # Arguments
+    /// * `commanded` - the angles the joints are commanded to, clamped to their range
+    /// * `delta` - seconds since the last call
+    ///
+    /// # Returns
+    /// The name of the joint that has been stalling for long enough
+    pub fn update(&mut self, commanded: JointAngles, delta: f64) -> Option<&'static str> {
+        self.elapsed += delta;
+        let reported = self.pending.take()?;
+
+        let elapsed = self.elapsed;
+        let last = self.last.replace((reported, commanded));
+        self.elapsed = 0.;
+        let (last_reported, last_commanded) = last?;
+        if elapsed <= 0. {
+            return None;
+        }
+
+        let factor = if self.config.smoothing > 0. {
+            1. - (-elapsed / self.config.smoothing).exp()
+        } else {
+            1.
+        };
+        let velocities = [
+            (joints(reported), joints(last_reported), &mut self.reported),
+            (
+                joints(commanded),
+                joints(last_commanded),
+                &mut self.commanded,
+            ),
+        ];
+        for (now, last, filtered) in velocities {
+            for ((filtered, now), last) in filtered.iter_mut().zip(now).zip(last) {
+                *filtered += ((now - last) / elapsed - *filtered) * factor;
+            }
+        }
+
+        let threshold = joints(self.config.threshold);
+        let mut stalled = None;
+        for i in 0..JOINTS.len() {
+            let commanded = self.commanded[i];
+            let behind = (commanded - self.reported[i]) * commanded.signum();
+
+            if behind > threshold[i] {
+                self.stalling[i] += elapsed;
+            } else {
+                self.stalling[i] = 0.;
+            }
+            if stalled.is_none() && self.stalling[i] >= self.config.duration {
+                stalled = Some(JOINTS[i]);
+            }
+        }
+
+        stalled
+    }
+
+    /// Forget everything measured, for when the joints were moved by something the
+    /// commanded angles don't show
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+}
+
+fn joints(angles: JointAngles) -> [f64; 4] {
+    [angles.base, angles.shoulder, angles.elbow, angles.claw]
+}
+
+impl Default for StallConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            // the base turns the whole arm and sticks and slips the most
+            threshold: JointAngles {
+                base: 40.,
+                shoulder: 20.,
+                elbow: 20.,
+                claw: 20.,
+            },
+            duration: 0.3,
+            smoothing: 0.05,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn angles(shoulder: f64) -> JointAngles {
+        JointAngles {
+            shoulder,
+            ..Default::default()
+        }
+    }
+
+    /// Tick at 100Hz with a report every tick, the shoulder commanded at 50°/s and reported at
+    /// `reported`°/s
+    fn run(detector: &mut StallDetector, ticks: u32, reported: f64) -> Option<(u32, &'static str)> {
+        for tick in 1..=ticks {
+            let time = tick as f64 * 0.01;
+            detector.report(angles(time * reported));
+            if let Some(joint) = detector.update(angles(time * 50.), 0.01) {
+                return Some((tick, joint));
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn following() {
+        let mut detector = StallDetector::new(StallConfig::default());
+        assert_eq!(run(&mut detector, 200, 50.), None);
+        // a little slow is within the threshold
+        assert_eq!(run(&mut detector, 200, 40.), None);
+    }
+
+    #[test]
+    fn stalled() {
+        let mut detector = StallDetector::new(StallConfig::default());
+        let (tick, joint) = run(&mut detector, 200, 0.).unwrap();
+        assert_eq!(joint, "shoulder");
+
+        // the duration plus the time the filter takes to pass the threshold
+        assert!((30..=40).contains(&tick), "after {tick} ticks");
+
+        // a higher threshold for the shoulder lets it through
+        let mut detector = StallDetector::new(StallConfig {
+            threshold: JointAngles {
+                shoulder: 60.,
+                ..StallConfig::default().threshold
+            },
+            ..Default::default()
+        });
+        assert_eq!(run(&mut detector, 200, 0.), None);
+    }
+
+    #[test]
+    fn only_reports_count() {
+        let mut detector = StallDetector::new(StallConfig::default());
+
+        // ticks between reports don't look like a stopped joint
+        for tick in 1..=200 {
+            let time = tick as f64 * 0.01;
+            if tick % 5 == 0 {
+                detector.report(angles(time * 50.));
+            }
+            assert_eq!(detector.update(angles(time * 50.), 0.01), None);
+        }
+
+        // catching up faster than commanded isn't a stall
+        let mut detector = StallDetector::new(StallConfig::default());
+        assert_eq!(run(&mut detector, 200, 120.), None);
+    }
+}
@@ -0,0 +1,358 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{arm::JointAngles, idle::IdleState, Robot};
+use crate::{
+    kinematics::position::CordinateVec, logging::info, program::ClawAction, protocol::Frame,
+};
+
+/// How the servos start out
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupMode {
+    /// Ramp up with the soft start and hold, see [`StartupConfig`] for where
+    #[default]
+    Hold,
+
+    /// Leave the servos detached until the arm is moved, they then hold the pose they report
+    Detached,
+}
+
+/// What the arm does once it's connected, see [`Robot::prepare_startup`]
+///
+/// Without a target or `home` the servos hold the pose they report when the soft start
+/// begins, so nothing moves until commanded. If they report none within
+/// [`super::soft_start::FEEDBACK_WAIT`] they are taken to be in the neutral pose of the soft
+/// start
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartupConfig {
+    pub mode: StartupMode,
+
+    /// Position in the operator's frame the head moves to after the soft start, `None` to hold
+    pub target: Option<CordinateVec>,
+
+    /// Ramp to the neutral pose of the soft start instead of holding
+    pub home: bool,
+
+    /// Where the soft start turns the claw, `None` to hold it where it is
+    pub claw: Option<ClawAction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartupError {
+    /// A target or `home` with the servos left detached
+    Detached,
+
+    /// Both a target and `home`
+    TargetAndHome,
+
+    /// The target is further from the shoulder than the arm reaches
+    OutOfReach { target: CordinateVec, reach: f64 },
+
+    /// The joints would have to go past their limits to reach the target
+    JointLimits(CordinateVec),
+
+    /// The target is below the floor or in a keep out zone of the selected workspace
+    Workspace {
+        target: CordinateVec,
+        workspace: String,
+    },
+}
+
+impl StartupConfig {
+    /// The servos hold the pose they report rather than going anywhere
+    pub fn holds(&self) -> bool {
+        self.target.is_none() && !self.home
+    }
+}
+
+impl Robot {
+    /// Set the robot up to start like `startup`, before it's connected
+    ///
+    /// The arm is put in the neutral pose of the soft start, which is where the servos are
+    /// taken to be until they report otherwise, see [`Robot::hold_reported`]
+    pub fn prepare_startup(&mut self, startup: StartupConfig) {
+        let neutral = self
+            .soft_start
+            .map_or(self.idle.resume.neutral, |soft_start| soft_start.neutral);
+        let claw = startup
+            .claw
+            .map_or(neutral.claw, |claw| claw.angle(&self.arm.claw));
+        self.hold_pose(JointAngles { claw, ..neutral });
+
+        if let Some(target) = startup.target {
+            self.command_target(target);
+        }
+        if startup.mode == StartupMode::Detached {
+            self.soft_start = None;
+            self.idle.state = IdleState::Detached;
+            self.idle.pending = Some(Frame::Detach);
+        }
+        self.startup = Some(startup);
+
+        info(&format!(
+            "Starting {}",
+            match (startup.mode, startup.target) {
+                (StartupMode::Detached, _) => "detached until moved".to_string(),
+                (_, Some(target)) => format!("and moving to {target:?}"),
+                _ if startup.home => "and moving to the neutral pose".to_string(),
+                _ => "and holding the pose".to_string(),
+            }
+        ));
+    }
+
+    /// Keep what the servos report when the first soft start begins, the claw unless it's set
+    /// at startup and the rest of the arm if the [`Robot::startup`] holds
+    ///
+    /// Nothing happens until there is feedback, the soft start waits for it
+    pub(super) fn hold_reported(&mut self) {
+        let Some(feedback) = self.feedback else {
+            return;
+        };
+        let Some(startup) = self.startup.take() else {
+            return;
+        };
+
+        let reported = self.arm.angles_from_servos(feedback.pulses);
+        let pose = JointAngles {
+            claw: match startup.claw {
+                Some(_) => self.arm.claw.angle,
+                None => reported.claw,
+            },
+            ..if startup.holds() {
+                reported
+            } else {
+                self.arm.angles()
+            }
+        };
+        self.hold_pose(pose);
+    }
+
+    /// Put the joints in `pose` and the head where that takes it
+    fn hold_pose(&mut self, pose: JointAngles) {
+        self.arm.interpolate(pose, pose, 0.);
+        self.position = self.head_at(CordinateVec::forward_kinematics(
+            pose.base,
+            pose.shoulder,
+            pose.elbow,
+            self.upper_arm,
+            self.lower_arm,
+        ));
+    }
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupError::Detached => {
+                write!(
+                    f,
+                    "the servos can't move to a target or home while detached"
+                )
+            }
+            StartupError::TargetAndHome => write!(f, "either a target or home, not both"),
+            StartupError::OutOfReach { target, reach } => {
+                write!(f, "the target {target:?} is beyond the reach of {reach:.1}")
+            }
+            StartupError::JointLimits(target) => {
+                write!(f, "the target {target:?} is beyond the joint limits")
+            }
+            StartupError::Workspace { target, workspace } => {
+                write!(
+                    f,
+                    "the target {target:?} is outside the workspace `{workspace}`"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::Config,
+        robot::{arm::Arm, events::RobotEvent},
+        sim::{SimConfig, Simulator},
+    };
+
+    const NEUTRAL: JointAngles = JointAngles {
+        base: 90.,
+        shoulder: 90.,
+        elbow: 90.,
+        claw: 90.,
+    };
+
+    /// Where the servos are when the controller starts
+    const RESTING: JointAngles = JointAngles {
+        base: 120.,
+        shoulder: 40.,
+        elbow: 120.,
+        claw: 30.,
+    };
+
+    fn robot(startup: StartupConfig) -> Robot {
+        let config = Config {
+            startup,
+            sim: SimConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            soft_start_duration: 0.5,
+            soft_start_rate: 90.,
+            ..Default::default()
+        };
+        config.robot(Arm::default())
+    }
+
+    fn simulator() -> Simulator {
+        let mut arm = Arm::default();
+        arm.interpolate(RESTING, RESTING, 0.);
+        Simulator::new(&SimConfig::default(), arm.to_servos())
+    }
+
+    /// Run the robot against the simulator for `seconds` like `main` does
+    fn run(robo: &mut Robot, sim: &mut Simulator, seconds: f64) {
+        for _ in 0..(seconds / 0.01) as usize {
+            let feedback = Frame::Feedback(sim.feedback()).encode_with(
+                robo.connection.checksum,
+                robo.connection.servo_encoding,
+                0,
+            );
+            robo.connection.receive(&feedback);
+            if robo.update(0.01).unwrap().transmitted {
+                sim.command(robo.arm.to_servos());
+            }
+            sim.step(0.01);
+        }
+    }
+
+    fn close(actual: JointAngles, expected: JointAngles) {
+        let off = [
+            actual.base - expected.base,
+            actual.shoulder - expected.shoulder,
+            actual.elbow - expected.elbow,
+            actual.claw - expected.claw,
+        ];
+        assert!(
+            off.iter().all(|off| off.abs() < 0.5),
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn hold() {
+        let mut robo = robot(StartupConfig::default());
+        let mut sim = simulator();
+        run(&mut robo, &mut sim, 2.);
+
+        // the servos stay where they were and the head is held there
+        close(robo.arm.angles(), RESTING);
+        close(robo.arm.angles_from_servos(sim.feedback().pulses), RESTING);
+        assert_eq!(robo.target_position, None);
+        assert!((robo.segments().head - robo.position).dst() < 0.5);
+        assert_eq!(robo.startup, None);
+
+        // without feedback they are taken to rest in the neutral pose once the wait is up
+        let mut robo = robot(StartupConfig::default());
+        for _ in 0..200 {
+            robo.tick(0.01);
+        }
+        close(robo.arm.angles(), NEUTRAL);
+        assert_eq!(robo.startup, None);
+    }
+
+    #[test]
+    fn late_feedback() {
+        // the first ticks go by before the servos report, nothing is sent meanwhile
+        let mut robo = robot(StartupConfig::default());
+        for _ in 0..20 {
+            assert!(!robo.update(0.01).unwrap().transmitted);
+        }
+        assert!(robo.startup.is_some());
+
+        // they are still held where they report once they do
+        let mut sim = simulator();
+        run(&mut robo, &mut sim, 2.);
+        close(robo.arm.angles(), RESTING);
+        close(robo.arm.angles_from_servos(sim.feedback().pulses), RESTING);
+        assert_eq!(robo.startup, None);
+    }
+
+    #[test]
+    fn home() {
+        let mut robo = robot(StartupConfig {
+            home: true,
+            ..Default::default()
+        });
+        let mut sim = simulator();
+        run(&mut robo, &mut sim, 2.);
+
+        // the claw isn't part of it
+        let home = JointAngles {
+            claw: RESTING.claw,
+            ..NEUTRAL
+        };
+        close(robo.arm.angles(), home);
+        close(robo.arm.angles_from_servos(sim.feedback().pulses), home);
+    }
+
+    #[test]
+    fn target() {
+        let target = CordinateVec::new(50., 100., 80.);
+        let mut robo = robot(StartupConfig {
+            target: Some(target),
+            ..Default::default()
+        });
+        let mut sim = simulator();
+        run(&mut robo, &mut sim, 10.);
+
+        assert_eq!(robo.target_position, None);
+        assert!((robo.position - target).dst() < 0.5, "{:?}", robo.position);
+        assert!(robo
+            .take_events()
+            .iter()
+            .any(|event| matches!(event.event, RobotEvent::TargetReached(_))));
+        assert!((robo.arm.claw.angle - RESTING.claw).abs() < 0.5);
+    }
+
+    #[test]
+    fn claw() {
+        let mut robo = robot(StartupConfig {
+            claw: Some(ClawAction::Open),
+            ..Default::default()
+        });
+        let mut sim = simulator();
+        run(&mut robo, &mut sim, 3.);
+
+        // the rest of the arm holds
+        let opened = JointAngles {
+            claw: robo.arm.claw.max,
+            ..RESTING
+        };
+        close(robo.arm.angles(), opened);
+        close(robo.arm.angles_from_servos(sim.feedback().pulses), opened);
+    }
+
+    #[test]
+    fn detached() {
+        let mut robo = robot(StartupConfig {
+            mode: StartupMode::Detached,
+            ..Default::default()
+        });
+        let mut sim = simulator();
+        run(&mut robo, &mut sim, 1.);
+        assert_eq!(robo.idle.state, IdleState::Detached);
+        assert_eq!(robo.soft_start, None);
+        close(robo.arm.angles_from_servos(sim.feedback().pulses), RESTING);
+
+        // moving the arm attaches the servos, which hold where they are
+        robo.command_velocity(CordinateVec::new(1., 0., 0.));
+        run(&mut robo, &mut sim, 2.);
+        assert!(matches!(robo.idle.state, IdleState::Attached { .. }));
+        close(robo.arm.angles(), RESTING);
+        close(robo.arm.angles_from_servos(sim.feedback().pulses), RESTING);
+    }
+}
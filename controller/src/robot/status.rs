@@ -0,0 +1,163 @@
+use std::time::{Duration, Instant};
+
+use crate::protocol::FirmwareStatus;
+
+/// Keeps track of when to ask the arduino for its status and how old the last answer is
+#[derive(Debug)]
+pub struct StatusPoller {
+    /// How often to query the status, `None` to only query when requested
+    pub interval: Option<Duration>,
+
+    /// A status older than this is shown as stale
+    pub stale_after: Duration,
+
+    /// Set to query the status on the next update regardless of the interval
+    pub requested: bool,
+
+    /// When the last query was sent
+    pub last_query: Option<Instant>,
+
+    /// Latest status and when it was received
+    pub latest: Option<(FirmwareStatus, Instant)>,
+}
+
+/// What should be shown for the firmware status
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FirmwareStatusView {
+    /// No status has been received yet
+    Missing,
+
+    /// The last status is too old to be trusted
+    Stale { age: Duration },
+
+    Current(FirmwareStatus),
+}
+
+impl StatusPoller {
+    /// # Arguments
+    /// * `interval` - How often to query the status, `None` to only query when requested
+    /// * `stale_after` - How old a status can be before it is shown as stale
+    pub fn new(interval: Option<Duration>, stale_after: Duration) -> Self {
+        Self {
+            interval,
+            stale_after,
+            requested: false,
+            last_query: None,
+            latest: None,
+        }
+    }
+
+    /// Query the status on the next update
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// True if a status query should be sent now
+    pub fn due(&self, now: Instant) -> bool {
+        if self.requested {
+            return true;
+        }
+
+        match (self.interval, self.last_query) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(interval), Some(last)) => now.saturating_duration_since(last) >= interval,
+        }
+    }
+
+    /// Call after a status query has been sent
+    pub fn queried(&mut self, now: Instant) {
+        self.requested = false;
+        self.last_query = Some(now);
+    }
+
+    /// Call when a status frame is received
+    pub fn received(&mut self, status: FirmwareStatus, now: Instant) {
+        self.latest = Some((status, now));
+    }
+
+    pub fn view(&self, now: Instant) -> FirmwareStatusView {
+        match self.latest {
+            None => FirmwareStatusView::Missing,
+            Some((status, received)) => {
+                let age = now.saturating_duration_since(received);
+                if age > self.stale_after {
+                    FirmwareStatusView::Stale { age }
+                } else {
+                    FirmwareStatusView::Current(status)
+                }
+            }
+        }
+    }
+}
+
+impl Default for StatusPoller {
+    fn default() -> Self {
+        Self::new(Some(Duration::from_secs(5)), Duration::from_secs(15))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn periodic_queries() {
+        let start = Instant::now();
+        let mut poller = StatusPoller::new(Some(Duration::from_secs(5)), Duration::from_secs(15));
+
+        // query right away, then wait for the interval
+        assert!(poller.due(start));
+        poller.queried(start);
+        assert!(!poller.due(start + Duration::from_secs(4)));
+        assert!(poller.due(start + Duration::from_secs(5)));
+
+        // a manual request doesn't wait
+        poller.queried(start + Duration::from_secs(5));
+        poller.request();
+        assert!(poller.due(start + Duration::from_secs(6)));
+        poller.queried(start + Duration::from_secs(6));
+        assert!(!poller.due(start + Duration::from_secs(7)));
+        assert!(poller.due(start + Duration::from_secs(11)));
+    }
+
+    #[test]
+    fn manual_only() {
+        let start = Instant::now();
+        let mut poller = StatusPoller::new(None, Duration::from_secs(15));
+
+        assert!(!poller.due(start));
+        assert!(!poller.due(start + Duration::from_secs(1000)));
+
+        poller.request();
+        assert!(poller.due(start));
+        poller.queried(start);
+        assert!(!poller.due(start));
+    }
+
+    #[test]
+    fn stale_status() {
+        let start = Instant::now();
+        let mut poller = StatusPoller::new(None, Duration::from_secs(15));
+        let status = FirmwareStatus {
+            uptime_ms: 1000,
+            millivolts: 5000,
+            loop_hz: 500,
+            last_seq: 3,
+        };
+
+        assert_eq!(poller.view(start), FirmwareStatusView::Missing);
+
+        poller.received(status, start);
+        assert_eq!(
+            poller.view(start + Duration::from_secs(15)),
+            FirmwareStatusView::Current(status)
+        );
+        assert_eq!(
+            poller.view(start + Duration::from_secs(16)),
+            FirmwareStatusView::Stale {
+                age: Duration::from_secs(16)
+            }
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::{fmt, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::{events::RobotEvent, shutdown::ShutdownReason, Robot};
+use crate::logging::{info, warn};
+
+/// When the servo supply counts as low, see [`SupplyMonitor`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SupplyConfig {
+    /// Watch the supply at all, the robot only gets a monitor if this is set
+    pub enabled: bool,
+
+    /// At or below this many millivolts the servos get weak, see [`SupplyLevel::Low`]
+    pub low: u16,
+
+    /// At or below this many millivolts the arm is shut down, see [`SupplyLevel::Critical`]
+    pub critical: u16,
+
+    /// Millivolts above a threshold the supply has to recover to before it counts as above
+    /// it again, so a supply sagging under load doesn't flap between levels
+    pub hysteresis: u16,
+
+    /// Acceleration is scaled by this while the supply is low, 1 to keep it
+    pub low_acceleration: f64,
+}
+
+/// How good the supply is
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum SupplyLevel {
+    #[default]
+    Normal,
+
+    /// The acceleration is scaled down, see [`SupplyConfig::low_acceleration`]
+    Low,
+
+    /// The arm is shut down and its servos are detached until the supply recovers, see
+    /// [`Robot::begin_shutdown`]
+    Critical,
+}
+
+/// Keeps track of the servo supply voltage the arduino reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupplyMonitor {
+    pub config: SupplyConfig,
+    pub level: SupplyLevel,
+
+    /// Latest reported voltage, `None` until the first report
+    pub millivolts: Option<u16>,
+}
+
+impl SupplyMonitor {
+    pub fn new(config: SupplyConfig) -> Self {
+        Self {
+            config,
+            level: SupplyLevel::Normal,
+            millivolts: None,
+        }
+    }
+
+    /// The arduino reported the supply at `millivolts`
+    ///
+    /// Dropping to or below a threshold changes the level right away, getting back above one
+    /// only once the voltage is [`SupplyConfig::hysteresis`] past it
+    ///
+    /// # Returns
+    /// The new level if it changed
+    pub fn report(&mut self, millivolts: u16) -> Option<SupplyLevel> {
+        self.millivolts = Some(millivolts);
+
+        let config = &self.config;
+        let recovered = |threshold: u16| millivolts > threshold.saturating_add(config.hysteresis);
+        let level = if millivolts <= config.critical
+            || (self.level == SupplyLevel::Critical && !recovered(config.critical))
+        {
+            SupplyLevel::Critical
+        } else if millivolts <= config.low
+            || (self.level != SupplyLevel::Normal && !recovered(config.low))
+        {
+            SupplyLevel::Low
+        } else {
+            SupplyLevel::Normal
+        };
+
+        if level == self.level {
+            return None;
+        }
+        self.level = level;
+        Some(level)
+    }
+
+    /// What the acceleration is scaled by at this level
+    pub fn acceleration_scale(&self) -> f64 {
+        match self.level {
+            SupplyLevel::Normal => 1.,
+            SupplyLevel::Low | SupplyLevel::Critical => self.config.low_acceleration,
+        }
+    }
+}
+
+impl Robot {
+    /// The arduino reported the supply voltage, act on the level changing
+    pub(super) fn supply_report(&mut self, millivolts: u16) {
+        let Some(supply) = &mut self.supply else {
+            return;
+        };
+        let Some(level) = supply.report(millivolts) else {
+            return;
+        };
+
+        let volts = millivolts as f64 / 1000.;
+        match level {
+            SupplyLevel::Normal => info(&format!("Supply back up at {volts:.2}V")),
+            SupplyLevel::Low => warn(&format!(
+                "Supply low at {volts:.2}V, the servos may get weak"
+            )),
+            SupplyLevel::Critical => warn(&format!("Supply critical at {volts:.2}V")),
+        }
+        self.events.push(RobotEvent::Supply(level), Instant::now());
+        match level {
+            SupplyLevel::Critical => self.begin_shutdown(ShutdownReason::Supply),
+            _ => {
+                self.end_shutdown(ShutdownReason::Supply);
+            }
+        }
+    }
+
+    /// What [`Robot::acceleration`] is scaled by for the supply
+    pub(super) fn supply_scale(&self) -> f64 {
+        self.supply
+            .as_ref()
+            .map_or(1., SupplyMonitor::acceleration_scale)
+    }
+}
+
+impl Default for SupplyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            low: 4800,
+            critical: 4500,
+            hysteresis: 150,
+            low_acceleration: 0.5,
+        }
+    }
+}
+
+impl fmt::Display for SupplyLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SupplyLevel::Normal => write!(f, "normal"),
+            SupplyLevel::Low => write!(f, "low"),
+            SupplyLevel::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        kinematics::position::CordinateVec,
+        robot::{
+            idle::IdleState,
+            shutdown::{ShutdownBehavior, ShutdownConfig},
+        },
+    };
+
+    fn monitor() -> SupplyMonitor {
+        SupplyMonitor::new(SupplyConfig {
+            enabled: true,
+            ..Default::default()
+        })
+    }
+
+    /// Feed a trace of readings, the level after each
+    fn levels(monitor: &mut SupplyMonitor, trace: &[u16]) -> Vec<SupplyLevel> {
+        trace
+            .iter()
+            .map(|&millivolts| {
+                monitor.report(millivolts);
+                monitor.level
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sagging_under_load() {
+        use SupplyLevel::*;
+
+        // dips below the threshold while moving, it takes 4950 to count as recovered
+        let mut supply = monitor();
+        assert_eq!(
+            levels(
+                &mut supply,
+                &[5000, 4820, 4790, 4850, 4790, 4900, 4940, 4960, 4900]
+            ),
+            [Normal, Normal, Low, Low, Low, Low, Low, Normal, Normal]
+        );
+        assert_eq!(supply.millivolts, Some(4900));
+
+        // only the changes are reported
+        let mut supply = monitor();
+        assert_eq!(supply.report(5000), None);
+        assert_eq!(supply.report(4800), Some(Low));
+        assert_eq!(supply.report(4900), None);
+        assert_eq!(supply.report(5000), Some(Normal));
+    }
+
+    #[test]
+    fn critical() {
+        use SupplyLevel::*;
+
+        let mut supply = monitor();
+        assert_eq!(
+            levels(&mut supply, &[4700, 4500, 4600, 4640, 4660, 4900, 4960]),
+            [Low, Critical, Critical, Critical, Low, Low, Normal]
+        );
+
+        // straight down and straight back up
+        let mut supply = monitor();
+        assert_eq!(levels(&mut supply, &[4400, 5000]), [Critical, Normal]);
+        assert_eq!(supply.acceleration_scale(), 1.);
+        supply.report(4400);
+        assert_eq!(supply.acceleration_scale(), 0.5);
+    }
+
+    #[test]
+    fn low_acceleration() {
+        let mut robo = Robot {
+            position: CordinateVec::new(100., 50., 50.),
+            supply: Some(monitor()),
+            ..Default::default()
+        };
+
+        robo.supply_report(4700);
+        robo.command_velocity(CordinateVec::new(10., 0., 0.));
+        robo.tick(0.01);
+        assert!((robo.velocity.x - robo.acceleration * 0.5 * 0.01).abs() < 1e-9);
+
+        // back to full acceleration once recovered
+        robo.supply_report(5000);
+        robo.tick(0.01);
+        assert!((robo.velocity.x - robo.acceleration * 1.5 * 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn critical_shutdown() {
+        let mut robo = Robot {
+            position: CordinateVec::new(20., 50., 50.),
+            supply: Some(monitor()),
+            on_shutdown: ShutdownConfig {
+                behavior: ShutdownBehavior::Rest,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rest = CordinateVec::new(45., 75., 75.);
+        robo.program.poses.insert("rest".to_string(), rest);
+
+        // critical goes to the rest pose whatever the operator wants, then detaches
+        robo.supply_report(4400);
+        assert_eq!(robo.shutdown.unwrap().reason, ShutdownReason::Supply);
+        for _ in 0..2000 {
+            robo.command_velocity(CordinateVec::new(10., 0., 0.));
+            robo.tick(0.01);
+            if robo.idle.holding() {
+                break;
+            }
+        }
+        assert_eq!(robo.position, rest);
+        assert_eq!(robo.idle.state, IdleState::Detached);
+        let events: Vec<_> = robo
+            .events
+            .take()
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        assert_eq!(events, [RobotEvent::Supply(SupplyLevel::Critical)]);
+
+        // and stays detached until the supply recovers
+        robo.command_velocity(CordinateVec::new(10., 0., 0.));
+        robo.supply_report(4600);
+        for _ in 0..10 {
+            robo.tick(0.01);
+        }
+        assert_eq!(robo.idle.state, IdleState::Detached);
+        robo.supply_report(4700);
+        assert_eq!(robo.shutdown, None);
+        robo.tick(0.01);
+        assert!(matches!(robo.idle.state, IdleState::Settling { .. }));
+    }
+}
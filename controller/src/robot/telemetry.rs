@@ -0,0 +1,463 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+};
+
+use super::{
+    arm::{JointAngles, JOINTS},
+    estop::StopStage,
+    Robot, RobotState,
+};
+use crate::{
+    kinematics::{operator, position::CordinateVec},
+    session,
+};
+
+/// Columns of a [`TelemetryRecord`], the joint angles are operator angles, see
+/// [`operator`]
+const HEADER: &str = "time,x,y,z,target_x,target_y,target_z,vx,vy,vz,jog_x,jog_y,jog_z,\
+                      detached,emergency_stop,stalled,ik_failed,faults,\
+                      base_op,shoulder_op,elbow_op,claw_op";
+
+/// What the arm was doing at one frame, enough of the [`RobotState`] to show it again
+///
+/// Written as a comma separated line, an empty field is `None`
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct TelemetryRecord {
+    /// Seconds into the session, see [`session::clock`]
+    pub time: f64,
+
+    pub position: CordinateVec,
+    pub target_position: Option<CordinateVec>,
+    pub velocity: CordinateVec,
+    pub target_velocity: CordinateVec,
+    pub detached: bool,
+    pub emergency_stop: Option<StopStage>,
+    pub stalled: Option<&'static str>,
+
+    /// No joint angles were found for the position this frame
+    pub ik_failed: bool,
+
+    pub faults: u64,
+
+    /// Commanded joint angles as the kinematics use them
+    pub angles: JointAngles,
+}
+
+/// Something worth jumping to in a recorded session, see [`EventIndex`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TelemetryEvent {
+    EmergencyStop,
+    IkFailed,
+    Stall(&'static str),
+}
+
+/// Where the [`TelemetryEvent`]s of a recorded session are
+///
+/// An event is at the frame it starts, an emergency stop held for a second is one event
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventIndex {
+    /// Frame of every event in order
+    pub events: Vec<(usize, TelemetryEvent)>,
+}
+
+/// Records the [`TelemetryRecord`] of every frame to a file, see [`Robot::update`]
+///
+/// The file starts with a `# session <id>` line before the header, like the
+/// [`super::segment_stream`]
+#[derive(Debug)]
+pub struct TelemetryWriter {
+    file: BufWriter<File>,
+}
+
+#[derive(Debug)]
+pub enum TelemetryError {
+    Io(io::Error),
+
+    /// The file doesn't have the columns of a [`TelemetryRecord`]
+    Header,
+
+    /// A line couldn't be read
+    Record {
+        line: usize,
+        field: &'static str,
+    },
+
+    /// There are no records
+    Empty,
+}
+
+impl TelemetryRecord {
+    /// The record of `state` at `time`
+    pub fn new(time: f64, state: &RobotState, ik_failed: bool) -> Self {
+        Self {
+            time,
+            position: state.position,
+            target_position: state.target_position,
+            velocity: state.velocity,
+            target_velocity: state.target_velocity,
+            detached: state.detached,
+            emergency_stop: state.emergency_stop,
+            stalled: state.stalled,
+            ik_failed,
+            faults: state.faults,
+            angles: state.angles,
+        }
+    }
+
+    /// Put what was recorded over `state`, what isn't recorded is left as it is
+    pub fn apply(&self, state: &mut RobotState) {
+        state.position = self.position;
+        state.target_position = self.target_position;
+        state.velocity = self.velocity;
+        state.target_velocity = self.target_velocity;
+        state.detached = self.detached;
+        state.emergency_stop = self.emergency_stop;
+        state.stalled = self.stalled;
+        state.faults = self.faults;
+        state.angles = self.angles;
+    }
+
+    /// Read a record from a line of the file, see [`TelemetryRecord::line`]
+    ///
+    /// # Returns
+    /// The column that couldn't be read on failure
+    pub fn parse(line: &str) -> Result<Self, &'static str> {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let column = |i: usize| HEADER.split(',').nth(i).unwrap_or("time");
+        if fields.len() != HEADER.split(',').count() {
+            return Err(column(fields.len().min(HEADER.split(',').count() - 1)));
+        }
+
+        let number = |i: usize| fields[i].parse::<f64>().map_err(|_| column(i));
+        let vector = |i: usize| -> Result<CordinateVec, &'static str> {
+            Ok(CordinateVec::new(
+                number(i)?,
+                number(i + 1)?,
+                number(i + 2)?,
+            ))
+        };
+        let flag = |i: usize| match fields[i] {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => Err(column(i)),
+        };
+
+        Ok(Self {
+            time: number(0)?,
+            position: vector(1)?,
+            target_position: if fields[4..7].iter().all(|field| field.is_empty()) {
+                None
+            } else {
+                Some(vector(4)?)
+            },
+            velocity: vector(7)?,
+            target_velocity: vector(10)?,
+            detached: flag(13)?,
+            emergency_stop: match fields[14] {
+                "" => None,
+                "braking" => Some(StopStage::Braking),
+                "halted" => Some(StopStage::Halted),
+                "detached" => Some(StopStage::Detached),
+                _ => return Err(column(14)),
+            },
+            stalled: match fields[15] {
+                "" => None,
+                joint => Some(
+                    JOINTS
+                        .into_iter()
+                        .find(|&name| name == joint)
+                        .ok_or(column(15))?,
+                ),
+            },
+            ik_failed: flag(16)?,
+            faults: fields[17].parse().map_err(|_| column(17))?,
+            angles: operator::from_operator(JointAngles {
+                base: number(18)?,
+                shoulder: number(19)?,
+                elbow: number(20)?,
+                claw: number(21)?,
+            }),
+        })
+    }
+
+    /// The record as a line of the file, without the line break
+    pub fn line(&self) -> String {
+        let vector =
+            |vector: CordinateVec| format!("{:.3},{:.3},{:.3}", vector.x, vector.y, vector.z);
+        let angles = operator::to_operator(self.angles);
+        format!(
+            "{:.3},{},{},{},{},{},{},{},{},{},{:.3},{:.3},{:.3},{:.3}",
+            self.time,
+            vector(self.position),
+            self.target_position.map_or(",,".to_string(), vector),
+            vector(self.velocity),
+            vector(self.target_velocity),
+            self.detached as u8,
+            match self.emergency_stop {
+                None => "",
+                Some(StopStage::Braking) => "braking",
+                Some(StopStage::Halted) => "halted",
+                Some(StopStage::Detached) => "detached",
+            },
+            self.stalled.unwrap_or(""),
+            self.ik_failed as u8,
+            self.faults,
+            angles.base,
+            angles.shoulder,
+            angles.elbow,
+            angles.claw,
+        )
+    }
+}
+
+impl EventIndex {
+    /// Find the events in `records`
+    pub fn new(records: &[TelemetryRecord]) -> Self {
+        let mut events = Vec::new();
+        let mut previous = TelemetryRecord::default();
+        for (frame, record) in records.iter().enumerate() {
+            if record.emergency_stop.is_some() && previous.emergency_stop.is_none() {
+                events.push((frame, TelemetryEvent::EmergencyStop));
+            }
+            if record.ik_failed && !previous.ik_failed {
+                events.push((frame, TelemetryEvent::IkFailed));
+            }
+            if let Some(joint) = record.stalled.filter(|_| previous.stalled.is_none()) {
+                events.push((frame, TelemetryEvent::Stall(joint)));
+            }
+            previous = *record;
+        }
+
+        Self { events }
+    }
+
+    /// The first event after `frame`
+    pub fn next(&self, frame: usize) -> Option<(usize, TelemetryEvent)> {
+        self.events.iter().copied().find(|&(at, _)| at > frame)
+    }
+
+    /// The last event before `frame`
+    pub fn previous(&self, frame: usize) -> Option<(usize, TelemetryEvent)> {
+        self.events
+            .iter()
+            .copied()
+            .rev()
+            .find(|&(at, _)| at < frame)
+    }
+
+    /// The events starting at `frame`
+    pub fn at(&self, frame: usize) -> impl Iterator<Item = TelemetryEvent> + '_ {
+        self.events
+            .iter()
+            .filter(move |&&(at, _)| at == frame)
+            .map(|&(_, event)| event)
+    }
+}
+
+impl TelemetryWriter {
+    /// Start recording to `path`, replacing what was there
+    pub fn create(path: &str) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "# session {}", session::clock().id)?;
+        writeln!(file, "{HEADER}")?;
+
+        Ok(Self { file })
+    }
+
+    pub fn write(&mut self, record: &TelemetryRecord) -> io::Result<()> {
+        writeln!(self.file, "{}", record.line())
+    }
+
+    /// Write out what is buffered
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Read a file written by a [`TelemetryWriter`]
+pub fn load(path: &str) -> Result<Vec<TelemetryRecord>, TelemetryError> {
+    parse(&fs::read_to_string(path)?)
+}
+
+/// Read the records of a file, the `#` lines are skipped
+pub fn parse(data: &str) -> Result<Vec<TelemetryRecord>, TelemetryError> {
+    let mut lines = data
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('#') && !line.trim().is_empty());
+    if lines.next().map(|(_, header)| header.trim()) != Some(HEADER) {
+        return Err(TelemetryError::Header);
+    }
+
+    let records = lines
+        .map(|(i, line)| {
+            TelemetryRecord::parse(line)
+                .map_err(|field| TelemetryError::Record { line: i + 1, field })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if records.is_empty() {
+        return Err(TelemetryError::Empty);
+    }
+
+    Ok(records)
+}
+
+impl Robot {
+    /// Record the frame that was just ticked, see [`Robot::telemetry`]
+    pub(super) fn record_telemetry(&mut self, ik_failed: bool) {
+        let Some(mut writer) = self.telemetry.take() else {
+            return;
+        };
+
+        let record = TelemetryRecord::new(session::clock().now(), &self.state(), ik_failed);
+        match writer.write(&record) {
+            Ok(()) => self.telemetry = Some(writer),
+            Err(err) => crate::logging::warn(&format!("Stopped recording telemetry: {err}")),
+        }
+    }
+}
+
+impl fmt::Display for TelemetryEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelemetryEvent::EmergencyStop => write!(f, "emergency stop"),
+            TelemetryEvent::IkFailed => write!(f, "inverse kinematics failed"),
+            TelemetryEvent::Stall(joint) => write!(f, "{joint} stalled"),
+        }
+    }
+}
+
+impl fmt::Display for TelemetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelemetryError::Io(err) => write!(f, "{err}"),
+            TelemetryError::Header => write!(f, "not a telemetry file, the header doesn't match"),
+            TelemetryError::Record { line, field } => {
+                write!(f, "line {line}: could not read `{field}`")
+            }
+            TelemetryError::Empty => write!(f, "no records"),
+        }
+    }
+}
+
+impl From<io::Error> for TelemetryError {
+    fn from(err: io::Error) -> Self {
+        TelemetryError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(time: f64) -> TelemetryRecord {
+        TelemetryRecord {
+            time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut robo = Robot {
+            position: CordinateVec::new(10., -20.5, 30.25),
+            target_position: Some(CordinateVec::new(40., 50., 60.)),
+            velocity: CordinateVec::new(1., 2., 3.),
+            stalled: Some("elbow"),
+            faults: 2,
+            ..Default::default()
+        };
+        robo.estop.stage = Some(StopStage::Halted);
+        let angles = JointAngles {
+            base: 120.5,
+            shoulder: 30.,
+            elbow: 100.,
+            claw: 45.,
+        };
+        robo.arm.interpolate(angles, angles, 0.);
+
+        let recorded = TelemetryRecord::new(1.5, &robo.state(), true);
+        let path = std::env::temp_dir().join(format!("rac-telemetry-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut writer = TelemetryWriter::create(path).unwrap();
+        for record in [record(1.), recorded] {
+            writer.write(&record).unwrap();
+        }
+        writer.flush().unwrap();
+        let loaded = load(path);
+        let _ = fs::remove_file(path);
+        assert_eq!(loaded.unwrap(), [record(1.), recorded]);
+
+        // what was recorded shows as it was
+        let mut state = Robot::default().state();
+        recorded.apply(&mut state);
+        assert_eq!(state.target_position, robo.target_position);
+        assert_eq!(state.emergency_stop, Some(StopStage::Halted));
+        assert_eq!(state.stalled, Some("elbow"));
+        assert_eq!(state.faults, 2);
+        assert_eq!(state.angles, angles);
+
+        // the file has the angles the way the operator measures them
+        assert!(recorded.line().ends_with(",30.500,60.000,80.000,45.000"));
+    }
+
+    #[test]
+    fn malformed() {
+        let line = record(0.).line();
+        assert!(matches!(parse(""), Err(TelemetryError::Header)));
+        assert!(matches!(parse(HEADER), Err(TelemetryError::Empty)));
+        assert!(matches!(
+            parse(&format!("time,x\n{line}")),
+            Err(TelemetryError::Header)
+        ));
+
+        // the line number counts the session line
+        let bad = line.replace(",,,0,0", ",,,maybe,0");
+        match parse(&format!("# session 1\n{HEADER}\n{line}\n{bad}")) {
+            Err(TelemetryError::Record { line, field }) => {
+                assert_eq!((line, field), (4, "ik_failed"))
+            }
+            other => panic!("{other:?}"),
+        }
+        assert_eq!(
+            TelemetryRecord::parse(&line.replace(",,,0,0", ",,wrist,0,0")),
+            Err("stalled")
+        );
+        // the first missing column
+        assert_eq!(TelemetryRecord::parse("1,2,3"), Err("z"));
+    }
+
+    #[test]
+    fn event_index() {
+        let mut records = vec![record(0.); 10];
+        records[2].emergency_stop = Some(StopStage::Braking);
+        records[3].emergency_stop = Some(StopStage::Halted);
+        records[5].ik_failed = true;
+        records[6].ik_failed = true;
+        records[6].stalled = Some("base");
+        records[7].stalled = Some("base");
+        records[8].ik_failed = true;
+
+        let index = EventIndex::new(&records);
+        assert_eq!(
+            index.events,
+            [
+                (2, TelemetryEvent::EmergencyStop),
+                (5, TelemetryEvent::IkFailed),
+                (6, TelemetryEvent::Stall("base")),
+                (8, TelemetryEvent::IkFailed),
+            ]
+        );
+        assert_eq!(index.next(0), Some((2, TelemetryEvent::EmergencyStop)));
+        assert_eq!(index.next(2), Some((5, TelemetryEvent::IkFailed)));
+        assert_eq!(index.next(8), None);
+        assert_eq!(index.previous(6).map(|(at, _)| at), Some(5));
+        assert_eq!(index.previous(2), None);
+        assert_eq!(
+            index.at(6).collect::<Vec<_>>(),
+            [TelemetryEvent::Stall("base")]
+        );
+    }
+}
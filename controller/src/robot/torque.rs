@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+use super::arm::JointAngles;
+
+/// Slows the arm down in poses where gravity loads the shoulder or elbow close to what the
+/// servos are rated for, so they don't sag and miss positions
+///
+/// Torques are estimated from the masses and the horizontal lever arms in kg·units, so a servo
+/// rated at 10 kg·cm is rated at 100 kg·units if a unit is a millimeter
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TorqueLimit {
+    /// Slow down at all, the robot only gets the policy if this is set
+    pub enabled: bool,
+
+    /// Mass of the upper arm in kg, taken to be at its middle
+    pub upper_mass: f64,
+
+    /// Mass of the lower arm in kg, taken to be at its middle
+    pub lower_mass: f64,
+
+    /// Mass of the claw and what it holds in kg, at the head
+    pub payload: f64,
+
+    /// What the servos are rated for in kg·units
+    pub shoulder_rating: f64,
+    pub elbow_rating: f64,
+
+    /// Share of the rating from which the arm slows down
+    pub start: f64,
+
+    /// Speed and acceleration factor at the full rating and beyond
+    pub min_scale: f64,
+}
+
+impl TorqueLimit {
+    /// Estimated gravity torque on the shoulder and the elbow in kg·units
+    ///
+    /// # Arguments
+    /// * `angles` - the shoulder measured from the z axis and the elbow between the arms
+    pub fn torques(&self, angles: JointAngles, upper_arm: f64, lower_arm: f64) -> (f64, f64) {
+        let shoulder = angles.shoulder.to_radians();
+        // the lower arm bends back towards the z axis by however much the elbow isn't straight
+        let lower = shoulder - (180. - angles.elbow).to_radians();
+
+        let elbow_reach = upper_arm * shoulder.sin();
+        let lower_reach = lower_arm * lower.sin();
+
+        let elbow_torque = self.lower_mass * lower_reach / 2. + self.payload * lower_reach;
+        let shoulder_torque = self.upper_mass * elbow_reach / 2.
+            + (self.lower_mass + self.payload) * elbow_reach
+            + elbow_torque;
+
+        (shoulder_torque.abs(), elbow_torque.abs())
+    }
+
+    /// The same arm with `grams` more in the claw
+    pub fn carrying(&self, grams: f64) -> Self {
+        Self {
+            payload: self.payload + grams.max(0.) / 1000.,
+            ..*self
+        }
+    }
+
+    /// Highest share of its rating any servo is loaded with
+    pub fn load(&self, angles: JointAngles, upper_arm: f64, lower_arm: f64) -> f64 {
+        let (shoulder, elbow) = self.torques(angles, upper_arm, lower_arm);
+        (shoulder / self.shoulder_rating).max(elbow / self.elbow_rating)
+    }
+
+    /// Factor for the speed and acceleration in this pose
+    ///
+    /// 1 up to [`TorqueLimit::start`] of the rating, then falls linearly to
+    /// [`TorqueLimit::min_scale`] at the full rating so the arm doesn't jerk when it crosses
+    pub fn scale(&self, angles: JointAngles, upper_arm: f64, lower_arm: f64) -> f64 {
+        let load = self.load(angles, upper_arm, lower_arm);
+        let over = ((load - self.start) / (1. - self.start)).clamp(0., 1.);
+
+        1. - over * (1. - self.min_scale)
+    }
+}
+
+impl Default for TorqueLimit {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            upper_mass: 0.1,
+            lower_mass: 0.1,
+            payload: 0.2,
+            shoulder_rating: 100.,
+            elbow_rating: 100.,
+            start: 0.5,
+            min_scale: 0.2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pose(shoulder: f64, elbow: f64) -> JointAngles {
+        JointAngles {
+            shoulder,
+            elbow,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn torques() {
+        let limit = TorqueLimit {
+            upper_mass: 0.2,
+            lower_mass: 0.1,
+            payload: 0.5,
+            ..Default::default()
+        };
+
+        // straight up nothing is levered
+        let (shoulder, elbow) = limit.torques(pose(0., 180.), 100., 100.);
+        assert!(shoulder < 1e-9 && elbow < 1e-9);
+
+        // straight out everything is
+        let (shoulder, elbow) = limit.torques(pose(90., 180.), 100., 100.);
+        assert!((shoulder - (0.2 * 50. + 0.1 * 150. + 0.5 * 200.)).abs() < 1e-9);
+        assert!((elbow - (0.1 * 50. + 0.5 * 100.)).abs() < 1e-9);
+
+        // upper arm out, lower arm hanging straight down
+        let (_, elbow) = limit.torques(pose(90., 90.), 100., 100.);
+        assert!(elbow < 1e-9);
+    }
+
+    #[test]
+    fn scale_from_vertical_to_extended() {
+        let limit = TorqueLimit {
+            upper_mass: 0.2,
+            lower_mass: 0.1,
+            payload: 0.5,
+            shoulder_rating: 100.,
+            elbow_rating: 100.,
+            start: 0.5,
+            min_scale: 0.25,
+            ..Default::default()
+        };
+
+        let scales: Vec<f64> = (0..=9)
+            .map(|step| limit.scale(pose(step as f64 * 10., 180.), 100., 100.))
+            .collect();
+
+        // full speed while the load is low, slowest straight out where it's 125% of the rating
+        assert_eq!(scales[0], 1.);
+        assert_eq!(scales[1], 1.);
+        assert_eq!(scales[9], 0.25);
+        assert!(scales.windows(2).all(|pair| pair[1] <= pair[0]));
+
+        // no jumps between neighbouring poses
+        assert!(scales.windows(2).all(|pair| pair[0] - pair[1] < 0.3));
+
+        // in between the load grows with the sine of the shoulder angle
+        let load = 1.25 * 40f64.to_radians().sin();
+        let expected = 1. - (load - 0.5) / 0.5 * 0.75;
+        assert!((limit.scale(pose(40., 180.), 100., 100.) - expected).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,380 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::kinematics::{
+    constraint::{Floor, WorkspaceConstraint},
+    position::CordinateVec,
+};
+
+/// Constraints for one place the arm is used, in the robot's own units like
+/// [`super::Robot::position`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Workspace {
+    /// Lowest the head may go, `None` for no floor
+    pub floor: Option<f64>,
+
+    /// Boxes the head must stay out of
+    pub keep_out: Vec<KeepOut>,
+
+    /// Fastest the head may move in units/s, `None` for no cap
+    pub max_speed: Option<f64>,
+}
+
+/// Axis aligned box the head must stay out of, its surface is still allowed
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeepOut {
+    pub min: CordinateVec,
+    pub max: CordinateVec,
+}
+
+/// The workspace profiles and which one is enforced
+///
+/// Selecting a profile the head currently violates doesn't enforce it right away, the profile
+/// is pending until the head has moved to a point that complies, see [`Workspaces::settle`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Workspaces {
+    pub profiles: BTreeMap<String, Workspace>,
+
+    /// Name of the enforced profile, `None` to only be limited by the reach of the arm
+    pub active: Option<String>,
+
+    /// Profile that becomes active once the head complies with it
+    pub pending: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkspaceError {
+    /// There is no profile with this name
+    Unknown(String),
+}
+
+/// How often the floor and the keep out zones are applied in turn, pushing out of one zone can
+/// end up below the floor or in another zone
+const RESOLVE_PASSES: usize = 4;
+
+impl Workspace {
+    /// True if the head may be at `position`
+    pub fn allows(&self, position: CordinateVec) -> bool {
+        self.floor.is_none_or(|floor| position.z >= floor)
+            && !self.keep_out.iter().any(|zone| zone.contains(position))
+    }
+
+    /// Closest position to `position` the head may be at
+    pub fn nearest(&self, mut position: CordinateVec) -> CordinateVec {
+        for _ in 0..RESOLVE_PASSES {
+            if let Some(z) = self.floor {
+                position = Floor { z }.project(position);
+            }
+            for zone in &self.keep_out {
+                if zone.contains(position) {
+                    position = zone.push_out(position, self.floor);
+                }
+            }
+
+            if self.allows(position) {
+                break;
+            }
+        }
+
+        position
+    }
+
+    /// Scale `velocity` down to the speed cap
+    pub fn cap_speed(&self, velocity: CordinateVec) -> CordinateVec {
+        match self.max_speed {
+            Some(cap) if velocity.dst() > cap => velocity.scale_to(cap),
+            _ => velocity,
+        }
+    }
+}
+
+impl KeepOut {
+    /// True if `position` is inside the box, on its surface doesn't count
+    pub fn contains(&self, position: CordinateVec) -> bool {
+        let inside = |value: f64, min: f64, max: f64| min < value && value < max;
+
+        inside(position.x, self.min.x, self.max.x)
+            && inside(position.y, self.min.y, self.max.y)
+            && inside(position.z, self.min.z, self.max.z)
+    }
+
+    /// Move `position` onto the closest face of the box, but not through the floor
+    fn push_out(&self, position: CordinateVec, floor: Option<f64>) -> CordinateVec {
+        let mut faces = [
+            CordinateVec { x: self.min.x, ..position },
+            CordinateVec { x: self.max.x, ..position },
+            CordinateVec { y: self.min.y, ..position },
+            CordinateVec { y: self.max.y, ..position },
+            CordinateVec { z: self.max.z, ..position },
+            CordinateVec { z: self.min.z, ..position },
+        ];
+        // the bottom face is only reachable if it isn't below the floor
+        let faces = if floor.is_none_or(|floor| self.min.z >= floor) {
+            &mut faces[..]
+        } else {
+            &mut faces[..5]
+        };
+
+        faces.sort_by(|a, b| position.distance_to(a).total_cmp(&position.distance_to(b)));
+        faces[0]
+    }
+}
+
+impl WorkspaceConstraint for Workspace {
+    /// Out of the floor and the keep out zones together, see [`Workspace::nearest`]
+    fn project(&self, position: CordinateVec) -> CordinateVec {
+        self.nearest(position)
+    }
+}
+
+impl WorkspaceConstraint for KeepOut {
+    /// Onto the closest face of the box, any face if there is a floor below it
+    fn project(&self, position: CordinateVec) -> CordinateVec {
+        if self.contains(position) {
+            self.push_out(position, None)
+        } else {
+            position
+        }
+    }
+}
+
+impl Workspaces {
+    /// The enforced profile
+    pub fn current(&self) -> Option<&Workspace> {
+        self.profiles.get(self.active.as_ref()?)
+    }
+
+    /// Select a profile, `None` to drop all limits
+    ///
+    /// # Returns
+    /// Where the head has to move before the profile is enforced, `None` if it already complies
+    /// and the profile is active
+    pub fn select(
+        &mut self,
+        name: Option<&str>,
+        position: CordinateVec,
+    ) -> Result<Option<CordinateVec>, WorkspaceError> {
+        let Some(name) = name else {
+            self.active = None;
+            self.pending = None;
+            return Ok(None);
+        };
+
+        if !self.profiles.contains_key(name) {
+            return Err(WorkspaceError::Unknown(name.to_string()));
+        }
+
+        self.pending = Some(name.to_string());
+        Ok(self.settle(position))
+    }
+
+    /// Activate the pending profile if the head complies with it
+    ///
+    /// # Returns
+    /// Where the head has to move first, `None` if nothing is pending anymore
+    pub fn settle(&mut self, position: CordinateVec) -> Option<CordinateVec> {
+        let workspace = self.profiles.get(self.pending.as_ref()?)?;
+        if !workspace.allows(position) {
+            return Some(workspace.nearest(position));
+        }
+
+        self.active = self.pending.take();
+        None
+    }
+
+    /// Name of the profile after the active or pending one, wrapping around
+    pub fn next(&self) -> Option<&str> {
+        let current = self.pending.as_ref().or(self.active.as_ref());
+        let mut names = self.profiles.keys();
+
+        match current {
+            Some(current) => names
+                .clone()
+                .skip_while(|name| *name != current)
+                .nth(1)
+                .or_else(|| names.next()),
+            None => names.next(),
+        }
+        .map(String::as_str)
+    }
+}
+
+impl fmt::Display for WorkspaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkspaceError::Unknown(name) => write!(f, "unknown workspace `{name}`"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::robot::Robot;
+    use kinematics_core::assert_approx_eq;
+
+    fn desk() -> Workspace {
+        serde_json::from_str(
+            r#"{
+                "floor": 20,
+                "keep_out": [{ "min": { "x": -10, "y": 40, "z": 0 }, "max": { "x": 30, "y": 80, "z": 60 } }],
+                "max_speed": 25
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn workspaces() -> Workspaces {
+        Workspaces {
+            profiles: [("desk", desk()), ("table", Workspace::default())]
+                .into_iter()
+                .map(|(name, workspace)| (name.to_string(), workspace))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let desk = desk();
+        assert_eq!(desk.floor, Some(20.));
+        assert_eq!(desk.keep_out[0].max, CordinateVec::new(30., 80., 60.));
+        assert_eq!(desk.max_speed, Some(25.));
+
+        // everything is optional
+        let open: Workspace = serde_json::from_str("{}").unwrap();
+        assert_eq!(open, Workspace::default());
+        assert!(serde_json::from_str::<Workspace>(r#"{ "flor": 1 }"#).is_err());
+    }
+
+    #[test]
+    fn nearest() {
+        let desk = desk();
+
+        // below the floor goes straight up
+        assert_eq!(
+            desk.nearest(CordinateVec::new(50., 50., 5.)),
+            CordinateVec::new(50., 50., 20.)
+        );
+
+        // out of the closest side of the zone
+        assert_eq!(
+            desk.nearest(CordinateVec::new(25., 60., 30.)),
+            CordinateVec::new(30., 60., 30.)
+        );
+        assert!(desk.allows(CordinateVec::new(30., 60., 30.)));
+
+        // the zone reaches below the floor so it's never left through its bottom
+        let nearest = desk.nearest(CordinateVec::new(10., 60., 21.));
+        assert!(desk.allows(nearest));
+        assert!(nearest.z >= 20.);
+
+        assert_eq!(
+            desk.cap_speed(CordinateVec::new(30., 40., 0.)),
+            CordinateVec::new(15., 20., 0.)
+        );
+    }
+
+    #[test]
+    fn select_and_settle() {
+        let mut workspaces = workspaces();
+
+        // violating the new profile, it stays pending until the head is clear
+        let inside = CordinateVec::new(25., 60., 30.);
+        assert_eq!(
+            workspaces.select(Some("desk"), inside),
+            Ok(Some(CordinateVec::new(30., 60., 30.)))
+        );
+        assert_eq!(workspaces.current(), None);
+        assert_eq!(workspaces.settle(inside), Some(CordinateVec::new(30., 60., 30.)));
+
+        assert_eq!(workspaces.settle(CordinateVec::new(30., 60., 30.)), None);
+        assert_eq!(workspaces.active.as_deref(), Some("desk"));
+        assert_eq!(workspaces.current(), Some(&desk()));
+
+        // complying profiles switch right away
+        assert_eq!(workspaces.select(Some("table"), inside), Ok(None));
+        assert_eq!(workspaces.active.as_deref(), Some("table"));
+
+        assert_eq!(
+            workspaces.select(Some("garage"), inside),
+            Err(WorkspaceError::Unknown("garage".to_string()))
+        );
+        assert_eq!(workspaces.select(None, inside), Ok(None));
+        assert_eq!(workspaces.current(), None);
+    }
+
+    /// Everything with x below `x` is out of bounds
+    #[derive(Debug)]
+    struct Wall {
+        x: f64,
+    }
+
+    impl WorkspaceConstraint for Wall {
+        fn project(&self, position: CordinateVec) -> CordinateVec {
+            CordinateVec {
+                x: position.x.max(self.x),
+                ..position
+            }
+        }
+    }
+
+    #[test]
+    fn custom_constraints() {
+        let mut robo = Robot {
+            position: CordinateVec::new(60., 100., 30.),
+            workspaces: Workspaces {
+                active: Some("desk".to_string()),
+                ..workspaces()
+            },
+            constraints: vec![Box::new(Wall { x: 50. })],
+            ..Default::default()
+        };
+
+        // pushing into the wall and the floor slides along the edge where they meet
+        robo.velocity = CordinateVec::new(-20., 10., -20.);
+        for _ in 0..100 {
+            robo.update_position(robo.velocity, 0.01);
+        }
+        let position = robo.position;
+        assert_approx_eq!(position, CordinateVec::new(50., 110., 20.));
+
+        // a wall through the keep out zone, the head goes round both
+        robo.constraints = vec![Box::new(Wall { x: 20. })];
+        robo.position = CordinateVec::new(10., 60., 30.);
+        robo.velocity = CordinateVec::default();
+        robo.update_position(robo.velocity, 0.01);
+        assert_eq!(robo.position, CordinateVec::new(30., 60., 30.));
+
+        // out of reach and behind the wall ends up where the sphere meets the wall
+        robo.constraints = vec![Box::new(Wall { x: 50. })];
+        robo.position = CordinateVec::new(0., 250., 30.);
+        robo.update_position(robo.velocity, 0.01);
+        let reach = robo.upper_arm + robo.lower_arm;
+        assert!((robo.position.x - 50.).abs() < 1e-9);
+        assert!((robo.position.dst() - reach).abs() < 1e-6);
+        assert!(robo.position.y > 0. && robo.position.z > 20.);
+
+        // the same constraints in the same order always end up in the same place
+        let resolved = robo.position;
+        robo.position = CordinateVec::new(0., 250., 30.);
+        robo.update_position(robo.velocity, 0.01);
+        assert_eq!(robo.position, resolved);
+    }
+
+    #[test]
+    fn cycle() {
+        let mut workspaces = workspaces();
+        assert_eq!(workspaces.next(), Some("desk"));
+
+        workspaces.active = Some("desk".to_string());
+        assert_eq!(workspaces.next(), Some("table"));
+
+        workspaces.active = Some("table".to_string());
+        assert_eq!(workspaces.next(), Some("desk"));
+
+        assert_eq!(Workspaces::default().next(), None);
+    }
+}
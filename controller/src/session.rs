@@ -0,0 +1,188 @@
+use std::{
+    fmt,
+    sync::OnceLock,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{
+    protocol::ServoEncoding,
+    robot::{envelope::EnvelopeMode, Robot},
+};
+
+/// Version of the controller crate
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Commit the controller was built from, `None` when built outside a git checkout
+pub const GIT_HASH: Option<&str> = option_env!("RAC_GIT_HASH");
+
+/// Target triple the controller was built for, set by the build script
+pub const TARGET: &str = env!("RAC_TARGET");
+
+/// The one clock every log of a session takes its timestamps from, so they line up when read
+/// side by side, see [`clock`]
+#[derive(Debug)]
+pub struct SessionClock {
+    /// Tells this session's logs apart from other sessions', the start time in unix
+    /// milliseconds and the process id
+    pub id: String,
+
+    start: Instant,
+}
+
+impl SessionClock {
+    fn new() -> Self {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis());
+
+        Self {
+            id: format!("{millis:x}-{:x}", std::process::id()),
+            start: Instant::now(),
+        }
+    }
+
+    /// Seconds since the session started at `at`, 0 for anything before
+    pub fn seconds(&self, at: Instant) -> f64 {
+        at.saturating_duration_since(self.start).as_secs_f64()
+    }
+
+    /// Seconds since the session started
+    pub fn now(&self) -> f64 {
+        self.seconds(Instant::now())
+    }
+}
+
+/// Clock of this session, started the first time it's asked for
+pub fn clock() -> &'static SessionClock {
+    static CLOCK: OnceLock<SessionClock> = OnceLock::new();
+    CLOCK.get_or_init(SessionClock::new)
+}
+
+/// Which build and settings a session ran with, logged first thing so logs can be matched
+/// to them
+///
+/// The fields are part of the log format, rename or remove them only with care
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionHeader {
+    /// See [`SessionClock::id`]
+    pub session_id: String,
+
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+    pub target: &'static str,
+    pub os: &'static str,
+
+    /// See [`crate::config::Config::hash`]
+    pub config_hash: String,
+
+    /// How the head is moved, the workspace profile, envelope and mirror mode
+    pub workspace: Option<String>,
+    pub envelope_mode: EnvelopeMode,
+    pub mirror: bool,
+
+    pub port: String,
+
+    /// Baud rate that completed the handshake, `None` if none did
+    pub baud: Option<u32>,
+
+    /// Checksum and servo encoding agreed on in the handshake
+    pub checksum: String,
+    pub servo_encoding: ServoEncoding,
+
+    /// Running against the simulator or without an arduino
+    pub no_connect: bool,
+}
+
+impl SessionHeader {
+    pub fn new(robot: &Robot) -> Self {
+        let connection = &robot.connection;
+
+        Self {
+            session_id: clock().id.clone(),
+            version: VERSION,
+            git_hash: GIT_HASH,
+            target: TARGET,
+            os: std::env::consts::OS,
+            config_hash: format!("{:016x}", robot.config_hash),
+            workspace: robot.workspaces.active.clone(),
+            envelope_mode: robot.envelope_mode,
+            mirror: robot.mirror,
+            port: connection.port.clone(),
+            baud: connection.stats.confirmed_baud,
+            checksum: format!("{:?}", connection.checksum),
+            servo_encoding: connection.servo_encoding,
+            no_connect: connection.no_connect,
+        }
+    }
+}
+
+impl fmt::Display for SessionHeader {
+    /// Compact json on a single line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        write!(f, "{json}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn header() {
+        let mut robot = Robot {
+            config_hash: 0xabc,
+            mirror: true,
+            ..Default::default()
+        };
+        robot.connection.port = "/dev/ttyACM0".to_string();
+        robot.connection.stats.confirmed_baud = Some(115_200);
+        robot.envelope_mode = EnvelopeMode::Learn;
+
+        let header = SessionHeader::new(&robot);
+        assert_eq!(header.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(header.config_hash, "0000000000000abc");
+        assert!(!header.target.is_empty());
+        assert_eq!(header.session_id, clock().id);
+
+        // the log format, every field with its name
+        let json: Value = serde_json::from_str(&header.to_string()).unwrap();
+        let mut fields: Vec<_> = json.as_object().unwrap().keys().cloned().collect();
+        fields.sort();
+        assert_eq!(
+            fields,
+            [
+                "baud",
+                "checksum",
+                "config_hash",
+                "envelope_mode",
+                "git_hash",
+                "mirror",
+                "no_connect",
+                "os",
+                "port",
+                "servo_encoding",
+                "session_id",
+                "target",
+                "version",
+                "workspace",
+            ]
+        );
+        assert_eq!(json["baud"], 115_200);
+        assert_eq!(json["envelope_mode"], "learn");
+        assert_eq!(json["servo_encoding"], "microseconds_u16");
+        assert_eq!(json["workspace"], Value::Null);
+        assert!(!header.to_string().contains('\n'));
+    }
+
+    #[test]
+    fn clock_is_shared() {
+        let start = clock().now();
+        assert!(std::ptr::eq(clock(), clock()));
+        assert!(clock().now() >= start);
+        assert_eq!(clock().seconds(clock().start), 0.);
+    }
+}
@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{protocol::Feedback, robot::Servos};
+
+/// Longest step the servo model is integrated over at once in seconds
+const MAX_SUBSTEP: f64 = 0.001;
+
+/// Servos slower than this in µs/s count as stopped
+const MOVING_SPEED: f64 = 1.;
+
+/// Second order model of a hobby servo
+///
+/// The horn lags behind and, when underdamped, overshoots the commanded pulse width like a
+/// mass on a spring would. Errors within the deadband aren't corrected at all
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServoModel {
+    /// Undamped natural frequency in rad/s
+    pub natural_frequency: f64,
+
+    /// Damping ratio, 1 for critical damping and below 1 to overshoot
+    pub damping: f64,
+
+    /// Largest error in µs the servo doesn't react to
+    pub deadband: f64,
+}
+
+/// Simulated arm settings, see [`Simulator`]
+///
+/// Missing fields are the defaults so `--sim '{"enabled": true}'` is enough to try it out
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimConfig {
+    /// Run against the simulator instead of the arduino
+    pub enabled: bool,
+
+    pub base: ServoModel,
+    pub shoulder: ServoModel,
+    pub elbow: ServoModel,
+    pub claw: ServoModel,
+}
+
+/// A single simulated servo, positions are pulse widths in µs
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SimServo {
+    pub model: ServoModel,
+    pub position: f64,
+
+    /// µs/s
+    pub velocity: f64,
+
+    /// Last commanded pulse width
+    pub target: f64,
+
+    /// Held in place as if something blocked the horn, for testing stall detection
+    pub stuck: bool,
+}
+
+/// Stands in for the arduino and the servos so the controller can run without hardware
+///
+/// Servo positions sent to it are commanded with [`Simulator::command`], [`Simulator::step`]
+/// moves the servos and [`Simulator::feedback`] reports them like the firmware would
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Simulator {
+    /// In the same order as [`Servos`]
+    pub servos: [SimServo; 4],
+
+    /// Servo supply voltage reported in the feedback
+    pub millivolts: u16,
+}
+
+impl SimServo {
+    /// A servo at rest at `position`
+    pub fn new(model: ServoModel, position: f64) -> Self {
+        Self {
+            model,
+            position,
+            velocity: 0.,
+            target: position,
+            stuck: false,
+        }
+    }
+
+    /// Advance the servo by `delta` seconds
+    pub fn step(&mut self, delta: f64) {
+        if self.stuck {
+            self.velocity = 0.;
+            return;
+        }
+
+        let substeps = (delta / MAX_SUBSTEP).ceil().max(1.);
+        let h = delta / substeps;
+
+        for _ in 0..substeps as u32 {
+            // classic runge kutta, the model is stiff enough that euler visibly drifts
+            let (x, v) = (self.position, self.velocity);
+            let (dx1, dv1) = (v, self.acceleration(x, v));
+            let (dx2, dv2) = {
+                let (x, v) = (x + dx1 * h / 2., v + dv1 * h / 2.);
+                (v, self.acceleration(x, v))
+            };
+            let (dx3, dv3) = {
+                let (x, v) = (x + dx2 * h / 2., v + dv2 * h / 2.);
+                (v, self.acceleration(x, v))
+            };
+            let (dx4, dv4) = {
+                let (x, v) = (x + dx3 * h, v + dv3 * h);
+                (v, self.acceleration(x, v))
+            };
+
+            self.position += (dx1 + 2. * dx2 + 2. * dx3 + dx4) * h / 6.;
+            self.velocity += (dv1 + 2. * dv2 + 2. * dv3 + dv4) * h / 6.;
+        }
+    }
+
+    /// True while the servo is still correcting an error or coasting
+    pub fn moving(&self) -> bool {
+        self.velocity.abs() > MOVING_SPEED
+            || (self.target - self.position).abs() > self.model.deadband
+    }
+
+    fn acceleration(&self, position: f64, velocity: f64) -> f64 {
+        let ServoModel {
+            natural_frequency: omega,
+            damping,
+            deadband,
+        } = self.model;
+
+        let error = self.target - position;
+        let pull = if error.abs() > deadband { error } else { 0. };
+
+        omega * omega * pull - 2. * damping * omega * velocity
+    }
+}
+
+impl Simulator {
+    /// All servos at rest at `start`
+    pub fn new(config: &SimConfig, start: Servos) -> Self {
+        let servo = |model, pulse: u16| SimServo::new(model, pulse as f64);
+
+        Self {
+            servos: [
+                servo(config.base, start.base),
+                servo(config.shoulder, start.shoulder),
+                servo(config.elbow, start.elbow),
+                servo(config.claw, start.claw),
+            ],
+            millivolts: 5000,
+        }
+    }
+
+    /// New servo positions arrived
+    pub fn command(&mut self, servos: Servos) {
+        let targets = [servos.base, servos.shoulder, servos.elbow, servos.claw];
+        for (servo, target) in self.servos.iter_mut().zip(targets) {
+            servo.target = target as f64;
+        }
+    }
+
+    /// Advance all servos by `delta` seconds
+    pub fn step(&mut self, delta: f64) {
+        for servo in &mut self.servos {
+            servo.step(delta);
+        }
+    }
+
+    /// What the firmware would report right now
+    pub fn feedback(&self) -> Feedback {
+        let moving = self.servos.iter().any(SimServo::moving);
+
+        Feedback {
+            pulses: self
+                .servos
+                .map(|servo| servo.position.round().clamp(0., u16::MAX as f64) as u16),
+            millivolts: self.millivolts,
+            status: if moving { Feedback::MOVING } else { 0 },
+        }
+    }
+}
+
+impl Default for ServoModel {
+    /// Roughly a standard size analog hobby servo
+    fn default() -> Self {
+        Self {
+            natural_frequency: 25.,
+            damping: 0.6,
+            deadband: 4.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Analytic step response of an underdamped second order system starting at rest
+    fn step_response(model: &ServoModel, from: f64, to: f64, t: f64) -> f64 {
+        let ServoModel {
+            natural_frequency: omega,
+            damping,
+            ..
+        } = *model;
+        let damped = omega * (1. - damping * damping).sqrt();
+        let decay = (-damping * omega * t).exp();
+
+        to - (to - from)
+            * decay
+            * ((damped * t).cos() + damping / (1. - damping * damping).sqrt() * (damped * t).sin())
+    }
+
+    #[test]
+    fn matches_analytic_step() {
+        let model = ServoModel {
+            natural_frequency: 30.,
+            damping: 0.4,
+            deadband: 0.,
+        };
+        let mut servo = SimServo::new(model, 1500.);
+        servo.target = 2000.;
+
+        let mut peak: f64 = 0.;
+        for tick in 1..=50 {
+            servo.step(0.01);
+            let expected = step_response(&model, 1500., 2000., tick as f64 * 0.01);
+            assert!(
+                (servo.position - expected).abs() < 0.05,
+                "tick {tick}: {} instead of {expected}",
+                servo.position
+            );
+            peak = peak.max(servo.position);
+        }
+
+        // overshoot of e^(-πζ/√(1-ζ²)), about 25% at this damping, the 10ms samples can miss
+        // the very top of the peak by a little
+        let overshoot = (-std::f64::consts::PI * 0.4 / (1. - 0.4f64 * 0.4).sqrt()).exp();
+        assert!((peak - (2000. + 500. * overshoot)).abs() < 3.);
+    }
+
+    #[test]
+    fn deadband() {
+        let model = ServoModel {
+            deadband: 10.,
+            ..Default::default()
+        };
+
+        // small errors aren't corrected at all
+        let mut servo = SimServo::new(model, 1500.);
+        servo.target = 1508.;
+        servo.step(1.);
+        assert_eq!(servo.position, 1500.);
+        assert!(!servo.moving());
+
+        // large ones only until they are within the deadband
+        servo.target = 1700.;
+        assert!(servo.moving());
+        for _ in 0..300 {
+            servo.step(0.01);
+        }
+        assert!((servo.position - 1700.).abs() <= 10.);
+        assert!(!servo.moving());
+    }
+
+    #[test]
+    fn feedback() {
+        let start = Servos {
+            base: 1500,
+            shoulder: 1500,
+            elbow: 1500,
+            claw: 1000,
+            wrist: None,
+        };
+        let mut sim = Simulator::new(&SimConfig::default(), start);
+        assert_eq!(sim.feedback().pulses, [1500, 1500, 1500, 1000]);
+        assert_eq!(sim.feedback().status, 0);
+
+        sim.command(Servos {
+            elbow: 1800,
+            ..start
+        });
+        sim.step(0.05);
+        let feedback = sim.feedback();
+        assert_eq!(feedback.status, Feedback::MOVING);
+        // lags behind the command
+        assert!(feedback.pulses[2] > 1500 && feedback.pulses[2] < 1800);
+        assert_eq!(feedback.pulses[0], 1500);
+
+        for _ in 0..200 {
+            sim.step(0.01);
+        }
+        assert!(sim.feedback().pulses[2].abs_diff(1800) <= 4);
+        assert_eq!(sim.feedback().status, 0);
+    }
+}
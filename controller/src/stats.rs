@@ -0,0 +1,171 @@
+use crate::protocol::{kind, Frame};
+
+/// Counters for the serial connection
+///
+/// All values are totals since the connection was created
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Bytes read from the serial port
+    pub bytes_received: u64,
+
+    /// Bytes dropped because the read buffer was full
+    pub overflow_bytes: u64,
+
+    /// Frames that were decoded successfully
+    pub frames_received: u64,
+
+    /// Decoded frames by type
+    pub received: FrameCounts,
+
+    /// Frames sent with [`crate::communication::Connection::send`] by type
+    pub sent: FrameCounts,
+
+    /// Complete frames that failed to decode by type, the type byte itself may be corrupt
+    pub errors: FrameCounts,
+
+    /// Servo position messages written
+    pub servo_writes: u64,
+
+    /// Decoded frames thrown away because too many were waiting to be handled
+    pub frames_dropped: u64,
+
+    /// Complete frames that failed to decode
+    pub framing_errors: u64,
+
+    /// Number of times the connection moved on to the next candidate baud rate
+    pub baud_switches: u64,
+
+    /// Baud rate that completed the handshake, `None` if no handshake succeeded
+    pub confirmed_baud: Option<u32>,
+}
+
+/// Number of frames of each type
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FrameCounts {
+    pub feedback: u64,
+    pub hello: u64,
+    pub status_request: u64,
+    pub status: u64,
+    pub ack: u64,
+    pub detach: u64,
+    pub attach: u64,
+
+    /// Frames of a type we don't decode
+    pub unknown: u64,
+}
+
+/// Timing of the main loop
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct LoopStats {
+    pub ticks: u64,
+
+    /// Seconds of all ticks together
+    pub total: f64,
+
+    /// Longest tick in seconds
+    pub max: f64,
+}
+
+impl LoopStats {
+    /// Count a tick that took `delta` seconds
+    pub fn tick(&mut self, delta: f64) {
+        self.ticks += 1;
+        self.total += delta;
+        self.max = self.max.max(delta);
+    }
+
+    /// Average tick in seconds, 0 before the first tick
+    pub fn mean(&self) -> f64 {
+        match self.ticks {
+            0 => 0.,
+            ticks => self.total / ticks as f64,
+        }
+    }
+}
+
+impl FrameCounts {
+    /// Count a frame under its type
+    pub fn count(&mut self, frame: &Frame) {
+        self.count_kind(frame.kind());
+    }
+
+    /// Count a frame under its type byte
+    pub fn count_kind(&mut self, kind: u8) {
+        let counter = match kind {
+            kind::FEEDBACK => &mut self.feedback,
+            kind::HELLO => &mut self.hello,
+            kind::STATUS_REQUEST => &mut self.status_request,
+            kind::STATUS => &mut self.status,
+            kind::ACK => &mut self.ack,
+            kind::DETACH => &mut self.detach,
+            kind::ATTACH => &mut self.attach,
+            _ => &mut self.unknown,
+        };
+
+        *counter += 1;
+    }
+}
+
+/// Minimum number of framing errors before a baud mismatch is reported
+pub const MISMATCH_MIN_ERRORS: u64 = 8;
+
+/// Share of complete frames that have to fail decoding before a baud mismatch is reported
+pub const MISMATCH_ERROR_RATIO: f64 = 0.5;
+
+impl ConnectionStats {
+    /// Share of complete frames that failed to decode, 0 if nothing was received
+    pub fn error_ratio(&self) -> f64 {
+        let total = self.frames_received + self.framing_errors;
+        if total == 0 {
+            return 0.;
+        }
+
+        self.framing_errors as f64 / total as f64
+    }
+
+    /// True if the received data looks like it's being read at the wrong baud rate
+    ///
+    /// A wrong baud rate turns every byte into garbage, so almost every frame fails its checksum
+    pub fn probable_baud_mismatch(&self) -> bool {
+        self.framing_errors >= MISMATCH_MIN_ERRORS && self.error_ratio() > MISMATCH_ERROR_RATIO
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn loop_stats() {
+        let mut stats = LoopStats::default();
+        assert_eq!(stats.mean(), 0.);
+
+        for delta in [0.01, 0.03, 0.02] {
+            stats.tick(delta);
+        }
+        assert_eq!(stats.ticks, 3);
+        assert!((stats.mean() - 0.02).abs() < 1e-12);
+        assert_eq!(stats.max, 0.03);
+    }
+
+    #[test]
+    fn baud_mismatch() {
+        let mut stats = ConnectionStats::default();
+        assert!(!stats.probable_baud_mismatch());
+        assert_eq!(stats.error_ratio(), 0.);
+
+        // a few errors on a healthy link
+        stats.frames_received = 100;
+        stats.framing_errors = 10;
+        assert!(!stats.probable_baud_mismatch());
+
+        // too few samples to say anything
+        stats.frames_received = 0;
+        stats.framing_errors = MISMATCH_MIN_ERRORS - 1;
+        assert!(!stats.probable_baud_mismatch());
+
+        stats.framing_errors = 20;
+        stats.frames_received = 5;
+        assert!(stats.probable_baud_mismatch());
+    }
+}
@@ -0,0 +1,377 @@
+//! Plays back a session recorded with the `telemetry` setting, see `--view <file>`
+//!
+//! The frames are shown with [`display::render`] like a live session, nothing is connected.
+//! Controlled with a line on stdin:
+//! * `p` or an empty line, play or pause
+//! * `s [frames]`, step forward, or back for a negative count, and pause
+//! * `]` and `[`, jump to the next or previous event, see [`EventIndex`]
+//! * `g <seconds>`, go to the frame at that time into the session
+//! * `q`, quit
+
+use std::{fmt, time::Duration};
+
+use crate::{
+    clock::{Clock, PacedClock},
+    command, display, logging,
+    robot::{
+        telemetry::{self, EventIndex, TelemetryRecord},
+        Robot,
+    },
+};
+
+/// Where the playback is at in the recorded frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    /// Session time of every frame
+    times: Vec<f64>,
+
+    pub frame: usize,
+    pub playing: bool,
+
+    /// Session time of the playhead, from the time of the frame to that of the next
+    pub time: f64,
+}
+
+/// What the operator asked the viewer to do
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Control {
+    Toggle,
+    Step(isize),
+    NextEvent,
+    PreviousEvent,
+    Seek(f64),
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlError {
+    Unknown(String),
+    Argument(String),
+}
+
+impl Playback {
+    /// Paused at the first of frames recorded at `times`
+    pub fn new(times: Vec<f64>) -> Self {
+        let time = times.first().copied().unwrap_or(0.);
+        Self {
+            times,
+            frame: 0,
+            playing: false,
+            time,
+        }
+    }
+
+    /// Advance `delta` seconds if playing, pausing at the last frame
+    pub fn tick(&mut self, delta: f64) {
+        if !self.playing {
+            return;
+        }
+
+        self.time += delta;
+        while self
+            .times
+            .get(self.frame + 1)
+            .is_some_and(|&next| next <= self.time)
+        {
+            self.frame += 1;
+        }
+        if self.at_end() {
+            self.playing = false;
+            self.time = self.times[self.frame];
+        }
+    }
+
+    /// Play or pause, playing from the last frame starts over
+    pub fn toggle(&mut self) {
+        if !self.playing && self.at_end() {
+            self.seek(0);
+        }
+        self.playing = !self.playing && !self.at_end();
+    }
+
+    /// Pause and move `frames` on, or back if negative
+    pub fn step(&mut self, frames: isize) {
+        self.playing = false;
+        self.seek(self.frame.saturating_add_signed(frames));
+    }
+
+    /// Go to `frame`, or the last one if there aren't that many
+    pub fn seek(&mut self, frame: usize) {
+        self.frame = frame.min(self.times.len().saturating_sub(1));
+        self.time = self.times.get(self.frame).copied().unwrap_or(0.);
+    }
+
+    /// Go to the last frame recorded at or before `time`
+    pub fn seek_time(&mut self, time: f64) {
+        let frame = self.times.partition_point(|&at| at <= time);
+        self.seek(frame.saturating_sub(1));
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.frame + 1 >= self.times.len()
+    }
+
+    /// Act on `control`
+    ///
+    /// # Returns
+    /// False to quit
+    pub fn control(&mut self, control: Control, events: &EventIndex) -> bool {
+        match control {
+            Control::Toggle => self.toggle(),
+            Control::Step(frames) => self.step(frames),
+            Control::NextEvent => {
+                if let Some((frame, _)) = events.next(self.frame) {
+                    self.playing = false;
+                    self.seek(frame);
+                }
+            }
+            Control::PreviousEvent => {
+                if let Some((frame, _)) = events.previous(self.frame) {
+                    self.playing = false;
+                    self.seek(frame);
+                }
+            }
+            Control::Seek(time) => self.seek_time(time),
+            Control::Quit => return false,
+        }
+        true
+    }
+}
+
+impl Control {
+    /// Parse a line of input, see [`crate::viewer`]
+    pub fn parse(line: &str) -> Result<Self, ControlError> {
+        let mut words = line.split_whitespace();
+        let control = match words.next() {
+            None | Some("p") => Control::Toggle,
+            Some("s") => match words.next() {
+                Some(frames) => Control::Step(
+                    frames
+                        .parse()
+                        .map_err(|_| ControlError::Argument(frames.to_string()))?,
+                ),
+                None => Control::Step(1),
+            },
+            Some("]") => Control::NextEvent,
+            Some("[") => Control::PreviousEvent,
+            Some("g") => {
+                let time = words.next().unwrap_or_default();
+                Control::Seek(
+                    time.parse()
+                        .map_err(|_| ControlError::Argument(time.to_string()))?,
+                )
+            }
+            Some("q") => Control::Quit,
+            Some(word) => return Err(ControlError::Unknown(word.to_string())),
+        };
+
+        match words.next() {
+            Some(extra) => Err(ControlError::Argument(extra.to_string())),
+            None => Ok(control),
+        }
+    }
+}
+
+/// Play back the telemetry in `path` until quit
+///
+/// # Returns
+/// False if it couldn't be loaded
+pub fn run(path: &str, config_hash: u64) -> bool {
+    let records = match telemetry::load(path) {
+        Ok(records) => records,
+        Err(err) => {
+            logging::error(&format!("Could not load {path}: {err}"));
+            return false;
+        }
+    };
+    let events = EventIndex::new(&records);
+    let mut playback = Playback::new(records.iter().map(|record| record.time).collect());
+    logging::info(&format!(
+        "Viewing {} frames with {} events from {path}",
+        records.len(),
+        events.events.len()
+    ));
+
+    // what isn't recorded is shown as the arm at rest
+    let mut base = Robot::default().state();
+    base.config_hash = config_hash;
+
+    let commands = command::spawn_stdin();
+    let mut clock = PacedClock::new(Duration::from_millis(10));
+    loop {
+        let delta = clock.tick();
+        while let Ok(line) = commands.try_recv() {
+            match Control::parse(&line) {
+                Ok(control) => {
+                    if !playback.control(control, &events) {
+                        return true;
+                    }
+                }
+                Err(err) => logging::warn(&format!("{line}: {err}")),
+            }
+        }
+        playback.tick(delta);
+
+        clearscreen::clear().unwrap();
+        print!("{}", frame(&records[playback.frame], &base));
+        println!("{}", status(&playback, &events, records.len()));
+    }
+}
+
+/// The recorded frame rendered like a live one
+fn frame(record: &TelemetryRecord, base: &crate::robot::RobotState) -> String {
+    let mut state = base.clone();
+    record.apply(&mut state);
+    display::render(&state)
+}
+
+/// Line telling where the playback is at and what happened at the frame
+fn status(playback: &Playback, events: &EventIndex, frames: usize) -> String {
+    let mut line = format!(
+        "ply: frame {}/{} at {:.2}s, {}",
+        playback.frame + 1,
+        frames,
+        playback.time,
+        if playback.playing {
+            "playing"
+        } else {
+            "paused"
+        }
+    );
+    for event in events.at(playback.frame) {
+        line += &format!(", {event}");
+    }
+    line
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::Unknown(word) => write!(f, "unknown control `{word}`"),
+            ControlError::Argument(arg) => write!(f, "unexpected argument `{arg}`"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        kinematics::position::CordinateVec,
+        robot::{estop::StopStage, telemetry::TelemetryEvent},
+    };
+
+    fn playback() -> Playback {
+        Playback::new((0..10).map(|frame| 5. + frame as f64 * 0.1).collect())
+    }
+
+    #[test]
+    fn playing() {
+        let mut playback = playback();
+        assert_eq!((playback.frame, playback.time), (0, 5.));
+
+        // paused it stays put
+        playback.tick(1.);
+        assert_eq!(playback.frame, 0);
+
+        playback.toggle();
+        playback.tick(0.25);
+        assert_eq!(playback.frame, 2);
+        playback.tick(0.05);
+        assert_eq!(playback.frame, 3);
+
+        // and stops at the end
+        playback.tick(10.);
+        assert_eq!(playback.frame, 9);
+        assert!(!playback.playing);
+        assert!((playback.time - 5.9).abs() < 1e-9);
+
+        // playing again starts over
+        playback.toggle();
+        assert!(playback.playing);
+        assert_eq!((playback.frame, playback.time), (0, 5.));
+        playback.toggle();
+        assert!(!playback.playing);
+    }
+
+    #[test]
+    fn scrubbing() {
+        let mut playback = playback();
+        playback.toggle();
+        playback.step(3);
+        assert!(!playback.playing);
+        assert_eq!(playback.frame, 3);
+        assert!((playback.time - 5.3).abs() < 1e-9);
+
+        // clamped to the recording
+        playback.step(-5);
+        assert_eq!(playback.frame, 0);
+        playback.step(20);
+        assert_eq!(playback.frame, 9);
+
+        playback.seek_time(5.45);
+        assert_eq!(playback.frame, 4);
+        playback.seek_time(0.);
+        assert_eq!(playback.frame, 0);
+        playback.seek_time(100.);
+        assert_eq!(playback.frame, 9);
+
+        // jumping to events pauses at them
+        let mut records = vec![TelemetryRecord::default(); 10];
+        records[4].emergency_stop = Some(StopStage::Braking);
+        records[7].stalled = Some("claw");
+        let events = EventIndex::new(&records);
+        playback.seek(0);
+        playback.toggle();
+        assert!(playback.control(Control::NextEvent, &events));
+        assert_eq!((playback.frame, playback.playing), (4, false));
+        playback.control(Control::NextEvent, &events);
+        assert_eq!(playback.frame, 7);
+        assert_eq!(
+            status(&playback, &events, 10),
+            "ply: frame 8/10 at 5.70s, paused, claw stalled"
+        );
+        playback.control(Control::NextEvent, &events);
+        assert_eq!(playback.frame, 7);
+        playback.control(Control::PreviousEvent, &events);
+        assert_eq!(playback.frame, 4);
+        assert_eq!(events.at(4).next(), Some(TelemetryEvent::EmergencyStop));
+        assert!(!playback.control(Control::Quit, &events));
+    }
+
+    #[test]
+    fn controls() {
+        assert_eq!(Control::parse(""), Ok(Control::Toggle));
+        assert_eq!(Control::parse("p"), Ok(Control::Toggle));
+        assert_eq!(Control::parse("s"), Ok(Control::Step(1)));
+        assert_eq!(Control::parse("s -10"), Ok(Control::Step(-10)));
+        assert_eq!(Control::parse("]"), Ok(Control::NextEvent));
+        assert_eq!(Control::parse("["), Ok(Control::PreviousEvent));
+        assert_eq!(Control::parse("g 12.5"), Ok(Control::Seek(12.5)));
+        assert_eq!(Control::parse("q"), Ok(Control::Quit));
+
+        assert_eq!(
+            Control::parse("s many"),
+            Err(ControlError::Argument("many".into()))
+        );
+        assert_eq!(Control::parse("g"), Err(ControlError::Argument("".into())));
+        assert_eq!(
+            Control::parse("p p"),
+            Err(ControlError::Argument("p".into()))
+        );
+        assert_eq!(Control::parse("x"), Err(ControlError::Unknown("x".into())));
+    }
+
+    #[test]
+    fn rendered_like_live() {
+        let mut robo = Robot {
+            position: CordinateVec::new(1., 2., 3.),
+            ..Default::default()
+        };
+        robo.estop.stage = Some(StopStage::Halted);
+        let record = TelemetryRecord::new(0., &robo.state(), false);
+
+        let rendered = frame(&record, &Robot::default().state());
+        assert_eq!(rendered, display::render(&robo.state()));
+    }
+}
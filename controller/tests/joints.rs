@@ -0,0 +1,158 @@
+use controller::kinematics::{
+    joints::{
+        Chained, DirectDrive, DirectDriveOffset, DoubleLinkage, GearDrive, Joint, JointConfig,
+        Motion, MotionKind, MotionStage,
+    },
+    position::{CordinateVec, SphereVec},
+};
+use serde::Deserialize;
+
+/// The joints in `arm.toml`
+#[derive(Debug, Deserialize)]
+struct ArmFile {
+    base: JointConfig,
+    shoulder: JointConfig,
+    elbow: JointConfig,
+    claw: JointConfig,
+}
+
+#[test]
+fn arm_file() {
+    let file: ArmFile = toml::from_str(include_str!("arm.toml")).unwrap();
+
+    assert!(file.base.continuous);
+    assert_eq!(
+        file.base.motion,
+        MotionKind::DirectDriveOffset(DirectDriveOffset { offset: 90. })
+    );
+    assert_eq!(
+        file.shoulder.motion,
+        MotionKind::DoubleLinkage(DoubleLinkage::new(1., 10., 10., 1., 10., 20.))
+    );
+    // the reach of the linkage is worked out reading it, like building it by hand
+    let MotionKind::DoubleLinkage(linkage) = file.shoulder.motion else {
+        unreachable!()
+    };
+    assert!(linkage.achievable_range().is_some());
+    assert_eq!((file.elbow.min, file.elbow.max), (10., 170.));
+    assert_eq!(
+        file.claw.motion,
+        MotionKind::DirectDrive(DirectDrive::new())
+    );
+
+    // built into joints that turn the same as ones built by hand
+    let elbow = Joint::from(file.elbow);
+    let by_hand = Joint::new(10., 170., GearDrive::new(2.).unwrap());
+    assert_eq!(elbow.angle, 90.);
+    assert_eq!(
+        elbow.motion.get_pivot_angle(40.),
+        by_hand.motion.get_pivot_angle(40.)
+    );
+}
+
+#[test]
+fn round_trip() {
+    let motions = [
+        MotionKind::DirectDrive(DirectDrive::new()),
+        MotionKind::DirectDriveOffset(DirectDriveOffset { offset: -12.5 }),
+        MotionKind::GearDrive(GearDrive::new(0.75).unwrap().with_offset(-4.)),
+        MotionKind::DoubleLinkage(DoubleLinkage::new(1., 10., 10., 1., 10., 20.)),
+        MotionKind::Chained(Chained::new(
+            MotionStage::DirectDriveOffset(DirectDriveOffset { offset: 5. }),
+            MotionStage::GearDrive(GearDrive::new(2.).unwrap()),
+        )),
+    ];
+
+    for (motion, inverted) in motions.into_iter().zip([false, true, false, true, false]) {
+        let config = JointConfig {
+            angle: 33.,
+            min: 5.,
+            max: 175.,
+            continuous: false,
+            inverted,
+            soft_margin_deg: 5.,
+            motion,
+        };
+
+        // through a joint and back
+        assert_eq!(Joint::from(config).config(), config);
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<JointConfig>(&json).unwrap(), config);
+
+        let toml = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<JointConfig>(&toml).unwrap(), config);
+    }
+}
+
+#[test]
+fn gear_ratio_checked() {
+    let toml = |ratio: &str| format!("gear_drive = {{ gear_ratio = {ratio} }}");
+    let gear: MotionKind = toml::from_str(&toml("2.0")).unwrap();
+    assert_eq!(gear, MotionKind::GearDrive(GearDrive::new(2.).unwrap()));
+
+    // a ratio of 0 is an error in the file, not a joint stuck at the offset
+    let err = toml::from_str::<MotionKind>(&toml("0.0")).unwrap_err();
+    assert!(err.to_string().contains("gear ratio"), "{err}");
+    assert!(serde_json::from_str::<MotionKind>(r#"{"gear_drive":{"gear_ratio":0}}"#).is_err());
+}
+
+#[test]
+fn chained_file() {
+    let toml = r#"
+        angle = 60.0
+        min = 20.0
+        max = 110.0
+
+        [motion.chained.first.double_linkage]
+        connection_radial_offset = 1.0
+        connection_linear_offset = 10.0
+        controll_pivot_horizontal_offset = 10.0
+        controll_pivot_vertical_offset = 1.0
+        controller_pivot_rod_length = 10.0
+        connection_rod_length = 20.0
+
+        [motion.chained.then.gear_drive]
+        gear_ratio = 2.0
+        offset = 10.0
+    "#;
+    let joint = Joint::from(toml::from_str::<JointConfig>(toml).unwrap());
+
+    // a linkage turned through a gear, the same as the two one after the other
+    let linkage = DoubleLinkage::new(1., 10., 10., 1., 10., 20.);
+    let gear = GearDrive::new(2.).unwrap().with_offset(10.);
+    for target in [20., 60., 110.] {
+        assert_eq!(
+            joint.motion.get_pivot_angle(target),
+            gear.get_pivot_angle(linkage.get_pivot_angle(target))
+        );
+    }
+
+    // a chain can't be a stage of another
+    let nested = r#"{"chained":{"first":{"chained":{}},"then":{"direct_drive":{}}}}"#;
+    assert!(serde_json::from_str::<MotionKind>(nested).is_err());
+}
+
+#[test]
+fn positions() {
+    let position = CordinateVec::new(1.5, -2., 30.);
+    let json = serde_json::to_string(&position).unwrap();
+    assert_eq!(json, r#"{"x":1.5,"y":-2.0,"z":30.0}"#);
+    assert_eq!(
+        serde_json::from_str::<CordinateVec>(&json).unwrap(),
+        position
+    );
+
+    let sphere = SphereVec::new(0.5, 1., 4.);
+    let json = serde_json::to_string(&sphere).unwrap();
+    let back: SphereVec = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        (back.azmut, back.polar, back.distance, back.flat_distance),
+        (
+            sphere.azmut,
+            sphere.polar,
+            sphere.distance,
+            sphere.flat_distance
+        )
+    );
+}